@@ -0,0 +1,142 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Error as IoError;
+use std::io::Write;
+use std::path::Path;
+
+fn main() -> Result<(), IoError> {
+    // This call to unwrap is safe because cargo always sets OUT_DIR
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let dest_path = Path::new(&out_dir).join("opcodes.rs");
+    write_decode_table(Path::new("opcodes"), &dest_path)
+}
+
+// Bits of the operand fields of the riscv-opcodes files
+const OPCODE_FIELDS: [(&str, u32, u32); 16] = [
+    ("rd", 11, 7),
+    ("rs1", 19, 15),
+    ("rs2", 24, 20),
+    ("imm12", 31, 20),
+    ("imm12hi", 31, 25),
+    ("imm12lo", 11, 7),
+    ("bimm12hi", 31, 25),
+    ("bimm12lo", 11, 7),
+    ("imm20", 31, 12),
+    ("jimm20", 31, 12),
+    ("shamtw", 24, 20),
+    ("csr", 31, 20),
+    ("zimm", 19, 15),
+    ("fm", 31, 28),
+    ("pred", 27, 24),
+    ("succ", 23, 20),
+];
+
+/// Mask of the bits hi down to lo
+fn bit_range(hi: u32, lo: u32) -> u32 {
+    (!0u32 >> (31 - hi)) & (!0u32 << lo)
+}
+
+/// Parse an encoding of the riscv-opcodes files, like
+/// `addi rd rs1 imm12 14..12=0 6..2=0x04 1..0=3`
+///
+/// # Return Value
+/// The name, mask and match of the encoding. Every bit must be either fixed
+/// or part of exactly one operand field, otherwise this panics.
+fn parse_encoding(line: &str) -> (String, u32, u32) {
+    let mut tokens = line.split_whitespace();
+    // An instruction of another base shared by this one
+    if line.starts_with("$pseudo_op") {
+        tokens.nth(1);
+    }
+    let name = tokens.next().unwrap().to_string();
+    let (mut mask, mut value, mut covered) = (0, 0, 0);
+    for token in tokens {
+        let bits = if let Some(index) = token.find('=') {
+            let (range, fixed) = (&token[..index], &token[index + 1..]);
+            let (hi, lo) = match range.find("..") {
+                Some(dots) => (&range[..dots], &range[dots + 2..]),
+                None => (range, range),
+            };
+            let (hi, lo): (u32, u32) = (hi.parse().unwrap(), lo.parse().unwrap());
+            let fixed = if let Some(hex) = fixed.strip_prefix("0x") {
+                u32::from_str_radix(hex, 16).unwrap()
+            } else {
+                fixed.parse().unwrap()
+            };
+            let bits = bit_range(hi, lo);
+            if fixed << lo & !bits != 0 {
+                panic!("{}: {} does not fit in its bits", name, token);
+            }
+            mask |= bits;
+            value |= fixed << lo;
+            bits
+        } else {
+            match OPCODE_FIELDS.iter().find(|field| field.0 == token) {
+                Some((_, hi, lo)) => bit_range(*hi, *lo),
+                None => panic!("{}: unknown field {}", name, token),
+            }
+        };
+        if covered & bits != 0 {
+            panic!("{}: {} overlaps another field", name, token);
+        }
+        covered |= bits;
+    }
+    if covered != !0 {
+        panic!("{}: bits {:#010x} are not encoded", name, !covered);
+    }
+    (name, mask, value)
+}
+
+/// Write the decode table of the riscv-opcodes files of a directory. The
+/// operations are grouped by the bits 6 to 2 of their major opcode.
+///
+/// # Arguments
+/// * `dir` => directory of the riscv-opcodes files
+/// * `dest_path` => Rust file holding the table
+fn write_decode_table(dir: &Path, dest_path: &Path) -> Result<(), IoError> {
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut encodings: Vec<(String, u32, u32)> = Vec::new();
+    for path in paths {
+        let text = fs::read_to_string(&path)?;
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let encoding = parse_encoding(line);
+            if encoding.1 & 3 != 3 || encoding.2 & 3 != 3 || encoding.1 & 0x7c != 0x7c {
+                panic!("{}: not a 32 bit major opcode", encoding.0);
+            }
+            // Two encodings overlap when they agree on the bits both fix
+            for other in &encodings {
+                if (encoding.2 ^ other.2) & encoding.1 & other.1 == 0 {
+                    panic!("{} and {} overlap", encoding.0, other.0);
+                }
+            }
+            encodings.push(encoding);
+        }
+    }
+
+    let mut f = File::create(dest_path)?;
+    writeln!(f, "// Generated by build.rs from the files in opcodes/")?;
+    writeln!(f, "const OPCODES: [&[(u32, u32, RV32I)]; 32] = [")?;
+    for major in 0..32 {
+        let entries: Vec<_> = encodings
+            .iter()
+            .filter(|(_, _, value)| value >> 2 & 0x1f == major)
+            .map(|(name, mask, value)| {
+                format!(
+                    "({:#010x}, {:#010x}, RV32I::{})",
+                    mask,
+                    value,
+                    name.to_uppercase()
+                )
+            })
+            .collect();
+        writeln!(f, "    &[{}],", entries.join(", "))?;
+    }
+    writeln!(f, "];")
+}
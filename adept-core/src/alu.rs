@@ -1,7 +1,8 @@
 use riscv::isa::RV32I;
 
 /// ALU operations codes
-enum AluOpList {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AluOpList {
     Add,
     Sub,
     Sll,
@@ -33,11 +34,24 @@ impl From<RV32I> for AluOpList {
     }
 }
 
+/// Operation of the ALU and its second operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct AluOp {
     op: AluOpList,
     switch_2_imm: bool,
 }
 
+impl AluOp {
+    pub fn op(&self) -> AluOpList {
+        self.op
+    }
+
+    /// Check if the immediate replaces the second register operand
+    pub fn uses_imm(&self) -> bool {
+        self.switch_2_imm
+    }
+}
+
 impl From<RV32I> for AluOp {
     fn from(instr: RV32I) -> Self {
         let switch_2_imm = match instr {
@@ -72,8 +86,8 @@ pub fn alu(op_a: i32, op_b: i32, imm: i32, op: &AluOp) -> i32 {
     let operand_b = if op.switch_2_imm { imm } else { op_b };
 
     match op.op {
-        AluOpList::Add => op_a + operand_b,
-        AluOpList::Sub => op_a - operand_b,
+        AluOpList::Add => op_a.wrapping_add(operand_b),
+        AluOpList::Sub => op_a.wrapping_sub(operand_b),
         AluOpList::Sll => op_a << (operand_b & 0x0000_001f),
         AluOpList::Slt => {
             if op_a < operand_b {
@@ -112,11 +126,21 @@ mod tests {
         assert_eq!(3, result);
     }
 
+    #[test]
+    fn test_add_overflow() {
+        // Additions wrap around like the hardware adder
+        let result = alu(i32::MAX, 1, 3, &AluOp::from(RV32I::ADD));
+        assert_eq!(i32::MIN, result);
+    }
+
     #[test]
     fn test_sub() {
         // Test SUB
         let result = alu(1, 2, 3, &AluOp::from(RV32I::SUB));
         assert_eq!(-1, result);
+        // Subtractions wrap around like the hardware adder
+        let result = alu(i32::MIN, 1, 3, &AluOp::from(RV32I::SUB));
+        assert_eq!(i32::MAX, result);
     }
 
     #[test]
@@ -0,0 +1,17 @@
+//! Decoder, instruction set tables and ALU of the Adept processor, apart
+//! from the simulator so that they can run without the standard library,
+//! such as in embedded tooling or in the self-test of a bootloader. Nothing
+//! here allocates, unless the `alloc` feature adds the breakdowns of the
+//! encodings which need a heap.
+#![no_std]
+
+#[cfg(feature = "alloc")]
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(test)]
+extern crate std;
+
+pub mod alu;
+pub mod riscv;
@@ -0,0 +1,1210 @@
+use super::isa::{InstrType, RV32I, RVT};
+use core::cmp::PartialEq;
+use core::fmt::{self, Display, Formatter};
+use core::hash::{Hash, Hasher};
+use riscv::labels::*;
+use riscv::DecodeError;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Instruction {
+    /// Instruction Type
+    instr: InstrType,
+
+    // Registers
+    /// Destination Registers
+    rd: Option<u8>,
+    /// Register 1
+    rs1: Option<u8>,
+    /// Register 2
+    rs2: Option<u8>,
+
+    /// Shift Amount
+    shamt: Option<u8>,
+
+    /// Immediate, or the unsigned immediate of CSR instructions
+    imm: Option<i32>,
+
+    /// Control and status register address
+    csr: Option<u16>,
+
+    /// Set if the encoding has no effect on the architectural state
+    hint: Option<Hint>,
+
+    /// Format asked of the builder, checked by `build`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    builder_type: Option<RVT>,
+}
+
+/// Encodings which don't change the architectural state. Compilers use them
+/// to pad code, and the HINT space is reserved for performance hints.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Hint {
+    /// The canonical NOP, addi x0, x0, 0
+    Nop,
+    /// Another encoding of the HINT space, such as a computation writing x0
+    /// or a fence without predecessors or successors
+    Hint,
+}
+
+impl Hint {
+    // Find the hint of a decoded instruction
+    fn of(instr: &Instruction, raw_instr: u32) -> Option<Hint> {
+        if raw_instr == NOP {
+            return Some(Hint::Nop);
+        }
+        let is_hint = match instr.op() {
+            // Predecessor and successor sets
+            RV32I::FENCE => raw_instr & 0x0f00_0000 == 0 || raw_instr & 0x00f0_0000 == 0,
+            RV32I::LUI
+            | RV32I::AUIPC
+            | RV32I::ADDI
+            | RV32I::SLTI
+            | RV32I::SLTIU
+            | RV32I::XORI
+            | RV32I::ORI
+            | RV32I::ANDI
+            | RV32I::SLLI
+            | RV32I::SRLI
+            | RV32I::SRAI
+            | RV32I::ADD
+            | RV32I::SUB
+            | RV32I::SLL
+            | RV32I::SLT
+            | RV32I::SLTU
+            | RV32I::XOR
+            | RV32I::SRL
+            | RV32I::SRA
+            | RV32I::OR
+            | RV32I::AND => instr.rd == Some(0),
+            _ => false,
+        };
+        if is_hint {
+            Some(Hint::Hint)
+        } else {
+            None
+        }
+    }
+}
+
+// addi x0, x0, 0
+const NOP: u32 = 0x0000_0013;
+
+impl Instruction {
+    /// Decode RV32I Instruction
+    pub fn new(raw_instr: u32) -> Self {
+        let instr = InstrType::decode(raw_instr);
+
+        // Get registers IDs
+        let rd = if instr.has_rd() {
+            Some(((raw_instr & 0x0000_0f80) >> 7) as u8)
+        } else {
+            None
+        };
+        let rs1 = if instr.has_rs1() {
+            Some(((raw_instr & 0x000f_8000) >> 15) as u8)
+        } else {
+            None
+        };
+        let rs2 = if instr.has_rs2() {
+            Some(((raw_instr & 0x01f0_0000) >> 20) as u8)
+        } else {
+            None
+        };
+
+        // Get shift amount
+        let shamt = if instr.has_option() {
+            Some(((raw_instr & 0x01f0_0000) >> 20) as u8)
+        } else {
+            None
+        };
+
+        // Get immediate
+        let imm: Option<i32> = if shamt.is_none() && instr.has_operands() {
+            match instr.instr_type {
+                RVT::I => Some((raw_instr & 0xfff0_0000) as i32 >> 20),
+                RVT::S => Some(
+                    ((raw_instr & 0xfe00_0000) as i32 >> 20)
+                        | ((raw_instr & 0x0000_0f80) as i32 >> 7),
+                ),
+                RVT::B => Some(
+                    ((raw_instr & 0x8000_0000) as i32 >> 19)
+                        | ((raw_instr & 0x7e00_0000) as i32 >> 20)
+                        | ((raw_instr & 0x0000_0f00) as i32 >> 7)
+                        | (((raw_instr & 0x0000_0080) as i32) << 4),
+                ),
+                RVT::U => Some((raw_instr & 0xffff_f000) as i32),
+                RVT::J => Some(
+                    ((raw_instr & 0x7fe0_0000) as i32 >> 20)
+                        | ((raw_instr & 0x0010_0000) as i32 >> 9)
+                        | (raw_instr & 0x000f_f000) as i32
+                        | ((raw_instr & 0x8000_0000) as i32 >> 11),
+                ),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        // CSR instructions hold the register address in the immediate field
+        // and replace rs1 with a 5 bit unsigned immediate
+        let (imm, csr) = if instr.is_csr() {
+            let csr = Some((raw_instr >> 20) as u16);
+            if instr.is_csr_imm() {
+                (Some(((raw_instr & 0x000f_8000) >> 15) as i32), csr)
+            } else {
+                (None, csr)
+            }
+        } else {
+            (imm, None)
+        };
+
+        let mut instruction = Instruction {
+            instr,
+            rd,
+            rs1,
+            rs2,
+            shamt,
+            imm,
+            csr,
+            hint: None,
+            builder_type: None,
+        };
+        instruction.hint = Hint::of(&instruction, raw_instr);
+        instruction
+    }
+
+    /// Decode an RV32I instruction, rejecting the words which don't encode
+    /// any
+    pub fn decode(raw_instr: u32) -> Result<Self, DecodeError> {
+        let instruction = Instruction::new(raw_instr);
+        if instruction.is_valid() {
+            Ok(instruction)
+        } else {
+            Err(DecodeError::InvalidInstruction(raw_instr))
+        }
+    }
+
+    ///Get instruction validity
+    pub fn is_valid(&self) -> bool {
+        self.instr.instr_type != RVT::Invalid
+    }
+
+    /// Get instructon elements
+    pub fn get_rd(&self) -> Option<u8> {
+        self.rd
+    }
+
+    pub fn get_rs1(&self) -> Option<u8> {
+        self.rs1
+    }
+
+    pub fn get_rs2(&self) -> Option<u8> {
+        self.rs2
+    }
+
+    pub fn get_shamt(&self) -> Option<u8> {
+        self.shamt
+    }
+
+    pub fn get_imm(&self) -> Option<i32> {
+        self.imm
+    }
+
+    pub fn get_csr(&self) -> Option<u16> {
+        self.csr
+    }
+
+    /// Get the kind of hint of an encoding without any effect. Only decoded
+    /// instructions are tagged, built ones never are.
+    pub fn hint(&self) -> Option<Hint> {
+        self.hint
+    }
+
+    /// Start building an R type instruction. The fields are set by the
+    /// builder methods and checked by `build`, such as
+    /// `Instruction::r_type(RV32I::ADD).rd(4).rs1(6).rs2(2).build()`.
+    pub fn r_type(op: RV32I) -> Self {
+        Instruction::of_type(op, RVT::R)
+    }
+
+    /// Start building an I type instruction, including loads, jalr, CSR and
+    /// system instructions, such as
+    /// `Instruction::i_type(RV32I::ADDI).rd(4).rs1(3).imm(15).build()`
+    pub fn i_type(op: RV32I) -> Self {
+        Instruction::of_type(op, RVT::I)
+    }
+
+    /// Start building an S type instruction
+    pub fn s_type(op: RV32I) -> Self {
+        Instruction::of_type(op, RVT::S)
+    }
+
+    /// Start building a B type instruction
+    pub fn b_type(op: RV32I) -> Self {
+        Instruction::of_type(op, RVT::B)
+    }
+
+    /// Start building a U type instruction, whose immediate holds the upper
+    /// 20 bits
+    pub fn u_type(op: RV32I) -> Self {
+        Instruction::of_type(op, RVT::U)
+    }
+
+    /// Start building a J type instruction
+    pub fn j_type(op: RV32I) -> Self {
+        Instruction::of_type(op, RVT::J)
+    }
+
+    // Instruction without any field. A format other than the one of the
+    // operation is reported by build.
+    fn of_type(op: RV32I, instr_type: RVT) -> Self {
+        Instruction {
+            instr: InstrType::from_op(op),
+            rd: None,
+            rs1: None,
+            rs2: None,
+            shamt: None,
+            imm: None,
+            csr: None,
+            hint: None,
+            builder_type: Some(instr_type),
+        }
+    }
+
+    /// Finish building an instruction, checking that its operation is of the
+    /// format of the builder and that it has every field of its encoding and
+    /// no other
+    pub fn build(self) -> Result<Self, DecodeError> {
+        let op = self.op();
+        if let Some(instr_type) = self.builder_type {
+            if instr_type != self.instr.instr_type {
+                return Err(DecodeError::WrongType(op, instr_type));
+            }
+        }
+        let has_imm = if self.instr.is_csr() {
+            self.instr.is_csr_imm()
+        } else {
+            self.instr.has_operands() && !self.instr.has_option() && self.instr.instr_type != RVT::R
+        };
+        let fields = [
+            ("rd", self.rd.is_some(), self.instr.has_rd()),
+            ("rs1", self.rs1.is_some(), self.instr.has_rs1()),
+            ("rs2", self.rs2.is_some(), self.instr.has_rs2()),
+            ("shamt", self.shamt.is_some(), self.instr.has_option()),
+            ("imm", self.imm.is_some(), has_imm),
+            ("csr", self.csr.is_some(), self.instr.is_csr()),
+        ];
+        for &(field, set, needed) in &fields {
+            match (set, needed) {
+                (false, true) => return Err(DecodeError::MissingField(op, field)),
+                (true, false) => return Err(DecodeError::ExtraField(op, field)),
+                _ => (),
+            }
+        }
+        for register in [self.rd, self.rs1, self.rs2].iter().flatten() {
+            if *register > 31 {
+                return Err(DecodeError::InvalidRegister(*register));
+            }
+        }
+        Ok(Instruction {
+            builder_type: None,
+            ..self
+        })
+    }
+
+    /// Set the destination register
+    pub fn rd(mut self, rd: u8) -> Self {
+        self.rd = Some(rd);
+        self
+    }
+
+    pub fn rs1(mut self, rs1: u8) -> Self {
+        self.rs1 = Some(rs1);
+        self
+    }
+
+    pub fn rs2(mut self, rs2: u8) -> Self {
+        self.rs2 = Some(rs2);
+        self
+    }
+
+    /// Set the shift amount of slli, srli and srai
+    pub fn shamt(mut self, shamt: u8) -> Self {
+        self.shamt = Some(shamt);
+        self
+    }
+
+    /// Set the immediate, or the unsigned immediate of CSR instructions
+    pub fn imm(mut self, imm: i32) -> Self {
+        self.imm = Some(imm);
+        self
+    }
+
+    pub fn csr(mut self, csr: u16) -> Self {
+        self.csr = Some(csr);
+        self
+    }
+
+    /// Get the decoded operation
+    pub fn op(&self) -> RV32I {
+        self.instr.get_instr_op()
+    }
+
+    /// Get the format of the instruction
+    pub fn instr_type(&self) -> RVT {
+        self.instr.instr_type
+    }
+
+    /// Get the name of the operation, such as addi
+    pub fn mnemonic(&self) -> &'static str {
+        self.op().mnemonic()
+    }
+}
+
+// Name of a CSR, or its number if it has none
+struct CsrName(u16);
+
+impl Display for CsrName {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match get_csr_label(self.0) {
+            Some(label) => write!(f, "{}", label),
+            None => write!(f, "{:#05x}", self.0),
+        }
+    }
+}
+
+// Field of an instruction, or a question mark when a built one lacks it
+struct Field<T>(Option<T>);
+
+impl<T: Display> Display for Field<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(ref value) => write!(f, "{}", value),
+            None => write!(f, "?"),
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(csr) = self.csr {
+            let csr = CsrName(csr);
+            return match self.imm {
+                Some(zimm) => write!(
+                    f,
+                    "{:<8.6}{},{},{}",
+                    self.instr,
+                    Field(self.rd.map(get_register_label)),
+                    csr,
+                    zimm
+                ),
+                None => write!(
+                    f,
+                    "{:<8.6}{},{},{}",
+                    self.instr,
+                    Field(self.rd.map(get_register_label)),
+                    csr,
+                    Field(self.rs1.map(get_register_label))
+                ),
+            };
+        }
+
+        match self.instr.instr_type {
+            RVT::R => write!(
+                f,
+                "{:<8.6}{},{},{}",
+                self.instr,
+                Field(self.rd.map(get_register_label)),
+                Field(self.rs1.map(get_register_label)),
+                Field(self.rs2.map(get_register_label))
+            ),
+            RVT::I if !self.instr.has_operands() => write!(f, "{}", self.instr),
+            RVT::I if self.instr.is_load() => write!(
+                f,
+                "{:<8.6}{},{}({})",
+                self.instr,
+                Field(self.rd.map(get_register_label)),
+                Field(self.imm),
+                Field(self.rs1.map(get_register_label))
+            ),
+            RVT::I => write!(
+                f,
+                "{:<8.6}{},{},{}",
+                self.instr,
+                Field(self.rd.map(get_register_label)),
+                Field(self.rs1.map(get_register_label)),
+                if self.instr.is_shift() {
+                    Field(self.shamt.map(i32::from))
+                } else {
+                    Field(self.imm)
+                }
+            ),
+            RVT::S => write!(
+                f,
+                "{:<8.6}{}, {}({})",
+                self.instr,
+                Field(self.rs2.map(get_register_label)),
+                Field(self.imm),
+                Field(self.rs1.map(get_register_label))
+            ),
+            RVT::B => write!(
+                f,
+                "{:<8.6}{},{},{}",
+                self.instr,
+                Field(self.rs1.map(get_register_label)),
+                Field(self.rs2.map(get_register_label)),
+                Field(self.imm)
+            ),
+            RVT::U => write!(
+                f,
+                "{:<8.6}{},{}",
+                self.instr,
+                Field(self.rd.map(get_register_label)),
+                Field(self.imm)
+            ),
+            RVT::J => write!(
+                f,
+                "{:<8.6}{},{}",
+                self.instr,
+                Field(self.rd.map(get_register_label)),
+                Field(self.imm)
+            ),
+            _ => write!(f, "Invalid!"),
+        }
+    }
+}
+
+impl PartialEq for Instruction {
+    fn eq(&self, other: &Self) -> bool {
+        // Invalid instructions are always the equal regardless of the remaining
+        // parameters
+        if self.instr.instr_type == RVT::Invalid && other.instr.instr_type == RVT::Invalid {
+            return true;
+        }
+
+        if self.instr == other.instr {
+            if self.rd != other.rd {
+                return false;
+            }
+            if self.rs1 != other.rs1 {
+                return false;
+            }
+            if self.rs2 != other.rs2 {
+                return false;
+            }
+            if self.imm != other.imm {
+                return false;
+            }
+            if self.shamt != other.shamt {
+                return false;
+            }
+            if self.csr != other.csr {
+                return false;
+            }
+
+            return true;
+        }
+
+        false
+    }
+}
+
+// Consistent with the equality, so invalid instructions hash the same
+impl Hash for Instruction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.instr.instr_type.hash(state);
+        if self.instr.instr_type == RVT::Invalid {
+            return;
+        }
+        self.instr.hash(state);
+        self.rd.hash(state);
+        self.rs1.hash(state);
+        self.rs2.hash(state);
+        self.imm.hash(state);
+        self.shamt.hash(state);
+        self.csr.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use riscv::*;
+    use std::string::ToString;
+
+    /// Build Instruction object with specific instruction type
+    macro_rules! __create_instruction {
+        (imm, $rd:expr, $rs1:expr, $imm:expr, $op:expr, $option_op:expr) => {
+            Instruction::i_type(op!(RV32_OP_CODES_ARITH_IMM, $op, $option_op))
+                .rd($rd)
+                .rs1($rs1)
+                .imm($imm)
+        };
+
+        (shift_imm, $rd:expr, $rs1:expr, $shift:expr, $op:expr, $option_op:expr) => {
+            Instruction::i_type(op!(RV32_OP_CODES_ARITH_IMM, $op, $option_op))
+                .rd($rd)
+                .rs1($rs1)
+                .shamt($shift)
+        };
+
+        (register, $rd:expr, $rs1:expr, $rs2:expr, $op:expr, $option_op:expr) => {
+            Instruction::r_type(op!(RV32_OP_CODES_ARITH_REG, $op, $option_op))
+                .rd($rd)
+                .rs1($rs1)
+                .rs2($rs2)
+        };
+
+        (load, $rd:expr, $rs1:expr, $imm:expr, $op:expr) => {
+            Instruction::i_type(op!(RV32_OP_CODES_MEM_LD, $op, false))
+                .rd($rd)
+                .rs1($rs1)
+                .imm($imm)
+        };
+
+        (store, $rs2:expr, $rs1:expr, $imm:expr, $op:expr) => {
+            Instruction::s_type(op!(RV32_OP_CODES_MEM_ST, $op, false))
+                .rs1($rs1)
+                .rs2($rs2)
+                .imm($imm)
+        };
+
+        (branch, $rs2:expr, $rs1:expr, $imm:expr, $op:expr) => {
+            Instruction::b_type(op!(RV32_OP_CODES_BR, $op, false))
+                .rs1($rs1)
+                .rs2($rs2)
+                .imm($imm)
+        };
+
+        (jal, $rsd:expr, $imm:expr) => {
+            Instruction::j_type(RV32I::JAL).rd($rsd).imm($imm)
+        };
+
+        (jalr, $rsd:expr, $rs1:expr, $imm:expr) => {
+            Instruction::i_type(RV32I::JALR)
+                .rd($rsd)
+                .rs1($rs1)
+                .imm($imm)
+        };
+
+        (lui, $rsd:expr, $imm:expr) => {
+            Instruction::u_type(RV32I::LUI).rd($rsd).imm($imm)
+        };
+        (auipc, $rsd:expr, $imm:expr) => {
+            Instruction::u_type(RV32I::AUIPC).rd($rsd).imm($imm)
+        };
+    }
+
+    /// Operation of an op code and its functions
+    macro_rules! op {
+        ($op_code:expr, $funct3:expr, $option_op:expr) => {
+            InstrType::decode(
+                u32::from($op_code) | ($funct3 as u32) << 12 | u32::from($option_op) << 30,
+            )
+            .get_instr_op()
+        };
+    }
+
+    /// Generate the standard test every instruction. Create correct object and
+    /// then compare with generated object.
+    macro_rules! generate_test {
+        (
+            $type:tt, $rd:expr, $rs1:expr, $imm_or_rs2:expr, $op:expr, $instr:expr, $option_op:expr
+        ) => {
+            let final_instr = __create_instruction!($type, $rd, $rs1, $imm_or_rs2, $op, $option_op);
+
+            let parsed_instr = Instruction::new($instr);
+            assert_eq!(parsed_instr, final_instr);
+        };
+
+        ($type:tt, $rd:expr, $rs1:expr, $imm_or_rs2:expr, $op:expr, $instr:expr) => {
+            let final_instr = __create_instruction!($type, $rd, $rs1, $imm_or_rs2, $op);
+
+            let parsed_instr = Instruction::new($instr);
+            assert_eq!(parsed_instr, final_instr);
+        };
+
+        (jal, $rd:expr, $imm:expr, $instr:expr) => {
+            let final_instr = __create_instruction!(jal, $rd, $imm);
+
+            let parsed_instr = Instruction::new($instr);
+            assert_eq!(parsed_instr, final_instr);
+        };
+
+        (jalr, $rd:expr, $rs1:expr, $imm:expr, $instr:expr) => {
+            let final_instr = __create_instruction!(jalr, $rd, $rs1, $imm);
+
+            let parsed_instr = Instruction::new($instr);
+            assert_eq!(parsed_instr, final_instr);
+        };
+
+        (lui, $rd:expr, $imm:expr, $instr:expr) => {
+            let final_instr = __create_instruction!(lui, $rd, $imm);
+
+            let parsed_instr = Instruction::new($instr);
+            assert_eq!(parsed_instr, final_instr);
+        };
+
+        (auipc, $rd:expr, $imm:expr, $instr:expr) => {
+            let final_instr = __create_instruction!(auipc, $rd, $imm);
+
+            let parsed_instr = Instruction::new($instr);
+            assert_eq!(parsed_instr, final_instr);
+        };
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Immediate Instruction Tests
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test ADDI instruction with a positive immediate
+    #[test]
+    fn addi_pos() {
+        // ADDI R4 <= R3 + 15
+        generate_test!(imm, 4, 3, 15, 0, 0x00f1_8213, false);
+    }
+
+    /// Test ADDI instruction with a negative immediate
+    #[test]
+    fn addi_neg() {
+        // ADDI R4 <= R3 + (-15)
+        generate_test!(imm, 4, 3, -15, 0, 0xff11_8213, false);
+    }
+
+    /// Test SLTI instruction with a positive immediate
+    #[test]
+    fn slti_pos() {
+        // SLTI R4, R3, 2047
+        generate_test!(imm, 4, 3, 2047, 2, 0x7ff1_a213, false);
+    }
+
+    /// Test SLTI instruction with a negative immediate
+    #[test]
+    fn slti_neg() {
+        // SLTI R4, R3, -1
+        generate_test!(imm, 4, 3, -1, 2, 0xfff1_a213, false);
+    }
+
+    /// Test SLTIU instruction with a positive immediate
+    #[test]
+    fn sltiu_pos() {
+        // SLTI R4, R3, 2047
+        generate_test!(imm, 4, 3, 2047, 3, 0x7ff1_b213, false);
+    }
+
+    /// Test SLTIU instruction with a negative immediate
+    #[test]
+    fn sltiu_neg() {
+        // SLTI R4, R3, -1
+        generate_test!(imm, 4, 3, -1, 3, 0xfff1_b213, false);
+    }
+
+    /// Test XORI instruction with a positive immediate
+    #[test]
+    fn xori_pos() {
+        // XORI R4, R3, 2047
+        generate_test!(imm, 4, 3, 2047, 4, 0x7ff1_c213, false);
+    }
+
+    /// Test XORI instruction with a negative immediate
+    #[test]
+    fn xori_neg() {
+        // XORI R4, R3, -1
+        generate_test!(imm, 4, 3, -1, 4, 0xfff1_c213, false);
+    }
+
+    /// Test ORI instruction with a positive immediate
+    #[test]
+    fn ori_pos() {
+        // ORI R4, R3, 2047
+        generate_test!(imm, 4, 3, 2047, 6, 0x7ff1_e213, false);
+    }
+
+    /// Test ORI instruction with a negative immediate
+    #[test]
+    fn ori_neg() {
+        // ORI R4, R3, -1
+        generate_test!(imm, 4, 3, -1, 6, 0xfff1_e213, false);
+    }
+
+    /// Test ANDI instruction with a positive immediate
+    #[test]
+    fn andi_pos() {
+        // ANDI R4, R3, 2047
+        generate_test!(imm, 4, 3, 2047, 7, 0x7ff1_f213, false);
+    }
+
+    /// Test ANDI instruction with a negative immediate
+    #[test]
+    fn andi_neg() {
+        // ANDI R4, R3, -1
+        generate_test!(imm, 4, 3, -1, 7, 0xfff1_f213, false);
+    }
+
+    /// Test SLLI instruction with a positive immediate
+    #[test]
+    fn slli() {
+        generate_test!(shift_imm, 4, 3, 4, 1, 0x0041_9213, false);
+
+        // The remaining 7 bits must be zero
+        let parsed_instr = Instruction::new(0x6a41_9213);
+        assert_eq!(RV32I::Invalid, parsed_instr.op());
+    }
+
+    /// Test SRLI instruction with a positive immediate
+    #[test]
+    fn srli() {
+        // SRLI R4, R3, 5
+        generate_test!(shift_imm, 4, 3, 5, 5, 0x0051_d213, false);
+    }
+
+    /// Test SRAI instruction with a positive immediate
+    #[test]
+    fn srai() {
+        // SRAI R4, R3, 6
+        generate_test!(shift_imm, 4, 3, 6, 5, 0x4061_d213, true);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Register Instruction Tests
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test ADD decode
+    #[test]
+    fn add() {
+        // ADD R4, R6, R2
+        generate_test!(register, 4, 6, 2, 0, 0x0023_0233, false);
+    }
+
+    /// Test SUB decode
+    #[test]
+    fn sub() {
+        // SUB R4, R6, R2
+        generate_test!(register, 4, 6, 2, 0, 0x4023_0233, true);
+    }
+
+    /// Test SLL decode
+    #[test]
+    fn sll() {
+        // SLL R4, R6, R2
+        generate_test!(register, 4, 6, 2, 1, 0x0023_1233, false);
+    }
+
+    /// Test SLT decode
+    #[test]
+    fn slt() {
+        // SLT R4, R6, R2
+        generate_test!(register, 4, 6, 2, 2, 0x0023_2233, false);
+    }
+
+    /// Test SLTU decode
+    #[test]
+    fn sltu() {
+        // SLTU R4, R6, R2
+        generate_test!(register, 4, 6, 2, 3, 0x0023_3233, false);
+    }
+
+    /// Test XOR decode
+    #[test]
+    fn xor() {
+        // XOR R4, R6, R2
+        generate_test!(register, 4, 6, 2, 4, 0x0023_4233, false);
+    }
+
+    /// Test SRL decode
+    #[test]
+    fn srl() {
+        // SRL R4, R6, R2
+        generate_test!(register, 4, 6, 2, 5, 0x0023_5233, false);
+    }
+
+    /// Test SRA decode
+    #[test]
+    fn sra() {
+        // SRA R4, R6, R2
+        generate_test!(register, 4, 6, 2, 5, 0x4023_5233, true);
+    }
+
+    /// Test OR decode
+    #[test]
+    fn or() {
+        // OR R4, R6, R2
+        generate_test!(register, 4, 6, 2, 6, 0x0023_6233, false);
+    }
+
+    /// Test AND decode
+    #[test]
+    fn and() {
+        // AND R4, R6, R2
+        generate_test!(register, 4, 6, 2, 7, 0x0023_7233, false);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Load Instruction Tests
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test LB detection
+    #[test]
+    fn lb() {
+        // LB R4, 2(R6)
+        generate_test!(load, 4, 6, 2, 0, 0x0023_0203);
+    }
+
+    /// Test LH detection
+    #[test]
+    fn lh() {
+        // LH R4, 2(R6)
+        generate_test!(load, 4, 6, 2, 1, 0x0023_1203);
+    }
+
+    /// Test LW detection
+    #[test]
+    fn lw() {
+        // LW R4, 2(R6)
+        generate_test!(load, 4, 6, 2, 2, 0x0023_2203);
+    }
+
+    /// Test LBU detection
+    #[test]
+    fn lbu() {
+        // LBU R4, 2(R6)
+        generate_test!(load, 4, 6, 2, 4, 0x0023_4203);
+    }
+
+    /// Test LHU detection
+    #[test]
+    fn lhu() {
+        // LHU R4, 2(R6)
+        generate_test!(load, 4, 6, 2, 5, 0x0023_5203);
+    }
+
+    // TODO: Make tests for invalid op codes, and valid op codes and invalid
+    // functions
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Store Instruction Tests
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test SB detection with positive immediate
+    #[test]
+    fn sb_pos() {
+        // SB R4, 2(R6)
+        generate_test!(store, 4, 6, 2, 0, 0x0043_0123);
+        // SB R4, 17(R6)
+        generate_test!(store, 4, 6, 17, 0, 0x0043_08a3);
+        // SB R4, 1073(R6)
+        generate_test!(store, 4, 6, 1073, 0, 0x4243_08a3);
+    }
+
+    #[test]
+    fn sb_neg() {
+        // SB R4, -2(R6)
+        generate_test!(store, 4, 6, -2, 0, 0xfe43_0f23);
+        // SB R4, -17(R6)
+        generate_test!(store, 4, 6, -17, 0, 0xfe43_07a3);
+        // SB R4, -1073(R6)
+        generate_test!(store, 4, 6, -1073, 0, 0xbc43_07a3);
+    }
+
+    /// Test SH detection with positive immediate
+    #[test]
+    fn sh() {
+        // SH R4, 2(R6)
+        generate_test!(store, 4, 6, 2, 1, 0x0043_1123);
+    }
+
+    /// Test SW detection with positive immediate
+    #[test]
+    fn sw() {
+        // SW R4, 2(R6)
+        generate_test!(store, 4, 6, 2, 2, 0x0043_2123);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Branch Instruction Tests
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test BEQ detection
+    #[test]
+    fn beq_pos() {
+        // beqz	a3,8
+        generate_test!(branch, 0, 13, 8, 0, 0x0006_8463);
+    }
+
+    #[test]
+    fn beq_neg() {
+        // beq	r8,r23,-28
+        generate_test!(branch, 9, 15, -28, 0, 0xfe97_82e3);
+    }
+
+    /// Test BNE detection
+    #[test]
+    fn bne() {
+        // bnez	a1,-20
+        generate_test!(branch, 0, 11, -20, 1, 0xfe05_96e3);
+    }
+
+    /// Test BLT detection
+    #[test]
+    fn blt() {
+        // bltz	a1,20
+        generate_test!(branch, 0, 11, 20, 4, 0x0005_ca63);
+    }
+
+    /// Test BGE detection
+    #[test]
+    fn bge() {
+        // bgez	a0,100006d0
+        generate_test!(branch, 0, 10, -16, 5, 0xfe05_58e3);
+    }
+
+    /// Test BLTU detection
+    #[test]
+    fn bltu() {
+        // bltu	a0,a1,-8
+        generate_test!(branch, 11, 10, -8, 6, 0xfeb5_6ce3);
+    }
+
+    /// Test BGEU detection
+    #[test]
+    fn bgeu() {
+        // bleu	a1,a0,10000028
+        generate_test!(branch, 11, 10, 16, 7, 0x00b5_7863);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Jump Instruction Tests
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test JALR detection
+    #[test]
+    fn jalr() {
+        // jr	t0
+        generate_test!(jalr, 0, 5, 0, 0x0002_8067);
+        // ret
+        generate_test!(jalr, 0, 1, 0, 0x0000_8067);
+    }
+
+    /// Test JAL detection
+    #[test]
+    fn jal() {
+        // jal	ra,10000648
+        generate_test!(jal, 1, -136, 0xf79f_f0ef);
+        // jal	ra,10000648
+        generate_test!(jal, 1, -160, 0xf61f_f0ef);
+        // jal	ra,10000648
+        generate_test!(jal, 1, -112, 0xf91f_f0ef);
+        // jal	ra,10000648
+        generate_test!(jal, 1, -76, 0xfb5f_f0ef);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // LUI Instruction Test
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test LUI detection
+    #[test]
+    fn lui() {
+        // lui	a4,0xdead0
+        generate_test!(lui, 14, 0xdead << 16, 0xdead_0737);
+        // lui	a5,0x40000
+        generate_test!(lui, 15, 0x4000 << 16, 0x4000_07b7);
+        // lui	a5,0x10000
+        generate_test!(lui, 15, 0x1000 << 16, 0x1000_07b7);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // AUIPC Instruction Test
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test AUIPC detection
+    #[test]
+    fn auipc() {
+        // auipc	gp,0x70000
+        generate_test!(auipc, 3, 0x7000 << 16, 0x7000_0197);
+        // auipc	sp,0x70008
+        generate_test!(auipc, 2, 0x7000_8000, 0x7000_8117);
+        // auipc	ra,0x0
+        generate_test!(auipc, 1, 0, 0x0000_0097);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // CSR Instruction Tests
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test CSR instructions with a register source
+    #[test]
+    fn csr_register() {
+        // csrw mtvec, t0
+        let parsed_instr = Instruction::new(0x3052_9073);
+        let final_instr = Instruction::i_type(RV32I::CSRRW).rd(0).rs1(5).csr(0x305);
+        assert_eq!(parsed_instr, final_instr);
+        assert_eq!("csrrw   zero,mtvec,t0", parsed_instr.to_string());
+
+        // csrrc a1, 0x7c0, a2
+        let parsed_instr = Instruction::new(0x7c06_35f3);
+        assert_eq!(Some(0x7c0), parsed_instr.get_csr());
+        assert_eq!("csrrc   a1,0x7c0,a2", parsed_instr.to_string());
+    }
+
+    /// Test CSR instructions with an immediate
+    #[test]
+    fn csr_immediate() {
+        // csrrsi a0, mstatus, 8
+        let parsed_instr = Instruction::new(0x3004_6573);
+        let final_instr = Instruction::i_type(RV32I::CSRRSI).rd(10).imm(8).csr(0x300);
+        assert_eq!(parsed_instr, final_instr);
+        assert_eq!("csrrsi  a0,mstatus,8", parsed_instr.to_string());
+    }
+
+    #[test]
+    fn accessors() {
+        // addi x4, x3, 15
+        let decoded = Instruction::new(0x00f1_8213);
+        assert_eq!(RV32I::ADDI, decoded.op());
+        assert_eq!(RVT::I, decoded.instr_type());
+        assert_eq!("addi", decoded.mnemonic());
+
+        // sw x2, 8(x1)
+        let decoded = Instruction::new(0x0020_a423);
+        assert_eq!(RV32I::SW, decoded.op());
+        assert_eq!(RVT::S, decoded.instr_type());
+        assert_eq!("sw", decoded.mnemonic());
+
+        let decoded = Instruction::new(0xffff_ffff);
+        assert_eq!(RVT::Invalid, decoded.instr_type());
+        assert_eq!("Invalid", decoded.mnemonic());
+    }
+
+    /// Test the builder of instructions
+    #[test]
+    fn builder() {
+        let addi = Instruction::i_type(RV32I::ADDI).rd(4).rs1(3).imm(15);
+        assert_eq!(Instruction::new(0x00f1_8213), addi);
+        assert_eq!("addi    tp,gp,15", addi.to_string());
+        assert_eq!(RVT::I, addi.instr_type());
+
+        let sw = Instruction::s_type(RV32I::SW).rs1(1).rs2(2).imm(8);
+        assert_eq!(Instruction::new(0x0020_a423), sw);
+
+        let fence = Instruction::i_type(RV32I::FENCE);
+        assert_eq!(Instruction::new(0x0330_000f), fence);
+    }
+
+    #[test]
+    fn hash() {
+        use std::collections::HashSet;
+
+        let mut decoded = HashSet::new();
+        // addi x4, x3, 15 twice, then two different invalid instructions
+        for raw in &[0x00f1_8213, 0x00f1_8213, 0xffff_ffff, 0x0000_0000] {
+            decoded.insert(Instruction::new(*raw));
+        }
+        assert_eq!(2, decoded.len());
+
+        let addi = Instruction::new(0x00f1_8213);
+        let copy = addi;
+        assert!(decoded.contains(&addi) && decoded.contains(&copy));
+    }
+
+    #[test]
+    fn builder_check() {
+        let addi = Instruction::i_type(RV32I::ADDI).rd(4).rs1(3).imm(15);
+        assert_eq!(Ok(Instruction::new(0x00f1_8213)), addi.build());
+        let csrrsi = Instruction::i_type(RV32I::CSRRSI).rd(10).imm(8).csr(0x300);
+        assert_eq!(Ok(Instruction::new(0x3004_6573)), csrrsi.build());
+        assert!(Instruction::i_type(RV32I::SLLI)
+            .rd(1)
+            .rs1(1)
+            .shamt(3)
+            .build()
+            .is_ok());
+
+        assert_eq!(
+            Err(DecodeError::WrongType(RV32I::ADD, RVT::I)),
+            Instruction::i_type(RV32I::ADD).build()
+        );
+        assert_eq!(
+            "add is not of type I",
+            DecodeError::WrongType(RV32I::ADD, RVT::I).to_string()
+        );
+        assert_eq!(
+            Err(DecodeError::MissingField(RV32I::ADD, "rs1")),
+            Instruction::r_type(RV32I::ADD).rd(1).build()
+        );
+        assert_eq!(
+            Err(DecodeError::ExtraField(RV32I::ADD, "imm")),
+            Instruction::r_type(RV32I::ADD)
+                .rd(1)
+                .rs1(2)
+                .rs2(3)
+                .imm(4)
+                .build()
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidRegister(32)),
+            Instruction::r_type(RV32I::ADD).rd(32).rs1(2).rs2(3).build()
+        );
+        // Printing an unfinished instruction doesn't panic
+        assert_eq!(
+            "add     ra,?,?",
+            Instruction::r_type(RV32I::ADD).rd(1).to_string()
+        );
+    }
+
+    /// Test environment calls and breakpoints
+    #[test]
+    fn environment() {
+        let parsed_instr = Instruction::new(0x0000_0073);
+        let final_instr = Instruction::i_type(RV32I::ECALL);
+        assert_eq!(parsed_instr, final_instr);
+        assert_eq!("ecall", parsed_instr.to_string());
+        assert_eq!("ebreak", Instruction::new(0x0010_0073).to_string());
+
+        assert_eq!("mret", Instruction::new(0x3020_0073).to_string());
+        assert_eq!("wfi", Instruction::new(0x1050_0073).to_string());
+        // fence rw, rw
+        let fence = Instruction::new(0x0330_000f);
+        assert_eq!("fence", fence.to_string());
+        assert_eq!((None, None), (fence.get_rd(), fence.get_imm()));
+
+        // Unknown system instructions
+        assert_eq!(RV32I::Invalid, Instruction::new(0x1020_0073).op());
+    }
+
+    /// Test the canonical NOP and the other encodings of the HINT space
+    #[test]
+    fn hints() {
+        assert_eq!(Some(Hint::Nop), Instruction::new(0x0000_0013).hint());
+        // addi x0, x0, 1
+        assert_eq!(Some(Hint::Hint), Instruction::new(0x0010_0013).hint());
+        // add x0, a0, a1 and lui x0, 1
+        assert_eq!(Some(Hint::Hint), Instruction::new(0x00b5_0033).hint());
+        assert_eq!(Some(Hint::Hint), Instruction::new(0x0000_1037).hint());
+        // pause is a fence without successors
+        assert_eq!(Some(Hint::Hint), Instruction::new(0x0100_000f).hint());
+
+        // addi a0, x0, 0, fence rw, rw and jal x0, 0
+        assert_eq!(None, Instruction::new(0x0000_0513).hint());
+        assert_eq!(None, Instruction::new(0x0330_000f).hint());
+        assert_eq!(None, Instruction::new(0x0000_006f).hint());
+        assert_eq!(None, Instruction::new(0x0000_0000).hint());
+        // Built instructions compare equal to decoded ones
+        assert_eq!(
+            Instruction::i_type(RV32I::ADDI).rd(0).rs1(0).imm(0),
+            Instruction::new(0x0000_0013)
+        );
+    }
+
+    /// Test decoding which rejects the invalid words
+    #[test]
+    fn decode_invalid() {
+        assert_eq!(
+            Ok(Instruction::new(0x0000_0013)),
+            Instruction::decode(0x0000_0013)
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidInstruction(0xffff_ffff)),
+            Instruction::decode(0xffff_ffff)
+        );
+        assert_eq!(
+            "invalid instruction 0xffffffff",
+            DecodeError::InvalidInstruction(0xffff_ffff).to_string()
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! Breakdown of an encoding into its bit fields, following the format of the
+//! instruction: the opcode, the functions, the registers and the segments of
+//! the immediate, from the most significant bits down.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_core::riscv::fields::{fields, table};
+//! // add a0, a1, a2
+//! let fields = fields(0x00c5_8533);
+//! assert_eq!("funct7[31:25]", fields[0].label());
+//! assert_eq!(12, fields[1].value);
+//! assert_eq!(
+//!     "| funct7[31:25] | rs2[24:20] | rs1[19:15] | funct3[14:12] | rd[11:7] | opcode[6:0] |\n\
+//!      | 0000000       | 01100      | 01011      | 000           | 01010    | 0110011     |\n",
+//!     table(0x00c5_8533)
+//! );
+//! ```
+use super::decoder::Instruction;
+use super::isa::RVT;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Bits of an encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    /// Most significant bit of the field in the encoding
+    pub msb: u8,
+    /// Least significant bit of the field in the encoding
+    pub lsb: u8,
+    /// The bits, shifted down
+    pub value: u32,
+}
+
+impl Field {
+    fn new(raw: u32, name: &'static str, msb: u8, lsb: u8) -> Self {
+        let width = u32::from(msb - lsb) + 1;
+        Field {
+            name,
+            msb,
+            lsb,
+            value: (raw >> lsb) & (((1u64 << width) - 1) as u32),
+        }
+    }
+
+    /// Name and bits of the field, such as `rd[11:7]`
+    pub fn label(&self) -> String {
+        if self.msb == self.lsb {
+            format!("{}[{}]", self.name, self.msb)
+        } else {
+            format!("{}[{}:{}]", self.name, self.msb, self.lsb)
+        }
+    }
+
+    /// The bits in binary, as wide as the field
+    pub fn bits(&self) -> String {
+        format!(
+            "{:0width$b}",
+            self.value,
+            width = usize::from(self.msb - self.lsb) + 1
+        )
+    }
+}
+
+/// Split an encoding into the fields of its format. Encodings of an unknown
+/// major opcode only have the opcode.
+///
+/// # Return Value
+/// The fields from bit 31 down to bit 0
+pub fn fields(raw: u32) -> Vec<Field> {
+    let decoded = Instruction::new(raw);
+    let field = |name, msb, lsb| Field::new(raw, name, msb, lsb);
+    let low = |rd: &'static str| {
+        vec![
+            field("funct3", 14, 12),
+            field(rd, 11, 7),
+            field("opcode", 6, 0),
+        ]
+    };
+    let mut fields = match decoded.instr_type() {
+        RVT::R => vec![
+            field("funct7", 31, 25),
+            field("rs2", 24, 20),
+            field("rs1", 19, 15),
+        ],
+        RVT::I if decoded.get_shamt().is_some() => vec![
+            field("funct7", 31, 25),
+            field("shamt", 24, 20),
+            field("rs1", 19, 15),
+        ],
+        RVT::I if decoded.get_csr().is_some() && decoded.get_rs1().is_none() => {
+            vec![field("csr", 31, 20), field("uimm", 19, 15)]
+        }
+        RVT::I if decoded.get_csr().is_some() => vec![field("csr", 31, 20), field("rs1", 19, 15)],
+        RVT::I => vec![field("imm", 31, 20), field("rs1", 19, 15)],
+        RVT::S => {
+            return vec![
+                field("imm", 31, 25),
+                field("rs2", 24, 20),
+                field("rs1", 19, 15),
+                field("funct3", 14, 12),
+                field("imm", 11, 7),
+                field("opcode", 6, 0),
+            ]
+        }
+        RVT::B => {
+            return vec![
+                Field {
+                    name: "imm12",
+                    ..field("imm", 31, 31)
+                },
+                field("imm", 30, 25),
+                field("rs2", 24, 20),
+                field("rs1", 19, 15),
+                field("funct3", 14, 12),
+                field("imm", 11, 8),
+                Field {
+                    name: "imm11",
+                    ..field("imm", 7, 7)
+                },
+                field("opcode", 6, 0),
+            ]
+        }
+        RVT::U => {
+            return vec![
+                field("imm", 31, 12),
+                field("rd", 11, 7),
+                field("opcode", 6, 0),
+            ]
+        }
+        RVT::J => {
+            return vec![
+                Field {
+                    name: "imm20",
+                    ..field("imm", 31, 31)
+                },
+                field("imm", 30, 21),
+                Field {
+                    name: "imm11",
+                    ..field("imm", 20, 20)
+                },
+                field("imm", 19, 12),
+                field("rd", 11, 7),
+                field("opcode", 6, 0),
+            ]
+        }
+        RVT::Invalid => return vec![field("bits", 31, 7), field("opcode", 6, 0)],
+    };
+    fields.extend(low("rd"));
+    fields
+}
+
+/// Table of the fields of an encoding, a row with their names and bits and
+/// a row with their values in binary
+pub fn table(raw: u32) -> String {
+    let fields = fields(raw);
+    let (mut labels, mut values) = (String::from("|"), String::from("|"));
+    for field in &fields {
+        let (label, bits) = (field.label(), field.bits());
+        let width = label.len().max(bits.len());
+        labels.push_str(&format!(" {:width$} |", label, width = width));
+        values.push_str(&format!(" {:width$} |", bits, width = width));
+    }
+    format!("{}\n{}\n", labels, values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn labels(raw: u32) -> Vec<String> {
+        fields(raw).iter().map(Field::label).collect()
+    }
+
+    #[test]
+    fn test_formats() {
+        // addi a0, a0, -1
+        let addi = fields(0xfff5_0513);
+        assert_eq!("imm[31:20]", addi[0].label());
+        assert_eq!(0xfff, addi[0].value);
+        assert_eq!(
+            vec![
+                "funct7[31:25]",
+                "shamt[24:20]",
+                "rs1[19:15]",
+                "funct3[14:12]",
+                "rd[11:7]",
+                "opcode[6:0]"
+            ],
+            labels(0x0035_1513) // slli a0, a0, 3
+        );
+        assert_eq!("csr[31:20]", labels(0x3000_2573)[0]); // csrr a0, mstatus
+        assert_eq!("uimm[19:15]", labels(0x3002_e073)[1]); // csrsi mstatus, 5
+        assert_eq!(
+            vec![
+                "imm[31:25]",
+                "rs2[24:20]",
+                "rs1[19:15]",
+                "funct3[14:12]",
+                "imm[11:7]",
+                "opcode[6:0]"
+            ],
+            labels(0x00a1_2223) // sw a0, 4(sp)
+        );
+        assert_eq!(vec!["bits[31:7]", "opcode[6:0]"], labels(0x0000_007f));
+    }
+
+    #[test]
+    fn test_immediate_segments() {
+        // beq zero, zero, -4
+        let branch = fields(0xfe00_0ee3);
+        assert_eq!(
+            vec![
+                ("imm12[31]".to_string(), 1),
+                ("imm[30:25]".to_string(), 0x3f),
+                ("imm[11:8]".to_string(), 0xe),
+                ("imm11[7]".to_string(), 1)
+            ],
+            branch
+                .iter()
+                .filter(|field| field.name.starts_with("imm"))
+                .map(|field| (field.label(), field.value))
+                .collect::<Vec<_>>()
+        );
+        // jal ra, 8
+        assert_eq!(
+            vec![
+                "imm20[31]",
+                "imm[30:21]",
+                "imm11[20]",
+                "imm[19:12]",
+                "rd[11:7]",
+                "opcode[6:0]"
+            ],
+            labels(0x0080_00ef)
+        );
+        // lui a0, 0x12345
+        assert_eq!("00010010001101000101", fields(0x1234_5537)[0].bits());
+    }
+}
@@ -1,19 +1,64 @@
 //! The RISC-V Instruction Set
 use super::*;
 
-use std::fmt::{self, Display, Formatter};
+use core::fmt::{self, Display, Formatter};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Eq, PartialEq)]
+// Mask, match and operation of every encoding, grouped by the bits 6 to 2 of
+// the major opcode. Generated by the build script from the riscv-opcodes
+// files in opcodes/.
+include!(concat!(env!("OUT_DIR"), "/opcodes.rs"));
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InstrType {
     pub instr_type: RVT,
     instr_op: RV32I,
 }
 
 impl InstrType {
-    pub fn new(op_code: u8, funct3: u8, option_op: bool) -> Self {
+    /// Translate a raw instruction. Every bit not belonging to an operand must
+    /// match the encoding of the operation, otherwise it is invalid.
+    pub fn decode(raw_instr: u32) -> Self {
+        let op_code = (raw_instr & 0x7f) as u8;
+        let instr_op = if raw_instr & 3 == 3 {
+            OPCODES[(raw_instr >> 2 & 0x1f) as usize]
+                .iter()
+                .find(|(mask, value, _)| raw_instr & mask == *value)
+                .map_or(RV32I::Invalid, |(_, _, instr_op)| *instr_op)
+        } else {
+            RV32I::Invalid
+        };
         InstrType {
             instr_type: RVT::new(op_code),
-            instr_op: RV32I::new(op_code, funct3, option_op),
+            instr_op,
+        }
+    }
+
+    /// Type of an operation
+    pub fn from_op(instr_op: RV32I) -> Self {
+        let instr_type = match instr_op {
+            RV32I::ADD
+            | RV32I::SUB
+            | RV32I::SLL
+            | RV32I::SLT
+            | RV32I::SLTU
+            | RV32I::XOR
+            | RV32I::SRL
+            | RV32I::SRA
+            | RV32I::OR
+            | RV32I::AND => RVT::R,
+            RV32I::SB | RV32I::SH | RV32I::SW => RVT::S,
+            RV32I::BEQ | RV32I::BNE | RV32I::BLT | RV32I::BGE | RV32I::BLTU | RV32I::BGEU => RVT::B,
+            RV32I::LUI | RV32I::AUIPC => RVT::U,
+            RV32I::JAL => RVT::J,
+            RV32I::Invalid => RVT::Invalid,
+            _ => RVT::I,
+        };
+        InstrType {
+            instr_type,
+            instr_op,
         }
     }
 
@@ -24,18 +69,22 @@ impl InstrType {
 
     /// Check if instruction has a destination register
     pub fn has_rd(&self) -> bool {
-        self.instr_type == RVT::R
+        (self.instr_type == RVT::R
             || self.instr_type == RVT::I
             || self.instr_type == RVT::U
-            || self.instr_type == RVT::J
+            || self.instr_type == RVT::J)
+            && self.has_operands()
     }
 
-    /// Check if instruction has a register source 1
+    /// Check if instruction has a register source 1. CSR instructions with an
+    /// immediate use the register source 1 field for the immediate.
     pub fn has_rs1(&self) -> bool {
-        self.instr_type == RVT::R
+        (self.instr_type == RVT::R
             || self.instr_type == RVT::I
             || self.instr_type == RVT::S
-            || self.instr_type == RVT::B
+            || self.instr_type == RVT::B)
+            && !self.is_csr_imm()
+            && self.has_operands()
     }
 
     /// Check if instruction has a register source 2
@@ -55,13 +104,45 @@ impl InstrType {
         self.instr_op == RV32I::SLLI || self.instr_op == RV32I::SRAI || self.instr_op == RV32I::SRLI
     }
 
+    /// Check if instruction accesses a control and status register
+    pub fn is_csr(&self) -> bool {
+        self.instr_op == RV32I::CSRRW
+            || self.instr_op == RV32I::CSRRS
+            || self.instr_op == RV32I::CSRRC
+            || self.is_csr_imm()
+    }
+
+    /// Check if instruction accesses a control and status register with an
+    /// immediate instead of a register source
+    pub fn is_csr_imm(&self) -> bool {
+        self.instr_op == RV32I::CSRRWI
+            || self.instr_op == RV32I::CSRRSI
+            || self.instr_op == RV32I::CSRRCI
+    }
+
+    /// Check if instruction requests a service from the execution
+    /// environment
+    pub fn is_environment(&self) -> bool {
+        self.instr_op == RV32I::ECALL || self.instr_op == RV32I::EBREAK
+    }
+
+    /// Check if instruction uses its register and immediate fields. Fences
+    /// are executed in order anyway, so their fields are ignored.
+    pub fn has_operands(&self) -> bool {
+        !self.is_environment()
+            && self.instr_op != RV32I::MRET
+            && self.instr_op != RV32I::WFI
+            && self.instr_op != RV32I::FENCE
+    }
+
     pub fn get_instr_op(&self) -> RV32I {
         self.instr_op
     }
 }
 
 /// Instruction Register Types
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum RVT {
     /// Register Type
     R,
@@ -100,6 +181,10 @@ impl RVT {
             RV32_OP_CODES_ARITH_REG => RVT::R,
             // Immediate operations
             RV32_OP_CODES_ARITH_IMM => RVT::I,
+            // System operations
+            RV32_OP_CODES_SYSTEM => RVT::I,
+            // Fences
+            RV32_OP_CODES_MISC_MEM => RVT::I,
             _ => RVT::Invalid,
         }
     }
@@ -113,6 +198,13 @@ impl Display for InstrType {
 
 impl Display for RV32I {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.mnemonic().fmt(f)
+    }
+}
+
+impl RV32I {
+    /// Name of the operation in assembly
+    pub fn mnemonic(self) -> &'static str {
         match self {
             RV32I::ADD => "add",
             RV32I::ADDI => "addi",
@@ -125,6 +217,17 @@ impl Display for RV32I {
             RV32I::BLT => "blt",
             RV32I::BLTU => "bltu",
             RV32I::BNE => "bne",
+            RV32I::CSRRC => "csrrc",
+            RV32I::CSRRCI => "csrrci",
+            RV32I::CSRRS => "csrrs",
+            RV32I::CSRRSI => "csrrsi",
+            RV32I::CSRRW => "csrrw",
+            RV32I::CSRRWI => "csrrwi",
+            RV32I::EBREAK => "ebreak",
+            RV32I::ECALL => "ecall",
+            RV32I::FENCE => "fence",
+            RV32I::MRET => "mret",
+            RV32I::WFI => "wfi",
             RV32I::Invalid => "Invalid",
             RV32I::JAL => "jal",
             RV32I::JALR => "jalr",
@@ -152,14 +255,13 @@ impl Display for RV32I {
             RV32I::SW => "sw",
             RV32I::XOR => "xor",
             RV32I::XORI => "xori",
-        }.fmt(f)
+        }
     }
 }
 
 /// RISC-V 32-bit ISA
-// Copy trait is implemented for this struct to be taken from InstrType
-// struct when using it to create pseudoinstructions
-#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum RV32I {
     //////////////
     // Arithmetic
@@ -217,94 +319,25 @@ pub enum RV32I {
     LUI,
     AUIPC,
 
-    Invalid,
-}
+    //////////////
+    // System
+    //////////////
+    // Control and status registers
+    CSRRW,
+    CSRRS,
+    CSRRC,
+    CSRRWI,
+    CSRRSI,
+    CSRRCI,
+    ECALL,
+    EBREAK,
+    // Trap return and wait for interrupt
+    MRET,
+    WFI,
+    // Memory ordering
+    FENCE,
 
-impl RV32I {
-    /// Translate the OP code and its function into an Instruction enum
-    fn new(op_code: u8, funct3: u8, option_op: bool) -> Self {
-        match op_code {
-            // LUI
-            RV32_OP_CODES_LUI => RV32I::LUI,
-            // AUIPC
-            RV32_OP_CODES_AUIPC => RV32I::AUIPC,
-            // Jumps
-            RV32_OP_CODES_JAL => RV32I::JAL,
-            RV32_OP_CODES_JALR => match funct3 {
-                0 => RV32I::JALR,
-                _ => RV32I::Invalid,
-            },
-            // Branches
-            RV32_OP_CODES_BR => match funct3 {
-                0 => RV32I::BEQ,
-                1 => RV32I::BNE,
-                4 => RV32I::BLT,
-                5 => RV32I::BGE,
-                6 => RV32I::BLTU,
-                7 => RV32I::BGEU,
-                _ => RV32I::Invalid,
-            },
-            // Loads
-            RV32_OP_CODES_MEM_LD => match funct3 {
-                0 => RV32I::LB,
-                1 => RV32I::LH,
-                2 => RV32I::LW,
-                4 => RV32I::LBU,
-                5 => RV32I::LHU,
-                _ => RV32I::Invalid,
-            },
-            // Stores
-            RV32_OP_CODES_MEM_ST => match funct3 {
-                0 => RV32I::SB,
-                1 => RV32I::SH,
-                2 => RV32I::SW,
-                _ => RV32I::Invalid,
-            },
-            // Register operations
-            RV32_OP_CODES_ARITH_REG => match funct3 {
-                0 => {
-                    if !option_op {
-                        RV32I::ADD
-                    } else {
-                        RV32I::SUB
-                    }
-                }
-                1 => RV32I::SLL,
-                2 => RV32I::SLT,
-                3 => RV32I::SLTU,
-                4 => RV32I::XOR,
-                5 => {
-                    if option_op {
-                        RV32I::SRA
-                    } else {
-                        RV32I::SRL
-                    }
-                }
-                6 => RV32I::OR,
-                7 => RV32I::AND,
-                _ => RV32I::Invalid,
-            },
-            // Immediate operations
-            RV32_OP_CODES_ARITH_IMM => match funct3 {
-                0 => RV32I::ADDI,
-                1 => RV32I::SLLI,
-                2 => RV32I::SLTI,
-                3 => RV32I::SLTIU,
-                4 => RV32I::XORI,
-                5 => {
-                    if option_op {
-                        RV32I::SRAI
-                    } else {
-                        RV32I::SRLI
-                    }
-                }
-                6 => RV32I::ORI,
-                7 => RV32I::ANDI,
-                _ => RV32I::Invalid,
-            },
-            _ => RV32I::Invalid,
-        }
-    }
+    Invalid,
 }
 
 #[cfg(test)]
@@ -321,6 +354,12 @@ mod tests {
         };
     }
 
+    /// Raw instruction of an op code and its functions, with the other bits
+    /// cleared
+    fn raw(op_code: u8, funct3: u8, option_op: bool) -> u32 {
+        u32::from(op_code) | u32::from(funct3) << 12 | u32::from(option_op) << 30
+    }
+
     /// Generate the standard test for most instructions. Create correct object
     /// and then compare with generated object.
     macro_rules! generate_test {
@@ -329,9 +368,9 @@ mod tests {
             let final_instr_type = __create_instrtype!($type, $op);
 
             $(
-                let parsed_instr_type = InstrType::new($op_code, $x, false);
+                let parsed_instr_type = InstrType::decode(raw($op_code, $x, false));
                 assert_eq!(parsed_instr_type, final_instr_type);
-                let parsed_instr_type = InstrType::new($op_code, $x, true);
+                let parsed_instr_type = InstrType::decode(raw($op_code, $x, true));
                 assert_eq!(parsed_instr_type, final_instr_type);
             )*
         }};
@@ -345,7 +384,7 @@ mod tests {
         ($type:expr, $op:expr, $op_code:expr, $funct:expr, $option_op:expr) => {{
             let final_instr_type = __create_instrtype!($type, $op);
 
-            let parsed_instr_type = InstrType::new($op_code, $funct, $option_op);
+            let parsed_instr_type = InstrType::decode(raw($op_code, $funct, $option_op));
             assert_eq!(parsed_instr_type, final_instr_type);
         }};
     }
@@ -402,11 +441,11 @@ mod tests {
     }
 
     /// Test SLLI detection
-    /// When creating the InstrType instance the third argument for this
-    /// instruction is don't care.
+    /// When creating the InstrType instance the third argument must be false,
+    /// as the upper bits of the immediate are zero.
     #[test]
     fn slli() {
-        generate_test!(RVT::I, RV32I::SLLI, RV32_OP_CODES_ARITH_IMM, 1);
+        generate_test!(RVT::I, RV32I::SLLI, RV32_OP_CODES_ARITH_IMM, 1, false);
     }
 
     /// Test SRLI detection
@@ -443,25 +482,25 @@ mod tests {
     /// Test SLL detection
     #[test]
     fn sll() {
-        generate_test!(RVT::R, RV32I::SLL, RV32_OP_CODES_ARITH_REG, 1);
+        generate_test!(RVT::R, RV32I::SLL, RV32_OP_CODES_ARITH_REG, 1, false);
     }
 
     /// Test SLT detection
     #[test]
     fn slt() {
-        generate_test!(RVT::R, RV32I::SLT, RV32_OP_CODES_ARITH_REG, 2);
+        generate_test!(RVT::R, RV32I::SLT, RV32_OP_CODES_ARITH_REG, 2, false);
     }
 
     /// Test SLTU detection
     #[test]
     fn sltu() {
-        generate_test!(RVT::R, RV32I::SLTU, RV32_OP_CODES_ARITH_REG, 3);
+        generate_test!(RVT::R, RV32I::SLTU, RV32_OP_CODES_ARITH_REG, 3, false);
     }
 
     /// Test XOR detection
     #[test]
     fn xor() {
-        generate_test!(RVT::R, RV32I::XOR, RV32_OP_CODES_ARITH_REG, 4);
+        generate_test!(RVT::R, RV32I::XOR, RV32_OP_CODES_ARITH_REG, 4, false);
     }
 
     /// Test SRL detection
@@ -479,13 +518,26 @@ mod tests {
     /// Test OR detection
     #[test]
     fn or() {
-        generate_test!(RVT::R, RV32I::OR, RV32_OP_CODES_ARITH_REG, 6);
+        generate_test!(RVT::R, RV32I::OR, RV32_OP_CODES_ARITH_REG, 6, false);
     }
 
     /// Test AND detection
     #[test]
     fn and() {
-        generate_test!(RVT::R, RV32I::AND, RV32_OP_CODES_ARITH_REG, 7);
+        generate_test!(RVT::R, RV32I::AND, RV32_OP_CODES_ARITH_REG, 7, false);
+    }
+
+    /// Test the upper bits of the shifts by an immediate and of the register
+    /// operations, which must match the function 7 of the operation
+    #[test]
+    fn invalid_funct7() {
+        generate_test!(RVT::I, RV32I::Invalid, RV32_OP_CODES_ARITH_IMM, 1, true);
+        generate_test!(RVT::R, RV32I::Invalid, RV32_OP_CODES_ARITH_REG, 1, true);
+        generate_test!(RVT::R, RV32I::Invalid, RV32_OP_CODES_ARITH_REG, 7, true);
+        assert_eq!(RV32I::Invalid, InstrType::decode(0x0200_5013).instr_op);
+        assert_eq!(RV32I::Invalid, InstrType::decode(0x0200_0033).instr_op);
+        // Instructions of 16 bits
+        assert_eq!(RV32I::Invalid, InstrType::decode(0x0000_0011).instr_op);
     }
 
     ////////////////////////////////////////////////////////////////////////////////
@@ -652,4 +704,63 @@ mod tests {
     fn auipc() {
         generate_test!(RVT::U, RV32I::AUIPC, RV32_OP_CODES_AUIPC, 0);
     }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // CSR Instruction Tests
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test CSR instructions detection
+    #[test]
+    fn csr() {
+        generate_test!(RVT::I, RV32I::CSRRW, RV32_OP_CODES_SYSTEM, 1);
+        generate_test!(RVT::I, RV32I::CSRRS, RV32_OP_CODES_SYSTEM, 2);
+        generate_test!(RVT::I, RV32I::CSRRC, RV32_OP_CODES_SYSTEM, 3);
+        generate_test!(RVT::I, RV32I::CSRRWI, RV32_OP_CODES_SYSTEM, 5);
+        generate_test!(RVT::I, RV32I::CSRRSI, RV32_OP_CODES_SYSTEM, 6);
+        generate_test!(RVT::I, RV32I::CSRRCI, RV32_OP_CODES_SYSTEM, 7);
+
+        let csrrwi = InstrType::decode(raw(RV32_OP_CODES_SYSTEM, 5, false));
+        assert!(csrrwi.is_csr() && csrrwi.is_csr_imm());
+        assert!(!csrrwi.has_rs1());
+        let csrrs = InstrType::decode(raw(RV32_OP_CODES_SYSTEM, 2, false));
+        assert!(csrrs.is_csr() && !csrrs.is_csr_imm());
+        assert!(csrrs.has_rs1());
+    }
+
+    /// Test invalid system instructions
+    #[test]
+    fn invalid_system() {
+        generate_test!(RVT::I, RV32I::Invalid, RV32_OP_CODES_SYSTEM, 4);
+        assert_eq!(RV32I::Invalid, InstrType::decode(0x4000_0073).instr_op);
+
+        // sret and uret
+        assert_eq!(RV32I::Invalid, InstrType::decode(0x1020_0073).instr_op);
+        assert_eq!(RV32I::Invalid, InstrType::decode(0x0020_0073).instr_op);
+    }
+
+    /// Test trap return, wait for interrupt and fence detection
+    #[test]
+    fn privileged() {
+        let mret = InstrType::decode(0x3020_0073);
+        assert_eq!(__create_instrtype!(RVT::I, RV32I::MRET), mret);
+        assert!(!mret.has_operands() && !mret.has_rd() && !mret.has_rs1());
+        let wfi = InstrType::decode(0x1050_0073);
+        assert_eq!(__create_instrtype!(RVT::I, RV32I::WFI), wfi);
+
+        generate_test!(RVT::I, RV32I::FENCE, RV32_OP_CODES_MISC_MEM, 0);
+        // fence.i belongs to Zifencei
+        generate_test!(RVT::I, RV32I::Invalid, RV32_OP_CODES_MISC_MEM, 1);
+    }
+
+    /// Test environment call and breakpoint detection
+    #[test]
+    fn environment() {
+        let final_instr_type = __create_instrtype!(RVT::I, RV32I::ECALL);
+        assert_eq!(InstrType::decode(0x0000_0073), final_instr_type);
+        let final_instr_type = __create_instrtype!(RVT::I, RV32I::EBREAK);
+        assert_eq!(InstrType::decode(0x0010_0073), final_instr_type);
+
+        let ecall = InstrType::decode(0x0000_0073);
+        assert!(ecall.is_environment());
+        assert!(!ecall.has_rd() && !ecall.has_rs1() && !ecall.has_rs2());
+    }
 }
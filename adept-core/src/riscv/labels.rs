@@ -0,0 +1,192 @@
+use riscv::DecodeError;
+
+// ABI names of the registers, by number
+const REGISTER_LABELS: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", // Fixed roles
+    "t0", "t1", "t2", // Temporaries
+    "s0/fp", "s1", // Saved
+    "a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7", // Arguments
+    "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", // Saved
+    "t3", "t4", "t5", "t6", // Temporaries
+];
+
+/// ABI name of the register of an instruction field. Only the lowest 5 bits
+/// are read, like the fields of the encoding.
+pub fn get_register_label(reg: u8) -> &'static str {
+    REGISTER_LABELS[usize::from(reg & 0x1f)]
+}
+
+/// ABI name of a register, or an error if there is no such register
+pub fn register_label(reg: u8) -> Result<&'static str, DecodeError> {
+    REGISTER_LABELS
+        .get(usize::from(reg))
+        .cloned()
+        .ok_or(DecodeError::InvalidRegister(reg))
+}
+
+/// Number of a register given by its ABI name, as printed by
+/// `get_register_label`, or as xN
+pub fn get_register_number(name: &str) -> Option<u8> {
+    if let Some(number) = name.strip_prefix('x') {
+        return match number.parse::<u8>() {
+            Ok(register) if register < 32 && !number.starts_with('+') => Some(register),
+            _ => None,
+        };
+    }
+    match name {
+        "s0" | "fp" => Some(8),
+        _ => (0..32).find(|register| get_register_label(*register) == name),
+    }
+}
+
+/// Name of a CSR, as printed by the disassembler
+pub fn get_csr_label(csr: u16) -> Option<&'static str> {
+    match csr {
+        // User counters
+        0xc00 => Some("cycle"),
+        0xc01 => Some("time"),
+        0xc02 => Some("instret"),
+        0xc80 => Some("cycleh"),
+        0xc81 => Some("timeh"),
+        0xc82 => Some("instreth"),
+
+        // Machine information
+        0xf11 => Some("mvendorid"),
+        0xf12 => Some("marchid"),
+        0xf13 => Some("mimpid"),
+        0xf14 => Some("mhartid"),
+
+        // Machine trap setup
+        0x300 => Some("mstatus"),
+        0x301 => Some("misa"),
+        0x304 => Some("mie"),
+        0x305 => Some("mtvec"),
+
+        // Machine trap handling
+        0x340 => Some("mscratch"),
+        0x341 => Some("mepc"),
+        0x342 => Some("mcause"),
+        0x343 => Some("mtval"),
+        0x344 => Some("mip"),
+
+        // Machine counters
+        0xb00 => Some("mcycle"),
+        0xb02 => Some("minstret"),
+        0xb80 => Some("mcycleh"),
+        0xb82 => Some("minstreth"),
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    ////////////////////////////////////////////////////////////////////////////////
+    // Registers Printing Test
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test Registers Printing
+    #[test]
+    fn print_registers() {
+        // 0 = zero
+        assert_eq!("zero", super::get_register_label(0));
+        // 1 = ra
+        assert_eq!("ra", super::get_register_label(1));
+        // 2 = sp
+        assert_eq!("sp", super::get_register_label(2));
+        // 3 = gp
+        assert_eq!("gp", super::get_register_label(3));
+        // 4 = tp
+        assert_eq!("tp", super::get_register_label(4));
+        // 5 = t0
+        assert_eq!("t0", super::get_register_label(5));
+        // 6 = t1
+        assert_eq!("t1", super::get_register_label(6));
+        // 7 = t2
+        assert_eq!("t2", super::get_register_label(7));
+        // 8 = s0/fp
+        assert_eq!("s0/fp", super::get_register_label(8));
+        // 9 = s1
+        assert_eq!("s1", super::get_register_label(9));
+        // 10 = a0
+        assert_eq!("a0", super::get_register_label(10));
+        // 11 = a1
+        assert_eq!("a1", super::get_register_label(11));
+        // 12 = a2
+        assert_eq!("a2", super::get_register_label(12));
+        // 13 = a3
+        assert_eq!("a3", super::get_register_label(13));
+        // 14 = a4
+        assert_eq!("a4", super::get_register_label(14));
+        // 15 = a5
+        assert_eq!("a5", super::get_register_label(15));
+        // 16 = a6
+        assert_eq!("a6", super::get_register_label(16));
+        // 17 = a7
+        assert_eq!("a7", super::get_register_label(17));
+        // 18 = s2
+        assert_eq!("s2", super::get_register_label(18));
+        // 19 = s3
+        assert_eq!("s3", super::get_register_label(19));
+        // 20 = s4
+        assert_eq!("s4", super::get_register_label(20));
+        // 21 = s5
+        assert_eq!("s5", super::get_register_label(21));
+        // 22 = s6
+        assert_eq!("s6", super::get_register_label(22));
+        // 23 = s7
+        assert_eq!("s7", super::get_register_label(23));
+        // 24 = s8
+        assert_eq!("s8", super::get_register_label(24));
+        // 25 = s9
+        assert_eq!("s9", super::get_register_label(25));
+        // 26 = s10
+        assert_eq!("s10", super::get_register_label(26));
+        // 27 = s11
+        assert_eq!("s11", super::get_register_label(27));
+        // 28 = t3
+        assert_eq!("t3", super::get_register_label(28));
+        // 29 = t4
+        assert_eq!("t4", super::get_register_label(29));
+        // 30 = t5
+        assert_eq!("t5", super::get_register_label(30));
+        // 31 = t6
+        assert_eq!("t6", super::get_register_label(31));
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Invalid Registers
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test Registers Past x31
+    #[test]
+    fn print_registers_invalid() {
+        assert_eq!(Ok("t6"), super::register_label(31));
+        assert_eq!(
+            Err(super::DecodeError::InvalidRegister(35)),
+            super::register_label(35)
+        );
+        // Fields only hold 5 bits
+        assert_eq!("gp", super::get_register_label(35));
+    }
+
+    /// Test Register Names
+    #[test]
+    fn parse_registers() {
+        assert_eq!(Some(2), super::get_register_number("sp"));
+        assert_eq!(Some(8), super::get_register_number("fp"));
+        assert_eq!(Some(8), super::get_register_number("s0"));
+        assert_eq!(Some(31), super::get_register_number("x31"));
+        assert_eq!(None, super::get_register_number("x32"));
+        assert_eq!(None, super::get_register_number("main"));
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // CSR Printing Test
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test CSR names
+    #[test]
+    fn print_csrs() {
+        assert_eq!(Some("mtvec"), super::get_csr_label(0x305));
+        assert_eq!(Some("mhartid"), super::get_csr_label(0xf14));
+        assert_eq!(None, super::get_csr_label(0x7c0));
+    }
+}
@@ -0,0 +1,53 @@
+//! Helper RISC-V functions for decoding
+
+pub mod decoder;
+#[cfg(feature = "alloc")]
+pub mod fields;
+pub mod isa;
+pub mod labels;
+
+use core::fmt::{self, Display, Formatter};
+
+// Instruction OP codes
+pub const RV32_OP_CODES_ARITH_IMM: u8 = 0x13;
+pub const RV32_OP_CODES_ARITH_REG: u8 = 0x33;
+pub const RV32_OP_CODES_MEM_LD: u8 = 0x03;
+pub const RV32_OP_CODES_MEM_ST: u8 = 0x23;
+pub const RV32_OP_CODES_BR: u8 = 0x63;
+pub const RV32_OP_CODES_JALR: u8 = 0x67;
+pub const RV32_OP_CODES_JAL: u8 = 0x6f;
+pub const RV32_OP_CODES_AUIPC: u8 = 0x17;
+pub const RV32_OP_CODES_LUI: u8 = 0x37;
+pub const RV32_OP_CODES_SYSTEM: u8 = 0x73;
+pub const RV32_OP_CODES_MISC_MEM: u8 = 0x0f;
+
+/// Bits which aren't an instruction or a register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// A word which doesn't encode any RV32I instruction
+    InvalidInstruction(u32),
+    /// A register number past x31
+    InvalidRegister(u8),
+    /// An instruction built with the builder of another format
+    WrongType(isa::RV32I, isa::RVT),
+    /// A built instruction without a field its format needs
+    MissingField(isa::RV32I, &'static str),
+    /// A built instruction with a field its format doesn't have
+    ExtraField(isa::RV32I, &'static str),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidInstruction(raw) => write!(f, "invalid instruction 0x{:08x}", raw),
+            DecodeError::InvalidRegister(reg) => write!(f, "invalid register x{}", reg),
+            DecodeError::WrongType(op, instr_type) => {
+                write!(f, "{} is not of type {:?}", op, instr_type)
+            }
+            DecodeError::MissingField(op, field) => write!(f, "{} is missing its {}", op, field),
+            DecodeError::ExtraField(op, field) => write!(f, "{} has no {}", op, field),
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
@@ -0,0 +1,283 @@
+//! Post-mortem analysis of a commit log. The log is scanned once, a line at
+//! a time, so that the queries can be answered from logs of any size:
+//!
+//! * the instructions retired before the last one at an address, to see how
+//!   the program got there
+//! * every store writing a byte, to find who corrupted it
+//! * the calls in progress at an instruction of the log
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::analysis::{analyze, Queries};
+//! # use adept_lib::replay::records;
+//! // addi a0, zero, 5; sw a0, 0(sp); j 8; nop
+//! let log = b"00000000 00500513\n00000004 00a12023 00000ffc\n00000008 0080006f\n00000010 00000013\n";
+//! let queries = Queries {
+//!     before: Some(0x10),
+//!     count: 2,
+//!     writers: Some(0xffe),
+//!     call_path: None,
+//! };
+//! let report = analyze(records(&log[..]), &queries).unwrap();
+//! assert_eq!(4, report.instructions);
+//! let before: Vec<_> = report.before.unwrap().iter().map(|(_, retired)| retired.pc).collect();
+//! assert_eq!(vec![0x4, 0x8, 0x10], before);
+//! assert_eq!(1, report.writers[0].0);
+//! ```
+use callstack::CallStack;
+use elf::Symbol;
+use replay::{access_size, Retired};
+use riscv::decoder::Instruction;
+use riscv::isa::RV32I;
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+/// Questions about a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Queries {
+    /// Address of an instruction, to list the ones retired before it
+    pub before: Option<u32>,
+    /// Number of instructions listed before it
+    pub count: usize,
+    /// Address of a byte, to find the stores writing it
+    pub writers: Option<u32>,
+    /// Index of an instruction in the log, from 0, to find the calls in
+    /// progress at it
+    pub call_path: Option<u64>,
+}
+
+/// Answers to the queries, with the instructions by index in the log
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Number of instructions scanned
+    pub instructions: u64,
+    /// Instructions retired before the last one at the address, followed by
+    /// it, or None if it never retired
+    pub before: Option<Vec<(u64, Retired)>>,
+    /// Stores writing the byte, in order
+    pub writers: Vec<(u64, Retired)>,
+    /// Instruction at the index and the calls in progress at it, or None if
+    /// the log is shorter
+    pub call_path: Option<(Retired, CallStack)>,
+}
+
+/// Scan a commit log to answer queries
+///
+/// # Arguments
+/// * `records` => retired instructions of the run, as parsed by
+///   `replay::records`
+/// * `queries` => what to look for
+///
+/// # Return Value
+/// The answers, or the first malformed line of the log and why
+pub fn analyze<I>(records: I, queries: &Queries) -> Result<Report, String>
+where
+    I: IntoIterator<Item = Result<Retired, String>>,
+{
+    let mut report = Report::default();
+    let mut window = VecDeque::with_capacity(queries.count + 1);
+    let mut stack = CallStack::new();
+    let mut previous: Option<Retired> = None;
+    for (index, retired) in records.into_iter().enumerate() {
+        let retired = retired?;
+        let index = index as u64;
+        report.instructions += 1;
+
+        if let Some(pc) = queries.before {
+            window.push_back((index, retired));
+            if window.len() > queries.count + 1 {
+                window.pop_front();
+            }
+            if retired.pc == pc {
+                report.before = Some(window.iter().cloned().collect());
+            }
+        }
+
+        if let Some(addr) = queries.writers {
+            if writes(&retired, addr) {
+                report.writers.push((index, retired));
+            }
+        }
+
+        match queries.call_path {
+            Some(target) if index <= target => {
+                // The next pc tells where the previous instruction went
+                if let Some(previous) = previous {
+                    stack.execute(previous.pc, &Instruction::new(previous.raw), retired.pc);
+                }
+                previous = Some(retired);
+                if index == target {
+                    report.call_path = Some((retired, stack.clone()));
+                    // Nothing else needs the rest of the log
+                    if queries.before.is_none() && queries.writers.is_none() {
+                        break;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(report)
+}
+
+/// List instructions of a log, one per line with their index, address,
+/// encoding, disassembly and memory access
+///
+/// # Arguments
+/// * `out` => where to write the listing
+/// * `instructions` => instructions by index in the log
+/// * `functions` => functions sorted by address, to locate the instructions
+pub fn write_listing<W: Write>(
+    out: &mut W,
+    instructions: &[(u64, Retired)],
+    functions: &[Symbol],
+) -> io::Result<()> {
+    for (index, retired) in instructions {
+        write!(out, "{:>10} {:08x}", index, retired.pc)?;
+        if let Some(symbol) = Symbol::lookup(functions, retired.pc) {
+            write!(out, " <{}+0x{:x}>", symbol.name, retired.pc - symbol.value)?;
+        }
+        write!(
+            out,
+            ": {:08x}  {}",
+            retired.raw,
+            Instruction::new(retired.raw)
+        )?;
+        match retired.addr {
+            Some(addr) => writeln!(out, "  [{:08x}]", addr)?,
+            None => writeln!(out)?,
+        }
+    }
+    Ok(())
+}
+
+// A store whose bytes hold the address
+fn writes(retired: &Retired, addr: u32) -> bool {
+    let op = Instruction::new(retired.raw).op();
+    let is_store = op == RV32I::SB || op == RV32I::SH || op == RV32I::SW;
+    match retired.addr {
+        Some(start) if is_store => addr.wrapping_sub(start) < access_size(op),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use replay::read_trace;
+
+    // main calls f twice, and f stores a byte, a half and a word to the
+    // stack before returning
+    const LOG: &[u8] = b"\
+00000100 00500513
+00000104 100000ef
+00000204 00a10023 00000ff0
+00000208 00a11023 00000ff0
+0000020c 00a12023 00000ff0
+00000210 00008067
+00000108 0fc000ef
+00000204 00a10023 00000ff0
+00000208 00a11023 00000ff0
+0000020c 00a12023 00000ff0
+00000210 00008067
+0000010c 00000013
+";
+
+    fn analyze_log(queries: Queries) -> Report {
+        analyze(read_trace(LOG).unwrap().into_iter().map(Ok), &queries).unwrap()
+    }
+
+    fn indexes(instructions: &[(u64, Retired)]) -> Vec<u64> {
+        instructions.iter().map(|(index, _)| *index).collect()
+    }
+
+    #[test]
+    fn test_before() {
+        let report = analyze_log(Queries {
+            before: Some(0x208),
+            count: 3,
+            ..Queries::default()
+        });
+        assert_eq!(12, report.instructions);
+        // The last time the address retired
+        assert_eq!(vec![5, 6, 7, 8], indexes(&report.before.unwrap()));
+        let report = analyze_log(Queries {
+            before: Some(0x300),
+            count: 3,
+            ..Queries::default()
+        });
+        assert!(report.before.is_none());
+    }
+
+    #[test]
+    fn test_writers() {
+        let writers = |addr| {
+            let queries = Queries {
+                writers: Some(addr),
+                ..Queries::default()
+            };
+            indexes(&analyze_log(queries).writers)
+        };
+        assert_eq!(vec![2, 3, 4, 7, 8, 9], writers(0xff0));
+        assert_eq!(vec![3, 4, 8, 9], writers(0xff1));
+        assert_eq!(vec![4, 9], writers(0xff3));
+        assert!(writers(0xff4).is_empty());
+        assert!(writers(0xfef).is_empty());
+    }
+
+    #[test]
+    fn test_call_path() {
+        let call_path = |index| {
+            let queries = Queries {
+                call_path: Some(index),
+                ..Queries::default()
+            };
+            analyze_log(queries).call_path
+        };
+        let functions = vec![
+            Symbol {
+                name: "main".to_string(),
+                value: 0x100,
+                size: 0x10,
+            },
+            Symbol {
+                name: "f".to_string(),
+                value: 0x204,
+                size: 0x10,
+            },
+        ];
+        let (retired, stack) = call_path(8).unwrap();
+        assert_eq!(
+            "#0 0x00000208 in f+0x4\n#1 0x00000108 in main+0x8\n",
+            stack.backtrace(retired.pc, &functions).to_string()
+        );
+        let (retired, stack) = call_path(11).unwrap();
+        assert_eq!((0x10c, 0), (retired.pc, stack.depth()));
+        assert!(call_path(12).is_none());
+    }
+
+    #[test]
+    fn test_listing() {
+        let report = analyze_log(Queries {
+            before: Some(0x204),
+            count: 1,
+            ..Queries::default()
+        });
+        let functions = vec![Symbol {
+            name: "f".to_string(),
+            value: 0x204,
+            size: 0x10,
+        }];
+        let mut out = Vec::new();
+        write_listing(&mut out, &report.before.unwrap(), &functions).unwrap();
+        let listing = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = listing.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("         6 00000108: 0fc000ef  jal"));
+        assert!(lines[1].starts_with("         7 00000204 <f+0x0>: 00a10023  sb"));
+        assert!(lines[1].ends_with("  [00000ff0]"));
+    }
+}
@@ -0,0 +1,188 @@
+//! Results of the standard benchmarks. Dhrystone and CoreMark print their
+//! scores when they finish, which are recognised in the output of the program
+//! so they can be reported next to the statistics of the simulation.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::benchmark::{Benchmark, BenchmarkResult};
+//! let output = "Microseconds for one run through Dhrystone: 12.0\n\
+//!               Dhrystones per Second:                      83333.0\n";
+//! let result = BenchmarkResult::parse(output).unwrap();
+//! assert_eq!(Benchmark::Dhrystone, result.benchmark);
+//! assert!(result.completed);
+//! assert_eq!("83333.0", result.score("Dhrystones per Second").unwrap());
+//! ```
+use std::fmt::{self, Display, Formatter};
+
+/// Supported benchmarks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Benchmark {
+    Dhrystone,
+    CoreMark,
+}
+
+impl Benchmark {
+    const ALL: [Benchmark; 2] = [Benchmark::CoreMark, Benchmark::Dhrystone];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Benchmark::Dhrystone => "Dhrystone",
+            Benchmark::CoreMark => "CoreMark",
+        }
+    }
+
+    // Line printed once the benchmark finishes
+    fn completion_marker(self) -> &'static str {
+        match self {
+            Benchmark::Dhrystone => "Dhrystones per Second",
+            Benchmark::CoreMark => "Correct operation validated.",
+        }
+    }
+
+    // Labels of the reported scores, in the order they are printed
+    fn score_labels(self) -> &'static [&'static str] {
+        match self {
+            Benchmark::Dhrystone => &[
+                "Microseconds for one run through Dhrystone",
+                "Dhrystones per Second",
+            ],
+            Benchmark::CoreMark => &[
+                "Total ticks",
+                "Total time (secs)",
+                "Iterations/Sec",
+                "Iterations",
+                "CoreMark 1.0",
+            ],
+        }
+    }
+}
+
+/// Scores found in the output of a benchmark
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchmarkResult {
+    pub benchmark: Benchmark,
+    /// Set if the benchmark printed that it finished
+    pub completed: bool,
+    /// Label and value of every score, in the order they were printed
+    pub scores: Vec<(String, String)>,
+}
+
+impl BenchmarkResult {
+    /// Find the results of a benchmark in the output of a program
+    ///
+    /// # Arguments
+    /// * `output` => everything the program printed
+    ///
+    /// # Return Value
+    /// The results, or None if the output doesn't belong to a benchmark
+    pub fn parse(output: &str) -> Option<Self> {
+        let benchmark = *Benchmark::ALL
+            .iter()
+            .find(|benchmark| output.contains(benchmark.name()))?;
+
+        let mut scores = Vec::new();
+        for line in output.lines() {
+            let mut parts = line.splitn(2, ':');
+            let label = parts.next().unwrap_or("").trim();
+            let value = match parts.next() {
+                Some(value) => value.trim(),
+                None => continue,
+            };
+            if benchmark.score_labels().contains(&label) {
+                // The CoreMark score is followed by the compiler and the
+                // parameters of the run
+                let value = value.split(" / ").next().unwrap_or(value);
+                scores.push((label.to_string(), value.to_string()));
+            }
+        }
+
+        Some(BenchmarkResult {
+            benchmark,
+            completed: output.contains(benchmark.completion_marker()),
+            scores,
+        })
+    }
+
+    /// Get the value of a score
+    pub fn score(&self, label: &str) -> Option<&str> {
+        self.scores
+            .iter()
+            .find(|(score_label, _)| score_label == label)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl Display for BenchmarkResult {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "benchmark:     {} ({})",
+            self.benchmark.name(),
+            if self.completed {
+                "completed"
+            } else {
+                "incomplete"
+            }
+        )?;
+        for (label, value) in &self.scores {
+            writeln!(f, "{}: {}", label, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COREMARK: &str = "2K performance run parameters for coremark.
+CoreMark Size    : 666
+Total ticks      : 1234567
+Total time (secs): 12.345
+Iterations/Sec   : 81.004
+Iterations       : 1000
+Compiler version : GCC8.3.0
+Correct operation validated. See README.md for run and reporting rules.
+CoreMark 1.0 : 81.004 / GCC8.3.0 -O2 / STACK
+";
+
+    #[test]
+    fn test_coremark() {
+        let result = BenchmarkResult::parse(COREMARK).unwrap();
+        assert_eq!(Benchmark::CoreMark, result.benchmark);
+        assert!(result.completed);
+        assert_eq!(Some("1234567"), result.score("Total ticks"));
+        assert_eq!(Some("81.004"), result.score("Iterations/Sec"));
+        assert_eq!(Some("1000"), result.score("Iterations"));
+        assert_eq!(Some("81.004"), result.score("CoreMark 1.0"));
+        assert_eq!(None, result.score("CoreMark Size"));
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let output = "Dhrystone Benchmark, Version 2.1 (Language: C)\n\
+                      Execution starts, 2000 runs through Dhrystone\n";
+        let result = BenchmarkResult::parse(output).unwrap();
+        assert_eq!(Benchmark::Dhrystone, result.benchmark);
+        assert!(!result.completed);
+        assert!(result.scores.is_empty());
+
+        assert_eq!(None, BenchmarkResult::parse("Hello world!\n"));
+    }
+
+    #[test]
+    fn test_display() {
+        let result = BenchmarkResult::parse(
+            "Microseconds for one run through Dhrystone:    12.5 \n\
+             Dhrystones per Second:                      80000.0 \n",
+        )
+        .unwrap();
+        assert_eq!(
+            "benchmark:     Dhrystone (completed)\n\
+             Microseconds for one run through Dhrystone: 12.5\n\
+             Dhrystones per Second: 80000.0\n",
+            result.to_string()
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! The analyze command, which answers queries about the commit log of a run
+//! in one pass over it.
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+
+use adept_lib::analysis::{analyze, write_listing, Queries, Report};
+use adept_lib::elf::{self, Symbol};
+use adept_lib::replay::records;
+use adept_lib::Error;
+
+use cli::{AnalyzerOptions, GlobalOptions};
+use output_option;
+
+// Instructions listed before the address of --before unless another number
+// is given
+const DEFAULT_COUNT: usize = 20;
+
+/// Analyze the commit log of the command line
+///
+/// # Arguments
+/// * `global` => options of every command
+/// * `options` => options of the command
+pub fn run(global: &GlobalOptions, options: &AnalyzerOptions) -> Result<(), Error> {
+    let queries = Queries {
+        before: options.before,
+        count: options.count.map_or(DEFAULT_COUNT, |count| count as usize),
+        writers: options.writers,
+        call_path: options.call_path,
+    };
+    if queries.before.is_none() && queries.writers.is_none() && queries.call_path.is_none() {
+        warn!("Nothing to look for: give --before, --writers or --call-path");
+    }
+
+    // The functions locate the instructions of the answers
+    let functions = match options.elf {
+        Some(ref path) => match fs::read(path) {
+            Ok(data) => elf::functions(&data),
+            Err(e) => return Err(Error::load(path, e)),
+        },
+        None => Vec::new(),
+    };
+
+    let path = &options.commit_log;
+    info!("Analyzing commit log: {}", path);
+    let report = match File::open(path) {
+        Ok(file) => match analyze(records(BufReader::new(file)), &queries) {
+            Ok(report) => report,
+            Err(e) => return Err(Error::load(path, e)),
+        },
+        Err(e) => return Err(Error::load(path, e)),
+    };
+
+    let mut output = output_option(global.output.as_deref())?;
+    write_report(&mut output, &queries, &report, &functions)
+        .and_then(|_| output.flush())
+        .map_err(|e| Error::runtime("output", e))
+}
+
+// Write the answer of every query asked, in a section of its own
+fn write_report<W: Write>(
+    out: &mut W,
+    queries: &Queries,
+    report: &Report,
+    functions: &[Symbol],
+) -> io::Result<()> {
+    writeln!(out, "{} instructions", report.instructions)?;
+    if let Some(pc) = queries.before {
+        writeln!(out, "\nBefore 0x{:08x}:", pc)?;
+        match report.before {
+            Some(ref instructions) => write_listing(out, instructions, functions)?,
+            None => writeln!(out, "never retired")?,
+        }
+    }
+    if let Some(addr) = queries.writers {
+        writeln!(out, "\nWriters of 0x{:08x}:", addr)?;
+        if report.writers.is_empty() {
+            writeln!(out, "none")?;
+        }
+        write_listing(out, &report.writers, functions)?;
+    }
+    if let Some(index) = queries.call_path {
+        writeln!(out, "\nCall path at {}:", index)?;
+        match report.call_path {
+            Some((retired, ref stack)) => {
+                write!(out, "{}", stack.backtrace(retired.pc, functions))?
+            }
+            None => writeln!(out, "beyond the end of the log")?,
+        }
+    }
+    Ok(())
+}
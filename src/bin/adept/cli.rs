@@ -0,0 +1,1364 @@
+//! Command line of the adept binary. The options of every subcommand are
+//! declared here as a struct, which the command line is parsed into once,
+//! with the numbers, regions and policies already checked and converted.
+//! An option missing from the command line is taken from, in order:
+//!
+//! * its environment variable => `ADEPT_` and the name of the option in
+//!   capitals, such as `ADEPT_TIMEOUT_CYCLES` for --timeout-cycles, for the
+//!   options taking a single value
+//! * the defaults file => a file of `option = value` pairs given by
+//!   --defaults, read like the configuration files. The pairs before the
+//!   first table apply to every subcommand, those in a table named after a
+//!   subcommand only to it and before the others. A flag is set by `true`,
+//!   and an option given several times by an array:
+//!
+//! ```toml
+//! log-level = "warn"
+//!
+//! [run]
+//! timeout-cycles = 1_000_000
+//! load = ["boot.hex", "data.bin@0x2000"]
+//! check-returns = true
+//! ```
+use structopt::clap::App;
+use structopt::StructOpt;
+use tracing::Level;
+
+use std::env;
+use std::ffi::OsString;
+use std::slice;
+use std::time::Duration;
+
+use adept_lib::coherence::Protocol;
+use adept_lib::config::{self, Config, Table, Value};
+use adept_lib::expr::Expr;
+use adept_lib::fault::{BitFlip, RegisterFlip};
+use adept_lib::generator::Mix;
+use adept_lib::hart::{
+    EnvironmentCallPolicy, IllegalInstructionPolicy, MemoryErrorPolicy, UninitializedPolicy,
+};
+use adept_lib::mem::{MAX_ADDRESS_BITS, MIN_ADDRESS_BITS};
+use adept_lib::prefetch::PrefetchKind;
+use adept_lib::profile::ProfileSort;
+use adept_lib::stats::StallReason;
+use adept_lib::{cache, Error};
+
+use parse_number;
+
+// Global options taking a value, which may come before the subcommand
+const GLOBAL_VALUES: [&str; 4] = ["--log-level", "--log-file", "--output", "--defaults"];
+
+// Options taking every value which follows them, given last by the defaults
+const TRAILING: [&str; 1] = ["args"];
+
+/// The command line: the global options and a subcommand with its own
+#[derive(StructOpt)]
+#[structopt(
+    name = "adept",
+    author,
+    about = "Simulate and disassemble RV32I elfs",
+    setting = structopt::clap::AppSettings::SubcommandRequiredElseHelp
+)]
+pub struct Options {
+    #[structopt(flatten)]
+    pub global: GlobalOptions,
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+/// Subcommand of the adept binary
+#[derive(StructOpt)]
+pub enum Command {
+    /// Simulates an elf
+    Run(SimulatorOptions),
+    /// Simulates an elf, logging every event of the pipeline
+    Trace(SimulatorOptions),
+    /// Simulates an elf under the interactive debugger
+    Debug(SimulatorOptions),
+    /// Disassembles elfs
+    Disasm(DisassemblerOptions),
+    /// Generates a random program which checks its own results
+    Gen(GeneratorOptions),
+    /// Times the commit log of a run on a pipeline model without simulating
+    /// it again
+    Replay(ReplayOptions),
+    /// Answers queries about the commit log of a run
+    Analyze(AnalyzerOptions),
+}
+
+impl Command {
+    /// Least important messages written without --log-level, --quiet or
+    /// --verbose
+    pub fn log_level(&self) -> Level {
+        match *self {
+            Command::Trace(_) => Level::TRACE,
+            _ => Level::INFO,
+        }
+    }
+}
+
+// Options of every subcommand
+#[derive(StructOpt)]
+pub struct GlobalOptions {
+    /// Least important messages written, info by default and trace for the
+    /// trace command (error, warn, info, debug, trace), overriding --quiet
+    /// and --verbose
+    #[structopt(long, value_name = "LEVEL", global = true, env = "ADEPT_LOG_LEVEL")]
+    pub log_level: Option<Level>,
+    /// Writes the messages to a file instead of the standard error
+    #[structopt(long, value_name = "FILE", global = true, env = "ADEPT_LOG_FILE")]
+    pub log_file: Option<String>,
+    /// Writes nothing but the output of the program and the errors, without
+    /// the statistics
+    #[structopt(short, long, conflicts_with = "verbose", global = true)]
+    pub quiet: bool,
+    /// Writes the details of the setup to the log, and every event of the
+    /// pipeline when given twice
+    #[structopt(short, long, parse(from_occurrences), global = true)]
+    pub verbose: u64,
+    /// Writes what the command prints to the standard output to a file
+    /// instead, such as the listing or the output of the program
+    #[structopt(long, value_name = "FILE", global = true, env = "ADEPT_OUTPUT")]
+    pub output: Option<String>,
+    /// Reads the values of the options missing from the command line and the
+    /// environment from a file of option = value pairs, in a table named
+    /// after the subcommand for the options of a single one
+    #[structopt(long, value_name = "FILE", global = true, env = "ADEPT_DEFAULTS")]
+    pub defaults: Option<String>,
+}
+
+// Options of the simulation commands: run, trace and debug
+#[derive(StructOpt)]
+pub struct SimulatorOptions {
+    /// Sets the input elf file
+    #[structopt(value_name = "INPUTFILE")]
+    pub input_elf: String,
+    /// Loads an ELF, Intel HEX, $readmemh or binary file over the memory
+    /// after the input elf, at ADDR for binary and $readmemh files or moved
+    /// by ADDR for the others
+    #[structopt(long, value_name = "FILE[@ADDR]", number_of_values = 1)]
+    pub load: Vec<String>,
+    /// Sets the configuration file (pipeline stages, latencies)
+    #[structopt(short, long, value_name = "FILE", env = "ADEPT_CONFIG")]
+    pub config: Option<String>,
+    /// Writes which instruction occupies each pipeline stage every cycle
+    #[structopt(long, value_name = "FILE", env = "ADEPT_PIPELINE_LOG")]
+    pub pipeline_log: Option<String>,
+    /// Writes the pipeline activity in the Kanata format of the Konata viewer
+    #[structopt(long, value_name = "FILE", env = "ADEPT_KONATA")]
+    pub konata: Option<String>,
+    /// Writes the pipeline stages, the register write and the bus
+    /// transactions of every cycle as JSON lines, for the scoreboards of RTL
+    /// testbenches
+    #[structopt(long, value_name = "FILE", env = "ADEPT_STATE_LOG")]
+    pub state_log: Option<String>,
+    /// Writes the retired instructions and their register writes in the
+    /// trace CSV format of riscv-dv, to compare the simulator with the RTL
+    #[structopt(long, value_name = "FILE", env = "ADEPT_RISCV_DV_TRACE")]
+    pub riscv_dv_trace: Option<String>,
+    /// Writes the pc, the bits and the accessed address of every retired
+    /// instruction, which the replay command reads
+    #[structopt(long, value_name = "FILE", env = "ADEPT_COMMIT_LOG")]
+    pub commit_log: Option<String>,
+    /// Writes the fetch, the retirement and the flush of every instruction
+    /// to a file instead of the trace level of the log
+    #[structopt(long, value_name = "FILE", env = "ADEPT_TRACE_FILE")]
+    pub trace_file: Option<String>,
+    /// Writes the statistics of the simulation to a file instead of the
+    /// standard error, and the comparison of the configurations instead of
+    /// the standard output
+    #[structopt(long, value_name = "FILE", env = "ADEPT_STATS_FILE")]
+    pub stats_file: Option<String>,
+    /// Writes the stop reason, the exit code, the statistics and the device
+    /// counters of the run as JSON when the simulation stops
+    #[structopt(long, value_name = "FILE", env = "ADEPT_SUMMARY_JSON")]
+    pub summary_json: Option<String>,
+    /// Writes a self-contained HTML report with the summary, the instruction
+    /// mix, the hot functions and the stall cycles of the run
+    #[structopt(long, value_name = "FILE", env = "ADEPT_REPORT")]
+    pub report: Option<String>,
+    /// Writes the executed instructions and the outcomes of every branch
+    #[structopt(long, value_name = "FILE", env = "ADEPT_COVERAGE")]
+    pub coverage: Option<String>,
+    /// Format of the coverage report, an lcov tracefile or a list of
+    /// addresses (lcov, addresses)
+    #[structopt(
+        long,
+        value_name = "FORMAT",
+        env = "ADEPT_COVERAGE_FORMAT",
+        parse(try_from_str = coverage_format)
+    )]
+    pub coverage_format: Option<CoverageFormat>,
+    /// Writes the execution counts of every basic block and of the edges
+    /// between them
+    #[structopt(long, value_name = "FILE", env = "ADEPT_PROFILE")]
+    pub profile: Option<String>,
+    /// Format of the profile, a list of blocks and edges or a Graphviz
+    /// control flow graph (text, dot)
+    #[structopt(
+        long,
+        value_name = "FORMAT",
+        env = "ADEPT_PROFILE_FORMAT",
+        parse(try_from_str = profile_format)
+    )]
+    pub profile_format: Option<ProfileFormat>,
+    /// Writes a table of the cycles spent in every function of the ELF
+    /// symbols, with their stall cycles by reason
+    #[structopt(long, value_name = "FILE", env = "ADEPT_FUNCTION_PROFILE")]
+    pub function_profile: Option<String>,
+    /// Sorts the function table by the total cycles or by the stall cycles of
+    /// a reason (cycles, fetch, branch-flush, load-use, dependency,
+    /// execute-busy, memory, bus-contention)
+    #[structopt(
+        long,
+        value_name = "KEY",
+        requires = "function-profile",
+        env = "ADEPT_FUNCTION_SORT",
+        parse(try_from_str = function_sort)
+    )]
+    pub function_sort: Option<ProfileSort>,
+    /// Writes samples of the call stack as folded stacks for the flamegraph
+    /// tools
+    #[structopt(long, value_name = "FILE", env = "ADEPT_FLAMEGRAPH")]
+    pub flamegraph: Option<String>,
+    /// Instructions executed between two samples of the call stack, 1 by
+    /// default
+    #[structopt(
+        long,
+        value_name = "INSTRUCTIONS",
+        requires = "flamegraph",
+        env = "ADEPT_FLAMEGRAPH_PERIOD",
+        parse(try_from_str = positive)
+    )]
+    pub flamegraph_period: Option<u32>,
+    /// Writes the number of loads and stores to every region of the memory
+    #[structopt(long, value_name = "FILE", env = "ADEPT_HEATMAP")]
+    pub heatmap: Option<String>,
+    /// Format of the heatmap, a report with a bar per region or a table
+    /// (text, csv)
+    #[structopt(
+        long,
+        value_name = "FORMAT",
+        env = "ADEPT_HEATMAP_FORMAT",
+        parse(try_from_str = heatmap_format)
+    )]
+    pub heatmap_format: Option<HeatmapFormat>,
+    /// Size of the regions of the heatmap, a power of two, 4096 by default
+    #[structopt(
+        long,
+        value_name = "BYTES",
+        env = "ADEPT_HEATMAP_GRANULARITY",
+        parse(try_from_str = power_of_two)
+    )]
+    pub heatmap_granularity: Option<u32>,
+    /// Writes the pages of the memory which were written when the simulation
+    /// stops, as bin, ihex or readmemh (from the extension of FILE by
+    /// default)
+    #[structopt(long, value_name = "FILE[:FORMAT]", env = "ADEPT_EXPORT_MEM")]
+    pub export_mem: Option<String>,
+    /// Runs the program on every pipeline configuration and compares their
+    /// statistics
+    #[structopt(long)]
+    pub compare_configs: bool,
+    /// Runs the program on the pipeline configuration and on the one of
+    /// FILE, and reports the first difference in the stop reason, registers,
+    /// memory or output
+    #[structopt(long, value_name = "FILE", env = "ADEPT_DIFF_CONFIG")]
+    pub diff_config: Option<String>,
+    /// Checks the commit records of an RTL testbench, one retired
+    /// instruction per line, read from stdio or from a client of tcp:PORT
+    #[structopt(
+        long,
+        value_name = "TRANSPORT",
+        env = "ADEPT_LOCKSTEP",
+        parse(try_from_str = transport)
+    )]
+    pub lockstep: Option<Transport>,
+    /// Reports the scores printed by Dhrystone or CoreMark next to the
+    /// statistics
+    #[structopt(long)]
+    pub benchmark: bool,
+    /// Runs the program under the interactive debugger, reading its commands
+    /// from the standard input
+    #[structopt(long)]
+    pub debug: bool,
+    /// Starts the interactive debugger with a breakpoint given as 'EXPR [if
+    /// COND]', at the address of EXPR and stopping when COND holds
+    #[structopt(long = "break", value_name = "BREAKPOINT", number_of_values = 1)]
+    pub breakpoints: Vec<String>,
+    /// Runs the program up to an address, such as main or uart_putc+0x8,
+    /// then starts the interactive debugger
+    #[structopt(
+        long,
+        value_name = "EXPR",
+        env = "ADEPT_RUN_UNTIL",
+        parse(try_from_str = Expr::parse)
+    )]
+    pub run_until: Option<Expr>,
+    /// Records the session of the interactive debugger, so that its goto and
+    /// reverse-step commands can go back to any retired instruction
+    #[structopt(long)]
+    pub time_travel: bool,
+    /// Instructions between two snapshots of the recorded session, which
+    /// bound the time taken to go back, 10000 by default
+    #[structopt(
+        long,
+        value_name = "N",
+        requires = "time-travel",
+        env = "ADEPT_SNAPSHOT_INTERVAL",
+        parse(try_from_str = positive)
+    )]
+    pub snapshot_interval: Option<u32>,
+    /// Runs the program under an external debugger, serving the accesses to
+    /// the registers of the Debug Module on a local TCP port, as 'r ADDR'
+    /// and 'w ADDR DATA' lines
+    #[structopt(long, value_name = "PORT", env = "ADEPT_DEBUG_PORT")]
+    pub debug_port: Option<u16>,
+    /// Runs the program under OpenOCD, serving its remote_bitbang protocol
+    /// on a local TCP port with the JTAG TAP of the processor
+    #[structopt(long, value_name = "PORT", env = "ADEPT_REMOTE_BITBANG")]
+    pub remote_bitbang: Option<u16>,
+    /// Logs values each time the program reaches an address, given as 'EXPR:
+    /// EXPR[, EXPR...]'
+    #[structopt(long, value_name = "TRACEPOINT", number_of_values = 1)]
+    pub tracepoint: Vec<String>,
+    /// Stops the simulation when the program is still running after CYCLES
+    /// cycles, with the exit code 4 of a crash
+    #[structopt(
+        long,
+        value_name = "CYCLES",
+        env = "ADEPT_WATCHDOG",
+        parse(try_from_str = number)
+    )]
+    pub watchdog: Option<u32>,
+    /// Frequency of the core clock, which makes mtime and the gettimeofday
+    /// system call count the microseconds of the simulated cycles instead of
+    /// the instructions
+    #[structopt(
+        long = "clock-mhz",
+        value_name = "MHZ",
+        env = "ADEPT_CLOCK_MHZ",
+        parse(try_from_str = clock)
+    )]
+    pub clock: Option<u64>,
+    /// Stops the simulation after CYCLES cycles with the exit code 124
+    #[structopt(
+        long,
+        value_name = "CYCLES",
+        env = "ADEPT_TIMEOUT_CYCLES",
+        parse(try_from_str = number)
+    )]
+    pub timeout_cycles: Option<u32>,
+    /// Stops the simulation after SECONDS seconds of wall-clock time with the
+    /// exit code 124
+    #[structopt(
+        long,
+        value_name = "SECONDS",
+        env = "ADEPT_TIMEOUT_SECONDS",
+        parse(try_from_str = seconds)
+    )]
+    pub timeout_seconds: Option<Duration>,
+    /// Writes the state of the processor to a file when the program faults,
+    /// stops at an illegal instruction or an ebreak, or times out, or the
+    /// simulator itself fails
+    #[structopt(long, value_name = "FILE", env = "ADEPT_CRASH_REPORT")]
+    pub crash_report: Option<String>,
+    /// Number of retired instructions in the crash report, 32 by default
+    #[structopt(
+        long,
+        value_name = "COUNT",
+        env = "ADEPT_CRASH_HISTORY",
+        parse(try_from_str = number)
+    )]
+    pub crash_history: Option<u32>,
+    /// Address of the first instruction, the entry point of the elf by
+    /// default
+    #[structopt(
+        long,
+        value_name = "ADDR",
+        env = "ADEPT_RESET_VECTOR",
+        parse(try_from_str = number)
+    )]
+    pub reset_vector: Option<u32>,
+    /// Starts in a boot ROM at the reset vector which sets up mtvec and sp
+    /// before jumping to the entry point
+    #[structopt(long)]
+    pub boot_rom: bool,
+    /// Initial stack pointer, the top of the memory by default
+    #[structopt(
+        long,
+        value_name = "ADDR",
+        env = "ADEPT_STACK_POINTER",
+        parse(try_from_str = number)
+    )]
+    pub stack_pointer: Option<u32>,
+    /// Size of the heap after the program, which by default extends up to
+    /// the stack
+    #[structopt(
+        long,
+        value_name = "BYTES",
+        env = "ADEPT_HEAP_SIZE",
+        parse(try_from_str = number)
+    )]
+    pub heap_size: Option<u32>,
+    /// Size of the stack, 64 KiB by default
+    #[structopt(
+        long,
+        value_name = "BYTES",
+        env = "ADEPT_STACK_SIZE",
+        parse(try_from_str = number)
+    )]
+    pub stack_size: Option<u32>,
+    /// Places 4 KiB guard regions below the stack and after the heap which
+    /// stop the simulation when they are accessed
+    #[structopt(long)]
+    pub guard_pages: bool,
+    /// Places a device tree of the platform below the stack, passing the
+    /// hart id in a0 and its address in a1
+    #[structopt(long)]
+    pub device_tree: bool,
+    /// Stops when a function returns to an address other than the one after
+    /// its call
+    #[structopt(long)]
+    pub check_returns: bool,
+    /// Number of address bits of the memory, from 12 to 32, 23 (8 MiB) by
+    /// default. Only the pages which are written take host memory
+    #[structopt(
+        long,
+        value_name = "BITS",
+        env = "ADEPT_MEMORY_BITS",
+        parse(try_from_str = memory_bits)
+    )]
+    pub memory_bits: Option<u32>,
+    /// Splits loads and stores which cross a word boundary into accesses of
+    /// both words, taking an extra cycle, instead of stopping
+    #[structopt(long)]
+    pub misaligned_access: bool,
+    /// Maps a region of the loaded image read-only, like the flash of the
+    /// Adept board, so stores to it stop the simulation
+    #[structopt(
+        long,
+        value_name = "ADDR:SIZE",
+        env = "ADEPT_READ_ONLY",
+        parse(try_from_str = region)
+    )]
+    pub read_only: Option<(u32, u32)>,
+    /// Maps a scratchpad memory apart from the main one, answering in a
+    /// single cycle
+    #[structopt(
+        long,
+        value_name = "ADDR:SIZE",
+        env = "ADEPT_SCRATCHPAD",
+        parse(try_from_str = region)
+    )]
+    pub scratchpad: Option<(u32, u32)>,
+    /// Fills the memory with a 32 bit pattern before loading the program
+    #[structopt(
+        long,
+        value_name = "PATTERN",
+        env = "ADEPT_POISON",
+        parse(try_from_str = number)
+    )]
+    pub poison: Option<u32>,
+    /// Checks for loads of memory which was never written, and either warns
+    /// or stops (warn, stop)
+    #[structopt(
+        long,
+        value_name = "POLICY",
+        env = "ADEPT_UNINITIALIZED",
+        parse(try_from_str = uninitialized_policy)
+    )]
+    pub uninitialized: Option<UninitializedPolicy>,
+    /// Disables the warnings about computations writing x0, stores into the
+    /// code, reads of write-only device registers and jumps to unmapped
+    /// memory
+    #[structopt(long)]
+    pub no_diagnostics: bool,
+    /// Handles instructions which can't be executed by trapping to mtvec,
+    /// stopping or skipping them with a warning (trap, stop, skip), trap by
+    /// default, which stops when the program didn't set mtvec
+    #[structopt(
+        long,
+        value_name = "POLICY",
+        env = "ADEPT_ILLEGAL_INSTRUCTION",
+        parse(try_from_str = illegal_instruction_policy)
+    )]
+    pub illegal_instruction: Option<IllegalInstructionPolicy>,
+    /// Handles misaligned and faulting memory accesses by trapping to mtvec
+    /// with the address in mtval, or stopping (trap, stop), stop by default
+    #[structopt(
+        long,
+        value_name = "POLICY",
+        env = "ADEPT_MEMORY_ERROR",
+        parse(try_from_str = memory_error_policy)
+    )]
+    pub memory_error: Option<MemoryErrorPolicy>,
+    /// Connects the serial port to a console besides the output of the
+    /// simulator: a client of a local TCP port, waited for before the
+    /// simulation, a new pseudo-terminal, printed at the start, or a script
+    /// of input and expected output which fails the simulation on a mismatch
+    /// (stdio, tcp:PORT, pty, script:PATH), stdio by default
+    #[structopt(
+        long,
+        value_name = "CONSOLE",
+        env = "ADEPT_UART",
+        parse(try_from_str = console)
+    )]
+    pub uart: Option<ConsoleSpec>,
+    /// Handles environment calls by emulating the system calls or trapping
+    /// to mtvec, for operating systems (emulate, trap), emulate by default
+    #[structopt(
+        long,
+        value_name = "POLICY",
+        env = "ADEPT_ECALL",
+        parse(try_from_str = environment_call_policy)
+    )]
+    pub ecall: Option<EnvironmentCallPolicy>,
+    /// Marks a memory region as tainted and tracks where its data flows
+    #[structopt(
+        long,
+        value_name = "ADDR:SIZE",
+        number_of_values = 1,
+        parse(try_from_str = region)
+    )]
+    pub taint_source: Vec<(u32, u32)>,
+    /// Reports stores of tainted data to a memory region
+    #[structopt(
+        long,
+        value_name = "ADDR:SIZE",
+        number_of_values = 1,
+        parse(try_from_str = region)
+    )]
+    pub taint_sink: Vec<(u32, u32)>,
+    /// Flips a bit of the byte at an address of the memory at the start of a
+    /// cycle
+    #[structopt(
+        long,
+        value_name = "CYCLE:ADDR:BIT",
+        number_of_values = 1,
+        parse(try_from_str = bit_flip)
+    )]
+    pub inject_flip: Vec<BitFlip>,
+    /// Flips a bit of a register at the start of a cycle, or a random one
+    /// with random, and reports if the result of the program changed
+    #[structopt(
+        long,
+        value_name = "CYCLE:REG:BIT",
+        env = "ADEPT_INJECT_REGISTER",
+        parse(try_from_str = register_fault)
+    )]
+    pub inject_register: Option<RegisterFault>,
+    /// Runs the program once per random register flip and reports the
+    /// vulnerability of every function and instruction
+    #[structopt(
+        long,
+        value_name = "RUNS",
+        env = "ADEPT_FAULT_CAMPAIGN",
+        parse(try_from_str = number)
+    )]
+    pub fault_campaign: Option<u32>,
+    /// Flips random bits of the fault region, on average RATE per million
+    /// cycles
+    #[structopt(
+        long,
+        value_name = "RATE",
+        requires = "inject-region",
+        env = "ADEPT_INJECT_RATE",
+        parse(try_from_str = rate)
+    )]
+    pub inject_rate: Option<f64>,
+    /// Memory region hit by the random bit flips
+    #[structopt(
+        long,
+        value_name = "ADDR:SIZE",
+        env = "ADEPT_INJECT_REGION",
+        parse(try_from_str = region)
+    )]
+    pub inject_region: Option<(u32, u32)>,
+    /// Seed of the random bit flips, so a run can be repeated
+    #[structopt(
+        long,
+        value_name = "SEED",
+        env = "ADEPT_INJECT_SEED",
+        parse(try_from_str = seed)
+    )]
+    pub inject_seed: Option<u64>,
+    /// Seed of every random number of the run, the random faults included
+    /// unless --inject-seed is given
+    #[structopt(
+        long,
+        value_name = "SEED",
+        env = "ADEPT_SEED",
+        parse(try_from_str = seed)
+    )]
+    pub seed: Option<u64>,
+    /// Writes the version, the seed, the command line and the hashes of the
+    /// input files of the run, to repeat it exactly
+    #[structopt(long, value_name = "FILE", env = "ADEPT_MANIFEST")]
+    pub manifest: Option<String>,
+    /// Arguments passed to main after the name of the program
+    #[structopt(long, value_name = "ARGS", allow_hyphen_values = true)]
+    pub args: Vec<String>,
+    /// Environment variables of the program
+    #[structopt(long, value_name = "NAME=VALUE", number_of_values = 1)]
+    pub env: Vec<String>,
+}
+
+// Options of the disassembler command
+#[derive(StructOpt)]
+pub struct DisassemblerOptions {
+    /// Sets the input elf files, listed one after the other
+    #[structopt(value_name = "INPUTFILE", required = true)]
+    pub input_elf: Vec<String>,
+    /// Writes the listing of every input file to DIR/NAME.dis instead of the
+    /// standard output
+    #[structopt(long, value_name = "DIR", env = "ADEPT_OUTPUT_DIR")]
+    pub output_dir: Option<String>,
+    /// Displays Program Counter
+    #[structopt(short, long)]
+    pub pc: bool,
+    /// Displays a unsigned 32 bit in hex format Instruction
+    #[structopt(short, long)]
+    pub instruction: bool,
+    /// Displays Assembly code
+    #[structopt(short, long)]
+    pub assembly: bool,
+    /// Displays the Data
+    #[structopt(short = "c", long)]
+    pub ascii: bool,
+    /// Disassembles every section instead of the executable ones
+    #[structopt(long)]
+    pub all_sections: bool,
+    /// Writes the control flow graph of the code in the dot language of
+    /// Graphviz
+    #[structopt(long, value_name = "FILE", env = "ADEPT_CFG")]
+    pub cfg: Option<String>,
+    /// Annotates the control flow graph with the counts of a profile written
+    /// by the simulator in the text format
+    #[structopt(long, value_name = "FILE", env = "ADEPT_CFG_PROFILE")]
+    pub cfg_profile: Option<String>,
+    /// Prints the bit fields of the encoding of every instruction in a table
+    #[structopt(long)]
+    pub fields: bool,
+    /// Prints how often every mnemonic, format, register and extension
+    /// appears in the code instead of the listing
+    #[structopt(long)]
+    pub stats: bool,
+}
+
+// Options of the generator command
+#[derive(StructOpt)]
+pub struct GeneratorOptions {
+    /// Sets the file of the program, in the elf, bin, ihex or readmemh
+    /// FORMAT, guessed from the extension and an elf without one
+    #[structopt(value_name = "OUTPUTFILE[:FORMAT]")]
+    pub output_file: String,
+    /// Seed of the random instructions, the same seed giving the same program
+    #[structopt(
+        long,
+        value_name = "SEED",
+        env = "ADEPT_SEED",
+        parse(try_from_str = seed)
+    )]
+    pub seed: Option<u64>,
+    /// Number of random instructions between the setup of the registers and
+    /// the checks, 1000 by default
+    #[structopt(
+        long,
+        value_name = "COUNT",
+        env = "ADEPT_COUNT",
+        parse(try_from_str = number)
+    )]
+    pub count: Option<u32>,
+    /// Address of the first instruction, 0x80000000 by default
+    #[structopt(
+        long,
+        value_name = "ADDR",
+        env = "ADEPT_BASE",
+        parse(try_from_str = word_address)
+    )]
+    pub base: Option<u32>,
+    /// Weights of the classes of random instructions: alu, imm, load, store,
+    /// branch, jump and upper, the ones not given being left out
+    #[structopt(
+        long,
+        value_name = "CLASS=WEIGHT,...",
+        env = "ADEPT_MIX",
+        parse(try_from_str = Mix::parse)
+    )]
+    pub mix: Option<Mix>,
+}
+
+// Options of the replay command
+#[derive(StructOpt)]
+pub struct ReplayOptions {
+    /// Sets the commit log written by --commit-log
+    #[structopt(value_name = "COMMITLOG")]
+    pub commit_log: String,
+    /// Sets the configuration file of the pipeline model to replay the run on
+    /// (pipeline stages, latencies)
+    #[structopt(short, long, value_name = "FILE", env = "ADEPT_CONFIG")]
+    pub config: Option<String>,
+    /// Sweeps instruction and data caches of every SIZE in bytes, with K or M
+    /// suffixes, and every line and number of ways, writing their miss rates
+    /// and estimated cycles as CSV
+    #[structopt(
+        long,
+        value_name = "SIZE,...",
+        use_delimiter = true,
+        require_delimiter = true,
+        env = "ADEPT_CACHE_SIZES",
+        parse(try_from_str = size)
+    )]
+    pub cache_sizes: Vec<u32>,
+    /// Line sizes of the swept caches, 32 bytes by default
+    #[structopt(
+        long,
+        value_name = "BYTES,...",
+        use_delimiter = true,
+        require_delimiter = true,
+        requires = "cache-sizes",
+        env = "ADEPT_CACHE_LINES",
+        parse(try_from_str = number)
+    )]
+    pub cache_lines: Vec<u32>,
+    /// Associativities of the swept caches, direct mapped by default
+    #[structopt(
+        long,
+        value_name = "WAYS,...",
+        use_delimiter = true,
+        require_delimiter = true,
+        requires = "cache-sizes",
+        env = "ADEPT_CACHE_WAYS",
+        parse(try_from_str = number)
+    )]
+    pub cache_ways: Vec<u32>,
+    /// Cycles every cache miss adds to the estimate of the sweep, 10 by
+    /// default
+    #[structopt(
+        long,
+        value_name = "CYCLES",
+        requires = "cache-sizes",
+        env = "ADEPT_MISS_PENALTY",
+        parse(try_from_str = number)
+    )]
+    pub miss_penalty: Option<u32>,
+    /// Fills the swept instruction caches ahead of their use with a
+    /// next-line prefetcher, reporting its accuracy and coverage
+    #[structopt(
+        long,
+        value_name = "KIND",
+        requires = "cache-sizes",
+        env = "ADEPT_ICACHE_PREFETCH",
+        parse(try_from_str = prefetch_kind)
+    )]
+    pub icache_prefetch: Option<PrefetchKind>,
+    /// Fills the swept data caches ahead of their use with a next-line or a
+    /// stride prefetcher, reporting its accuracy and coverage
+    #[structopt(
+        long,
+        value_name = "KIND",
+        requires = "cache-sizes",
+        env = "ADEPT_DCACHE_PREFETCH",
+        parse(try_from_str = prefetch_kind)
+    )]
+    pub dcache_prefetch: Option<PrefetchKind>,
+    /// Passes the data accesses of the harts through private caches of the
+    /// swept geometries kept coherent by PROTOCOL (msi or mesi), writing
+    /// their coherence traffic as CSV instead of the sweep
+    #[structopt(
+        long,
+        value_name = "PROTOCOL",
+        requires = "cache-sizes",
+        env = "ADEPT_COHERENCE",
+        parse(try_from_str = protocol)
+    )]
+    pub coherence: Option<Protocol>,
+    /// Adds the commit log of another hart of the run to the coherence model,
+    /// interleaved an instruction of each hart in turn
+    #[structopt(
+        long,
+        value_name = "COMMITLOG",
+        number_of_values = 1,
+        requires = "coherence"
+    )]
+    pub hart: Vec<String>,
+}
+
+// Options of the analyze command
+#[derive(StructOpt)]
+pub struct AnalyzerOptions {
+    /// Sets the commit log written by --commit-log
+    #[structopt(value_name = "COMMITLOG")]
+    pub commit_log: String,
+    /// Lists the instructions retired before the last one at PC
+    #[structopt(
+        long,
+        value_name = "PC",
+        env = "ADEPT_BEFORE",
+        parse(try_from_str = number)
+    )]
+    pub before: Option<u32>,
+    /// Number of instructions listed by --before, 20 by default
+    #[structopt(
+        short = "n",
+        long,
+        value_name = "N",
+        requires = "before",
+        env = "ADEPT_COUNT",
+        parse(try_from_str = number)
+    )]
+    pub count: Option<u32>,
+    /// Lists every store writing the byte at ADDR
+    #[structopt(
+        long,
+        value_name = "ADDR",
+        env = "ADEPT_WRITERS",
+        parse(try_from_str = number)
+    )]
+    pub writers: Option<u32>,
+    // Logs can be longer than 2^32 instructions
+    /// Prints the calls in progress at the instruction of the log at INDEX,
+    /// from 0
+    #[structopt(long, value_name = "INDEX", env = "ADEPT_CALL_PATH")]
+    pub call_path: Option<u64>,
+    /// Locates the instructions in the functions of the elf which ran
+    #[structopt(long, value_name = "FILE", env = "ADEPT_ELF")]
+    pub elf: Option<String>,
+}
+
+/// Console of the serial port given by --uart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleSpec {
+    /// Only the output of the simulator
+    Stdio,
+    /// A client of a local TCP port
+    Tcp(u16),
+    /// A new pseudo-terminal
+    Pty,
+    /// A script of input and expected output, read from a file
+    Script(String),
+}
+
+/// Where the commit records of --lockstep are read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// The standard input, answered on the standard output
+    Stdio,
+    /// A client of a local TCP port
+    Tcp(u16),
+}
+
+/// Register flip given by --inject-register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterFault {
+    /// A flip drawn from the seed, within the run without faults
+    Random,
+    /// The flip of the command line
+    Flip(RegisterFlip),
+}
+
+/// Format of the report of --coverage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverageFormat {
+    Lcov,
+    Addresses,
+}
+
+/// Format of the report of --profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFormat {
+    Text,
+    Dot,
+}
+
+/// Format of the report of --heatmap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeatmapFormat {
+    Text,
+    Csv,
+}
+
+/// The command line with its subcommands, and the version of the crate
+/// followed by the commit it was built from as the long version
+pub fn app() -> App<'static, 'static> {
+    Options::clap().long_version(concat!(
+        env!("CARGO_PKG_VERSION"),
+        "-",
+        env!("ADEPT_COMMIT")
+    ))
+}
+
+/// Parse the command line of the process, with the options it doesn't give
+/// taken from the defaults file. A command line which can't be parsed exits
+/// the process with the usage.
+pub fn parse() -> Result<Options, Error> {
+    let args: Vec<OsString> = env::args_os().collect();
+    let matches = app().get_matches_from(&args);
+    let options = Options::from_clap(&matches);
+    let (path, command, given) = match (&options.global.defaults, matches.subcommand()) {
+        (Some(path), (command, Some(given))) => (path, command, given),
+        _ => return Ok(options),
+    };
+    let config = Config::from_file(path).map_err(|e| Error::load(path, e))?;
+    let (defaults, trailing) = default_args(&config, command, |name| given.is_present(name))
+        .map_err(|e| Error::load(path, e))?;
+    // The defaults go where the options of the subcommand start
+    let index = subcommand_index(&args, command);
+    let mut merged = args[..=index].to_vec();
+    merged.extend(defaults.into_iter().map(OsString::from));
+    merged.extend_from_slice(&args[index + 1..]);
+    merged.extend(trailing.into_iter().map(OsString::from));
+    match app().get_matches_from_safe(merged) {
+        Ok(matches) => Ok(Options::from_clap(&matches)),
+        Err(e) => Err(Error::load(path, e)),
+    }
+}
+
+// Index of the subcommand in the arguments, past the global options and
+// their values
+fn subcommand_index(args: &[OsString], command: &str) -> usize {
+    let mut index = 1;
+    while index < args.len() {
+        match args[index].to_str() {
+            Some(arg) if arg == command => break,
+            Some(arg) if GLOBAL_VALUES.contains(&arg) => index += 2,
+            _ => index += 1,
+        }
+    }
+    index
+}
+
+// Options of the defaults file which the command line doesn't give, as
+// arguments. Those of the trailing options are apart, to be given last.
+//
+// # Arguments
+// * `given` => whether the command line or the environment gives an option,
+//   by its name on the command line without the dashes
+fn default_args<F>(
+    config: &Config,
+    command: &str,
+    given: F,
+) -> Result<(Vec<String>, Vec<String>), config::ConfigError>
+where
+    F: Fn(&str) -> bool,
+{
+    let empty = Table::new();
+    let common = config.table("").unwrap_or(&empty);
+    let specific = config.table(command).unwrap_or(&empty);
+    let pairs = common
+        .iter()
+        .filter(|(key, _)| {
+            !specific
+                .keys()
+                .any(|other| other.replace('_', "-") == key.replace('_', "-"))
+        })
+        .map(|pair| ("", pair))
+        .chain(specific.iter().map(|pair| (command, pair)));
+
+    let mut args = Vec::new();
+    let mut trailing = Vec::new();
+    for (table, (key, value)) in pairs {
+        let name = key.replace('_', "-");
+        if given(&name) {
+            continue;
+        }
+        let option = format!("--{}", name);
+        let values = match value {
+            Value::Array(values) => values.as_slice(),
+            value => slice::from_ref(value),
+        };
+        if TRAILING.contains(&name.as_str()) {
+            trailing.push(option);
+            for value in values {
+                trailing.push(default_value(table, key, value)?);
+            }
+            continue;
+        }
+        for value in values {
+            match value {
+                Value::Boolean(true) => args.push(option.clone()),
+                Value::Boolean(false) => (),
+                value => args.push(format!("{}={}", option, default_value(table, key, value)?)),
+            }
+        }
+    }
+    Ok((args, trailing))
+}
+
+// A value of the defaults file as the value of an option
+fn default_value(table: &str, key: &str, value: &Value) -> Result<String, config::ConfigError> {
+    match value {
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::String(value) => Ok(value.clone()),
+        Value::Boolean(_) => Err(config::value_error(table, key, "expected a value")),
+        Value::Array(_) => Err(config::value_error(table, key, "arrays can't be nested")),
+    }
+}
+
+// The choice named by a value, or the list of the names
+fn choose<N, T, I>(value: &str, choices: I) -> Result<T, String>
+where
+    N: AsRef<str>,
+    I: IntoIterator<Item = (N, T)>,
+{
+    let mut names = Vec::new();
+    for (name, choice) in choices {
+        if name.as_ref() == value {
+            return Ok(choice);
+        }
+        names.push(name.as_ref().to_string());
+    }
+    Err(format!("expected one of {}", names.join(", ")))
+}
+
+// An address, a size or a count, in hexadecimal with a 0x prefix or in
+// decimal
+fn number(value: &str) -> Result<u32, String> {
+    parse_number(value).ok_or_else(|| "expected a number, in decimal or with a 0x prefix".into())
+}
+
+fn positive(value: &str) -> Result<u32, String> {
+    match number(value)? {
+        0 => Err("expected more than 0".to_string()),
+        number => Ok(number),
+    }
+}
+
+fn power_of_two(value: &str) -> Result<u32, String> {
+    match number(value)? {
+        number if number.is_power_of_two() => Ok(number),
+        _ => Err("expected a power of two".to_string()),
+    }
+}
+
+fn word_address(value: &str) -> Result<u32, String> {
+    match number(value)? {
+        addr if addr & 3 == 0 => Ok(addr),
+        _ => Err("expected an address aligned to words".to_string()),
+    }
+}
+
+fn memory_bits(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(bits) if (MIN_ADDRESS_BITS..=MAX_ADDRESS_BITS).contains(&bits) => Ok(bits),
+        _ => Err(format!(
+            "expected {} to {}",
+            MIN_ADDRESS_BITS, MAX_ADDRESS_BITS
+        )),
+    }
+}
+
+// A cache size in bytes, with K or M suffixes
+fn size(value: &str) -> Result<u32, String> {
+    cache::parse_size(value).ok_or_else(|| "expected a size such as 512, 4K or 1M".into())
+}
+
+fn seed(value: &str) -> Result<u64, String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|e| e.to_string())
+}
+
+fn seconds(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds <= 1e9 => Ok(Duration::from_secs_f64(seconds)),
+        _ => Err("expected up to 1e9 seconds, more than 0".to_string()),
+    }
+}
+
+// Frequency in MHz, as Hz
+fn clock(value: &str) -> Result<u64, String> {
+    match value.parse::<f64>() {
+        Ok(mhz) if mhz > 0.0 && mhz <= 1e6 => Ok(((mhz * 1e6).round() as u64).max(1)),
+        _ => Err("expected up to 1e6 MHz, more than 0".to_string()),
+    }
+}
+
+fn rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if (0.0..=1_000_000.0).contains(&rate) => Ok(rate),
+        _ => Err("expected 0 to 1000000 flips per million cycles".to_string()),
+    }
+}
+
+// A memory region given as ADDR:SIZE
+fn region(value: &str) -> Result<(u32, u32), String> {
+    let mut parts = value.splitn(2, ':');
+    match (
+        parts.next().and_then(parse_number),
+        parts.next().and_then(parse_number),
+    ) {
+        (Some(addr), Some(size)) => Ok((addr, size)),
+        _ => Err("expected ADDR:SIZE".to_string()),
+    }
+}
+
+// A bit flip given as CYCLE:ADDR:BIT
+fn bit_flip(value: &str) -> Result<BitFlip, String> {
+    let mut parts = value.splitn(3, ':');
+    match (
+        parts.next().and_then(|cycle| cycle.parse().ok()),
+        parts.next().and_then(parse_number),
+        parts.next().and_then(parse_number),
+    ) {
+        (Some(cycle), Some(addr), Some(bit)) if bit < 8 => Ok(BitFlip { cycle, addr, bit }),
+        _ => Err("expected CYCLE:ADDR:BIT, with BIT below 8".to_string()),
+    }
+}
+
+// A register flip given as CYCLE:REG:BIT, with the register as a number or
+// as xN, or random
+fn register_fault(value: &str) -> Result<RegisterFault, String> {
+    if value == "random" {
+        return Ok(RegisterFault::Random);
+    }
+    let mut parts = value.splitn(3, ':');
+    let cycle = parts.next().and_then(|cycle| cycle.parse().ok());
+    let register = parts
+        .next()
+        .and_then(|register| parse_number(register.strip_prefix('x').unwrap_or(register)));
+    match (cycle, register, parts.next().and_then(parse_number)) {
+        (Some(cycle), Some(register), Some(bit)) if (1..32).contains(&register) && bit < 32 => {
+            Ok(RegisterFault::Flip(RegisterFlip {
+                cycle,
+                register: register as u8,
+                bit,
+            }))
+        }
+        _ => Err("expected CYCLE:REG:BIT, with REG from x1 to x31, or random".to_string()),
+    }
+}
+
+fn console(value: &str) -> Result<ConsoleSpec, String> {
+    match value {
+        "stdio" => Ok(ConsoleSpec::Stdio),
+        "pty" => Ok(ConsoleSpec::Pty),
+        spec if spec.starts_with("tcp:") => match spec[4..].parse() {
+            Ok(port) => Ok(ConsoleSpec::Tcp(port)),
+            Err(e) => Err(format!("invalid port {}: {}", &spec[4..], e)),
+        },
+        spec if spec.starts_with("script:") => Ok(ConsoleSpec::Script(spec[7..].to_string())),
+        _ => Err("expected stdio, tcp:PORT, pty or script:PATH".to_string()),
+    }
+}
+
+fn transport(value: &str) -> Result<Transport, String> {
+    match value {
+        "stdio" => Ok(Transport::Stdio),
+        spec if spec.starts_with("tcp:") => match spec[4..].parse() {
+            Ok(port) => Ok(Transport::Tcp(port)),
+            Err(e) => Err(format!("invalid port {}: {}", &spec[4..], e)),
+        },
+        _ => Err("expected stdio or tcp:PORT".to_string()),
+    }
+}
+
+fn coverage_format(value: &str) -> Result<CoverageFormat, String> {
+    choose(
+        value,
+        vec![
+            ("lcov", CoverageFormat::Lcov),
+            ("addresses", CoverageFormat::Addresses),
+        ],
+    )
+}
+
+fn profile_format(value: &str) -> Result<ProfileFormat, String> {
+    choose(
+        value,
+        vec![("text", ProfileFormat::Text), ("dot", ProfileFormat::Dot)],
+    )
+}
+
+fn heatmap_format(value: &str) -> Result<HeatmapFormat, String> {
+    choose(
+        value,
+        vec![("text", HeatmapFormat::Text), ("csv", HeatmapFormat::Csv)],
+    )
+}
+
+// The total cycles, or the stall cycles of a reason named with dashes
+fn function_sort(value: &str) -> Result<ProfileSort, String> {
+    let stalls = StallReason::ALL.iter().map(|reason| {
+        (
+            reason.name().replace(' ', "-"),
+            ProfileSort::Stalls(*reason),
+        )
+    });
+    choose(
+        value,
+        Some(("cycles".to_string(), ProfileSort::Cycles))
+            .into_iter()
+            .chain(stalls),
+    )
+}
+
+fn uninitialized_policy(value: &str) -> Result<UninitializedPolicy, String> {
+    choose(
+        value,
+        vec![
+            ("warn", UninitializedPolicy::Warn),
+            ("stop", UninitializedPolicy::Stop),
+        ],
+    )
+}
+
+fn illegal_instruction_policy(value: &str) -> Result<IllegalInstructionPolicy, String> {
+    choose(
+        value,
+        vec![
+            ("trap", IllegalInstructionPolicy::Trap),
+            ("stop", IllegalInstructionPolicy::Stop),
+            ("skip", IllegalInstructionPolicy::Skip),
+        ],
+    )
+}
+
+fn memory_error_policy(value: &str) -> Result<MemoryErrorPolicy, String> {
+    choose(
+        value,
+        vec![
+            ("trap", MemoryErrorPolicy::Trap),
+            ("stop", MemoryErrorPolicy::Stop),
+        ],
+    )
+}
+
+fn environment_call_policy(value: &str) -> Result<EnvironmentCallPolicy, String> {
+    choose(
+        value,
+        vec![
+            ("emulate", EnvironmentCallPolicy::Emulate),
+            ("trap", EnvironmentCallPolicy::Trap),
+        ],
+    )
+}
+
+fn prefetch_kind(value: &str) -> Result<PrefetchKind, String> {
+    choose(
+        value,
+        PrefetchKind::ALL.iter().map(|kind| (kind.name(), *kind)),
+    )
+}
+
+fn protocol(value: &str) -> Result<Protocol, String> {
+    choose(
+        value,
+        Protocol::ALL
+            .iter()
+            .map(|protocol| (protocol.name(), *protocol)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_args() {
+        let config = Config::parse(
+            "log-level = \"warn\"\ntimeout-cycles = 10\n\
+             [run]\ntimeout_cycles = 20\nload = [\"a.bin\", \"b.hex@0x100\"]\n\
+             check-returns = true\ndevice-tree = false\nargs = [\"-n\", 3]\nseed = 7\n\
+             [disasm]\nstats = true\n",
+        )
+        .unwrap();
+        let (args, trailing) = default_args(&config, "run", |name| name == "seed").unwrap();
+        assert_eq!(
+            vec![
+                "--log-level=warn",
+                "--check-returns",
+                "--load=a.bin",
+                "--load=b.hex@0x100",
+                "--timeout-cycles=20",
+            ],
+            args
+        );
+        assert_eq!(vec!["--args", "-n", "3"], trailing);
+        let (args, _) = default_args(&config, "disasm", |_| true).unwrap();
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn test_default_args_invalid() {
+        let config = Config::parse("[run]\nargs = [\"-n\", true]\ndebug = false\n").unwrap();
+        let e = default_args(&config, "run", |_| false).unwrap_err();
+        assert_eq!(
+            "invalid value for run.args: expected a value",
+            e.to_string()
+        );
+        let (args, trailing) = default_args(&config, "run", |name| name == "args").unwrap();
+        assert!(args.is_empty() && trailing.is_empty());
+    }
+
+    #[test]
+    fn test_subcommand_index() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            1,
+            subcommand_index(&args(&["adept", "gen", "a.elf"]), "gen")
+        );
+        assert_eq!(
+            4,
+            subcommand_index(
+                &args(&["adept", "--output", "run", "-q", "run", "a.elf"]),
+                "run"
+            )
+        );
+        assert_eq!(
+            2,
+            subcommand_index(&args(&["adept", "--output=run", "run"]), "run")
+        );
+    }
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(Ok((0x2000, 16)), region("0x2000:16"));
+        assert!(region("0x2000").is_err());
+        assert_eq!(Ok(RegisterFault::Random), register_fault("random"));
+        assert!(register_fault("10:x0:1").is_err());
+        assert_eq!(Ok(Transport::Tcp(4000)), transport("tcp:4000"));
+        assert_eq!(
+            Ok(ProfileSort::Stalls(StallReason::LoadUse)),
+            function_sort("load-use")
+        );
+        assert_eq!(
+            Err("expected one of emulate, trap".to_string()),
+            environment_call_policy("syscall")
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let options = Options::from_iter_safe(&[
+            "adept",
+            "run",
+            "a.elf",
+            "--memory-bits",
+            "16",
+            "--scratchpad=0x1000:0x100",
+            "--taint-source",
+            "0:4",
+            "--taint-source",
+            "8:4",
+            "-q",
+        ])
+        .unwrap();
+        assert!(options.global.quiet);
+        match options.command {
+            Command::Run(ref run) => {
+                assert_eq!("a.elf", run.input_elf);
+                assert_eq!(Some(16), run.memory_bits);
+                assert_eq!(Some((0x1000, 0x100)), run.scratchpad);
+                assert_eq!(vec![(0, 4), (8, 4)], run.taint_source);
+            }
+            _ => panic!("not the run command"),
+        }
+        assert!(
+            Options::from_iter_safe(&["adept", "run", "a.elf", "--memory-bits", "40"]).is_err()
+        );
+    }
+}
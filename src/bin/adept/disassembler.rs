@@ -0,0 +1,242 @@
+//! The disassembler command, which lists the code of programs.
+use adapt_mem_adept;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use adept_lib::boundary;
+use adept_lib::cfg::Cfg;
+use adept_lib::elf;
+use adept_lib::profile::Profile;
+use adept_lib::riscv::decoder::Instruction;
+use adept_lib::riscv::fields;
+use adept_lib::riscv::histogram::Histogram;
+use adept_lib::Error;
+
+use cli::{DisassemblerOptions, GlobalOptions};
+use {create_output_file, output_option};
+
+/// Disassemble the programs of the command line
+///
+/// # Arguments
+/// * `global` => options of every command
+/// * `options` => options of the command
+pub fn run(global: &GlobalOptions, options: &DisassemblerOptions) -> Result<(), Error> {
+    let filenames = &options.input_elf;
+    if filenames.len() > 1 && options.cfg.is_some() {
+        return Err(Error::Invalid(
+            "--cfg takes a single input file".to_string(),
+        ));
+    }
+
+    // Every file gets its own listing in the output directory, or all of them
+    // are merged on the output with a header before each one
+    let output_dir = options.output_dir.as_ref().map(Path::new);
+    if let Some(dir) = output_dir {
+        if global.output.is_some() {
+            return Err(Error::Invalid(
+                "--output and --output-dir can't be used together".to_string(),
+            ));
+        }
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(Error::runtime(&dir.to_string_lossy(), e));
+        }
+    }
+    let mut output = output_option(global.output.as_deref())?;
+    for (index, filename) in filenames.iter().enumerate() {
+        match output_dir {
+            Some(dir) => {
+                let stem = Path::new(filename).file_stem().unwrap_or_default();
+                let path = dir.join(stem).with_extension("dis");
+                let path = path.to_string_lossy();
+                let mut out = create_output_file(&path)?;
+                disassemble(filename, options, &mut out)?;
+                out.flush().map_err(|e| Error::runtime(&path, e))?;
+            }
+            None => {
+                if filenames.len() > 1 {
+                    let separator = if index > 0 { "\n" } else { "" };
+                    writeln!(output, "{}{}:", separator, filename)
+                        .map_err(|e| Error::runtime("output", e))?;
+                }
+                disassemble(filename, options, &mut output)?;
+            }
+        }
+    }
+    output.flush().map_err(|e| Error::runtime("output", e))
+}
+
+// Write the listing of an input file
+fn disassemble<W: Write>(
+    filename: &str,
+    options: &DisassemblerOptions,
+    out: &mut W,
+) -> Result<(), Error> {
+    info!("Loading elf: {}", filename);
+
+    let mem_data = match adapt_mem_adept::get_adept_data(filename) {
+        Ok(chunks) => chunks,
+        Err(e) => return Err(Error::load(filename, e.to_string())),
+    };
+
+    let show_disassembled = options.assembly;
+    let show_hex = options.instruction;
+    let show_counter = options.pc;
+    let show_ascii = options.ascii;
+    let show_all = !(show_disassembled || show_hex || show_counter || show_ascii);
+    let show_fields = options.fields;
+
+    // Only the chunks of executable sections hold instructions, unless
+    // the file has no section headers
+    let data = fs::read(filename).map_err(|e| Error::load(filename, e))?;
+    let sections = elf::sections(&data).unwrap_or_default();
+    let executable: Vec<_> = sections
+        .iter()
+        .filter(|section| section.is_executable())
+        .collect();
+    let all_sections = options.all_sections || sections.is_empty();
+
+    let chunks: Vec<_> = mem_data
+        .iter()
+        .filter(|chunk| {
+            all_sections
+                || executable.iter().any(|section| {
+                    section.overlaps(
+                        chunk.get_base_address() as u32,
+                        chunk.get_contents_length() as u32,
+                    )
+                })
+        })
+        .collect();
+
+    let mut words = Vec::new();
+    for chunk in &chunks {
+        let base_address = chunk.get_base_address() as u32;
+        let chunk_data = chunk.get_contents();
+        for offset in 0..(chunk.get_contents_length() >> 2) {
+            let bytes = &chunk_data[offset << 2..(offset << 2) + 4];
+            words.push((base_address + ((offset as u32) << 2), word_of(bytes)));
+        }
+    }
+
+    // The output is grouped by function, found by their code in stripped
+    // programs
+    let mut functions = elf::functions(&data);
+    if functions.is_empty() {
+        functions = boundary::detect(&words);
+    }
+
+    if let Some(ref path) = options.cfg {
+        let mut cfg = Cfg::new(&words);
+        if let Some(ref profile_path) = options.cfg_profile {
+            let profile = match fs::read_to_string(profile_path) {
+                Ok(text) => match Profile::parse_text(&text) {
+                    Ok(profile) => profile,
+                    Err(line) => {
+                        let message = format!("line {}: invalid profile line", line);
+                        return Err(Error::load(profile_path, message));
+                    }
+                },
+                Err(e) => return Err(Error::load(profile_path, e)),
+            };
+            cfg.annotate(&profile);
+        }
+        let mut dot = create_output_file(path)?;
+        cfg.write_dot(&mut dot, &functions)
+            .and_then(|_| dot.flush())
+            .map_err(|e| Error::runtime(path, e))?;
+    }
+
+    // The writes of the listing fail together
+    let list = || -> io::Result<()> {
+        if options.stats {
+            let mut histogram = Histogram::new();
+            for (_, word) in &words {
+                histogram.add(*word);
+            }
+            return write!(out, "{}", histogram);
+        }
+
+        for chunk in chunks {
+            let base_address = chunk.get_base_address();
+            let chunk_length = chunk.get_contents_length();
+            let chunk_data = chunk.get_contents();
+            writeln!(out, "{:x}", base_address)?;
+            for offset in 0..(chunk_length >> 2) {
+                let actual_offset = offset << 2;
+
+                let address = (base_address as u32) + (actual_offset as u32);
+
+                let bytes = &(chunk_data[actual_offset..actual_offset + 4]);
+
+                let instruction = word_of(bytes);
+
+                let decoded = Instruction::new(instruction);
+
+                if let Ok(index) = functions.binary_search_by_key(&address, |symbol| symbol.value) {
+                    writeln!(out, "\n{:08x} <{}>:", address, functions[index].name)?;
+                }
+
+                if show_counter || show_all {
+                    write!(out, "{:>8} ", address)?;
+                }
+                if show_hex || show_all {
+                    write!(out, "{:>10} ", instruction)?;
+                }
+                if show_ascii || show_all {
+                    write!(
+                        out,
+                        "[{}{}{}{}] ",
+                        byte_in_char(bytes[3]),
+                        byte_in_char(bytes[2]),
+                        byte_in_char(bytes[1]),
+                        byte_in_char(bytes[0])
+                    )?;
+                }
+                if show_disassembled || show_all {
+                    write!(out, "{}", decoded)?;
+                }
+                writeln!(out)?;
+                if show_fields {
+                    write!(out, "{}", fields::table(instruction))?;
+                }
+            }
+        }
+        Ok(())
+    };
+    list().map_err(|e| Error::runtime(filename, e))
+}
+
+// Little endian word of the first four bytes
+fn word_of(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0])
+        | u32::from(bytes[1]) << 8
+        | u32::from(bytes[2]) << 16
+        | u32::from(bytes[3]) << 24
+}
+
+fn byte_in_char(byte_in: u8) -> char {
+    if byte_in > 126 || byte_in < 32 {
+        '.'
+    } else {
+        byte_in as char
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    ////////////////////////////////////////////////////////////////////////////////
+    // Byte to Char Conversion Test
+    ////////////////////////////////////////////////////////////////////////////////
+    /// Test Registers Printing
+    #[test]
+    fn byte_to_char_test() {
+        // 128 = non_ASCII
+        assert_eq!('.', super::byte_in_char(128));
+        // 97 = letter 'a'
+        assert_eq!('a', super::byte_in_char(97));
+        // 65 = letter 'A'
+        assert_eq!('A', super::byte_in_char(65));
+    }
+}
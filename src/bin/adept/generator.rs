@@ -0,0 +1,50 @@
+//! The generator command, which writes random programs checking their own
+//! results, for the simulator or the testbench of a core.
+use std::io::Write;
+
+use adept_lib::fault::DEFAULT_SEED;
+use adept_lib::generator::{Generator, DEFAULT_BASE, DEFAULT_COUNT};
+use adept_lib::image::ExportFormat;
+use adept_lib::Error;
+
+use cli::GeneratorOptions;
+use create_output_file;
+
+/// Generate the program of the command line
+///
+/// # Arguments
+/// * `options` => options of the command
+pub fn run(options: &GeneratorOptions) -> Result<(), Error> {
+    let seed = options.seed.unwrap_or(DEFAULT_SEED);
+    let count = options.count.map_or(DEFAULT_COUNT, |count| count as usize);
+    let base = options.base.unwrap_or(DEFAULT_BASE);
+    let mix = options.mix.unwrap_or_default();
+
+    let program = Generator::new(seed, mix).generate(base, count)?;
+    info!(
+        "Generated {} instructions at 0x{:08x} with seed {}",
+        program.code.len(),
+        base,
+        seed
+    );
+
+    // The output is given as FILE[:FORMAT], an ELF file by default
+    let spec = options.output_file.as_str();
+    let (path, format) = match spec.rfind(':') {
+        Some(index) => (&spec[..index], Some(&spec[index + 1..])),
+        None => (spec, None),
+    };
+    let data = match format {
+        None if path.ends_with(".elf") || !path.contains('.') => program.elf(),
+        None => program.image().export(ExportFormat::detect(path)),
+        Some("elf") => program.elf(),
+        Some(name) => match ExportFormat::parse(name) {
+            Some(format) => program.image().export(format),
+            None => return Err(Error::invalid("program format", name)),
+        },
+    };
+    let mut file = create_output_file(path)?;
+    file.write_all(&data)
+        .and_then(|_| file.flush())
+        .map_err(|e| Error::runtime(path, e))
+}
@@ -0,0 +1,128 @@
+//! The adept command, which simulates, disassembles and generates RV32I
+//! programs through its subcommands:
+//!
+//! * `run` => simulate a program
+//! * `trace` => simulate a program, logging every event of the pipeline
+//! * `debug` => simulate a program under the interactive debugger
+//! * `disasm` => list the code of programs
+//! * `gen` => write random programs which check their own results
+//! * `replay` => time the commit log of a run on another pipeline model, or
+//!   sweep cache geometries over it
+//! * `analyze` => answer queries about the commit log of a run
+//!
+//! The options missing from the command line are taken from the environment
+//! or from the file given by --defaults, as the cli module tells.
+//!
+//! The simulation commands exit with a status telling how the program ended:
+//!
+//! * `0` => it exited with code 0
+//! * `1` => it exited with another code, or a check of the run failed
+//! * `3` => it ran into an instruction the processor can't decode
+//! * `4` => it crashed: an ebreak, a fault or the watchdog stopped it
+//! * `124` => --timeout-cycles or --timeout-seconds stopped it
+//! * `101` => the simulator itself failed
+extern crate adapt_mem_adept;
+extern crate adept_lib;
+extern crate structopt;
+#[macro_use]
+extern crate tracing;
+extern crate tracing_subscriber;
+
+mod analyzer;
+mod cli;
+mod disassembler;
+mod generator;
+mod replayer;
+mod simulator;
+
+use tracing::Level;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::sync::Mutex;
+
+use adept_lib::config::Config;
+use adept_lib::pipeline::PipelineConfig;
+use adept_lib::Error;
+
+use cli::{Command, GlobalOptions};
+
+fn main() {
+    let ran = cli::parse().and_then(|options| {
+        let global = &options.global;
+        set_logger(global, options.command.log_level())?;
+        match options.command {
+            Command::Run(ref run) | Command::Trace(ref run) => simulator::run(global, run, false),
+            Command::Debug(ref run) => simulator::run(global, run, true),
+            Command::Disasm(ref disasm) => disassembler::run(global, disasm),
+            Command::Gen(ref gen) => generator::run(gen),
+            Command::Replay(ref replay) => replayer::run(global, replay),
+            Command::Analyze(ref analyze) => analyzer::run(global, analyze),
+        }
+    });
+    if let Err(e) = ran {
+        eprintln!("error: {}", e.full_message());
+        process::exit(simulator::INTERNAL_ERROR_EXIT_CODE);
+    }
+}
+
+// Send the messages to the log file of the command line or to the standard
+// error, from the log level of the command line, its verbosity or the
+// default one of the subcommand
+fn set_logger(global: &GlobalOptions, default: Level) -> Result<(), Error> {
+    let level = match global.log_level {
+        Some(level) => level,
+        None if global.quiet => Level::ERROR,
+        None => match global.verbose {
+            0 => default,
+            1 => default.max(Level::DEBUG),
+            _ => Level::TRACE,
+        },
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .without_time()
+        .with_level(false)
+        .with_target(false);
+    match global.log_file {
+        // Unbuffered, the messages are kept if the simulator crashes
+        Some(ref path) => match File::create(path) {
+            Ok(file) => subscriber.with_writer(Mutex::new(file)).init(),
+            Err(e) => return Err(Error::runtime(path, e)),
+        },
+        None => subscriber.with_writer(io::stderr).init(),
+    }
+    Ok(())
+}
+
+// Open an output file of a command, such as a trace or a listing
+fn create_output_file(path: &str) -> Result<BufWriter<File>, Error> {
+    match File::create(path) {
+        Ok(file) => Ok(BufWriter::new(file)),
+        Err(e) => Err(Error::runtime(path, e)),
+    }
+}
+
+// Open the file of an option, or the standard output without it
+fn output_option(path: Option<&str>) -> Result<Box<dyn Write>, Error> {
+    Ok(match path {
+        Some(path) => Box::new(create_output_file(path)?),
+        None => Box::new(io::stdout()),
+    })
+}
+
+// Parse a number given in hexadecimal with a 0x prefix or in decimal
+fn parse_number(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+// Read the pipeline model parameters from a configuration file
+fn load_pipeline_config(path: &str) -> Result<PipelineConfig, Error> {
+    Config::from_file(path)
+        .and_then(|config| PipelineConfig::from_config(&config))
+        .map_err(|e| Error::load(path, e))
+}
@@ -0,0 +1,101 @@
+//! The replay command, which times the commit log of a run on a pipeline
+//! model without simulating the program again, or sweeps a grid of cache
+//! geometries over it, alone or with the logs of other harts through
+//! coherent caches.
+use std::fs::File;
+use std::io::{BufReader, Write};
+
+use adept_lib::cache::CacheConfig;
+use adept_lib::pipeline::PipelineConfig;
+use adept_lib::prefetch::{PrefetchConfig, PrefetchKind};
+use adept_lib::replay::{
+    coherence_sweep, read_trace, replay, sweep, write_coherence_csv, write_sweep_csv, Retired,
+};
+use adept_lib::Error;
+
+use cli::{GlobalOptions, ReplayOptions};
+use {load_pipeline_config, output_option};
+
+// Cycles a cache miss adds unless another number is given
+const DEFAULT_MISS_PENALTY: u32 = 10;
+
+/// Replay the commit log of the command line
+///
+/// # Arguments
+/// * `global` => options of every command
+/// * `options` => options of the command
+pub fn run(global: &GlobalOptions, options: &ReplayOptions) -> Result<(), Error> {
+    let config = match options.config {
+        Some(ref path) => load_pipeline_config(path)?,
+        None => PipelineConfig::default(),
+    };
+
+    let trace = load_trace(&options.commit_log)?;
+
+    let mut output = output_option(global.output.as_deref())?;
+    let written = match (cache_grid(options), options.coherence) {
+        (Some(caches), Some(protocol)) => {
+            let mut traces = vec![trace];
+            for path in &options.hart {
+                traces.push(load_trace(path)?);
+            }
+            let results = coherence_sweep(&traces, &caches, protocol);
+            write_coherence_csv(&results, protocol, &mut output)
+        }
+        (Some(caches), None) => {
+            let miss_penalty = options.miss_penalty.unwrap_or(DEFAULT_MISS_PENALTY);
+            let prefetch = PrefetchConfig {
+                icache: options.icache_prefetch,
+                dcache: options.dcache_prefetch,
+            };
+            if prefetch.icache == Some(PrefetchKind::Stride) {
+                return Err(Error::Invalid(
+                    "the stride prefetcher follows loads and stores, not fetches".to_string(),
+                ));
+            }
+            let results = sweep(config, &trace, &caches, miss_penalty, prefetch);
+            write_sweep_csv(&results, &mut output)
+        }
+        (None, _) => write!(output, "{}", replay(config, &trace)),
+    };
+    written
+        .and_then(|_| output.flush())
+        .map_err(|e| Error::runtime("output", e))
+}
+
+// Read the commit log of a run
+fn load_trace(path: &str) -> Result<Vec<Retired>, Error> {
+    info!("Loading commit log: {}", path);
+    match File::open(path) {
+        Ok(file) => read_trace(BufReader::new(file)).map_err(|e| Error::load(path, e)),
+        Err(e) => Err(Error::load(path, e)),
+    }
+}
+
+// Every geometry of the sizes, lines and ways of the command line, if it
+// asks for a sweep. The combinations which don't make a cache are skipped.
+fn cache_grid(options: &ReplayOptions) -> Option<Vec<CacheConfig>> {
+    let list = |values: &[u32], default: u32| match values {
+        [] => vec![default],
+        values => values.to_vec(),
+    };
+    if options.cache_sizes.is_empty() {
+        return None;
+    }
+    let sizes = &options.cache_sizes;
+    let lines = list(&options.cache_lines, 32);
+    let ways = list(&options.cache_ways, 1);
+
+    let mut caches = Vec::new();
+    for size in sizes {
+        for line in &lines {
+            for way in &ways {
+                match CacheConfig::new(*size, *line, *way) {
+                    Ok(cache) => caches.push(cache),
+                    Err(e) => warn!("Skipping cache: {}", e),
+                }
+            }
+        }
+    }
+    Some(caches)
+}
@@ -0,0 +1,1127 @@
+//! The simulation commands, which run a program and differ in how it's
+//! observed: run, trace and debug.
+use tracing::Level;
+
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+
+use adept_lib::benchmark::BenchmarkResult;
+use adept_lib::boot::{BootRom, DEFAULT_RESET_VECTOR};
+use adept_lib::bus::Region;
+use adept_lib::campaign::Campaign;
+use adept_lib::coverage::Coverage;
+use adept_lib::cpu::Cpu;
+use adept_lib::crash::{self, Crash, DEFAULT_HISTORY};
+use adept_lib::debug_module::DebugModule;
+use adept_lib::debugger::{BreakpointSpec, Command, Debugger};
+use adept_lib::diagnostics::Diagnostics;
+use adept_lib::divergence::Divergence;
+use adept_lib::elf::{self, Symbol, SEGMENT_EXECUTE};
+use adept_lib::env::{self, Guard, Heap, DEFAULT_STACK_SIZE, GUARD_SIZE};
+use adept_lib::fault::{MemoryFaults, Outcome, RegisterFlip, Rng, DEFAULT_SEED};
+use adept_lib::fdt::{self, DeviceTree};
+use adept_lib::flamegraph::Flamegraph;
+use adept_lib::hart::{
+    EnvironmentCallPolicy, IllegalInstructionPolicy, MemoryErrorPolicy, StopReason,
+    UninitializedPolicy,
+};
+use adept_lib::heatmap::{Heatmap, DEFAULT_GRANULARITY};
+use adept_lib::image::{ExportFormat, ImageFormat, MemoryImage};
+use adept_lib::jtag::serve_remote_bitbang;
+use adept_lib::lockstep::Lockstep;
+use adept_lib::manifest::Manifest;
+use adept_lib::mem::{MemStoreOp, Memory};
+use adept_lib::pipeline::{PipelineConfig, Stages};
+use adept_lib::profile::{CycleProfile, Profile, ProfileSort};
+use adept_lib::report::Report;
+use adept_lib::riscv::isa::RV32I;
+use adept_lib::stats::Comparison;
+use adept_lib::stimulus::ScriptConsole;
+use adept_lib::summary::Summary;
+use adept_lib::taint::{TaintSink, TaintTracker};
+use adept_lib::timeline;
+use adept_lib::trace::{CommitLog, EventLog, KonataLog, OccupancyLog, RiscvDvLog, StateLog};
+use adept_lib::tracepoint::TracepointSpec;
+use adept_lib::uart::{Console, StreamConsole};
+use adept_lib::Error;
+
+use cli::{
+    ConsoleSpec, CoverageFormat, GlobalOptions, HeatmapFormat, ProfileFormat, RegisterFault,
+    SimulatorOptions, Transport,
+};
+use {create_output_file, load_pipeline_config, output_option, parse_number};
+
+// Exit code of a program which exited with a code other than 0, through
+// tohost or the exit system call
+const GUEST_FAIL_EXIT_CODE: i32 = 1;
+// Exit code of a program stopped by an instruction the processor can't decode
+const ILLEGAL_INSTRUCTION_EXIT_CODE: i32 = 3;
+// Exit code of a program stopped for any other reason: an ebreak, a fault, an
+// unsupported system call or the watchdog
+const CRASH_EXIT_CODE: i32 = 4;
+// Exit code of a simulation stopped by --timeout-cycles or --timeout-seconds,
+// the one of timeout(1)
+const TIMEOUT_EXIT_CODE: i32 = 124;
+// Exit code of an error of the simulator itself rather than of the program,
+// the one of a panic
+pub const INTERNAL_ERROR_EXIT_CODE: i32 = 101;
+// Exit code of a simulation whose console script didn't get the output it
+// expected
+const CONSOLE_SCRIPT_EXIT_CODE: i32 = 1;
+// Exit code of --diff-config when the runs diverge
+const DIVERGENCE_EXIT_CODE: i32 = 1;
+// Exit code of --lockstep when a commit record of the testbench didn't match
+const LOCKSTEP_EXIT_CODE: i32 = 1;
+
+/// Simulate the program of the command line
+///
+/// # Arguments
+/// * `global` => options of every command
+/// * `options` => options of the command
+/// * `interactive` => run the program under the interactive debugger
+pub fn run(
+    global: &GlobalOptions,
+    options: &SimulatorOptions,
+    interactive: bool,
+) -> Result<(), Error> {
+    let mut output = output_option(global.output.as_deref())?;
+    let mut pipeline_config = match options.config {
+        Some(ref path) => load_pipeline_config(path)?,
+        None => PipelineConfig::default(),
+    };
+
+    let reset_vector = options.reset_vector;
+    // The scratchpad answers in a single cycle, whatever the bus regions
+    let scratchpad = match options.scratchpad {
+        Some((addr, size)) => {
+            if size == 0 || u64::from(addr) + u64::from(size) > 1 << 32 {
+                return Err(Error::invalid(
+                    "scratchpad",
+                    format!("0x{:x}:0x{:x}", addr, size),
+                ));
+            }
+            let scratchpad = Region::new("scratchpad", addr, size, 1);
+            if let Some(other) = pipeline_config
+                .bus
+                .regions()
+                .iter()
+                .find(|other| other.overlaps(&scratchpad))
+            {
+                return Err(Error::Invalid(format!(
+                    "the scratchpad overlaps the bus region {}",
+                    other.name
+                )));
+            }
+            pipeline_config.bus.add_region(scratchpad);
+            Some((addr, size))
+        }
+        None => None,
+    };
+
+    let filename = &options.input_elf;
+    info!("Loading elf: {}", filename);
+
+    let mut image = MemoryImage::new();
+    load_elf(&mut image, filename, 0)?;
+    // The heap follows the main program
+    let image_end = image.end();
+    for spec in &options.load {
+        load_image(&mut image, spec)?;
+    }
+    // Address and value of every word of the program
+    let program = image.words();
+
+    let data = fs::read(filename).map_err(|e| Error::load(filename, e))?;
+    if let Some(ref path) = options.manifest {
+        write_manifest(options, path, interactive)?;
+    }
+    let entry = elf::entry_point(&data);
+    // Start in the boot ROM, which jumps to the entry point, or directly
+    // in the program
+    let boot_rom = if options.boot_rom {
+        let base = reset_vector.unwrap_or(DEFAULT_RESET_VECTOR);
+        Some(BootRom::new(base, entry.unwrap_or(0), 0))
+    } else {
+        None
+    };
+    let boot = Boot {
+        start: match boot_rom {
+            Some(ref rom) => rom.base,
+            None => reset_vector.or(entry).unwrap_or(0),
+        },
+        rom: boot_rom,
+        stack_pointer: options.stack_pointer,
+        stack_size: options.stack_size,
+        heap_size: options.heap_size,
+        guards: options.guard_pages,
+        check_returns: options.check_returns,
+        misaligned_access: options.misaligned_access,
+        memory_bits: options.memory_bits,
+        scratchpad,
+        read_only: match options.read_only {
+            Some((addr, size)) => {
+                if addr & 3 != 0 || size & 3 != 0 {
+                    return Err(Error::Invalid(format!(
+                        "the read-only region must be aligned to words: 0x{:x}:0x{:x}",
+                        addr, size
+                    )));
+                }
+                Some((addr, size))
+            }
+            None => None,
+        },
+        tohost: elf::symbol(&data, "tohost"),
+        poison: options.poison,
+        taint: taint_option(options),
+        coverage: options.coverage.is_some() || options.profile.is_some(),
+        diagnostics: if options.no_diagnostics {
+            None
+        } else {
+            let mut diagnostics = Diagnostics::new();
+            for segment in elf::segments(&data).into_iter().flatten() {
+                if segment.flags & SEGMENT_EXECUTE != 0 {
+                    diagnostics.add_code_region(segment.address, segment.size);
+                }
+            }
+            Some(diagnostics)
+        },
+        heatmap: options
+            .heatmap
+            .as_ref()
+            .map(|_| options.heatmap_granularity.unwrap_or(DEFAULT_GRANULARITY)),
+        flamegraph: options
+            .flamegraph
+            .as_ref()
+            .map(|_| u64::from(options.flamegraph_period.unwrap_or(1))),
+        uninitialized: options.uninitialized,
+        illegal_instructions: options
+            .illegal_instruction
+            .unwrap_or(IllegalInstructionPolicy::Trap),
+        memory_errors: options.memory_error.unwrap_or(MemoryErrorPolicy::Stop),
+        environment_calls: options.ecall.unwrap_or(EnvironmentCallPolicy::Emulate),
+        device_tree: options.device_tree,
+        clock: options.clock,
+        arguments: if !options.args.is_empty() || !options.env.is_empty() {
+            // The first argument is the name of the program
+            let mut args = vec![filename.to_string()];
+            args.extend(options.args.iter().cloned());
+            Some((args, options.env.clone()))
+        } else {
+            None
+        },
+        image_end,
+    };
+    if boot.device_tree && boot.arguments.is_some() {
+        return Err(Error::Invalid(
+            "the device tree and the arguments of main are both passed in a1".to_string(),
+        ));
+    }
+
+    let memory_faults = memory_faults_option(options)?;
+
+    if options.compare_configs {
+        let mut comparison = Comparison::new();
+        for stages in Stages::ALL.iter() {
+            let mut cpu = Cpu::new(PipelineConfig {
+                stages: *stages,
+                ..pipeline_config.clone()
+            });
+            load_program(&mut cpu, &program, &boot)?;
+            cpu.set_memory_faults(memory_faults.clone());
+            let reason = cpu.run();
+            info!("{}: simulation stopped: {}", stages.name(), reason);
+            comparison.add(stages.name(), cpu.stats().clone());
+        }
+        return write_stats(options, &mut output, &comparison);
+    }
+
+    if let Some(ref path) = options.diff_config {
+        let other_config = load_pipeline_config(path)?;
+        let mut runs = Vec::new();
+        for config in [pipeline_config, other_config].iter() {
+            let mut cpu = Cpu::new(config.clone());
+            load_program(&mut cpu, &program, &boot)?;
+            let reason = cpu.run();
+            info!(
+                "{} cycles: simulation stopped: {}",
+                cpu.stats().cycles,
+                reason
+            );
+            runs.push((cpu, reason));
+        }
+        match Divergence::find((&runs[0].0, runs[0].1), (&runs[1].0, runs[1].1)) {
+            Some(divergence) => {
+                print_to(&mut output, format_args!("divergence: {}\n", divergence))?;
+                output.flush().ok();
+                process::exit(DIVERGENCE_EXIT_CODE);
+            }
+            None => return print_to(&mut output, format_args!("no divergence\n")),
+        }
+    }
+
+    if let Some(transport) = options.lockstep {
+        let mut cpu = Cpu::new(pipeline_config);
+        load_program(&mut cpu, &program, &boot)?;
+        let mut lockstep = Lockstep::new();
+        let served = match transport {
+            Transport::Stdio => {
+                let stdin = io::stdin();
+                lockstep.serve(cpu.hart_mut(), stdin.lock(), io::stdout())
+            }
+            Transport::Tcp(port) => {
+                let listener = TcpListener::bind(("127.0.0.1", port))
+                    .map_err(|e| Error::runtime(&format!("lockstep port {}", port), e))?;
+                info!("Waiting for the testbench on port {}", port);
+                listener.accept().and_then(|(stream, addr)| {
+                    info!("Testbench connected from {}", addr);
+                    let reader = io::BufReader::new(stream.try_clone()?);
+                    lockstep.serve(cpu.hart_mut(), reader, stream)
+                })
+            }
+        };
+        let summary = served.map_err(|e| Error::runtime("lockstep", e))?;
+        info!(
+            "Checked {} commits, {} mismatches",
+            summary.commits, summary.mismatches
+        );
+        if let Some(reason) = summary.stopped {
+            info!("simulation stopped: {}", reason);
+        }
+        if summary.mismatches > 0 {
+            process::exit(LOCKSTEP_EXIT_CODE);
+        }
+        return Ok(());
+    }
+
+    if let Some(runs) = options.fault_campaign {
+        let mut cpu = Cpu::new(pipeline_config);
+        load_program(&mut cpu, &program, &boot)?;
+        let mut campaign = Campaign::new(&cpu);
+        info!(
+            "Run without faults stopped after {} cycles: {}",
+            campaign.golden().cycles,
+            campaign.golden().reason
+        );
+        let seed = seed_option(options);
+        let flips = campaign.random_flips(runs as usize, seed);
+        campaign.run(&flips)?;
+        return campaign
+            .write_report(&mut output, &elf::functions(&data))
+            .map_err(|e| Error::runtime("campaign report", e));
+    }
+
+    if let Some(fault) = options.inject_register {
+        let mut golden = Cpu::new(pipeline_config.clone());
+        load_program(&mut golden, &program, &boot)?;
+        golden.set_memory_faults(memory_faults.clone());
+        let golden = golden.run_to_result();
+        info!(
+            "Run without the fault stopped after {} cycles: {}",
+            golden.cycles, golden.reason
+        );
+
+        let flip = match fault {
+            RegisterFault::Random => {
+                let seed = seed_option(options);
+                RegisterFlip::random(&mut Rng::new(seed), golden.cycles.max(1))
+            }
+            RegisterFault::Flip(flip) => flip,
+        };
+        let mut cpu = Cpu::new(pipeline_config);
+        load_program(&mut cpu, &program, &boot)?;
+        cpu.set_memory_faults(memory_faults);
+        cpu.set_register_fault(Some(flip))?;
+        cpu.set_cycle_limit(Some(golden.hang_limit()));
+        let result = cpu.run_to_result();
+        info!("Run with the fault stopped: {}", result.reason);
+        return print_to(
+            &mut output,
+            format_args!(
+                "fault: {}\noutcome: {}\n",
+                flip,
+                Outcome::classify(&golden, &result)
+            ),
+        );
+    }
+
+    let mut cpu = Cpu::new(pipeline_config);
+    load_program(&mut cpu, &program, &boot)?;
+    cpu.set_memory_faults(memory_faults);
+    // The report lists the hot functions
+    if options.function_profile.is_some() || options.report.is_some() {
+        cpu.set_cycle_profile(Some(CycleProfile::new()));
+    }
+    info!("Finished loading memory from elf");
+    if let Some(console) = console_option(options)? {
+        cpu.hart_mut().uart_mut().set_console(Some(console));
+    }
+
+    let stages = cpu.pipeline_config().stages;
+    if let Some(ref path) = options.trace_file {
+        let out = create_output_file(path)?;
+        cpu.add_tracer(Box::new(EventLog::with_output(Box::new(out))));
+    } else if enabled!(Level::TRACE) {
+        cpu.add_tracer(Box::new(EventLog::new()));
+    }
+    if let Some(ref path) = options.pipeline_log {
+        let out = create_output_file(path)?;
+        cpu.add_tracer(Box::new(OccupancyLog::new(out, stages)));
+    }
+    if let Some(ref path) = options.konata {
+        let out = create_output_file(path)?;
+        cpu.add_tracer(Box::new(KonataLog::new(out, stages)));
+    }
+    if let Some(ref path) = options.state_log {
+        let out = create_output_file(path)?;
+        cpu.add_tracer(Box::new(StateLog::new(out)));
+    }
+    if let Some(ref path) = options.riscv_dv_trace {
+        let out = create_output_file(path)?;
+        cpu.add_tracer(Box::new(RiscvDvLog::new(out)));
+    }
+    if let Some(ref path) = options.commit_log {
+        let out = create_output_file(path)?;
+        cpu.add_tracer(Box::new(CommitLog::new(out)));
+    }
+
+    let timeout_cycles = options.timeout_cycles;
+    cpu.set_cycle_limit(timeout_cycles.or(options.watchdog).map(u64::from));
+    cpu.set_time_limit(options.timeout_seconds);
+    cpu.keep_history(
+        options
+            .crash_history
+            .map_or(DEFAULT_HISTORY, |count| count as usize),
+    );
+
+    let functions = elf::functions(&data);
+    let symbols = elf::symbols(&data);
+    for spec in &options.tracepoint {
+        let tracepoint = TracepointSpec::parse(spec).and_then(|spec| {
+            spec.resolve(cpu.hart(), &symbols)
+                .map_err(|e| e.to_string())
+        });
+        match tracepoint {
+            Ok(tracepoint) => cpu.tracepoints_mut().add(tracepoint),
+            Err(e) => {
+                return Err(Error::Invalid(format!(
+                    "invalid tracepoint {}: {}",
+                    spec, e
+                )))
+            }
+        }
+    }
+    let run_until = &options.run_until;
+    let interactive = interactive
+        || options.debug
+        || !options.breakpoints.is_empty()
+        || options.time_travel
+        || run_until.is_some();
+    let mut debugger = if interactive {
+        let mut debugger = Debugger::new(symbols);
+        if options.time_travel {
+            let interval = options
+                .snapshot_interval
+                .map_or(timeline::DEFAULT_INTERVAL, u64::from);
+            debugger.record(&cpu, interval);
+        }
+        for spec in &options.breakpoints {
+            let added = BreakpointSpec::parse(spec).and_then(|spec| {
+                debugger
+                    .add_breakpoint(&spec, cpu.hart())
+                    .map_err(|e| e.to_string())
+            });
+            if let Err(e) = added {
+                let message = format!("invalid breakpoint {}: {}", spec, e);
+                return Err(Error::Invalid(message));
+            }
+        }
+        Some(debugger)
+    } else {
+        None
+    };
+    // A panic of the simulator is reported like a crash of the program
+    let reason = match panic::catch_unwind(AssertUnwindSafe(|| match debugger {
+        Some(ref mut debugger) => {
+            let stdin = io::stdin();
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            let ran = match run_until {
+                Some(ref location) => {
+                    debugger.execute(&mut cpu, &Command::Until(location.clone()), &mut out)
+                }
+                None => Ok(()),
+            };
+            ran.and_then(|_| debugger.run(&mut cpu, stdin.lock(), &mut out))
+                .map_err(|e| Error::runtime("debugger", e))
+        }
+        None => match (options.debug_port, options.remote_bitbang) {
+            (Some(port), _) => {
+                serve_debugger(&mut cpu, port, |dm, cpu, stream| dm.serve(cpu, stream))
+            }
+            (None, Some(port)) => serve_debugger(&mut cpu, port, serve_remote_bitbang),
+            (None, None) => Ok(Some(cpu.run())),
+        },
+    })) {
+        // The user left the debugger before the program stopped
+        Ok(Ok(None)) => return Ok(()),
+        Ok(Ok(Some(reason))) => reason,
+        Ok(Err(e)) => return Err(e),
+        Err(payload) => {
+            write_crash_report(options, &Crash::from_panic(&*payload), &cpu, &functions)?;
+            // The panic hook already reported the error
+            output.flush().ok();
+            process::exit(INTERNAL_ERROR_EXIT_CODE);
+        }
+    };
+    info!("Simulation stopped: {}", reason);
+    if let Some(crash) = Crash::of(reason) {
+        write_crash_report(options, &crash, &cpu, &functions)?;
+    }
+    if reason != StopReason::Exit(0) {
+        warn!(
+            "Backtrace:\n{}",
+            cpu.hart()
+                .call_stack()
+                .backtrace(cpu.hart().pc(), &functions)
+        );
+    }
+    for read in cpu.hart().uninitialized_reads() {
+        warn!(
+            "warning: load at pc 0x{:08x} read uninitialized memory at 0x{:08x}",
+            read.pc, read.addr
+        );
+    }
+    for used in cpu.hart().extension_uses() {
+        warn!(
+            "warning: {} instructions of the unsupported {} extension, the first one at pc 0x{:08x}",
+            used.count, used.extension, used.first_pc
+        );
+    }
+    for skipped in cpu.hart().skipped_instructions() {
+        warn!(
+            "warning: skipped invalid instruction 0x{:08x} at pc 0x{:08x}",
+            skipped.raw, skipped.pc
+        );
+    }
+    if let Some(tracker) = cpu.hart().taint_tracker() {
+        for event in tracker.events() {
+            match event.sink {
+                TaintSink::Pc => {
+                    warn!("taint: jump to a tainted address at pc 0x{:08x}", event.pc)
+                }
+                TaintSink::Memory(addr) => warn!(
+                    "taint: store of tainted data to 0x{:08x} at pc 0x{:08x}",
+                    addr, event.pc
+                ),
+            }
+        }
+    }
+    if let Some(faults) = cpu.memory_faults() {
+        for flip in faults.injected() {
+            warn!("fault: {}", flip);
+        }
+    }
+    if let (Some(path), Some(coverage)) = (&options.coverage, cpu.hart().coverage()) {
+        let mut out = create_output_file(path)?;
+        let written = match options.coverage_format.unwrap_or(CoverageFormat::Lcov) {
+            CoverageFormat::Lcov => coverage.write_lcov(&mut out, filename, &functions, &program),
+            CoverageFormat::Addresses => coverage.write_addresses(&mut out, &functions),
+        };
+        written
+            .and_then(|_| out.flush())
+            .map_err(|e| Error::runtime(path, e))?;
+    }
+    if let (Some(path), Some(coverage)) = (&options.profile, cpu.hart().coverage()) {
+        let profile = Profile::new(coverage);
+        let mut out = create_output_file(path)?;
+        let written = match options.profile_format.unwrap_or(ProfileFormat::Text) {
+            ProfileFormat::Text => profile.write_text(&mut out, &functions),
+            ProfileFormat::Dot => profile.write_dot(&mut out, &functions),
+        };
+        written
+            .and_then(|_| out.flush())
+            .map_err(|e| Error::runtime(path, e))?;
+    }
+    if let (Some(path), Some(profile)) = (&options.function_profile, cpu.cycle_profile()) {
+        let sort = options.function_sort.unwrap_or(ProfileSort::Cycles);
+        let mut out = create_output_file(path)?;
+        profile
+            .write_table(&mut out, &functions, sort)
+            .and_then(|_| out.flush())
+            .map_err(|e| Error::runtime(path, e))?;
+    }
+    if let (Some(path), Some(flamegraph)) = (&options.flamegraph, cpu.hart().flamegraph()) {
+        let mut out = create_output_file(path)?;
+        flamegraph
+            .write_folded(&mut out, &functions)
+            .and_then(|_| out.flush())
+            .map_err(|e| Error::runtime(path, e))?;
+    }
+    if let (Some(path), Some(heatmap)) = (&options.heatmap, cpu.hart().heatmap()) {
+        let mut out = create_output_file(path)?;
+        let written = match options.heatmap_format.unwrap_or(HeatmapFormat::Text) {
+            HeatmapFormat::Text => heatmap.write_text(&mut out),
+            HeatmapFormat::Csv => heatmap.write_csv(&mut out),
+        };
+        written
+            .and_then(|_| out.flush())
+            .map_err(|e| Error::runtime(path, e))?;
+    }
+    if let Some(ref path) = options.summary_json {
+        let mut out = create_output_file(path)?;
+        Summary::new(&cpu, reason)
+            .write_json(&mut out)
+            .and_then(|_| out.flush())
+            .map_err(|e| Error::runtime(path, e))?;
+    }
+    if let Some(ref path) = options.report {
+        let functions = cpu.cycle_profile().map_or_else(Vec::new, |profile| {
+            profile.functions(&functions, ProfileSort::Cycles)
+        });
+        let mut out = create_output_file(path)?;
+        Report::new(filename, Summary::new(&cpu, reason), functions)
+            .write_html(&mut out)
+            .and_then(|_| out.flush())
+            .map_err(|e| Error::runtime(path, e))?;
+    }
+    if let Some(ref spec) = options.export_mem {
+        export_memory(cpu.hart().memory(), spec)?;
+    }
+    // Only the output of the program goes to the output, the reports of
+    // the simulator go to the standard error unless they're quiet
+    let program_output = String::from_utf8_lossy(cpu.hart().output());
+    print_to(&mut output, format_args!("{}", program_output))?;
+    let mut report = String::new();
+    if options.benchmark {
+        match BenchmarkResult::parse(&program_output) {
+            Some(result) => report.push_str(&result.to_string()),
+            None => warn!("No benchmark results found"),
+        }
+    }
+    report.push_str(&cpu.stats().to_string());
+    if !global.quiet || options.stats_file.is_some() {
+        write_stats(options, &mut io::stderr(), &report)?;
+    }
+    let exit_code = match exit_code(reason, timeout_cycles.is_some()) {
+        0 => match cpu.hart().uart().console() {
+            Some(console) if !console.finished() => {
+                error!("The console script didn't run to its end");
+                CONSOLE_SCRIPT_EXIT_CODE
+            }
+            _ => 0,
+        },
+        code => code,
+    };
+    if exit_code != 0 {
+        // The tracers flush their files when the processor is dropped
+        drop(cpu);
+        output.flush().ok();
+        process::exit(exit_code);
+    }
+    Ok(())
+}
+
+// Exit code of the command for the reason the simulation stopped, so that
+// scripts tell a passing program from a failing or crashing one. The watchdog
+// stops a hung program, it only times out with --timeout-cycles.
+fn exit_code(reason: StopReason, timeout_cycles: bool) -> i32 {
+    match reason {
+        StopReason::Exit(0) => 0,
+        StopReason::Exit(_) => GUEST_FAIL_EXIT_CODE,
+        StopReason::InvalidInstruction { .. } => ILLEGAL_INSTRUCTION_EXIT_CODE,
+        StopReason::CycleLimit(_) if timeout_cycles => TIMEOUT_EXIT_CODE,
+        StopReason::TimeLimit(_) => TIMEOUT_EXIT_CODE,
+        StopReason::ConsoleMismatch { .. } => CONSOLE_SCRIPT_EXIT_CODE,
+        StopReason::TraceError(_) => INTERNAL_ERROR_EXIT_CODE,
+        _ => CRASH_EXIT_CODE,
+    }
+}
+
+// Where the processor starts and how the environment of the program is set
+// up
+struct Boot {
+    start: u32,
+    rom: Option<BootRom>,
+    // The top of the memory by default
+    stack_pointer: Option<u32>,
+    // DEFAULT_STACK_SIZE by default
+    stack_size: Option<u32>,
+    // Everything between the program and the stack by default
+    heap_size: Option<u32>,
+    // Place guard regions below the stack and after the heap
+    guards: bool,
+    // Stop when a return doesn't match its call
+    check_returns: bool,
+    // Split loads and stores which cross a word instead of stopping
+    misaligned_access: bool,
+    // Address bits of the memory, DEFAULT_ADDRESS_BITS by default
+    memory_bits: Option<u32>,
+    // Scratchpad memory apart from the main one, as ADDR and SIZE
+    scratchpad: Option<(u32, u32)>,
+    // Region of the image which is mapped read-only, as ADDR and SIZE
+    read_only: Option<(u32, u32)>,
+    // Address where the program stores its exit code
+    tohost: Option<u32>,
+    // Pattern filling the memory before the program is loaded
+    poison: Option<u32>,
+    // Sources and sinks of tainted data, when tracked
+    taint: Option<TaintTracker>,
+    // Record the executed instructions and branch outcomes
+    coverage: bool,
+    // Warn about suspicious behavior of the program, with its code regions
+    diagnostics: Option<Diagnostics>,
+    // Size of the regions of the heatmap of memory accesses, when recorded
+    heatmap: Option<u32>,
+    // Instructions between two samples of the call stack, when sampled
+    flamegraph: Option<u64>,
+    // Policy for reads of memory the program never wrote, when checked
+    uninitialized: Option<UninitializedPolicy>,
+    // Handling of instructions which can't be executed
+    illegal_instructions: IllegalInstructionPolicy,
+    // Handling of misaligned and faulting memory accesses
+    memory_errors: MemoryErrorPolicy,
+    // Emulated system calls or traps to the operating system
+    environment_calls: EnvironmentCallPolicy,
+    // Place a device tree below the stack, with its address in a1
+    device_tree: bool,
+    // Frequency of the core clock in Hz, which mtime follows when given
+    clock: Option<u64>,
+    // Arguments and environment variables passed to main
+    arguments: Option<(Vec<String>, Vec<String>)>,
+    image_end: u32,
+}
+
+// Store the program and the boot ROM in the memory of the processor, set up
+// the stack, the arguments and the heap and point the processor to the first
+// instruction
+fn load_program(cpu: &mut Cpu, program: &[(u32, u32)], boot: &Boot) -> Result<(), Error> {
+    let hart = cpu.hart_mut();
+    if let Some(bits) = boot.memory_bits {
+        *hart.memory_mut() = Memory::with_address_bits(bits)?;
+    }
+    if let Some(pattern) = boot.poison {
+        hart.memory_mut().fill(pattern);
+    }
+    // Words of the image in the scratchpad are stored there
+    if let Some((base, size)) = boot.scratchpad {
+        hart.memory_mut().map_scratchpad(base, size)?;
+    }
+    hart.memory_mut()
+        .set_misaligned_access(boot.misaligned_access);
+    if let Some(policy) = boot.uninitialized {
+        hart.memory_mut().track_writes();
+        hart.set_uninitialized_policy(policy);
+    }
+    // The words in the read-only region are kept apart from the memory
+    let mut rom = match boot.read_only {
+        Some((_, size)) if u64::from(size) > hart.memory().size() => {
+            return Err(Error::Invalid(
+                "the read-only region is bigger than the memory".to_string(),
+            ));
+        }
+        Some((base, size)) => Some((base, vec![0; size as usize])),
+        None => None,
+    };
+    for (address, word) in program {
+        if let Some((base, ref mut bytes)) = rom {
+            let offset = address.wrapping_sub(base) as usize;
+            if offset < bytes.len() {
+                bytes[offset..offset + 4].copy_from_slice(&word.to_le_bytes());
+                continue;
+            }
+        }
+        // Words of the image are aligned
+        hart.memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SW), *address, *word)
+            .unwrap();
+    }
+    if let Some((base, bytes)) = rom {
+        hart.memory_mut().map_rom(base, bytes);
+    }
+
+    // The top of a memory covering the whole address space is past the last
+    // address, so its stack starts just below
+    let memory_size = hart.memory().size();
+    let mut stack_top = boot
+        .stack_pointer
+        .unwrap_or_else(|| memory_size.min(u64::from(u32::MAX) & !15) as u32);
+    if boot.device_tree {
+        if memory_size > u64::from(u32::MAX) {
+            return Err(Error::Invalid(
+                "the device tree can't describe a memory of 4 GiB".to_string(),
+            ));
+        }
+        let blob = DeviceTree::new(fdt::platform(memory_size as u32)).to_blob();
+        let address = stack_top.wrapping_sub(blob.len() as u32) & !15;
+        for (offset, byte) in blob.iter().enumerate() {
+            hart.memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SB),
+                    address.wrapping_add(offset as u32),
+                    u32::from(*byte),
+                )
+                .unwrap();
+        }
+        // Boot hart and device tree
+        hart.registers_mut().write(10, 0);
+        hart.registers_mut().write(11, address as i32);
+        stack_top = address;
+    }
+    let mut stack_pointer = stack_top;
+    if let Some((ref args, ref vars)) = boot.arguments {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let vars: Vec<&str> = vars.iter().map(String::as_str).collect();
+        let block = env::write_arguments(hart.memory_mut(), stack_pointer, &args, &vars);
+        let registers = hart.registers_mut();
+        registers.write(10, block.argc as i32);
+        registers.write(11, block.argv as i32);
+        registers.write(12, block.envp as i32);
+        stack_pointer = block.stack_pointer;
+    }
+    if let Some(ref rom) = boot.rom {
+        let rom = BootRom {
+            stack_pointer,
+            ..rom.clone()
+        };
+        if let Err(e) = rom.load(hart.memory_mut()) {
+            return Err(Error::invalid("reset vector", e));
+        }
+    }
+    hart.registers_mut().write(2, stack_pointer as i32);
+    hart.set_illegal_instruction_policy(boot.illegal_instructions);
+    hart.set_memory_error_policy(boot.memory_errors);
+    hart.set_environment_call_policy(boot.environment_calls);
+    hart.set_return_checks(boot.check_returns);
+    hart.set_tohost(boot.tohost);
+    hart.clint_mut().set_clock(boot.clock);
+    hart.set_taint_tracker(boot.taint.clone());
+    if boot.coverage {
+        hart.set_coverage(Some(Coverage::new()));
+    }
+    hart.set_diagnostics(boot.diagnostics.clone());
+    if let Some(granularity) = boot.heatmap {
+        hart.set_heatmap(Some(Heatmap::new(granularity)));
+    }
+    if let Some(period) = boot.flamegraph {
+        hart.set_flamegraph(Some(Flamegraph::new(period)));
+    }
+
+    // The guard of the stack sits between the heap and the stack
+    let stack_size = boot.stack_size.unwrap_or(DEFAULT_STACK_SIZE);
+    let reserved = if boot.guards {
+        stack_size.saturating_add(GUARD_SIZE)
+    } else {
+        stack_size
+    };
+    let heap = Heap::below_stack(boot.image_end, stack_top, boot.heap_size, reserved);
+    if boot.guards {
+        let stack_guard = Guard::below_stack(stack_top, stack_size);
+        let heap_guard = Guard::after_heap(&heap);
+        hart.add_guard(stack_guard);
+        if !stack_guard.overlaps(heap_guard.start, heap_guard.size) {
+            hart.add_guard(heap_guard);
+        }
+    }
+    hart.set_heap(heap);
+
+    cpu.set_pc(boot.start);
+    Ok(())
+}
+
+// Serve a debugger connecting to a local port with the Debug Module,
+// returning why the program stopped the last time
+fn serve_debugger<F>(cpu: &mut Cpu, port: u16, serve: F) -> Result<Option<StopReason>, Error>
+where
+    F: FnOnce(&mut DebugModule, &mut Cpu, TcpStream) -> io::Result<Option<StopReason>>,
+{
+    let context = format!("debug port {}", port);
+    let listener =
+        TcpListener::bind(("127.0.0.1", port)).map_err(|e| Error::runtime(&context, e))?;
+    info!("Waiting for the debugger on port {}", port);
+    listener
+        .accept()
+        .and_then(|(stream, addr)| {
+            info!("Debugger connected from {}", addr);
+            serve(&mut DebugModule::new(), cpu, stream)
+        })
+        .map_err(|e| Error::runtime(&context, e))
+}
+
+// Write the loadable segments of an ELF file to the image, moved by
+// `offset`, clearing the bytes past their contents
+fn load_elf(image: &mut MemoryImage, path: &str, offset: u32) -> Result<(), Error> {
+    let data = fs::read(path).map_err(|e| Error::load(path, e))?;
+    let segments = match elf::segments(&data) {
+        Some(segments) => segments,
+        None => return Err(Error::load(path, "invalid or unsupported program headers")),
+    };
+
+    for segment in segments {
+        let address = segment.address.wrapping_add(offset);
+        debug!(
+            "Loading segment 0x{:08x}-0x{:08x} {}",
+            address,
+            address.wrapping_add(segment.size),
+            segment.permissions()
+        );
+        image.write_bytes(address, &segment.contents);
+        // The BSS is cleared by the loader
+        let bss = address.wrapping_add(segment.contents.len() as u32);
+        image.write_bytes(bss, &vec![0; segment.zero_size() as usize]);
+    }
+    Ok(())
+}
+
+// Load a file given as FILE[@ADDR] over the image. Binary and $readmemh
+// files are stored at the address, and the addresses of ELF and Intel HEX
+// files are moved by it.
+fn load_image(image: &mut MemoryImage, spec: &str) -> Result<(), Error> {
+    let (path, addr) = match spec.rfind('@') {
+        Some(index) => match parse_number(&spec[index + 1..]) {
+            Some(addr) => (&spec[..index], addr),
+            None => return Err(Error::invalid("load address", spec)),
+        },
+        None => (spec, 0),
+    };
+    let data = fs::read(path).map_err(|e| Error::load(path, e))?;
+
+    match ImageFormat::detect(path, &data) {
+        ImageFormat::Elf => return load_elf(image, path, addr),
+        ImageFormat::Binary => image.write_bytes(addr, &data),
+        ImageFormat::IntelHex => {
+            let text = String::from_utf8_lossy(&data);
+            image
+                .write_intel_hex(&text, addr)
+                .map_err(|e| Error::load(path, e))?;
+        }
+        ImageFormat::ReadMemH => {
+            let text = String::from_utf8_lossy(&data);
+            image
+                .write_readmemh(&text, addr)
+                .map_err(|e| Error::load(path, e))?;
+        }
+    }
+    Ok(())
+}
+
+// Write the pages of the memory which were written to a file given as
+// FILE[:FORMAT]
+fn export_memory(memory: &Memory, spec: &str) -> Result<(), Error> {
+    let (path, format) = match spec.rfind(':') {
+        Some(index) => match ExportFormat::parse(&spec[index + 1..]) {
+            Some(format) => (&spec[..index], format),
+            None => return Err(Error::invalid("memory export format", &spec[index + 1..])),
+        },
+        None => (spec, ExportFormat::detect(spec)),
+    };
+    let mut image = MemoryImage::new();
+    for (base, bytes) in memory.pages() {
+        image.write_bytes(base, bytes);
+    }
+    let mut out = create_output_file(path)?;
+    out.write_all(&image.export(format))
+        .and_then(|_| out.flush())
+        .map_err(|e| Error::runtime(path, e))
+}
+
+// Connect the serial port to the console of the command line, none for the
+// standard input and output
+fn console_option(options: &SimulatorOptions) -> Result<Option<Box<dyn Console>>, Error> {
+    let console = match options.uart {
+        None | Some(ConsoleSpec::Stdio) => return Ok(None),
+        Some(ConsoleSpec::Pty) => StreamConsole::open_pty().map(|(console, path)| {
+            info!("Console on {}", path);
+            console
+        }),
+        Some(ConsoleSpec::Tcp(port)) => StreamConsole::listen(port),
+        Some(ConsoleSpec::Script(ref path)) => {
+            return match ScriptConsole::from_file(path) {
+                Ok(console) => Ok(Some(Box::new(console))),
+                Err(e) => Err(Error::load(path, e)),
+            };
+        }
+    };
+    match console {
+        Ok(console) => Ok(Some(Box::new(console))),
+        Err(e) => Err(Error::runtime("console", e)),
+    }
+}
+
+// Build the taint tracker from the sources and sinks of the command line
+fn taint_option(options: &SimulatorOptions) -> Option<TaintTracker> {
+    if options.taint_source.is_empty() {
+        return None;
+    }
+
+    let mut tracker = TaintTracker::new();
+    for &(addr, size) in &options.taint_source {
+        tracker.taint(addr, size);
+    }
+    for &(addr, size) in &options.taint_sink {
+        tracker.add_sink(addr, size);
+    }
+    Some(tracker)
+}
+
+// Seed of every random number of the run: the one of the faults if given,
+// or the global one
+fn seed_option(options: &SimulatorOptions) -> u64 {
+    options.inject_seed.or(options.seed).unwrap_or(DEFAULT_SEED)
+}
+
+// Write the manifest of the run, with the files it reads and the options
+// which make it depend on something else
+fn write_manifest(options: &SimulatorOptions, path: &str, interactive: bool) -> Result<(), Error> {
+    let mut manifest = Manifest::new(seed_option(options), std::env::args().skip(1).collect());
+    let mut inputs = vec![("program", Some(options.input_elf.as_str()))];
+    inputs.push(("config", options.config.as_deref()));
+    inputs.push(("config", options.diff_config.as_deref()));
+    for spec in &options.load {
+        inputs.push((
+            "load",
+            Some(spec.rfind('@').map_or(spec, |index| &spec[..index])),
+        ));
+    }
+    match options.uart {
+        None | Some(ConsoleSpec::Stdio) => (),
+        Some(ConsoleSpec::Script(ref script)) => inputs.push(("uart", Some(script))),
+        Some(_) => manifest.add_nondeterministic("uart"),
+    }
+    for (role, input) in inputs {
+        if let Some(input) = input {
+            manifest
+                .add_file(role, input)
+                .map_err(|e| Error::load(input, e))?;
+        }
+    }
+    if interactive || options.debug {
+        manifest.add_nondeterministic("debug");
+    }
+    for &(option, given) in &[
+        ("timeout-seconds", options.timeout_seconds.is_some()),
+        ("debug-port", options.debug_port.is_some()),
+        ("remote-bitbang", options.remote_bitbang.is_some()),
+        ("lockstep", options.lockstep.is_some()),
+    ] {
+        if given {
+            manifest.add_nondeterministic(option);
+        }
+    }
+
+    let mut out = create_output_file(path)?;
+    manifest
+        .write(&mut out)
+        .and_then(|_| out.flush())
+        .map_err(|e| Error::runtime(path, e))
+}
+
+// Build the bit flips of the memory from the command line
+fn memory_faults_option(options: &SimulatorOptions) -> Result<Option<MemoryFaults>, Error> {
+    if options.inject_flip.is_empty() && options.inject_rate.is_none() {
+        return Ok(None);
+    }
+
+    let mut faults = MemoryFaults::new(seed_option(options));
+    for flip in &options.inject_flip {
+        faults.add_flip(*flip);
+    }
+    if let Some(rate) = options.inject_rate {
+        // The command line gives the region with the rate
+        match options.inject_region {
+            Some((addr, size)) if size > 0 => faults.set_rate(rate, addr, size),
+            _ => {
+                return Err(Error::Invalid(
+                    "the random bit flips need an inject region".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(Some(faults))
+}
+
+// Write to the output of the command line, like print! to the standard
+// output
+fn print_to(output: &mut dyn Write, text: fmt::Arguments) -> Result<(), Error> {
+    output
+        .write_fmt(text)
+        .map_err(|e| Error::runtime("output", e))
+}
+
+// Write a statistics report to the file of the command line, or to `output`
+// without it
+fn write_stats<R: Display>(
+    options: &SimulatorOptions,
+    output: &mut dyn Write,
+    report: &R,
+) -> Result<(), Error> {
+    match options.stats_file {
+        Some(ref path) => {
+            let mut out = create_output_file(path)?;
+            write!(out, "{}", report)
+                .and_then(|_| out.flush())
+                .map_err(|e| Error::runtime(path, e))
+        }
+        None => print_to(output, format_args!("{}", report)),
+    }
+}
+
+// Write the crash report to the file of the command line, if it gives one
+fn write_crash_report(
+    options: &SimulatorOptions,
+    crash: &Crash,
+    cpu: &Cpu,
+    functions: &[Symbol],
+) -> Result<(), Error> {
+    let path = match options.crash_report {
+        Some(ref path) => path,
+        None => return Ok(()),
+    };
+    let mut out = create_output_file(path)?;
+    crash::write_report(&mut out, crash, cpu, functions)
+        .and_then(|_| out.flush())
+        .map_err(|e| Error::runtime(path, e))?;
+    error!("Crash report written to {}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(0, exit_code(StopReason::Exit(0), false));
+        assert_eq!(GUEST_FAIL_EXIT_CODE, exit_code(StopReason::Exit(7), false));
+        assert_eq!(
+            ILLEGAL_INSTRUCTION_EXIT_CODE,
+            exit_code(StopReason::InvalidInstruction { pc: 0, raw: 0 }, false)
+        );
+        assert_eq!(
+            CRASH_EXIT_CODE,
+            exit_code(StopReason::Breakpoint { pc: 0 }, false)
+        );
+    }
+
+    #[test]
+    fn test_timeout_exit_code() {
+        assert_eq!(
+            TIMEOUT_EXIT_CODE,
+            exit_code(StopReason::CycleLimit(10), true)
+        );
+        // Stopped by the watchdog
+        assert_eq!(
+            CRASH_EXIT_CODE,
+            exit_code(StopReason::CycleLimit(10), false)
+        );
+        assert_eq!(
+            TIMEOUT_EXIT_CODE,
+            exit_code(StopReason::TimeLimit(Duration::from_secs(1)), false)
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Boot ROM of the Adept board. Instead of starting cold at the entry point of
+//! the program, the processor can start in a small ROM placed at the reset
+//! vector. The ROM sets up the trap vector and the stack pointer, then jumps
+//! to the entry point.
+//!
+//! Unless another trap vector is given, traps go to an `ebreak` at the end of
+//! the ROM.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::boot::BootRom;
+//! # use adept_lib::csr::MTVEC;
+//! # use adept_lib::hart::Hart;
+//! let mut hart = Hart::new();
+//! let rom = BootRom::new(0x1000, 0x8000, 0x0010_0000);
+//! rom.load(hart.memory_mut()).unwrap();
+//!
+//! hart.set_pc(0x1000);
+//! while hart.pc() != 0x8000 {
+//!     let pc = hart.pc();
+//!     let raw = hart.memory().read_pc(pc);
+//!     hart.execute(pc, raw).unwrap();
+//! }
+//! assert_eq!((0x0010_0000, 0), hart.registers().read(2, 0));
+//! assert_eq!(Some(rom.trap_vector()), hart.csrs().read(MTVEC));
+//! ```
+use csr::MTVEC;
+use mem::{MemError, MemStoreOp, Memory};
+use riscv::isa::RV32I;
+
+/// Address of the boot ROM when no reset vector is given
+pub const DEFAULT_RESET_VECTOR: u32 = 0x0000_1000;
+
+// Registers used by the ROM
+const SP: u32 = 2;
+const T0: u32 = 5;
+
+// Offset of the default trap handler inside the ROM
+const HANDLER_OFFSET: u32 = 7 << 2;
+
+// ebreak
+const EBREAK: u32 = 0x0010_0073;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootRom {
+    /// Address of the ROM, where the processor starts
+    pub base: u32,
+    /// Entry point of the program
+    pub entry: u32,
+    /// Initial stack pointer
+    pub stack_pointer: u32,
+    /// Address of the trap handler, or None to use the handler of the ROM
+    pub mtvec: Option<u32>,
+}
+
+impl BootRom {
+    /// Create a ROM using its own trap handler
+    ///
+    /// # Arguments
+    /// * `base` => address of the ROM
+    /// * `entry` => entry point of the program
+    /// * `stack_pointer` => initial stack pointer
+    pub fn new(base: u32, entry: u32, stack_pointer: u32) -> Self {
+        BootRom {
+            base,
+            entry,
+            stack_pointer,
+            mtvec: None,
+        }
+    }
+
+    /// Address written to mtvec
+    pub fn trap_vector(&self) -> u32 {
+        self.mtvec
+            .unwrap_or_else(|| self.base.wrapping_add(HANDLER_OFFSET))
+    }
+
+    /// Instructions of the ROM
+    pub fn code(&self) -> Vec<u32> {
+        let (mtvec_hi, mtvec_lo) = split(self.trap_vector());
+        let (sp_hi, sp_lo) = split(self.stack_pointer);
+        let (entry_hi, entry_lo) = split(self.entry);
+
+        vec![
+            // li t0, mtvec
+            lui(T0, mtvec_hi),
+            addi(T0, T0, mtvec_lo),
+            // csrw mtvec, t0
+            csrrw(0, MTVEC, T0),
+            // li sp, stack_pointer
+            lui(SP, sp_hi),
+            addi(SP, SP, sp_lo),
+            // jump to the entry point
+            lui(T0, entry_hi),
+            jalr(0, T0, entry_lo),
+            // Default trap handler
+            EBREAK,
+        ]
+    }
+
+    /// Store the ROM in memory
+    ///
+    /// # Return Value
+    /// An error if the base of the ROM isn't aligned to a word
+    pub fn load(&self, memory: &mut Memory) -> Result<(), MemError> {
+        for (index, instruction) in self.code().iter().enumerate() {
+            let address = self.base.wrapping_add((index as u32) << 2);
+            memory.write_data(&MemStoreOp::from(RV32I::SW), address, *instruction)?;
+        }
+        Ok(())
+    }
+}
+
+// Split a value into the upper immediate of lui and the sign extended lower
+// 12 bits added to it
+fn split(value: u32) -> (u32, u32) {
+    let lo = ((value << 20) as i32 >> 20) as u32;
+    (value.wrapping_sub(lo), lo & 0xfff)
+}
+
+fn lui(rd: u32, upper: u32) -> u32 {
+    (upper & 0xffff_f000) | (rd << 7) | 0x37
+}
+
+fn addi(rd: u32, rs1: u32, imm: u32) -> u32 {
+    (imm << 20) | (rs1 << 15) | (rd << 7) | 0x13
+}
+
+fn csrrw(rd: u32, csr: u16, rs1: u32) -> u32 {
+    (u32::from(csr) << 20) | (rs1 << 15) | (1 << 12) | (rd << 7) | 0x73
+}
+
+fn jalr(rd: u32, rs1: u32, imm: u32) -> u32 {
+    (imm << 20) | (rs1 << 15) | (rd << 7) | 0x67
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hart::Hart;
+
+    #[test]
+    fn test_encodings() {
+        // lui t0, 0x12345
+        assert_eq!(0x1234_52b7, lui(T0, 0x1234_5000));
+        // addi sp, sp, -16
+        assert_eq!(0xff01_0113, addi(SP, SP, 0xff0));
+        // csrw mtvec, t0
+        assert_eq!(0x3052_9073, csrrw(0, MTVEC, T0));
+        // jr 4(t0)
+        assert_eq!(0x0042_8067, jalr(0, T0, 4));
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!((0x1234_5000, 0x678), split(0x1234_5678));
+        // The lower part is negative, so the upper part is rounded up
+        assert_eq!((0x1234_6000, 0x800), split(0x1234_5800));
+        assert_eq!((0, 0xfff), split(0xffff_ffff));
+    }
+
+    #[test]
+    fn test_boot() {
+        let mut rom = BootRom::new(0x2000, 0x0001_0800, 0x0080_0000);
+        rom.mtvec = Some(0x0000_0abc);
+
+        let mut hart = Hart::new();
+        rom.load(hart.memory_mut()).unwrap();
+        hart.set_pc(0x2000);
+        for _ in 0..rom.code().len() - 1 {
+            let pc = hart.pc();
+            let raw = hart.memory().read_pc(pc);
+            hart.execute(pc, raw).unwrap();
+        }
+
+        assert_eq!(0x0001_0800, hart.pc());
+        assert_eq!((0x0080_0000, 0), hart.registers().read(2, 0));
+        assert_eq!(Some(0xabc), hart.csrs().read(MTVEC));
+    }
+
+    #[test]
+    fn test_default_handler() {
+        let rom = BootRom::new(0x1000, 0, 0);
+        assert_eq!(0x101c, rom.trap_vector());
+        assert_eq!(EBREAK, rom.code()[7]);
+    }
+}
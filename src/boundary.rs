@@ -0,0 +1,132 @@
+//! Detection of the functions of programs without symbols. A function starts
+//! at the target of a call, at the start of the code, or at a prologue
+//! reserving stack space right after a return or a jump, and ends where the
+//! next one starts.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::boundary;
+//! let words = [
+//!     (0x100, 0x0080_00ef), // jal ra, 0x108
+//!     (0x104, 0x0000_006f), // j .
+//!     (0x108, 0x0000_8067), // ret
+//! ];
+//! let functions = boundary::detect(&words);
+//! assert_eq!("fn_00000100", functions[0].name);
+//! assert_eq!(0x108, functions[1].value);
+//! ```
+use elf::Symbol;
+use riscv::decoder::Instruction;
+use riscv::isa::RV32I;
+
+use std::collections::BTreeSet;
+
+// Registers holding the return address of calls
+const LINK_REGISTERS: [u8; 2] = [1, 5];
+const SP: u8 = 2;
+
+/// Find the likely functions of code
+///
+/// # Arguments
+/// * `words` => address and value of every word of the code, sorted by
+///   address
+///
+/// # Return Value
+/// The functions sorted by address, named after their address like
+/// fn_80000120, each up to the next one or to the end of its run of words
+pub fn detect(words: &[(u32, u32)]) -> Vec<Symbol> {
+    let addresses: BTreeSet<u32> = words.iter().map(|(addr, _)| *addr).collect();
+    let mut starts = BTreeSet::new();
+    // Whether the previous word ends the flow of instructions, so that a
+    // prologue following it starts a function
+    let mut after_jump = true;
+    let mut previous = None;
+
+    for (addr, raw) in words {
+        if previous != Some(addr.wrapping_sub(4)) {
+            starts.insert(*addr);
+            after_jump = true;
+        }
+        let instruction = Instruction::new(*raw);
+        match instruction.op() {
+            RV32I::JAL => {
+                if let (Some(rd), Some(imm)) = (instruction.get_rd(), instruction.get_imm()) {
+                    let target = addr.wrapping_add(imm as u32);
+                    if LINK_REGISTERS.contains(&rd) && addresses.contains(&target) {
+                        starts.insert(target);
+                    }
+                }
+            }
+            RV32I::ADDI
+                if after_jump
+                    && instruction.get_rd() == Some(SP)
+                    && instruction.get_rs1() == Some(SP)
+                    && matches!(instruction.get_imm(), Some(imm) if imm < 0) =>
+            {
+                starts.insert(*addr);
+            }
+            _ => (),
+        }
+        after_jump = match instruction.op() {
+            RV32I::JAL | RV32I::JALR => instruction.get_rd() == Some(0),
+            _ => false,
+        };
+        previous = Some(*addr);
+    }
+
+    // A function ends at the next one or after the last word of its run
+    let mut functions: Vec<Symbol> = Vec::new();
+    for (addr, _) in words {
+        if starts.contains(addr) {
+            functions.push(Symbol {
+                name: format!("fn_{:08x}", addr),
+                value: *addr,
+                size: 4,
+            });
+        } else if let Some(function) = functions.last_mut() {
+            if function.value.wrapping_add(function.size) == *addr {
+                function.size += 4;
+            }
+        }
+    }
+    functions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let code = [
+            0x00c0_00ef, // jal ra, helper
+            0x0000_006f, // j .
+            0xff01_0113, // addi sp, sp, -16 (after a jump)
+            0x0000_8067, // helper: ret
+            0xff01_0113, // addi sp, sp, -16 (after a return)
+            0x0141_0113, // addi sp, sp, 20
+            0xff01_0113, // addi sp, sp, -16 (in the middle of code)
+            0x0000_8067, // ret
+        ];
+        let mut words: Vec<_> = code
+            .iter()
+            .enumerate()
+            .map(|(index, raw)| (0x100 + 4 * index as u32, *raw))
+            .collect();
+        // A separate run of words
+        words.push((0x200, 0x0000_8067));
+
+        let functions = detect(&words);
+        let bounds: Vec<_> = functions
+            .iter()
+            .map(|function| (function.value, function.size))
+            .collect();
+        assert_eq!(
+            vec![(0x100, 8), (0x108, 4), (0x10c, 4), (0x110, 16), (0x200, 4)],
+            bounds
+        );
+        assert_eq!("fn_0000010c", functions[2].name);
+        assert!(detect(&[]).is_empty());
+    }
+}
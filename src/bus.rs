@@ -0,0 +1,416 @@
+//! Timing model of the memory bus. The address space is divided in regions,
+//! each one with its own access latency in cycles (e.g. an SRAM answering in
+//! a single cycle and an external DRAM taking 20). Addresses outside every
+//! region use the default latency. Every cycle above the first one is a wait
+//! state which stalls the pipeline.
+//!
+//! The core and the DMA engine are masters of the bus. When both access it
+//! in the same cycle, the arbiter grants it to one of them and the other one
+//! waits for the next cycle. The fixed priority arbiter always grants it to
+//! the core, while the round-robin one alternates between the masters.
+//!
+//! Regions are read from the `[bus.<name>]` tables of a configuration:
+//!
+//! ```toml
+//! [bus]
+//! default_latency = 1
+//! arbitration = "round_robin"
+//!
+//! [bus.dram]
+//! base = 0x8000_0000
+//! size = 0x1000_0000
+//! latency = 20
+//! ```
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::bus::{Bus, Region};
+//! let mut bus = Bus::new();
+//! bus.add_region(Region::new("dram", 0x8000_0000, 0x1000_0000, 20));
+//! assert_eq!(19, bus.wait_states(0x8000_0100));
+//! assert_eq!(0, bus.wait_states(0x100));
+//! ```
+use config::{value_error, Config, ConfigError};
+
+/// A range of addresses sharing the same access latency
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// Name used in the configuration file
+    pub name: String,
+    /// First address of the region
+    pub base: u32,
+    /// Size of the region in bytes
+    pub size: u32,
+    /// Cycles taken by an access to the region
+    pub latency: u32,
+}
+
+impl Region {
+    /// Create a new region
+    ///
+    /// # Arguments
+    /// * `name` => name of the region
+    /// * `base` => first address of the region
+    /// * `size` => size in bytes, must not be 0
+    /// * `latency` => access cycles, must be at least 1
+    pub fn new(name: &str, base: u32, size: u32, latency: u32) -> Self {
+        assert!(size > 0, "Regions can't be empty");
+        assert!(latency >= 1, "Latencies must be at least one cycle");
+        Region {
+            name: name.to_string(),
+            base,
+            size,
+            latency,
+        }
+    }
+
+    /// Check if the region contains an address
+    pub fn contains(&self, addr: u32) -> bool {
+        addr.wrapping_sub(self.base) < self.size
+    }
+
+    /// Check if two regions share any address
+    pub fn overlaps(&self, other: &Region) -> bool {
+        self.contains(other.base) || other.contains(self.base)
+    }
+}
+
+/// Masters of the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Master {
+    Core,
+    Dma,
+}
+
+impl Master {
+    /// Every master, by decreasing priority
+    pub const ALL: [Master; 2] = [Master::Core, Master::Dma];
+
+    /// Name used in reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Master::Core => "core",
+            Master::Dma => "dma",
+        }
+    }
+}
+
+/// Policies granting the bus to one of the masters accessing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arbitration {
+    /// The master with the highest priority always wins
+    FixedPriority,
+    /// The masters take turns, starting after the last one granted
+    RoundRobin,
+}
+
+impl Arbitration {
+    /// Name used in configuration files
+    pub fn name(self) -> &'static str {
+        match self {
+            Arbitration::FixedPriority => "fixed_priority",
+            Arbitration::RoundRobin => "round_robin",
+        }
+    }
+}
+
+/// Arbiter of the bus, which keeps the turn of the round-robin policy and
+/// counts the cycles each master waited
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arbiter {
+    arbitration: Arbitration,
+    last: Option<Master>,
+    waits: [u64; 2],
+}
+
+impl Default for Arbiter {
+    fn default() -> Self {
+        Self::new(Arbitration::FixedPriority)
+    }
+}
+
+impl Arbiter {
+    /// Create an arbiter which didn't grant the bus yet
+    pub fn new(arbitration: Arbitration) -> Self {
+        Arbiter {
+            arbitration,
+            last: None,
+            waits: [0; 2],
+        }
+    }
+
+    pub fn arbitration(&self) -> Arbitration {
+        self.arbitration
+    }
+
+    /// Grant the bus for a cycle
+    ///
+    /// # Arguments
+    /// * `requests` => masters accessing the bus during the cycle
+    ///
+    /// # Return Value
+    /// The master using the bus, or None if nobody asked for it. The other
+    /// ones wait for the next cycle.
+    pub fn grant(&mut self, requests: &[Master]) -> Option<Master> {
+        let first = match (self.arbitration, self.last) {
+            (Arbitration::RoundRobin, Some(last)) => last as usize + 1,
+            _ => 0,
+        };
+        let granted = (first..first + Master::ALL.len())
+            .map(|index| Master::ALL[index % Master::ALL.len()])
+            .find(|master| requests.contains(master));
+        for master in requests {
+            if Some(*master) != granted {
+                self.waits[*master as usize] += 1;
+            }
+        }
+        if granted.is_some() {
+            self.last = granted;
+        }
+        granted
+    }
+
+    /// Cycles a master waited for another one
+    pub fn waits(&self, master: Master) -> u64 {
+        self.waits[master as usize]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bus {
+    regions: Vec<Region>,
+    default_latency: u32,
+    arbitration: Arbitration,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    /// Create a bus without regions where every access takes a single cycle
+    pub fn new() -> Self {
+        Bus {
+            regions: Vec::new(),
+            default_latency: 1,
+            arbitration: Arbitration::FixedPriority,
+        }
+    }
+
+    /// Create a bus from the `[bus]` and `[bus.<name>]` tables of a
+    /// configuration
+    ///
+    /// # Arguments
+    /// * `config` => parsed configuration
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let mut bus = Self::new();
+
+        if let Some(latency) = get_u32(config, "bus", "default_latency")? {
+            if latency == 0 {
+                return Err(value_error(
+                    "bus",
+                    "default_latency",
+                    "expected at least 1 cycle",
+                ));
+            }
+            bus.default_latency = latency;
+        }
+        bus.arbitration = match config.get_string("bus", "arbitration")? {
+            None => bus.arbitration,
+            Some("fixed_priority") => Arbitration::FixedPriority,
+            Some("round_robin") => Arbitration::RoundRobin,
+            Some(_) => {
+                return Err(value_error(
+                    "bus",
+                    "arbitration",
+                    "expected \"fixed_priority\" or \"round_robin\"",
+                ))
+            }
+        };
+
+        for (table, _) in config.tables() {
+            let name = match table.strip_prefix("bus.") {
+                Some(name) => name,
+                None => continue,
+            };
+            let base = match get_u32(config, table, "base")? {
+                Some(base) => base,
+                None => return Err(value_error(table, "base", "missing region base")),
+            };
+            let size = match get_u32(config, table, "size")? {
+                Some(size) if size > 0 && u64::from(base) + u64::from(size) <= 1 << 32 => size,
+                Some(_) => {
+                    return Err(value_error(
+                        table,
+                        "size",
+                        "region must be inside the address space",
+                    ))
+                }
+                None => return Err(value_error(table, "size", "missing region size")),
+            };
+            let latency = match get_u32(config, table, "latency")? {
+                Some(0) => return Err(value_error(table, "latency", "expected at least 1 cycle")),
+                Some(latency) => latency,
+                None => bus.default_latency,
+            };
+
+            let region = Region::new(name, base, size, latency);
+            if let Some(other) = bus.regions.iter().find(|other| other.overlaps(&region)) {
+                return Err(value_error(
+                    table,
+                    "base",
+                    &format!("region overlaps `{}`", other.name),
+                ));
+            }
+            bus.regions.push(region);
+        }
+
+        Ok(bus)
+    }
+
+    /// Add a region to the bus
+    ///
+    /// # Arguments
+    /// * `region` => new region, must not overlap the existing ones
+    pub fn add_region(&mut self, region: Region) {
+        assert!(
+            !self.regions.iter().any(|other| other.overlaps(&region)),
+            "Regions can't overlap"
+        );
+        self.regions.push(region);
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Policy granting the bus to the core or the DMA engine
+    pub fn arbitration(&self) -> Arbitration {
+        self.arbitration
+    }
+
+    pub fn set_arbitration(&mut self, arbitration: Arbitration) {
+        self.arbitration = arbitration;
+    }
+
+    /// Cycles taken by an access to an address
+    pub fn latency(&self, addr: u32) -> u32 {
+        self.regions
+            .iter()
+            .find(|region| region.contains(addr))
+            .map_or(self.default_latency, |region| region.latency)
+    }
+
+    /// Cycles an access to an address stalls the pipeline for
+    pub fn wait_states(&self, addr: u32) -> u32 {
+        self.latency(addr) - 1
+    }
+}
+
+// Get an integer which must fit in 32 bits
+fn get_u32(config: &Config, table: &str, key: &str) -> Result<Option<u32>, ConfigError> {
+    match config.get_integer(table, key)? {
+        Some(value) if value >= 0 && value <= i64::from(u32::MAX) => Ok(Some(value as u32)),
+        Some(_) => Err(value_error(
+            table,
+            key,
+            "expected a 32 bit unsigned integer",
+        )),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regions() {
+        let mut bus = Bus::new();
+        bus.add_region(Region::new("sram", 0, 0x1000, 1));
+        bus.add_region(Region::new("dram", 0x8000_0000, 0x8000_0000, 20));
+
+        assert_eq!(1, bus.latency(0xfff));
+        assert_eq!(1, bus.latency(0x1000));
+        assert_eq!(20, bus.latency(0x8000_0000));
+        assert_eq!(20, bus.latency(0xffff_ffff));
+        assert_eq!(0, bus.wait_states(0x7fff_ffff));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_overlapping_regions() {
+        let mut bus = Bus::new();
+        bus.add_region(Region::new("low", 0, 0x1000, 1));
+        bus.add_region(Region::new("high", 0xfff, 0x1000, 2));
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = Config::parse(
+            "[bus]\ndefault_latency = 2\n\
+             arbitration = \"round_robin\"\n\
+             [bus.sram]\nbase = 0\nsize = 0x1000\nlatency = 1\n\
+             [bus.rom]\nbase = 0x1000\nsize = 0x100\n",
+        )
+        .unwrap();
+        let bus = Bus::from_config(&config).unwrap();
+
+        assert_eq!(2, bus.regions().len());
+        assert_eq!(Arbitration::RoundRobin, bus.arbitration());
+        assert_eq!(1, bus.latency(0x10));
+        // Regions without a latency use the default one
+        assert_eq!(2, bus.latency(0x1010));
+        assert_eq!(2, bus.latency(0x2000));
+    }
+
+    #[test]
+    fn test_fixed_priority() {
+        let mut arbiter = Arbiter::new(Arbitration::FixedPriority);
+        let both = [Master::Dma, Master::Core];
+        assert_eq!(Some(Master::Core), arbiter.grant(&both));
+        assert_eq!(Some(Master::Core), arbiter.grant(&both));
+        assert_eq!(Some(Master::Dma), arbiter.grant(&[Master::Dma]));
+        assert_eq!(Some(Master::Core), arbiter.grant(&both));
+        assert_eq!(None, arbiter.grant(&[]));
+        assert_eq!(0, arbiter.waits(Master::Core));
+        assert_eq!(3, arbiter.waits(Master::Dma));
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut arbiter = Arbiter::new(Arbitration::RoundRobin);
+        let both = [Master::Core, Master::Dma];
+        let grants: Vec<_> = (0..4).map(|_| arbiter.grant(&both).unwrap()).collect();
+        assert_eq!(
+            vec![Master::Core, Master::Dma, Master::Core, Master::Dma],
+            grants
+        );
+        // A master alone keeps the bus, then the other one gets its turn
+        assert_eq!(Some(Master::Core), arbiter.grant(&[Master::Core]));
+        assert_eq!(Some(Master::Dma), arbiter.grant(&both));
+        assert_eq!(None, arbiter.grant(&[]));
+        assert_eq!(Some(Master::Core), arbiter.grant(&both));
+        assert_eq!(3, arbiter.waits(Master::Core));
+        assert_eq!(3, arbiter.waits(Master::Dma));
+    }
+
+    #[test]
+    fn test_from_config_errors() {
+        for text in &[
+            "[bus]\ndefault_latency = 0\n",
+            "[bus]\narbitration = \"lottery\"\n",
+            "[bus.a]\nsize = 4\n",
+            "[bus.a]\nbase = 0\nsize = 0\n",
+            "[bus.a]\nbase = 0xffff_fff0\nsize = 0x20\n",
+            "[bus.a]\nbase = 0\nsize = 4\nlatency = 0\n",
+            "[bus.a]\nbase = 0\nsize = 8\n[bus.b]\nbase = 4\nsize = 8\n",
+        ] {
+            let config = Config::parse(text).unwrap();
+            assert!(Bus::from_config(&config).is_err(), "{}", text);
+        }
+    }
+}
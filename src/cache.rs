@@ -0,0 +1,269 @@
+//! Set associative cache model with least recently used replacement. It
+//! only tracks which lines are present, to count the hits and the misses of
+//! a stream of addresses. Lines can also be filled ahead of their use by a
+//! prefetcher, which counts how many of them were used before their
+//! eviction.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cache::{Cache, CacheConfig};
+//! // 1 KiB of 16 byte lines, 2 ways
+//! let mut cache = Cache::new(CacheConfig::new(1024, 16, 2).unwrap());
+//! assert!(!cache.access(0x100));
+//! assert!(cache.access(0x10c));
+//! assert_eq!((1, 1), (cache.hits(), cache.misses()));
+//! ```
+use std::fmt::{self, Display, Formatter};
+use std::mem;
+
+/// Geometry of a cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Capacity in bytes
+    pub size: u32,
+    /// Bytes of a line
+    pub line: u32,
+    /// Lines of a set
+    pub ways: u32,
+}
+
+impl CacheConfig {
+    /// Check the geometry of a cache
+    ///
+    /// # Return Value
+    /// The configuration, or why the line size or the number of sets isn't
+    /// a power of two
+    pub fn new(size: u32, line: u32, ways: u32) -> Result<CacheConfig, String> {
+        if !line.is_power_of_two() {
+            return Err(format!("line of {} bytes isn't a power of two", line));
+        }
+        let sets = line
+            .checked_mul(ways)
+            .filter(|set_size| *set_size > 0)
+            .map(|set_size| (size / set_size, set_size));
+        let valid = match sets {
+            Some((sets, set_size)) => sets * set_size == size && sets.is_power_of_two(),
+            None => false,
+        };
+        if !valid {
+            return Err(format!(
+                "{} bytes don't make a power of two sets of {} lines of {} bytes",
+                size, ways, line
+            ));
+        }
+        Ok(CacheConfig { size, line, ways })
+    }
+
+    /// Number of sets
+    pub fn sets(&self) -> u32 {
+        self.size / (self.line * self.ways)
+    }
+}
+
+/// The geometry as SIZE:LINE:WAYS
+impl Display for CacheConfig {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.size, self.line, self.ways)
+    }
+}
+
+/// Parse a size in bytes, with an optional K or M suffix for KiB and MiB
+pub fn parse_size(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let (digits, unit) = match text.chars().last()? {
+        'k' | 'K' => (&text[..text.len() - 1], 1 << 10),
+        'm' | 'M' => (&text[..text.len() - 1], 1 << 20),
+        _ => (text, 1),
+    };
+    digits.parse::<u32>().ok()?.checked_mul(unit)
+}
+
+// A valid line of a cache
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    tag: u32,
+    last_use: u64,
+    // Set if a prefetch filled the line and nothing used it yet
+    prefetched: bool,
+}
+
+/// Lines present in a cache and its hit counts
+#[derive(Debug, Clone)]
+pub struct Cache {
+    config: CacheConfig,
+    // Valid lines, by set
+    sets: Vec<Vec<Line>>,
+    accesses: u64,
+    hits: u64,
+    prefetches: u64,
+    useful_prefetches: u64,
+}
+
+impl Cache {
+    /// Create an empty cache
+    pub fn new(config: CacheConfig) -> Self {
+        Cache {
+            config,
+            sets: vec![Vec::new(); config.sets() as usize],
+            accesses: 0,
+            hits: 0,
+            prefetches: 0,
+            useful_prefetches: 0,
+        }
+    }
+
+    pub fn config(&self) -> CacheConfig {
+        self.config
+    }
+
+    /// Access the line holding an address, filling it on a miss
+    ///
+    /// # Return Value
+    /// True on a hit
+    pub fn access(&mut self, addr: u32) -> bool {
+        self.accesses += 1;
+        let now = self.accesses;
+        // Whether the line was prefetched, on a hit
+        let prefetched = self.find(addr).map(|line| {
+            line.last_use = now;
+            mem::take(&mut line.prefetched)
+        });
+        match prefetched {
+            Some(prefetched) => {
+                if prefetched {
+                    self.useful_prefetches += 1;
+                }
+                self.hits += 1;
+                true
+            }
+            None => {
+                self.fill(addr, false);
+                false
+            }
+        }
+    }
+
+    /// Fill the line holding an address ahead of its use, unless the cache
+    /// holds it. The prefetch isn't an access.
+    ///
+    /// # Return Value
+    /// True if the line was filled
+    pub fn prefetch(&mut self, addr: u32) -> bool {
+        if self.find(addr).is_some() {
+            return false;
+        }
+        self.prefetches += 1;
+        self.fill(addr, true);
+        true
+    }
+
+    pub fn accesses(&self) -> u64 {
+        self.accesses
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.accesses - self.hits
+    }
+
+    /// Lines filled by prefetches
+    pub fn prefetches(&self) -> u64 {
+        self.prefetches
+    }
+
+    /// Prefetched lines which were accessed before their eviction
+    pub fn useful_prefetches(&self) -> u64 {
+        self.useful_prefetches
+    }
+
+    // Set and tag of an address
+    fn locate(&self, addr: u32) -> (usize, u32) {
+        let line = addr / self.config.line;
+        let sets = self.config.sets();
+        ((line % sets) as usize, line / sets)
+    }
+
+    // The line holding an address, if the cache holds it
+    fn find(&mut self, addr: u32) -> Option<&mut Line> {
+        let (set, tag) = self.locate(addr);
+        self.sets[set].iter_mut().find(|line| line.tag == tag)
+    }
+
+    // Fill the line holding an address, evicting the least recently used one
+    // of a full set
+    fn fill(&mut self, addr: u32, prefetched: bool) {
+        let (set, tag) = self.locate(addr);
+        let line = Line {
+            tag,
+            last_use: self.accesses,
+            prefetched,
+        };
+        let lines = &mut self.sets[set];
+        if lines.len() < self.config.ways as usize {
+            lines.push(line);
+        } else if let Some(victim) = lines.iter_mut().min_by_key(|line| line.last_use) {
+            *victim = line;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config = CacheConfig::new(4096, 32, 4).unwrap();
+        assert_eq!(32, config.sets());
+        assert_eq!("4096:32:4", config.to_string());
+        assert!(CacheConfig::new(4096, 24, 1).is_err());
+        assert!(CacheConfig::new(4096, 32, 0).is_err());
+        assert!(CacheConfig::new(3072, 32, 1).is_err());
+        assert!(CacheConfig::new(16, 32, 1).is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(Some(512), parse_size("512"));
+        assert_eq!(Some(4096), parse_size("4K"));
+        assert_eq!(Some(2 << 20), parse_size("2m"));
+        assert_eq!(None, parse_size("4G"));
+        assert_eq!(None, parse_size(""));
+    }
+
+    #[test]
+    fn test_lru_replacement() {
+        // 2 sets of 2 ways of 16 bytes
+        let mut cache = Cache::new(CacheConfig::new(64, 16, 2).unwrap());
+        // 0x00, 0x20 and 0x40 map to the first set
+        assert!(!cache.access(0x00));
+        assert!(!cache.access(0x20));
+        assert!(cache.access(0x04));
+        // 0x20 is the least recently used line and makes room for 0x40
+        assert!(!cache.access(0x40));
+        assert!(cache.access(0x00));
+        assert!(!cache.access(0x20));
+        // The other set is untouched
+        assert!(!cache.access(0x10));
+        assert_eq!((7, 2, 5), (cache.accesses(), cache.hits(), cache.misses()));
+    }
+
+    #[test]
+    fn test_prefetch() {
+        let mut cache = Cache::new(CacheConfig::new(64, 16, 2).unwrap());
+        assert!(!cache.access(0x00));
+        assert!(cache.prefetch(0x10));
+        assert!(cache.prefetch(0x20));
+        // Lines the cache holds aren't prefetched again
+        assert!(!cache.prefetch(0x04));
+        assert!(!cache.prefetch(0x14));
+        assert!(cache.access(0x10));
+        assert!(cache.access(0x14));
+        assert_eq!((3, 2, 1), (cache.accesses(), cache.hits(), cache.misses()));
+        assert_eq!((2, 1), (cache.prefetches(), cache.useful_prefetches()));
+    }
+}
@@ -0,0 +1,286 @@
+//! Shadow call stack of the guest program. Calls and returns are recognised
+//! from the link registers of JAL and JALR, following the return address
+//! stack hints of the RISC-V specification, so that a backtrace of the
+//! program can be printed when it stops.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::callstack::CallStack;
+//! # use adept_lib::elf::Symbol;
+//! # use adept_lib::riscv::decoder::Instruction;
+//! let functions = vec![
+//!     Symbol { name: "main".to_string(), value: 0x100, size: 0x10 },
+//!     Symbol { name: "f".to_string(), value: 0x200, size: 0x10 },
+//! ];
+//! let mut stack = CallStack::new();
+//! // jal ra, f at 0x104
+//! stack.execute(0x104, &Instruction::new(0x0fc0_00ef), 0x200);
+//! assert_eq!(
+//!     "#0 0x00000208 in f+0x8\n#1 0x00000104 in main+0x4\n",
+//!     stack.backtrace(0x208, &functions).to_string()
+//! );
+//! ```
+use elf::Symbol;
+use riscv::decoder::Instruction;
+use riscv::isa::RV32I;
+
+use std::collections::VecDeque;
+use std::fmt::{self, Display, Formatter};
+
+// Deepest frames kept, the outermost ones are dropped beyond it
+const MAX_DEPTH: usize = 4096;
+
+/// Function call which didn't return yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// Address of the call instruction
+    pub call_pc: u32,
+    /// Address of the called function
+    pub function: u32,
+    /// Address the function is expected to return to
+    pub return_address: u32,
+}
+
+/// Calls made by the program, outermost first
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: VecDeque<Frame>,
+    // Outermost frames which didn't fit
+    dropped: usize,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        CallStack::default()
+    }
+
+    /// Frames of the calls in progress, outermost first
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter()
+    }
+
+    /// Number of calls in progress, including the dropped frames
+    pub fn depth(&self) -> usize {
+        self.frames.len() + self.dropped
+    }
+
+    /// Push or pop frames for an executed instruction
+    ///
+    /// # Arguments
+    /// * `pc` => address of the instruction
+    /// * `decoded` => the instruction
+    /// * `next_pc` => address of the next instruction
+    ///
+    /// # Return Value
+    /// The frame popped by a return
+    pub fn execute(&mut self, pc: u32, decoded: &Instruction, next_pc: u32) -> Option<Frame> {
+        let returned = if is_return(decoded) { self.pop() } else { None };
+        if is_call(decoded) {
+            self.push(Frame {
+                call_pc: pc,
+                function: next_pc,
+                return_address: pc.wrapping_add(4),
+            });
+        }
+        returned
+    }
+
+    /// Get the address a return should jump to
+    ///
+    /// # Arguments
+    /// * `decoded` => instruction about to be executed
+    ///
+    /// # Return Value
+    /// The return address of the innermost frame if the instruction is a
+    /// return, or None if it isn't or there's no frame to return from
+    pub fn expected_return(&self, decoded: &Instruction) -> Option<u32> {
+        if is_return(decoded) {
+            self.frames.back().map(|frame| frame.return_address)
+        } else {
+            None
+        }
+    }
+
+    /// Symbolized backtrace of the program
+    ///
+    /// # Arguments
+    /// * `pc` => address of the current instruction
+    /// * `functions` => functions sorted by address
+    pub fn backtrace<'a>(&self, pc: u32, functions: &'a [Symbol]) -> Backtrace<'a> {
+        let mut addresses = vec![pc];
+        addresses.extend(self.frames.iter().rev().map(|frame| frame.call_pc));
+        Backtrace {
+            addresses,
+            dropped: self.dropped,
+            functions,
+        }
+    }
+
+    fn push(&mut self, frame: Frame) {
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.pop_front();
+            self.dropped += 1;
+        }
+        self.frames.push_back(frame);
+    }
+
+    // Returns without a frame leave functions which were entered before
+    // the first call, such as the entry point
+    fn pop(&mut self) -> Option<Frame> {
+        let frame = self.frames.pop_back();
+        if frame.is_none() && self.dropped > 0 {
+            self.dropped -= 1;
+        }
+        frame
+    }
+}
+
+// ra and t0 hold return addresses in the standard calling convention
+fn is_link(register: u8) -> bool {
+    register == 1 || register == 5
+}
+
+// A jump with a link register as the destination
+pub(crate) fn is_call(decoded: &Instruction) -> bool {
+    let op = decoded.op();
+    (op == RV32I::JAL || op == RV32I::JALR) && is_link(decoded.get_rd().unwrap_or(0))
+}
+
+// A jalr through a link register other than the destination
+pub(crate) fn is_return(decoded: &Instruction) -> bool {
+    let rs1 = decoded.get_rs1().unwrap_or(0);
+    decoded.op() == RV32I::JALR && is_link(rs1) && decoded.get_rd() != Some(rs1)
+}
+
+/// Addresses of the current instruction and of every call in progress,
+/// innermost first
+pub struct Backtrace<'a> {
+    addresses: Vec<u32>,
+    dropped: usize,
+    functions: &'a [Symbol],
+}
+
+impl<'a> Display for Backtrace<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (index, addr) in self.addresses.iter().enumerate() {
+            write!(f, "#{} 0x{:08x}", index, addr)?;
+            match Symbol::lookup(self.functions, *addr) {
+                Some(symbol) => writeln!(f, " in {}+0x{:x}", symbol.name, addr - symbol.value)?,
+                None => writeln!(f)?,
+            }
+        }
+        if self.dropped > 0 {
+            writeln!(f, "... {} more frames", self.dropped)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Update the call stack with an instruction
+    fn track(stack: &mut CallStack, pc: u32, raw: u32, next_pc: u32) -> Option<Frame> {
+        stack.execute(pc, &Instruction::new(raw), next_pc)
+    }
+
+    #[test]
+    fn test_calls_and_returns() {
+        let mut stack = CallStack::new();
+        // jal ra, 0x100
+        track(&mut stack, 0x10, 0x1000_00ef, 0x110);
+        // jalr t0, 0(a0)
+        track(&mut stack, 0x114, 0x0005_02e7, 0x200);
+        assert_eq!(2, stack.depth());
+        assert_eq!(
+            vec![0x14, 0x118],
+            stack
+                .frames()
+                .map(|frame| frame.return_address)
+                .collect::<Vec<_>>()
+        );
+
+        // j and jr a0 are neither calls nor returns
+        assert_eq!(None, track(&mut stack, 0x200, 0x0100_006f, 0x210));
+        assert_eq!(None, track(&mut stack, 0x210, 0x0005_0067, 0x220));
+        assert_eq!(2, stack.depth());
+
+        // jr t0
+        let frame = track(&mut stack, 0x220, 0x0002_8067, 0x118).unwrap();
+        assert_eq!((0x114, 0x200), (frame.call_pc, frame.function));
+        // ret
+        assert_eq!(
+            0x10,
+            track(&mut stack, 0x118, 0x0000_8067, 0x14).unwrap().call_pc
+        );
+        // Returns from the entry point are ignored
+        assert_eq!(None, track(&mut stack, 0x14, 0x0000_8067, 0));
+        assert_eq!(0, stack.depth());
+    }
+
+    #[test]
+    fn test_coroutine_swap() {
+        let mut stack = CallStack::new();
+        track(&mut stack, 0x10, 0x1000_00ef, 0x110);
+        // jalr ra, 0(t0) returns and calls at the same time
+        let frame = track(&mut stack, 0x110, 0x0002_80e7, 0x300).unwrap();
+        assert_eq!(0x10, frame.call_pc);
+        assert_eq!(
+            vec![0x110],
+            stack
+                .frames()
+                .map(|frame| frame.call_pc)
+                .collect::<Vec<_>>()
+        );
+        // jalr ra, 0(ra) is a call
+        track(&mut stack, 0x300, 0x0000_80e7, 0x400);
+        assert_eq!(2, stack.depth());
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        let mut stack = CallStack::new();
+        for _ in 0..MAX_DEPTH + 2 {
+            track(&mut stack, 0x100, 0x0000_00ef, 0x100);
+        }
+        assert_eq!(MAX_DEPTH + 2, stack.depth());
+        assert!(stack
+            .backtrace(0x100, &[])
+            .to_string()
+            .ends_with("... 2 more frames\n"));
+
+        for _ in 0..MAX_DEPTH + 2 {
+            track(&mut stack, 0x104, 0x0000_8067, 0x104);
+        }
+        assert_eq!(0, stack.depth());
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let functions = vec![Symbol {
+            name: "main".to_string(),
+            value: 0x100,
+            size: 0x100,
+        }];
+        let mut stack = CallStack::new();
+        track(&mut stack, 0x10, 0x0f00_00ef, 0x100);
+        track(&mut stack, 0x120, 0x0005_00e7, 0x400);
+        assert_eq!(
+            "#0 0x00000404\n#1 0x00000120 in main+0x20\n#2 0x00000010\n",
+            stack.backtrace(0x404, &functions).to_string()
+        );
+    }
+
+    #[test]
+    fn test_expected_return() {
+        let mut stack = CallStack::new();
+        let ret = Instruction::new(0x0000_8067);
+        assert_eq!(None, stack.expected_return(&ret));
+        track(&mut stack, 0x10, 0x1000_00ef, 0x110);
+        assert_eq!(Some(0x14), stack.expected_return(&ret));
+        // jr a0
+        assert_eq!(None, stack.expected_return(&Instruction::new(0x0005_0067)));
+    }
+}
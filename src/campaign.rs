@@ -0,0 +1,445 @@
+//! Fault injection campaigns, which flip a bit of a register in thousands of
+//! runs of the same program and report how vulnerable each instruction and
+//! function is. Every run starts from a snapshot of the processor taken just
+//! before its fault, instead of simulating the program from the start, so
+//! the campaign takes about as long as the faulty part of the runs.
+//!
+//! Faults are attributed to the instruction the hart executes next when the
+//! bit flips.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::campaign::Campaign;
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::fault::{Outcome, RegisterFlip};
+//! # use adept_lib::mem::MemStoreOp;
+//! # use adept_lib::pipeline::PipelineConfig;
+//! # use adept_lib::riscv::isa::RV32I;
+//! let mut cpu = Cpu::new(PipelineConfig::default());
+//! // li a0, 5; ebreak
+//! for (addr, word) in [(0x100, 0x0050_0513), (0x104, 0x0010_0073)].iter() {
+//!     cpu.hart_mut().memory_mut().write_data(&MemStoreOp::from(RV32I::SW), *addr, *word).unwrap();
+//! }
+//! cpu.set_pc(0x100);
+//! let mut campaign = Campaign::new(&cpu);
+//! // The flip of a0 is overwritten
+//! campaign.run(&[RegisterFlip { cycle: 1, register: 10, bit: 0 }]).unwrap();
+//! assert_eq!(Outcome::Masked, campaign.injections()[0].outcome);
+//! assert_eq!(1, campaign.total().faults);
+//! ```
+use cpu::Cpu;
+use elf::Symbol;
+use error::Error;
+use fault::{Outcome, RegisterFlip, Rng, RunResult};
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+// Width of the names in the tables of the report
+const NAME_WIDTH: usize = 24;
+
+/// One run of a campaign
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Injection {
+    pub flip: RegisterFlip,
+    /// Next instruction executed when the bit flipped
+    pub pc: u32,
+    pub outcome: Outcome,
+}
+
+/// Outcomes of the faults attributed to an instruction or a function
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tally {
+    pub faults: u64,
+    pub masked: u64,
+    pub wrong_results: u64,
+    pub crashes: u64,
+    pub hangs: u64,
+}
+
+impl Tally {
+    /// Count the outcome of a fault
+    pub fn add(&mut self, outcome: Outcome) {
+        self.faults += 1;
+        match outcome {
+            Outcome::Masked => self.masked += 1,
+            Outcome::WrongResult => self.wrong_results += 1,
+            Outcome::Crash(_) => self.crashes += 1,
+            Outcome::Hang => self.hangs += 1,
+        }
+    }
+
+    /// Faults which were not masked
+    pub fn vulnerable(&self) -> u64 {
+        self.faults - self.masked
+    }
+
+    /// Share of the faults which were not masked, from 0 to 1
+    pub fn vulnerability(&self) -> f64 {
+        if self.faults == 0 {
+            0.0
+        } else {
+            self.vulnerable() as f64 / self.faults as f64
+        }
+    }
+}
+
+/// Runs of a program with a fault each, compared to the run without faults
+pub struct Campaign {
+    start: Cpu,
+    golden: RunResult,
+    injections: Vec<Injection>,
+}
+
+impl Campaign {
+    /// Run the program once without faults, as the reference of the
+    /// campaign
+    ///
+    /// # Arguments
+    /// * `start` => processor with the program loaded, about to start
+    pub fn new(start: &Cpu) -> Self {
+        let golden = start.snapshot().run_to_result();
+        Campaign {
+            start: start.snapshot(),
+            golden,
+            injections: Vec::new(),
+        }
+    }
+
+    /// Result of the run without faults
+    pub fn golden(&self) -> &RunResult {
+        &self.golden
+    }
+
+    /// Draw flips of random bits of random registers during the run without
+    /// faults
+    ///
+    /// # Arguments
+    /// * `count` => number of flips
+    /// * `seed` => seed of the random numbers
+    pub fn random_flips(&self, count: usize, seed: u64) -> Vec<RegisterFlip> {
+        let mut rng = Rng::new(seed);
+        let cycles = self.golden.cycles.max(1);
+        (0..count)
+            .map(|_| RegisterFlip::random(&mut rng, cycles))
+            .collect()
+    }
+
+    /// Run the program once for each flip. The runs are made in the order
+    /// of their cycles, each one from a snapshot of the processor at the
+    /// cycle before its flip.
+    ///
+    /// # Arguments
+    /// * `flips` => faults injected, one per run
+    ///
+    /// # Return Value
+    /// An error at the first flip which can't be injected
+    pub fn run(&mut self, flips: &[RegisterFlip]) -> Result<(), Error> {
+        let mut flips = flips.to_vec();
+        flips.sort_by_key(|flip| flip.cycle);
+
+        let mut progress = self.start.snapshot();
+        let limit = self.golden.hang_limit();
+        for flip in flips {
+            // A flip after the end of the program can't change anything
+            let outcome = match progress.run_until_cycle(flip.cycle.saturating_sub(1)) {
+                Ok(()) => {
+                    let mut faulty = progress.snapshot();
+                    faulty.set_register_fault(Some(flip))?;
+                    faulty.set_cycle_limit(Some(limit));
+                    Outcome::classify(&self.golden, &faulty.run_to_result())
+                }
+                Err(_) => Outcome::Masked,
+            };
+            self.injections.push(Injection {
+                flip,
+                pc: progress.hart().pc(),
+                outcome,
+            });
+        }
+        Ok(())
+    }
+
+    /// Runs made so far, in the order of their cycles
+    pub fn injections(&self) -> &[Injection] {
+        &self.injections
+    }
+
+    /// Outcomes of every run
+    pub fn total(&self) -> Tally {
+        let mut total = Tally::default();
+        for injection in &self.injections {
+            total.add(injection.outcome);
+        }
+        total
+    }
+
+    /// Outcomes by instruction address
+    pub fn by_pc(&self) -> BTreeMap<u32, Tally> {
+        let mut tallies = BTreeMap::new();
+        for injection in &self.injections {
+            tallies
+                .entry(injection.pc)
+                .or_insert_with(Tally::default)
+                .add(injection.outcome);
+        }
+        tallies
+    }
+
+    /// Outcomes by function, the most vulnerable first. Instructions outside
+    /// every function are counted under `?`.
+    ///
+    /// # Arguments
+    /// * `functions` => functions sorted by address
+    pub fn by_function(&self, functions: &[Symbol]) -> Vec<(String, Tally)> {
+        let mut tallies = BTreeMap::new();
+        for injection in &self.injections {
+            let name = Symbol::lookup(functions, injection.pc)
+                .map_or_else(|| "?".to_string(), |symbol| symbol.name.clone());
+            tallies
+                .entry(name)
+                .or_insert_with(Tally::default)
+                .add(injection.outcome);
+        }
+        let mut tallies: Vec<_> = tallies.into_iter().collect();
+        tallies.sort_by_key(|&(_, tally)| Reverse(tally.vulnerable()));
+        tallies
+    }
+
+    /// Write the totals of the campaign, then a table of the outcomes by
+    /// function and one by instruction
+    ///
+    /// # Arguments
+    /// * `out` => destination of the report
+    /// * `functions` => functions sorted by address
+    pub fn write_report<W: Write>(&self, out: &mut W, functions: &[Symbol]) -> io::Result<()> {
+        let total = self.total();
+        writeln!(
+            out,
+            "{} faults injected in a run of {} cycles stopped by: {}",
+            total.faults, self.golden.cycles, self.golden.reason
+        )?;
+        let share = |count: u64| 100.0 * count as f64 / total.faults.max(1) as f64;
+        writeln!(
+            out,
+            "masked:       {} ({:.1}%)",
+            total.masked,
+            share(total.masked)
+        )?;
+        writeln!(
+            out,
+            "wrong result: {} ({:.1}%)",
+            total.wrong_results,
+            share(total.wrong_results)
+        )?;
+        writeln!(
+            out,
+            "crash:        {} ({:.1}%)",
+            total.crashes,
+            share(total.crashes)
+        )?;
+        writeln!(
+            out,
+            "hang:         {} ({:.1}%)",
+            total.hangs,
+            share(total.hangs)
+        )?;
+
+        writeln!(out)?;
+        write_header(out, "function")?;
+        for (name, tally) in self.by_function(functions) {
+            write_row(out, &name, &tally)?;
+        }
+        writeln!(out)?;
+        write_header(out, "pc")?;
+        for (pc, tally) in self.by_pc() {
+            write_row(out, &format!("0x{:08x}", pc), &tally)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_header<W: Write>(out: &mut W, name: &str) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:w$} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        name,
+        "faults",
+        "masked",
+        "wrong",
+        "crash",
+        "hang",
+        "vuln",
+        w = NAME_WIDTH
+    )
+}
+
+fn write_row<W: Write>(out: &mut W, name: &str, tally: &Tally) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:w$} {:>8} {:>8} {:>8} {:>8} {:>8} {:>7.1}%",
+        name,
+        tally.faults,
+        tally.masked,
+        tally.wrong_results,
+        tally.crashes,
+        tally.hangs,
+        100.0 * tally.vulnerability(),
+        w = NAME_WIDTH
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fault::DEFAULT_SEED;
+    use hart::StopReason;
+    use mem::MemStoreOp;
+    use pipeline::{PipelineConfig, Stages};
+    use riscv::isa::RV32I;
+
+    // Exit with the sum of the numbers from 1 to 10
+    const SUM: [u32; 7] = [
+        0x0000_0513, // li a0, 0
+        0x00a0_0593, // li a1, 10
+        0x00b5_0533, // loop: add a0, a0, a1
+        0xfff5_8593, // addi a1, a1, -1
+        0xfe05_9ce3, // bnez a1, loop
+        0x05d0_0893, // li a7, 93
+        0x0000_0073, // ecall
+    ];
+
+    fn campaign() -> Campaign {
+        let mut cpu = Cpu::new(PipelineConfig {
+            stages: Stages::Three,
+            ..PipelineConfig::default()
+        });
+        for (index, instruction) in SUM.iter().enumerate() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    0x100 + ((index as u32) << 2),
+                    *instruction,
+                )
+                .unwrap();
+        }
+        cpu.set_pc(0x100);
+        Campaign::new(&cpu)
+    }
+
+    #[test]
+    fn test_outcomes() {
+        let mut campaign = campaign();
+        assert_eq!(StopReason::Exit(55), campaign.golden().reason);
+        let flip = |cycle, register, bit| RegisterFlip {
+            cycle,
+            register,
+            bit,
+        };
+        campaign
+            .run(&[
+                // Counting down from 1 << 30
+                flip(8, 11, 30),
+                // Registers the program never reads
+                flip(10, 31, 0),
+                // The sum
+                flip(20, 10, 4),
+                // After the end of the program
+                flip(1000, 10, 0),
+            ])
+            .unwrap();
+
+        let outcomes: Vec<Outcome> = campaign
+            .injections()
+            .iter()
+            .map(|injection| injection.outcome)
+            .collect();
+        assert_eq!(
+            vec![
+                Outcome::Hang,
+                Outcome::Masked,
+                Outcome::WrongResult,
+                Outcome::Masked
+            ],
+            outcomes
+        );
+        let total = campaign.total();
+        assert_eq!(
+            (4, 2, 1, 0, 1),
+            (
+                total.faults,
+                total.masked,
+                total.wrong_results,
+                total.crashes,
+                total.hangs
+            )
+        );
+        assert_eq!(0.5, total.vulnerability());
+    }
+
+    #[test]
+    fn test_snapshots() {
+        // The runs from snapshots match runs from the start
+        let mut campaign = campaign();
+        let flips = campaign.random_flips(200, DEFAULT_SEED);
+        campaign.run(&flips).unwrap();
+        for injection in campaign.injections().iter().step_by(20) {
+            let mut cpu = campaign.start.snapshot();
+            cpu.set_register_fault(Some(injection.flip)).unwrap();
+            cpu.set_cycle_limit(Some(campaign.golden().hang_limit()));
+            assert_eq!(
+                injection.outcome,
+                Outcome::classify(campaign.golden(), &cpu.run_to_result()),
+                "{}",
+                injection.flip
+            );
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let mut campaign = campaign();
+        campaign
+            .run(&[
+                RegisterFlip {
+                    cycle: 20,
+                    register: 10,
+                    bit: 4,
+                },
+                RegisterFlip {
+                    cycle: 21,
+                    register: 31,
+                    bit: 4,
+                },
+            ])
+            .unwrap();
+        let functions = vec![Symbol {
+            name: "sum".to_string(),
+            value: 0x100,
+            size: 0x1c,
+        }];
+        let by_function = campaign.by_function(&functions);
+        assert_eq!(1, by_function.len());
+        assert_eq!(
+            ("sum", 2, 1),
+            (
+                by_function[0].0.as_str(),
+                by_function[0].1.faults,
+                by_function[0].1.vulnerable()
+            )
+        );
+
+        let mut out = Vec::new();
+        campaign.write_report(&mut out, &functions).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        assert!(report.starts_with("2 faults injected in a run of "));
+        assert!(report.contains("\nmasked:       1 (50.0%)\n"));
+        assert!(report.contains(&format!(
+            "\n{:24} {:>8} {:>8} {:>8} {:>8} {:>8} {:>7.1}%\n",
+            "sum", 2, 1, 1, 0, 0, 50.0
+        )));
+    }
+}
@@ -0,0 +1,367 @@
+//! Static control flow graphs, built from the code of a program without
+//! running it. Blocks start at the start of the code, at the targets of
+//! branches and jumps and after control flow instructions, and the graph is
+//! exported in the dot language of Graphviz with the instructions of every
+//! block. The profile of a previous run can annotate the graph with the
+//! number of times every block and edge was executed.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cfg::Cfg;
+//! let words = [
+//!     (0x100, 0x0050_0513), // li a0, 5
+//!     (0x104, 0xfff5_0513), // addi a0, a0, -1
+//!     (0x108, 0xfe05_1ee3), // bnez a0, 0x104
+//!     (0x10c, 0x0000_8067), // ret
+//! ];
+//! let cfg = Cfg::new(&words);
+//! assert_eq!(3, cfg.blocks().len());
+//! assert_eq!(3, cfg.edges().len());
+//! ```
+use elf::Symbol;
+use profile::{EdgeKind, Profile};
+use riscv::decoder::Instruction;
+use riscv::isa::RV32I;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+/// Sequence of instructions which always execute together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Block {
+    /// Address of the first instruction
+    pub start: u32,
+    /// Address of the last instruction
+    pub end: u32,
+    /// Number of times the block was entered, once annotated
+    pub count: Option<u64>,
+}
+
+/// Possible control flow between the end of a block and the start of another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    /// Start of the source block
+    pub from: u32,
+    /// Start of the destination block
+    pub to: u32,
+    pub kind: EdgeKind,
+    /// Number of times the edge was taken, once annotated
+    pub count: Option<u64>,
+}
+
+/// Blocks of the code and the edges between them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cfg {
+    code: BTreeMap<u32, u32>,
+    blocks: Vec<Block>,
+    edges: Vec<Edge>,
+}
+
+// Destinations of the control flow after an instruction, or None when it
+// continues with the next instruction. Calls continue after their return and
+// the targets of indirect jumps are unknown.
+fn successors(addr: u32, raw: u32) -> Option<Vec<(u32, EdgeKind)>> {
+    let instruction = Instruction::new(raw);
+    let target = addr.wrapping_add(instruction.get_imm().unwrap_or(0) as u32);
+    let next = addr.wrapping_add(4);
+    match instruction.op() {
+        RV32I::BEQ | RV32I::BNE | RV32I::BLT | RV32I::BGE | RV32I::BLTU | RV32I::BGEU => {
+            Some(vec![
+                (target, EdgeKind::Taken),
+                (next, EdgeKind::Fallthrough),
+            ])
+        }
+        RV32I::JAL if instruction.get_rd() == Some(0) => Some(vec![(target, EdgeKind::Jump)]),
+        RV32I::JAL => Some(vec![
+            (target, EdgeKind::Jump),
+            (next, EdgeKind::Fallthrough),
+        ]),
+        RV32I::JALR if instruction.get_rd() == Some(0) => Some(Vec::new()),
+        RV32I::JALR => Some(vec![(next, EdgeKind::Fallthrough)]),
+        _ => None,
+    }
+}
+
+impl Cfg {
+    /// Split code into blocks
+    ///
+    /// # Arguments
+    /// * `words` => address and value of every word of the code
+    pub fn new(words: &[(u32, u32)]) -> Self {
+        let code: BTreeMap<u32, u32> = words.iter().cloned().collect();
+
+        let mut leaders = BTreeSet::new();
+        let mut previous = None;
+        for (&addr, &raw) in &code {
+            if previous != Some(addr.wrapping_sub(4)) {
+                leaders.insert(addr);
+            }
+            if let Some(targets) = successors(addr, raw) {
+                leaders.insert(addr.wrapping_add(4));
+                leaders.extend(targets.iter().map(|(target, _)| *target));
+            }
+            previous = Some(addr);
+        }
+
+        let mut blocks: Vec<Block> = Vec::new();
+        for &addr in code.keys() {
+            match blocks.last_mut() {
+                Some(block) if !leaders.contains(&addr) => block.end = addr,
+                _ => blocks.push(Block {
+                    start: addr,
+                    end: addr,
+                    count: None,
+                }),
+            }
+        }
+
+        let mut edges = Vec::new();
+        for block in &blocks {
+            let targets = successors(block.end, code[&block.end])
+                .unwrap_or_else(|| vec![(block.end.wrapping_add(4), EdgeKind::Fallthrough)]);
+            for (to, kind) in targets {
+                if code.contains_key(&to) {
+                    edges.push(Edge {
+                        from: block.start,
+                        to,
+                        kind,
+                        count: None,
+                    });
+                }
+            }
+        }
+        edges.sort_unstable_by_key(|edge| (edge.from, edge.to));
+        edges.dedup_by_key(|edge| (edge.from, edge.to));
+
+        Cfg {
+            code,
+            blocks,
+            edges,
+        }
+    }
+
+    /// Blocks of the code, in increasing order
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Edges between the blocks, sorted by source and destination
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Count the executions of every block and edge in the profile of a run.
+    /// Blocks and edges which the run never reached are counted as zero.
+    pub fn annotate(&mut self, profile: &Profile) {
+        // Every instruction of an executed block runs as many times as the
+        // block was entered
+        let executed = |pc: u32| {
+            let blocks = profile.blocks();
+            let index = match blocks.binary_search_by_key(&pc, |block| block.start) {
+                Ok(index) => index,
+                Err(index) => index.checked_sub(1)?,
+            };
+            Some(blocks[index]).filter(|block| pc <= block.end)
+        };
+
+        for block in &mut self.blocks {
+            block.count = Some(executed(block.start).map_or(0, |executed| executed.count));
+        }
+        let ends: BTreeMap<u32, u32> = self
+            .blocks
+            .iter()
+            .map(|block| (block.start, block.end))
+            .collect();
+        for edge in &mut self.edges {
+            let end = ends[&edge.from];
+            let count = match executed(end) {
+                // Within an executed block of the run
+                Some(executed) if executed.end != end => executed.count,
+                Some(executed) => profile
+                    .edges()
+                    .iter()
+                    .find(|dynamic| dynamic.from == executed.start && dynamic.to == edge.to)
+                    .map_or(0, |dynamic| dynamic.count),
+                None => 0,
+            };
+            edge.count = Some(count);
+        }
+    }
+
+    /// Write the control flow graph in the dot language of Graphviz, with
+    /// the instructions of every block. Jumps are dashed, and once annotated
+    /// hot edges are drawn thicker and edges never taken are dotted.
+    ///
+    /// # Arguments
+    /// * `out` => destination of the graph
+    /// * `functions` => functions sorted by address
+    pub fn write_dot<W: Write>(&self, out: &mut W, functions: &[Symbol]) -> io::Result<()> {
+        writeln!(out, "digraph cfg {{")?;
+        writeln!(out, "    node [shape=box, fontname=monospace];")?;
+        for block in &self.blocks {
+            let mut label = Symbol::lookup(functions, block.start)
+                .map(|symbol| format!("{}+0x{:x}", symbol.name, block.start - symbol.value))
+                .unwrap_or_else(|| format!("0x{:08x}", block.start));
+            if let Some(count) = block.count {
+                label += &format!(" ({} executions)", count);
+            }
+            label += "\\l";
+            for (addr, raw) in self.code.range(block.start..=block.end) {
+                let text = Instruction::new(*raw).to_string();
+                label += &format!("{:08x}: {}\\l", addr, text.trim_end());
+            }
+            writeln!(out, "    b{:08x} [label=\"{}\"];", block.start, label)?;
+        }
+
+        let hottest = self
+            .edges
+            .iter()
+            .filter_map(|edge| edge.count)
+            .max()
+            .unwrap_or(0);
+        for edge in &self.edges {
+            let mut attributes = Vec::new();
+            if let Some(count) = edge.count {
+                let width = 1.0 + 4.0 * count as f64 / hottest.max(1) as f64;
+                attributes.push(format!("label=\"{}\"", count));
+                attributes.push(format!("penwidth={:.1}", width));
+            }
+            if edge.count == Some(0) {
+                attributes.push(String::from("style=dotted"));
+            } else if edge.kind == EdgeKind::Jump {
+                attributes.push(String::from("style=dashed"));
+            }
+            write!(out, "    b{:08x} -> b{:08x}", edge.from, edge.to)?;
+            if !attributes.is_empty() {
+                write!(out, " [{}]", attributes.join(", "))?;
+            }
+            writeln!(out, ";")?;
+        }
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coverage::Coverage;
+    use latency::InstrClass;
+
+    // li a0, 2, a loop of addi and bnez, a call to 0x118 and a jump back to
+    // itself, then a function returning right away
+    fn words() -> Vec<(u32, u32)> {
+        vec![
+            (0x100, 0x0020_0513), // li a0, 2
+            (0x104, 0xfff5_0513), // addi a0, a0, -1
+            (0x108, 0xfe05_1ee3), // bnez a0, 0x104
+            (0x10c, 0x00c0_00ef), // jal ra, 0x118
+            (0x110, 0x0000_0013), // nop
+            (0x114, 0x0000_006f), // j .
+            (0x118, 0x0000_8067), // ret
+        ]
+    }
+
+    #[test]
+    fn test_blocks() {
+        let cfg = Cfg::new(&words());
+        let blocks: Vec<_> = cfg
+            .blocks()
+            .iter()
+            .map(|block| (block.start, block.end))
+            .collect();
+        assert_eq!(
+            vec![
+                (0x100, 0x100),
+                (0x104, 0x108),
+                (0x10c, 0x10c),
+                (0x110, 0x110),
+                (0x114, 0x114),
+                (0x118, 0x118)
+            ],
+            blocks
+        );
+
+        let edges: Vec<_> = cfg
+            .edges()
+            .iter()
+            .map(|edge| (edge.from, edge.to, edge.kind))
+            .collect();
+        assert_eq!(
+            vec![
+                (0x100, 0x104, EdgeKind::Fallthrough),
+                (0x104, 0x104, EdgeKind::Taken),
+                (0x104, 0x10c, EdgeKind::Fallthrough),
+                (0x10c, 0x110, EdgeKind::Fallthrough),
+                (0x10c, 0x118, EdgeKind::Jump),
+                (0x110, 0x114, EdgeKind::Fallthrough),
+                (0x114, 0x114, EdgeKind::Jump),
+            ],
+            edges
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        let mut coverage = Coverage::new();
+        coverage.execute(0x100, InstrClass::Alu, 0x104);
+        for next_pc in &[0x104, 0x10c] {
+            coverage.execute(0x104, InstrClass::Alu, 0x108);
+            coverage.execute(0x108, InstrClass::Branch, *next_pc);
+        }
+        coverage.execute(0x10c, InstrClass::Jump, 0x118);
+        coverage.execute(0x118, InstrClass::Jump, 0x110);
+        coverage.execute(0x110, InstrClass::Alu, 0x114);
+        coverage.execute(0x114, InstrClass::Jump, 0x114);
+
+        let mut cfg = Cfg::new(&words());
+        cfg.annotate(&Profile::new(&coverage));
+        let counts: Vec<_> = cfg.blocks().iter().map(|block| block.count).collect();
+        assert_eq!(
+            vec![Some(1), Some(2), Some(1), Some(1), Some(1), Some(1)],
+            counts
+        );
+        let counts: Vec<_> = cfg.edges().iter().map(|edge| edge.count).collect();
+        // The call returns through an indirect jump, so its fallthrough is
+        // not counted
+        assert_eq!(
+            vec![
+                Some(1),
+                Some(1),
+                Some(1),
+                Some(0),
+                Some(1),
+                Some(1),
+                Some(1)
+            ],
+            counts
+        );
+    }
+
+    #[test]
+    fn test_write_dot() {
+        let functions = vec![Symbol {
+            name: "f".to_string(),
+            value: 0x118,
+            size: 4,
+        }];
+        let mut cfg = Cfg::new(&words());
+        let mut out = Vec::new();
+        cfg.write_dot(&mut out, &functions).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("digraph cfg {\n"));
+        assert!(out.contains("    b00000118 [label=\"f+0x0\\l00000118: jalr"));
+        assert!(out.contains("    b00000104 -> b00000104;\n"));
+        assert!(out.contains("    b0000010c -> b00000118 [style=dashed];\n"));
+        assert!(out.ends_with("}\n"));
+
+        cfg.annotate(&Profile::default());
+        let mut out = Vec::new();
+        cfg.write_dot(&mut out, &functions).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("    b00000100 [label=\"0x00000100 (0 executions)\\l"));
+        assert!(
+            out.contains("    b0000010c -> b00000118 [label=\"0\", penwidth=1.0, style=dotted];\n")
+        );
+    }
+}
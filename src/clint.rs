@@ -0,0 +1,248 @@
+//! Core local interruptor, the timer and software interrupts of the hart at
+//! the addresses used by the SiFive CLINT, which is what Zephyr, FreeRTOS
+//! and OpenSBI expect on RISC-V boards. The hart has no notion of time, so
+//! mtime counts executed instructions, unless it's given the frequency of the
+//! core clock. mtime then runs at the time base frequency, counting
+//! microseconds of the simulated cycles.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::clint::{Clint, CLINT_BASE};
+//! let mut clint = Clint::new();
+//! // mtimecmp of hart 0
+//! clint.write(CLINT_BASE + 0x4000, 4, 2);
+//! clint.write(CLINT_BASE + 0x4004, 4, 0);
+//! clint.tick();
+//! assert!(!clint.timer_pending());
+//! clint.tick();
+//! assert!(clint.timer_pending());
+//! ```
+
+/// First address of the CLINT
+pub const CLINT_BASE: u32 = 0x0200_0000;
+/// Size of the CLINT registers in bytes
+pub const CLINT_SIZE: u32 = 0x0001_0000;
+/// Frequency of mtime when the core follows a clock, and the time base
+/// given to the operating system
+pub const TIMEBASE_FREQUENCY: u32 = 1_000_000;
+
+// Offsets of the registers of hart 0
+const MSIP: u32 = 0x0000;
+const MTIMECMP: u32 = 0x4000;
+const MTIME: u32 = 0xbff8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clint {
+    msip: bool,
+    mtimecmp: u64,
+    mtime: u64,
+    // Frequency of the core clock in Hz, and the time base cycles counted
+    // since the last tick of mtime
+    clock: Option<u64>,
+    phase: u64,
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clint {
+    /// Create a CLINT at time 0. The timer never fires until mtimecmp is
+    /// written.
+    pub fn new() -> Self {
+        Clint {
+            msip: false,
+            mtimecmp: u64::MAX,
+            mtime: 0,
+            clock: None,
+            phase: 0,
+        }
+    }
+
+    /// Check if an address belongs to the CLINT
+    pub fn contains(&self, addr: u32) -> bool {
+        addr.wrapping_sub(CLINT_BASE) < CLINT_SIZE
+    }
+
+    /// Read a register
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first byte
+    /// * `size` => number of bytes read, up to 4
+    ///
+    /// # Return Value
+    /// The bytes in little endian, unmapped bytes read as 0
+    pub fn read(&self, addr: u32, size: u32) -> u32 {
+        (0..size).fold(0, |value, index| {
+            let offset = addr.wrapping_add(index).wrapping_sub(CLINT_BASE);
+            value | u32::from(self.read_byte(offset)) << (index << 3)
+        })
+    }
+
+    /// Write a register
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first byte
+    /// * `size` => number of bytes written, up to 4
+    /// * `value` => the bytes in little endian
+    pub fn write(&mut self, addr: u32, size: u32, value: u32) {
+        for index in 0..size {
+            let offset = addr.wrapping_add(index).wrapping_sub(CLINT_BASE);
+            self.write_byte(offset, (value >> (index << 3)) as u8);
+        }
+    }
+
+    /// Make mtime follow the cycles of a core clock instead of the
+    /// instructions
+    ///
+    /// # Arguments
+    /// * `hz` => frequency of the core clock, more than 0
+    pub fn set_clock(&mut self, hz: Option<u64>) {
+        assert!(hz != Some(0), "core clock of 0 Hz");
+        self.clock = hz;
+        self.phase = 0;
+    }
+
+    /// Frequency of the core clock in Hz, if mtime follows one
+    pub fn clock(&self) -> Option<u64> {
+        self.clock
+    }
+
+    /// Advance the time by one instruction, unless mtime follows a clock
+    pub fn tick(&mut self) {
+        if self.clock.is_none() {
+            self.mtime = self.mtime.wrapping_add(1);
+        }
+    }
+
+    /// Advance the time by one cycle of the core clock, if mtime follows
+    /// one
+    pub fn count_cycle(&mut self) {
+        if let Some(hz) = self.clock {
+            self.phase += u64::from(TIMEBASE_FREQUENCY);
+            while self.phase >= hz {
+                self.phase -= hz;
+                self.mtime = self.mtime.wrapping_add(1);
+            }
+        }
+    }
+
+    /// Advance the time to the next timer interrupt, used to skip the idle
+    /// time of a wait for interrupt
+    pub fn skip_to_timer(&mut self) {
+        if self.mtimecmp != u64::MAX {
+            self.mtime = self.mtime.max(self.mtimecmp);
+        }
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    /// Check if the timer interrupt is pending
+    pub fn timer_pending(&self) -> bool {
+        self.mtime >= self.mtimecmp
+    }
+
+    /// Check if the software interrupt is pending
+    pub fn software_pending(&self) -> bool {
+        self.msip
+    }
+
+    // Read one byte of a register at an offset from the base
+    fn read_byte(&self, offset: u32) -> u8 {
+        match offset {
+            MSIP => self.msip as u8,
+            _ if offset.wrapping_sub(MTIMECMP) < 8 => {
+                (self.mtimecmp >> ((offset - MTIMECMP) << 3)) as u8
+            }
+            _ if offset.wrapping_sub(MTIME) < 8 => (self.mtime >> ((offset - MTIME) << 3)) as u8,
+            _ => 0,
+        }
+    }
+
+    // Write one byte of a register at an offset from the base. Only the
+    // lowest bit of msip is writable.
+    fn write_byte(&mut self, offset: u32, byte: u8) {
+        let replace =
+            |register: u64, shift: u32| register & !(0xff << shift) | u64::from(byte) << shift;
+        match offset {
+            MSIP => self.msip = byte & 1 != 0,
+            _ if offset.wrapping_sub(MTIMECMP) < 8 => {
+                self.mtimecmp = replace(self.mtimecmp, (offset - MTIMECMP) << 3)
+            }
+            _ if offset.wrapping_sub(MTIME) < 8 => {
+                self.mtime = replace(self.mtime, (offset - MTIME) << 3)
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registers() {
+        let mut clint = Clint::new();
+        assert!(clint.contains(CLINT_BASE + 0xbffc));
+        assert!(!clint.contains(CLINT_BASE + CLINT_SIZE));
+
+        clint.write(CLINT_BASE + MTIME, 4, 0xffff_fffe);
+        clint.tick();
+        clint.tick();
+        // The low word carries into the high one
+        assert_eq!(0, clint.read(CLINT_BASE + MTIME, 4));
+        assert_eq!(1, clint.read(CLINT_BASE + MTIME + 4, 4));
+        assert_eq!(0xffff, clint.read(CLINT_BASE + MTIMECMP + 2, 2));
+
+        clint.write(CLINT_BASE + MSIP, 4, 0xffff_ffff);
+        assert!(clint.software_pending());
+        assert_eq!(1, clint.read(CLINT_BASE + MSIP, 4));
+        assert_eq!(0, clint.read(CLINT_BASE + 0x100, 4));
+    }
+
+    #[test]
+    fn test_timer() {
+        let mut clint = Clint::new();
+        clint.skip_to_timer();
+        assert_eq!(0, clint.mtime());
+        assert!(!clint.timer_pending());
+
+        clint.write(CLINT_BASE + MTIMECMP + 4, 4, 0);
+        clint.write(CLINT_BASE + MTIMECMP, 4, 100);
+        clint.skip_to_timer();
+        assert_eq!(100, clint.mtime());
+        assert!(clint.timer_pending());
+        // The interrupt is cleared by moving the compare value
+        clint.write(CLINT_BASE + MTIMECMP, 4, 200);
+        assert!(!clint.timer_pending());
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut clint = Clint::new();
+        // 2.5 cycles per microsecond
+        clint.set_clock(Some(2_500_000));
+        clint.tick();
+        assert_eq!(0, clint.mtime());
+        for _ in 0..10 {
+            clint.count_cycle();
+        }
+        assert_eq!(4, clint.mtime());
+
+        // Cores slower than the time base tick mtime more than once a cycle
+        clint.set_clock(Some(500_000));
+        clint.count_cycle();
+        assert_eq!(6, clint.mtime());
+
+        clint.set_clock(None);
+        clint.count_cycle();
+        clint.tick();
+        assert_eq!(7, clint.mtime());
+    }
+}
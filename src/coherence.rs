@@ -0,0 +1,401 @@
+//! Snooping coherence model of the private data caches of several harts,
+//! with the MSI or the MESI protocol. The caches only track the state of
+//! their lines, to count the hits, the misses and the traffic the protocol
+//! puts on the shared bus:
+//!
+//! * a read miss broadcasts a read, after which a modified copy in another
+//!   cache is written back and every copy is shared
+//! * a write miss broadcasts a read for ownership, which invalidates every
+//!   other copy
+//! * a write to a shared line broadcasts an upgrade, which invalidates every
+//!   other copy without moving the data
+//!
+//! With MESI, a line read while no other cache holds it is exclusive, and a
+//! write to it takes no bus transaction. Evicting a modified line writes it
+//! back.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cache::CacheConfig;
+//! # use adept_lib::coherence::{CoherentCaches, LineState, Protocol};
+//! let mut caches = CoherentCaches::new(Protocol::Mesi, CacheConfig::new(1024, 16, 2).unwrap(), 2);
+//! assert!(!caches.read(0, 0x100));
+//! assert_eq!(LineState::Exclusive, caches.state(0, 0x100));
+//! assert!(!caches.write(1, 0x104));
+//! assert_eq!(LineState::Invalid, caches.state(0, 0x100));
+//! assert_eq!(1, caches.stats().invalidations);
+//! ```
+use cache::CacheConfig;
+
+use std::fmt::{self, Display, Formatter};
+
+/// Coherence protocols
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Modified, shared and invalid lines
+    Msi,
+    /// MSI with exclusive lines, which are written without a bus transaction
+    Mesi,
+}
+
+impl Protocol {
+    /// Every protocol, from the simplest one
+    pub const ALL: [Protocol; 2] = [Protocol::Msi, Protocol::Mesi];
+
+    /// Name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Msi => "msi",
+            Protocol::Mesi => "mesi",
+        }
+    }
+
+    /// Find a protocol by name, whatever the case
+    pub fn from_name(name: &str) -> Option<Protocol> {
+        Self::ALL
+            .iter()
+            .cloned()
+            .find(|protocol| protocol.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// States of a line in a cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineState {
+    /// Written since it was filled, the other caches don't hold it
+    Modified,
+    /// Clean and held by no other cache
+    Exclusive,
+    /// Clean and possibly held by other caches
+    Shared,
+    /// Not in the cache
+    Invalid,
+}
+
+/// Accesses of the caches and traffic of the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CoherenceStats {
+    /// Reads and writes of every hart
+    pub accesses: u64,
+    /// Accesses to a line the cache didn't hold
+    pub misses: u64,
+    /// Reads broadcast on a read miss
+    pub bus_reads: u64,
+    /// Reads for ownership broadcast on a write miss
+    pub bus_read_exclusives: u64,
+    /// Writes to shared lines, broadcast to invalidate the other copies
+    pub upgrades: u64,
+    /// Copies invalidated by the writes of other harts
+    pub invalidations: u64,
+    /// Modified lines written back to the memory, when evicted or snooped
+    pub writebacks: u64,
+    /// Misses another cache answered from a modified or exclusive line
+    pub interventions: u64,
+}
+
+impl CoherenceStats {
+    /// Transactions broadcast on the bus, without the writebacks
+    pub fn bus_transactions(&self) -> u64 {
+        self.bus_reads + self.bus_read_exclusives + self.upgrades
+    }
+
+    /// Misses per access. It's 0 if nothing was accessed.
+    pub fn miss_rate(&self) -> f64 {
+        if self.accesses == 0 {
+            0.0
+        } else {
+            self.misses as f64 / self.accesses as f64
+        }
+    }
+}
+
+impl Display for CoherenceStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "accesses:            {}", self.accesses)?;
+        writeln!(
+            f,
+            "misses:              {} ({:.2}%)",
+            self.misses,
+            self.miss_rate() * 100.0
+        )?;
+        writeln!(f, "bus reads:           {}", self.bus_reads)?;
+        writeln!(f, "bus read exclusives: {}", self.bus_read_exclusives)?;
+        writeln!(f, "upgrades:            {}", self.upgrades)?;
+        writeln!(f, "invalidations:       {}", self.invalidations)?;
+        writeln!(f, "writebacks:          {}", self.writebacks)?;
+        write!(f, "interventions:       {}", self.interventions)
+    }
+}
+
+// A valid line of a cache
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    tag: u32,
+    state: LineState,
+    last_use: u64,
+}
+
+/// Private caches of the harts, kept coherent by snooping the bus
+#[derive(Debug, Clone)]
+pub struct CoherentCaches {
+    protocol: Protocol,
+    config: CacheConfig,
+    // Valid lines of every cache, by set
+    caches: Vec<Vec<Vec<Line>>>,
+    // Accesses so far, which orders the uses of the lines
+    now: u64,
+    stats: CoherenceStats,
+}
+
+impl CoherentCaches {
+    /// Create empty caches
+    ///
+    /// # Arguments
+    /// * `protocol` => coherence protocol
+    /// * `config` => geometry of every cache
+    /// * `harts` => number of caches, one per hart
+    pub fn new(protocol: Protocol, config: CacheConfig, harts: usize) -> Self {
+        CoherentCaches {
+            protocol,
+            config,
+            caches: vec![vec![Vec::new(); config.sets() as usize]; harts],
+            now: 0,
+            stats: CoherenceStats::default(),
+        }
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn config(&self) -> CacheConfig {
+        self.config
+    }
+
+    pub fn stats(&self) -> &CoherenceStats {
+        &self.stats
+    }
+
+    /// State of the line holding an address in the cache of a hart
+    pub fn state(&self, hart: usize, addr: u32) -> LineState {
+        let (set, tag) = self.locate(addr);
+        self.caches[hart][set]
+            .iter()
+            .find(|line| line.tag == tag)
+            .map_or(LineState::Invalid, |line| line.state)
+    }
+
+    /// Read an address from the cache of a hart
+    ///
+    /// # Return Value
+    /// True on a hit
+    pub fn read(&mut self, hart: usize, addr: u32) -> bool {
+        let (set, tag) = self.locate(addr);
+        if self.touch(hart, set, tag).is_some() {
+            return true;
+        }
+        self.stats.bus_reads += 1;
+        let shared = self.snoop(hart, set, tag, false);
+        let state = match self.protocol {
+            Protocol::Mesi if !shared => LineState::Exclusive,
+            _ => LineState::Shared,
+        };
+        self.fill(hart, set, tag, state);
+        false
+    }
+
+    /// Write an address from the cache of a hart
+    ///
+    /// # Return Value
+    /// True on a hit, even if the line had to be upgraded
+    pub fn write(&mut self, hart: usize, addr: u32) -> bool {
+        let (set, tag) = self.locate(addr);
+        match self.touch(hart, set, tag) {
+            Some(LineState::Shared) => {
+                self.stats.upgrades += 1;
+                self.snoop(hart, set, tag, true);
+            }
+            Some(_) => (),
+            None => {
+                self.stats.bus_read_exclusives += 1;
+                self.snoop(hart, set, tag, true);
+                self.fill(hart, set, tag, LineState::Modified);
+                return false;
+            }
+        }
+        if let Some(line) = self.caches[hart][set]
+            .iter_mut()
+            .find(|line| line.tag == tag)
+        {
+            line.state = LineState::Modified;
+        }
+        true
+    }
+
+    // Set and tag of an address
+    fn locate(&self, addr: u32) -> (usize, u32) {
+        let line = addr / self.config.line;
+        let sets = self.config.sets();
+        ((line % sets) as usize, line / sets)
+    }
+
+    // Count an access, and use the line if the cache holds it
+    fn touch(&mut self, hart: usize, set: usize, tag: u32) -> Option<LineState> {
+        self.now += 1;
+        self.stats.accesses += 1;
+        let now = self.now;
+        match self.caches[hart][set]
+            .iter_mut()
+            .find(|line| line.tag == tag)
+        {
+            Some(line) => {
+                line.last_use = now;
+                Some(line.state)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    // Let the other caches see a transaction on the line, which they
+    // invalidate if exclusive is set and share otherwise
+    //
+    // Return Value
+    // True if another cache held the line
+    fn snoop(&mut self, hart: usize, set: usize, tag: u32, exclusive: bool) -> bool {
+        let mut shared = false;
+        for (other, cache) in self.caches.iter_mut().enumerate() {
+            if other == hart {
+                continue;
+            }
+            let position = match cache[set].iter().position(|line| line.tag == tag) {
+                Some(position) => position,
+                None => continue,
+            };
+            shared = true;
+            match cache[set][position].state {
+                LineState::Modified => {
+                    self.stats.writebacks += 1;
+                    self.stats.interventions += 1;
+                }
+                LineState::Exclusive => self.stats.interventions += 1,
+                _ => (),
+            }
+            if exclusive {
+                cache[set].remove(position);
+                self.stats.invalidations += 1;
+            } else {
+                cache[set][position].state = LineState::Shared;
+            }
+        }
+        shared
+    }
+
+    // Fill a line, evicting the least recently used one of a full set
+    fn fill(&mut self, hart: usize, set: usize, tag: u32, state: LineState) {
+        let line = Line {
+            tag,
+            state,
+            last_use: self.now,
+        };
+        let lines = &mut self.caches[hart][set];
+        if lines.len() < self.config.ways as usize {
+            lines.push(line);
+        } else if let Some(victim) = lines.iter_mut().min_by_key(|line| line.last_use) {
+            if victim.state == LineState::Modified {
+                self.stats.writebacks += 1;
+            }
+            *victim = line;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caches(protocol: Protocol) -> CoherentCaches {
+        // 2 sets of 2 ways of 16 bytes
+        CoherentCaches::new(protocol, CacheConfig::new(64, 16, 2).unwrap(), 2)
+    }
+
+    #[test]
+    fn test_protocol_names() {
+        assert_eq!(Some(Protocol::Mesi), Protocol::from_name("MESI"));
+        assert_eq!(Some(Protocol::Msi), Protocol::from_name("msi"));
+        assert_eq!(None, Protocol::from_name("moesi"));
+    }
+
+    #[test]
+    fn test_msi() {
+        let mut caches = caches(Protocol::Msi);
+        assert!(!caches.read(0, 0x00));
+        assert_eq!(LineState::Shared, caches.state(0, 0x00));
+        // Writing a line read before takes an upgrade
+        assert!(caches.write(0, 0x04));
+        assert_eq!(LineState::Modified, caches.state(0, 0x00));
+        // The other hart gets the modified line, which is written back
+        assert!(!caches.read(1, 0x08));
+        assert_eq!(LineState::Shared, caches.state(0, 0x00));
+        assert_eq!(LineState::Shared, caches.state(1, 0x00));
+        assert!(caches.write(1, 0x0c));
+        assert_eq!(LineState::Invalid, caches.state(0, 0x00));
+        assert_eq!(
+            CoherenceStats {
+                accesses: 4,
+                misses: 2,
+                bus_reads: 2,
+                bus_read_exclusives: 0,
+                upgrades: 2,
+                invalidations: 1,
+                writebacks: 1,
+                interventions: 1,
+            },
+            *caches.stats()
+        );
+    }
+
+    #[test]
+    fn test_mesi() {
+        let mut caches = caches(Protocol::Mesi);
+        assert!(!caches.read(0, 0x00));
+        assert_eq!(LineState::Exclusive, caches.state(0, 0x00));
+        // An exclusive line is written without telling anyone
+        assert!(caches.write(0, 0x04));
+        assert_eq!(LineState::Modified, caches.state(0, 0x00));
+        assert_eq!(1, caches.stats().bus_transactions());
+        // A write miss invalidates the modified copy
+        assert!(!caches.write(1, 0x00));
+        assert_eq!(LineState::Invalid, caches.state(0, 0x00));
+        assert_eq!(LineState::Modified, caches.state(1, 0x00));
+        // Both harts share the line after a read
+        assert!(!caches.read(0, 0x00));
+        assert_eq!(LineState::Shared, caches.state(0, 0x00));
+        assert_eq!(LineState::Shared, caches.state(1, 0x00));
+        let stats = caches.stats();
+        assert_eq!(
+            (2, 1, 0),
+            (stats.bus_reads, stats.bus_read_exclusives, stats.upgrades)
+        );
+        assert_eq!(
+            (1, 2, 2),
+            (stats.invalidations, stats.writebacks, stats.interventions)
+        );
+    }
+
+    #[test]
+    fn test_eviction_writes_back() {
+        let mut caches = caches(Protocol::Mesi);
+        // 0x00, 0x20 and 0x40 map to the first set
+        assert!(!caches.write(0, 0x00));
+        assert!(!caches.read(0, 0x20));
+        assert!(!caches.read(0, 0x40));
+        assert_eq!(LineState::Invalid, caches.state(0, 0x00));
+        assert!(!caches.read(0, 0x60));
+        assert_eq!(1, caches.stats().writebacks);
+        assert_eq!(4, caches.stats().misses);
+    }
+}
@@ -0,0 +1,352 @@
+//! Simulator configuration files, written in TOML. Values can be integers,
+//! booleans, strings and arrays of those, and the tables nested in another
+//! one are named by their dotted path, such as `bus.sram`.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::config::{Config, Value};
+//! let config = Config::parse("[latency]\nmul = 3 # cycles\ndiv = 34\n").unwrap();
+//! assert_eq!(Some(&Value::Integer(3)), config.get("latency", "mul"));
+//! assert_eq!(Some(34), config.get_integer("latency", "div").unwrap());
+//! ```
+use std::collections::btree_map;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Error as IoError;
+use std::io::Read;
+use std::path::Path;
+
+/// A value stored in a configuration table
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Array(Vec<Value>),
+}
+
+/// Key/value pairs of a single configuration table
+pub type Table = BTreeMap<String, Value>;
+
+/// Configuration error type
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read
+    Io(IoError),
+    /// The configuration text is malformed
+    Parse { line: usize, message: String },
+    /// A well formed value is not acceptable for its key
+    Value { key: String, message: String },
+}
+
+/// Conversion from IoError
+impl From<IoError> for ConfigError {
+    fn from(err: IoError) -> ConfigError {
+        ConfigError::Io(err)
+    }
+}
+
+/// Display trait implementation for ConfigError
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(io_error) => io_error.fmt(f),
+            ConfigError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            ConfigError::Value { key, message } => {
+                write!(f, "invalid value for {}: {}", key, message)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// A parsed configuration. Keys that appear before the first table header are
+/// stored in the table with an empty name.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    tables: BTreeMap<String, Table>,
+}
+
+impl Config {
+    /// Create an empty configuration
+    pub fn new() -> Self {
+        Config {
+            tables: BTreeMap::new(),
+        }
+    }
+
+    /// Read and parse a configuration file
+    ///
+    /// # Arguments
+    /// * `path` => path of the configuration file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        Self::parse(&text)
+    }
+
+    /// Parse configuration text
+    ///
+    /// # Arguments
+    /// * `text` => configuration contents
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let document: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+            // The span of the error starts on the line it reports
+            let start = e.span().map_or(0, |span| span.start);
+            ConfigError::Parse {
+                line: text[..start].matches('\n').count() + 1,
+                message: e.message().to_string(),
+            }
+        })?;
+        let mut config = Config::new();
+        config.add_table(String::new(), document)?;
+        Ok(config)
+    }
+
+    // Store the values of a table, and the tables nested in it under their
+    // dotted names
+    fn add_table(&mut self, name: String, document: toml::Table) -> Result<(), ConfigError> {
+        let mut table = Table::new();
+        for (key, value) in document {
+            match value {
+                toml::Value::Table(nested) => {
+                    let nested_name = if name.is_empty() {
+                        key
+                    } else {
+                        format!("{}.{}", name, key)
+                    };
+                    self.add_table(nested_name, nested)?;
+                }
+                value => {
+                    let value =
+                        convert(value).map_err(|message| value_error(&name, &key, message))?;
+                    table.insert(key, value);
+                }
+            }
+        }
+        if !name.is_empty() || !table.is_empty() {
+            self.tables.entry(name).or_default().extend(table);
+        }
+        Ok(())
+    }
+
+    /// Iterate over every table, sorted by name
+    pub fn tables(&self) -> btree_map::Iter<'_, String, Table> {
+        self.tables.iter()
+    }
+
+    /// Get a table by name
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(name)
+    }
+
+    /// Get a value from a table
+    pub fn get(&self, table: &str, key: &str) -> Option<&Value> {
+        self.table(table).and_then(|table| table.get(key))
+    }
+
+    /// Get an integer from a table. It's an error if the key exists with a
+    /// value of another type.
+    pub fn get_integer(&self, table: &str, key: &str) -> Result<Option<i64>, ConfigError> {
+        match self.get(table, key) {
+            Some(Value::Integer(value)) => Ok(Some(*value)),
+            Some(_) => Err(value_error(table, key, "expected an integer")),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a boolean from a table. It's an error if the key exists with a
+    /// value of another type.
+    pub fn get_boolean(&self, table: &str, key: &str) -> Result<Option<bool>, ConfigError> {
+        match self.get(table, key) {
+            Some(Value::Boolean(value)) => Ok(Some(*value)),
+            Some(_) => Err(value_error(table, key, "expected a boolean")),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a string from a table. It's an error if the key exists with a
+    /// value of another type.
+    pub fn get_string(&self, table: &str, key: &str) -> Result<Option<&str>, ConfigError> {
+        match self.get(table, key) {
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(value_error(table, key, "expected a string")),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Create a ConfigError::Value for `table.key`
+pub fn value_error(table: &str, key: &str, message: &str) -> ConfigError {
+    ConfigError::Value {
+        key: if table.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", table, key)
+        },
+        message: message.to_string(),
+    }
+}
+
+// Value of a key, which can't be a float, a date or a table in an array
+fn convert(value: toml::Value) -> Result<Value, &'static str> {
+    match value {
+        toml::Value::Integer(value) => Ok(Value::Integer(value)),
+        toml::Value::Boolean(value) => Ok(Value::Boolean(value)),
+        toml::Value::String(value) => Ok(Value::String(value)),
+        toml::Value::Array(values) => values
+            .into_iter()
+            .map(convert)
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+        _ => Err("expected an integer, a boolean, a string or an array"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ////////////////////////////////////////
+    // Values
+    ////////////////////////////////////////
+    // Value of `key` in a document holding only it
+    fn parse_value(text: &str) -> Result<Value, ConfigError> {
+        let config = Config::parse(&format!("key = {}\n", text))?;
+        Ok(config.get("", "key").unwrap().clone())
+    }
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(Value::Integer(42), parse_value("42").unwrap());
+        assert_eq!(Value::Integer(-7), parse_value("-7").unwrap());
+        assert_eq!(
+            Value::Integer(0x8000_0000),
+            parse_value("0x8000_0000").unwrap()
+        );
+        assert_eq!(Value::Integer(5), parse_value("0b101").unwrap());
+        assert!(parse_value("0xg").is_err());
+        assert!(parse_value("twelve").is_err());
+    }
+
+    #[test]
+    fn test_booleans() {
+        assert_eq!(Value::Boolean(true), parse_value("true").unwrap());
+        assert_eq!(Value::Boolean(false), parse_value("false").unwrap());
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(
+            Value::String(String::from("sram")),
+            parse_value("\"sram\"").unwrap()
+        );
+        assert_eq!(
+            Value::String(String::from("a \"b\"\n")),
+            parse_value("\"a \\\"b\\\"\\n\"").unwrap()
+        );
+        assert!(parse_value("\"open").is_err());
+        assert!(parse_value("\"a\" b").is_err());
+    }
+
+    #[test]
+    fn test_arrays() {
+        assert_eq!(
+            Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+            parse_value("[1, 2]").unwrap()
+        );
+        assert_eq!(
+            Value::Array(vec![
+                Value::String(String::from("a,b")),
+                Value::Boolean(true),
+            ]),
+            parse_value("[\"a,b\", true]").unwrap()
+        );
+        assert_eq!(Value::Array(vec![]), parse_value("[]").unwrap());
+    }
+
+    #[test]
+    fn test_unsupported_values() {
+        match parse_value("1.5") {
+            Err(ConfigError::Value { key, .. }) => assert_eq!("key", key),
+            _ => panic!("Expected a value error"),
+        }
+        assert!(parse_value("[1, 2.5]").is_err());
+        assert!(Config::parse("[[regions]]\nbase = 0\n").is_err());
+    }
+
+    ////////////////////////////////////////
+    // Documents
+    ////////////////////////////////////////
+    #[test]
+    fn test_parse_tables() {
+        let config = Config::parse(
+            "name = \"adept\" # root key\n\
+             \n\
+             [latency]\n\
+             mul = 3\n\
+             load = 2\n\
+             [pipeline]\n\
+             stages = 3\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(&Value::String(String::from("adept"))),
+            config.get("", "name")
+        );
+        assert_eq!(Some(&Value::Integer(3)), config.get("latency", "mul"));
+        assert_eq!(Some(&Value::Integer(2)), config.get("latency", "load"));
+        assert_eq!(Some(&Value::Integer(3)), config.get("pipeline", "stages"));
+        assert_eq!(None, config.get("pipeline", "mul"));
+        assert_eq!(2, config.table("latency").unwrap().len());
+    }
+
+    #[test]
+    fn test_nested_tables() {
+        let config = Config::parse("[bus]\nlatency = 1\n[bus.sram]\nbase = 0x1000\n").unwrap();
+        assert_eq!(Some(1), config.get_integer("bus", "latency").unwrap());
+        assert_eq!(
+            Some(0x1000),
+            config.get_integer("bus.sram", "base").unwrap()
+        );
+        assert_eq!(None, config.get("bus", "sram"));
+        let names: Vec<&String> = config.tables().map(|(name, _)| name).collect();
+        assert_eq!(vec!["bus", "bus.sram"], names);
+    }
+
+    #[test]
+    fn test_comment_in_string() {
+        let config = Config::parse("[a]\nb = \"#1\" # comment\n").unwrap();
+        assert_eq!(Some("#1"), config.get_string("a", "b").unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        match Config::parse("[latency]\nmul 3\n") {
+            Err(ConfigError::Parse { line, .. }) => assert_eq!(2, line),
+            _ => panic!("Expected a parse error"),
+        }
+        assert!(Config::parse("[latency\n").is_err());
+        assert!(Config::parse("[]\n").is_err());
+        assert!(Config::parse("= 3\n").is_err());
+        assert!(Config::parse("a = 1\na = 2\n").is_err());
+    }
+
+    #[test]
+    fn test_typed_getters() {
+        let config = Config::parse("[t]\ni = 1\nb = true\ns = \"x\"\n").unwrap();
+
+        assert_eq!(Some(1), config.get_integer("t", "i").unwrap());
+        assert_eq!(Some(true), config.get_boolean("t", "b").unwrap());
+        assert_eq!(Some("x"), config.get_string("t", "s").unwrap());
+        assert_eq!(None, config.get_integer("t", "missing").unwrap());
+        assert!(config.get_integer("t", "s").is_err());
+        assert!(config.get_boolean("t", "i").is_err());
+        assert!(config.get_string("t", "b").is_err());
+    }
+}
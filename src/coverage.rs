@@ -0,0 +1,268 @@
+//! Code coverage of the guest program. Every executed instruction is counted
+//! and the outcomes of every conditional branch are recorded, then exported
+//! either as a plain list of addresses or in the tracefile format of lcov.
+//! Without debug information the lcov lines are instruction addresses, and
+//! the functions come from the symbol table of the ELF. The control flow
+//! edges of branches and jumps are counted as well, for the profiles built
+//! from the coverage.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::coverage::Coverage;
+//! # use adept_lib::latency::InstrClass;
+//! let mut coverage = Coverage::new();
+//! coverage.execute(0x100, InstrClass::Alu, 0x104);
+//! // A taken branch
+//! coverage.execute(0x104, InstrClass::Branch, 0x100);
+//! coverage.execute(0x100, InstrClass::Alu, 0x104);
+//! assert_eq!(2, coverage.count(0x100));
+//! assert_eq!((1, 0), coverage.branch(0x104));
+//! ```
+use elf::Symbol;
+use latency::InstrClass;
+use riscv::decoder::Instruction;
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Executed instructions and branch outcomes
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    executed: HashMap<u32, u64>,
+    // Taken and not taken counts of every conditional branch
+    branches: HashMap<u32, (u64, u64)>,
+    // Source and destination of branches and jumps
+    edges: HashMap<(u32, u32), u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage::default()
+    }
+
+    /// Record an executed instruction
+    ///
+    /// # Arguments
+    /// * `pc` => address of the instruction
+    /// * `class` => class of the instruction
+    /// * `next_pc` => address of the next instruction
+    pub fn execute(&mut self, pc: u32, class: InstrClass, next_pc: u32) {
+        *self.executed.entry(pc).or_default() += 1;
+        if class == InstrClass::Branch || class == InstrClass::Jump {
+            *self.edges.entry((pc, next_pc)).or_default() += 1;
+        }
+        if class == InstrClass::Branch {
+            let outcomes = self.branches.entry(pc).or_default();
+            if next_pc == pc.wrapping_add(4) {
+                outcomes.1 += 1;
+            } else {
+                outcomes.0 += 1;
+            }
+        }
+    }
+
+    /// Number of times the instruction at an address was executed
+    pub fn count(&self, pc: u32) -> u64 {
+        self.executed.get(&pc).cloned().unwrap_or(0)
+    }
+
+    /// Number of times the branch at an address was taken and not taken
+    pub fn branch(&self, pc: u32) -> (u64, u64) {
+        self.branches.get(&pc).cloned().unwrap_or((0, 0))
+    }
+
+    /// Check if the instruction at an address is a branch which was executed
+    pub fn is_branch(&self, pc: u32) -> bool {
+        self.branches.contains_key(&pc)
+    }
+
+    /// Source, destination and count of every control flow edge taken by a
+    /// branch or a jump, in increasing order
+    pub fn edges(&self) -> Vec<(u32, u32, u64)> {
+        let mut edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(|(&(from, to), &count)| (from, to, count))
+            .collect();
+        edges.sort_unstable();
+        edges
+    }
+
+    /// Addresses of the executed instructions, in increasing order
+    pub fn addresses(&self) -> Vec<u32> {
+        let mut addresses: Vec<u32> = self.executed.keys().cloned().collect();
+        addresses.sort_unstable();
+        addresses
+    }
+
+    /// Write one line per executed instruction with its address, its
+    /// execution count, its branch outcomes and its function
+    ///
+    /// # Arguments
+    /// * `out` => destination of the list
+    /// * `functions` => functions sorted by address
+    pub fn write_addresses<W: Write>(&self, out: &mut W, functions: &[Symbol]) -> io::Result<()> {
+        for pc in self.addresses() {
+            write!(out, "0x{:08x} {}", pc, self.count(pc))?;
+            if let Some(&(taken, not_taken)) = self.branches.get(&pc) {
+                write!(out, " taken={} not_taken={}", taken, not_taken)?;
+            }
+            if let Some(symbol) = Symbol::lookup(functions, pc) {
+                write!(out, " {}+0x{:x}", symbol.name, pc - symbol.value)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Write an lcov tracefile. Every instruction of the program which
+    /// belongs to a function is a line, or every instruction if there are
+    /// no function symbols.
+    ///
+    /// # Arguments
+    /// * `out` => destination of the tracefile
+    /// * `source` => name of the covered file
+    /// * `functions` => functions sorted by address
+    /// * `program` => address and value of every word of the program
+    pub fn write_lcov<W: Write>(
+        &self,
+        out: &mut W,
+        source: &str,
+        functions: &[Symbol],
+        program: &[(u32, u32)],
+    ) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", source)?;
+
+        let mut functions_hit = 0;
+        for symbol in functions {
+            writeln!(out, "FN:{},{}", symbol.value, symbol.name)?;
+        }
+        for symbol in functions {
+            let count = self.count(symbol.value);
+            writeln!(out, "FNDA:{},{}", count, symbol.name)?;
+            if count > 0 {
+                functions_hit += 1;
+            }
+        }
+        writeln!(out, "FNF:{}", functions.len())?;
+        writeln!(out, "FNH:{}", functions_hit)?;
+
+        let mut program = program.to_vec();
+        program.sort_unstable();
+        let lines = program
+            .iter()
+            .filter(|(addr, _)| functions.is_empty() || Symbol::lookup(functions, *addr).is_some());
+        // Lines and branch outcomes
+        let (mut found, mut hit) = ((0, 0), (0, 0));
+        for (addr, raw) in lines {
+            let count = self.count(*addr);
+            writeln!(out, "DA:{},{}", addr, count)?;
+            found.0 += 1;
+            if count > 0 {
+                hit.0 += 1;
+            }
+
+            if InstrClass::from(Instruction::new(*raw).op()) != InstrClass::Branch {
+                continue;
+            }
+            let (taken, not_taken) = self.branch(*addr);
+            for (index, outcome) in [taken, not_taken].iter().enumerate() {
+                if count == 0 {
+                    writeln!(out, "BRDA:{},0,{},-", addr, index)?;
+                } else {
+                    writeln!(out, "BRDA:{},0,{},{}", addr, index, outcome)?;
+                }
+                found.1 += 1;
+                if *outcome > 0 {
+                    hit.1 += 1;
+                }
+            }
+        }
+        writeln!(out, "BRF:{}", found.1)?;
+        writeln!(out, "BRH:{}", hit.1)?;
+        writeln!(out, "LF:{}", found.0)?;
+        writeln!(out, "LH:{}", hit.0)?;
+        writeln!(out, "end_of_record")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cover a loop at 0x100 running twice: addi, then bnez back to 0x100
+    fn loop_coverage() -> Coverage {
+        let mut coverage = Coverage::new();
+        coverage.execute(0x100, InstrClass::Alu, 0x104);
+        coverage.execute(0x104, InstrClass::Branch, 0x100);
+        coverage.execute(0x100, InstrClass::Alu, 0x104);
+        coverage.execute(0x104, InstrClass::Branch, 0x108);
+        coverage
+    }
+
+    const PROGRAM: [(u32, u32); 4] = [
+        (0x104, 0xfe05_1ee3), // bnez a0, -4
+        (0x100, 0xfff5_0513), // addi a0, a0, -1
+        (0x108, 0x0000_8067), // ret
+        (0x10c, 0x0000_0463), // beqz zero, 8
+    ];
+
+    fn functions() -> Vec<Symbol> {
+        vec![Symbol {
+            name: "count".to_string(),
+            value: 0x100,
+            size: 0x10,
+        }]
+    }
+
+    #[test]
+    fn test_execute() {
+        let coverage = loop_coverage();
+        assert_eq!(vec![0x100, 0x104], coverage.addresses());
+        assert_eq!(2, coverage.count(0x104));
+        assert_eq!(0, coverage.count(0x108));
+        assert_eq!((1, 1), coverage.branch(0x104));
+        assert_eq!((0, 0), coverage.branch(0x100));
+        assert!(coverage.is_branch(0x104));
+        assert_eq!(vec![(0x104, 0x100, 1), (0x104, 0x108, 1)], coverage.edges());
+    }
+
+    #[test]
+    fn test_write_addresses() {
+        let mut out = Vec::new();
+        loop_coverage()
+            .write_addresses(&mut out, &functions())
+            .unwrap();
+        assert_eq!(
+            "0x00000100 2 count+0x0\n\
+             0x00000104 2 taken=1 not_taken=1 count+0x4\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_lcov() {
+        let mut out = Vec::new();
+        loop_coverage()
+            .write_lcov(&mut out, "loop.elf", &functions(), &PROGRAM)
+            .unwrap();
+        assert_eq!(
+            "TN:\nSF:loop.elf\nFN:256,count\nFNDA:2,count\nFNF:1\nFNH:1\n\
+             DA:256,2\nDA:260,2\nBRDA:260,0,0,1\nBRDA:260,0,1,1\nDA:264,0\n\
+             DA:268,0\nBRDA:268,0,0,-\nBRDA:268,0,1,-\n\
+             BRF:4\nBRH:2\nLF:4\nLH:2\nend_of_record\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        // Without symbols every word is a line
+        let mut out = Vec::new();
+        loop_coverage()
+            .write_lcov(&mut out, "loop.elf", &[], &PROGRAM[..3])
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("FNF:0\nFNH:0\n"));
+        assert!(out.contains("LF:3\nLH:2\n"));
+    }
+}
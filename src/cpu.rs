@@ -0,0 +1,835 @@
+//! The simulated processor: a hart and the pipeline model that drives it.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::hart::StopReason;
+//! # use adept_lib::mem::MemStoreOp;
+//! # use adept_lib::pipeline::PipelineConfig;
+//! # use adept_lib::riscv::isa::RV32I;
+//! let mut cpu = Cpu::new(PipelineConfig::default());
+//! // addi a0, zero, 5
+//! cpu.hart_mut().memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 0x100, 0x0050_0513).unwrap();
+//! cpu.set_pc(0x100);
+//! // The simulation stops at the first invalid instruction
+//! assert_eq!(StopReason::InvalidInstruction { pc: 0x104, raw: 0 }, cpu.run());
+//! assert_eq!(1, cpu.stats().instructions);
+//! ```
+use error::Error;
+use fault::{MemoryFaults, RegisterFlip, RunResult};
+use hart::{Hart, StopReason};
+use pipeline::{Pipeline, PipelineConfig};
+use profile::CycleProfile;
+use riscv::DecodeError;
+use stats::Stats;
+use trace::{CycleRecord, TraceEntry, Tracer};
+use tracepoint::Tracepoints;
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+// Cycles simulated between two reads of the clock, which are slower than a
+// cycle of a small program, a power of two
+const CLOCK_INTERVAL: u64 = 1024;
+
+// When a time limit runs out. A snapshot keeps the time left instead, since
+// the clock goes on while it isn't running.
+#[derive(Clone, Copy)]
+enum Deadline {
+    At(Instant),
+    After(Duration),
+}
+
+impl Deadline {
+    fn remaining(self) -> Duration {
+        match self {
+            Deadline::At(deadline) => deadline.saturating_duration_since(Instant::now()),
+            Deadline::After(remaining) => remaining,
+        }
+    }
+
+    fn start(self) -> Self {
+        Deadline::At(Instant::now() + self.remaining())
+    }
+}
+
+pub struct Cpu {
+    hart: Hart,
+    pipeline: Pipeline,
+    stats: Stats,
+    tracers: Vec<Box<dyn Tracer>>,
+    tracepoints: Tracepoints,
+    memory_faults: Option<MemoryFaults>,
+    register_fault: Option<RegisterFlip>,
+    cycle_limit: Option<u64>,
+    // Length of the time limit and when it runs out
+    time_limit: Option<(Duration, Deadline)>,
+    // Last retired instructions, up to history_size
+    history: VecDeque<TraceEntry>,
+    history_size: usize,
+    cycle_profile: Option<CycleProfile>,
+}
+
+impl Cpu {
+    /// Create a processor with a fresh hart, starting at address 0
+    ///
+    /// # Arguments
+    /// * `config` => configuration of the pipeline model
+    pub fn new(config: PipelineConfig) -> Self {
+        let mut hart = Hart::new();
+        hart.set_arbitration(config.bus.arbitration());
+        Cpu {
+            hart,
+            pipeline: Pipeline::new(config),
+            stats: Stats::new(),
+            tracers: Vec::new(),
+            tracepoints: Tracepoints::new(),
+            memory_faults: None,
+            register_fault: None,
+            cycle_limit: None,
+            time_limit: None,
+            history: VecDeque::new(),
+            history_size: 0,
+            cycle_profile: None,
+        }
+    }
+
+    pub fn hart(&self) -> &Hart {
+        &self.hart
+    }
+
+    pub fn hart_mut(&mut self) -> &mut Hart {
+        &mut self.hart
+    }
+
+    pub fn pipeline_config(&self) -> &PipelineConfig {
+        self.pipeline.config()
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Report the pipeline activity of every following cycle to a tracer
+    pub fn add_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracers.push(tracer);
+    }
+
+    /// Addresses where values are logged as the program reaches them
+    pub fn tracepoints(&self) -> &Tracepoints {
+        &self.tracepoints
+    }
+
+    pub fn tracepoints_mut(&mut self) -> &mut Tracepoints {
+        &mut self.tracepoints
+    }
+
+    /// Flip bits of the memory while the program runs
+    pub fn set_memory_faults(&mut self, faults: Option<MemoryFaults>) {
+        self.memory_faults = faults;
+    }
+
+    pub fn memory_faults(&self) -> Option<&MemoryFaults> {
+        self.memory_faults.as_ref()
+    }
+
+    /// Flip a bit of a register once the program reaches a cycle
+    ///
+    /// # Arguments
+    /// * `flip` => cycle, register other than x0, and bit below 32
+    pub fn set_register_fault(&mut self, flip: Option<RegisterFlip>) -> Result<(), Error> {
+        if let Some(flip) = flip {
+            if flip.register > 31 {
+                return Err(DecodeError::InvalidRegister(flip.register).into());
+            }
+            if flip.register == 0 || flip.bit > 31 {
+                let context = format!("register fault {}", flip);
+                return Err(Error::runtime(
+                    &context,
+                    "x0 and bits past 31 can't be flipped",
+                ));
+            }
+        }
+        self.register_fault = flip;
+        Ok(())
+    }
+
+    /// Stop the simulation after a number of cycles, which catches programs
+    /// that never finish
+    pub fn set_cycle_limit(&mut self, cycles: Option<u64>) {
+        self.cycle_limit = cycles;
+    }
+
+    /// Stop the simulation when it still runs after some time, counted from
+    /// now, which bounds the time taken by a program that never finishes
+    pub fn set_time_limit(&mut self, limit: Option<Duration>) {
+        self.time_limit = limit.map(|limit| (limit, Deadline::At(Instant::now() + limit)));
+    }
+
+    /// Keep the last retired instructions, to report what led to a crash
+    ///
+    /// # Arguments
+    /// * `entries` => number of instructions kept, none by default
+    pub fn keep_history(&mut self, entries: usize) {
+        self.history_size = entries;
+        while self.history.len() > entries {
+            self.history.pop_front();
+        }
+    }
+
+    /// Last retired instructions, the oldest first
+    pub fn history(&self) -> &VecDeque<TraceEntry> {
+        &self.history
+    }
+
+    /// Enable or disable the profile of the cycles by instruction
+    pub fn set_cycle_profile(&mut self, profile: Option<CycleProfile>) {
+        self.cycle_profile = profile;
+    }
+
+    pub fn cycle_profile(&self) -> Option<&CycleProfile> {
+        self.cycle_profile.as_ref()
+    }
+
+    /// Copy of the processor in its current state, to run it again from
+    /// here. The tracers aren't copied, and the time limit keeps the time
+    /// left, which starts running again with the snapshot.
+    pub fn snapshot(&self) -> Self {
+        Cpu {
+            hart: self.hart.clone(),
+            pipeline: self.pipeline.clone(),
+            stats: self.stats.clone(),
+            tracers: Vec::new(),
+            tracepoints: self.tracepoints.clone(),
+            memory_faults: self.memory_faults.clone(),
+            register_fault: self.register_fault,
+            cycle_limit: self.cycle_limit,
+            time_limit: self
+                .time_limit
+                .map(|(limit, deadline)| (limit, Deadline::After(deadline.remaining()))),
+            history: self.history.clone(),
+            history_size: self.history_size,
+            cycle_profile: self.cycle_profile.clone(),
+        }
+    }
+
+    /// Go back to a snapshot, keeping the tracers, which see the program
+    /// jump to the state of the snapshot
+    pub fn restore(&mut self, snapshot: &Cpu) {
+        let tracers = mem::take(&mut self.tracers);
+        *self = snapshot.snapshot();
+        self.tracers = tracers;
+        self.time_limit = self
+            .time_limit
+            .map(|(limit, deadline)| (limit, deadline.start()));
+    }
+
+    /// Start executing from `pc`. Instructions in flight are discarded.
+    pub fn set_pc(&mut self, pc: u32) {
+        self.hart.set_pc(pc);
+        self.pipeline.reset(pc);
+    }
+
+    /// Simulate until the program stops, keeping what it produced
+    pub fn run_to_result(&mut self) -> RunResult {
+        let reason = self.run();
+        RunResult {
+            reason,
+            output: self.hart.output().to_vec(),
+            cycles: self.stats.cycles,
+        }
+    }
+
+    /// Simulate until the program stops
+    ///
+    /// # Return Value
+    /// The reason why the simulation stopped
+    pub fn run(&mut self) -> StopReason {
+        let _run = info_span!("run").entered();
+        loop {
+            if let Err(reason) = self.simulate_cycle() {
+                return reason;
+            }
+        }
+    }
+
+    /// Simulate until the program stops without blocking an async runtime,
+    /// such as tokio or async-std, which keeps serving the other tasks of a
+    /// web or GUI front end in the meantime
+    ///
+    /// # Arguments
+    /// * `instructions` => instructions retired before yielding to the
+    ///   runtime, more than 0
+    ///
+    /// # Return Value
+    /// A future of the reason why the simulation stopped, or an error if it
+    /// would never yield
+    pub fn run_async(&mut self, instructions: u64) -> Result<RunAsync<'_>, Error> {
+        if instructions == 0 {
+            return Err(Error::runtime("async run", "yielding every 0 instructions"));
+        }
+        Ok(RunAsync {
+            cpu: self,
+            instructions,
+        })
+    }
+
+    /// Simulate up to a cycle, unless the program stops before
+    ///
+    /// # Arguments
+    /// * `cycle` => last cycle simulated
+    pub fn run_until_cycle(&mut self, cycle: u64) -> Result<(), StopReason> {
+        while self.stats.cycles < cycle {
+            self.simulate_cycle()?;
+        }
+        Ok(())
+    }
+
+    /// Simulate a number of cycles under the control of another simulator,
+    /// such as a SystemC or Verilator testbench which owns the clock. The
+    /// devices, the interrupts and the limits advance as in `run`, so
+    /// ticking the whole run in any steps gives the same result.
+    ///
+    /// # Arguments
+    /// * `cycles` => number of cycles simulated
+    ///
+    /// # Return Value
+    /// The reason why the simulation stopped before the last cycle, if it
+    /// did
+    pub fn tick(&mut self, cycles: u64) -> Result<(), StopReason> {
+        for _ in 0..cycles {
+            self.simulate_cycle()?;
+        }
+        Ok(())
+    }
+
+    /// Simulate one cycle, for testbenches which check the state of the
+    /// pipeline after every cycle
+    ///
+    /// # Return Value
+    /// The stage contents, the register write and the bus transactions of
+    /// the cycle, or the reason why the simulation stopped
+    pub fn step_cycle(&mut self) -> Result<&CycleRecord, StopReason> {
+        self.simulate_cycle()?;
+        Ok(self.pipeline.last_cycle())
+    }
+
+    /// Simulate until the next instruction retires, unless the program stops
+    /// before
+    pub fn step(&mut self) -> Result<(), StopReason> {
+        let instructions = self.stats.instructions;
+        while self.stats.instructions == instructions {
+            self.simulate_cycle()?;
+        }
+        Ok(())
+    }
+
+    /// Stage contents, register write and bus transactions of the last
+    /// simulated cycle
+    pub fn last_cycle(&self) -> &CycleRecord {
+        self.pipeline.last_cycle()
+    }
+
+    // Inject the faults of the next cycle, simulate it and report it to the
+    // tracers
+    fn simulate_cycle(&mut self) -> Result<(), StopReason> {
+        let cycle = self.stats.cycles + 1;
+        let _step = trace_span!("step", cycle).entered();
+        if let Some(limit) = self.cycle_limit {
+            if cycle > limit {
+                return Err(StopReason::CycleLimit(self.stats.cycles));
+            }
+        }
+        if let Some((limit, ref mut deadline)) = self.time_limit {
+            // A snapshot run as it is starts its deadline here
+            if let Deadline::After(_) = *deadline {
+                *deadline = deadline.start();
+            }
+            if cycle & (CLOCK_INTERVAL - 1) == 0 && deadline.remaining() == Duration::from_secs(0) {
+                return Err(StopReason::TimeLimit(limit));
+            }
+        }
+        if let Some(ref mut faults) = self.memory_faults {
+            faults.tick(cycle, self.hart.memory_mut());
+        }
+        match self.register_fault {
+            Some(flip) if flip.cycle <= cycle => {
+                let (value, _) = self.hart.registers().read(flip.register, 0);
+                self.hart
+                    .registers_mut()
+                    .write(flip.register, value ^ 1 << flip.bit);
+                self.register_fault = None;
+            }
+            _ => (),
+        }
+        let ticked = self.pipeline.tick(&mut self.hart, &mut self.stats);
+        if self.history_size > 0 {
+            let record = self.pipeline.last_cycle();
+            for entry in record.retired.iter().chain(&record.paired) {
+                if self.history.len() == self.history_size {
+                    self.history.pop_front();
+                }
+                self.history.push_back(*entry);
+            }
+        }
+        ticked?;
+        if let Some(ref mut profile) = self.cycle_profile {
+            profile.record(self.pipeline.last_cycle());
+        }
+        if !self.tracepoints.is_empty() && self.pipeline.last_cycle().retired.is_some() {
+            self.tracepoints.reach(&self.hart);
+        }
+        for tracer in &mut self.tracers {
+            if let Err(e) = tracer.cycle(self.pipeline.last_cycle()) {
+                return Err(StopReason::TraceError(e.kind()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Simulation of a processor until its program stops, which yields to the
+/// runtime every few instructions
+pub struct RunAsync<'a> {
+    cpu: &'a mut Cpu,
+    instructions: u64,
+}
+
+impl<'a> Future for RunAsync<'a> {
+    type Output = StopReason;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<StopReason> {
+        let run = &mut *self;
+        for _ in 0..run.instructions {
+            if let Err(reason) = run.cpu.step() {
+                return Poll::Ready(reason);
+            }
+        }
+        // Nothing else wakes the task up
+        context.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csr::MEPC;
+    use elf::Symbol;
+    use fault::{BitFlip, Outcome, DEFAULT_SEED};
+    use mem::MemStoreOp;
+    use pipeline::Stages;
+    use profile::ProfileSort;
+    use riscv::isa::RV32I;
+    use std::io;
+    use std::ptr;
+    use std::sync::{Arc, Mutex};
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+    use trace::{BusKind, BusTransaction};
+    use tracepoint::TracepointSpec;
+
+    // Keeps a copy of every record
+    struct Recorder(Arc<Mutex<Vec<CycleRecord>>>);
+
+    impl Tracer for Recorder {
+        fn cycle(&mut self, record: &CycleRecord) -> io::Result<()> {
+            self.0.lock().unwrap().push(record.clone());
+            Ok(())
+        }
+    }
+
+    struct Failing;
+
+    impl Tracer for Failing {
+        fn cycle(&mut self, _: &CycleRecord) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::WriteZero, "full"))
+        }
+    }
+
+    // Sum the numbers from 1 to 10 into a0
+    const LOOP: [u32; 5] = [
+        0x0000_0513, // li a0, 0
+        0x00a0_0593, // li a1, 10
+        0x00b5_0533, // loop: add a0, a0, a1
+        0xfff5_8593, // addi a1, a1, -1
+        0xfe05_9ce3, // bnez a1, loop
+    ];
+
+    fn cpu_with_loop(stages: Stages) -> Cpu {
+        let mut cpu = Cpu::new(PipelineConfig {
+            stages,
+            ..PipelineConfig::default()
+        });
+        for (index, instruction) in LOOP.iter().enumerate() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    0x200 + ((index as u32) << 2),
+                    *instruction,
+                )
+                .unwrap();
+        }
+        cpu.set_pc(0x200);
+        cpu
+    }
+
+    #[test]
+    fn test_run_one_stage() {
+        let mut cpu = cpu_with_loop(Stages::One);
+
+        assert_eq!(
+            StopReason::InvalidInstruction { pc: 0x214, raw: 0 },
+            cpu.run()
+        );
+        assert_eq!((55, 0), cpu.hart().registers().read(10, 11));
+        assert_eq!(32, cpu.stats().instructions);
+        assert_eq!(32, cpu.stats().cycles);
+    }
+
+    #[test]
+    fn test_run_three_stages() {
+        let mut cpu = cpu_with_loop(Stages::Three);
+
+        cpu.run();
+        assert_eq!((55, 0), cpu.hart().registers().read(10, 11));
+        assert_eq!(32, cpu.stats().instructions);
+        // Fill the pipeline, then two flush cycles for each of the nine taken
+        // branches
+        assert_eq!(32 + 2 + 9 * 2, cpu.stats().cycles);
+    }
+
+    #[test]
+    fn test_timer_interrupts() {
+        // A tick every 100 instructions, printed by the handler on the
+        // serial port, with the idle task waiting for interrupts. The third
+        // tick stops the simulation.
+        let kernel = [
+            0x0000_0297, // auipc t0, 0
+            0x0302_8293, // addi t0, t0, trap
+            0x3052_9073, // csrw mtvec, t0
+            0x0200_4537, // lui a0, 0x2004
+            0x0640_0313, // li t1, 100
+            0x0065_2023, // sw t1, 0(a0)
+            0x0005_2223, // sw zero, 4(a0)
+            0x0800_0293, // li t0, 0x80
+            0x3042_9073, // csrw mie, t0
+            0x3004_6073, // csrsi mstatus, 8
+            0x1050_0073, // idle: wfi
+            0xffdf_f06f, // j idle
+            0x1000_0e37, // trap: lui t3, 0x10000
+            0x0540_0e93, // li t4, 'T'
+            0x01de_0023, // sb t4, 0(t3)
+            0x0014_0413, // addi s0, s0, 1
+            0x0030_0f13, // li t5, 3
+            0x01e4_0a63, // beq s0, t5, done
+            0x0005_2f83, // lw t6, 0(a0)
+            0x064f_8f93, // addi t6, t6, 100
+            0x01f5_2023, // sw t6, 0(a0)
+            0x3020_0073, // mret
+            0x0010_0073, // done: ebreak
+        ];
+        let mut cpu = Cpu::new(PipelineConfig {
+            stages: Stages::Three,
+            ..PipelineConfig::default()
+        });
+        for (index, instruction) in kernel.iter().enumerate() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    (index as u32) << 2,
+                    *instruction,
+                )
+                .unwrap();
+        }
+        cpu.set_pc(0);
+
+        assert_eq!(StopReason::Breakpoint { pc: 0x58 }, cpu.run());
+        assert_eq!(b"TTT", cpu.hart().output());
+        assert_eq!((3, 0), cpu.hart().registers().read(8, 0));
+        // Interrupted in the idle loop
+        let mepc = cpu.hart().csrs().read(MEPC).unwrap();
+        assert!(mepc == 0x28 || mepc == 0x2c);
+        assert!(cpu.hart().clint().mtime() >= 300);
+    }
+
+    #[test]
+    fn test_memory_faults() {
+        let mut cpu = cpu_with_loop(Stages::One);
+        let mut faults = MemoryFaults::new(DEFAULT_SEED);
+        // li a1, 10 becomes li a1, 8 before it is fetched
+        faults.add_flip(BitFlip {
+            cycle: 1,
+            addr: 0x206,
+            bit: 5,
+        });
+        cpu.set_memory_faults(Some(faults));
+
+        cpu.run();
+        assert_eq!((36, 0), cpu.hart().registers().read(10, 11));
+        assert_eq!(26, cpu.stats().instructions);
+        assert_eq!(1, cpu.memory_faults().unwrap().injected().len());
+    }
+
+    #[test]
+    fn test_register_fault() {
+        let mut golden = cpu_with_loop(Stages::Three);
+        let golden = golden.run_to_result();
+
+        // a1 counts down from 14 instead of 10 after the first addition
+        let mut cpu = cpu_with_loop(Stages::One);
+        cpu.set_register_fault(Some(RegisterFlip {
+            cycle: 4,
+            register: 11,
+            bit: 2,
+        }))
+        .unwrap();
+        let result = cpu.run_to_result();
+        assert_eq!(golden.reason, result.reason);
+        assert_eq!((10 + 13 * 14 / 2, 0), cpu.hart().registers().read(10, 11));
+
+        // Counting down from 1 << 30 takes too long
+        let mut cpu = cpu_with_loop(Stages::One);
+        cpu.set_register_fault(Some(RegisterFlip {
+            cycle: 3,
+            register: 11,
+            bit: 30,
+        }))
+        .unwrap();
+        cpu.set_cycle_limit(Some(1000));
+        assert_eq!(StopReason::CycleLimit(1000), cpu.run());
+        assert_eq!(
+            Outcome::Hang,
+            Outcome::classify(&golden, &cpu.run_to_result())
+        );
+
+        // x0, registers past x31 and bits past 31 can't be flipped
+        for &(register, bit) in &[(0, 0), (1, 32)] {
+            let flip = RegisterFlip {
+                cycle: 1,
+                register,
+                bit,
+            };
+            assert!(cpu.set_register_fault(Some(flip)).is_err());
+        }
+        assert_eq!(
+            "invalid register x32",
+            cpu.set_register_fault(Some(RegisterFlip {
+                cycle: 1,
+                register: 32,
+                bit: 0,
+            }))
+            .unwrap_err()
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_time_limit() {
+        // j .
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        cpu.hart_mut()
+            .memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SW), 0x100, 0x0000_006f)
+            .unwrap();
+        cpu.set_pc(0x100);
+        cpu.set_time_limit(Some(Duration::from_millis(10)));
+        let reason = cpu.run();
+        assert_eq!(StopReason::TimeLimit(Duration::from_millis(10)), reason);
+        assert!(reason.is_timeout());
+        // The clock is read before the cycle which would end the interval
+        assert_eq!(CLOCK_INTERVAL - 1, cpu.stats().cycles % CLOCK_INTERVAL);
+    }
+
+    #[test]
+    fn test_snapshot_time_limit() {
+        // j .
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        cpu.hart_mut()
+            .memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SW), 0x100, 0x0000_006f)
+            .unwrap();
+        cpu.set_pc(0x100);
+        cpu.set_time_limit(Some(Duration::from_millis(100)));
+        let snapshot = cpu.snapshot();
+        // The time limit doesn't run while the snapshot is kept
+        std::thread::sleep(Duration::from_millis(150));
+        cpu.restore(&snapshot);
+        cpu.set_cycle_limit(Some(4 * CLOCK_INTERVAL));
+        assert_eq!(StopReason::CycleLimit(4 * CLOCK_INTERVAL), cpu.run());
+    }
+
+    #[test]
+    fn test_tracepoints() {
+        let mut cpu = cpu_with_loop(Stages::Three);
+        let spec = TracepointSpec::parse("0x208: a0, a1").unwrap();
+        let tracepoint = spec.resolve(cpu.hart(), &[]).unwrap();
+        cpu.tracepoints_mut().add(tracepoint);
+        cpu.run();
+        // Once for every iteration of the loop
+        assert_eq!(10, cpu.tracepoints().points()[0].hits);
+    }
+
+    #[test]
+    fn test_tracers() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let mut cpu = cpu_with_loop(Stages::Three);
+        cpu.add_tracer(Box::new(Recorder(records.clone())));
+        cpu.run();
+
+        let records = records.lock().unwrap();
+        assert_eq!(cpu.stats().cycles as usize, records.len());
+        assert_eq!(1, records[0].cycle);
+        assert_eq!(
+            32,
+            records
+                .iter()
+                .filter(|record| record.retired.is_some())
+                .count()
+        );
+
+        let mut cpu = cpu_with_loop(Stages::Three);
+        cpu.add_tracer(Box::new(Failing));
+        assert_eq!(StopReason::TraceError(io::ErrorKind::WriteZero), cpu.run());
+        assert_eq!(1, cpu.stats().cycles);
+    }
+
+    #[test]
+    fn test_cycle_profile() {
+        let mut cpu = cpu_with_loop(Stages::Three);
+        cpu.set_cycle_profile(Some(CycleProfile::new()));
+        cpu.run();
+
+        // Every cycle and every stall is charged to the loop
+        let functions = [Symbol {
+            name: "loop".to_string(),
+            value: 0x200,
+            size: 0x14,
+        }];
+        let profile = cpu.cycle_profile().unwrap();
+        let totals = profile.functions(&functions, ProfileSort::Cycles);
+        assert_eq!(1, totals.len());
+        assert_eq!(cpu.stats().cycles, totals[0].1.cycles);
+        assert_eq!(cpu.stats().stall_cycles, totals[0].1.stalls);
+    }
+
+    #[test]
+    fn test_tick() {
+        let mut run = cpu_with_loop(Stages::Three);
+        let reason = run.run();
+
+        let mut cpu = cpu_with_loop(Stages::Three);
+        cpu.tick(0).unwrap();
+        assert_eq!(0, cpu.stats().cycles);
+        for cycles in 1..6 {
+            let before = cpu.stats().cycles;
+            cpu.tick(cycles).unwrap();
+            assert_eq!(before + cycles, cpu.stats().cycles);
+        }
+        // The program stops in the middle of the last steps
+        let mut stopped = Ok(());
+        while stopped.is_ok() {
+            stopped = cpu.tick(7);
+        }
+        assert_eq!(Err(reason), stopped);
+        assert_eq!(run.stats().cycles, cpu.stats().cycles);
+        assert_eq!(
+            run.hart().registers().read(10, 0),
+            cpu.hart().registers().read(10, 0)
+        );
+    }
+
+    #[test]
+    fn test_run_async() {
+        // Wakers which do nothing, polling is up to the test
+        fn waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(ptr::null(), &VTABLE)
+        }
+        let waker = unsafe { Waker::from_raw(waker()) };
+        let mut context = Context::from_waker(&waker);
+
+        let mut cpu = cpu_with_loop(Stages::Three);
+        assert!(cpu.run_async(0).is_err());
+        let mut pending = 0;
+        let reason = {
+            let mut run = cpu.run_async(10).unwrap();
+            loop {
+                match Pin::new(&mut run).poll(&mut context) {
+                    Poll::Ready(reason) => break reason,
+                    Poll::Pending => pending += 1,
+                }
+            }
+        };
+        assert_eq!(StopReason::InvalidInstruction { pc: 0x214, raw: 0 }, reason);
+        // 32 instructions retire before the invalid one
+        assert_eq!(3, pending);
+        assert_eq!(32, cpu.stats().instructions);
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut cpu = cpu_with_loop(Stages::Three);
+        let start = cpu.snapshot();
+        let records = Arc::new(Mutex::new(Vec::new()));
+        cpu.add_tracer(Box::new(Recorder(records.clone())));
+        cpu.run();
+        let cycles = records.lock().unwrap().len();
+
+        cpu.restore(&start);
+        assert_eq!((0, 0), (cpu.stats().cycles, cpu.stats().instructions));
+        cpu.step().unwrap();
+        // The tracer sees the cycles after the restore
+        assert!(records.lock().unwrap().len() > cycles);
+        assert_eq!(0x200, cpu.last_cycle().retired.unwrap().pc);
+    }
+
+    #[test]
+    fn test_step_cycle() {
+        let mut cpu = Cpu::new(PipelineConfig {
+            stages: Stages::One,
+            ..PipelineConfig::default()
+        });
+        // addi a0, zero, 5; sw a0, 64(zero)
+        for (addr, word) in [(0, 0x0050_0513), (4, 0x04a0_2023)].iter() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), *addr, *word)
+                .unwrap();
+        }
+        let fetch = |addr| BusTransaction {
+            kind: BusKind::Fetch,
+            addr,
+            wait_states: 0,
+        };
+
+        let record = cpu.step_cycle().unwrap().clone();
+        assert_eq!(0, record.retired.unwrap().pc);
+        assert_eq!(Some((10, 5)), record.register_write);
+        assert_eq!(vec![fetch(0)], record.bus);
+
+        let record = cpu.step_cycle().unwrap().clone();
+        assert_eq!(None, record.register_write);
+        assert_eq!(
+            vec![
+                fetch(4),
+                BusTransaction {
+                    kind: BusKind::Store,
+                    addr: 64,
+                    wait_states: 0
+                }
+            ],
+            record.bus
+        );
+    }
+}
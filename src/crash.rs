@@ -0,0 +1,264 @@
+//! Crash reports of the simulation. When the program faults, runs into an
+//! illegal instruction or an ebreak nothing handles, or runs out of time, or
+//! the simulator itself panics, the report holds what is needed to
+//! find out why: the cause, the pc, the registers, the last retired
+//! instructions, the backtrace and the memory around the faulting address.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::crash::{self, Crash};
+//! # use adept_lib::mem::MemStoreOp;
+//! # use adept_lib::pipeline::PipelineConfig;
+//! # use adept_lib::riscv::isa::RV32I;
+//! let mut cpu = Cpu::new(PipelineConfig::default());
+//! // j 0, which never ends
+//! cpu.hart_mut()
+//!     .memory_mut()
+//!     .write_data(&MemStoreOp::from(RV32I::SW), 0, 0x0000_006f)
+//!     .unwrap();
+//! cpu.keep_history(8);
+//! cpu.set_cycle_limit(Some(100));
+//! let crash = Crash::of(cpu.run()).unwrap();
+//! let mut report = Vec::new();
+//! crash::write_report(&mut report, &crash, &cpu, &[]).unwrap();
+//! assert!(String::from_utf8(report).unwrap().starts_with("crash: still running"));
+//! ```
+use cpu::Cpu;
+use elf::Symbol;
+use hart::StopReason;
+use mem::{MemLoadOp, Memory};
+use riscv::decoder::Instruction;
+use riscv::labels::get_register_label;
+
+use std::any::Any;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+
+/// Retired instructions kept for a crash report by default
+pub const DEFAULT_HISTORY: usize = 32;
+
+// Bytes of the memory dumped on each side of the faulting address
+const EXCERPT_RADIUS: u32 = 64;
+const BYTES_PER_LINE: u32 = 16;
+
+/// Why the simulation crashed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Crash {
+    /// The simulation stopped before the program exited
+    Stop(StopReason),
+    /// The simulator panicked with the given message
+    Panic(String),
+}
+
+impl Crash {
+    /// The crash of a simulation which stopped on a fault of the program,
+    /// including the illegal instructions and breakpoints it didn't handle,
+    /// or a timeout. The program exiting and the checks of the run failing
+    /// aren't crashes.
+    pub fn of(reason: StopReason) -> Option<Crash> {
+        match reason {
+            StopReason::InvalidInstruction { .. }
+            | StopReason::Breakpoint { .. }
+            | StopReason::GuardFault { .. }
+            | StopReason::ReturnMismatch { .. }
+            | StopReason::UninitializedRead { .. }
+            | StopReason::MemoryFault { .. }
+            | StopReason::CycleLimit(_)
+            | StopReason::TimeLimit(_) => Some(Crash::Stop(reason)),
+            _ => None,
+        }
+    }
+
+    /// The crash of a panic, with the message of its payload
+    pub fn from_panic(payload: &(dyn Any + Send)) -> Crash {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None => String::from("unknown panic"),
+            },
+        };
+        Crash::Panic(message)
+    }
+
+    /// Address of the memory accessed by the faulting instruction, if any
+    pub fn fault_address(&self) -> Option<u32> {
+        match self {
+            Crash::Stop(StopReason::GuardFault { addr, .. })
+            | Crash::Stop(StopReason::UninitializedRead { addr, .. }) => Some(*addr),
+            Crash::Stop(StopReason::MemoryFault { error, .. }) => Some(error.addr),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Crash {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Crash::Stop(reason) => write!(f, "{}", reason),
+            Crash::Panic(message) => write!(f, "internal error: {}", message),
+        }
+    }
+}
+
+/// Write the state of the processor when it crashed
+///
+/// # Arguments
+/// * `out` => destination of the report
+/// * `crash` => cause of the crash
+/// * `cpu` => processor which crashed, holding the last retired instructions
+///   if it kept a history
+/// * `functions` => functions sorted by address, to symbolize the backtrace
+pub fn write_report<W: Write>(
+    out: &mut W,
+    crash: &Crash,
+    cpu: &Cpu,
+    functions: &[Symbol],
+) -> io::Result<()> {
+    let hart = cpu.hart();
+    writeln!(out, "crash: {}", crash)?;
+    writeln!(out, "cycle: {}", cpu.stats().cycles)?;
+    writeln!(out, "pc:    0x{:08x}", hart.pc())?;
+
+    writeln!(out, "\nregisters:")?;
+    for row in 0..8 {
+        let line: Vec<String> = (0..4)
+            .map(|column| {
+                let register = row * 4 + column;
+                let (value, _) = hart.registers().read(register, 0);
+                format!("{:>5} 0x{:08x}", get_register_label(register), value)
+            })
+            .collect();
+        writeln!(out, "{}", line.join("  "))?;
+    }
+
+    writeln!(out, "\nlast {} retired instructions:", cpu.history().len())?;
+    for entry in cpu.history() {
+        writeln!(out, "0x{:08x} {}", entry.pc, Instruction::new(entry.raw))?;
+    }
+
+    write!(
+        out,
+        "\nbacktrace:\n{}",
+        hart.call_stack().backtrace(hart.pc(), functions)
+    )?;
+
+    let addr = crash.fault_address().unwrap_or_else(|| hart.pc());
+    writeln!(out, "\nmemory around 0x{:08x}:", addr)?;
+    let first = addr.wrapping_sub(EXCERPT_RADIUS) & !(BYTES_PER_LINE - 1);
+    write_memory(
+        out,
+        hart.memory(),
+        first,
+        2 * EXCERPT_RADIUS + BYTES_PER_LINE,
+    )
+}
+
+/// Write bytes of the memory in hexadecimal, 16 on each line after their
+/// address. Bytes which can't be read are left blank.
+///
+/// # Arguments
+/// * `out` => destination of the bytes
+/// * `memory` => memory read
+/// * `start` => address of the first byte
+/// * `size` => number of bytes
+pub fn write_memory<W: Write>(
+    out: &mut W,
+    memory: &Memory,
+    start: u32,
+    size: u32,
+) -> io::Result<()> {
+    for line in (0..size).step_by(BYTES_PER_LINE as usize) {
+        let line_start = start.wrapping_add(line);
+        write!(out, "0x{:08x}:", line_start)?;
+        for offset in 0..BYTES_PER_LINE.min(size - line) {
+            match memory.load_data(
+                &MemLoadOp::LoadByteUnsigned,
+                line_start.wrapping_add(offset),
+            ) {
+                Ok(byte) => write!(out, " {:02x}", byte)?,
+                Err(_) => write!(out, " --")?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem::{MemAccess, MemError, MemErrorKind, MemStoreOp};
+    use pipeline::PipelineConfig;
+    use riscv::isa::RV32I;
+    use std::panic;
+
+    #[test]
+    fn test_causes() {
+        assert_eq!(None, Crash::of(StopReason::Exit(1)));
+        assert_eq!(None, Crash::of(StopReason::ConsoleMismatch { pc: 0x100 }));
+        assert_eq!(
+            None,
+            Crash::of(StopReason::TraceError(io::ErrorKind::BrokenPipe))
+        );
+        let error = MemError {
+            kind: MemErrorKind::AccessFault,
+            addr: 0x1234,
+            size: 4,
+            access: MemAccess::Load,
+        };
+        let crash = Crash::of(StopReason::InvalidInstruction { pc: 0, raw: 0 }).unwrap();
+        assert_eq!(None, crash.fault_address());
+        assert!(Crash::of(StopReason::Breakpoint { pc: 0x100 }).is_some());
+        let crash = Crash::of(StopReason::MemoryFault { pc: 0x100, error }).unwrap();
+        assert_eq!(Some(0x1234), crash.fault_address());
+        assert_eq!(
+            None,
+            Crash::of(StopReason::CycleLimit(10))
+                .unwrap()
+                .fault_address()
+        );
+
+        let payload = panic::catch_unwind(|| panic!("invalid {}", "load")).unwrap_err();
+        assert_eq!(
+            "internal error: invalid load",
+            Crash::from_panic(&*payload).to_string()
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        let program = [
+            0x1050_0513, // li a0, 0x105
+            0x0005_2583, // lw a1, 0(a0)
+        ];
+        for (index, word) in program.iter().enumerate() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    0x100 + 4 * index as u32,
+                    *word,
+                )
+                .unwrap();
+        }
+        cpu.keep_history(1);
+        cpu.set_pc(0x100);
+        let crash = Crash::of(cpu.run()).unwrap();
+
+        let mut report = Vec::new();
+        write_report(&mut report, &crash, &cpu, &[]).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(format!("crash: {}", crash), lines[0]);
+        assert_eq!("pc:    0x00000104", lines[2]);
+        assert!(lines[7].contains("   a0 0x00000105"));
+        assert_eq!("last 1 retired instructions:", lines[14]);
+        assert_eq!("0x00000100 addi    a0,zero,261", lines[15]);
+        assert!(report.contains("\nmemory around 0x00000105:\n0x000000c0:"));
+        assert!(report.contains("\n0x00000100: 13 05 50 10 83 25 05 00 00"));
+    }
+}
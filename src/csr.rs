@@ -0,0 +1,424 @@
+//! Machine mode control and status registers. Only the registers needed to
+//! boot and to handle traps are implemented, the remaining addresses are
+//! reported as missing so the instruction accessing them can be rejected.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::csr::{CsrFile, MTVEC, MHARTID};
+//! let mut csrs = CsrFile::new();
+//! assert!(csrs.write(MTVEC, 0x100));
+//! assert_eq!(Some(0x100), csrs.read(MTVEC));
+//! // Read only registers can't be written
+//! assert!(!csrs.write(MHARTID, 1));
+//! ```
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use trigger::Triggers;
+
+/// Machine status
+pub const MSTATUS: u16 = 0x300;
+/// Supported ISA and extensions
+pub const MISA: u16 = 0x301;
+/// Machine interrupt enable
+pub const MIE: u16 = 0x304;
+/// Machine trap vector base address
+pub const MTVEC: u16 = 0x305;
+/// Scratch register for machine trap handlers
+pub const MSCRATCH: u16 = 0x340;
+/// Machine exception program counter
+pub const MEPC: u16 = 0x341;
+/// Machine trap cause
+pub const MCAUSE: u16 = 0x342;
+/// Machine bad address or instruction
+pub const MTVAL: u16 = 0x343;
+/// Machine interrupt pending
+pub const MIP: u16 = 0x344;
+/// Machine cycle counter
+pub const MCYCLE: u16 = 0xb00;
+/// Machine instructions retired counter
+pub const MINSTRET: u16 = 0xb02;
+/// Upper 32 bits of mcycle
+pub const MCYCLEH: u16 = 0xb80;
+/// Upper 32 bits of minstret
+pub const MINSTRETH: u16 = 0xb82;
+/// Cycle counter for user mode
+pub const CYCLE: u16 = 0xc00;
+/// Timer for user mode
+pub const TIME: u16 = 0xc01;
+/// Instructions retired counter for user mode
+pub const INSTRET: u16 = 0xc02;
+/// Upper 32 bits of cycle
+pub const CYCLEH: u16 = 0xc80;
+/// Upper 32 bits of time
+pub const TIMEH: u16 = 0xc81;
+/// Upper 32 bits of instret
+pub const INSTRETH: u16 = 0xc82;
+/// Vendor ID
+pub const MVENDORID: u16 = 0xf11;
+/// Architecture ID
+pub const MARCHID: u16 = 0xf12;
+/// Implementation ID
+pub const MIMPID: u16 = 0xf13;
+/// Hardware thread ID
+pub const MHARTID: u16 = 0xf14;
+
+/// Exception code of mcause for fetches of a misaligned pc
+pub const CAUSE_MISALIGNED_FETCH: u32 = 0;
+/// Exception code of mcause for fetches from a faulting address
+pub const CAUSE_FETCH_ACCESS: u32 = 1;
+/// Exception code of mcause for illegal instructions
+pub const CAUSE_ILLEGAL_INSTRUCTION: u32 = 2;
+/// Exception code of mcause for breakpoints
+pub const CAUSE_BREAKPOINT: u32 = 3;
+/// Exception code of mcause for misaligned loads
+pub const CAUSE_MISALIGNED_LOAD: u32 = 4;
+/// Exception code of mcause for loads from a faulting address
+pub const CAUSE_LOAD_ACCESS: u32 = 5;
+/// Exception code of mcause for misaligned stores
+pub const CAUSE_MISALIGNED_STORE: u32 = 6;
+/// Exception code of mcause for stores to a faulting address
+pub const CAUSE_STORE_ACCESS: u32 = 7;
+/// Exception code of mcause for environment calls from machine mode
+pub const CAUSE_MACHINE_ECALL: u32 = 11;
+/// Set in mcause when the trap is an interrupt
+pub const CAUSE_INTERRUPT: u32 = 1 << 31;
+/// Interrupt code of the machine software interrupt
+pub const INTERRUPT_MACHINE_SOFTWARE: u32 = 3;
+/// Interrupt code of the machine timer interrupt
+pub const INTERRUPT_MACHINE_TIMER: u32 = 7;
+/// Interrupt code of the machine external interrupt
+pub const INTERRUPT_MACHINE_EXTERNAL: u32 = 11;
+
+// RV32 with the I base ISA
+const MISA_VALUE: u32 = 0x4000_0100;
+// MIE, MPIE and MPP
+const MSTATUS_MASK: u32 = 0x0000_1888;
+// Fields of mstatus saving the state before a trap
+const MSTATUS_MIE: u32 = 1 << 3;
+const MSTATUS_MPIE: u32 = 1 << 7;
+const MSTATUS_MPP: u32 = 3 << 11;
+// Software, timer and external interrupts
+const INTERRUPT_MASK: u32 = 0x0000_0888;
+
+// Interrupts in decreasing priority
+const INTERRUPT_PRIORITY: [u32; 3] = [
+    INTERRUPT_MACHINE_EXTERNAL,
+    INTERRUPT_MACHINE_SOFTWARE,
+    INTERRUPT_MACHINE_TIMER,
+];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CsrFile {
+    mstatus: u32,
+    mie: u32,
+    mtvec: u32,
+    mscratch: u32,
+    mepc: u32,
+    mcause: u32,
+    mtval: u32,
+    mip: u32,
+    mhartid: u32,
+    // Counted by the pipeline model, or once per instruction without it
+    mcycle: u64,
+    minstret: u64,
+    time: u64,
+    triggers: Triggers,
+    // Interrupts taken, by code
+    interrupts_taken: [u64; 12],
+}
+
+impl CsrFile {
+    /// Create the registers of hart 0 with their reset values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a register
+    ///
+    /// # Arguments
+    /// * `csr` => address of the register
+    ///
+    /// # Return Value
+    /// The value of the register, or None if it isn't implemented
+    pub fn read(&self, csr: u16) -> Option<u32> {
+        match csr {
+            MSTATUS => Some(self.mstatus),
+            MISA => Some(MISA_VALUE),
+            MIE => Some(self.mie),
+            MTVEC => Some(self.mtvec),
+            MSCRATCH => Some(self.mscratch),
+            MEPC => Some(self.mepc),
+            MCAUSE => Some(self.mcause),
+            MTVAL => Some(self.mtval),
+            MIP => Some(self.mip),
+            MCYCLE | CYCLE => Some(self.mcycle as u32),
+            MCYCLEH | CYCLEH => Some((self.mcycle >> 32) as u32),
+            MINSTRET | INSTRET => Some(self.minstret as u32),
+            MINSTRETH | INSTRETH => Some((self.minstret >> 32) as u32),
+            TIME => Some(self.time as u32),
+            TIMEH => Some((self.time >> 32) as u32),
+            MVENDORID | MARCHID | MIMPID => Some(0),
+            MHARTID => Some(self.mhartid),
+            _ => self.triggers.read(csr),
+        }
+    }
+
+    /// Write a register. Bits which are not writable keep their value.
+    ///
+    /// # Arguments
+    /// * `csr` => address of the register
+    /// * `value` => new value
+    ///
+    /// # Return Value
+    /// False if the register isn't implemented or is read only
+    pub fn write(&mut self, csr: u16, value: u32) -> bool {
+        match csr {
+            MSTATUS => self.mstatus = value & MSTATUS_MASK,
+            // Only RV32I is supported
+            MISA => (),
+            MIE => self.mie = value & INTERRUPT_MASK,
+            // Direct and vectored modes
+            MTVEC => self.mtvec = value & !2,
+            MSCRATCH => self.mscratch = value,
+            MEPC => self.mepc = value & !3,
+            MCAUSE => self.mcause = value,
+            MTVAL => self.mtval = value,
+            // Pending interrupts are set by the devices
+            MIP => (),
+            MCYCLE => self.mcycle = self.mcycle & !0xffff_ffff | u64::from(value),
+            MCYCLEH => self.mcycle = self.mcycle & 0xffff_ffff | u64::from(value) << 32,
+            MINSTRET => self.minstret = self.minstret & !0xffff_ffff | u64::from(value),
+            MINSTRETH => self.minstret = self.minstret & 0xffff_ffff | u64::from(value) << 32,
+            _ => return self.triggers.write(csr, value),
+        }
+        true
+    }
+
+    /// Enter the trap handler for a synchronous exception, saving the pc
+    /// and the interrupt enable and staying in machine mode
+    ///
+    /// # Arguments
+    /// * `pc` => address of the instruction which caused the exception
+    /// * `cause` => exception code
+    /// * `tval` => faulting address or instruction bits, or 0
+    ///
+    /// # Return Value
+    /// The address of the trap handler
+    pub fn trap(&mut self, pc: u32, cause: u32, tval: u32) -> u32 {
+        self.enter_trap(pc, cause, tval);
+        // Exceptions use the base address in both modes
+        self.mtvec & !3
+    }
+
+    /// Enter the trap handler for an interrupt
+    ///
+    /// # Arguments
+    /// * `pc` => address of the instruction which didn't execute yet
+    /// * `code` => interrupt code, without the interrupt bit
+    ///
+    /// # Return Value
+    /// The address of the trap handler, which depends on the code in
+    /// vectored mode
+    pub fn interrupt(&mut self, pc: u32, code: u32) -> u32 {
+        self.enter_trap(pc, CAUSE_INTERRUPT | code, 0);
+        self.interrupts_taken[code as usize] += 1;
+        if self.mtvec & 1 != 0 {
+            (self.mtvec & !3).wrapping_add(code << 2)
+        } else {
+            self.mtvec & !3
+        }
+    }
+
+    /// Number of interrupts taken with a code, such as
+    /// INTERRUPT_MACHINE_TIMER
+    pub fn interrupts_taken(&self, code: u32) -> u64 {
+        self.interrupts_taken
+            .get(code as usize)
+            .map_or(0, |taken| *taken)
+    }
+
+    /// Return from a trap handler, restoring the interrupt enable
+    ///
+    /// # Return Value
+    /// The address to return to
+    pub fn mret(&mut self) -> u32 {
+        let mie = if self.mstatus & MSTATUS_MPIE != 0 {
+            MSTATUS_MIE
+        } else {
+            0
+        };
+        // Machine mode is the only privilege mode, so it stays in mpp
+        self.mstatus = self.mstatus & !MSTATUS_MIE | mie | MSTATUS_MPIE;
+        self.mepc
+    }
+
+    /// Highest priority interrupt which is pending and enabled
+    ///
+    /// # Return Value
+    /// The interrupt code, or None if no interrupt must be taken
+    pub fn pending_interrupt(&self) -> Option<u32> {
+        if self.mstatus & MSTATUS_MIE == 0 {
+            return None;
+        }
+        let pending = self.mip & self.mie;
+        INTERRUPT_PRIORITY
+            .iter()
+            .cloned()
+            .find(|code| pending & (1 << code) != 0)
+    }
+
+    /// Set or clear the pending bit of an interrupt, for the devices
+    /// raising it
+    pub fn set_pending(&mut self, code: u32, pending: bool) {
+        if pending {
+            self.mip |= 1 << code;
+        } else {
+            self.mip &= !(1 << code);
+        }
+    }
+
+    /// Hardware breakpoints and watchpoints of the debug spec
+    pub fn triggers(&self) -> &Triggers {
+        &self.triggers
+    }
+
+    pub fn triggers_mut(&mut self) -> &mut Triggers {
+        &mut self.triggers
+    }
+
+    /// Count a retired instruction
+    pub fn retire(&mut self) {
+        self.minstret = self.minstret.wrapping_add(1);
+    }
+
+    /// Cycles counted by mcycle
+    pub fn cycles(&self) -> u64 {
+        self.mcycle
+    }
+
+    /// Count a cycle
+    pub fn tick(&mut self) {
+        self.mcycle = self.mcycle.wrapping_add(1);
+    }
+
+    /// Set the value of the time register, a copy of mtime
+    pub fn set_time(&mut self, time: u64) {
+        self.time = time;
+    }
+
+    // Save the pc and the interrupt enable, staying in machine mode
+    fn enter_trap(&mut self, pc: u32, cause: u32, tval: u32) {
+        self.mepc = pc & !3;
+        self.mcause = cause;
+        self.mtval = tval;
+        let mpie = if self.mstatus & MSTATUS_MIE != 0 {
+            MSTATUS_MPIE
+        } else {
+            0
+        };
+        self.mstatus = self.mstatus & !(MSTATUS_MIE | MSTATUS_MPIE) | mpie | MSTATUS_MPP;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_values() {
+        let csrs = CsrFile::new();
+        assert_eq!(Some(0), csrs.read(MSTATUS));
+        assert_eq!(Some(0x4000_0100), csrs.read(MISA));
+        assert_eq!(Some(0), csrs.read(MHARTID));
+        assert_eq!(None, csrs.read(0x7c0));
+    }
+
+    #[test]
+    fn test_write_masks() {
+        let mut csrs = CsrFile::new();
+        assert!(csrs.write(MSTATUS, 0xffff_ffff));
+        assert_eq!(Some(0x1888), csrs.read(MSTATUS));
+        assert!(csrs.write(MTVEC, 0x0000_0103));
+        assert_eq!(Some(0x101), csrs.read(MTVEC));
+        assert!(csrs.write(MEPC, 0x0000_0103));
+        assert_eq!(Some(0x100), csrs.read(MEPC));
+        // Writes to misa are ignored
+        assert!(csrs.write(MISA, 0));
+        assert_eq!(Some(0x4000_0100), csrs.read(MISA));
+    }
+
+    #[test]
+    fn test_trap() {
+        let mut csrs = CsrFile::new();
+        csrs.write(MTVEC, 0x201);
+        csrs.write(MSTATUS, MSTATUS_MIE);
+        assert_eq!(
+            0x200,
+            csrs.trap(0x104, CAUSE_ILLEGAL_INSTRUCTION, 0xffff_ffff)
+        );
+        assert_eq!(Some(0x104), csrs.read(MEPC));
+        assert_eq!(Some(2), csrs.read(MCAUSE));
+        assert_eq!(Some(0xffff_ffff), csrs.read(MTVAL));
+        assert_eq!(Some(MSTATUS_MPIE | MSTATUS_MPP), csrs.read(MSTATUS));
+    }
+
+    #[test]
+    fn test_interrupts() {
+        let mut csrs = CsrFile::new();
+        csrs.write(MTVEC, 0x201);
+        csrs.write(
+            MIE,
+            1 << INTERRUPT_MACHINE_TIMER | 1 << INTERRUPT_MACHINE_SOFTWARE,
+        );
+        csrs.set_pending(INTERRUPT_MACHINE_TIMER, true);
+        // Interrupts are globally disabled
+        assert_eq!(None, csrs.pending_interrupt());
+        csrs.write(MSTATUS, MSTATUS_MIE);
+        assert_eq!(Some(INTERRUPT_MACHINE_TIMER), csrs.pending_interrupt());
+        csrs.set_pending(INTERRUPT_MACHINE_SOFTWARE, true);
+        assert_eq!(Some(INTERRUPT_MACHINE_SOFTWARE), csrs.pending_interrupt());
+        // Software writes to mip are ignored
+        assert!(csrs.write(MIP, 0));
+        assert_eq!(Some(0x88), csrs.read(MIP));
+
+        // Vectored mode
+        assert_eq!(0x20c, csrs.interrupt(0x104, INTERRUPT_MACHINE_SOFTWARE));
+        assert_eq!(Some(0x8000_0003), csrs.read(MCAUSE));
+        assert_eq!(1, csrs.interrupts_taken(INTERRUPT_MACHINE_SOFTWARE));
+        assert_eq!(0, csrs.interrupts_taken(INTERRUPT_MACHINE_TIMER));
+        assert_eq!(None, csrs.pending_interrupt());
+        assert_eq!(0x104, csrs.mret());
+        assert_eq!(
+            Some(MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP),
+            csrs.read(MSTATUS)
+        );
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut csrs = CsrFile::new();
+        assert!(csrs.write(MINSTRET, 0xffff_ffff));
+        csrs.retire();
+        assert_eq!(Some(0), csrs.read(INSTRET));
+        assert_eq!(Some(1), csrs.read(MINSTRETH));
+        assert_eq!(Some(0), csrs.read(CYCLE));
+        csrs.tick();
+        assert_eq!(Some(1), csrs.read(CYCLE));
+        csrs.set_time(0x1_0000_0002);
+        assert_eq!(Some(2), csrs.read(TIME));
+        assert_eq!(Some(1), csrs.read(TIMEH));
+        // The user mode counters are read only
+        assert!(!csrs.write(TIME, 0));
+        assert!(!csrs.write(CYCLE, 0));
+    }
+
+    #[test]
+    fn test_invalid_writes() {
+        let mut csrs = CsrFile::new();
+        assert!(!csrs.write(MHARTID, 1));
+        assert!(!csrs.write(MVENDORID, 1));
+        assert!(!csrs.write(0x7c0, 1));
+    }
+}
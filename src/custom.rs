@@ -0,0 +1,156 @@
+//! Custom instructions, decoded and executed by a plugin instead of the core
+//! decoder. The plugin is asked about every instruction the core decoder
+//! reports invalid, which covers the custom and reserved opcode spaces as well
+//! as the encodings of unsupported extensions, so that experiments with new
+//! instructions don't have to touch the decode table.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::custom::{CustomExtension, CustomInstruction};
+//! # use adept_lib::hart::Hart;
+//! # use adept_lib::latency::InstrClass;
+//! # use adept_lib::mem::{MemStoreOp, Memory};
+//! # use adept_lib::riscv::isa::RV32I;
+//! // Unsigned maximum in the custom-0 opcode space
+//! #[derive(Clone)]
+//! struct MaxU;
+//!
+//! impl CustomExtension for MaxU {
+//!     fn decode(&self, raw: u32) -> Option<CustomInstruction> {
+//!         if raw & 0x7f != 0x0b {
+//!             return None;
+//!         }
+//!         let mut instr = CustomInstruction::new(0, "maxu", InstrClass::Alu);
+//!         instr.rd = Some((raw >> 7 & 0x1f) as u8);
+//!         instr.rs1 = Some((raw >> 15 & 0x1f) as u8);
+//!         instr.rs2 = Some((raw >> 20 & 0x1f) as u8);
+//!         Some(instr)
+//!     }
+//!
+//!     fn execute(&mut self, _: &CustomInstruction, _: u32, rs1: i32, rs2: i32, _: &mut Memory) -> Option<i32> {
+//!         Some((rs1 as u32).max(rs2 as u32) as i32)
+//!     }
+//!
+//!     fn box_clone(&self) -> Box<dyn CustomExtension> {
+//!         Box::new(self.clone())
+//!     }
+//! }
+//!
+//! let mut hart = Hart::new();
+//! hart.set_custom_extension(Some(Box::new(MaxU)));
+//! hart.registers_mut().write(11, -1);
+//! // maxu a0, zero, a1
+//! hart.memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 0, 0x00b0_050b).unwrap();
+//! hart.execute(0, hart.memory().read_pc(0)).unwrap();
+//! assert_eq!((-1, 0), hart.registers().read(10, 0));
+//! ```
+use latency::InstrClass;
+use mem::Memory;
+
+use std::fmt::{self, Display, Formatter};
+
+/// Instruction decoded by a custom extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CustomInstruction {
+    /// Number chosen by the extension to tell its instructions apart
+    pub id: u32,
+    /// Name of the instruction in traces
+    pub name: &'static str,
+    /// Class deciding the latency of the instruction in the pipeline model
+    pub class: InstrClass,
+    /// Destination register
+    pub rd: Option<u8>,
+    /// Register sources, read before the instruction executes
+    pub rs1: Option<u8>,
+    pub rs2: Option<u8>,
+}
+
+impl CustomInstruction {
+    /// Create an instruction without registers
+    ///
+    /// # Arguments
+    /// * `id` => number of the instruction within its extension
+    /// * `name` => name of the instruction
+    /// * `class` => class of the instruction
+    pub fn new(id: u32, name: &'static str, class: InstrClass) -> Self {
+        CustomInstruction {
+            id,
+            name,
+            class,
+            rd: None,
+            rs1: None,
+            rs2: None,
+        }
+    }
+}
+
+impl Display for CustomInstruction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        let registers: Vec<_> = [self.rd, self.rs1, self.rs2]
+            .iter()
+            .filter_map(|register| register.map(|index| format!("x{}", index)))
+            .collect();
+        if !registers.is_empty() {
+            write!(f, " {}", registers.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Decoder and execution of the instructions of a custom extension
+pub trait CustomExtension: Send {
+    /// Decode an instruction which the core decoder reports invalid
+    ///
+    /// # Arguments
+    /// * `raw` => instruction bits
+    ///
+    /// # Return Value
+    /// The instruction, or None if it doesn't belong to the extension either
+    fn decode(&self, raw: u32) -> Option<CustomInstruction>;
+
+    /// Execute an instruction decoded by this extension. It continues with
+    /// the next instruction.
+    ///
+    /// # Arguments
+    /// * `instr` => decoded instruction
+    /// * `pc` => address of the instruction
+    /// * `rs1` => value of the register source 1, or 0 without one
+    /// * `rs2` => value of the register source 2, or 0 without one
+    /// * `memory` => memory of the hart
+    ///
+    /// # Return Value
+    /// The value written to the destination register, if any
+    fn execute(
+        &mut self,
+        instr: &CustomInstruction,
+        pc: u32,
+        rs1: i32,
+        rs2: i32,
+        memory: &mut Memory,
+    ) -> Option<i32>;
+
+    /// Copy the extension with its state, for snapshots of the hart
+    fn box_clone(&self) -> Box<dyn CustomExtension>;
+}
+
+impl Clone for Box<dyn CustomExtension> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let mut instr = CustomInstruction::new(1, "flush", InstrClass::System);
+        assert_eq!("flush", instr.to_string());
+        instr.rd = Some(10);
+        instr.rs2 = Some(11);
+        assert_eq!("flush x10, x11", instr.to_string());
+    }
+}
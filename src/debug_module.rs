@@ -0,0 +1,655 @@
+//! Debug Module of the debug spec, the external view of the hart that a
+//! debugger reaches through the Debug Module Interface (DMI) of the JTAG TAP.
+//! It halts and resumes the hart, and reads and writes its registers and the
+//! memory with abstract commands while it's halted. There is no program
+//! buffer: the abstract commands access the state directly, including dcsr
+//! which reports why the hart halted and single steps it when it resumes.
+//!
+//! `serve` exposes the DMI over a socket, one access per line:
+//!
+//! * `r ADDR` => read the register at ADDR
+//! * `w ADDR DATA` => write DATA to the register at ADDR
+//!
+//! The numbers are in hexadecimal. Every access is answered with `DATA OP`,
+//! the read value (0 for writes) and the result of the access as reported by
+//! the DTM: 0 on success and 2 if the request was malformed. The simulation
+//! runs between the accesses unless the hart is halted.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::debug_module::{DebugModule, COMMAND, DATA0, DMCONTROL, DMSTATUS};
+//! # use adept_lib::pipeline::PipelineConfig;
+//! let mut cpu = Cpu::new(PipelineConfig::default());
+//! let mut dm = DebugModule::new();
+//! // Activate the module and halt the hart
+//! dm.write(&mut cpu, DMCONTROL, 1 << 31 | 1);
+//! assert_ne!(0, dm.read(DMSTATUS) & 1 << 9);
+//! // Write 5 to a0 with an Access Register command
+//! dm.write(&mut cpu, DATA0, 5);
+//! dm.write(&mut cpu, COMMAND, 2 << 20 | 1 << 17 | 1 << 16 | 0x100a);
+//! assert_eq!((5, 0), cpu.hart().registers().read(10, 0));
+//! ```
+use cpu::Cpu;
+use hart::StopReason;
+use mem::{MemLoadOp, MemStoreOp};
+use riscv::isa::RV32I;
+
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::TcpStream;
+
+/// Abstract data 0, the value of the abstract commands
+pub const DATA0: u32 = 0x04;
+/// Abstract data 1, the address of the Access Memory commands
+pub const DATA1: u32 = 0x05;
+/// Debug Module control
+pub const DMCONTROL: u32 = 0x10;
+/// Debug Module status
+pub const DMSTATUS: u32 = 0x11;
+/// Hart information
+pub const HARTINFO: u32 = 0x12;
+/// Abstract control and status
+pub const ABSTRACTCS: u32 = 0x16;
+/// Abstract command
+pub const COMMAND: u32 = 0x17;
+
+/// Register number of dcsr in the Access Register commands, the cause of
+/// the halt and the single step control
+pub const DCSR: u16 = 0x7b0;
+/// Register number of dpc in the Access Register commands, the pc where the
+/// hart resumes
+pub const DPC: u16 = 0x7b1;
+
+// Fields of dmcontrol
+const DMCONTROL_HALTREQ: u32 = 1 << 31;
+const DMCONTROL_RESUMEREQ: u32 = 1 << 30;
+const DMCONTROL_HARTSEL: u32 = 0x3ff << 16;
+const DMCONTROL_NDMRESET: u32 = 1 << 1;
+const DMCONTROL_DMACTIVE: u32 = 1;
+
+// Fields of dmstatus
+const DMSTATUS_ALLRESUMEACK: u32 = 1 << 17;
+const DMSTATUS_ANYRESUMEACK: u32 = 1 << 16;
+const DMSTATUS_ALLNONEXISTENT: u32 = 1 << 15;
+const DMSTATUS_ANYNONEXISTENT: u32 = 1 << 14;
+const DMSTATUS_ALLRUNNING: u32 = 1 << 11;
+const DMSTATUS_ANYRUNNING: u32 = 1 << 10;
+const DMSTATUS_ALLHALTED: u32 = 1 << 9;
+const DMSTATUS_ANYHALTED: u32 = 1 << 8;
+const DMSTATUS_AUTHENTICATED: u32 = 1 << 7;
+const DMSTATUS_VERSION: u32 = 2;
+
+// Fields of dcsr, the hart always runs in machine mode
+const DCSR_XDEBUGVER: u32 = 4 << 28;
+const DCSR_CAUSE_SHIFT: u32 = 6;
+const DCSR_STEP: u32 = 1 << 2;
+const DCSR_PRV_MACHINE: u32 = 3;
+
+// Halt causes of dcsr
+const CAUSE_EBREAK: u32 = 1;
+const CAUSE_TRIGGER: u32 = 2;
+const CAUSE_HALTREQ: u32 = 3;
+const CAUSE_STEP: u32 = 4;
+
+// Fields of abstractcs
+const ABSTRACTCS_CMDERR_SHIFT: u32 = 8;
+const DATACOUNT: u32 = 2;
+
+// Fields of the abstract commands
+const CMDTYPE_ACCESS_REGISTER: u32 = 0;
+const CMDTYPE_ACCESS_MEMORY: u32 = 2;
+const COMMAND_SIZE_SHIFT: u32 = 20;
+const COMMAND_POSTINCREMENT: u32 = 1 << 19;
+const COMMAND_POSTEXEC: u32 = 1 << 18;
+const COMMAND_TRANSFER: u32 = 1 << 17;
+const COMMAND_WRITE: u32 = 1 << 16;
+const COMMAND_AAMVIRTUAL: u32 = 1 << 23;
+
+// First register number of the GPRs
+const REGNO_GPR: u16 = 0x1000;
+
+// Instructions simulated between two polls of the socket
+const SLICE: usize = 1024;
+
+/// Errors of the abstract commands, as reported by cmderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError {
+    /// The command or one of its options isn't supported
+    NotSupported = 2,
+    /// The command failed, like an access to a missing register
+    Exception = 3,
+    /// The hart wasn't halted
+    HaltResume = 4,
+    /// The memory access failed
+    Bus = 5,
+}
+
+/// State of the Debug Module, with a single hart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugModule {
+    active: bool,
+    ndmreset: bool,
+    hartsel: u32,
+    halted: bool,
+    resumeack: bool,
+    cmderr: u32,
+    data: [u32; DATACOUNT as usize],
+    cause: u32,
+    step: bool,
+    stopped: Option<StopReason>,
+}
+
+impl Default for DebugModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugModule {
+    /// Create an inactive Debug Module with the hart running
+    pub fn new() -> Self {
+        DebugModule {
+            active: false,
+            ndmreset: false,
+            hartsel: 0,
+            halted: false,
+            resumeack: false,
+            cmderr: 0,
+            data: [0; DATACOUNT as usize],
+            cause: 0,
+            step: false,
+            stopped: None,
+        }
+    }
+
+    /// Whether the hart is halted, either by the debugger or because the
+    /// program stopped
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Why the program stopped the last time, if it did
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stopped
+    }
+
+    /// Read a DMI register. The registers which don't exist read as 0.
+    ///
+    /// # Arguments
+    /// * `addr` => DMI address of the register
+    pub fn read(&self, addr: u32) -> u32 {
+        match addr {
+            DATA0 | DATA1 => self.data[(addr - DATA0) as usize],
+            DMCONTROL => {
+                self.hartsel
+                    | if self.ndmreset { DMCONTROL_NDMRESET } else { 0 }
+                    | if self.active { DMCONTROL_DMACTIVE } else { 0 }
+            }
+            DMSTATUS => self.status(),
+            // No data registers are shadowed by CSRs or memory
+            HARTINFO => 0,
+            ABSTRACTCS => DATACOUNT | self.cmderr << ABSTRACTCS_CMDERR_SHIFT,
+            _ => 0,
+        }
+    }
+
+    /// Write a DMI register. The registers which don't exist ignore the
+    /// writes, and so do all but dmcontrol while the module is inactive.
+    ///
+    /// # Arguments
+    /// * `cpu` => the simulated processor
+    /// * `addr` => DMI address of the register
+    /// * `value` => written value
+    pub fn write(&mut self, cpu: &mut Cpu, addr: u32, value: u32) {
+        if !self.active && addr != DMCONTROL {
+            return;
+        }
+        match addr {
+            DATA0 | DATA1 => self.data[(addr - DATA0) as usize] = value,
+            DMCONTROL => self.write_control(cpu, value),
+            // cmderr is cleared by writing ones
+            ABSTRACTCS => self.cmderr &= !(value >> ABSTRACTCS_CMDERR_SHIFT & 7),
+            // A command is ignored until the error of the previous one is
+            // cleared
+            COMMAND if self.cmderr == 0 => {
+                if let Err(error) = self.execute(cpu, value) {
+                    debug!("debug module: command 0x{:08x}: {:?}", value, error);
+                    self.cmderr = error as u32;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Simulate the program for a while if the hart isn't halted. The hart
+    /// halts if the program stops.
+    pub fn run_slice(&mut self, cpu: &mut Cpu) {
+        if self.halted {
+            return;
+        }
+        for _ in 0..SLICE {
+            if let Err(reason) = cpu.step() {
+                self.stop(reason);
+                return;
+            }
+        }
+    }
+
+    /// Serve DMI accesses read from a connection until it's closed,
+    /// simulating the program while the hart isn't halted
+    ///
+    /// # Arguments
+    /// * `cpu` => the simulated processor
+    /// * `stream` => connection of the debugger
+    ///
+    /// # Return Value
+    /// Why the program stopped the last time, or None if it didn't
+    pub fn serve(&mut self, cpu: &mut Cpu, stream: TcpStream) -> io::Result<Option<StopReason>> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        let mut line = String::new();
+        loop {
+            // Wait for the debugger while the hart is halted, otherwise poll it
+            // between slices of the simulation
+            reader.get_ref().set_nonblocking(!self.halted)?;
+            match reader.read_line(&mut line) {
+                Ok(0) => return Ok(self.stopped),
+                // A partial line is completed by the next reads
+                Ok(_) if !line.ends_with('\n') => (),
+                Ok(_) => {
+                    let response = self.request(cpu, &line);
+                    line.clear();
+                    reader.get_ref().set_nonblocking(false)?;
+                    writeln!(writer, "{}", response)?;
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => self.run_slice(cpu),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Perform the DMI access of a line of the socket protocol, returning
+    // the response
+    fn request(&mut self, cpu: &mut Cpu, line: &str) -> String {
+        let mut words = line.split_whitespace();
+        let op = words.next();
+        let fields: Vec<_> = words.map(|word| u32::from_str_radix(word, 16)).collect();
+        match (op, fields.as_slice()) {
+            (Some("r"), &[Ok(addr)]) => format!("{:08x} 0", self.read(addr)),
+            (Some("w"), &[Ok(addr), Ok(value)]) => {
+                self.write(cpu, addr, value);
+                format!("{:08x} 0", 0)
+            }
+            _ => {
+                warn!("debug module: malformed request {:?}", line.trim());
+                format!("{:08x} 2", 0)
+            }
+        }
+    }
+
+    fn status(&self) -> u32 {
+        let mut status = DMSTATUS_VERSION | DMSTATUS_AUTHENTICATED;
+        if self.hartsel != 0 {
+            status |= DMSTATUS_ALLNONEXISTENT | DMSTATUS_ANYNONEXISTENT;
+        } else {
+            status |= if self.halted {
+                DMSTATUS_ALLHALTED | DMSTATUS_ANYHALTED
+            } else {
+                DMSTATUS_ALLRUNNING | DMSTATUS_ANYRUNNING
+            };
+            if self.resumeack {
+                status |= DMSTATUS_ALLRESUMEACK | DMSTATUS_ANYRESUMEACK;
+            }
+        }
+        status
+    }
+
+    // Halt the hart because the program stopped
+    fn stop(&mut self, reason: StopReason) {
+        debug!("debug module: hart halted: {}", reason);
+        self.halted = true;
+        self.cause = match reason {
+            StopReason::Breakpoint { .. } => CAUSE_EBREAK,
+            StopReason::Trigger { .. } => CAUSE_TRIGGER,
+            _ => CAUSE_HALTREQ,
+        };
+        self.stopped = Some(reason);
+    }
+
+    fn write_control(&mut self, cpu: &mut Cpu, value: u32) {
+        if value & DMCONTROL_DMACTIVE == 0 {
+            // Deactivating the module resets it, the hart keeps running
+            // though
+            let halted = self.halted;
+            let stopped = self.stopped;
+            *self = DebugModule::new();
+            self.halted = halted;
+            self.stopped = stopped;
+            return;
+        }
+        self.active = true;
+        self.ndmreset = value & DMCONTROL_NDMRESET != 0;
+        self.hartsel = value & DMCONTROL_HARTSEL;
+        if self.hartsel != 0 {
+            return;
+        }
+        if value & DMCONTROL_HALTREQ != 0 {
+            if !self.halted {
+                self.halted = true;
+                self.cause = CAUSE_HALTREQ;
+            }
+        } else if value & DMCONTROL_RESUMEREQ != 0 && self.halted {
+            // Resume at dpc, discarding the instructions in flight
+            let pc = cpu.hart().pc();
+            cpu.set_pc(pc);
+            self.halted = false;
+            self.resumeack = true;
+            if self.step {
+                match cpu.step() {
+                    Ok(()) => {
+                        self.halted = true;
+                        self.cause = CAUSE_STEP;
+                    }
+                    Err(reason) => self.stop(reason),
+                }
+            }
+        }
+    }
+
+    // Execute an abstract command
+    fn execute(&mut self, cpu: &mut Cpu, command: u32) -> Result<(), CommandError> {
+        let size = command >> COMMAND_SIZE_SHIFT & 7;
+        match command >> 24 {
+            CMDTYPE_ACCESS_REGISTER => {
+                if command & COMMAND_POSTEXEC != 0 || command & COMMAND_POSTINCREMENT != 0 {
+                    return Err(CommandError::NotSupported);
+                }
+                if command & COMMAND_TRANSFER == 0 {
+                    return Ok(());
+                }
+                if size != 2 {
+                    return Err(CommandError::NotSupported);
+                }
+                if !self.halted {
+                    return Err(CommandError::HaltResume);
+                }
+                self.access_register(cpu, command as u16, command & COMMAND_WRITE != 0)
+            }
+            CMDTYPE_ACCESS_MEMORY => {
+                if command & COMMAND_AAMVIRTUAL != 0 {
+                    return Err(CommandError::NotSupported);
+                }
+                let (load, store) = match size {
+                    0 => (RV32I::LBU, RV32I::SB),
+                    1 => (RV32I::LHU, RV32I::SH),
+                    2 => (RV32I::LW, RV32I::SW),
+                    _ => return Err(CommandError::NotSupported),
+                };
+                if !self.halted {
+                    return Err(CommandError::HaltResume);
+                }
+                let addr = self.data[1];
+                let memory = cpu.hart_mut().memory_mut();
+                if command & COMMAND_WRITE != 0 {
+                    memory
+                        .write_data(&MemStoreOp::from(store), addr, self.data[0])
+                        .map_err(|_| CommandError::Bus)?;
+                } else {
+                    self.data[0] = memory
+                        .load_data(&MemLoadOp::from(load), addr)
+                        .map_err(|_| CommandError::Bus)? as u32;
+                }
+                if command & COMMAND_POSTINCREMENT != 0 {
+                    self.data[1] = addr.wrapping_add(1 << size);
+                }
+                Ok(())
+            }
+            _ => Err(CommandError::NotSupported),
+        }
+    }
+
+    // Transfer a register between data0 and the halted hart
+    fn access_register(
+        &mut self,
+        cpu: &mut Cpu,
+        regno: u16,
+        write: bool,
+    ) -> Result<(), CommandError> {
+        match regno {
+            DCSR if write => self.step = self.data[0] & DCSR_STEP != 0,
+            DCSR => {
+                self.data[0] = DCSR_XDEBUGVER
+                    | self.cause << DCSR_CAUSE_SHIFT
+                    | if self.step { DCSR_STEP } else { 0 }
+                    | DCSR_PRV_MACHINE
+            }
+            DPC if write => cpu.set_pc(self.data[0]),
+            DPC => self.data[0] = cpu.hart().pc(),
+            0..=0xfff if write => {
+                if !cpu.hart_mut().csrs_mut().write(regno, self.data[0]) {
+                    return Err(CommandError::Exception);
+                }
+            }
+            0..=0xfff => {
+                self.data[0] = cpu
+                    .hart()
+                    .csrs()
+                    .read(regno)
+                    .ok_or(CommandError::Exception)?
+            }
+            REGNO_GPR..=0x101f if write => cpu
+                .hart_mut()
+                .registers_mut()
+                .write((regno - REGNO_GPR) as u8, self.data[0] as i32),
+            REGNO_GPR..=0x101f => {
+                let (value, _) = cpu.hart().registers().read((regno - REGNO_GPR) as u8, 0);
+                self.data[0] = value as u32;
+            }
+            _ => return Err(CommandError::Exception),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pipeline::PipelineConfig;
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Command accessing a GPR or CSR with 32 bits
+    fn access_register(regno: u32, write: bool) -> u32 {
+        let write = if write { COMMAND_WRITE } else { 0 };
+        2 << COMMAND_SIZE_SHIFT | COMMAND_TRANSFER | write | regno
+    }
+
+    fn cmderr(dm: &DebugModule) -> u32 {
+        dm.read(ABSTRACTCS) >> ABSTRACTCS_CMDERR_SHIFT & 7
+    }
+
+    #[test]
+    fn test_halt_resume() {
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        let mut dm = DebugModule::new();
+        // Ignored while inactive
+        dm.write(&mut cpu, DATA0, 1);
+        assert_eq!(0, dm.read(DATA0));
+        dm.write(&mut cpu, DMCONTROL, DMCONTROL_DMACTIVE);
+        assert_eq!(DMCONTROL_DMACTIVE, dm.read(DMCONTROL));
+        let status = dm.read(DMSTATUS);
+        assert_eq!(DMSTATUS_VERSION, status & 0xf);
+        assert_ne!(0, status & DMSTATUS_ALLRUNNING);
+
+        dm.write(&mut cpu, DMCONTROL, DMCONTROL_HALTREQ | DMCONTROL_DMACTIVE);
+        let status = dm.read(DMSTATUS);
+        assert_eq!(DMSTATUS_ALLHALTED | DMSTATUS_ANYHALTED, status & 0xf00);
+        assert_eq!(0, status & DMSTATUS_ALLRESUMEACK);
+
+        dm.write(
+            &mut cpu,
+            DMCONTROL,
+            DMCONTROL_RESUMEREQ | DMCONTROL_DMACTIVE,
+        );
+        let status = dm.read(DMSTATUS);
+        assert_eq!(DMSTATUS_ALLRUNNING | DMSTATUS_ANYRUNNING, status & 0xf00);
+        assert_ne!(0, status & DMSTATUS_ALLRESUMEACK);
+
+        // Only hart 0 exists
+        dm.write(&mut cpu, DMCONTROL, 1 << 16 | DMCONTROL_DMACTIVE);
+        assert_ne!(0, dm.read(DMSTATUS) & DMSTATUS_ALLNONEXISTENT);
+    }
+
+    #[test]
+    fn test_access_register() {
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        let mut dm = DebugModule::new();
+        dm.write(&mut cpu, DMCONTROL, DMCONTROL_DMACTIVE);
+        // The hart must be halted
+        dm.write(&mut cpu, COMMAND, access_register(0x100a, false));
+        assert_eq!(CommandError::HaltResume as u32, cmderr(&dm));
+        // Commands are ignored until cmderr is cleared
+        dm.write(&mut cpu, DMCONTROL, DMCONTROL_HALTREQ | DMCONTROL_DMACTIVE);
+        dm.write(&mut cpu, DATA0, 0x1234);
+        dm.write(&mut cpu, COMMAND, access_register(0x100a, true));
+        assert_eq!((0, 0), cpu.hart().registers().read(10, 0));
+        dm.write(&mut cpu, ABSTRACTCS, 7 << ABSTRACTCS_CMDERR_SHIFT);
+        assert_eq!(0, cmderr(&dm));
+
+        dm.write(&mut cpu, COMMAND, access_register(0x100a, true));
+        assert_eq!((0x1234, 0), cpu.hart().registers().read(10, 0));
+        // mscratch
+        dm.write(&mut cpu, COMMAND, access_register(0x340, true));
+        dm.write(&mut cpu, DATA0, 0);
+        dm.write(&mut cpu, COMMAND, access_register(0x340, false));
+        assert_eq!(0x1234, dm.read(DATA0));
+        dm.write(&mut cpu, COMMAND, access_register(u32::from(DPC), true));
+        assert_eq!(0x1234, cpu.hart().pc());
+        assert_eq!(0, cmderr(&dm));
+
+        // 64-bit accesses
+        dm.write(
+            &mut cpu,
+            COMMAND,
+            access_register(0x100a, false) + (1 << 20),
+        );
+        assert_eq!(CommandError::NotSupported as u32, cmderr(&dm));
+        dm.write(&mut cpu, ABSTRACTCS, 7 << ABSTRACTCS_CMDERR_SHIFT);
+        // Floating point registers
+        dm.write(&mut cpu, COMMAND, access_register(0x1020, false));
+        assert_eq!(CommandError::Exception as u32, cmderr(&dm));
+    }
+
+    #[test]
+    fn test_step() {
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        // addi a0, a0, 1; ebreak
+        for (addr, word) in [(0, 0x0015_0513), (4, 0x0010_0073)].iter() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), *addr, *word)
+                .unwrap();
+        }
+        let mut dm = DebugModule::new();
+        dm.write(&mut cpu, DMCONTROL, DMCONTROL_HALTREQ | DMCONTROL_DMACTIVE);
+        dm.write(&mut cpu, COMMAND, access_register(u32::from(DCSR), false));
+        assert_eq!(
+            DCSR_XDEBUGVER | CAUSE_HALTREQ << DCSR_CAUSE_SHIFT | DCSR_PRV_MACHINE,
+            dm.read(DATA0)
+        );
+
+        dm.write(&mut cpu, DATA0, DCSR_STEP);
+        dm.write(&mut cpu, COMMAND, access_register(u32::from(DCSR), true));
+        dm.write(
+            &mut cpu,
+            DMCONTROL,
+            DMCONTROL_RESUMEREQ | DMCONTROL_DMACTIVE,
+        );
+        assert!(dm.halted());
+        assert_eq!((1, 0), cpu.hart().registers().read(10, 0));
+        dm.write(&mut cpu, COMMAND, access_register(u32::from(DCSR), false));
+        assert_eq!(CAUSE_STEP, dm.read(DATA0) >> DCSR_CAUSE_SHIFT & 7);
+        dm.write(&mut cpu, COMMAND, access_register(u32::from(DPC), false));
+        assert_eq!(4, dm.read(DATA0));
+
+        // Stepping the ebreak halts at it
+        dm.write(
+            &mut cpu,
+            DMCONTROL,
+            DMCONTROL_RESUMEREQ | DMCONTROL_DMACTIVE,
+        );
+        dm.write(&mut cpu, COMMAND, access_register(u32::from(DCSR), false));
+        assert_eq!(CAUSE_EBREAK, dm.read(DATA0) >> DCSR_CAUSE_SHIFT & 7);
+        assert_eq!(Some(StopReason::Breakpoint { pc: 4 }), dm.stop_reason());
+    }
+
+    #[test]
+    fn test_access_memory() {
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        let mut dm = DebugModule::new();
+        dm.write(&mut cpu, DMCONTROL, DMCONTROL_HALTREQ | DMCONTROL_DMACTIVE);
+        let word = CMDTYPE_ACCESS_MEMORY << 24 | 2 << COMMAND_SIZE_SHIFT | COMMAND_POSTINCREMENT;
+        dm.write(&mut cpu, DATA1, 0x100);
+        dm.write(&mut cpu, DATA0, 0x1122_3344);
+        dm.write(&mut cpu, COMMAND, word | COMMAND_WRITE);
+        dm.write(&mut cpu, DATA0, 0x5566_7788);
+        dm.write(&mut cpu, COMMAND, word | COMMAND_WRITE);
+        assert_eq!(0x108, dm.read(DATA1));
+
+        // Read back the byte at 0x105
+        let byte = CMDTYPE_ACCESS_MEMORY << 24;
+        dm.write(&mut cpu, DATA1, 0x105);
+        dm.write(&mut cpu, COMMAND, byte);
+        assert_eq!(0x77, dm.read(DATA0));
+        assert_eq!(0x105, dm.read(DATA1));
+        assert_eq!(0, cmderr(&dm));
+
+        // Misaligned words fail on the bus
+        dm.write(&mut cpu, DATA1, 0x102);
+        dm.write(&mut cpu, COMMAND, word);
+        assert_eq!(CommandError::Bus as u32, cmderr(&dm));
+    }
+
+    #[test]
+    fn test_serve() {
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        // addi a0, zero, 5; ebreak
+        for (addr, word) in [(0, 0x0050_0513), (4, 0x0010_0073)].iter() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), *addr, *word)
+                .unwrap();
+        }
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut access = |request: &str| {
+                writeln!(writer, "{}", request).unwrap();
+                let mut response = String::new();
+                reader.read_line(&mut response).unwrap();
+                response.trim().to_string()
+            };
+            assert_eq!("00000000 0", access("w 10 1"));
+            // Wait for the ebreak
+            while u32::from_str_radix(&access("r 11")[..8], 16).unwrap() & DMSTATUS_ALLHALTED == 0 {
+            }
+            access("w 17 22100a");
+            let a0 = access("r 4");
+            assert_eq!("00000000 2", access("x 4"));
+            a0
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut dm = DebugModule::new();
+        assert_eq!(
+            Some(StopReason::Breakpoint { pc: 4 }),
+            dm.serve(&mut cpu, stream).unwrap()
+        );
+        assert_eq!("00000005 0", client.join().unwrap());
+    }
+}
@@ -0,0 +1,828 @@
+//! Interactive debugger of the simulation. It reads commands line by line,
+//! steps or runs the processor and prints expressions and the memory:
+//!
+//! * `step [N]` or `s [N]` => retire N instructions, one by default
+//! * `continue` or `c` => run until the program stops
+//! * `until EXPR` or `advance EXPR` => run until the program reaches an
+//!   address, such as `until uart_putc+0x8`, or a breakpoint
+//! * `goto N` => go to the state after N retired instructions, back in time
+//!   if the session is recorded, see `timeline`
+//! * `reverse-step [N]` or `rs [N]` => go back N instructions, one by
+//!   default, in a recorded session
+//! * `print EXPR` or `p EXPR` => value of an expression, such as
+//!   `*(u32*)(a0+8)`
+//! * `mem[START..+SIZE]` or `mem[START..END]` => bytes of the memory
+//! * `x/NFU EXPR` => N units U (b, h or w) at an address, in format F (x, d,
+//!   u, or i for instructions), such as `x/4w sp+16`
+//! * `break EXPR [if COND]` or `b ...` => stop `continue` before the
+//!   instruction at an address, when the condition holds, such as
+//!   `break main if a0==0`
+//! * `ignore N COUNT` => let the breakpoint N pass COUNT times
+//! * `delete N` => remove the breakpoint N
+//! * `info breakpoints` or `info b` => list the breakpoints
+//! * `trace EXPR: EXPR[, EXPR...]` => log values at an address without
+//!   stopping, see `tracepoint`
+//! * `quit` or `q` => leave the debugger
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::debugger::Debugger;
+//! # use adept_lib::pipeline::PipelineConfig;
+//! let mut cpu = Cpu::new(PipelineConfig::default());
+//! let mut out = Vec::new();
+//! let stopped = Debugger::new(Vec::new())
+//!     .run(&mut cpu, &b"print pc + 4\nquit\n"[..], &mut out)
+//!     .unwrap();
+//! assert_eq!(None, stopped);
+//! assert!(String::from_utf8(out).unwrap().contains("0x00000004 4"));
+//! ```
+use cpu::Cpu;
+use crash;
+use elf::Symbol;
+use expr::{Expr, ExprError, MemRange, Width};
+use hart::{Hart, StopReason};
+use riscv::decoder::Instruction;
+use timeline::Timeline;
+use tracepoint::TracepointSpec;
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+const PROMPT: &str = "(adept) ";
+
+/// How `x` prints the values it reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Hex,
+    Signed,
+    Unsigned,
+    Instruction,
+}
+
+/// Command of the debugger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Retire a number of instructions
+    Step(u64),
+    /// Run until the program stops
+    Continue,
+    /// Run until the program reaches an address
+    Until(Expr),
+    /// Go to the state after a number of retired instructions
+    Goto(u64),
+    /// Go back a number of instructions
+    ReverseStep(u64),
+    /// Print the value of an expression
+    Print(Expr),
+    /// Print bytes of the memory
+    Dump(MemRange),
+    /// Print values of the memory from an address
+    Examine {
+        count: u32,
+        format: Format,
+        width: Width,
+        addr: Expr,
+    },
+    /// Add a breakpoint
+    Break(BreakpointSpec),
+    /// Let a breakpoint pass a number of times
+    Ignore {
+        number: usize,
+        count: u64,
+    },
+    /// Remove a breakpoint
+    Delete(usize),
+    /// List the breakpoints
+    Breakpoints,
+    /// Add a tracepoint
+    Trace(TracepointSpec),
+    Help,
+    Quit,
+}
+
+/// Breakpoint given as `EXPR [if COND]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakpointSpec {
+    /// Address of the instruction
+    pub location: Expr,
+    /// Expression which must be true, other than 0, to stop
+    pub condition: Option<Expr>,
+    text: String,
+}
+
+impl BreakpointSpec {
+    pub fn parse(text: &str) -> Result<BreakpointSpec, String> {
+        let text = text.trim();
+        let (location, condition) = match text.find(" if ") {
+            Some(index) => (&text[..index], Some(&text[index + 4..])),
+            None => (text, None),
+        };
+        Ok(BreakpointSpec {
+            location: Expr::parse(location).map_err(|e| e.to_string())?,
+            condition: match condition {
+                Some(condition) => Some(Expr::parse(condition).map_err(|e| e.to_string())?),
+                None => None,
+            },
+            text: text.to_string(),
+        })
+    }
+}
+
+/// Breakpoint of the debugger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u32,
+    pub condition: Option<Expr>,
+    /// Number of stops left to skip
+    pub ignore: u64,
+    /// Number of times the instruction was reached with the condition true
+    pub hits: u64,
+    // Text given by the user
+    text: String,
+}
+
+impl Command {
+    /// Parse a line of the user
+    ///
+    /// # Return Value
+    /// The command, None for an empty line, or why the line isn't a command
+    pub fn parse(line: &str) -> Result<Option<Command>, String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        if let Some(range) = MemRange::parse(line) {
+            return Ok(Some(Command::Dump(range.map_err(|e| e.to_string())?)));
+        }
+        let (name, argument) = match line.find(|c: char| c.is_whitespace() || c == '/') {
+            Some(index) => (&line[..index], line[index..].trim_start()),
+            None => (line, ""),
+        };
+        let expr = |text: &str| Expr::parse(text).map_err(|e| e.to_string());
+        Ok(Some(match name {
+            "step" | "s" if argument.is_empty() => Command::Step(1),
+            "step" | "s" => match argument.parse() {
+                Ok(count) => Command::Step(count),
+                Err(_) => return Err(format!("invalid number of instructions: {}", argument)),
+            },
+            "continue" | "c" => Command::Continue,
+            "goto" => match argument.parse() {
+                Ok(index) => Command::Goto(index),
+                Err(_) => return Err(String::from("usage: goto N")),
+            },
+            "reverse-step" | "rs" if argument.is_empty() => Command::ReverseStep(1),
+            "reverse-step" | "rs" => match argument.parse() {
+                Ok(count) => Command::ReverseStep(count),
+                Err(_) => return Err(format!("invalid number of instructions: {}", argument)),
+            },
+            "until" | "advance" => Command::Until(expr(argument)?),
+            "print" | "p" => match MemRange::parse(argument) {
+                Some(range) => Command::Dump(range.map_err(|e| e.to_string())?),
+                None => Command::Print(expr(argument)?),
+            },
+            "x" => {
+                let (spec, addr) = match argument.strip_prefix('/') {
+                    Some(rest) => match rest.find(char::is_whitespace) {
+                        Some(index) => (&rest[..index], &rest[index..]),
+                        None => return Err(String::from("x needs an address")),
+                    },
+                    None => ("", argument),
+                };
+                let (count, format, width) = parse_examine(spec)?;
+                Command::Examine {
+                    count,
+                    format,
+                    width,
+                    addr: expr(addr)?,
+                }
+            }
+            "break" | "b" => Command::Break(BreakpointSpec::parse(argument)?),
+            "ignore" => {
+                let mut numbers = argument.split_whitespace().map(str::parse::<u64>);
+                match (numbers.next(), numbers.next(), numbers.next()) {
+                    (Some(Ok(number)), Some(Ok(count)), None) => Command::Ignore {
+                        number: number as usize,
+                        count,
+                    },
+                    _ => return Err(String::from("usage: ignore N COUNT")),
+                }
+            }
+            "delete" | "d" => match argument.parse() {
+                Ok(number) => Command::Delete(number),
+                Err(_) => return Err(String::from("usage: delete N")),
+            },
+            "info" if argument == "breakpoints" || argument == "b" => Command::Breakpoints,
+            "trace" => Command::Trace(TracepointSpec::parse(argument)?),
+            "help" | "h" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            _ => return Err(format!("unknown command: {}, try help", name)),
+        }))
+    }
+}
+
+// Parse the NFU of x/NFU into the count, the format and the unit
+fn parse_examine(spec: &str) -> Result<(u32, Format, Width), String> {
+    let digits = spec
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(spec.len());
+    let count = match &spec[..digits] {
+        "" => 1,
+        count => match count.parse() {
+            Ok(count) => count,
+            Err(_) => return Err(format!("invalid count: {}", count)),
+        },
+    };
+    let mut format = Format::Hex;
+    let mut width = Width::U32;
+    for letter in spec[digits..].chars() {
+        match letter {
+            'x' => format = Format::Hex,
+            'd' => format = Format::Signed,
+            'u' => format = Format::Unsigned,
+            'i' => format = Format::Instruction,
+            'b' => width = Width::U8,
+            'h' => width = Width::U16,
+            'w' => width = Width::U32,
+            _ => return Err(format!("invalid format or unit: {}", letter)),
+        }
+    }
+    // Instructions are words
+    if format == Format::Instruction {
+        width = Width::U32;
+    }
+    Ok((count, format, width))
+}
+
+/// Interactive debugger of a processor
+pub struct Debugger {
+    symbols: Vec<Symbol>,
+    // Breakpoints by number, from 1
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    next_breakpoint: usize,
+    // Why the program stopped, once it did
+    stopped: Option<StopReason>,
+    // Recording of the session, to go back in time
+    timeline: Option<Timeline>,
+}
+
+impl Debugger {
+    /// Create a debugger
+    ///
+    /// # Arguments
+    /// * `symbols` => symbols of the program which expressions can name
+    pub fn new(symbols: Vec<Symbol>) -> Self {
+        Debugger {
+            symbols,
+            breakpoints: BTreeMap::new(),
+            next_breakpoint: 1,
+            stopped: None,
+            timeline: None,
+        }
+    }
+
+    /// Record the session from the current state of the processor, so that
+    /// `goto` and `reverse-step` can go back to any instruction retired
+    /// since. `continue` then steps through the program to record it.
+    ///
+    /// # Arguments
+    /// * `cpu` => processor debugged
+    /// * `interval` => instructions between two snapshots of the processor
+    pub fn record(&mut self, cpu: &Cpu, interval: u64) {
+        self.timeline = Some(Timeline::new(cpu, interval));
+    }
+
+    /// Add a breakpoint at the address of its location in the current state
+    /// of the hart
+    ///
+    /// # Return Value
+    /// The number of the breakpoint
+    pub fn add_breakpoint(
+        &mut self,
+        spec: &BreakpointSpec,
+        hart: &Hart,
+    ) -> Result<usize, ExprError> {
+        let addr = spec.location.evaluate(hart, &self.symbols)?;
+        let number = self.next_breakpoint;
+        self.next_breakpoint += 1;
+        self.breakpoints.insert(
+            number,
+            Breakpoint {
+                addr,
+                condition: spec.condition.clone(),
+                ignore: 0,
+                hits: 0,
+                text: spec.text.clone(),
+            },
+        );
+        Ok(number)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeMap<usize, Breakpoint> {
+        &self.breakpoints
+    }
+
+    /// Read and execute commands until the user quits or the input ends
+    ///
+    /// # Arguments
+    /// * `cpu` => processor debugged
+    /// * `input` => commands, one per line
+    /// * `out` => destination of the prompts and of the answers
+    ///
+    /// # Return Value
+    /// Why the program stopped, or None if it was still running
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        cpu: &mut Cpu,
+        mut input: R,
+        out: &mut W,
+    ) -> io::Result<Option<StopReason>> {
+        loop {
+            write!(out, "{}", PROMPT)?;
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(out)?;
+                return Ok(self.stopped);
+            }
+            match Command::parse(&line) {
+                Ok(None) => (),
+                Ok(Some(Command::Quit)) => return Ok(self.stopped),
+                Ok(Some(command)) => self.execute(cpu, &command, out)?,
+                Err(message) => writeln!(out, "{}", message)?,
+            }
+        }
+    }
+
+    /// Execute a command other than quit
+    pub fn execute<W: Write>(
+        &mut self,
+        cpu: &mut Cpu,
+        command: &Command,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let hart = cpu.hart();
+        match command {
+            Command::Step(count) => {
+                for _ in 0..*count {
+                    if !self.resume(cpu, out, Cpu::step)? {
+                        return Ok(());
+                    }
+                }
+                self.write_location(cpu, out)
+            }
+            Command::Continue if self.breakpoints.is_empty() && self.timeline.is_none() => {
+                self.resume(cpu, out, |cpu| Err(cpu.run())).map(|_| ())
+            }
+            Command::Continue => self.run_to(cpu, None, out),
+            Command::Until(location) => match location.evaluate(hart, &self.symbols) {
+                Ok(addr) => self.run_to(cpu, Some(addr), out),
+                Err(e) => writeln!(out, "{}", e),
+            },
+            Command::Goto(index) => self.travel(cpu, *index, out),
+            Command::ReverseStep(count) => match cpu.stats().instructions.checked_sub(*count) {
+                Some(index) => self.travel(cpu, index, out),
+                None => writeln!(
+                    out,
+                    "only {} instructions retired",
+                    cpu.stats().instructions
+                ),
+            },
+            Command::Print(expr) => match expr.evaluate(hart, &self.symbols) {
+                Ok(value) => writeln!(out, "0x{:08x} {}", value, value as i32),
+                Err(e) => writeln!(out, "{}", e),
+            },
+            Command::Dump(range) => match range.evaluate(hart, &self.symbols) {
+                Ok((start, size)) => crash::write_memory(out, hart.memory(), start, size),
+                Err(e) => writeln!(out, "{}", e),
+            },
+            Command::Examine {
+                count,
+                format,
+                width,
+                addr,
+            } => match addr.evaluate(hart, &self.symbols) {
+                Ok(addr) => self.examine(cpu, addr, *count, *format, *width, out),
+                Err(e) => writeln!(out, "{}", e),
+            },
+            Command::Break(spec) => match self.add_breakpoint(spec, hart) {
+                Ok(number) => writeln!(
+                    out,
+                    "Breakpoint {} at 0x{:08x}",
+                    number, self.breakpoints[&number].addr
+                ),
+                Err(e) => writeln!(out, "{}", e),
+            },
+            Command::Ignore { number, count } => match self.breakpoints.get_mut(number) {
+                Some(breakpoint) => {
+                    breakpoint.ignore = *count;
+                    writeln!(
+                        out,
+                        "Will ignore the next {} stops of breakpoint {}",
+                        count, number
+                    )
+                }
+                None => writeln!(out, "no breakpoint {}", number),
+            },
+            Command::Delete(number) => match self.breakpoints.remove(number) {
+                Some(_) => Ok(()),
+                None => writeln!(out, "no breakpoint {}", number),
+            },
+            Command::Breakpoints => {
+                for (number, breakpoint) in &self.breakpoints {
+                    write!(
+                        out,
+                        "{} 0x{:08x} {} hits {}",
+                        number, breakpoint.addr, breakpoint.text, breakpoint.hits
+                    )?;
+                    if breakpoint.ignore > 0 {
+                        write!(out, " ignore {}", breakpoint.ignore)?;
+                    }
+                    writeln!(out)?;
+                }
+                Ok(())
+            }
+            Command::Trace(spec) => match spec.resolve(hart, &self.symbols) {
+                Ok(tracepoint) => {
+                    let addr = tracepoint.addr;
+                    cpu.tracepoints_mut().add(tracepoint);
+                    writeln!(out, "Tracepoint at 0x{:08x}", addr)
+                }
+                Err(e) => writeln!(out, "{}", e),
+            },
+            Command::Help => writeln!(
+                out,
+                "step [N], continue, until EXPR, goto N, reverse-step [N], print EXPR, \
+                 mem[START..+SIZE], x/NFU EXPR, \
+                 break EXPR [if COND], ignore N COUNT, delete N, info breakpoints, \
+                 trace EXPR: EXPR[, EXPR...], quit"
+            ),
+            Command::Quit => Ok(()),
+        }
+    }
+
+    // Run until the program reaches the target or a breakpoint, or stops
+    fn run_to<W: Write>(
+        &mut self,
+        cpu: &mut Cpu,
+        target: Option<u32>,
+        out: &mut W,
+    ) -> io::Result<()> {
+        loop {
+            if !self.resume(cpu, out, Cpu::step)? {
+                return Ok(());
+            }
+            if let Some(number) = self.hit_breakpoint(cpu.hart(), out)? {
+                write!(out, "Breakpoint {}, ", number)?;
+                return self.write_location(cpu, out);
+            }
+            if target == Some(cpu.hart().pc()) {
+                return self.write_location(cpu, out);
+            }
+        }
+    }
+
+    // Go to the state after a number of retired instructions, running
+    // forward to the ones which weren't retired yet
+    fn travel<W: Write>(&mut self, cpu: &mut Cpu, index: u64, out: &mut W) -> io::Result<()> {
+        let travelled = match self.timeline {
+            Some(ref timeline) if index <= timeline.last() => Some(timeline.travel(cpu, index)),
+            _ => None,
+        };
+        match travelled {
+            Some(Err(e)) => return writeln!(out, "{}", e),
+            // The program runs again from there
+            Some(Ok(())) => self.stopped = None,
+            None if index < cpu.stats().instructions => {
+                return writeln!(
+                    out,
+                    "going back needs a recording of the session, start it with --time-travel"
+                )
+            }
+            None => (),
+        }
+        while cpu.stats().instructions < index {
+            if !self.resume(cpu, out, Cpu::step)? {
+                return Ok(());
+            }
+        }
+        self.write_location(cpu, out)
+    }
+
+    // Write the pc, with the symbol holding it, and the instruction about to
+    // be executed
+    fn write_location<W: Write>(&self, cpu: &Cpu, out: &mut W) -> io::Result<()> {
+        let pc = cpu.hart().pc();
+        write!(out, "0x{:08x}", pc)?;
+        if let Some(symbol) = Symbol::lookup(&self.symbols, pc) {
+            match pc - symbol.value {
+                0 => write!(out, " <{}>", symbol.name)?,
+                offset => write!(out, " <{}+0x{:x}>", symbol.name, offset)?,
+            }
+        }
+        let raw = cpu.hart().memory().read_pc(pc);
+        writeln!(out, " {}", Instruction::new(raw))
+    }
+
+    // Count the hit of the breakpoints at the pc whose condition holds
+    //
+    // # Return Value
+    // The number of the first breakpoint which stops the program, if any
+    fn hit_breakpoint<W: Write>(&mut self, hart: &Hart, out: &mut W) -> io::Result<Option<usize>> {
+        let pc = hart.pc();
+        let symbols = &self.symbols;
+        for (number, breakpoint) in self.breakpoints.iter_mut() {
+            if breakpoint.addr != pc {
+                continue;
+            }
+            if let Some(ref condition) = breakpoint.condition {
+                match condition.evaluate(hart, symbols) {
+                    Ok(0) => continue,
+                    Ok(_) => (),
+                    // A condition which can't be evaluated stops the program
+                    Err(e) => {
+                        writeln!(
+                            out,
+                            "Error in the condition of breakpoint {}: {}",
+                            number, e
+                        )?;
+                        return Ok(Some(*number));
+                    }
+                }
+            }
+            breakpoint.hits += 1;
+            if breakpoint.ignore > 0 {
+                breakpoint.ignore -= 1;
+            } else {
+                return Ok(Some(*number));
+            }
+        }
+        Ok(None)
+    }
+
+    // Resume the program, unless it already stopped
+    //
+    // # Return Value
+    // false if the program stopped
+    fn resume<W, F>(&mut self, cpu: &mut Cpu, out: &mut W, run: F) -> io::Result<bool>
+    where
+        W: Write,
+        F: FnOnce(&mut Cpu) -> Result<(), StopReason>,
+    {
+        if self.stopped.is_none() {
+            self.stopped = run(cpu).err();
+            if let Some(ref mut timeline) = self.timeline {
+                timeline.record(cpu);
+            }
+        }
+        match self.stopped {
+            Some(reason) => {
+                writeln!(out, "The program stopped: {}", reason)?;
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+
+    // Print `count` values of a width from an address, four words or eight
+    // smaller values on a line
+    fn examine<W: Write>(
+        &self,
+        cpu: &Cpu,
+        addr: u32,
+        count: u32,
+        format: Format,
+        width: Width,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let memory = cpu.hart().memory();
+        let op = width.load_op();
+        let size = op.size();
+        let per_line = if format == Format::Instruction {
+            1
+        } else if size == 4 {
+            4
+        } else {
+            8
+        };
+        for index in 0..count {
+            let value_addr = addr.wrapping_add(index * size);
+            if index % per_line == 0 {
+                if index > 0 {
+                    writeln!(out)?;
+                }
+                write!(out, "0x{:08x}:", value_addr)?;
+            }
+            let value = match memory.load_data(&op, value_addr) {
+                Ok(value) => value as u32,
+                Err(e) => {
+                    writeln!(out)?;
+                    return writeln!(out, "can't read the memory: {}", e);
+                }
+            };
+            match format {
+                Format::Hex => write!(out, " 0x{:0width$x}", value, width = 2 * size as usize)?,
+                Format::Signed => write!(out, " {}", sign_extend(value, size))?,
+                Format::Unsigned => write!(out, " {}", value)?,
+                Format::Instruction => write!(out, " {}", Instruction::new(value))?,
+            }
+        }
+        writeln!(out)
+    }
+}
+
+// Sign extend the lowest bytes of a value
+fn sign_extend(value: u32, size: u32) -> i32 {
+    let shift = 32 - 8 * size;
+    ((value << shift) as i32) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem::MemStoreOp;
+    use pipeline::PipelineConfig;
+    use riscv::isa::RV32I;
+
+    // Sum the numbers from 1 to 3 into a0, then stop at an invalid
+    // instruction
+    const PROGRAM: [u32; 5] = [
+        0x0000_0513, // li a0, 0
+        0x0030_0593, // li a1, 3
+        0x00b5_0533, // loop: add a0, a0, a1
+        0xfff5_8593, // addi a1, a1, -1
+        0xfe05_9ce3, // bnez a1, loop
+    ];
+
+    fn debug(commands: &str) -> (Option<StopReason>, Vec<String>) {
+        debug_recorded(commands, None)
+    }
+
+    // Debug the program, recording the session with snapshots every
+    // interval instructions
+    fn debug_recorded(commands: &str, interval: Option<u64>) -> (Option<StopReason>, Vec<String>) {
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        for (index, word) in PROGRAM.iter().enumerate() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    0x100 + 4 * index as u32,
+                    *word,
+                )
+                .unwrap();
+        }
+        cpu.set_pc(0x100);
+        let symbols = vec![Symbol {
+            name: String::from("loop"),
+            value: 0x108,
+            size: 0,
+        }];
+        let mut out = Vec::new();
+        let mut debugger = Debugger::new(symbols);
+        if let Some(interval) = interval {
+            debugger.record(&cpu, interval);
+        }
+        let stopped = debugger
+            .run(&mut cpu, commands.as_bytes(), &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        (
+            stopped,
+            out.split(PROMPT)
+                .skip(1)
+                .map(|answer| answer.trim_end().to_string())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(
+            Ok(Some(Command::Examine {
+                count: 4,
+                format: Format::Hex,
+                width: Width::U32,
+                addr: Expr::parse("sp+16").unwrap(),
+            })),
+            Command::parse("x/4w sp+16")
+        );
+        assert_eq!(Ok(Some(Command::Step(10))), Command::parse("s 10"));
+        assert_eq!(Ok(Some(Command::Goto(42))), Command::parse("goto 42"));
+        assert_eq!(Ok(Some(Command::ReverseStep(1))), Command::parse("rs"));
+        assert!(Command::parse("goto main").is_err());
+        assert_eq!(Ok(None), Command::parse("  "));
+        assert!(Command::parse("jump 0x100").is_err());
+        assert!(Command::parse("x/4q sp").is_err());
+        assert!(matches!(
+            Command::parse("print mem[loop..+8]"),
+            Ok(Some(Command::Dump(_)))
+        ));
+    }
+
+    #[test]
+    fn test_session() {
+        let (stopped, answers) = debug(
+            "step 2\nprint a1 * 2\nx/2i loop\nx/2xh loop\nmem[loop..+6]\nprint nothing\ncontinue\nstep\nprint a0\n",
+        );
+        assert_eq!(
+            Some(StopReason::InvalidInstruction { pc: 0x114, raw: 0 }),
+            stopped
+        );
+        assert_eq!("0x00000108 <loop> add     a0,a0,a1", answers[0]);
+        assert_eq!("0x00000006 6", answers[1]);
+        assert_eq!(
+            "0x00000108: add     a0,a0,a1\n0x0000010c: addi    a1,a1,-1",
+            answers[2]
+        );
+        assert_eq!("0x00000108: 0x0533 0x00b5", answers[3]);
+        assert_eq!("0x00000108: 33 05 b5 00 93 85", answers[4]);
+        assert_eq!("no register or symbol named nothing", answers[5]);
+        assert!(answers[6].starts_with("The program stopped: invalid instruction"));
+        assert_eq!(answers[6], answers[7]);
+        assert_eq!("0x00000006 6", answers[8]);
+
+        let (stopped, answers) = debug("s\nquit\nstep\n");
+        assert_eq!(None, stopped);
+        assert_eq!(2, answers.len());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        assert_eq!(
+            Ok(Some(Command::Ignore {
+                number: 1,
+                count: 100
+            })),
+            Command::parse("ignore 1 100")
+        );
+        assert!(Command::parse("break main if").is_err());
+
+        // The loop is entered with a1 from 3 down to 1
+        let (_, answers) = debug(
+            "break loop if a1 < 3\nbreak loop+4\nignore 2 1\ncontinue\nprint a1\n\
+             continue\nprint a1\ninfo breakpoints\ndelete 2\ncontinue\ncontinue\n",
+        );
+        assert_eq!("Breakpoint 1 at 0x00000108", answers[0]);
+        assert_eq!("Breakpoint 2 at 0x0000010c", answers[1]);
+        assert_eq!(
+            "Breakpoint 1, 0x00000108 <loop> add     a0,a0,a1",
+            answers[3]
+        );
+        assert_eq!("0x00000002 2", answers[4]);
+        // The first stop of breakpoint 2 was ignored
+        assert_eq!(
+            "Breakpoint 2, 0x0000010c <loop+0x4> addi    a1,a1,-1",
+            answers[5]
+        );
+        assert_eq!("0x00000002 2", answers[6]);
+        assert_eq!(
+            "1 0x00000108 loop if a1 < 3 hits 1\n2 0x0000010c loop+4 hits 2",
+            answers[7]
+        );
+        assert_eq!(
+            "Breakpoint 1, 0x00000108 <loop> add     a0,a0,a1",
+            answers[9]
+        );
+        assert!(answers[10].starts_with("The program stopped"));
+    }
+
+    #[test]
+    fn test_until() {
+        let (_, answers) =
+            debug("until loop+8\nprint a0\nbreak loop\nadvance 0x110\nuntil 0x200\n");
+        assert_eq!("0x00000110 <loop+0x8> bne     a1,zero,-8", answers[0]);
+        assert_eq!("0x00000003 3", answers[1]);
+        // The breakpoint is reached first
+        assert_eq!(
+            "Breakpoint 1, 0x00000108 <loop> add     a0,a0,a1",
+            answers[3]
+        );
+        assert!(Command::parse("until").is_err());
+        assert!(answers[4].starts_with("Breakpoint 1"));
+    }
+
+    #[test]
+    fn test_time_travel() {
+        let (stopped, answers) = debug_recorded(
+            "continue\ngoto 3\nprint a0\nrs 2\nprint a1\ngoto 7\nprint a0\ngoto 20\ncontinue\n",
+            Some(4),
+        );
+        assert!(stopped.is_some());
+        assert!(answers[0].starts_with("The program stopped"));
+        assert_eq!("0x0000010c <loop+0x4> addi    a1,a1,-1", answers[1]);
+        assert_eq!("0x00000003 3", answers[2]);
+        assert!(answers[3].starts_with("0x00000104 "));
+        assert_eq!("0x00000000 0", answers[4]);
+        assert_eq!("0x00000005 5", answers[6]);
+        // Past the recording, goto runs forward again
+        assert!(answers[7].starts_with("The program stopped"));
+        assert_eq!(answers[7], answers[8]);
+
+        // Without a recording, goto only runs forward
+        let (_, answers) = debug("goto 3\nprint a0\nrs\n");
+        assert_eq!("0x00000003 3", answers[1]);
+        assert!(answers[2].starts_with("going back needs a recording"));
+    }
+}
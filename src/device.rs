@@ -0,0 +1,381 @@
+//! Peripherals modelled outside the simulator. An embedding application
+//! implements `Device` for its peripherals, in its own crate, and maps them
+//! into the address space of the hart next to the CLINT, the UART and the
+//! DMA engine. It can also register them by name in a `DeviceRegistry`, so
+//! that its users instantiate them from specs such as `timer@0x10002000`.
+//!
+//! The interrupt requests of the devices are ORed into the machine external
+//! interrupt.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::device::{Device, DeviceRegistry};
+//! # use adept_lib::hart::Hart;
+//! // Counter of the cycles since it was last written
+//! #[derive(Clone, Default)]
+//! struct Ticker(u32);
+//!
+//! impl Device for Ticker {
+//!     fn size(&self) -> u32 {
+//!         4
+//!     }
+//!
+//!     fn read(&mut self, _: u32, _: u32) -> u32 {
+//!         self.0
+//!     }
+//!
+//!     fn write(&mut self, _: u32, _: u32, value: u32) {
+//!         self.0 = value;
+//!     }
+//!
+//!     fn tick(&mut self) {
+//!         self.0 += 1;
+//!     }
+//!
+//!     fn box_clone(&self) -> Box<dyn Device> {
+//!         Box::new(self.clone())
+//!     }
+//! }
+//!
+//! let mut registry = DeviceRegistry::new();
+//! registry.register("ticker", |_| Ok(Box::new(Ticker::default())));
+//! let (base, ticker) = registry.create("ticker@0x20000000").unwrap();
+//! let mut hart = Hart::new();
+//! hart.add_device(base, ticker).unwrap();
+//! ```
+use clint::{CLINT_BASE, CLINT_SIZE};
+use dma::{DMA_BASE, DMA_SIZE};
+use uart::{UART_BASE, UART_SIZE};
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// A memory mapped peripheral
+pub trait Device: Send {
+    /// Size of the registers in bytes, starting at the base of the device
+    fn size(&self) -> u32;
+
+    /// Read the registers
+    ///
+    /// # Arguments
+    /// * `offset` => offset of the first byte from the base of the device
+    /// * `size` => number of bytes read, up to 4
+    ///
+    /// # Return Value
+    /// The bytes in little endian
+    fn read(&mut self, offset: u32, size: u32) -> u32;
+
+    /// Write the registers
+    ///
+    /// # Arguments
+    /// * `offset` => offset of the first byte from the base of the device
+    /// * `size` => number of bytes written, up to 4
+    /// * `value` => the bytes in little endian
+    fn write(&mut self, offset: u32, size: u32, value: u32);
+
+    /// Whether the register at an offset can only be written, so that
+    /// reading it is reported as a mistake of the program
+    fn write_only(&self, _offset: u32) -> bool {
+        false
+    }
+
+    /// Advance the device by a cycle of the platform, once per instruction
+    fn tick(&mut self) {}
+
+    /// Whether the device requests an interrupt
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Copy the device with its state, for snapshots of the hart
+    fn box_clone(&self) -> Box<dyn Device>;
+}
+
+impl Clone for Box<dyn Device> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Why a device can't be created or mapped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceError {
+    /// The spec isn't `NAME@ADDRESS` or `NAME@ADDRESS:ARGS`
+    InvalidSpec(String),
+    /// No device was registered with the name
+    UnknownDevice(String),
+    /// The factory of the device rejected its arguments
+    InvalidArguments { name: String, message: String },
+    /// The registers of the device would overlap those of another device
+    Overlap { base: u32, size: u32 },
+}
+
+impl Display for DeviceError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DeviceError::InvalidSpec(spec) => write!(f, "invalid device spec {:?}", spec),
+            DeviceError::UnknownDevice(name) => write!(f, "unknown device {}", name),
+            DeviceError::InvalidArguments { name, message } => write!(f, "{}: {}", name, message),
+            DeviceError::Overlap { base, size } => write!(
+                f,
+                "the registers at 0x{:08x}..0x{:08x} overlap another device",
+                base,
+                u64::from(*base) + u64::from(*size)
+            ),
+        }
+    }
+}
+
+impl Error for DeviceError {}
+
+/// Creates a device from the arguments of its spec
+pub type DeviceFactory = fn(&str) -> Result<Box<dyn Device>, String>;
+
+/// Devices known by name
+#[derive(Debug, Default, Clone)]
+pub struct DeviceRegistry {
+    factories: BTreeMap<String, DeviceFactory>,
+}
+
+impl DeviceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a device, replacing the one with the same name
+    ///
+    /// # Arguments
+    /// * `name` => name of the device in the specs
+    /// * `factory` => creates the device from its arguments, empty without
+    ///   them
+    pub fn register(&mut self, name: &str, factory: DeviceFactory) {
+        self.factories.insert(name.to_string(), factory);
+    }
+
+    /// Names of the registered devices, in order
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.keys().map(String::as_str).collect()
+    }
+
+    /// Create a device from a spec
+    ///
+    /// # Arguments
+    /// * `spec` => `NAME@ADDRESS` or `NAME@ADDRESS:ARGS`, with the address
+    ///   in decimal or hexadecimal
+    ///
+    /// # Return Value
+    /// The base address and the device
+    pub fn create(&self, spec: &str) -> Result<(u32, Box<dyn Device>), DeviceError> {
+        let invalid = || DeviceError::InvalidSpec(spec.to_string());
+        let at = spec.find('@').ok_or_else(invalid)?;
+        let name = &spec[..at];
+        let (addr, args) = match spec[at + 1..].find(':') {
+            Some(colon) => (&spec[at + 1..at + 1 + colon], &spec[at + 2 + colon..]),
+            None => (&spec[at + 1..], ""),
+        };
+        let base = if let Some(digits) = addr.strip_prefix("0x") {
+            u32::from_str_radix(digits, 16)
+        } else {
+            addr.parse()
+        }
+        .map_err(|_| invalid())?;
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| DeviceError::UnknownDevice(name.to_string()))?;
+        let device = factory(args).map_err(|message| DeviceError::InvalidArguments {
+            name: name.to_string(),
+            message,
+        })?;
+        Ok((base, device))
+    }
+}
+
+/// Devices mapped at their base addresses
+#[derive(Default, Clone)]
+pub struct DeviceMap {
+    devices: Vec<(u32, Box<dyn Device>)>,
+}
+
+impl DeviceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map a device at a base address, unless it overlaps the built-in
+    /// devices or another mapped device
+    pub fn add(&mut self, base: u32, device: Box<dyn Device>) -> Result<(), DeviceError> {
+        let size = device.size();
+        let end = u64::from(base) + u64::from(size);
+        let overlaps = |(other, other_size): (u32, u32)| {
+            u64::from(base) < u64::from(other) + u64::from(other_size) && u64::from(other) < end
+        };
+        let built_in = [
+            (CLINT_BASE, CLINT_SIZE),
+            (UART_BASE, UART_SIZE),
+            (DMA_BASE, DMA_SIZE),
+        ];
+        if size == 0
+            || built_in.iter().cloned().any(overlaps)
+            || self
+                .devices
+                .iter()
+                .any(|(other, device)| overlaps((*other, device.size())))
+        {
+            return Err(DeviceError::Overlap { base, size });
+        }
+        self.devices.push((base, device));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Read the registers of the device at an address
+    ///
+    /// # Return Value
+    /// The bytes in little endian, or None if no device answers at the
+    /// address
+    pub fn read(&mut self, addr: u32, size: u32) -> Option<u32> {
+        self.find(addr)
+            .map(|(base, device)| device.read(addr - base, size))
+    }
+
+    /// Write the registers of the device at an address
+    ///
+    /// # Return Value
+    /// False if no device answers at the address
+    pub fn write(&mut self, addr: u32, size: u32, value: u32) -> bool {
+        match self.find(addr) {
+            Some((base, device)) => {
+                device.write(addr - base, size, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the register at an address belongs to a device and can only
+    /// be written
+    pub fn write_only(&mut self, addr: u32) -> bool {
+        match self.find(addr) {
+            Some((base, device)) => device.write_only(addr - base),
+            None => false,
+        }
+    }
+
+    /// Advance all the devices by a cycle
+    pub fn tick(&mut self) {
+        for (_, device) in &mut self.devices {
+            device.tick();
+        }
+    }
+
+    /// Whether any device requests an interrupt
+    pub fn irq(&self) -> bool {
+        self.devices.iter().any(|(_, device)| device.irq())
+    }
+
+    fn find(&mut self, addr: u32) -> Option<(u32, &mut Box<dyn Device>)> {
+        self.devices
+            .iter_mut()
+            .find(|(base, device)| addr.wrapping_sub(*base) < device.size())
+            .map(|(base, device)| (*base, device))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Register which requests an interrupt while it's not 0
+    #[derive(Clone)]
+    struct Flag(u32);
+
+    impl Device for Flag {
+        fn size(&self) -> u32 {
+            0x10
+        }
+
+        fn read(&mut self, offset: u32, _: u32) -> u32 {
+            self.0 + offset
+        }
+
+        fn write(&mut self, _: u32, _: u32, value: u32) {
+            self.0 = value;
+        }
+
+        fn irq(&self) -> bool {
+            self.0 != 0
+        }
+
+        // The last word triggers the device
+        fn write_only(&self, offset: u32) -> bool {
+            offset >= 0xc
+        }
+
+        fn box_clone(&self) -> Box<dyn Device> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn flag(args: &str) -> Result<Box<dyn Device>, String> {
+        match args {
+            "" => Ok(Box::new(Flag(0))),
+            _ => args
+                .parse()
+                .map(|value| Box::new(Flag(value)) as Box<dyn Device>)
+                .map_err(|e| format!("invalid value {}: {}", args, e)),
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = DeviceRegistry::new();
+        registry.register("flag", flag);
+        assert_eq!(vec!["flag"], registry.names());
+        let (base, mut device) = registry.create("flag@0x2000:7").unwrap();
+        assert_eq!((0x2000, 7), (base, device.read(0, 4)));
+        assert_eq!(0x4000, registry.create("flag@16384").unwrap().0);
+        assert_eq!(
+            Some(DeviceError::UnknownDevice("gpio".to_string())),
+            registry.create("gpio@0x2000").err()
+        );
+        assert_eq!(
+            Some(DeviceError::InvalidSpec("flag".to_string())),
+            registry.create("flag").err()
+        );
+        assert_eq!(
+            "flag: invalid value x: invalid digit found in string",
+            registry.create("flag@0:x").err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_map() {
+        let mut map = DeviceMap::new();
+        map.add(0x2000, Box::new(Flag(0))).unwrap();
+        assert_eq!(
+            Err(DeviceError::Overlap {
+                base: 0x2008,
+                size: 0x10
+            }),
+            map.add(0x2008, Box::new(Flag(0)))
+        );
+        assert!(map.add(UART_BASE - 8, Box::new(Flag(0))).is_err());
+        map.add(0x1ff0, Box::new(Flag(0x100))).unwrap();
+
+        assert_eq!(Some(0x104), map.read(0x1ff4, 4));
+        assert_eq!(None, map.read(0x2010, 4));
+        assert!(map.irq());
+        assert!(map.write(0x1ff0, 4, 0));
+        assert!(!map.irq());
+        assert!(!map.write(0x3000, 4, 0));
+        assert!(map.write_only(0x1ffc));
+        assert!(!map.write_only(0x1ff8));
+        assert!(!map.write_only(0x3000));
+    }
+}
@@ -0,0 +1,173 @@
+//! Warnings about suspicious behavior of the program, which often comes from
+//! a misconfigured toolchain or linker script rather than from the program
+//! itself: computations whose result is discarded in x0, stores into the
+//! code, reads of registers which devices only let write, and jumps to
+//! memory where nothing was loaded. Each warning is logged once per
+//! instruction, with its pc.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::diagnostics::{Diagnostic, Diagnostics};
+//! let mut diagnostics = Diagnostics::new();
+//! diagnostics.add_code_region(0x100, 0x40);
+//! assert!(diagnostics.is_code(0x13c, 4));
+//! assert!(diagnostics.report(Diagnostic::StoreToCode { pc: 0x200, addr: 0x13c }));
+//! // The same store isn't reported again
+//! assert!(!diagnostics.report(Diagnostic::StoreToCode { pc: 0x200, addr: 0x120 }));
+//! assert_eq!(1, diagnostics.reported().len());
+//! ```
+use riscv::decoder::Instruction;
+
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+
+/// A suspicious action of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// A computation writing x0, in the HINT space, where its result is lost
+    WriteToZero { pc: u32, raw: u32 },
+    /// A store into the code of the program
+    StoreToCode { pc: u32, addr: u32 },
+    /// A load of a register which a device only lets write
+    WriteOnlyRead { pc: u32, addr: u32 },
+    /// A branch or jump to memory where nothing was loaded
+    UnmappedJump { pc: u32, target: u32 },
+}
+
+impl Diagnostic {
+    /// Address of the instruction
+    pub fn pc(&self) -> u32 {
+        match *self {
+            Diagnostic::WriteToZero { pc, .. }
+            | Diagnostic::StoreToCode { pc, .. }
+            | Diagnostic::WriteOnlyRead { pc, .. }
+            | Diagnostic::UnmappedJump { pc, .. } => pc,
+        }
+    }
+
+    // Index of the variant, which makes up with the pc the key of the
+    // reported diagnostics
+    fn kind(&self) -> u8 {
+        match self {
+            Diagnostic::WriteToZero { .. } => 0,
+            Diagnostic::StoreToCode { .. } => 1,
+            Diagnostic::WriteOnlyRead { .. } => 2,
+            Diagnostic::UnmappedJump { .. } => 3,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "pc 0x{:08x}: ", self.pc())?;
+        match self {
+            Diagnostic::WriteToZero { raw, .. } => write!(
+                f,
+                "{} writes x0, which discards its result",
+                Instruction::new(*raw)
+            ),
+            Diagnostic::StoreToCode { addr, .. } => {
+                write!(f, "store into the code at 0x{:08x}", addr)
+            }
+            Diagnostic::WriteOnlyRead { addr, .. } => {
+                write!(
+                    f,
+                    "read of the write-only device register at 0x{:08x}",
+                    addr
+                )
+            }
+            Diagnostic::UnmappedJump { target, .. } => {
+                write!(f, "jump to unmapped memory at 0x{:08x}", target)
+            }
+        }
+    }
+}
+
+/// Diagnostics of a run
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    // Base and size of the regions holding code
+    code: Vec<(u32, u32)>,
+    seen: HashSet<(u8, u32)>,
+    reported: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark a region as code, such as an executable segment of the ELF
+    pub fn add_code_region(&mut self, base: u32, size: u32) {
+        self.code.push((base, size));
+    }
+
+    /// Check if any byte of an access falls in the code
+    pub fn is_code(&self, addr: u32, size: u32) -> bool {
+        self.code.iter().any(|(base, region_size)| {
+            u64::from(addr) < u64::from(*base) + u64::from(*region_size)
+                && u64::from(*base) < u64::from(addr) + u64::from(size)
+        })
+    }
+
+    /// Log a diagnostic, unless the same one was already reported for the
+    /// instruction
+    ///
+    /// # Return Value
+    /// True if the diagnostic was logged
+    pub fn report(&mut self, diagnostic: Diagnostic) -> bool {
+        if !self.seen.insert((diagnostic.kind(), diagnostic.pc())) {
+            return false;
+        }
+        warn!("{}", diagnostic);
+        self.reported.push(diagnostic);
+        true
+    }
+
+    /// Diagnostics logged so far, in order
+    pub fn reported(&self) -> &[Diagnostic] {
+        &self.reported
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            "pc 0x00000010: add     zero,a0,a1 writes x0, which discards its result",
+            Diagnostic::WriteToZero {
+                pc: 0x10,
+                raw: 0x00b5_0033
+            }
+            .to_string()
+        );
+        assert_eq!(
+            "pc 0x00000010: jump to unmapped memory at 0x80000000",
+            Diagnostic::UnmappedJump {
+                pc: 0x10,
+                target: 0x8000_0000
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_report_once() {
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.add_code_region(0xffff_ff00, 0x100);
+        assert!(diagnostics.is_code(0xffff_fffe, 4));
+        assert!(!diagnostics.is_code(0xffff_fefc, 4));
+
+        let read = Diagnostic::WriteOnlyRead { pc: 4, addr: 0x10 };
+        assert!(diagnostics.report(read));
+        assert!(!diagnostics.report(read));
+        // Other kinds and other instructions are reported on their own
+        assert!(diagnostics.report(Diagnostic::StoreToCode { pc: 4, addr: 0 }));
+        assert!(diagnostics.report(Diagnostic::WriteOnlyRead { pc: 8, addr: 0x10 }));
+        assert_eq!(3, diagnostics.reported().len());
+    }
+}
@@ -0,0 +1,242 @@
+//! Functional comparison of two runs of a program. The microarchitecture,
+//! the pipeline depth, the latencies or the bus, must never change what a
+//! program computes: runs on two configurations have to stop for the same
+//! reason with the same pc, registers, CSRs, memory and output. The counters
+//! are left out, they count cycles.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::divergence::Divergence;
+//! # use adept_lib::mem::MemStoreOp;
+//! # use adept_lib::pipeline::{PipelineConfig, Stages};
+//! # use adept_lib::riscv::isa::RV32I;
+//! let mut runs = Vec::new();
+//! for stages in Stages::ALL.iter() {
+//!     let mut cpu = Cpu::new(PipelineConfig { stages: *stages, ..PipelineConfig::default() });
+//!     // addi a0, zero, 5
+//!     cpu.hart_mut().memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 0, 0x0050_0513).unwrap();
+//!     let reason = cpu.run();
+//!     runs.push((cpu, reason));
+//! }
+//! assert_eq!(None, Divergence::find((&runs[0].0, runs[0].1), (&runs[1].0, runs[1].1)));
+//! ```
+use cpu::Cpu;
+use csr::{MCAUSE, MEPC, MIE, MSCRATCH, MSTATUS, MTVAL, MTVEC};
+use hart::StopReason;
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+
+// CSRs of the architectural state, all but the counters
+const COMPARED_CSRS: [u16; 7] = [MSTATUS, MIE, MTVEC, MSCRATCH, MEPC, MCAUSE, MTVAL];
+
+/// First difference between two runs, with the values of the first run and
+/// then of the second one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Divergence {
+    StopReason(StopReason, StopReason),
+    Pc(u32, u32),
+    Register {
+        index: u8,
+        values: (i32, i32),
+    },
+    Csr {
+        csr: u16,
+        values: (u32, u32),
+    },
+    /// A byte of the memory, None if it was never written
+    Memory {
+        addr: u32,
+        values: (Option<u8>, Option<u8>),
+    },
+    /// A byte of the output, None past its end
+    Output {
+        offset: usize,
+        values: (Option<u8>, Option<u8>),
+    },
+}
+
+impl Divergence {
+    /// Compare two runs
+    ///
+    /// # Arguments
+    /// * `first` => processor of the first run and why it stopped
+    /// * `second` => processor of the second run and why it stopped
+    ///
+    /// # Return Value
+    /// The first difference, in the order of the variants of `Divergence`
+    /// and then by register number or address, or None if the runs agree
+    pub fn find(first: (&Cpu, StopReason), second: (&Cpu, StopReason)) -> Option<Divergence> {
+        let (a, b) = (first.0.hart(), second.0.hart());
+        if first.1 != second.1 {
+            return Some(Divergence::StopReason(first.1, second.1));
+        }
+        if a.pc() != b.pc() {
+            return Some(Divergence::Pc(a.pc(), b.pc()));
+        }
+        for index in 1..32 {
+            let values = (
+                a.registers().read(index, 0).0,
+                b.registers().read(index, 0).0,
+            );
+            if values.0 != values.1 {
+                return Some(Divergence::Register { index, values });
+            }
+        }
+        for csr in COMPARED_CSRS.iter() {
+            let values = (a.csrs().read(*csr), b.csrs().read(*csr));
+            if values.0 != values.1 {
+                return Some(Divergence::Csr {
+                    csr: *csr,
+                    values: (values.0.unwrap_or(0), values.1.unwrap_or(0)),
+                });
+            }
+        }
+        let (pages_a, pages_b) = (a.memory().pages(), b.memory().pages());
+        let bases: BTreeSet<u32> = pages_a.iter().chain(&pages_b).map(|page| page.0).collect();
+        for base in bases {
+            let page_a = pages_a
+                .iter()
+                .find(|page| page.0 == base)
+                .map(|page| page.1);
+            let page_b = pages_b
+                .iter()
+                .find(|page| page.0 == base)
+                .map(|page| page.1);
+            let size = page_a.or(page_b).map_or(0, <[u8]>::len);
+            for offset in 0..size {
+                let values = (
+                    page_a.map(|page| page[offset]),
+                    page_b.map(|page| page[offset]),
+                );
+                if values.0 != values.1 {
+                    return Some(Divergence::Memory {
+                        addr: base + offset as u32,
+                        values,
+                    });
+                }
+            }
+        }
+        let (output_a, output_b) = (a.output(), b.output());
+        (0..output_a.len().max(output_b.len()))
+            .map(|offset| {
+                (
+                    offset,
+                    (output_a.get(offset).cloned(), output_b.get(offset).cloned()),
+                )
+            })
+            .find(|(_, values)| values.0 != values.1)
+            .map(|(offset, values)| Divergence::Output { offset, values })
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Divergence::StopReason(a, b) => write!(f, "stop reason: {} vs {}", a, b),
+            Divergence::Pc(a, b) => write!(f, "pc: 0x{:08x} vs 0x{:08x}", a, b),
+            Divergence::Register { index, values } => {
+                write!(f, "x{}: 0x{:08x} vs 0x{:08x}", index, values.0, values.1)
+            }
+            Divergence::Csr { csr, values } => write!(
+                f,
+                "csr 0x{:03x}: 0x{:08x} vs 0x{:08x}",
+                csr, values.0, values.1
+            ),
+            Divergence::Memory { addr, values } => write!(
+                f,
+                "memory at 0x{:08x}: {} vs {}",
+                addr,
+                byte(values.0, "unwritten"),
+                byte(values.1, "unwritten")
+            ),
+            Divergence::Output { offset, values } => write!(
+                f,
+                "output byte {}: {} vs {}",
+                offset,
+                byte(values.0, "end"),
+                byte(values.1, "end")
+            ),
+        }
+    }
+}
+
+fn byte(value: Option<u8>, missing: &str) -> String {
+    value.map_or_else(|| missing.to_string(), |value| format!("0x{:02x}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem::MemStoreOp;
+    use pipeline::{PipelineConfig, Stages};
+    use riscv::isa::RV32I;
+
+    // Run a program to its first invalid instruction
+    fn run(stages: Stages, program: &[u32]) -> (Cpu, StopReason) {
+        let mut cpu = Cpu::new(PipelineConfig {
+            stages,
+            ..PipelineConfig::default()
+        });
+        for (index, word) in program.iter().enumerate() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), (index as u32) << 2, *word)
+                .unwrap();
+        }
+        let reason = cpu.run();
+        (cpu, reason)
+    }
+
+    #[test]
+    fn test_same_results() {
+        // addi a0, zero, 5; sw a0, 64(zero)
+        let program = [0x0050_0513, 0x04a0_2023];
+        let one = run(Stages::One, &program);
+        let three = run(Stages::Three, &program);
+        assert_ne!(one.0.stats().cycles, three.0.stats().cycles);
+        assert_eq!(None, Divergence::find((&one.0, one.1), (&three.0, three.1)));
+    }
+
+    #[test]
+    fn test_divergences() {
+        let (mut a, reason) = run(Stages::One, &[0x0050_0513]);
+        let (mut b, _) = run(Stages::Three, &[0x0050_0513]);
+        b.hart_mut().registers_mut().write(11, 1);
+        assert_eq!(
+            "x11: 0x00000000 vs 0x00000001",
+            Divergence::find((&a, reason), (&b, reason))
+                .unwrap()
+                .to_string()
+        );
+        b.hart_mut().registers_mut().write(11, 0);
+
+        b.hart_mut()
+            .memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SB), 0x2001, 0)
+            .unwrap();
+        assert_eq!(
+            Some(Divergence::Memory {
+                addr: 0x2000,
+                values: (None, Some(0))
+            }),
+            Divergence::find((&a, reason), (&b, reason))
+        );
+        a.hart_mut()
+            .memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SB), 0x2001, 1)
+            .unwrap();
+        assert_eq!(
+            "memory at 0x00002001: 0x01 vs 0x00",
+            Divergence::find((&a, reason), (&b, reason))
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            Some(Divergence::StopReason(reason, StopReason::Exit(0))),
+            Divergence::find((&a, reason), (&b, StopReason::Exit(0)))
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! DMA engine of the Adept platform, which copies a region of the memory in
+//! the background while the hart keeps executing. A transfer moves
+//! `DMA_BYTES_PER_CYCLE` bytes per instruction, so copying `n` bytes takes
+//! `n / DMA_BYTES_PER_CYCLE` instructions rounded up. When an instruction
+//! loads or stores to the memory during a transfer, the arbiter of the bus
+//! grants it to the core or the engine, and the other one waits. Once the transfer finishes the engine raises the machine
+//! external interrupt, if enabled, until the done bit of the status is
+//! cleared.
+//!
+//! | Offset | Register | Fields                                          |
+//! |--------|----------|-------------------------------------------------|
+//! | 0x00   | source   | address of the first byte read                  |
+//! | 0x04   | dest     | address of the first byte written               |
+//! | 0x08   | length   | number of bytes copied                          |
+//! | 0x0c   | control  | bit 0 starts a transfer, bit 1 enables the IRQ  |
+//! | 0x10   | status   | bit 0 busy, bit 1 done, bit 2 error; write 1 to |
+//! |        |          | clear done and error                            |
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::dma::{Dma, DMA_BASE};
+//! # use adept_lib::mem::{Memory, MemLoadOp, MemStoreOp};
+//! # use adept_lib::riscv::isa::RV32I;
+//! let mut memory = Memory::new();
+//! memory.write_data(&MemStoreOp::from(RV32I::SW), 0x100, 0x1234_5678).unwrap();
+//! let mut dma = Dma::new();
+//! dma.write(DMA_BASE, 4, 0x100);
+//! dma.write(DMA_BASE + 0x4, 4, 0x200);
+//! dma.write(DMA_BASE + 0x8, 4, 4);
+//! dma.write(DMA_BASE + 0xc, 4, 0b11);
+//! assert!(dma.busy());
+//! dma.tick(&mut memory, false);
+//! assert!(dma.interrupt_pending());
+//! assert_eq!(Ok(0x1234_5678), memory.load_data(&MemLoadOp::from(RV32I::LW), 0x200));
+//! ```
+use mem::{MemLoadOp, MemStoreOp, Memory};
+
+/// First address of the DMA engine
+pub const DMA_BASE: u32 = 0x1000_1000;
+/// Size of the DMA engine registers in bytes
+pub const DMA_SIZE: u32 = 0x100;
+/// Bytes copied by the engine every cycle the bus is free
+pub const DMA_BYTES_PER_CYCLE: u32 = 4;
+
+// Offsets of the registers
+const SOURCE: u32 = 0x00;
+const DEST: u32 = 0x04;
+const LENGTH: u32 = 0x08;
+const CONTROL: u32 = 0x0c;
+const STATUS: u32 = 0x10;
+// Fields of the control
+const CONTROL_START: u32 = 1 << 0;
+const CONTROL_INTERRUPT: u32 = 1 << 1;
+// Fields of the status
+const STATUS_BUSY: u32 = 1 << 0;
+const STATUS_DONE: u32 = 1 << 1;
+const STATUS_ERROR: u32 = 1 << 2;
+
+// Progress of the transfer in flight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Transfer {
+    source: u32,
+    dest: u32,
+    remaining: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Dma {
+    source: u32,
+    dest: u32,
+    length: u32,
+    interrupt_enabled: bool,
+    done: bool,
+    error: bool,
+    transfer: Option<Transfer>,
+    transfers: u64,
+    bytes_copied: u64,
+}
+
+impl Dma {
+    /// Create an idle DMA engine
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check if an address belongs to the DMA engine
+    pub fn contains(&self, addr: u32) -> bool {
+        addr.wrapping_sub(DMA_BASE) < DMA_SIZE
+    }
+
+    /// Read the registers
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first byte
+    /// * `size` => number of bytes read, up to 4
+    ///
+    /// # Return Value
+    /// The bytes in little endian, unmapped bytes read as 0
+    pub fn read(&self, addr: u32, size: u32) -> u32 {
+        (0..size).fold(0, |value, index| {
+            let offset = addr.wrapping_add(index).wrapping_sub(DMA_BASE);
+            let byte = self.read_register(offset & !3) >> ((offset & 3) << 3);
+            value | (byte & 0xff) << (index << 3)
+        })
+    }
+
+    /// Write the registers. The start bit takes effect on the byte holding
+    /// it, so the transfer registers must be written first.
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first byte
+    /// * `size` => number of bytes written, up to 4
+    /// * `value` => the bytes in little endian
+    pub fn write(&mut self, addr: u32, size: u32, value: u32) {
+        for index in 0..size {
+            let offset = addr.wrapping_add(index).wrapping_sub(DMA_BASE);
+            self.write_byte(offset, (value >> (index << 3)) as u8);
+        }
+    }
+
+    /// Advance the transfer in flight by one cycle
+    ///
+    /// # Arguments
+    /// * `memory` => memory read and written by the transfer
+    /// * `bus_taken` => set if the core was granted the bus during the
+    ///   cycle, which stalls the engine
+    pub fn tick(&mut self, memory: &mut Memory, bus_taken: bool) {
+        if !bus_taken {
+            self.copy(memory, DMA_BYTES_PER_CYCLE);
+        }
+    }
+
+    /// Copy the rest of the transfer in flight at once, used to skip the
+    /// idle time of a wait for interrupt
+    pub fn finish(&mut self, memory: &mut Memory) {
+        if let Some(transfer) = self.transfer {
+            self.copy(memory, transfer.remaining);
+        }
+    }
+
+    /// Check if a transfer is in flight
+    pub fn busy(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    /// Transfers which finished without an error
+    pub fn transfers(&self) -> u64 {
+        self.transfers
+    }
+
+    /// Bytes copied by every transfer
+    pub fn bytes_copied(&self) -> u64 {
+        self.bytes_copied
+    }
+
+    /// Check if the completion interrupt is pending
+    pub fn interrupt_pending(&self) -> bool {
+        self.interrupt_enabled && (self.done || self.error)
+    }
+
+    // Start a transfer with the current registers, unless one is in flight
+    fn start(&mut self) {
+        if self.transfer.is_none() {
+            self.done = false;
+            self.error = false;
+            self.transfer = Some(Transfer {
+                source: self.source,
+                dest: self.dest,
+                remaining: self.length,
+            });
+        }
+    }
+
+    // Copy up to a number of bytes of the transfer in flight. A byte which
+    // can't be read or written aborts the transfer with an error.
+    fn copy(&mut self, memory: &mut Memory, bytes: u32) {
+        let mut transfer = match self.transfer {
+            Some(transfer) => transfer,
+            None => return,
+        };
+        for _ in 0..bytes.min(transfer.remaining) {
+            let copied = memory
+                .load_data(&MemLoadOp::LoadByteUnsigned, transfer.source)
+                .and_then(|byte| {
+                    memory.write_data(&MemStoreOp::StoreByte, transfer.dest, byte as u32)
+                });
+            if copied.is_err() {
+                self.error = true;
+                transfer.remaining = 0;
+                break;
+            }
+            transfer.source = transfer.source.wrapping_add(1);
+            transfer.dest = transfer.dest.wrapping_add(1);
+            transfer.remaining -= 1;
+            self.bytes_copied += 1;
+        }
+        if transfer.remaining == 0 {
+            self.done = !self.error;
+            if self.done {
+                self.transfers += 1;
+            }
+            self.transfer = None;
+        } else {
+            self.transfer = Some(transfer);
+        }
+    }
+
+    // Read the register at a word aligned offset from the base
+    fn read_register(&self, offset: u32) -> u32 {
+        match offset {
+            SOURCE => self.source,
+            DEST => self.dest,
+            LENGTH => self.length,
+            CONTROL => {
+                let start = if self.busy() { CONTROL_START } else { 0 };
+                let interrupt = if self.interrupt_enabled {
+                    CONTROL_INTERRUPT
+                } else {
+                    0
+                };
+                start | interrupt
+            }
+            STATUS => {
+                let mut status = 0;
+                if self.busy() {
+                    status |= STATUS_BUSY;
+                }
+                if self.done {
+                    status |= STATUS_DONE;
+                }
+                if self.error {
+                    status |= STATUS_ERROR;
+                }
+                status
+            }
+            _ => 0,
+        }
+    }
+
+    // Write one byte of a register at an offset from the base. The upper
+    // bytes of the control and the status have no fields.
+    fn write_byte(&mut self, offset: u32, byte: u8) {
+        let shift = (offset & 3) << 3;
+        let replace = |register: u32| register & !(0xff << shift) | u32::from(byte) << shift;
+        match offset {
+            _ if offset & !3 == SOURCE => self.source = replace(self.source),
+            _ if offset & !3 == DEST => self.dest = replace(self.dest),
+            _ if offset & !3 == LENGTH => self.length = replace(self.length),
+            CONTROL => {
+                self.interrupt_enabled = u32::from(byte) & CONTROL_INTERRUPT != 0;
+                if u32::from(byte) & CONTROL_START != 0 {
+                    self.start();
+                }
+            }
+            STATUS => {
+                if u32::from(byte) & STATUS_DONE != 0 {
+                    self.done = false;
+                }
+                if u32::from(byte) & STATUS_ERROR != 0 {
+                    self.error = false;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use riscv::isa::RV32I;
+
+    fn start(dma: &mut Dma, source: u32, dest: u32, length: u32) {
+        dma.write(DMA_BASE + SOURCE, 4, source);
+        dma.write(DMA_BASE + DEST, 4, dest);
+        dma.write(DMA_BASE + LENGTH, 4, length);
+        dma.write(DMA_BASE + CONTROL, 4, CONTROL_START | CONTROL_INTERRUPT);
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut memory = Memory::new();
+        for addr in (0x100..0x10a).step_by(4) {
+            memory
+                .write_data(&MemStoreOp::from(RV32I::SW), addr, addr)
+                .unwrap();
+        }
+        let mut dma = Dma::new();
+        assert!(dma.contains(DMA_BASE + STATUS));
+        assert!(!dma.contains(DMA_BASE + DMA_SIZE));
+        start(&mut dma, 0x100, 0x201, 10);
+        assert_eq!(STATUS_BUSY, dma.read(DMA_BASE + STATUS, 4));
+
+        // Ten bytes take three cycles, plus the one the core took the bus
+        dma.tick(&mut memory, false);
+        dma.tick(&mut memory, true);
+        dma.tick(&mut memory, false);
+        assert!(dma.busy());
+        assert!(!dma.interrupt_pending());
+        dma.tick(&mut memory, false);
+        assert!(!dma.busy());
+        assert!(dma.interrupt_pending());
+        assert_eq!(STATUS_DONE, dma.read(DMA_BASE + STATUS, 4));
+        assert_eq!((1, 10), (dma.transfers(), dma.bytes_copied()));
+        assert_eq!(
+            Ok(0x0001_0000),
+            memory.load_data(&MemLoadOp::from(RV32I::LW), 0x200)
+        );
+        assert_eq!(
+            Ok(0x0108),
+            memory.load_data(&MemLoadOp::from(RV32I::LHU), 0x209)
+        );
+        assert_eq!(Ok(0), memory.load_data(&MemLoadOp::from(RV32I::LBU), 0x20b));
+
+        dma.write(DMA_BASE + STATUS, 4, STATUS_DONE);
+        assert!(!dma.interrupt_pending());
+        assert_eq!(0x100, dma.read(DMA_BASE + SOURCE, 4));
+        assert_eq!(0x02, dma.read(DMA_BASE + DEST + 1, 1));
+    }
+
+    #[test]
+    fn test_finish() {
+        let mut memory = Memory::new();
+        let mut dma = Dma::new();
+        start(&mut dma, 0, 0x1000, 0x100);
+        assert_eq!(
+            CONTROL_START | CONTROL_INTERRUPT,
+            dma.read(DMA_BASE + CONTROL, 4)
+        );
+        dma.finish(&mut memory);
+        assert!(!dma.busy());
+        assert!(dma.interrupt_pending());
+        assert_eq!(CONTROL_INTERRUPT, dma.read(DMA_BASE + CONTROL, 4));
+    }
+
+    #[test]
+    fn test_error() {
+        let mut memory = Memory::new();
+        memory.map_rom(0x1000, vec![0; 4]);
+        let mut dma = Dma::new();
+        start(&mut dma, 0, 0x1000, 4);
+        dma.tick(&mut memory, false);
+        assert!(dma.interrupt_pending());
+        assert_eq!(STATUS_ERROR, dma.read(DMA_BASE + STATUS, 4));
+        assert_eq!(0, dma.transfers());
+        dma.write(DMA_BASE + STATUS, 1, STATUS_ERROR);
+        assert_eq!(0, dma.read(DMA_BASE + STATUS, 4));
+    }
+}
@@ -0,0 +1,755 @@
+//! Helpers to inspect the ELF files given to the simulator. Only 32-bit
+//! little endian RISC-V files are supported.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::elf::entry_point;
+//! let mut header = vec![0; 52];
+//! header[..7].copy_from_slice(&[0x7f, b'E', b'L', b'F', 1, 1, 1]);
+//! header[18] = 0xf3;
+//! header[24..28].copy_from_slice(&[0x00, 0x10, 0x00, 0x00]);
+//! assert_eq!(Some(0x1000), entry_point(&header));
+//! ```
+
+use std::cmp::Ordering;
+
+// Header fields
+const MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const CLASS_32: u8 = 1;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+const MACHINE_RISCV: u16 = 0xf3;
+const HEADER_SIZE: usize = 52;
+
+const TYPE_EXECUTABLE: u16 = 2;
+const VERSION_CURRENT: u8 = 1;
+
+// Program header fields
+const PROGRAM_HEADER_SIZE: usize = 32;
+const SEGMENT_TYPE_LOAD: u32 = 1;
+
+/// Permission of a segment to be executed
+pub const SEGMENT_EXECUTE: u32 = 1;
+/// Permission of a segment to be written
+pub const SEGMENT_WRITE: u32 = 2;
+/// Permission of a segment to be read
+pub const SEGMENT_READ: u32 = 4;
+
+// Section header fields
+const SECTION_HEADER_SIZE: usize = 40;
+const SECTION_TYPE_SYMTAB: u32 = 2;
+
+/// Flag of a section taking memory while the program runs
+pub const SECTION_ALLOC: u32 = 2;
+/// Flag of a section holding instructions
+pub const SECTION_EXECINSTR: u32 = 4;
+const SYMBOL_SIZE: usize = 16;
+const SYMBOL_TYPE_FUNC: u8 = 2;
+
+/// Function or variable found in the symbol tables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u32,
+    pub size: u32,
+}
+
+impl Symbol {
+    /// Find the function holding an address
+    ///
+    /// # Arguments
+    /// * `functions` => functions sorted by address, as returned by
+    ///   `functions`
+    /// * `addr` => address of an instruction
+    ///
+    /// # Return Value
+    /// The last function starting at or before the address which holds it,
+    /// or None if no function does
+    pub fn lookup(functions: &[Symbol], addr: u32) -> Option<&Symbol> {
+        let index = match functions.binary_search_by(|symbol| match symbol.value.cmp(&addr) {
+            // Find the last function starting at the address
+            Ordering::Equal => Ordering::Less,
+            ordering => ordering,
+        }) {
+            Ok(index) | Err(index) => index.checked_sub(1)?,
+        };
+        let symbol = &functions[index];
+        // Symbols without a size extend up to the next function
+        if symbol.size == 0 || addr - symbol.value < symbol.size {
+            Some(symbol)
+        } else {
+            None
+        }
+    }
+}
+
+/// Loadable segment of an ELF file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// Physical address the segment is loaded at
+    pub address: u32,
+    /// Bytes stored in the file, which may be fewer than the segment size
+    pub contents: Vec<u8>,
+    /// Size of the segment in memory. The bytes after the contents, such as
+    /// the BSS, are zero.
+    pub size: u32,
+    /// Permissions, a combination of `SEGMENT_READ`, `SEGMENT_WRITE` and
+    /// `SEGMENT_EXECUTE`
+    pub flags: u32,
+}
+
+impl Segment {
+    /// Number of zero bytes after the contents
+    pub fn zero_size(&self) -> u32 {
+        self.size - self.contents.len() as u32
+    }
+
+    /// Permissions in the rwx form of readelf
+    pub fn permissions(&self) -> String {
+        [
+            (SEGMENT_READ, 'r'),
+            (SEGMENT_WRITE, 'w'),
+            (SEGMENT_EXECUTE, 'x'),
+        ]
+        .iter()
+        .map(|(flag, name)| if self.flags & flag != 0 { *name } else { '-' })
+        .collect()
+    }
+}
+
+/// Section of an ELF file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    /// Address in memory, 0 for sections which don't take memory
+    pub address: u32,
+    pub size: u32,
+    /// Combination of `SECTION_ALLOC`, `SECTION_EXECINSTR` and other flags
+    pub flags: u32,
+}
+
+impl Section {
+    /// Check if the section holds instructions
+    pub fn is_executable(&self) -> bool {
+        self.flags & SECTION_EXECINSTR != 0
+    }
+
+    /// Check if the section overlaps `size` bytes from an address
+    pub fn overlaps(&self, address: u32, size: u32) -> bool {
+        u64::from(address) < u64::from(self.address) + u64::from(self.size)
+            && u64::from(self.address) < u64::from(address) + u64::from(size)
+    }
+}
+
+/// Get the sections of an ELF file
+///
+/// # Arguments
+/// * `data` => contents of the file
+///
+/// # Return Value
+/// The sections in the order of the section headers, without the null
+/// section, or None if it isn't a 32-bit little endian RISC-V ELF or a
+/// section header doesn't fit in the file
+pub fn sections(data: &[u8]) -> Option<Vec<Section>> {
+    if !is_supported(data) {
+        return None;
+    }
+
+    let section_offset = read_u32(data, 32)? as usize;
+    let section_size = read_u16(data, 46)? as usize;
+    let section_count = read_u16(data, 48)? as usize;
+    if section_count > 0 && section_size < SECTION_HEADER_SIZE {
+        return None;
+    }
+    let section = |index: usize| section_offset.checked_add(index.checked_mul(section_size)?);
+    // The names are stored in the section given by the ELF header, if any
+    let names = match read_u16(data, 50)? as usize {
+        0 => None,
+        index => Some(read_u32(data, section(index)? + 16)? as usize),
+    };
+
+    let mut sections = Vec::new();
+    for index in 1..section_count {
+        let header = section(index)?;
+        let name = match names {
+            Some(names) => {
+                let offset = names.checked_add(read_u32(data, header)? as usize)?;
+                String::from_utf8_lossy(read_string(data, offset)?).into_owned()
+            }
+            None => String::new(),
+        };
+        sections.push(Section {
+            name,
+            flags: read_u32(data, header + 8)?,
+            address: read_u32(data, header + 12)?,
+            size: read_u32(data, header + 20)?,
+        });
+    }
+    Some(sections)
+}
+
+/// Get the loadable segments of an ELF file
+///
+/// # Arguments
+/// * `data` => contents of the file
+///
+/// # Return Value
+/// The segments which take memory, in the order of the program headers, or
+/// None if it isn't a 32-bit little endian RISC-V ELF or a segment doesn't
+/// fit in the file or in its size
+pub fn segments(data: &[u8]) -> Option<Vec<Segment>> {
+    if !is_supported(data) {
+        return None;
+    }
+
+    let header_offset = read_u32(data, 28)? as usize;
+    let header_size = read_u16(data, 42)? as usize;
+    let header_count = read_u16(data, 44)? as usize;
+    if header_count > 0 && header_size < PROGRAM_HEADER_SIZE {
+        return None;
+    }
+
+    let mut segments = Vec::new();
+    for index in 0..header_count {
+        let header = header_offset.checked_add(index.checked_mul(header_size)?)?;
+        if read_u32(data, header)? != SEGMENT_TYPE_LOAD {
+            continue;
+        }
+        let offset = read_u32(data, header + 4)? as usize;
+        let file_size = read_u32(data, header + 16)?;
+        let size = read_u32(data, header + 20)?;
+        if file_size > size {
+            return None;
+        }
+        if size == 0 {
+            continue;
+        }
+        let contents = data.get(offset..offset.checked_add(file_size as usize)?)?;
+        segments.push(Segment {
+            address: read_u32(data, header + 12)?,
+            contents: contents.to_vec(),
+            size,
+            flags: read_u32(data, header + 24)?,
+        });
+    }
+    Some(segments)
+}
+
+/// Get the entry point of an ELF file
+///
+/// # Arguments
+/// * `data` => contents of the file
+///
+/// # Return Value
+/// The entry point, or None if it isn't a 32-bit little endian RISC-V ELF
+pub fn entry_point(data: &[u8]) -> Option<u32> {
+    if !is_supported(data) {
+        return None;
+    }
+    read_u32(data, 24)
+}
+
+/// Find the value of a symbol in the symbol tables of an ELF file
+///
+/// # Arguments
+/// * `data` => contents of the file
+/// * `name` => name of the symbol
+///
+/// # Return Value
+/// The value of the first symbol with that name, or None if there's no such
+/// symbol
+pub fn symbol(data: &[u8], name: &str) -> Option<u32> {
+    find_symbol(data, |symbol_name, value, _, _| {
+        if symbol_name == name.as_bytes() {
+            Some(value)
+        } else {
+            None
+        }
+    })
+}
+
+/// Get the functions of the symbol tables of an ELF file
+///
+/// # Arguments
+/// * `data` => contents of the file
+///
+/// # Return Value
+/// The functions sorted by address, which is empty if the file has no symbol
+/// table
+pub fn functions(data: &[u8]) -> Vec<Symbol> {
+    let mut functions = Vec::new();
+    find_symbol(data, |name, value, size, info| -> Option<()> {
+        if info & 0xf == SYMBOL_TYPE_FUNC {
+            functions.push(Symbol {
+                name: String::from_utf8_lossy(name).into_owned(),
+                value,
+                size,
+            });
+        }
+        None
+    });
+    functions.sort_by_key(|symbol| symbol.value);
+    functions
+}
+
+/// Get the functions, the variables and the labels of the symbol tables of
+/// an ELF file, to resolve names given by the user
+///
+/// # Arguments
+/// * `data` => contents of the file
+///
+/// # Return Value
+/// The named symbols sorted by address, without the sections and the files
+pub fn symbols(data: &[u8]) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    find_symbol(data, |name, value, size, info| -> Option<()> {
+        if !name.is_empty() && info & 0xf <= SYMBOL_TYPE_FUNC {
+            symbols.push(Symbol {
+                name: String::from_utf8_lossy(name).into_owned(),
+                value,
+                size,
+            });
+        }
+        None
+    });
+    symbols.sort_by_key(|symbol| symbol.value);
+    symbols
+}
+
+/// Write an executable ELF file, without sections, which loads segments in
+/// memory and starts at its entry point
+///
+/// # Arguments
+/// * `entry` => address of the first instruction
+/// * `segments` => segments to load, with their contents and permissions
+///
+/// # Return Value
+/// The contents of the file
+pub fn executable(entry: u32, segments: &[Segment]) -> Vec<u8> {
+    let mut data = vec![0; HEADER_SIZE];
+    data[..4].copy_from_slice(&MAGIC);
+    data[4] = CLASS_32;
+    data[5] = DATA_LITTLE_ENDIAN;
+    data[6] = VERSION_CURRENT;
+    data[16..18].copy_from_slice(&TYPE_EXECUTABLE.to_le_bytes());
+    data[18..20].copy_from_slice(&MACHINE_RISCV.to_le_bytes());
+    data[20..24].copy_from_slice(&u32::from(VERSION_CURRENT).to_le_bytes());
+    data[24..28].copy_from_slice(&entry.to_le_bytes());
+    data[28..32].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+    data[40..42].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+    data[42..44].copy_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    data[44..46].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+    data[46..48].copy_from_slice(&(SECTION_HEADER_SIZE as u16).to_le_bytes());
+
+    // The contents follow the program headers, aligned to words
+    let mut offset = HEADER_SIZE + segments.len() * PROGRAM_HEADER_SIZE;
+    let mut contents = Vec::new();
+    for segment in segments {
+        for field in &[
+            SEGMENT_TYPE_LOAD,
+            offset as u32,
+            segment.address,
+            segment.address,
+            segment.contents.len() as u32,
+            segment.size,
+            segment.flags,
+            4,
+        ] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        contents.extend_from_slice(&segment.contents);
+        while contents.len() % 4 != 0 {
+            contents.push(0);
+        }
+        offset = HEADER_SIZE + segments.len() * PROGRAM_HEADER_SIZE + contents.len();
+    }
+    data.extend_from_slice(&contents);
+    data
+}
+
+// Visit the name, value, size and info of every symbol until `visit` returns
+// a value
+fn find_symbol<T, F>(data: &[u8], mut visit: F) -> Option<T>
+where
+    F: FnMut(&[u8], u32, u32, u8) -> Option<T>,
+{
+    if !is_supported(data) {
+        return None;
+    }
+
+    let section_offset = read_u32(data, 32)? as usize;
+    let section_size = read_u16(data, 46)? as usize;
+    let section_count = read_u16(data, 48)? as usize;
+    let section = |index: usize| section_offset.checked_add(index.checked_mul(section_size)?);
+
+    for index in 0..section_count {
+        let header = section(index)?;
+        if read_u32(data, header + 4)? != SECTION_TYPE_SYMTAB {
+            continue;
+        }
+        let symbols = read_u32(data, header + 16)? as usize;
+        let symbols_size = read_u32(data, header + 20)? as usize;
+        // The names are stored in the string table linked to the symbol table
+        let strings =
+            read_u32(data, section(read_u32(data, header + 24)? as usize)? + 16)? as usize;
+
+        for symbol in (symbols..symbols.saturating_add(symbols_size)).step_by(SYMBOL_SIZE) {
+            let name = read_string(data, strings.checked_add(read_u32(data, symbol)? as usize)?)?;
+            let info = *data.get(symbol + 12)?;
+            let found = visit(
+                name,
+                read_u32(data, symbol + 4)?,
+                read_u32(data, symbol + 8)?,
+                info,
+            );
+            if found.is_some() {
+                return found;
+            }
+        }
+    }
+
+    None
+}
+
+// Check if the file is a 32-bit little endian RISC-V ELF
+fn is_supported(data: &[u8]) -> bool {
+    data.len() >= HEADER_SIZE
+        && data[..4] == MAGIC
+        && data[4] == CLASS_32
+        && data[5] == DATA_LITTLE_ENDIAN
+        && read_u16(data, 18) == Some(MACHINE_RISCV)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from(bytes[0]) | u16::from(bytes[1]) << 8)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(
+        u32::from(bytes[0])
+            | u32::from(bytes[1]) << 8
+            | u32::from(bytes[2]) << 16
+            | u32::from(bytes[3]) << 24,
+    )
+}
+
+// Null terminated string starting at `offset`, without the terminator
+fn read_string(data: &[u8], offset: usize) -> Option<&[u8]> {
+    let bytes = data.get(offset..)?;
+    let length = bytes.iter().position(|byte| *byte == 0)?;
+    Some(&bytes[..length])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Vec<u8> {
+        let mut header = vec![0; HEADER_SIZE];
+        header[..6].copy_from_slice(&[0x7f, b'E', b'L', b'F', 1, 1]);
+        header[18] = 0xf3;
+        header[24..28].copy_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+        header
+    }
+
+    fn push_u32(data: &mut Vec<u8>, value: u32) {
+        data.extend_from_slice(&[
+            value as u8,
+            (value >> 8) as u8,
+            (value >> 16) as u8,
+            (value >> 24) as u8,
+        ]);
+    }
+
+    // File with a symbol table holding objects without a size
+    fn with_symbols(symbols: &[(&str, u32)]) -> Vec<u8> {
+        let entries: Vec<_> = symbols
+            .iter()
+            .map(|(name, value)| (*name, *value, 0, 1))
+            .collect();
+        with_entries(&entries)
+    }
+
+    // File with a symbol table holding the name, value, size and info of
+    // every entry and its string table
+    fn with_entries(symbols: &[(&str, u32, u32, u8)]) -> Vec<u8> {
+        let mut data = header();
+
+        let mut strings = vec![0];
+        let mut table = vec![0; SYMBOL_SIZE];
+        for (name, value, size, info) in symbols {
+            push_u32(&mut table, strings.len() as u32);
+            push_u32(&mut table, *value);
+            push_u32(&mut table, *size);
+            table.extend_from_slice(&[*info, 0, 0, 0]);
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+
+        let strings_offset = data.len() as u32;
+        data.extend_from_slice(&strings);
+        let table_offset = data.len() as u32;
+        data.extend_from_slice(&table);
+
+        // Null section, string table and symbol table
+        let section_offset = data.len() as u32;
+        data.extend_from_slice(&[0; 40]);
+        for (section_type, offset, size, link) in &[
+            (3, strings_offset, strings.len() as u32, 0),
+            (SECTION_TYPE_SYMTAB, table_offset, table.len() as u32, 1),
+        ] {
+            for field in &[0, *section_type, 0, 0, *offset, *size, *link, 0, 0, 0] {
+                push_u32(&mut data, *field);
+            }
+        }
+
+        data[32..36].copy_from_slice(&section_offset.to_le_bytes());
+        data[46] = 40;
+        data[48] = 3;
+        data
+    }
+
+    // File with a section for every name, flags, address and size, and the
+    // string table of their names
+    fn with_sections(sections: &[(&str, u32, u32, u32)]) -> Vec<u8> {
+        let mut data = header();
+        let mut strings = vec![0];
+        let mut headers = vec![0; SECTION_HEADER_SIZE];
+        for (name, flags, address, size) in
+            sections.iter().cloned().chain(Some((".shstrtab", 0, 0, 0)))
+        {
+            for field in &[strings.len() as u32, 1, flags, address, 0, size, 0, 0, 0, 0] {
+                push_u32(&mut headers, *field);
+            }
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+
+        // The string table is the last section
+        let strings_offset = data.len() as u32;
+        let last = headers.len() - SECTION_HEADER_SIZE;
+        headers[last + 16..last + 24].copy_from_slice(
+            &[
+                strings_offset.to_le_bytes(),
+                (strings.len() as u32).to_le_bytes(),
+            ]
+            .concat(),
+        );
+        data.extend_from_slice(&strings);
+        let section_offset = data.len() as u32;
+        data.extend_from_slice(&headers);
+
+        data[32..36].copy_from_slice(&section_offset.to_le_bytes());
+        data[46] = SECTION_HEADER_SIZE as u8;
+        data[48] = sections.len() as u8 + 2;
+        data[50] = sections.len() as u8 + 1;
+        data
+    }
+
+    // File with a program header for every type, file and memory size and
+    // flags of a segment, followed by their contents
+    fn with_segments(segments: &[(u32, &[u8], u32, u32)]) -> Vec<u8> {
+        let mut data = header();
+        let mut contents_offset = (HEADER_SIZE + segments.len() * PROGRAM_HEADER_SIZE) as u32;
+        for (index, (segment_type, contents, size, flags)) in segments.iter().enumerate() {
+            let address = 0x1000 * (index as u32 + 1);
+            for field in &[
+                *segment_type,
+                contents_offset,
+                address,
+                address,
+                contents.len() as u32,
+                *size,
+                *flags,
+                4,
+            ] {
+                push_u32(&mut data, *field);
+            }
+            contents_offset += contents.len() as u32;
+        }
+        for (_, contents, _, _) in segments {
+            data.extend_from_slice(contents);
+        }
+
+        data[28..32].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data[42] = PROGRAM_HEADER_SIZE as u8;
+        data[44] = segments.len() as u8;
+        data
+    }
+
+    #[test]
+    fn test_segments() {
+        let data = with_segments(&[
+            (
+                SEGMENT_TYPE_LOAD,
+                &[0x13, 0, 0, 0, 0x6f],
+                5,
+                SEGMENT_READ | SEGMENT_EXECUTE,
+            ),
+            // Notes aren't loaded
+            (4, &[1, 2, 3, 4], 4, SEGMENT_READ),
+            // Data of 3 bytes followed by the BSS
+            (
+                SEGMENT_TYPE_LOAD,
+                &[1, 2, 3],
+                0x103,
+                SEGMENT_READ | SEGMENT_WRITE,
+            ),
+            (SEGMENT_TYPE_LOAD, &[], 0, SEGMENT_READ),
+        ]);
+        let segments = segments(&data).unwrap();
+        assert_eq!(2, segments.len());
+        assert_eq!(0x1000, segments[0].address);
+        assert_eq!(vec![0x13, 0, 0, 0, 0x6f], segments[0].contents);
+        assert_eq!(0, segments[0].zero_size());
+        assert_eq!("r-x", segments[0].permissions());
+
+        assert_eq!(0x3000, segments[1].address);
+        assert_eq!(vec![1, 2, 3], segments[1].contents);
+        assert_eq!(0x100, segments[1].zero_size());
+        assert_eq!("rw-", segments[1].permissions());
+    }
+
+    #[test]
+    fn test_invalid_segments() {
+        // More bytes in the file than in memory
+        let data = with_segments(&[(SEGMENT_TYPE_LOAD, &[1, 2, 3, 4], 2, SEGMENT_READ)]);
+        assert_eq!(None, segments(&data));
+        // Contents past the end of the file
+        let data = with_segments(&[(SEGMENT_TYPE_LOAD, &[1, 2, 3, 4], 4, SEGMENT_READ)]);
+        assert_eq!(None, segments(&data[..data.len() - 1]));
+        assert_eq!(Some(Vec::new()), segments(&header()));
+    }
+
+    #[test]
+    fn test_entry_point() {
+        assert_eq!(Some(0x1234_5678), entry_point(&header()));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert_eq!(None, entry_point(&header()[..40]));
+
+        let mut big_endian = header();
+        big_endian[5] = 2;
+        assert_eq!(None, entry_point(&big_endian));
+
+        let mut x86 = header();
+        x86[18] = 3;
+        assert_eq!(None, entry_point(&x86));
+
+        let mut text = header();
+        text[..4].copy_from_slice(b"#!/b");
+        assert_eq!(None, entry_point(&text));
+    }
+
+    #[test]
+    fn test_symbol() {
+        let data = with_symbols(&[("main", 0x100), ("tohost", 0x8000_1000)]);
+        assert_eq!(Some(0x8000_1000), symbol(&data, "tohost"));
+        assert_eq!(Some(0x100), symbol(&data, "main"));
+        assert_eq!(None, symbol(&data, "fromhost"));
+        assert_eq!(None, symbol(&data, "tohos"));
+        // Files without sections
+        assert_eq!(None, symbol(&header(), "main"));
+    }
+
+    #[test]
+    fn test_truncated_symbols() {
+        let mut data = with_symbols(&[("tohost", 0x8000_1000)]);
+        // Make the symbol table run past the end of the file
+        let size = data.len() - 40 + 20;
+        data[size..size + 4].copy_from_slice(&[0xf0, 0xff, 0xff, 0xff]);
+        assert_eq!(Some(0x8000_1000), symbol(&data, "tohost"));
+        assert_eq!(None, symbol(&data, "fromhost"));
+    }
+
+    #[test]
+    fn test_functions() {
+        let data = with_entries(&[
+            ("main", 0x200, 0x20, SYMBOL_TYPE_FUNC),
+            ("buffer", 0x100, 0x10, 1),
+            // Global functions
+            ("_start", 0x100, 0x8, 0x10 | SYMBOL_TYPE_FUNC),
+            ("loop", 0x300, 0, SYMBOL_TYPE_FUNC),
+        ]);
+        let functions = functions(&data);
+        let names: Vec<_> = functions
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+        assert_eq!(vec!["_start", "main", "loop"], names);
+        assert!(super::functions(&header()).is_empty());
+
+        let lookup = |addr| Symbol::lookup(&functions, addr).map(|symbol| symbol.name.as_str());
+        assert_eq!(None, lookup(0xfc));
+        assert_eq!(Some("_start"), lookup(0x104));
+        assert_eq!(None, lookup(0x108));
+        assert_eq!(Some("main"), lookup(0x200));
+        assert_eq!(Some("main"), lookup(0x21c));
+        assert_eq!(None, lookup(0x220));
+        assert_eq!(Some("loop"), lookup(0x1000));
+    }
+
+    #[test]
+    fn test_sections() {
+        let data = with_sections(&[
+            (".text", SECTION_ALLOC | SECTION_EXECINSTR, 0x100, 0x40),
+            (".data", SECTION_ALLOC, 0x140, 0x10),
+        ]);
+        let sections = sections(&data).unwrap();
+        let names: Vec<_> = sections
+            .iter()
+            .map(|section| section.name.as_str())
+            .collect();
+        assert_eq!(vec![".text", ".data", ".shstrtab"], names);
+        assert!(sections[0].is_executable());
+        assert!(!sections[1].is_executable());
+        assert!(sections[0].overlaps(0x13c, 8));
+        assert!(!sections[0].overlaps(0x140, 8));
+        assert!(!sections[0].overlaps(0xfc, 4));
+        assert_eq!(None, super::sections(&data[..data.len() - 20]));
+        assert_eq!(Some(Vec::new()), super::sections(&header()));
+    }
+
+    #[test]
+    fn test_symbols() {
+        let data = with_entries(&[
+            ("main", 0x200, 0x20, SYMBOL_TYPE_FUNC),
+            ("buffer", 0x100, 0x10, 1),
+            ("loop", 0x300, 0, 0),
+            // Section
+            (".text", 0x100, 0, 3),
+        ]);
+        let names: Vec<_> = symbols(&data)
+            .into_iter()
+            .map(|symbol| symbol.name)
+            .collect();
+        assert_eq!(vec!["buffer", "main", "loop"], names);
+    }
+
+    #[test]
+    fn test_executable() {
+        let segments = vec![
+            Segment {
+                address: 0x1000,
+                contents: vec![0x13, 0, 0, 0, 0x73],
+                size: 5,
+                flags: SEGMENT_READ | SEGMENT_EXECUTE,
+            },
+            Segment {
+                address: 0x2000,
+                contents: vec![1, 2, 3, 4],
+                size: 16,
+                flags: SEGMENT_READ | SEGMENT_WRITE,
+            },
+        ];
+        let data = executable(0x1000, &segments);
+        assert_eq!(Some(0x1000), entry_point(&data));
+        assert_eq!(Some(segments), self::segments(&data));
+        assert_eq!(Some(Vec::new()), sections(&data));
+        assert!(functions(&data).is_empty());
+    }
+}
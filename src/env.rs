@@ -0,0 +1,329 @@
+//! Execution environment of the guest programs. Programs linked against a C
+//! library such as newlib expect a stack pointer and a heap when they start,
+//! and ask for services with `ecall`. The system call numbers are the ones of
+//! the RISC-V Linux ABI, which newlib uses as well. The arguments of `main`
+//! are passed in the standard argument block at the initial stack pointer.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::env::Heap;
+//! let mut heap = Heap::new(0x1000, 0x2000);
+//! // brk(0) reports the current break
+//! assert_eq!(0x1000, heap.set_brk(0));
+//! assert_eq!(0x1800, heap.set_brk(0x1800));
+//! // The break can't leave the heap
+//! assert_eq!(0x1800, heap.set_brk(0x3000));
+//! ```
+use mem::{MemStoreOp, Memory};
+use riscv::isa::RV32I;
+
+/// Write a2 bytes from the address in a1 to the file descriptor in a0
+pub const SYS_WRITE: u32 = 64;
+/// Terminate the program, with the exit code in a0
+pub const SYS_EXIT: u32 = 93;
+/// Write the time since the start, from mtime, to the struct timeval at the
+/// address in a0
+pub const SYS_GETTIMEOFDAY: u32 = 169;
+/// Move the program break to the address in a0
+pub const SYS_BRK: u32 = 214;
+
+/// Size of the stack when it isn't given
+pub const DEFAULT_STACK_SIZE: u32 = 64 << 10;
+/// Size of the guard regions around the stack and the heap
+pub const GUARD_SIZE: u32 = 4 << 10;
+
+// Alignment of the start of the heap and of the stack pointer
+const ALIGNMENT: u32 = 16;
+
+/// Memory region handed out through the brk system call
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Heap {
+    start: u32,
+    end: u32,
+    brk: u32,
+}
+
+impl Heap {
+    /// Create a heap with the break at its start
+    ///
+    /// # Arguments
+    /// * `start` => first address of the heap
+    /// * `end` => address after the last one of the heap
+    pub fn new(start: u32, end: u32) -> Self {
+        assert!(start <= end, "the heap ends before it starts");
+        Heap {
+            start,
+            end,
+            brk: start,
+        }
+    }
+
+    /// Create a heap between the end of the program and the stack
+    ///
+    /// # Arguments
+    /// * `image_end` => address after the last word of the program
+    /// * `stack_pointer` => initial stack pointer
+    /// * `size` => size of the heap, or None to use everything up to
+    ///   `reserved` bytes below the stack pointer
+    /// * `reserved` => space kept for the stack and its guard
+    pub fn below_stack(
+        image_end: u32,
+        stack_pointer: u32,
+        size: Option<u32>,
+        reserved: u32,
+    ) -> Self {
+        let start = match image_end % ALIGNMENT {
+            0 => image_end,
+            rem => image_end.saturating_add(ALIGNMENT - rem),
+        };
+        let end = match size {
+            Some(size) => start.saturating_add(size),
+            None => stack_pointer.saturating_sub(reserved).max(start),
+        };
+        Heap::new(start, end)
+    }
+
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// Current program break
+    pub fn brk(&self) -> u32 {
+        self.brk
+    }
+
+    /// Move the program break. Like the Linux system call, a break outside
+    /// of the heap is ignored so brk(0) reports the current break.
+    ///
+    /// # Arguments
+    /// * `brk` => requested break
+    ///
+    /// # Return Value
+    /// The program break after the request
+    pub fn set_brk(&mut self, brk: u32) -> u32 {
+        if brk >= self.start && brk <= self.end {
+            self.brk = brk;
+        }
+        self.brk
+    }
+}
+
+/// What a guard region protects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardKind {
+    /// The region below the stack, reached when the stack overflows
+    Stack,
+    /// The region after the heap, reached by accesses past its end
+    Heap,
+}
+
+impl GuardKind {
+    /// Likely cause of an access to the region
+    pub fn diagnostic(self) -> &'static str {
+        match self {
+            GuardKind::Stack => "probable stack overflow",
+            GuardKind::Heap => "access past the end of the heap",
+        }
+    }
+}
+
+/// Memory region which faults when it's accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guard {
+    pub start: u32,
+    pub size: u32,
+    pub kind: GuardKind,
+}
+
+impl Guard {
+    /// Create the guard below a stack
+    ///
+    /// # Arguments
+    /// * `stack_top` => initial stack pointer
+    /// * `stack_size` => size of the stack
+    pub fn below_stack(stack_top: u32, stack_size: u32) -> Self {
+        Guard {
+            start: stack_top.wrapping_sub(stack_size).wrapping_sub(GUARD_SIZE),
+            size: GUARD_SIZE,
+            kind: GuardKind::Stack,
+        }
+    }
+
+    /// Create the guard after a heap
+    pub fn after_heap(heap: &Heap) -> Self {
+        Guard {
+            start: heap.end(),
+            size: GUARD_SIZE,
+            kind: GuardKind::Heap,
+        }
+    }
+
+    /// Check if an access touches the region
+    ///
+    /// # Arguments
+    /// * `addr` => first address of the access
+    /// * `size` => number of bytes accessed
+    pub fn overlaps(&self, addr: u32, size: u32) -> bool {
+        (0..size).any(|offset| addr.wrapping_add(offset).wrapping_sub(self.start) < self.size)
+    }
+}
+
+/// Location of the arguments of the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArgumentBlock {
+    /// Stack pointer below the block, where argc is stored
+    pub stack_pointer: u32,
+    pub argc: u32,
+    /// Address of the null terminated array of arguments
+    pub argv: u32,
+    /// Address of the null terminated array of environment variables
+    pub envp: u32,
+}
+
+/// Write the argument block below the stack pointer. From the stack pointer
+/// up, the block holds argc, the argv and envp arrays, an empty auxiliary
+/// vector and the strings they point to.
+///
+/// # Arguments
+/// * `memory` => memory of the guest
+/// * `stack_pointer` => initial stack pointer of the program
+/// * `args` => arguments, starting with the name of the program
+/// * `vars` => environment variables, as NAME=VALUE
+///
+/// # Return Value
+/// The new stack pointer and the addresses of the arrays
+pub fn write_arguments(
+    memory: &mut Memory,
+    stack_pointer: u32,
+    args: &[&str],
+    vars: &[&str],
+) -> ArgumentBlock {
+    // Strings are stored first, at the top of the stack
+    let mut top = stack_pointer;
+    let mut pointers = Vec::with_capacity(args.len() + vars.len());
+    for string in args.iter().chain(vars.iter()) {
+        top = top.wrapping_sub(string.len() as u32 + 1);
+        for (offset, byte) in string.bytes().chain(Some(0)).enumerate() {
+            let address = top.wrapping_add(offset as u32);
+            // This call to unwrap is safe because bytes are always aligned
+            memory
+                .write_data(&MemStoreOp::from(RV32I::SB), address, u32::from(byte))
+                .unwrap();
+        }
+        pointers.push(top);
+    }
+    let (arg_pointers, var_pointers) = pointers.split_at(args.len());
+
+    // argc, argv, NULL, envp, NULL and the AT_NULL auxiliary entry
+    let mut words = vec![args.len() as u32];
+    words.extend_from_slice(arg_pointers);
+    words.push(0);
+    words.extend_from_slice(var_pointers);
+    words.extend_from_slice(&[0, 0, 0]);
+
+    let size = (words.len() as u32) << 2;
+    let stack_pointer = top.wrapping_sub(size) & !(ALIGNMENT - 1);
+    for (index, word) in words.iter().enumerate() {
+        let address = stack_pointer.wrapping_add((index as u32) << 2);
+        // This call to unwrap is safe because the stack pointer was aligned
+        memory
+            .write_data(&MemStoreOp::from(RV32I::SW), address, *word)
+            .unwrap();
+    }
+
+    let argv = stack_pointer.wrapping_add(4);
+    ArgumentBlock {
+        stack_pointer,
+        argc: args.len() as u32,
+        argv,
+        envp: argv.wrapping_add((args.len() as u32 + 1) << 2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem::MemLoadOp;
+
+    #[test]
+    fn test_set_brk() {
+        let mut heap = Heap::new(0x100, 0x200);
+        assert_eq!(0x100, heap.set_brk(0));
+        assert_eq!(0x200, heap.set_brk(0x200));
+        assert_eq!(0x200, heap.set_brk(0x201));
+        // The heap can shrink back to its start
+        assert_eq!(0x100, heap.set_brk(0x100));
+        assert_eq!(0x100, heap.brk());
+    }
+
+    #[test]
+    fn test_below_stack() {
+        let heap = Heap::below_stack(0x1234, 0x0080_0000, None, DEFAULT_STACK_SIZE);
+        assert_eq!(0x1240, heap.start());
+        assert_eq!(0x0080_0000 - DEFAULT_STACK_SIZE, heap.end());
+
+        let heap = Heap::below_stack(0x1000, 0x0080_0000, Some(0x100), DEFAULT_STACK_SIZE);
+        assert_eq!((0x1000, 0x1100), (heap.start(), heap.end()));
+
+        // No room between the program and the stack
+        let heap = Heap::below_stack(0x8000, 0x9000, None, DEFAULT_STACK_SIZE);
+        assert_eq!((0x8000, 0x8000), (heap.start(), heap.end()));
+    }
+
+    #[test]
+    fn test_guards() {
+        let guard = Guard::below_stack(0x0010_0000, 0x1_0000);
+        assert_eq!(0x000e_f000, guard.start);
+        assert!(guard.overlaps(0x000e_effe, 4));
+        assert!(guard.overlaps(0x000e_ffff, 1));
+        assert!(!guard.overlaps(0x000f_0000, 4));
+        assert!(!guard.overlaps(0x000e_effc, 4));
+
+        let guard = Guard::after_heap(&Heap::new(0x1000, 0x2000));
+        assert_eq!((0x2000, GuardKind::Heap), (guard.start, guard.kind));
+        assert_eq!("access past the end of the heap", guard.kind.diagnostic());
+    }
+
+    // Read a null terminated string from memory
+    fn read_string(memory: &Memory, mut address: u32) -> String {
+        let mut string = String::new();
+        loop {
+            let byte = memory
+                .load_data(&MemLoadOp::from(RV32I::LBU), address)
+                .unwrap() as u8;
+            if byte == 0 {
+                return string;
+            }
+            string.push(byte as char);
+            address += 1;
+        }
+    }
+
+    #[test]
+    fn test_write_arguments() {
+        let mut memory = Box::new(Memory::new());
+        let block = write_arguments(&mut memory, 0x1000, &["prog", "-n", "10"], &["HOME=/"]);
+
+        assert_eq!(0, block.stack_pointer % 16);
+        assert!(block.stack_pointer < 0x1000 - 18);
+        assert_eq!(3, block.argc);
+        assert_eq!(block.stack_pointer + 4, block.argv);
+        assert_eq!(block.argv + 16, block.envp);
+
+        let word = |address| memory.read_pc(address);
+        assert_eq!(3, word(block.stack_pointer));
+        assert_eq!("prog", read_string(&memory, word(block.argv)));
+        assert_eq!("-n", read_string(&memory, word(block.argv + 4)));
+        assert_eq!("10", read_string(&memory, word(block.argv + 8)));
+        assert_eq!(0, word(block.argv + 12));
+        assert_eq!("HOME=/", read_string(&memory, word(block.envp)));
+        assert_eq!(0, word(block.envp + 4));
+        // Auxiliary vector
+        assert_eq!((0, 0), (word(block.envp + 8), word(block.envp + 12)));
+    }
+}
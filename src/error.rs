@@ -0,0 +1,200 @@
+//! Errors of the library as a single type, for the programs embedding the
+//! simulator which must recover from them rather than stop. The errors of the
+//! modules convert to it, and those raised with a cause keep it as their
+//! source:
+//!
+//! * load => a program, an image or another input which can't be read or
+//!   understood, with the path of the input
+//! * decode => bits which aren't an instruction or a register
+//! * memory => a memory access which failed
+//! * device => a device which can't be created or mapped
+//! * invalid => a value which can't set what it was given for, such as the
+//!   size of a memory or an option of the command line
+//! * runtime => a failure while the program runs or its results are
+//!   written, such as a lost connection or a full disk
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::Error;
+//! # use std::error::Error as StdError;
+//! # use std::io;
+//! let error = Error::load("hello.elf", io::Error::new(io::ErrorKind::NotFound, "no such file"));
+//! assert_eq!("hello.elf", error.to_string());
+//! assert!(error.source().is_some());
+//! assert_eq!("hello.elf: no such file", error.full_message());
+//! ```
+use adept_core::riscv::DecodeError;
+#[cfg(feature = "sim")]
+use device::DeviceError;
+#[cfg(feature = "sim")]
+use mem::MemError;
+
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+
+/// Cause of an error, of any type
+pub type Source = Box<dyn StdError + Send + Sync>;
+
+/// Error of the library
+#[derive(Debug)]
+pub enum Error {
+    /// An input couldn't be read or understood
+    Load { path: String, source: Source },
+    /// Bits which aren't an instruction or a register
+    Decode(DecodeError),
+    /// A memory access failed
+    #[cfg(feature = "sim")]
+    Memory(MemError),
+    /// A device can't be created or mapped
+    #[cfg(feature = "sim")]
+    Device(DeviceError),
+    /// A value can't set what it was given for
+    Invalid(String),
+    /// Something failed while the program ran or once it stopped
+    Runtime {
+        context: String,
+        source: Option<Source>,
+    },
+}
+
+impl Error {
+    /// Create the error of an input
+    ///
+    /// # Arguments
+    /// * `path` => path of the input, or what it is if it isn't a file
+    /// * `source` => why it can't be loaded, an error or a message
+    pub fn load<E: Into<Source>>(path: &str, source: E) -> Self {
+        Error::Load {
+            path: path.to_string(),
+            source: source.into(),
+        }
+    }
+
+    /// Create the error of a value which isn't valid, as "invalid NAME:
+    /// VALUE"
+    pub fn invalid<V: Display>(name: &str, value: V) -> Self {
+        Error::Invalid(format!("invalid {}: {}", name, value))
+    }
+
+    /// Create the error of a failure while running
+    ///
+    /// # Arguments
+    /// * `context` => what failed, such as a port or the path of an output
+    /// * `source` => why it failed, an error or a message
+    pub fn runtime<E: Into<Source>>(context: &str, source: E) -> Self {
+        Error::Runtime {
+            context: context.to_string(),
+            source: Some(source.into()),
+        }
+    }
+
+    /// Message of the error followed by those of its sources, separated by
+    /// colons
+    pub fn full_message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = self.source();
+        while let Some(error) = source {
+            message.push_str(": ");
+            message.push_str(&error.to_string());
+            source = error.source();
+        }
+        message
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::Load { path, .. } => write!(f, "{}", path),
+            Error::Decode(error) => error.fmt(f),
+            #[cfg(feature = "sim")]
+            Error::Memory(error) => error.fmt(f),
+            #[cfg(feature = "sim")]
+            Error::Device(error) => error.fmt(f),
+            Error::Invalid(message) => write!(f, "{}", message),
+            Error::Runtime { context, .. } => write!(f, "{}", context),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Load { source, .. } => Some(&**source),
+            Error::Runtime {
+                source: Some(source),
+                ..
+            } => Some(&**source),
+            _ => None,
+        }
+    }
+}
+
+/// Conversion from DecodeError
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Error {
+        Error::Decode(err)
+    }
+}
+
+/// Conversion from MemError
+#[cfg(feature = "sim")]
+impl From<MemError> for Error {
+    fn from(err: MemError) -> Error {
+        Error::Memory(err)
+    }
+}
+
+/// Conversion from DeviceError
+#[cfg(feature = "sim")]
+impl From<DeviceError> for Error {
+    fn from(err: DeviceError) -> Error {
+        Error::Device(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+
+    #[test]
+    fn test_source_chain() {
+        let error = Error::runtime(
+            "debug port 3333",
+            io::Error::new(io::ErrorKind::AddrInUse, "address in use"),
+        );
+        assert_eq!("debug port 3333", error.to_string());
+        assert_eq!("debug port 3333: address in use", error.full_message());
+        // Messages are sources too
+        let error = Error::load("a.elf", "invalid program headers");
+        assert_eq!("a.elf: invalid program headers", error.full_message());
+        let error = Error::invalid("memory bits", 40);
+        assert_eq!("invalid memory bits: 40", error.full_message());
+        assert!(error.source().is_none());
+    }
+
+    #[cfg(feature = "sim")]
+    #[test]
+    fn test_conversions() {
+        use mem::{MemAccess, MemErrorKind};
+
+        let error: Error = DecodeError::InvalidRegister(40).into();
+        assert_eq!("invalid register x40", error.to_string());
+        let error: Error = MemError {
+            kind: MemErrorKind::Misaligned,
+            addr: 0x102,
+            size: 4,
+            access: MemAccess::Load,
+        }
+        .into();
+        assert_eq!(
+            "misaligned load at 0x00000102 (4 bytes)",
+            error.full_message()
+        );
+        let error: Error = DeviceError::UnknownDevice("gpio".to_string()).into();
+        assert_eq!("unknown device gpio", error.to_string());
+    }
+}
@@ -0,0 +1,570 @@
+//! Expressions of the debugger, such as `sp+16` or `*(u16*)(a0+8)`. The
+//! operands are numbers, registers by ABI name or as xN, the pc and the
+//! symbols of the program, combined with the operators of C. Values are 32
+//! bits wide and wrap around; comparisons are unsigned.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::expr::Expr;
+//! # use adept_lib::hart::Hart;
+//! let mut hart = Hart::new();
+//! hart.registers_mut().write(2, 0x1000);
+//! let expr = Expr::parse("sp + 16 * 2").unwrap();
+//! assert_eq!(Ok(0x1020), expr.evaluate(&hart, &[]));
+//! ```
+use elf::Symbol;
+use hart::Hart;
+use mem::{MemError, MemLoadOp};
+use riscv::labels::get_register_number;
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Errors of the parsing and of the evaluation of expressions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprError {
+    /// The text isn't a valid expression
+    Parse(String),
+    /// No register nor symbol has the name
+    UnknownName(String),
+    /// A load of the expression failed
+    Memory(MemError),
+    /// Division or remainder by zero
+    DivisionByZero,
+}
+
+impl Display for ExprError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ExprError::Parse(message) => write!(f, "invalid expression: {}", message),
+            ExprError::UnknownName(name) => write!(f, "no register or symbol named {}", name),
+            ExprError::Memory(error) => write!(f, "can't read the memory: {}", error),
+            ExprError::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+impl Error for ExprError {}
+
+/// Type of a value read from the memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+}
+
+impl Width {
+    /// The type with a C-like name, such as u16
+    pub fn parse(name: &str) -> Option<Width> {
+        match name {
+            "u8" => Some(Width::U8),
+            "i8" => Some(Width::I8),
+            "u16" => Some(Width::U16),
+            "i16" => Some(Width::I16),
+            "u32" => Some(Width::U32),
+            "i32" => Some(Width::I32),
+            _ => None,
+        }
+    }
+
+    /// Load of the memory reading a value of the type
+    pub fn load_op(self) -> MemLoadOp {
+        match self {
+            Width::U8 => MemLoadOp::LoadByteUnsigned,
+            Width::I8 => MemLoadOp::LoadByte,
+            Width::U16 => MemLoadOp::LoadHalfUnsigned,
+            Width::I16 => MemLoadOp::LoadHalf,
+            Width::U32 | Width::I32 => MemLoadOp::LoadWord,
+        }
+    }
+}
+
+/// Operators with a single operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    LogicalNot,
+}
+
+/// Operators with two operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Xor,
+    Or,
+    LogicalAnd,
+    LogicalOr,
+}
+
+// Binary operators by their text, the longest first, and their precedence,
+// the highest binding the tightest
+const BINARY_OPS: [(&str, BinaryOp, u8); 18] = [
+    ("||", BinaryOp::LogicalOr, 1),
+    ("&&", BinaryOp::LogicalAnd, 2),
+    ("==", BinaryOp::Eq, 6),
+    ("!=", BinaryOp::Ne, 6),
+    ("<=", BinaryOp::Le, 7),
+    (">=", BinaryOp::Ge, 7),
+    ("<<", BinaryOp::Shl, 8),
+    (">>", BinaryOp::Shr, 8),
+    ("|", BinaryOp::Or, 3),
+    ("^", BinaryOp::Xor, 4),
+    ("&", BinaryOp::And, 5),
+    ("<", BinaryOp::Lt, 7),
+    (">", BinaryOp::Gt, 7),
+    ("+", BinaryOp::Add, 9),
+    ("-", BinaryOp::Sub, 9),
+    ("*", BinaryOp::Mul, 10),
+    ("/", BinaryOp::Div, 10),
+    ("%", BinaryOp::Rem, 10),
+];
+
+// Punctuation which isn't a binary operator
+const PUNCTUATION: [&str; 5] = ["(", ")", "~", "!", ".."];
+
+/// A parsed expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(u32),
+    Register(u8),
+    Pc,
+    /// Address of a symbol of the program
+    Symbol(String),
+    /// Pointer to a type, which only changes how it's dereferenced
+    Cast(Width, Box<Expr>),
+    /// Value of a type read from the memory at an address
+    Load(Width, Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Parse an expression
+    ///
+    /// # Arguments
+    /// * `text` => the whole text is the expression
+    pub fn parse(text: &str) -> Result<Expr, ExprError> {
+        let mut parser = Parser::new(text)?;
+        let expr = parser.expr()?;
+        parser.finish()?;
+        Ok(expr)
+    }
+
+    /// Replace the symbols of the expression by their addresses, to
+    /// evaluate it later without the symbols
+    pub fn resolve(&self, symbols: &[Symbol]) -> Result<Expr, ExprError> {
+        let resolve = |expr: &Expr| expr.resolve(symbols).map(Box::new);
+        Ok(match self {
+            Expr::Symbol(name) => match symbols.iter().find(|symbol| symbol.name == *name) {
+                Some(symbol) => Expr::Number(symbol.value),
+                None => return Err(ExprError::UnknownName(name.clone())),
+            },
+            Expr::Cast(width, expr) => Expr::Cast(*width, resolve(expr)?),
+            Expr::Load(width, expr) => Expr::Load(*width, resolve(expr)?),
+            Expr::Unary(op, expr) => Expr::Unary(*op, resolve(expr)?),
+            Expr::Binary(op, left, right) => Expr::Binary(*op, resolve(left)?, resolve(right)?),
+            expr => expr.clone(),
+        })
+    }
+
+    /// Compute the value of the expression in the current state of a hart
+    ///
+    /// # Arguments
+    /// * `hart` => registers and memory read by the expression
+    /// * `symbols` => symbols of the program which the expression can name
+    pub fn evaluate(&self, hart: &Hart, symbols: &[Symbol]) -> Result<u32, ExprError> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Register(register) => Ok(hart.registers().read(*register, 0).0 as u32),
+            Expr::Pc => Ok(hart.pc()),
+            Expr::Symbol(name) => match symbols.iter().find(|symbol| symbol.name == *name) {
+                Some(symbol) => Ok(symbol.value),
+                None => Err(ExprError::UnknownName(name.clone())),
+            },
+            Expr::Cast(_, expr) => expr.evaluate(hart, symbols),
+            Expr::Load(width, addr) => {
+                let addr = addr.evaluate(hart, symbols)?;
+                match hart.memory().load_data(&width.load_op(), addr) {
+                    Ok(value) => Ok(value as u32),
+                    Err(error) => Err(ExprError::Memory(error)),
+                }
+            }
+            Expr::Unary(op, expr) => {
+                let value = expr.evaluate(hart, symbols)?;
+                Ok(match op {
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Not => !value,
+                    UnaryOp::LogicalNot => u32::from(value == 0),
+                })
+            }
+            // The right operand of a logical operator is only evaluated when
+            // needed, like in C
+            Expr::Binary(BinaryOp::LogicalAnd, left, right) => Ok(u32::from(
+                left.evaluate(hart, symbols)? != 0 && right.evaluate(hart, symbols)? != 0,
+            )),
+            Expr::Binary(BinaryOp::LogicalOr, left, right) => Ok(u32::from(
+                left.evaluate(hart, symbols)? != 0 || right.evaluate(hart, symbols)? != 0,
+            )),
+            Expr::Binary(op, left, right) => {
+                let left = left.evaluate(hart, symbols)?;
+                let right = right.evaluate(hart, symbols)?;
+                Ok(match op {
+                    BinaryOp::Mul => left.wrapping_mul(right),
+                    BinaryOp::Div => left.checked_div(right).ok_or(ExprError::DivisionByZero)?,
+                    BinaryOp::Rem => left.checked_rem(right).ok_or(ExprError::DivisionByZero)?,
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Sub => left.wrapping_sub(right),
+                    BinaryOp::Shl => left.checked_shl(right).unwrap_or(0),
+                    BinaryOp::Shr => left.checked_shr(right).unwrap_or(0),
+                    BinaryOp::Lt => u32::from(left < right),
+                    BinaryOp::Le => u32::from(left <= right),
+                    BinaryOp::Gt => u32::from(left > right),
+                    BinaryOp::Ge => u32::from(left >= right),
+                    BinaryOp::Eq => u32::from(left == right),
+                    BinaryOp::Ne => u32::from(left != right),
+                    BinaryOp::And => left & right,
+                    BinaryOp::Xor => left ^ right,
+                    BinaryOp::Or => left | right,
+                    BinaryOp::LogicalAnd | BinaryOp::LogicalOr => unreachable!(),
+                })
+            }
+        }
+    }
+}
+
+/// Bytes of the memory from a start address up to an end, written as
+/// `mem[START..END]` or `mem[START..+SIZE]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemRange {
+    pub start: Expr,
+    pub end: RangeEnd,
+}
+
+/// End of a range of the memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeEnd {
+    /// Address past the last byte
+    Address(Expr),
+    /// Number of bytes
+    Size(Expr),
+}
+
+impl MemRange {
+    /// Parse a range, or None if the text isn't one
+    pub fn parse(text: &str) -> Option<Result<MemRange, ExprError>> {
+        let inner = text.trim().strip_prefix("mem")?.trim_start();
+        let inner = inner.strip_prefix('[')?.strip_suffix(']')?;
+        Some(match inner.find("..") {
+            Some(index) => {
+                let (start, end) = (&inner[..index], &inner[index + 2..]);
+                Expr::parse(start).and_then(|start| {
+                    let end = match end.trim_start().strip_prefix('+') {
+                        Some(size) => RangeEnd::Size(Expr::parse(size)?),
+                        None => RangeEnd::Address(Expr::parse(end)?),
+                    };
+                    Ok(MemRange { start, end })
+                })
+            }
+            None => Err(ExprError::Parse(String::from("expected START..END"))),
+        })
+    }
+
+    /// Compute the start address and the size of the range
+    pub fn evaluate(&self, hart: &Hart, symbols: &[Symbol]) -> Result<(u32, u32), ExprError> {
+        let start = self.start.evaluate(hart, symbols)?;
+        let size = match &self.end {
+            RangeEnd::Address(end) => end.evaluate(hart, symbols)?.saturating_sub(start),
+            RangeEnd::Size(size) => size.evaluate(hart, symbols)?,
+        };
+        Ok((start, size))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(u32),
+    Name(String),
+    Punct(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Punct(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+// Split an expression into tokens
+fn tokenize(text: &str) -> Result<Vec<Token>, ExprError> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let length = if c.is_ascii_digit() {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let number = &rest[..length];
+            let value = match number.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => number.parse(),
+            };
+            match value {
+                Ok(value) => tokens.push(Token::Number(value)),
+                Err(_) => return Err(ExprError::Parse(format!("invalid number {}", number))),
+            }
+            length
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' && !rest.starts_with("..") {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '.' && c != '$')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..length].to_string()));
+            length
+        } else {
+            match BINARY_OPS
+                .iter()
+                .map(|(text, _, _)| *text)
+                .chain(PUNCTUATION.iter().cloned())
+                .filter(|text| rest.starts_with(text))
+                .max_by_key(|text| text.len())
+            {
+                Some(text) => {
+                    tokens.push(Token::Punct(text));
+                    text.len()
+                }
+                None => return Err(ExprError::Parse(format!("unexpected {}", c))),
+            }
+        };
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// Recursive descent parser of the tokens of an expression
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Self, ExprError> {
+        Ok(Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        })
+    }
+
+    fn finish(&self) -> Result<(), ExprError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(ExprError::Parse(format!("unexpected {}", token))),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), ExprError> {
+        match self.next() {
+            Some(Token::Punct(text)) if text == punct => Ok(()),
+            Some(token) => Err(ExprError::Parse(format!(
+                "expected {} instead of {}",
+                punct, token
+            ))),
+            None => Err(ExprError::Parse(format!("expected {}", punct))),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ExprError> {
+        self.binary(0)
+    }
+
+    // Operators binding tighter than `precedence`, from left to right
+    fn binary(&mut self, precedence: u8) -> Result<Expr, ExprError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Punct(text)) => BINARY_OPS
+                    .iter()
+                    .find(|(op_text, _, op_precedence)| {
+                        op_text == text && *op_precedence > precedence
+                    })
+                    .cloned(),
+                _ => None,
+            };
+            match op {
+                Some((_, op, op_precedence)) => {
+                    self.position += 1;
+                    let right = self.binary(op_precedence)?;
+                    left = Expr::Binary(op, Box::new(left), Box::new(right));
+                }
+                None => return Ok(left),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        let op = match self.peek() {
+            Some(Token::Punct("-")) => UnaryOp::Neg,
+            Some(Token::Punct("~")) => UnaryOp::Not,
+            Some(Token::Punct("!")) => UnaryOp::LogicalNot,
+            Some(Token::Punct("*")) => {
+                self.position += 1;
+                return Ok(match self.unary()? {
+                    Expr::Cast(width, addr) => Expr::Load(width, addr),
+                    addr => Expr::Load(Width::U32, Box::new(addr)),
+                });
+            }
+            _ => return self.primary(),
+        };
+        self.position += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExprError> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Name(name)) => Ok(match get_register_number(&name) {
+                Some(register) => Expr::Register(register),
+                None if name == "pc" => Expr::Pc,
+                None => Expr::Symbol(name),
+            }),
+            Some(Token::Punct("(")) => {
+                // A pointer type, such as (u32*), casts the operand which
+                // follows
+                if let (Some(Token::Name(name)), Some(Token::Punct("*"))) = (
+                    self.tokens.get(self.position),
+                    self.tokens.get(self.position + 1),
+                ) {
+                    if let Some(width) = Width::parse(name) {
+                        self.position += 2;
+                        self.expect(")")?;
+                        return Ok(Expr::Cast(width, Box::new(self.unary()?)));
+                    }
+                }
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(token) => Err(ExprError::Parse(format!("unexpected {}", token))),
+            None => Err(ExprError::Parse(String::from("unexpected end"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem::MemStoreOp;
+    use riscv::isa::RV32I;
+
+    fn hart() -> Hart {
+        let mut hart = Hart::new();
+        hart.registers_mut().write(2, 0x200);
+        hart.registers_mut().write(10, 0x100);
+        hart.memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SW), 0x108, 0x1234_80ff)
+            .unwrap();
+        hart
+    }
+
+    fn evaluate(text: &str) -> Result<u32, ExprError> {
+        let symbols = [Symbol {
+            name: String::from("buffer"),
+            value: 0x100,
+            size: 16,
+        }];
+        Expr::parse(text)?.evaluate(&hart(), &symbols)
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(Ok(7), evaluate("1 + 2 * 3"));
+        assert_eq!(Ok(9), evaluate("(1 + 2) * 3"));
+        assert_eq!(Ok(1), evaluate("1 < 2 == 1"));
+        assert_eq!(Ok(0xffff_ffff), evaluate("-1"));
+        assert_eq!(Ok(1), evaluate("a0 == 0x100 && !(sp & 3)"));
+        assert_eq!(Ok(0x110), evaluate("buffer+0x10"));
+        assert_eq!(Ok(0x3c), evaluate("0xf0 >> 2"));
+        assert_eq!(Err(ExprError::DivisionByZero), evaluate("1 / zero"));
+        // The division isn't evaluated
+        assert_eq!(Ok(0), evaluate("0 && 1 / 0"));
+    }
+
+    #[test]
+    fn test_loads() {
+        assert_eq!(Ok(0x1234_80ff), evaluate("*(a0+8)"));
+        assert_eq!(Ok(0x1234_80ff), evaluate("*(u32*)(buffer+8)"));
+        assert_eq!(Ok(0xff), evaluate("*(u8*)(a0+8)"));
+        assert_eq!(Ok(0xffff_80ff), evaluate("*(i16*)(a0+8)"));
+        assert_eq!(Ok(0x80ff + 1), evaluate("*(u16*)(a0+8) + 1"));
+        assert!(matches!(
+            evaluate("*(u16*)(a0+11)"),
+            Err(ExprError::Memory(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let symbols = [Symbol {
+            name: String::from("buffer"),
+            value: 0x100,
+            size: 16,
+        }];
+        let expr = Expr::parse("*(u8*)(buffer + 8) + a0").unwrap();
+        let resolved = expr.resolve(&symbols).unwrap();
+        assert_eq!(Expr::parse("*(u8*)(0x100 + 8) + a0").unwrap(), resolved);
+        assert_eq!(Ok(0x1ff), resolved.evaluate(&hart(), &[]));
+        assert!(Expr::parse("main").unwrap().resolve(&symbols).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Err(ExprError::UnknownName(String::from("main"))),
+            evaluate("main")
+        );
+        assert!(Expr::parse("1 +").is_err());
+        assert!(Expr::parse("(1").is_err());
+        assert!(Expr::parse("1 2").is_err());
+        assert!(Expr::parse("0x1g").is_err());
+    }
+
+    #[test]
+    fn test_ranges() {
+        let hart = hart();
+        let range = |text| MemRange::parse(text).unwrap().unwrap().evaluate(&hart, &[]);
+        assert_eq!(Ok((0x100, 64)), range("mem[0x100..+64]"));
+        assert_eq!(Ok((0x200, 0x10)), range("mem[sp..sp+16]"));
+        assert!(MemRange::parse("a0").is_none());
+        assert!(MemRange::parse("mem[a0]").unwrap().is_err());
+    }
+}
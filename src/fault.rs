@@ -0,0 +1,424 @@
+//! Fault injection, to exercise the error handling of the firmware. Bits of
+//! the memory are flipped at scripted cycles and addresses, or at random
+//! with a given rate inside a region, as a soft error would. Random faults
+//! come from a seeded generator, so a run can be repeated exactly.
+//!
+//! A bit of a register can be flipped too, to study how vulnerable the core
+//! is to soft errors. The run with the fault is compared with a run without
+//! it, which classifies the outcome of the fault.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::fault::{BitFlip, MemoryFaults};
+//! # use adept_lib::mem::Memory;
+//! let mut memory = Memory::new();
+//! let mut faults = MemoryFaults::new(1);
+//! faults.add_flip(BitFlip { cycle: 10, addr: 0x100, bit: 3 });
+//! faults.tick(9, &mut memory);
+//! assert_eq!(0, memory.read_pc(0x100));
+//! faults.tick(10, &mut memory);
+//! assert_eq!(0x8, memory.read_pc(0x100));
+//! assert_eq!(1, faults.injected().len());
+//! ```
+use hart::StopReason;
+use mem::Memory;
+use riscv::labels::get_register_label;
+
+use std::fmt;
+
+/// Seed of the random faults unless another one is given
+pub const DEFAULT_SEED: u64 = 1;
+
+/// Pseudo random number generator, a xorshift64* which is fast and good
+/// enough to pick fault sites
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator. Seeds give different sequences, including 0.
+    pub fn new(seed: u64) -> Self {
+        Rng {
+            // The state of a xorshift must not be 0
+            state: seed ^ 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+
+    /// Next number of the sequence
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Number in `0..bound`, which must not be 0
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    /// Check if an event with a probability between 0 and 1 happens
+    pub fn chance(&mut self, probability: f64) -> bool {
+        // The top 53 bits fill the mantissa of a number in [0, 1)
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+/// One bit of the memory inverted at the start of a cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitFlip {
+    pub cycle: u64,
+    /// Address of the byte holding the bit
+    pub addr: u32,
+    /// Bit of the byte, 0 being the least significant
+    pub bit: u32,
+}
+
+impl fmt::Display for BitFlip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "bit {} of 0x{:08x} flipped at cycle {}",
+            self.bit, self.addr, self.cycle
+        )
+    }
+}
+
+/// One bit of a register inverted at the start of a cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterFlip {
+    pub cycle: u64,
+    /// Register from 1 to 31, x0 can't hold a fault
+    pub register: u8,
+    /// Bit of the register, 0 being the least significant
+    pub bit: u32,
+}
+
+impl RegisterFlip {
+    /// Draw a flip of any bit of any register, by a cycle of a run
+    ///
+    /// # Arguments
+    /// * `rng` => source of the random numbers
+    /// * `cycles` => cycles of the run without faults, not 0
+    pub fn random(rng: &mut Rng, cycles: u64) -> Self {
+        RegisterFlip {
+            cycle: 1 + rng.below(cycles),
+            register: 1 + rng.below(31) as u8,
+            bit: rng.below(32) as u32,
+        }
+    }
+}
+
+impl fmt::Display for RegisterFlip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "bit {} of {} flipped at cycle {}",
+            self.bit,
+            get_register_label(self.register),
+            self.cycle
+        )
+    }
+}
+
+/// What a program produced, compared between runs with and without faults
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunResult {
+    pub reason: StopReason,
+    pub output: Vec<u8>,
+    pub cycles: u64,
+}
+
+impl RunResult {
+    /// Cycles after which a run with a fault is considered stuck, twice
+    /// the cycles of this run with some slack for short programs
+    pub fn hang_limit(&self) -> u64 {
+        self.cycles.saturating_mul(2).saturating_add(1000)
+    }
+}
+
+/// Effect of a fault on a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The program stopped the same way with the same output
+    Masked,
+    /// The program stopped the same way but its output or exit code
+    /// changed, a silent data corruption
+    WrongResult,
+    /// The program stopped for another reason, such as a memory fault
+    Crash(StopReason),
+    /// The program didn't stop by the cycle limit
+    Hang,
+}
+
+impl Outcome {
+    /// Compare a run with a fault to the run without it
+    ///
+    /// # Arguments
+    /// * `golden` => run without the fault
+    /// * `faulty` => run with the fault
+    pub fn classify(golden: &RunResult, faulty: &RunResult) -> Self {
+        match (golden.reason, faulty.reason) {
+            (_, StopReason::CycleLimit(_)) | (_, StopReason::TimeLimit(_)) => Outcome::Hang,
+            (expected, reason) if expected == reason => {
+                if golden.output == faulty.output {
+                    Outcome::Masked
+                } else {
+                    Outcome::WrongResult
+                }
+            }
+            (StopReason::Exit(_), StopReason::Exit(_)) => Outcome::WrongResult,
+            (_, reason) => Outcome::Crash(reason),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Outcome::Masked => "masked",
+            Outcome::WrongResult => "wrong result",
+            Outcome::Crash(_) => "crash",
+            Outcome::Hang => "hang",
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Crash(reason) => write!(f, "crash: {}", reason),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+/// Bit flips injected in the memory during a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryFaults {
+    // Scripted flips not injected yet, the next one last
+    scripted: Vec<BitFlip>,
+    // Probability of a random flip in every cycle, and the region it hits
+    rate: f64,
+    region: (u32, u32),
+    rng: Rng,
+    injected: Vec<BitFlip>,
+}
+
+impl MemoryFaults {
+    /// Create an injector without faults
+    ///
+    /// # Arguments
+    /// * `seed` => seed of the random faults
+    pub fn new(seed: u64) -> Self {
+        MemoryFaults {
+            scripted: Vec::new(),
+            rate: 0.0,
+            region: (0, 0),
+            rng: Rng::new(seed),
+            injected: Vec::new(),
+        }
+    }
+
+    /// Script a flip
+    ///
+    /// # Arguments
+    /// * `flip` => cycle, address, and bit below 8
+    pub fn add_flip(&mut self, flip: BitFlip) {
+        assert!(flip.bit < 8, "invalid bit of a byte: {}", flip.bit);
+        self.scripted.push(flip);
+        self.scripted
+            .sort_by(|a, b| b.cycle.cmp(&a.cycle).then(b.addr.cmp(&a.addr)));
+    }
+
+    /// Flip random bits of a region
+    ///
+    /// # Arguments
+    /// * `rate` => average flips per million cycles
+    /// * `base` => address of the first byte of the region
+    /// * `size` => size of the region in bytes, not 0
+    pub fn set_rate(&mut self, rate: f64, base: u32, size: u32) {
+        assert!(size > 0, "the region of the random faults can't be empty");
+        self.rate = rate / 1_000_000.0;
+        self.region = (base, size);
+    }
+
+    /// Inject the faults of a cycle, the scripted ones first
+    ///
+    /// # Arguments
+    /// * `cycle` => cycle about to be simulated
+    /// * `memory` => memory whose bits are flipped
+    pub fn tick(&mut self, cycle: u64, memory: &mut Memory) {
+        while let Some(&flip) = self.scripted.last() {
+            if flip.cycle > cycle {
+                break;
+            }
+            self.scripted.pop();
+            self.inject(flip, memory);
+        }
+        if self.rate > 0.0 && self.rng.chance(self.rate) {
+            let (base, size) = self.region;
+            let flip = BitFlip {
+                cycle,
+                addr: base.wrapping_add(self.rng.below(u64::from(size)) as u32),
+                bit: self.rng.below(8) as u32,
+            };
+            self.inject(flip, memory);
+        }
+    }
+
+    /// Flips injected so far, in order
+    pub fn injected(&self) -> &[BitFlip] {
+        &self.injected
+    }
+
+    fn inject(&mut self, flip: BitFlip, memory: &mut Memory) {
+        memory.flip_bit(flip.addr, flip.bit);
+        self.injected.push(flip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng() {
+        let mut a = Rng::new(0);
+        let mut b = Rng::new(0);
+        let mut c = Rng::new(1);
+        let first = a.next_u64();
+        assert_eq!(first, b.next_u64());
+        assert_ne!(first, c.next_u64());
+        assert!((0..1000).all(|_| a.below(10) < 10));
+        assert!(!(0..1000).any(|_| a.chance(0.0)));
+        assert!((0..1000).all(|_| a.chance(1.0)));
+    }
+
+    #[test]
+    fn test_scripted_flips() {
+        let mut memory = Memory::new();
+        let mut faults = MemoryFaults::new(DEFAULT_SEED);
+        faults.add_flip(BitFlip {
+            cycle: 5,
+            addr: 0x203,
+            bit: 7,
+        });
+        faults.add_flip(BitFlip {
+            cycle: 2,
+            addr: 0x200,
+            bit: 0,
+        });
+        faults.add_flip(BitFlip {
+            cycle: 5,
+            addr: 0x200,
+            bit: 0,
+        });
+        for cycle in 1..5 {
+            faults.tick(cycle, &mut memory);
+        }
+        assert_eq!(0x0000_0001, memory.read_pc(0x200));
+        faults.tick(5, &mut memory);
+        // The second flip of the bit restores it
+        assert_eq!(0x8000_0000, memory.read_pc(0x200));
+        assert_eq!(
+            vec![2, 5, 5],
+            faults
+                .injected()
+                .iter()
+                .map(|flip| flip.cycle)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "bit 7 of 0x00000203 flipped at cycle 5",
+            faults.injected()[2].to_string()
+        );
+    }
+
+    #[test]
+    fn test_random_flips() {
+        let run = |seed| {
+            let mut memory = Memory::new();
+            let mut faults = MemoryFaults::new(seed);
+            // One flip every hundred cycles on average
+            faults.set_rate(10_000.0, 0x1000, 0x100);
+            for cycle in 1..=10_000 {
+                faults.tick(cycle, &mut memory);
+            }
+            faults.injected().to_vec()
+        };
+        let flips = run(7);
+        assert!(flips.len() > 50 && flips.len() < 150, "{}", flips.len());
+        assert!(flips
+            .iter()
+            .all(|flip| flip.addr >= 0x1000 && flip.addr < 0x1100 && flip.bit < 8));
+        assert_eq!(flips, run(7));
+        assert_ne!(flips, run(8));
+    }
+
+    #[test]
+    fn test_random_register_flips() {
+        let mut rng = Rng::new(DEFAULT_SEED);
+        for _ in 0..1000 {
+            let flip = RegisterFlip::random(&mut rng, 10);
+            assert!(flip.cycle >= 1 && flip.cycle <= 10);
+            assert!(flip.register >= 1 && flip.register < 32);
+            assert!(flip.bit < 32);
+        }
+        let flip = RegisterFlip {
+            cycle: 3,
+            register: 10,
+            bit: 31,
+        };
+        assert_eq!("bit 31 of a0 flipped at cycle 3", flip.to_string());
+    }
+
+    #[test]
+    fn test_outcomes() {
+        let golden = RunResult {
+            reason: StopReason::Exit(0),
+            output: b"ok\n".to_vec(),
+            cycles: 100,
+        };
+        let run = |reason, output: &[u8]| RunResult {
+            reason,
+            output: output.to_vec(),
+            cycles: 100,
+        };
+        assert_eq!(
+            Outcome::Masked,
+            Outcome::classify(&golden, &run(StopReason::Exit(0), b"ok\n"))
+        );
+        assert_eq!(
+            Outcome::WrongResult,
+            Outcome::classify(&golden, &run(StopReason::Exit(0), b"ko\n"))
+        );
+        assert_eq!(
+            Outcome::WrongResult,
+            Outcome::classify(&golden, &run(StopReason::Exit(1), b"ok\n"))
+        );
+        let crash = StopReason::Breakpoint { pc: 0x100 };
+        assert_eq!(
+            Outcome::Crash(crash),
+            Outcome::classify(&golden, &run(crash, b"ok\n"))
+        );
+        assert_eq!(
+            Outcome::Hang,
+            Outcome::classify(&golden, &run(StopReason::CycleLimit(200), b""))
+        );
+        assert_eq!(
+            "crash: breakpoint at pc 0x00000100",
+            Outcome::Crash(crash).to_string()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_bit() {
+        MemoryFaults::new(DEFAULT_SEED).add_flip(BitFlip {
+            cycle: 0,
+            addr: 0,
+            bit: 8,
+        });
+    }
+}
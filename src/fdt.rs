@@ -0,0 +1,362 @@
+//! Flattened device trees describing the simulated platform. Firmware which
+//! discovers the hardware through a device tree, such as OpenSBI or Zephyr,
+//! finds the blob at the address in a1 when it starts, with the id of the
+//! hart in a0.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::fdt::{platform, DeviceTree};
+//! let blob = DeviceTree::new(platform(0x0080_0000)).to_blob();
+//! // Magic number and total size, in big endian
+//! assert_eq!(&[0xd0, 0x0d, 0xfe, 0xed], &blob[..4]);
+//! assert_eq!(blob.len(), u32::from_be_bytes([blob[4], blob[5], blob[6], blob[7]]) as usize);
+//! ```
+use clint::{CLINT_BASE, CLINT_SIZE, TIMEBASE_FREQUENCY};
+use csr::{INTERRUPT_MACHINE_EXTERNAL, INTERRUPT_MACHINE_SOFTWARE, INTERRUPT_MACHINE_TIMER};
+use dma::{DMA_BASE, DMA_SIZE};
+use uart::{UART_BASE, UART_CLOCK_FREQUENCY, UART_SIZE};
+
+// Handle of the interrupt controller of the hart
+const INTC_PHANDLE: u32 = 1;
+
+// Blob header fields and structure tokens
+const MAGIC: u32 = 0xd00d_feed;
+const VERSION: u32 = 17;
+const LAST_COMPATIBLE_VERSION: u32 = 16;
+const HEADER_SIZE: usize = 40;
+const BEGIN_NODE: u32 = 1;
+const END_NODE: u32 = 2;
+const PROP: u32 = 3;
+const END: u32 = 9;
+
+/// Node of a device tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub name: String,
+    properties: Vec<(String, Vec<u8>)>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    /// Create a node without properties, named `name@address` for devices
+    pub fn new(name: &str) -> Self {
+        Node {
+            name: name.to_string(),
+            properties: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Add a property holding raw bytes
+    pub fn property(&mut self, name: &str, value: &[u8]) -> &mut Self {
+        self.properties.push((name.to_string(), value.to_vec()));
+        self
+    }
+
+    /// Add a property without a value, used as a flag
+    pub fn property_empty(&mut self, name: &str) -> &mut Self {
+        self.property(name, &[])
+    }
+
+    /// Add a property holding 32 bit cells
+    pub fn property_cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+        let value: Vec<u8> = cells
+            .iter()
+            .flat_map(|cell| cell.to_be_bytes().to_vec())
+            .collect();
+        self.property(name, &value)
+    }
+
+    /// Add a property holding a list of null terminated strings
+    pub fn property_strings(&mut self, name: &str, strings: &[&str]) -> &mut Self {
+        let mut value = Vec::new();
+        for string in strings {
+            value.extend_from_slice(string.as_bytes());
+            value.push(0);
+        }
+        self.property(name, &value)
+    }
+
+    pub fn add_child(&mut self, child: Node) -> &mut Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Find a child by name
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Node> {
+        self.children.iter_mut().find(|child| child.name == name)
+    }
+
+    /// Find the value of a property
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.properties
+            .iter()
+            .find(|(property, _)| property == name)
+            .map(|(_, value)| value.as_slice())
+    }
+}
+
+/// Device tree with the root node and the hart which boots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceTree {
+    pub root: Node,
+    pub boot_hart: u32,
+}
+
+impl DeviceTree {
+    /// Create a tree booting hart 0
+    pub fn new(root: Node) -> Self {
+        DeviceTree { root, boot_hart: 0 }
+    }
+
+    /// Flatten the tree into the blob format read by firmware
+    pub fn to_blob(&self) -> Vec<u8> {
+        let mut structure = Vec::new();
+        let mut strings = Vec::new();
+        flatten(&self.root, "", &mut structure, &mut strings);
+        push_u32(&mut structure, END);
+
+        // The reservation map is a single terminating entry
+        let reservations = HEADER_SIZE;
+        let structure_offset = reservations + 16;
+        let strings_offset = structure_offset + structure.len();
+        let total_size = align(strings_offset + strings.len());
+
+        let mut blob = Vec::with_capacity(total_size);
+        for field in &[
+            MAGIC,
+            total_size as u32,
+            structure_offset as u32,
+            strings_offset as u32,
+            reservations as u32,
+            VERSION,
+            LAST_COMPATIBLE_VERSION,
+            self.boot_hart,
+            strings.len() as u32,
+            structure.len() as u32,
+        ] {
+            push_u32(&mut blob, *field);
+        }
+        blob.extend_from_slice(&[0; 16]);
+        blob.extend_from_slice(&structure);
+        blob.extend_from_slice(&strings);
+        blob.resize(total_size, 0);
+        blob
+    }
+}
+
+/// Describe the simulated platform: one RV32I hart, the memory starting at
+/// address 0, the CLINT, the serial port, which is the console, and the DMA
+/// engine.
+///
+/// # Arguments
+/// * `memory_size` => size of the memory in bytes
+pub fn platform(memory_size: u32) -> Node {
+    let mut intc = Node::new("interrupt-controller");
+    intc.property_cells("#interrupt-cells", &[1])
+        .property_empty("interrupt-controller")
+        .property_strings("compatible", &["riscv,cpu-intc"])
+        .property_cells("phandle", &[INTC_PHANDLE]);
+
+    let mut cpu = Node::new("cpu@0");
+    cpu.property_strings("device_type", &["cpu"])
+        .property_cells("reg", &[0])
+        .property_strings("compatible", &["riscv"])
+        .property_strings("riscv,isa", &["rv32i"])
+        .property_strings("status", &["okay"])
+        .add_child(intc);
+
+    let mut cpus = Node::new("cpus");
+    cpus.property_cells("#address-cells", &[1])
+        .property_cells("#size-cells", &[0])
+        .property_cells("timebase-frequency", &[TIMEBASE_FREQUENCY])
+        .add_child(cpu);
+
+    let mut memory = Node::new("memory@0");
+    memory
+        .property_strings("device_type", &["memory"])
+        .property_cells("reg", &[0, memory_size]);
+
+    let mut clint = Node::new(&format!("clint@{:x}", CLINT_BASE));
+    clint
+        .property_strings("compatible", &["sifive,clint0", "riscv,clint0"])
+        .property_cells("reg", &[CLINT_BASE, CLINT_SIZE])
+        .property_cells(
+            "interrupts-extended",
+            &[
+                INTC_PHANDLE,
+                INTERRUPT_MACHINE_SOFTWARE,
+                INTC_PHANDLE,
+                INTERRUPT_MACHINE_TIMER,
+            ],
+        );
+
+    let uart_name = format!("serial@{:x}", UART_BASE);
+    let mut uart = Node::new(&uart_name);
+    uart.property_strings("compatible", &["ns16550a"])
+        .property_cells("reg", &[UART_BASE, UART_SIZE])
+        .property_cells("clock-frequency", &[UART_CLOCK_FREQUENCY])
+        .property_cells("reg-shift", &[0])
+        .property_cells("reg-io-width", &[1]);
+
+    let mut dma = Node::new(&format!("dma@{:x}", DMA_BASE));
+    dma.property_strings("compatible", &["adept,dma"])
+        .property_cells("reg", &[DMA_BASE, DMA_SIZE])
+        .property_cells(
+            "interrupts-extended",
+            &[INTC_PHANDLE, INTERRUPT_MACHINE_EXTERNAL],
+        );
+
+    let mut soc = Node::new("soc");
+    soc.property_cells("#address-cells", &[1])
+        .property_cells("#size-cells", &[1])
+        .property_strings("compatible", &["simple-bus"])
+        .property_empty("ranges")
+        .add_child(clint)
+        .add_child(uart)
+        .add_child(dma);
+
+    let mut chosen = Node::new("chosen");
+    chosen.property_strings("stdout-path", &[&format!("/soc/{}", uart_name)]);
+
+    let mut root = Node::new("");
+    root.property_cells("#address-cells", &[1])
+        .property_cells("#size-cells", &[1])
+        .property_strings("compatible", &["adept,adept-sim"])
+        .property_strings("model", &["Adept simulator"])
+        .add_child(chosen)
+        .add_child(cpus)
+        .add_child(memory)
+        .add_child(soc);
+    root
+}
+
+// Write the tokens of a node and its children. Property names are stored
+// once in the strings block.
+fn flatten(node: &Node, name: &str, structure: &mut Vec<u8>, strings: &mut Vec<u8>) {
+    push_u32(structure, BEGIN_NODE);
+    structure.extend_from_slice(name.as_bytes());
+    structure.push(0);
+    structure.resize(align(structure.len()), 0);
+
+    for (property, value) in &node.properties {
+        let offset = string_offset(strings, property);
+        push_u32(structure, PROP);
+        push_u32(structure, value.len() as u32);
+        push_u32(structure, offset);
+        structure.extend_from_slice(value);
+        structure.resize(align(structure.len()), 0);
+    }
+    for child in &node.children {
+        flatten(child, &child.name, structure, strings);
+    }
+    push_u32(structure, END_NODE);
+}
+
+// Offset of a string in the strings block, adding it if it's missing
+fn string_offset(strings: &mut Vec<u8>, string: &str) -> u32 {
+    let mut offset = 0;
+    for existing in strings.split(|byte| *byte == 0) {
+        if existing == string.as_bytes() && offset < strings.len() {
+            return offset as u32;
+        }
+        offset += existing.len() + 1;
+    }
+    let offset = strings.len();
+    strings.extend_from_slice(string.as_bytes());
+    strings.push(0);
+    offset as u32
+}
+
+fn push_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+// Structure tokens are aligned to 4 bytes
+fn align(size: usize) -> usize {
+    (size + 3) & !3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(blob: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes([
+            blob[offset],
+            blob[offset + 1],
+            blob[offset + 2],
+            blob[offset + 3],
+        ])
+    }
+
+    #[test]
+    fn test_header() {
+        let blob = DeviceTree::new(Node::new("")).to_blob();
+        assert_eq!(MAGIC, read_u32(&blob, 0));
+        assert_eq!(blob.len() as u32, read_u32(&blob, 4));
+        assert_eq!(56, read_u32(&blob, 8));
+        assert_eq!(17, read_u32(&blob, 20));
+        // An empty root node: begin, its empty name, end and the final end
+        assert_eq!(16, read_u32(&blob, 36));
+        assert_eq!(
+            vec![BEGIN_NODE, 0, END_NODE, END],
+            (56..72)
+                .step_by(4)
+                .map(|offset| read_u32(&blob, offset))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_properties() {
+        let mut root = Node::new("");
+        root.property_cells("reg", &[0x1000, 0x100])
+            .property_strings("compatible", &["a", "bc"]);
+        let mut child = Node::new("uart@1000");
+        child.property_cells("reg", &[0x1000, 0x100]);
+        root.add_child(child);
+        assert_eq!(Some(&b"a\0bc\0"[..]), root.get("compatible"));
+
+        let blob = DeviceTree::new(root).to_blob();
+        let strings = read_u32(&blob, 12) as usize;
+        // The name of reg is stored once
+        assert_eq!(
+            b"reg\0compatible\0",
+            &blob[strings..strings + read_u32(&blob, 32) as usize]
+        );
+        // First property of the root: token, length, name offset and cells
+        assert_eq!(
+            vec![PROP, 8, 0, 0x1000, 0x100],
+            (64..84)
+                .step_by(4)
+                .map(|offset| read_u32(&blob, offset))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(0, blob.len() % 4);
+    }
+
+    #[test]
+    fn test_platform() {
+        let mut root = platform(0x0080_0000);
+        let memory = root.child_mut("memory@0").unwrap();
+        assert_eq!(Some(&[0, 0, 0, 0, 0, 0x80, 0, 0][..]), memory.get("reg"));
+        let cpus = root.child_mut("cpus").unwrap();
+        let cpu = cpus.child_mut("cpu@0").unwrap();
+        assert_eq!(Some(&b"rv32i\0"[..]), cpu.get("riscv,isa"));
+        let chosen = root.child_mut("chosen").unwrap();
+        assert_eq!(
+            Some(&b"/soc/serial@10000000\0"[..]),
+            chosen.get("stdout-path")
+        );
+        let soc = root.child_mut("soc").unwrap();
+        let clint = soc.child_mut("clint@2000000").unwrap();
+        assert_eq!(
+            Some(&[0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0, 7][..]),
+            clint.get("interrupts-extended")
+        );
+        let dma = soc.child_mut("dma@10001000").unwrap();
+        assert_eq!(Some(&b"adept,dma\0"[..]), dma.get("compatible"));
+    }
+}
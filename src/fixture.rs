@@ -0,0 +1,445 @@
+//! Test fixture running firmware under the simulator from the `#[test]`
+//! functions of another crate, so that `cargo test` also tests the binaries
+//! of a firmware repository:
+//!
+//! ```no_run
+//! # use adept_lib::fixture::AdeptTest;
+//! #[test]
+//! fn self_test_passes() {
+//!     AdeptTest::new()
+//!         .load("target/riscv32i-unknown-none-elf/release/self-test")
+//!         .expect_uart("PASS")
+//!         .max_cycles(1e6)
+//!         .run();
+//! }
+//! ```
+//!
+//! The ELF file is loaded like the simulator loads it, with the stack at the
+//! top of the memory and the exit through the `exit` system call or the
+//! `tohost` symbol. A test expecting output on the serial port passes as soon
+//! as all of it was written, since firmware often never exits. Without any
+//! expectation the program has to exit with code 0.
+//!
+//! `run` panics with the reason of a failure, which makes it the last call
+//! of a test, while `check` returns it.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::fixture::AdeptTest;
+//! # use adept_lib::generator::{Generator, Mix};
+//! # use adept_lib::hart::StopReason;
+//! let program = Generator::new(7, Mix::default()).generate(0x1000, 100).unwrap();
+//! let run = AdeptTest::new()
+//!     .elf(program.elf())
+//!     .expect_exit(0)
+//!     .max_cycles(1e6)
+//!     .run();
+//! assert_eq!(Some(StopReason::Exit(0)), run.reason);
+//! ```
+use cpu::Cpu;
+use csr::INTERRUPT_MACHINE_TIMER;
+use elf;
+use env::{Heap, DEFAULT_STACK_SIZE};
+use hart::{EnvironmentCallPolicy, StopReason};
+use image::MemoryImage;
+use mem::MemStoreOp;
+use pipeline::PipelineConfig;
+use riscv::isa::RV32I;
+use stats::Stats;
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+
+/// Cycles a test runs for unless told otherwise
+pub const DEFAULT_MAX_CYCLES: u64 = 10_000_000;
+
+// Cycles simulated between two checks of the output
+const BATCH_CYCLES: u64 = 1024;
+
+// Where the program comes from
+#[derive(Debug, Clone)]
+enum Source {
+    File(String),
+    Elf(Vec<u8>),
+}
+
+/// Why a test failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestFailure {
+    /// The program couldn't be read or isn't a RISC-V ELF file
+    Load(String),
+    /// The program was still running after the most cycles of the test
+    Timeout { cycles: u64, output: String },
+    /// The program stopped without exiting, such as on an invalid
+    /// instruction
+    Stopped { reason: StopReason, output: String },
+    /// The program exited with another code than the expected one
+    ExitCode {
+        expected: i32,
+        code: i32,
+        output: String,
+    },
+    /// The program didn't write an expected text to the serial port
+    MissingOutput { expected: String, output: String },
+}
+
+impl Display for TestFailure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TestFailure::Load(e) => write!(f, "failed to load the program: {}", e),
+            TestFailure::Timeout { cycles, output } => write!(
+                f,
+                "the program was still running after {} cycles\noutput:\n{}",
+                cycles, output
+            ),
+            TestFailure::Stopped { reason, output } => {
+                write!(f, "the program stopped: {}\noutput:\n{}", reason, output)
+            }
+            TestFailure::ExitCode {
+                expected,
+                code,
+                output,
+            } => write!(
+                f,
+                "the program exited with code {} instead of {}\noutput:\n{}",
+                code, expected, output
+            ),
+            TestFailure::MissingOutput { expected, output } => write!(
+                f,
+                "the program didn't write {:?}\noutput:\n{}",
+                expected, output
+            ),
+        }
+    }
+}
+
+impl Error for TestFailure {}
+
+/// A run which met the expectations of its test
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestRun {
+    /// Why the program stopped, or None if the test stopped it once the
+    /// expected output was written
+    pub reason: Option<StopReason>,
+    /// Everything the program wrote to the standard output and error, and
+    /// to the serial port
+    pub output: String,
+    pub stats: Stats,
+    /// Timer interrupts the program took
+    pub timer_interrupts: u64,
+}
+
+/// Test of a program, built from its expectations
+#[derive(Debug, Clone)]
+pub struct AdeptTest {
+    source: Option<Source>,
+    config: PipelineConfig,
+    uart: Vec<String>,
+    exit_code: Option<i32>,
+    max_cycles: u64,
+    environment_calls: EnvironmentCallPolicy,
+}
+
+impl Default for AdeptTest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdeptTest {
+    /// Create a test with the default pipeline and no program
+    pub fn new() -> Self {
+        AdeptTest {
+            source: None,
+            config: PipelineConfig::default(),
+            uart: Vec::new(),
+            exit_code: None,
+            max_cycles: DEFAULT_MAX_CYCLES,
+            environment_calls: EnvironmentCallPolicy::Emulate,
+        }
+    }
+
+    /// Run the ELF file at a path
+    pub fn load(mut self, path: &str) -> Self {
+        self.source = Some(Source::File(path.to_string()));
+        self
+    }
+
+    /// Run an ELF file already in memory
+    pub fn elf(mut self, data: Vec<u8>) -> Self {
+        self.source = Some(Source::Elf(data));
+        self
+    }
+
+    /// Simulate the program with another pipeline
+    pub fn pipeline(mut self, config: PipelineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Expect a text in the output of the program. Every expected text has
+    /// to appear, in any order.
+    pub fn expect_uart(mut self, text: &str) -> Self {
+        self.uart.push(text.to_string());
+        self
+    }
+
+    /// Expect the program to exit with a code
+    pub fn expect_exit(mut self, code: i32) -> Self {
+        self.exit_code = Some(code);
+        self
+    }
+
+    /// Fail once the program runs for more cycles, which can be written in
+    /// scientific notation such as `1e6`
+    pub fn max_cycles(mut self, cycles: f64) -> Self {
+        self.max_cycles = cycles as u64;
+        self
+    }
+
+    /// Handle the environment calls of the program, emulated as system
+    /// calls by default. A kernel enters its trap handler with them.
+    pub fn environment_calls(mut self, policy: EnvironmentCallPolicy) -> Self {
+        self.environment_calls = policy;
+        self
+    }
+
+    /// Run the test
+    ///
+    /// # Return Value
+    /// The run, or why it didn't meet the expectations
+    pub fn check(self) -> Result<TestRun, TestFailure> {
+        let data = match self.source {
+            Some(Source::File(ref path)) => {
+                fs::read(path).map_err(|e| TestFailure::Load(format!("{}: {}", path, e)))?
+            }
+            Some(Source::Elf(ref data)) => data.clone(),
+            None => return Err(TestFailure::Load("no program".to_string())),
+        };
+        let mut cpu = Cpu::new(self.config.clone());
+        load(&mut cpu, &data)?;
+        cpu.hart_mut()
+            .set_environment_call_policy(self.environment_calls);
+        cpu.set_cycle_limit(Some(self.max_cycles));
+
+        // Without any expectation the program has to exit successfully
+        let exit_code = if self.uart.is_empty() {
+            Some(self.exit_code.unwrap_or(0))
+        } else {
+            self.exit_code
+        };
+        let written = |cpu: &Cpu| {
+            let output = String::from_utf8_lossy(cpu.hart().output());
+            self.uart.iter().all(|text| output.contains(text.as_str()))
+        };
+        let reason = loop {
+            match cpu.tick(BATCH_CYCLES) {
+                Err(reason) => break Some(reason),
+                Ok(()) if exit_code.is_none() && written(&cpu) => break None,
+                Ok(()) => (),
+            }
+        };
+
+        let output = String::from_utf8_lossy(cpu.hart().output()).into_owned();
+        match reason {
+            Some(reason) if reason.is_timeout() && (exit_code.is_some() || !written(&cpu)) => {
+                return Err(TestFailure::Timeout {
+                    cycles: cpu.stats().cycles,
+                    output,
+                });
+            }
+            Some(StopReason::Exit(code)) => {
+                if let Some(expected) = exit_code.filter(|expected| *expected != code) {
+                    return Err(TestFailure::ExitCode {
+                        expected,
+                        code,
+                        output,
+                    });
+                }
+            }
+            Some(reason) if !reason.is_timeout() => {
+                return Err(TestFailure::Stopped { reason, output });
+            }
+            _ => (),
+        }
+        if let Some(expected) = self
+            .uart
+            .iter()
+            .find(|text| !output.contains(text.as_str()))
+        {
+            return Err(TestFailure::MissingOutput {
+                expected: expected.clone(),
+                output,
+            });
+        }
+        Ok(TestRun {
+            reason,
+            output,
+            stats: cpu.stats().clone(),
+            timer_interrupts: cpu.hart().csrs().interrupts_taken(INTERRUPT_MACHINE_TIMER),
+        })
+    }
+
+    /// Run the test, and panic if it fails
+    pub fn run(self) -> TestRun {
+        match self.check() {
+            Ok(run) => run,
+            Err(failure) => panic!("{}", failure),
+        }
+    }
+}
+
+// Load an ELF file in the memory of a processor, with the stack at the top
+// of the memory and the heap after the program
+fn load(cpu: &mut Cpu, data: &[u8]) -> Result<(), TestFailure> {
+    let invalid = || TestFailure::Load("invalid or unsupported ELF file".to_string());
+    let segments = elf::segments(data).ok_or_else(invalid)?;
+    let entry = elf::entry_point(data).ok_or_else(invalid)?;
+    let mut image = MemoryImage::new();
+    for segment in &segments {
+        image.write_bytes(segment.address, &segment.contents);
+        let bss = segment.address.wrapping_add(segment.contents.len() as u32);
+        image.write_bytes(bss, &vec![0; segment.zero_size() as usize]);
+    }
+
+    let hart = cpu.hart_mut();
+    for (address, word) in image.words() {
+        hart.memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SW), address, word)
+            .map_err(|e| TestFailure::Load(e.to_string()))?;
+    }
+    let stack_top = hart.memory().size().min(u64::from(u32::MAX) & !15) as u32;
+    hart.registers_mut().write(2, stack_top as i32);
+    hart.set_heap(Heap::below_stack(
+        image.end(),
+        stack_top,
+        None,
+        DEFAULT_STACK_SIZE,
+    ));
+    hart.set_tohost(elf::symbol(data, "tohost"));
+    cpu.set_pc(entry);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elf::{Segment, SEGMENT_EXECUTE, SEGMENT_READ};
+
+    // Program writing a text to the serial port, then exiting with a code
+    // or spinning forever
+    fn program(text: &str, exit: Option<i32>) -> Vec<u8> {
+        let mut code = vec![0x1000_0537]; // lui a0, 0x10000
+        for byte in text.bytes() {
+            code.push(u32::from(byte) << 20 | 0x0000_0593); // li a1, byte
+            code.push(0x00b5_0023); // sb a1, 0(a0)
+        }
+        match exit {
+            Some(exit) => {
+                code.push((exit as u32) << 20 | 0x0000_0513); // li a0, exit
+                code.push(0x05d0_0893); // li a7, 93
+                code.push(0x0000_0073); // ecall
+            }
+            None => code.push(0x0000_006f), // j 0
+        }
+        executable(&code)
+    }
+
+    fn executable(code: &[u32]) -> Vec<u8> {
+        let contents: Vec<u8> = code
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .collect();
+        let segment = Segment {
+            address: 0x100,
+            size: contents.len() as u32,
+            contents,
+            flags: SEGMENT_READ | SEGMENT_EXECUTE,
+        };
+        elf::executable(0x100, &[segment])
+    }
+
+    #[test]
+    fn test_expect_uart() {
+        // Firmware which never exits passes once the text is written
+        let run = AdeptTest::new()
+            .elf(program("PASS", None))
+            .expect_uart("PASS")
+            .max_cycles(1e6)
+            .run();
+        assert_eq!(None, run.reason);
+        assert_eq!("PASS", run.output);
+
+        assert_eq!(
+            Err(TestFailure::Timeout {
+                cycles: 1000,
+                output: "FAIL".to_string()
+            }),
+            AdeptTest::new()
+                .elf(program("FAIL", None))
+                .expect_uart("PASS")
+                .max_cycles(1e3)
+                .check()
+        );
+        assert_eq!(
+            Err(TestFailure::MissingOutput {
+                expected: "PASS".to_string(),
+                output: "FAIL".to_string()
+            }),
+            AdeptTest::new()
+                .elf(program("FAIL", Some(0)))
+                .expect_uart("PASS")
+                .check()
+        );
+    }
+
+    #[test]
+    fn test_expect_exit() {
+        let run = AdeptTest::new().elf(program("ok", Some(0))).run();
+        assert_eq!(Some(StopReason::Exit(0)), run.reason);
+        assert_eq!(
+            Err(TestFailure::ExitCode {
+                expected: 0,
+                code: 3,
+                output: "ok".to_string()
+            }),
+            AdeptTest::new().elf(program("ok", Some(3))).check()
+        );
+        assert!(AdeptTest::new()
+            .elf(program("", Some(3)))
+            .expect_exit(3)
+            .check()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_failures() {
+        assert_eq!(
+            Err(TestFailure::Load(
+                "invalid or unsupported ELF file".to_string()
+            )),
+            AdeptTest::new().elf(vec![0; 64]).check()
+        );
+        assert!(
+            match AdeptTest::new().load("/nonexistent/firmware").check() {
+                Err(TestFailure::Load(e)) => e.starts_with("/nonexistent/firmware: "),
+                _ => false,
+            }
+        );
+        // Running off the end of the program
+        match AdeptTest::new().elf(executable(&[0x1000_0537])).check() {
+            Err(failure) => assert!(failure.to_string().starts_with(
+                "the program stopped: invalid instruction 0x00000000 at pc 0x00000104"
+            )),
+            Ok(_) => panic!("the program didn't stop"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the program exited with code 1 instead of 0")]
+    fn test_run_panics() {
+        AdeptTest::new().elf(program("", Some(1))).run();
+    }
+}
@@ -0,0 +1,202 @@
+//! Flamegraph of the guest program. The shadow call stack is sampled every
+//! few executed instructions, and the samples are written as folded stacks,
+//! one line per distinct stack with the functions from the outermost one and
+//! the number of samples:
+//!
+//! ```text
+//! _start;main;memcpy 840
+//! _start;main 120
+//! ```
+//!
+//! This is the input of the flamegraph tools, such as `flamegraph.pl` or
+//! `inferno-flamegraph`. Frames dropped by a call stack too deep for the
+//! shadow stack show as a `[truncated]` root.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::callstack::CallStack;
+//! # use adept_lib::elf::Symbol;
+//! # use adept_lib::flamegraph::Flamegraph;
+//! # use adept_lib::riscv::decoder::Instruction;
+//! let functions = vec![
+//!     Symbol { name: "main".to_string(), value: 0x100, size: 0x10 },
+//!     Symbol { name: "f".to_string(), value: 0x200, size: 0x10 },
+//! ];
+//! let mut stack = CallStack::new();
+//! let mut flamegraph = Flamegraph::new(1);
+//! flamegraph.sample(0x104, &stack);
+//! // jal ra, f at 0x104
+//! stack.execute(0x104, &Instruction::new(0x0fc0_00ef), 0x200);
+//! flamegraph.sample(0x200, &stack);
+//! flamegraph.sample(0x204, &stack);
+//! let mut out = Vec::new();
+//! flamegraph.write_folded(&mut out, &functions).unwrap();
+//! assert_eq!("main;f 2\nmain 1\n", String::from_utf8(out).unwrap());
+//! ```
+use callstack::CallStack;
+use elf::Symbol;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Samples of the call stack of the program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flamegraph {
+    period: u64,
+    // Instructions left until the next sample
+    countdown: u64,
+    // Samples by stack, with the addresses of the calls from the outermost
+    // one and the sampled instruction last
+    stacks: HashMap<Vec<u32>, u64>,
+    // Samples of stacks which lost their outermost frames
+    truncated: HashMap<Vec<u32>, u64>,
+}
+
+impl Flamegraph {
+    /// Create a flamegraph without samples
+    ///
+    /// # Arguments
+    /// * `period` => instructions executed between two samples, at least 1
+    pub fn new(period: u64) -> Self {
+        assert!(period > 0, "the sampling period of a flamegraph can't be 0");
+        Flamegraph {
+            period,
+            countdown: period,
+            stacks: HashMap::new(),
+            truncated: HashMap::new(),
+        }
+    }
+
+    pub fn period(&self) -> u64 {
+        self.period
+    }
+
+    /// Count an executed instruction, and sample the call stack when the
+    /// period is over
+    ///
+    /// # Arguments
+    /// * `pc` => address of the instruction, before the call stack follows
+    ///   it
+    /// * `stack` => calls in progress
+    pub fn sample(&mut self, pc: u32, stack: &CallStack) {
+        self.countdown -= 1;
+        if self.countdown > 0 {
+            return;
+        }
+        self.countdown = self.period;
+        let mut addresses: Vec<u32> = stack.frames().map(|frame| frame.call_pc).collect();
+        addresses.push(pc);
+        let stacks = if stack.depth() > addresses.len() - 1 {
+            &mut self.truncated
+        } else {
+            &mut self.stacks
+        };
+        *stacks.entry(addresses).or_insert(0) += 1;
+    }
+
+    /// Number of samples taken
+    pub fn samples(&self) -> u64 {
+        self.stacks.values().chain(self.truncated.values()).sum()
+    }
+
+    /// Symbolized stacks with their samples, the most sampled first
+    ///
+    /// # Arguments
+    /// * `functions` => functions sorted by address
+    pub fn folded(&self, functions: &[Symbol]) -> Vec<(String, u64)> {
+        let name = |addr: &u32| {
+            Symbol::lookup(functions, *addr).map_or("[unknown]", |symbol| &symbol.name)
+        };
+        let mut folded: HashMap<String, u64> = HashMap::new();
+        let all = self
+            .stacks
+            .iter()
+            .map(|stack| (false, stack))
+            .chain(self.truncated.iter().map(|stack| (true, stack)));
+        for (truncated, (addresses, samples)) in all {
+            let mut names: Vec<&str> = Vec::with_capacity(addresses.len() + 1);
+            if truncated {
+                names.push("[truncated]");
+            }
+            names.extend(addresses.iter().map(name));
+            *folded.entry(names.join(";")).or_insert(0) += samples;
+        }
+        let mut folded: Vec<(String, u64)> = folded.into_iter().collect();
+        folded.sort_by_key(|(stack, samples)| (Reverse(*samples), stack.clone()));
+        folded
+    }
+
+    /// Write the samples as folded stacks
+    ///
+    /// # Arguments
+    /// * `out` => destination of the stacks
+    /// * `functions` => functions sorted by address
+    pub fn write_folded<W: Write>(&self, out: &mut W, functions: &[Symbol]) -> io::Result<()> {
+        for (stack, samples) in self.folded(functions) {
+            writeln!(out, "{} {}", stack, samples)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use riscv::decoder::Instruction;
+
+    const CALL: u32 = 0x0fc0_00ef; // jal ra, 252
+    const RET: u32 = 0x0000_8067; // ret
+
+    fn functions() -> Vec<Symbol> {
+        vec![
+            Symbol {
+                name: "main".to_string(),
+                value: 0x100,
+                size: 0x10,
+            },
+            Symbol {
+                name: "f".to_string(),
+                value: 0x200,
+                size: 0x10,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_period() {
+        let stack = CallStack::new();
+        let mut flamegraph = Flamegraph::new(3);
+        for index in 0..10 {
+            flamegraph.sample(0x100 + 4 * (index % 4), &stack);
+        }
+        // The 3rd, 6th and 9th instructions
+        assert_eq!(3, flamegraph.samples());
+        assert_eq!(
+            vec![("main".to_string(), 3)],
+            flamegraph.folded(&functions())
+        );
+    }
+
+    #[test]
+    fn test_calls_and_returns() {
+        let mut stack = CallStack::new();
+        let mut flamegraph = Flamegraph::new(1);
+        for _ in 0..2 {
+            flamegraph.sample(0x104, &stack);
+            stack.execute(0x104, &Instruction::new(CALL), 0x200);
+            flamegraph.sample(0x200, &stack);
+            flamegraph.sample(0x204, &stack);
+            stack.execute(0x204, &Instruction::new(RET), 0x108);
+        }
+        // Outside of any known function
+        flamegraph.sample(0x400, &stack);
+        let mut out = Vec::new();
+        flamegraph.write_folded(&mut out, &functions()).unwrap();
+        assert_eq!(
+            "main;f 4\nmain 2\n[unknown] 1\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
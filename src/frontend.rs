@@ -0,0 +1,291 @@
+//! Prediction of the next fetch address in the fetch stage of the pipeline.
+//! Without them the pipeline fetches the next instruction in order, and
+//! every taken branch or jump flushes the younger instructions when it
+//! resolves. Two structures can give the fetch stage a target instead:
+//!
+//! * the branch target buffer => a direct mapped table of the taken branches
+//!   and jumps with their last target. A hit predicts that the instruction is
+//!   taken again, and a branch which falls through leaves the table.
+//! * the return address stack => calls push their return address and returns
+//!   pop it. When the stack is full the oldest address is lost. It isn't
+//!   repaired after a flush.
+//!
+//! A branch or a jump whose predicted address was the right one doesn't
+//! flush anything. The hit rates of both structures are reported apart from
+//! the mispredictions, which also count the branches predicted taken that
+//! fell through.
+//!
+//! They are configured in the `[frontend]` table, with 0 entries leaving a
+//! structure out:
+//!
+//! ```toml
+//! [frontend]
+//! btb_entries = 16
+//! ras_depth = 4
+//! ```
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::frontend::{Frontend, FrontendConfig, Source};
+//! # use adept_lib::stats::Stats;
+//! let mut frontend = Frontend::new(FrontendConfig {
+//!     btb_entries: 16,
+//!     ras_depth: 0,
+//! });
+//! let mut stats = Stats::new();
+//! // j 0x40, at 0x100
+//! let first = frontend.predict(0x100, 0xf41f_f06f);
+//! assert_eq!(Source::Sequential, first.source);
+//! frontend.resolve(0x100, 0xf41f_f06f, first, 0x40, &mut stats);
+//! let second = frontend.predict(0x100, 0xf41f_f06f);
+//! assert_eq!((Source::Btb, 0x40), (second.source, second.next_pc));
+//! ```
+use callstack::{is_call, is_return};
+use config::{value_error, Config, ConfigError};
+use riscv::decoder::Instruction;
+use stats::Stats;
+
+// Largest structure of a configuration
+const MAX_ENTRIES: i64 = 1 << 16;
+
+/// Sizes of the prediction structures, 0 to leave one out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrontendConfig {
+    /// Entries of the branch target buffer
+    pub btb_entries: u32,
+    /// Return addresses held by the return address stack
+    pub ras_depth: u32,
+}
+
+impl FrontendConfig {
+    /// Create a frontend configuration from the `[frontend]` table of a
+    /// configuration. Missing values leave the structures out.
+    ///
+    /// # Arguments
+    /// * `config` => parsed configuration
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let entries = |key: &str| match config.get_integer("frontend", key)? {
+            None => Ok(0),
+            Some(entries) if (0..=MAX_ENTRIES).contains(&entries) => Ok(entries as u32),
+            Some(_) => Err(value_error(
+                "frontend",
+                key,
+                &format!("expected 0 to {} entries", MAX_ENTRIES),
+            )),
+        };
+        Ok(FrontendConfig {
+            btb_entries: entries("btb_entries")?,
+            ras_depth: entries("ras_depth")?,
+        })
+    }
+}
+
+/// Where the fetch stage took the address of the next instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The following instruction
+    Sequential,
+    /// The target of a branch target buffer hit
+    Btb,
+    /// The top of the return address stack
+    Ras,
+}
+
+/// Address the fetch stage continues from after an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Prediction {
+    pub next_pc: u32,
+    pub source: Source,
+}
+
+impl Prediction {
+    /// The instruction following the one at `pc`
+    pub fn sequential(pc: u32) -> Self {
+        Prediction {
+            next_pc: pc.wrapping_add(4),
+            source: Source::Sequential,
+        }
+    }
+}
+
+/// Branch target buffer and return address stack of the fetch stage
+#[derive(Debug, Clone)]
+pub struct Frontend {
+    // Address and target of the taken branches and jumps, by index
+    btb: Vec<Option<(u32, u32)>>,
+    // Return addresses, the last one on top
+    ras: Vec<u32>,
+    ras_depth: usize,
+}
+
+impl Frontend {
+    /// Create empty prediction structures
+    pub fn new(config: FrontendConfig) -> Self {
+        Frontend {
+            btb: vec![None; config.btb_entries as usize],
+            ras: Vec::with_capacity(config.ras_depth as usize),
+            ras_depth: config.ras_depth as usize,
+        }
+    }
+
+    /// Predict the address fetched after an instruction
+    ///
+    /// # Arguments
+    /// * `pc` => address of the fetched instruction
+    /// * `raw` => its bits, which tell the calls and the returns
+    pub fn predict(&mut self, pc: u32, raw: u32) -> Prediction {
+        let decoded = Instruction::new(raw);
+        let mut prediction = Prediction::sequential(pc);
+        if self.ras_depth > 0 && is_return(&decoded) {
+            if let Some(addr) = self.ras.pop() {
+                prediction = Prediction {
+                    next_pc: addr,
+                    source: Source::Ras,
+                };
+            }
+        } else if let Some(target) = self.lookup(pc) {
+            prediction = Prediction {
+                next_pc: target,
+                source: Source::Btb,
+            };
+        }
+        // A call through a link register may also return
+        if self.ras_depth > 0 && is_call(&decoded) {
+            if self.ras.len() == self.ras_depth {
+                self.ras.remove(0);
+            }
+            self.ras.push(pc.wrapping_add(4));
+        }
+        prediction
+    }
+
+    /// Learn where a branch or a jump went, and count the predictions which
+    /// were right
+    ///
+    /// # Arguments
+    /// * `pc` => address of the instruction
+    /// * `raw` => its bits
+    /// * `prediction` => what the fetch stage predicted for it
+    /// * `next_pc` => where it went
+    /// * `stats` => statistics to update
+    pub fn resolve(
+        &mut self,
+        pc: u32,
+        raw: u32,
+        prediction: Prediction,
+        next_pc: u32,
+        stats: &mut Stats,
+    ) {
+        if prediction.next_pc != next_pc {
+            stats.mispredictions += 1;
+        }
+        let taken = next_pc != pc.wrapping_add(4);
+        if self.ras_depth > 0 && is_return(&Instruction::new(raw)) {
+            stats.ras_lookups += 1;
+            if prediction.source == Source::Ras && prediction.next_pc == next_pc {
+                stats.ras_hits += 1;
+            }
+        } else if !self.btb.is_empty() {
+            let index = self.index(pc);
+            if taken {
+                stats.btb_lookups += 1;
+                if prediction.source == Source::Btb && prediction.next_pc == next_pc {
+                    stats.btb_hits += 1;
+                }
+                self.btb[index] = Some((pc, next_pc));
+            } else if prediction.source == Source::Btb {
+                self.btb[index] = None;
+            }
+        }
+    }
+
+    fn index(&self, pc: u32) -> usize {
+        (pc >> 2) as usize % self.btb.len()
+    }
+
+    // Target of a branch target buffer hit
+    fn lookup(&self, pc: u32) -> Option<u32> {
+        if self.btb.is_empty() {
+            return None;
+        }
+        match self.btb[self.index(pc)] {
+            Some((address, target)) if address == pc => Some(target),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALL: u32 = 0x0100_00ef; // jal ra, 16
+    const RET: u32 = 0x0000_8067; // ret
+    const BNEZ: u32 = 0xfe05_1ee3; // bnez a0, -4
+
+    #[test]
+    fn test_btb() {
+        let mut frontend = Frontend::new(FrontendConfig {
+            btb_entries: 4,
+            ras_depth: 0,
+        });
+        let mut stats = Stats::new();
+        let mut branch = |frontend: &mut Frontend, pc: u32, taken: bool| {
+            let prediction = frontend.predict(pc, BNEZ);
+            let next_pc = if taken { pc - 4 } else { pc + 4 };
+            frontend.resolve(pc, BNEZ, prediction, next_pc, &mut stats);
+            prediction.source
+        };
+        assert_eq!(Source::Sequential, branch(&mut frontend, 0x104, true));
+        assert_eq!(Source::Btb, branch(&mut frontend, 0x104, true));
+        // 0x114 takes the entry of 0x104
+        assert_eq!(Source::Sequential, branch(&mut frontend, 0x114, true));
+        assert_eq!(Source::Sequential, branch(&mut frontend, 0x104, true));
+        // Falling through removes the entry
+        assert_eq!(Source::Btb, branch(&mut frontend, 0x104, false));
+        assert_eq!(Source::Sequential, branch(&mut frontend, 0x104, false));
+        assert_eq!((4, 1), (stats.btb_lookups, stats.btb_hits));
+        assert_eq!(4, stats.mispredictions);
+    }
+
+    #[test]
+    fn test_return_address_stack() {
+        let mut frontend = Frontend::new(FrontendConfig {
+            btb_entries: 0,
+            ras_depth: 2,
+        });
+        let mut stats = Stats::new();
+        // Three nested calls, which only leave room for two return addresses
+        for pc in &[0x100, 0x200, 0x300] {
+            let prediction = frontend.predict(*pc, CALL);
+            frontend.resolve(*pc, CALL, prediction, pc + 16, &mut stats);
+        }
+        let returns: Vec<Prediction> = (0..3).map(|_| frontend.predict(0x400, RET)).collect();
+        assert_eq!(
+            vec![0x304, 0x204, 0x404],
+            returns.iter().map(|p| p.next_pc).collect::<Vec<_>>()
+        );
+        for (prediction, next_pc) in returns.iter().zip(&[0x304, 0x204, 0x104]) {
+            frontend.resolve(0x400, RET, *prediction, *next_pc, &mut stats);
+        }
+        assert_eq!((3, 2), (stats.ras_lookups, stats.ras_hits));
+        // The calls were taken jumps, which the stack doesn't count
+        assert_eq!((0, 0), (stats.btb_lookups, stats.btb_hits));
+        assert_eq!(4, stats.mispredictions);
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = Config::parse("[frontend]\nbtb_entries = 32\n").unwrap();
+        assert_eq!(
+            FrontendConfig {
+                btb_entries: 32,
+                ras_depth: 0
+            },
+            FrontendConfig::from_config(&config).unwrap()
+        );
+        let config = Config::parse("[frontend]\nras_depth = -1\n").unwrap();
+        assert!(FrontendConfig::from_config(&config).is_err());
+    }
+}
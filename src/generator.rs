@@ -0,0 +1,563 @@
+//! Random instruction stream generator. A generated program sets every
+//! register to a random value, runs a body of random RV32I instructions drawn
+//! from a mix of classes, and ends with a self-checking epilogue which
+//! compares the registers and the data the body works on with the values the
+//! generator got by running the body itself. The program exits with code 0
+//! when they all match and with code 1 otherwise.
+//!
+//! Loads and stores only access the data block of the program, through a
+//! base register the body never writes, and branches and jumps only go
+//! forward, so every body runs to the epilogue. The M extension isn't
+//! generated since the hart doesn't execute it.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::generator::{Generator, Mix};
+//! # use adept_lib::hart::{Hart, StopReason};
+//! # use adept_lib::mem::MemStoreOp;
+//! # use adept_lib::riscv::isa::RV32I;
+//! let mix = Mix::parse("alu=2,load=1,store=1,branch=1").unwrap();
+//! let program = Generator::new(7, mix).generate(0x1000, 200).unwrap();
+//! let mut hart = Hart::new();
+//! for (addr, word) in program.image().words() {
+//!     hart.memory_mut().write_data(&MemStoreOp::from(RV32I::SW), addr, word).unwrap();
+//! }
+//! hart.set_pc(program.base);
+//! let reason = loop {
+//!     let pc = hart.pc();
+//!     let raw = hart.memory().read_pc(pc);
+//!     if let Err(reason) = hart.execute(pc, raw) {
+//!         break reason;
+//!     }
+//! };
+//! assert_eq!(StopReason::Exit(0), reason);
+//! ```
+use elf::{self, Segment, SEGMENT_EXECUTE, SEGMENT_READ, SEGMENT_WRITE};
+use env::SYS_EXIT;
+use error::Error;
+use fault::Rng;
+use hart::Hart;
+use image::MemoryImage;
+use mem::{MemLoadOp, MemStoreOp};
+use riscv::isa::RV32I;
+
+/// Address of the first instruction unless another one is given
+pub const DEFAULT_BASE: u32 = 0x8000_0000;
+/// Instructions of the body unless another number is given
+pub const DEFAULT_COUNT: usize = 1000;
+
+// Words of the data block
+const DATA_WORDS: usize = 64;
+// Base address of the data block, never written by the body
+const DATA_REGISTER: u32 = 8;
+// Scratch registers of the epilogue, never written by the body
+const VALUE_REGISTER: u32 = 30;
+const EXPECTED_REGISTER: u32 = 31;
+// Instructions the body skips at most with a branch or a jump
+const MAX_SKIP: u32 = 4;
+
+/// Weights of the classes of instructions in the body of a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    /// Register to register operations
+    pub alu: u32,
+    /// Operations with an immediate, shifts included
+    pub imm: u32,
+    pub load: u32,
+    pub store: u32,
+    /// Forward conditional branches
+    pub branch: u32,
+    /// Forward jumps
+    pub jump: u32,
+    /// lui and auipc
+    pub upper: u32,
+}
+
+impl Default for Mix {
+    fn default() -> Self {
+        Mix {
+            alu: 4,
+            imm: 4,
+            load: 2,
+            store: 2,
+            branch: 2,
+            jump: 1,
+            upper: 1,
+        }
+    }
+}
+
+impl Mix {
+    /// Parse a mix given as `CLASS=WEIGHT` pairs separated by commas, such
+    /// as `alu=4,load=1`. The classes which aren't given are never drawn.
+    ///
+    /// # Return Value
+    /// The mix, or why it's invalid
+    pub fn parse(spec: &str) -> Result<Mix, String> {
+        let mut mix = Mix {
+            alu: 0,
+            imm: 0,
+            load: 0,
+            store: 0,
+            branch: 0,
+            jump: 0,
+            upper: 0,
+        };
+        for pair in spec.split(',') {
+            let (class, weight) = match pair.find('=') {
+                Some(index) => (pair[..index].trim(), pair[index + 1..].trim()),
+                None => return Err(format!("expected CLASS=WEIGHT, got {:?}", pair)),
+            };
+            let weight = weight
+                .parse::<u32>()
+                .map_err(|e| format!("invalid weight {}: {}", weight, e))?;
+            let slot = match class {
+                "alu" => &mut mix.alu,
+                "imm" => &mut mix.imm,
+                "load" => &mut mix.load,
+                "store" => &mut mix.store,
+                "branch" => &mut mix.branch,
+                "jump" => &mut mix.jump,
+                "upper" => &mut mix.upper,
+                _ => return Err(format!("unknown instruction class {}", class)),
+            };
+            *slot = weight;
+        }
+        if mix.total() == 0 {
+            return Err("every weight of the mix is 0".to_string());
+        }
+        Ok(mix)
+    }
+
+    fn total(&self) -> u64 {
+        [
+            self.alu,
+            self.imm,
+            self.load,
+            self.store,
+            self.branch,
+            self.jump,
+            self.upper,
+        ]
+        .iter()
+        .map(|weight| u64::from(*weight))
+        .sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Alu,
+    Imm,
+    Load,
+    Store,
+    Branch,
+    Jump,
+    Upper,
+}
+
+/// A generated program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    /// Address of the first instruction, where the program starts
+    pub base: u32,
+    /// Instructions of the prologue, the body and the epilogue
+    pub code: Vec<u32>,
+    /// Address of the data block, following the code
+    pub data_base: u32,
+    /// Initial words of the data block
+    pub data: Vec<u32>,
+}
+
+impl Program {
+    /// The code and the data in memory
+    pub fn image(&self) -> MemoryImage {
+        let mut image = MemoryImage::new();
+        for (index, word) in self.code.iter().enumerate() {
+            image.write_word(self.base.wrapping_add(4 * index as u32), *word);
+        }
+        for (index, word) in self.data.iter().enumerate() {
+            image.write_word(self.data_base.wrapping_add(4 * index as u32), *word);
+        }
+        image
+    }
+
+    /// An executable ELF file with a segment for the code and one for the
+    /// data
+    pub fn elf(&self) -> Vec<u8> {
+        let bytes = |words: &[u32]| -> Vec<u8> {
+            words
+                .iter()
+                .flat_map(|word| word.to_le_bytes().to_vec())
+                .collect()
+        };
+        let segments = [
+            Segment {
+                address: self.base,
+                contents: bytes(&self.code),
+                size: 4 * self.code.len() as u32,
+                flags: SEGMENT_READ | SEGMENT_EXECUTE,
+            },
+            Segment {
+                address: self.data_base,
+                contents: bytes(&self.data),
+                size: 4 * self.data.len() as u32,
+                flags: SEGMENT_READ | SEGMENT_WRITE,
+            },
+        ];
+        elf::executable(self.base, &segments)
+    }
+}
+
+/// Generator of random programs
+pub struct Generator {
+    rng: Rng,
+    mix: Mix,
+}
+
+impl Generator {
+    /// Create a generator. The same seed and mix give the same programs.
+    pub fn new(seed: u64, mix: Mix) -> Self {
+        Generator {
+            rng: Rng::new(seed),
+            mix,
+        }
+    }
+
+    /// Generate a program
+    ///
+    /// # Arguments
+    /// * `base` => address of the first instruction, aligned to a word
+    /// * `count` => instructions of the body
+    ///
+    /// Fails when the body stops the hart before the epilogue or the
+    /// program doesn't fit in memory
+    pub fn generate(&mut self, base: u32, count: usize) -> Result<Program, Error> {
+        let pool = registers();
+        // The prologue sets the data base and the registers, and the
+        // epilogue checks the registers, then the data, then exits
+        let prologue_size = 2 + 2 * pool.len();
+        let epilogue_size = 3 * pool.len() + 4 * DATA_WORDS + 6;
+        let code_size = 4 * (prologue_size + count + epilogue_size) as u32;
+        let data_base = base.wrapping_add(code_size + 15) & !15;
+        let data: Vec<u32> = (0..DATA_WORDS)
+            .map(|_| self.rng.next_u64() as u32)
+            .collect();
+
+        let mut code = load_immediate(DATA_REGISTER, data_base).to_vec();
+        for register in &pool {
+            let value = self.rng.next_u64() as u32;
+            code.extend_from_slice(&load_immediate(*register, value));
+        }
+        for index in 0..count {
+            let remaining = (count - index - 1) as u32;
+            let instruction = self.instruction(&pool, remaining);
+            code.push(instruction);
+        }
+
+        // Run the program up to the epilogue to get the values it checks
+        let mut hart = Hart::new();
+        let store = MemStoreOp::from(RV32I::SW);
+        for (index, word) in code.iter().enumerate() {
+            let addr = base.wrapping_add(4 * index as u32);
+            hart.memory_mut().write_data(&store, addr, *word)?;
+        }
+        for (index, word) in data.iter().enumerate() {
+            let addr = data_base.wrapping_add(4 * index as u32);
+            hart.memory_mut().write_data(&store, addr, *word)?;
+        }
+        hart.set_pc(base);
+        let epilogue = base.wrapping_add(4 * code.len() as u32);
+        while hart.pc() != epilogue {
+            let pc = hart.pc();
+            let raw = hart.memory().read_pc(pc);
+            if let Err(reason) = hart.execute(pc, raw) {
+                let context = format!("generated instruction at pc 0x{:08x}", pc);
+                return Err(Error::runtime(&context, reason.to_string()));
+            }
+        }
+
+        // Every check branches to the exit with code 1, after the exit with
+        // code 0
+        let fail = epilogue.wrapping_add(4 * (epilogue_size - 3) as u32);
+        let check = |code: &mut Vec<u32>, register: u32, expected: u32| {
+            code.extend_from_slice(&load_immediate(EXPECTED_REGISTER, expected));
+            let pc = base.wrapping_add(4 * code.len() as u32);
+            code.push(branch(
+                1,
+                register,
+                EXPECTED_REGISTER,
+                fail.wrapping_sub(pc),
+            ));
+        };
+        for register in &pool {
+            let (value, _) = hart.registers().read(*register as u8, 0);
+            check(&mut code, *register, value as u32);
+        }
+        let load = MemLoadOp::from(RV32I::LW);
+        for index in 0..DATA_WORDS as u32 {
+            let addr = data_base.wrapping_add(4 * index);
+            let value = hart.memory().load_data(&load, addr)?;
+            code.push(i_type(0x03, 2, VALUE_REGISTER, DATA_REGISTER, 4 * index));
+            check(&mut code, VALUE_REGISTER, value as u32);
+        }
+        for exit_code in 0..2 {
+            code.push(i_type(0x13, 0, 10, 0, exit_code));
+            code.push(i_type(0x13, 0, 17, 0, SYS_EXIT));
+            code.push(0x0000_0073);
+        }
+
+        Ok(Program {
+            base,
+            code,
+            data_base,
+            data,
+        })
+    }
+
+    // Draw an instruction of the body, followed by `remaining` instructions
+    fn instruction(&mut self, pool: &[u32], remaining: u32) -> u32 {
+        let rd = pool[self.rng.below(pool.len() as u64) as usize];
+        // Sources are the registers of the body, the data base or zero
+        let source = |rng: &mut Rng| match rng.below(pool.len() as u64 + 2) as usize {
+            index if index < pool.len() => pool[index],
+            index if index == pool.len() => DATA_REGISTER,
+            _ => 0,
+        };
+        let rs1 = source(&mut self.rng);
+        let rs2 = source(&mut self.rng);
+        let imm = self.rng.below(1 << 12) as u32;
+        match self.class(remaining) {
+            Class::Alu => {
+                let (funct3, funct7) = [
+                    (0, 0),
+                    (0, 0x20),
+                    (1, 0),
+                    (2, 0),
+                    (3, 0),
+                    (4, 0),
+                    (5, 0),
+                    (5, 0x20),
+                    (6, 0),
+                    (7, 0),
+                ][self.rng.below(10) as usize];
+                (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | 0x33
+            }
+            Class::Imm => match self.rng.below(9) {
+                // slli, srli and srai take a shift amount
+                shift @ 0..=2 => {
+                    let (funct3, funct7) = [(1, 0), (5, 0), (5, 0x20)][shift as usize];
+                    i_type(0x13, funct3, rd, rs1, (funct7 << 5) | (imm & 0x1f))
+                }
+                _ => {
+                    let funct3 = [0, 2, 3, 4, 6, 7][self.rng.below(6) as usize];
+                    i_type(0x13, funct3, rd, rs1, imm)
+                }
+            },
+            Class::Load => {
+                let (funct3, size) =
+                    [(0, 1), (1, 2), (2, 4), (4, 1), (5, 2)][self.rng.below(5) as usize];
+                i_type(0x03, funct3, rd, DATA_REGISTER, self.data_offset(size))
+            }
+            Class::Store => {
+                let (funct3, size) = [(0, 1), (1, 2), (2, 4)][self.rng.below(3) as usize];
+                let offset = self.data_offset(size);
+                ((offset >> 5) << 25)
+                    | (rs2 << 20)
+                    | (DATA_REGISTER << 15)
+                    | (funct3 << 12)
+                    | ((offset & 0x1f) << 7)
+                    | 0x23
+            }
+            Class::Branch => {
+                let funct3 = [0, 1, 4, 5, 6, 7][self.rng.below(6) as usize];
+                branch(funct3, rs1, rs2, self.skip(remaining))
+            }
+            Class::Jump => jal(rd, self.skip(remaining)),
+            Class::Upper => {
+                let upper = self.rng.next_u64() as u32 & 0xffff_f000;
+                // lui or auipc
+                let opcode = [0x37, 0x17][self.rng.below(2) as usize];
+                upper | (rd << 7) | opcode
+            }
+        }
+    }
+
+    // Draw a class from the mix. Branches and jumps need an instruction to
+    // skip before the epilogue.
+    fn class(&mut self, remaining: u32) -> Class {
+        loop {
+            let mut draw = self.rng.below(self.mix.total());
+            for (class, weight) in &[
+                (Class::Alu, self.mix.alu),
+                (Class::Imm, self.mix.imm),
+                (Class::Load, self.mix.load),
+                (Class::Store, self.mix.store),
+                (Class::Branch, self.mix.branch),
+                (Class::Jump, self.mix.jump),
+                (Class::Upper, self.mix.upper),
+            ] {
+                let weight = u64::from(*weight);
+                if draw < weight {
+                    match class {
+                        Class::Branch | Class::Jump if remaining == 0 => break,
+                        _ => return *class,
+                    }
+                }
+                draw -= weight;
+            }
+            // Only branches and jumps can be drawn at the end of the body
+            if self.mix.total() == u64::from(self.mix.branch) + u64::from(self.mix.jump) {
+                return Class::Imm;
+            }
+        }
+    }
+
+    // Offset of a branch or a jump skipping up to MAX_SKIP of the
+    // `remaining` instructions
+    fn skip(&mut self, remaining: u32) -> u32 {
+        let skipped = 1 + self.rng.below(u64::from(remaining.min(MAX_SKIP))) as u32;
+        4 * (skipped + 1)
+    }
+
+    // Offset of a naturally aligned access in the data block
+    fn data_offset(&mut self, size: u32) -> u32 {
+        size * self.rng.below(u64::from(4 * DATA_WORDS as u32 / size)) as u32
+    }
+}
+
+// Registers the body writes: all but zero, the data base and the scratch
+// registers of the epilogue
+fn registers() -> Vec<u32> {
+    (1..32)
+        .filter(|register| ![DATA_REGISTER, VALUE_REGISTER, EXPECTED_REGISTER].contains(register))
+        .collect()
+}
+
+// lui and addi loading a value in a register
+fn load_immediate(rd: u32, value: u32) -> [u32; 2] {
+    let lo = ((value << 20) as i32 >> 20) as u32;
+    let hi = value.wrapping_sub(lo);
+    [
+        (hi & 0xffff_f000) | (rd << 7) | 0x37,
+        i_type(0x13, 0, rd, rd, lo),
+    ]
+}
+
+fn i_type(opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: u32) -> u32 {
+    ((imm & 0xfff) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn branch(funct3: u32, rs1: u32, rs2: u32, offset: u32) -> u32 {
+    (((offset >> 12) & 1) << 31)
+        | (((offset >> 5) & 0x3f) << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (funct3 << 12)
+        | (((offset >> 1) & 0xf) << 8)
+        | (((offset >> 11) & 1) << 7)
+        | 0x63
+}
+
+fn jal(rd: u32, offset: u32) -> u32 {
+    (((offset >> 20) & 1) << 31)
+        | (((offset >> 1) & 0x3ff) << 21)
+        | (((offset >> 11) & 1) << 20)
+        | (((offset >> 12) & 0xff) << 12)
+        | (rd << 7)
+        | 0x6f
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hart::StopReason;
+
+    fn run(program: &Program) -> StopReason {
+        let mut hart = Hart::new();
+        for (addr, word) in program.image().words() {
+            hart.memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), addr, word)
+                .unwrap();
+        }
+        hart.set_pc(program.base);
+        loop {
+            let pc = hart.pc();
+            let raw = hart.memory().read_pc(pc);
+            if let Err(reason) = hart.execute(pc, raw) {
+                return reason;
+            }
+        }
+    }
+
+    #[test]
+    fn test_encodings() {
+        // addi a0, a0, 1
+        assert_eq!(0x0015_0513, i_type(0x13, 0, 10, 10, 1));
+        // beq zero, zero, -4
+        assert_eq!(0xfe00_0ee3, branch(0, 0, 0, (-4i32) as u32));
+        // jal ra, 8
+        assert_eq!(0x0080_00ef, jal(1, 8));
+        // lui a0, 0x12345; addi a0, a0, -1
+        assert_eq!([0x1234_5537, 0xfff5_0513], load_immediate(10, 0x1234_4fff));
+    }
+
+    #[test]
+    fn test_self_check() {
+        for seed in 0..8 {
+            let program = Generator::new(seed, Mix::default())
+                .generate(DEFAULT_BASE, 300)
+                .unwrap();
+            assert_eq!(StopReason::Exit(0), run(&program));
+            // Changing the expected value of a register fails the check
+            let mut broken = program.clone();
+            let check = broken.code.len() - 3 * 2 - 4 * DATA_WORDS - 3 * registers().len();
+            broken.code[check] ^= 1 << 12;
+            assert_eq!(StopReason::Exit(1), run(&broken));
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let program = Generator::new(3, Mix::default())
+            .generate(0x1000, 100)
+            .unwrap();
+        assert_eq!(
+            program,
+            Generator::new(3, Mix::default())
+                .generate(0x1000, 100)
+                .unwrap()
+        );
+        assert_ne!(
+            program,
+            Generator::new(4, Mix::default())
+                .generate(0x1000, 100)
+                .unwrap()
+        );
+        assert_eq!(0, program.data_base % 16);
+        assert!(program.data_base >= 0x1000 + 4 * program.code.len() as u32);
+    }
+
+    #[test]
+    fn test_misaligned_base() {
+        let error = Generator::new(0, Mix::default())
+            .generate(0x1002, 10)
+            .unwrap_err();
+        assert!(matches!(error, Error::Memory(_)));
+    }
+
+    #[test]
+    fn test_mix() {
+        let mix = Mix::parse("alu=3, jump=1").unwrap();
+        assert_eq!((3, 1, 0), (mix.alu, mix.jump, mix.load));
+        assert!(Mix::parse("alu").is_err());
+        assert!(Mix::parse("mul=1").is_err());
+        assert!(Mix::parse("alu=0").is_err());
+        // A body of jumps still reaches the epilogue
+        let program = Generator::new(1, Mix::parse("jump=1").unwrap())
+            .generate(0, 20)
+            .unwrap();
+        assert_eq!(StopReason::Exit(0), run(&program));
+    }
+}
@@ -0,0 +1,2254 @@
+//! The architectural state of the processor: the program counter, the
+//! register file, the control and status registers, the memory, the devices
+//! and the heap of the execution environment. A hart executes one
+//! instruction at a time without any notion of time, the timing is the job
+//! of the pipeline model.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::hart::Hart;
+//! # use adept_lib::mem::MemStoreOp;
+//! # use adept_lib::riscv::isa::RV32I;
+//! let mut hart = Hart::new();
+//! // addi a0, zero, 5
+//! hart.memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 0, 0x0050_0513).unwrap();
+//! let executed = hart.execute(0, hart.memory().read_pc(0)).unwrap();
+//! assert_eq!(4, executed.next_pc);
+//! assert_eq!((5, 0), hart.registers().read(10, 0));
+//! ```
+use alu::{alu, AluOp};
+use bus::{Arbiter, Arbitration, Master};
+use callstack::CallStack;
+use clint::{Clint, TIMEBASE_FREQUENCY};
+use coverage::Coverage;
+use csr::{
+    CsrFile, CAUSE_BREAKPOINT, CAUSE_FETCH_ACCESS, CAUSE_ILLEGAL_INSTRUCTION, CAUSE_LOAD_ACCESS,
+    CAUSE_MACHINE_ECALL, CAUSE_MISALIGNED_FETCH, CAUSE_MISALIGNED_LOAD, CAUSE_MISALIGNED_STORE,
+    CAUSE_STORE_ACCESS, INTERRUPT_MACHINE_EXTERNAL, INTERRUPT_MACHINE_SOFTWARE,
+    INTERRUPT_MACHINE_TIMER, MIE, MTVEC,
+};
+use custom::{CustomExtension, CustomInstruction};
+use device::{Device, DeviceError, DeviceMap};
+use diagnostics::{Diagnostic, Diagnostics};
+use dma::Dma;
+use env::{Guard, GuardKind, Heap, SYS_BRK, SYS_EXIT, SYS_GETTIMEOFDAY, SYS_WRITE};
+use flamegraph::Flamegraph;
+use heatmap::Heatmap;
+use latency::InstrClass;
+use mem::{MemAccess, MemError, MemErrorKind, MemLoadOp, MemStoreOp, Memory};
+use register_file::RegisterFile;
+use riscv::decoder::{Hint, Instruction};
+use riscv::extension::Extension;
+use riscv::isa::RV32I;
+use taint::TaintTracker;
+use trigger::TriggerAction;
+use uart::Uart;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use std::fmt::{self, Display, Formatter};
+use std::io::ErrorKind;
+use std::time::Duration;
+
+/// Reasons for the simulation to stop
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum StopReason {
+    /// The instruction at `pc` could not be decoded
+    InvalidInstruction { pc: u32, raw: u32 },
+    /// The program requested a system call which isn't emulated
+    UnsupportedSyscall { pc: u32, number: u32 },
+    /// The program exited with the given code
+    Exit(i32),
+    /// An ebreak was executed at `pc`
+    Breakpoint { pc: u32 },
+    /// A trigger entering debug mode fired on the instruction at `pc`, which
+    /// executes when the simulation resumes
+    Trigger { pc: u32, index: usize },
+    /// The load or store at `pc` accessed a guard region at `addr`
+    GuardFault { pc: u32, addr: u32, kind: GuardKind },
+    /// The return at `pc` jumped to `target` instead of the return address
+    /// recorded by its call
+    ReturnMismatch { pc: u32, expected: u32, target: u32 },
+    /// The load at `pc` read memory at `addr` which was never written
+    UninitializedRead { pc: u32, addr: u32 },
+    /// The memory access of the instruction at `pc` failed
+    MemoryFault { pc: u32, error: MemError },
+    /// A pipeline trace could not be written
+    TraceError(ErrorKind),
+    /// The console of the serial port didn't get the output it expected by
+    /// the instruction at `pc`
+    ConsoleMismatch { pc: u32 },
+    /// The program was still running after the given number of cycles
+    CycleLimit(u64),
+    /// The program was still running when the time limit of the simulation
+    /// ran out
+    TimeLimit(Duration),
+}
+
+impl StopReason {
+    /// Check if the simulation was stopped by one of its limits rather than
+    /// by the program
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, StopReason::CycleLimit(_) | StopReason::TimeLimit(_))
+    }
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            StopReason::InvalidInstruction { pc, raw } => {
+                write!(f, "invalid instruction 0x{:08x} at pc 0x{:08x}", raw, pc)?;
+                match Extension::of(*raw) {
+                    Some(extension) => write!(f, " of the unsupported {} extension", extension),
+                    None => Ok(()),
+                }
+            }
+            StopReason::UnsupportedSyscall { pc, number } => {
+                write!(f, "unsupported system call {} at pc 0x{:08x}", number, pc)
+            }
+            StopReason::Exit(code) => write!(f, "exited with code {}", code),
+            StopReason::Breakpoint { pc } => write!(f, "breakpoint at pc 0x{:08x}", pc),
+            StopReason::Trigger { pc, index } => {
+                write!(f, "trigger {} fired at pc 0x{:08x}", index, pc)
+            }
+            StopReason::GuardFault { pc, addr, kind } => write!(
+                f,
+                "access fault at 0x{:08x} by pc 0x{:08x}: {}",
+                addr,
+                pc,
+                kind.diagnostic()
+            ),
+            StopReason::ReturnMismatch {
+                pc,
+                expected,
+                target,
+            } => write!(
+                f,
+                "return at pc 0x{:08x} to 0x{:08x} instead of 0x{:08x}: probable stack corruption",
+                pc, target, expected
+            ),
+            StopReason::UninitializedRead { pc, addr } => write!(
+                f,
+                "read of uninitialized memory at 0x{:08x} by pc 0x{:08x}",
+                addr, pc
+            ),
+            StopReason::MemoryFault { pc, error } => write!(f, "{} by pc 0x{:08x}", error, pc),
+            StopReason::ConsoleMismatch { pc } => write!(
+                f,
+                "the console didn't get the expected output by pc 0x{:08x}",
+                pc
+            ),
+            StopReason::TraceError(kind) => write!(f, "failed to write the trace: {}", kind),
+            StopReason::CycleLimit(cycles) => write!(f, "still running after {} cycles", cycles),
+            StopReason::TimeLimit(limit) => {
+                write!(f, "still running after {:.3} seconds", limit.as_secs_f64())
+            }
+        }
+    }
+}
+
+/// What to do when a load reads memory which was never written
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum UninitializedPolicy {
+    /// Record the load and keep going
+    Warn,
+    /// Stop the simulation
+    Stop,
+}
+
+/// What to do with instructions which can't be executed
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum IllegalInstructionPolicy {
+    /// Raise an illegal instruction exception, jumping to mtvec. Programs
+    /// which never set mtvec stop instead, since they would keep trapping
+    /// to address 0.
+    Trap,
+    /// Stop the simulation
+    Stop,
+    /// Record the instruction and continue with the next one
+    Skip,
+}
+
+/// What to do with misaligned and faulting memory accesses
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum MemoryErrorPolicy {
+    /// Stop the simulation
+    Stop,
+    /// Raise an access fault or misaligned exception, jumping to mtvec with
+    /// the faulting address in mtval
+    Trap,
+}
+
+/// What to do with environment calls
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum EnvironmentCallPolicy {
+    /// Emulate the system calls of the execution environment
+    Emulate,
+    /// Raise an environment call exception, jumping to mtvec. Operating
+    /// systems use it to enter the kernel.
+    Trap,
+}
+
+/// Instruction which was skipped because it can't be executed
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct IllegalInstruction {
+    pub pc: u32,
+    pub raw: u32,
+}
+
+/// Instructions of an unsupported extension which the program tried to
+/// execute
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ExtensionUse {
+    pub extension: Extension,
+    /// Address of the first instruction
+    pub first_pc: u32,
+    /// Number of executed instructions
+    pub count: u64,
+}
+
+/// Load which read memory that was never written
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct UninitializedRead {
+    pub pc: u32,
+    pub addr: u32,
+}
+
+/// Result of executing one instruction
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Executed {
+    /// Address of the next instruction in program order
+    pub next_pc: u32,
+    /// Class of the executed instruction
+    pub class: InstrClass,
+    /// Address accessed by a load or a store
+    pub mem_addr: Option<u32>,
+    /// Set if the load or store crossed the boundary of a word, taking two
+    /// memory accesses
+    pub split_access: bool,
+    /// Cycles the load or store waited for the DMA engine to free the bus
+    pub bus_wait: u32,
+}
+
+/// Snapshot of the state a program sees: the pc, the registers and the
+/// written pages of the memory. It can be saved to a file with the serde
+/// feature, and restored into a hart to resume the program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ArchState {
+    pub pc: u32,
+    pub registers: RegisterFile,
+    pub csrs: CsrFile,
+    /// Address and contents of every written page
+    pub memory: Vec<(u32, Vec<u8>)>,
+}
+
+/// Architectural state of one RISC-V hart
+#[derive(Clone)]
+pub struct Hart {
+    pc: u32,
+    registers: RegisterFile,
+    csrs: CsrFile,
+    memory: Box<Memory>,
+    clint: Clint,
+    uart: Uart,
+    dma: Dma,
+    devices: DeviceMap,
+    // Master granted the bus during the instruction, None if nobody asked
+    // for it
+    bus_grant: Option<Master>,
+    arbiter: Arbiter,
+    heap: Heap,
+    // Bytes written to the standard output and error
+    output: Vec<u8>,
+    // Address polled by the host for the exit code of the program
+    tohost: Option<u32>,
+    uninitialized_policy: UninitializedPolicy,
+    uninitialized_reads: Vec<UninitializedRead>,
+    illegal_policy: IllegalInstructionPolicy,
+    memory_error_policy: MemoryErrorPolicy,
+    ecall_policy: EnvironmentCallPolicy,
+    skipped_instructions: Vec<IllegalInstruction>,
+    extension_uses: Vec<ExtensionUse>,
+    taint: Option<TaintTracker>,
+    guards: Vec<Guard>,
+    call_stack: CallStack,
+    check_returns: bool,
+    coverage: Option<Coverage>,
+    diagnostics: Option<Diagnostics>,
+    heatmap: Option<Heatmap>,
+    flamegraph: Option<Flamegraph>,
+    custom: Option<Box<dyn CustomExtension>>,
+    // Instruction and access stopped by a trigger, which is ignored once so
+    // that the instruction can be executed
+    trigger_stop: Option<(u32, MemAccess)>,
+    // Set once the pipeline model counts the cycles, until then every
+    // instruction takes a cycle
+    timed: bool,
+    // Register written by the last executed instruction and its value
+    last_write: Option<(u8, u32)>,
+}
+
+impl Default for Hart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hart {
+    /// Create a hart with a fresh register file and memory, starting at
+    /// address 0
+    pub fn new() -> Self {
+        Hart {
+            pc: 0,
+            registers: RegisterFile::new(),
+            csrs: CsrFile::new(),
+            memory: Box::new(Memory::new()),
+            clint: Clint::new(),
+            uart: Uart::new(),
+            dma: Dma::new(),
+            devices: DeviceMap::new(),
+            bus_grant: None,
+            arbiter: Arbiter::default(),
+            heap: Heap::default(),
+            output: Vec::new(),
+            tohost: None,
+            uninitialized_policy: UninitializedPolicy::Warn,
+            uninitialized_reads: Vec::new(),
+            illegal_policy: IllegalInstructionPolicy::Trap,
+            memory_error_policy: MemoryErrorPolicy::Stop,
+            ecall_policy: EnvironmentCallPolicy::Emulate,
+            skipped_instructions: Vec::new(),
+            extension_uses: Vec::new(),
+            taint: None,
+            guards: Vec::new(),
+            call_stack: CallStack::new(),
+            check_returns: false,
+            coverage: None,
+            diagnostics: None,
+            heatmap: None,
+            flamegraph: None,
+            custom: None,
+            trigger_stop: None,
+            timed: false,
+            last_write: None,
+        }
+    }
+
+    /// Address of the next instruction to execute
+    pub fn pc(&self) -> u32 {
+        self.pc
+    }
+
+    /// Change the address of the next instruction to execute
+    pub fn set_pc(&mut self, pc: u32) {
+        self.pc = pc;
+    }
+
+    pub fn registers(&self) -> &RegisterFile {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut RegisterFile {
+        &mut self.registers
+    }
+
+    /// Register written by the last executed instruction, other than x0,
+    /// and its value
+    pub fn last_write(&self) -> Option<(u8, u32)> {
+        self.last_write
+    }
+
+    pub fn csrs(&self) -> &CsrFile {
+        &self.csrs
+    }
+
+    pub fn csrs_mut(&mut self) -> &mut CsrFile {
+        &mut self.csrs
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// Snapshot of the pc, the registers and the memory
+    pub fn arch_state(&self) -> ArchState {
+        ArchState {
+            pc: self.pc,
+            registers: self.registers.clone(),
+            csrs: self.csrs.clone(),
+            memory: self
+                .memory
+                .pages()
+                .into_iter()
+                .map(|(base, bytes)| (base, bytes.to_vec()))
+                .collect(),
+        }
+    }
+
+    /// Go back to a snapshot of the pc, the registers and the memory. The
+    /// devices and the other settings of the hart are kept.
+    pub fn restore(&mut self, state: &ArchState) {
+        self.pc = state.pc;
+        self.registers = state.registers.clone();
+        self.csrs = state.csrs.clone();
+        self.memory.set_pages(&state.memory);
+    }
+
+    /// Timer and software interrupts
+    pub fn clint(&self) -> &Clint {
+        &self.clint
+    }
+
+    pub fn clint_mut(&mut self) -> &mut Clint {
+        &mut self.clint
+    }
+
+    /// Serial port of the console
+    pub fn uart(&self) -> &Uart {
+        &self.uart
+    }
+
+    pub fn uart_mut(&mut self) -> &mut Uart {
+        &mut self.uart
+    }
+
+    /// Heap handed out through the brk system call
+    /// Background copies of the memory
+    pub fn dma(&self) -> &Dma {
+        &self.dma
+    }
+
+    pub fn dma_mut(&mut self) -> &mut Dma {
+        &mut self.dma
+    }
+
+    /// Select how the bus is shared between the core and the DMA engine
+    pub fn set_arbitration(&mut self, arbitration: Arbitration) {
+        self.arbiter = Arbiter::new(arbitration);
+    }
+
+    /// Arbiter of the bus, with the cycles each master waited
+    pub fn arbiter(&self) -> &Arbiter {
+        &self.arbiter
+    }
+
+    /// Map a device modelled outside the simulator at a base address
+    pub fn add_device(&mut self, base: u32, device: Box<dyn Device>) -> Result<(), DeviceError> {
+        self.devices.add(base, device)
+    }
+
+    pub fn devices(&self) -> &DeviceMap {
+        &self.devices
+    }
+
+    pub fn devices_mut(&mut self) -> &mut DeviceMap {
+        &mut self.devices
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    pub fn set_heap(&mut self, heap: Heap) {
+        self.heap = heap;
+    }
+
+    /// Everything the program wrote to the standard output and error, and
+    /// to the serial port
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Set the address of the tohost word. The program exits by storing
+    /// its exit code shifted left by one, with the lowest bit set.
+    pub fn set_tohost(&mut self, tohost: Option<u32>) {
+        self.tohost = tohost;
+    }
+
+    /// Choose what happens when a load reads memory which was never written.
+    /// Reads are only checked once the memory tracks writes.
+    pub fn set_uninitialized_policy(&mut self, policy: UninitializedPolicy) {
+        self.uninitialized_policy = policy;
+    }
+
+    /// First read of uninitialized memory by every load instruction
+    pub fn uninitialized_reads(&self) -> &[UninitializedRead] {
+        &self.uninitialized_reads
+    }
+
+    /// Choose what happens to instructions which can't be executed. They
+    /// trap by default, and stop the simulation when the program didn't set
+    /// up a trap vector.
+    pub fn set_illegal_instruction_policy(&mut self, policy: IllegalInstructionPolicy) {
+        self.illegal_policy = policy;
+    }
+
+    /// Choose what happens to misaligned and faulting memory accesses,
+    /// including fetches. The simulation stops by default.
+    pub fn set_memory_error_policy(&mut self, policy: MemoryErrorPolicy) {
+        self.memory_error_policy = policy;
+    }
+
+    /// Choose between emulating system calls and trapping to the handler of
+    /// the program on environment calls
+    pub fn set_environment_call_policy(&mut self, policy: EnvironmentCallPolicy) {
+        self.ecall_policy = policy;
+    }
+
+    /// First skipped instruction at every address
+    pub fn skipped_instructions(&self) -> &[IllegalInstruction] {
+        &self.skipped_instructions
+    }
+
+    /// Unsupported extensions used by the program, in the order they were
+    /// first found
+    pub fn extension_uses(&self) -> &[ExtensionUse] {
+        &self.extension_uses
+    }
+
+    /// Enable or disable taint tracking
+    pub fn set_taint_tracker(&mut self, tracker: Option<TaintTracker>) {
+        self.taint = tracker;
+    }
+
+    pub fn taint_tracker(&self) -> Option<&TaintTracker> {
+        self.taint.as_ref()
+    }
+
+    pub fn taint_tracker_mut(&mut self) -> Option<&mut TaintTracker> {
+        self.taint.as_mut()
+    }
+
+    /// Make loads and stores to a region fault
+    pub fn add_guard(&mut self, guard: Guard) {
+        self.guards.push(guard);
+    }
+
+    pub fn guards(&self) -> &[Guard] {
+        &self.guards
+    }
+
+    /// Calls of the program which didn't return yet
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    /// Stop when a return doesn't jump to the address after its call,
+    /// usually because the saved return address was overwritten. Programs
+    /// using longjmp or switching between coroutines break this rule.
+    pub fn set_return_checks(&mut self, enabled: bool) {
+        self.check_returns = enabled;
+    }
+
+    /// Enable or disable the collection of code coverage
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) {
+        self.coverage = coverage;
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Enable or disable the warnings about suspicious behavior
+    pub fn set_diagnostics(&mut self, diagnostics: Option<Diagnostics>) {
+        self.diagnostics = diagnostics;
+    }
+
+    pub fn diagnostics(&self) -> Option<&Diagnostics> {
+        self.diagnostics.as_ref()
+    }
+
+    /// Enable or disable the heatmap of the memory accesses
+    pub fn set_heatmap(&mut self, heatmap: Option<Heatmap>) {
+        self.heatmap = heatmap;
+    }
+
+    pub fn heatmap(&self) -> Option<&Heatmap> {
+        self.heatmap.as_ref()
+    }
+
+    /// Enable or disable the sampling of the call stack for a flamegraph
+    pub fn set_flamegraph(&mut self, flamegraph: Option<Flamegraph>) {
+        self.flamegraph = flamegraph;
+    }
+
+    pub fn flamegraph(&self) -> Option<&Flamegraph> {
+        self.flamegraph.as_ref()
+    }
+
+    /// Count a cycle of the timing model in the cycle counter. Once called,
+    /// the counter only advances through it instead of once per instruction,
+    /// so that it includes the stalls.
+    pub fn count_cycle(&mut self) {
+        self.timed = true;
+        self.csrs.tick();
+        self.clint.count_cycle();
+    }
+
+    /// Decode and execute the instructions which the core decoder reports
+    /// invalid with a plugin
+    pub fn set_custom_extension(&mut self, extension: Option<Box<dyn CustomExtension>>) {
+        self.custom = extension;
+    }
+
+    pub fn custom_extension(&self) -> Option<&dyn CustomExtension> {
+        self.custom.as_ref().map(|extension| extension.as_ref())
+    }
+
+    /// Decode an instruction with the custom extension
+    ///
+    /// # Return Value
+    /// The custom instruction, or None if the core decoder accepts the
+    /// instruction or the extension doesn't
+    pub fn decode_custom(&self, raw: u32) -> Option<CustomInstruction> {
+        match self.custom {
+            Some(ref extension) if is_illegal(&Instruction::new(raw), raw) => extension.decode(raw),
+            _ => None,
+        }
+    }
+
+    /// Execute one instruction and update the architectural state
+    ///
+    /// # Arguments
+    /// * `pc` => address of the instruction
+    /// * `raw` => instruction bits
+    ///
+    /// # Return Value
+    /// The address of the next instruction and the class of the executed
+    /// instruction, or the reason why the instruction can't be executed
+    pub fn execute(&mut self, pc: u32, raw: u32) -> Result<Executed, StopReason> {
+        // The interrupt takes the place of the instruction, which executes
+        // once the handler returns
+        self.last_write = None;
+        self.tick();
+        if !self.uart.tick(self.csrs.cycles()) {
+            return Err(StopReason::ConsoleMismatch { pc });
+        }
+        if let Some(code) = self.csrs.pending_interrupt() {
+            let next_pc = self.csrs.interrupt(pc, code);
+            self.pc = next_pc;
+            return Ok(Executed {
+                next_pc,
+                class: InstrClass::System,
+                mem_addr: None,
+                split_access: false,
+                bus_wait: 0,
+            });
+        }
+
+        // Instructions are fetched as whole words
+        if pc & 3 != 0 {
+            let error = MemError {
+                kind: MemErrorKind::Misaligned,
+                addr: pc,
+                size: 4,
+                access: MemAccess::Fetch,
+            };
+            return self.memory_error(pc, error);
+        }
+
+        if self.trigger_stop.map(|(stop_pc, _)| stop_pc) != Some(pc) {
+            self.trigger_stop = None;
+        }
+        if let Some(executed) = self.check_triggers(pc, MemAccess::Fetch, pc)? {
+            return Ok(executed);
+        }
+
+        let decoded = Instruction::new(raw);
+        let op = decoded.op();
+        if is_illegal(&decoded, raw) {
+            // The custom extension gets the first refusal
+            if let Some(custom) = self.decode_custom(raw) {
+                return Ok(self.execute_custom(pc, &custom));
+            }
+            return self.illegal_instruction(pc, raw);
+        }
+        if let Some(ref mut diagnostics) = self.diagnostics {
+            if decoded.get_rd() == Some(0)
+                && decoded.hint() == Some(Hint::Hint)
+                && op != RV32I::FENCE
+            {
+                diagnostics.report(Diagnostic::WriteToZero { pc, raw });
+            }
+        }
+
+        let (rs1, rs2) = self.registers.read(
+            decoded.get_rs1().unwrap_or(0),
+            decoded.get_rs2().unwrap_or(0),
+        );
+        // Shift immediates are decoded as a shift amount instead of an
+        // immediate
+        let imm = match decoded.get_shamt() {
+            Some(shamt) => i32::from(shamt),
+            None => decoded.get_imm().unwrap_or(0),
+        };
+
+        let class = InstrClass::from(op);
+        let mut next_pc = next_pc(op, class, pc, rs1, rs2, imm);
+        if self.check_returns {
+            if let Some(expected) = self.call_stack.expected_return(&decoded) {
+                if expected != next_pc {
+                    return Err(StopReason::ReturnMismatch {
+                        pc,
+                        expected,
+                        target: next_pc,
+                    });
+                }
+            }
+        }
+        let mut mem_addr = None;
+        let mut split_access = false;
+        let mut bus_wait = 0;
+
+        let rd_value = match class {
+            InstrClass::Load => {
+                let addr = (rs1 as u32).wrapping_add(imm as u32);
+                mem_addr = Some(addr);
+                let load = MemLoadOp::from(op);
+                if let Some(executed) = self.check_triggers(pc, MemAccess::Load, addr)? {
+                    return Ok(executed);
+                }
+                self.check_guards(pc, addr, load.size())?;
+                if let Some(ref mut heatmap) = self.heatmap {
+                    heatmap.load(addr, load.size());
+                }
+                if let Some(ref mut diagnostics) = self.diagnostics {
+                    if self.devices.write_only(addr) {
+                        diagnostics.report(Diagnostic::WriteOnlyRead { pc, addr });
+                    }
+                }
+                match self.load_device(addr, load.size()) {
+                    Some(value) => Some(load.extend(value)),
+                    None => {
+                        if !self.memory.is_initialized(&load, addr) {
+                            self.uninitialized_read(pc, addr)?;
+                        }
+                        let value = match self.memory.load_data(&load, addr) {
+                            Ok(value) => value,
+                            Err(error) => return self.memory_error(pc, error),
+                        };
+                        bus_wait = self.request_bus();
+                        split_access = Memory::crosses_word(addr, load.size());
+                        Some(value)
+                    }
+                }
+            }
+            InstrClass::Store => {
+                let addr = (rs1 as u32).wrapping_add(imm as u32);
+                mem_addr = Some(addr);
+                let store = MemStoreOp::from(op);
+                if let Some(executed) = self.check_triggers(pc, MemAccess::Store, addr)? {
+                    return Ok(executed);
+                }
+                self.check_guards(pc, addr, store.size())?;
+                if let Some(ref mut heatmap) = self.heatmap {
+                    heatmap.store(addr, store.size());
+                }
+                if let Some(ref mut diagnostics) = self.diagnostics {
+                    if diagnostics.is_code(addr, store.size()) {
+                        diagnostics.report(Diagnostic::StoreToCode { pc, addr });
+                    }
+                }
+                if !self.store_device(addr, store.size(), rs2 as u32) {
+                    if let Err(error) = self.memory.write_data(&store, addr, rs2 as u32) {
+                        return self.memory_error(pc, error);
+                    }
+                    bus_wait = self.request_bus();
+                    split_access = Memory::crosses_word(addr, store.size());
+                }
+                if self.tohost == Some(addr) && rs2 & 1 != 0 {
+                    return Err(StopReason::Exit(rs2 >> 1));
+                }
+                None
+            }
+            InstrClass::Branch => None,
+            InstrClass::Jump => Some(pc.wrapping_add(4) as i32),
+            InstrClass::System if op == RV32I::ECALL => {
+                match self.ecall_policy {
+                    EnvironmentCallPolicy::Emulate => self.environment_call(pc)?,
+                    EnvironmentCallPolicy::Trap => {
+                        next_pc = self.csrs.trap(pc, CAUSE_MACHINE_ECALL, 0)
+                    }
+                }
+                None
+            }
+            InstrClass::System if op == RV32I::MRET => {
+                next_pc = self.csrs.mret();
+                None
+            }
+            InstrClass::System if op == RV32I::WFI => {
+                // Nothing else happens until the timer fires
+                let enabled = self.csrs.read(MIE).unwrap_or(0);
+                if enabled & 1 << INTERRUPT_MACHINE_TIMER != 0 {
+                    self.clint.skip_to_timer();
+                }
+                if enabled & 1 << INTERRUPT_MACHINE_EXTERNAL != 0 {
+                    self.dma.finish(&mut self.memory);
+                }
+                None
+            }
+            InstrClass::System if op == RV32I::EBREAK => {
+                return Err(StopReason::Breakpoint { pc });
+            }
+            InstrClass::Alu if op == RV32I::FENCE => None,
+            InstrClass::System => {
+                // This call to unwrap is safe because every instruction of
+                // the class accesses a CSR
+                let csr = decoded.get_csr().unwrap();
+                let (source, source_is_zero) = match decoded.get_rs1() {
+                    Some(index) => (rs1 as u32, index == 0),
+                    None => (imm as u32, imm == 0),
+                };
+                let reads =
+                    op != RV32I::CSRRW && op != RV32I::CSRRWI || decoded.get_rd() != Some(0);
+                match self.access_csr(op, csr, source, reads, source_is_zero) {
+                    Some(value) => Some(value as i32),
+                    None => return self.illegal_instruction(pc, raw),
+                }
+            }
+            _ => Some(match op {
+                RV32I::LUI => imm,
+                RV32I::AUIPC => pc.wrapping_add(imm as u32) as i32,
+                _ => alu(rs1, rs2, imm, &AluOp::from(op)),
+            }),
+        };
+
+        if let (Some(rd), Some(value)) = (decoded.get_rd(), rd_value) {
+            self.write_register(rd, value);
+        }
+        if let Some(ref mut tracker) = self.taint {
+            tracker.execute(pc, &decoded, mem_addr);
+        }
+        // Sampled before a call or a return, which belongs to its caller
+        if let Some(ref mut flamegraph) = self.flamegraph {
+            flamegraph.sample(pc, &self.call_stack);
+        }
+        if class == InstrClass::Jump {
+            self.call_stack.execute(pc, &decoded, next_pc);
+        }
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.execute(pc, class, next_pc);
+        }
+        if let Some(ref mut diagnostics) = self.diagnostics {
+            let taken = next_pc != pc.wrapping_add(4);
+            if (class == InstrClass::Branch || class == InstrClass::Jump)
+                && taken
+                && !self.memory.is_mapped(next_pc)
+            {
+                diagnostics.report(Diagnostic::UnmappedJump {
+                    pc,
+                    target: next_pc,
+                });
+            }
+        }
+        self.retire();
+        self.pc = next_pc;
+
+        Ok(Executed {
+            next_pc,
+            class,
+            mem_addr,
+            split_access,
+            bus_wait,
+        })
+    }
+
+    // Execute an instruction of the custom extension
+    fn execute_custom(&mut self, pc: u32, custom: &CustomInstruction) -> Executed {
+        let (rs1, rs2) = self
+            .registers
+            .read(custom.rs1.unwrap_or(0), custom.rs2.unwrap_or(0));
+        // This call to unwrap is safe because the instruction was decoded by
+        // the extension
+        let value = self
+            .custom
+            .as_mut()
+            .unwrap()
+            .execute(custom, pc, rs1, rs2, &mut self.memory);
+        if let (Some(rd), Some(value)) = (custom.rd, value) {
+            self.write_register(rd, value);
+        }
+
+        let next_pc = pc.wrapping_add(4);
+        if let Some(ref mut flamegraph) = self.flamegraph {
+            flamegraph.sample(pc, &self.call_stack);
+        }
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.execute(pc, custom.class, next_pc);
+        }
+        self.retire();
+        self.pc = next_pc;
+        Executed {
+            next_pc,
+            class: custom.class,
+            mem_addr: None,
+            split_access: false,
+            bus_wait: 0,
+        }
+    }
+
+    // Write a register through the write port of the register file
+    fn write_register(&mut self, rd: u8, value: i32) {
+        self.registers.write(rd, value);
+        if rd != 0 {
+            self.last_write = Some((rd, value as u32));
+        }
+    }
+
+    // Count an executed instruction, and its cycle without a timing model
+    fn retire(&mut self) {
+        self.csrs.retire();
+        if !self.timed {
+            self.csrs.tick();
+        }
+    }
+
+    // Apply the illegal instruction policy to an instruction which can't be
+    // executed
+    fn illegal_instruction(&mut self, pc: u32, raw: u32) -> Result<Executed, StopReason> {
+        if let Some(extension) = Extension::of(raw) {
+            match self
+                .extension_uses
+                .iter_mut()
+                .find(|used| used.extension == extension)
+            {
+                Some(used) => used.count += 1,
+                None => self.extension_uses.push(ExtensionUse {
+                    extension,
+                    first_pc: pc,
+                    count: 1,
+                }),
+            }
+        }
+        let (next_pc, class) = match self.illegal_policy {
+            IllegalInstructionPolicy::Stop => {
+                return Err(StopReason::InvalidInstruction { pc, raw })
+            }
+            IllegalInstructionPolicy::Trap if self.csrs.read(MTVEC) == Some(0) => {
+                return Err(StopReason::InvalidInstruction { pc, raw })
+            }
+            IllegalInstructionPolicy::Trap => {
+                debug!(
+                    "{}, trapping to mtvec",
+                    StopReason::InvalidInstruction { pc, raw }
+                );
+                (
+                    self.csrs.trap(pc, CAUSE_ILLEGAL_INSTRUCTION, raw),
+                    InstrClass::System,
+                )
+            }
+            IllegalInstructionPolicy::Skip => {
+                if !self
+                    .skipped_instructions
+                    .iter()
+                    .any(|skipped| skipped.pc == pc)
+                {
+                    self.skipped_instructions
+                        .push(IllegalInstruction { pc, raw });
+                }
+                // Skipped instructions take the time of a nop
+                (pc.wrapping_add(4), InstrClass::Alu)
+            }
+        };
+        self.pc = next_pc;
+        Ok(Executed {
+            next_pc,
+            class,
+            mem_addr: None,
+            split_access: false,
+            bus_wait: 0,
+        })
+    }
+
+    // Fire the triggers matching an access of the instruction at pc, before
+    // the access happens
+    //
+    // Return Value
+    // The breakpoint exception raised instead of the instruction, or None to
+    // go on with the instruction
+    fn check_triggers(
+        &mut self,
+        pc: u32,
+        access: MemAccess,
+        addr: u32,
+    ) -> Result<Option<Executed>, StopReason> {
+        if self.trigger_stop == Some((pc, access)) {
+            self.trigger_stop = None;
+            return Ok(None);
+        }
+        match self.csrs.triggers_mut().check(access, addr) {
+            None => Ok(None),
+            Some((index, TriggerAction::Debug)) => {
+                self.trigger_stop = Some((pc, access));
+                Err(StopReason::Trigger { pc, index })
+            }
+            Some((index, TriggerAction::Breakpoint)) => {
+                debug!(
+                    "trigger {} fired at pc 0x{:08x}, trapping to mtvec",
+                    index, pc
+                );
+                let next_pc = self.csrs.trap(pc, CAUSE_BREAKPOINT, addr);
+                self.pc = next_pc;
+                Ok(Some(Executed {
+                    next_pc,
+                    class: InstrClass::System,
+                    mem_addr: None,
+                    split_access: false,
+                    bus_wait: 0,
+                }))
+            }
+        }
+    }
+
+    // Apply the memory error policy to a faulting access of the instruction
+    // at pc
+    fn memory_error(&mut self, pc: u32, error: MemError) -> Result<Executed, StopReason> {
+        if self.memory_error_policy == MemoryErrorPolicy::Stop {
+            return Err(StopReason::MemoryFault { pc, error });
+        }
+        debug!(
+            "{}, trapping to mtvec",
+            StopReason::MemoryFault { pc, error }
+        );
+        let cause = match (error.access, error.kind) {
+            (MemAccess::Fetch, MemErrorKind::Misaligned) => CAUSE_MISALIGNED_FETCH,
+            (MemAccess::Fetch, MemErrorKind::AccessFault) => CAUSE_FETCH_ACCESS,
+            (MemAccess::Load, MemErrorKind::Misaligned) => CAUSE_MISALIGNED_LOAD,
+            (MemAccess::Load, MemErrorKind::AccessFault) => CAUSE_LOAD_ACCESS,
+            (MemAccess::Store, MemErrorKind::Misaligned) => CAUSE_MISALIGNED_STORE,
+            (MemAccess::Store, MemErrorKind::AccessFault) => CAUSE_STORE_ACCESS,
+        };
+        let next_pc = self.csrs.trap(pc, cause, error.addr);
+        self.pc = next_pc;
+        Ok(Executed {
+            next_pc,
+            class: InstrClass::System,
+            mem_addr: None,
+            split_access: false,
+            bus_wait: 0,
+        })
+    }
+
+    // Arbitrate a load or a store of the core against the DMA engine
+    //
+    // Return Value
+    // The cycles the access waits for the engine
+    fn request_bus(&mut self) -> u32 {
+        let requests: &[Master] = if self.dma.busy() {
+            &[Master::Core, Master::Dma]
+        } else {
+            &[Master::Core]
+        };
+        self.bus_grant = self.arbiter.grant(requests);
+        match self.bus_grant {
+            Some(Master::Core) => 0,
+            _ => 1,
+        }
+    }
+
+    // Advance the time by one instruction and raise the interrupts of the
+    // CLINT and the DMA engine, which only copies if the last instruction
+    // didn't take the bus from it
+    fn tick(&mut self) {
+        self.clint.tick();
+        if !self.timed {
+            self.clint.count_cycle();
+        }
+        if self.bus_grant.is_none() && self.dma.busy() {
+            self.bus_grant = self.arbiter.grant(&[Master::Dma]);
+        }
+        self.dma
+            .tick(&mut self.memory, self.bus_grant == Some(Master::Core));
+        self.devices.tick();
+        self.bus_grant = None;
+        self.csrs.set_time(self.clint.mtime());
+        self.csrs
+            .set_pending(INTERRUPT_MACHINE_TIMER, self.clint.timer_pending());
+        self.csrs
+            .set_pending(INTERRUPT_MACHINE_SOFTWARE, self.clint.software_pending());
+        self.csrs.set_pending(
+            INTERRUPT_MACHINE_EXTERNAL,
+            self.dma.interrupt_pending() || self.devices.irq(),
+        );
+    }
+
+    // Read a device register, or None if no device answers at the address
+    fn load_device(&mut self, addr: u32, size: u32) -> Option<u32> {
+        let value = if self.clint.contains(addr) {
+            self.clint.read(addr, size)
+        } else if self.uart.contains(addr) {
+            self.uart.read(addr, size)
+        } else if self.dma.contains(addr) {
+            self.dma.read(addr, size)
+        } else {
+            self.devices.read(addr, size)?
+        };
+        trace!(
+            "device read of {} bytes at 0x{:08x}: 0x{:x}",
+            size,
+            addr,
+            value
+        );
+        Some(value)
+    }
+
+    // Write a device register, returning false if no device answers at the
+    // address
+    fn store_device(&mut self, addr: u32, size: u32, value: u32) -> bool {
+        if self.clint.contains(addr) {
+            self.clint.write(addr, size, value);
+        } else if self.uart.contains(addr) {
+            if let Some(byte) = self.uart.write(addr, size, value) {
+                self.output.push(byte);
+            }
+        } else if self.dma.contains(addr) {
+            self.dma.write(addr, size, value);
+        } else if !self.devices.write(addr, size, value) {
+            return false;
+        }
+        trace!(
+            "device write of {} bytes at 0x{:08x}: 0x{:x}",
+            size,
+            addr,
+            value
+        );
+        true
+    }
+
+    // Fault if an access touches a guard region
+    fn check_guards(&self, pc: u32, addr: u32, size: u32) -> Result<(), StopReason> {
+        match self.guards.iter().find(|guard| guard.overlaps(addr, size)) {
+            Some(guard) => Err(StopReason::GuardFault {
+                pc,
+                addr,
+                kind: guard.kind,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // Apply the uninitialized read policy to a load
+    fn uninitialized_read(&mut self, pc: u32, addr: u32) -> Result<(), StopReason> {
+        match self.uninitialized_policy {
+            UninitializedPolicy::Stop => Err(StopReason::UninitializedRead { pc, addr }),
+            UninitializedPolicy::Warn => {
+                if !self.uninitialized_reads.iter().any(|read| read.pc == pc) {
+                    self.uninitialized_reads
+                        .push(UninitializedRead { pc, addr });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Emulate the system call selected by a7, with the arguments and the
+    // return value in a0
+    fn environment_call(&mut self, pc: u32) -> Result<(), StopReason> {
+        let (number, arg) = self.registers.read(17, 10);
+        match number as u32 {
+            SYS_BRK => {
+                let brk = self.heap.set_brk(arg as u32);
+                self.write_register(10, brk as i32);
+                Ok(())
+            }
+            SYS_WRITE => {
+                let (buffer, length) = self.registers.read(11, 12);
+                let written = match arg {
+                    // A length which doesn't fit in a signed size is invalid
+                    1 | 2 if length < 0 => -22,
+                    1 | 2 => {
+                        for offset in 0..length as u32 {
+                            let address = (buffer as u32).wrapping_add(offset);
+                            let byte = self
+                                .memory
+                                .load_data(&MemLoadOp::from(RV32I::LBU), address)
+                                .map_err(|error| StopReason::MemoryFault { pc, error })?;
+                            self.output.push(byte as u8);
+                        }
+                        length
+                    }
+                    // Bad file descriptor
+                    _ => -9,
+                };
+                self.write_register(10, written);
+                Ok(())
+            }
+            SYS_GETTIMEOFDAY => {
+                // struct timeval of newlib, with a 64 bit tv_sec and a 32
+                // bit tv_usec. The time zone is ignored.
+                if arg != 0 {
+                    let mtime = self.clint.mtime();
+                    let timebase = u64::from(TIMEBASE_FREQUENCY);
+                    let seconds = mtime / timebase;
+                    let microseconds = mtime % timebase * 1_000_000 / timebase;
+                    let store = MemStoreOp::from(RV32I::SW);
+                    let timeval = [seconds as u32, (seconds >> 32) as u32, microseconds as u32];
+                    for (index, word) in timeval.iter().enumerate() {
+                        let address = (arg as u32).wrapping_add(4 * index as u32);
+                        self.memory
+                            .write_data(&store, address, *word)
+                            .map_err(|error| StopReason::MemoryFault { pc, error })?;
+                    }
+                }
+                self.write_register(10, 0);
+                Ok(())
+            }
+            SYS_EXIT => Err(StopReason::Exit(arg)),
+            number => Err(StopReason::UnsupportedSyscall { pc, number }),
+        }
+    }
+
+    // Perform the read, modify and write of a CSR instruction. Registers are
+    // only read and written when the instruction requires it, so it's legal
+    // to write a register which can't be read and vice versa.
+    //
+    // # Return Value
+    // The previous value of the register, or None if the access is illegal
+    fn access_csr(
+        &mut self,
+        op: RV32I,
+        csr: u16,
+        source: u32,
+        reads: bool,
+        source_is_zero: bool,
+    ) -> Option<u32> {
+        let old = if reads { self.csrs.read(csr)? } else { 0 };
+        let new = match op {
+            RV32I::CSRRW | RV32I::CSRRWI => Some(source),
+            _ if source_is_zero => None,
+            RV32I::CSRRS | RV32I::CSRRSI => Some(old | source),
+            _ => Some(old & !source),
+        };
+
+        match new {
+            Some(new) if !self.csrs.write(csr, new) => None,
+            _ => Some(old),
+        }
+    }
+
+    /// Resolve a branch or a jump with the current register values, without
+    /// executing it
+    ///
+    /// # Arguments
+    /// * `pc` => address of the instruction
+    /// * `raw` => instruction bits
+    ///
+    /// # Return Value
+    /// The address of the next instruction, or None if it isn't a valid
+    /// branch or jump
+    pub fn resolve_control(&self, pc: u32, raw: u32) -> Option<u32> {
+        let decoded = Instruction::new(raw);
+        let op = decoded.op();
+        let class = InstrClass::from(op);
+        if !decoded.is_valid() || (class != InstrClass::Branch && class != InstrClass::Jump) {
+            return None;
+        }
+
+        let (rs1, rs2) = self.registers.read(
+            decoded.get_rs1().unwrap_or(0),
+            decoded.get_rs2().unwrap_or(0),
+        );
+        let imm = decoded.get_imm().unwrap_or(0);
+        Some(next_pc(op, class, pc, rs1, rs2, imm))
+    }
+}
+
+// Check if an instruction can't be executed by the core. Encodings of the
+// unsupported extensions are checked too, in case they decode as another
+// instruction.
+fn is_illegal(decoded: &Instruction, raw: u32) -> bool {
+    !decoded.is_valid() || decoded.op() == RV32I::Invalid || Extension::of(raw).is_some()
+}
+
+// Address of the instruction following the one at `pc`
+fn next_pc(op: RV32I, class: InstrClass, pc: u32, rs1: i32, rs2: i32, imm: i32) -> u32 {
+    match class {
+        InstrClass::Branch if branch_taken(op, rs1, rs2) => pc.wrapping_add(imm as u32),
+        InstrClass::Jump => match op {
+            RV32I::JAL => pc.wrapping_add(imm as u32),
+            _ => (rs1 as u32).wrapping_add(imm as u32) & !1,
+        },
+        _ => pc.wrapping_add(4),
+    }
+}
+
+// Evaluate a branch condition with the ALU. BEQ and BNE subtract the operands
+// while the remaining branches perform a (unsigned) set less than.
+fn branch_taken(op: RV32I, rs1: i32, rs2: i32) -> bool {
+    let result = alu(rs1, rs2, 0, &AluOp::from(op));
+
+    match op {
+        RV32I::BEQ | RV32I::BGE | RV32I::BGEU => result == 0,
+        _ => result != 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clint::CLINT_BASE;
+    use csr::{CAUSE_INTERRUPT, MCAUSE, MEPC, MIP, MSTATUS, MTVAL, MTVEC};
+    use elf::Symbol;
+    use stimulus::ScriptConsole;
+    use trigger::{TDATA1, TDATA2, TSELECT};
+
+    // Create a hart with a program stored at address 0
+    fn hart_with_program(program: &[u32]) -> Hart {
+        let mut hart = Hart::new();
+        for (index, instruction) in program.iter().enumerate() {
+            hart.memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    (index as u32) << 2,
+                    *instruction,
+                )
+                .unwrap();
+        }
+        hart
+    }
+
+    // Execute instructions until the hart reaches `end`
+    fn run_until(hart: &mut Hart, end: u32) {
+        while hart.pc() != end {
+            let pc = hart.pc();
+            let raw = hart.memory().read_pc(pc);
+            hart.execute(pc, raw).unwrap();
+        }
+    }
+
+    ////////////////////////////////////////
+    // Arithmetic
+    ////////////////////////////////////////
+    #[test]
+    fn test_arithmetic() {
+        let mut hart = hart_with_program(&[
+            0x0050_0513, // li a0, 5
+            0x0070_0593, // li a1, 7
+            0x00b5_0633, // add a2, a0, a1
+            0x40b5_0e33, // sub t3, a0, a1
+            0x4015_5393, // srai t2, a0, 1
+        ]);
+        run_until(&mut hart, 20);
+
+        assert_eq!((5, 7), hart.registers().read(10, 11));
+        assert_eq!((12, -2), hart.registers().read(12, 28));
+        assert_eq!((2, 0), hart.registers().read(7, 0));
+    }
+
+    #[test]
+    fn test_add_wraps() {
+        let mut hart = hart_with_program(&[
+            0x8000_02b7, // lui t0, 0x80000
+            0x0052_8333, // add t1, t0, t0
+        ]);
+        run_until(&mut hart, 8);
+
+        assert_eq!((0, 0), hart.registers().read(6, 0));
+    }
+
+    #[test]
+    fn test_upper_immediates() {
+        let mut hart = Hart::new();
+        hart.memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SW), 0x40, 0x1234_52b7)
+            .unwrap();
+        hart.memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SW), 0x44, 0x0000_1317)
+            .unwrap();
+        hart.set_pc(0x40);
+        run_until(&mut hart, 0x48);
+
+        // lui t0, 0x12345
+        assert_eq!(0x1234_5000, hart.registers().read(5, 0).0);
+        // auipc t1, 1
+        assert_eq!(0x1044, hart.registers().read(6, 0).0);
+    }
+
+    #[test]
+    fn test_arch_state() {
+        let mut hart = hart_with_program(&[
+            0x0050_0513, // li a0, 5
+            0x04a0_2023, // sw a0, 64(zero)
+            0x0070_0513, // li a0, 7
+            0x04a0_2023, // sw a0, 64(zero)
+        ]);
+        run_until(&mut hart, 8);
+        let state = hart.arch_state();
+        assert_eq!(8, state.pc);
+        assert_eq!(
+            vec![0],
+            state
+                .memory
+                .iter()
+                .map(|(base, _)| *base)
+                .collect::<Vec<_>>()
+        );
+
+        run_until(&mut hart, 16);
+        assert_eq!(7, hart.memory().read_pc(64));
+        hart.restore(&state);
+        assert_eq!(state, hart.arch_state());
+        assert_eq!((5, 0), hart.registers().read(10, 0));
+        assert_eq!(5, hart.memory().read_pc(64));
+    }
+
+    ////////////////////////////////////////
+    // Memory
+    ////////////////////////////////////////
+    #[test]
+    fn test_load_store() {
+        let mut hart = hart_with_program(&[
+            0x0050_0513, // li a0, 5
+            0x04a0_2023, // sw a0, 64(zero)
+            0x0400_2683, // lw a3, 64(zero)
+        ]);
+        run_until(&mut hart, 12);
+
+        assert_eq!((5, 0), hart.registers().read(13, 0));
+        assert_eq!(5, hart.memory().read_pc(64));
+
+        // lw a3, 64(zero)
+        let executed = hart.execute(8, 0x0400_2683).unwrap();
+        assert_eq!(Some(64), executed.mem_addr);
+        // li a0, 5
+        assert_eq!(None, hart.execute(0, 0x0050_0513).unwrap().mem_addr);
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let mut hart = hart_with_program(&[
+            0x0050_0513, // li a0, 5
+            0x04a0_2023, // sw a0, 64(zero)
+            0x0400_2683, // lw a3, 64(zero)
+        ]);
+        hart.set_heatmap(Some(Heatmap::new(0x40)));
+        run_until(&mut hart, 12);
+
+        // Fetches don't count
+        let heatmap = hart.heatmap().unwrap();
+        assert_eq!(vec![(0x40, 1, 1)], heatmap.regions());
+    }
+
+    #[test]
+    fn test_memory_faults() {
+        let mut hart = hart_with_program(&[]);
+        // lw a3, 66(zero)
+        let stopped = hart.execute(0, 0x0420_2683).unwrap_err();
+        assert_eq!(
+            StopReason::MemoryFault {
+                pc: 0,
+                error: MemError {
+                    kind: MemErrorKind::Misaligned,
+                    addr: 66,
+                    size: 4,
+                    access: MemAccess::Load,
+                },
+            },
+            stopped
+        );
+        assert_eq!(
+            "misaligned load at 0x00000042 (4 bytes) by pc 0x00000000",
+            stopped.to_string()
+        );
+        // sh a0, 67(zero)
+        assert_eq!(
+            Err(StopReason::MemoryFault {
+                pc: 4,
+                error: MemError {
+                    kind: MemErrorKind::Misaligned,
+                    addr: 67,
+                    size: 2,
+                    access: MemAccess::Store,
+                },
+            }),
+            hart.execute(4, 0x04a0_11a3)
+        );
+        // Jumps to the middle of a word stop before the fetched instruction
+        assert_eq!(
+            Err(StopReason::MemoryFault {
+                pc: 6,
+                error: MemError {
+                    kind: MemErrorKind::Misaligned,
+                    addr: 6,
+                    size: 4,
+                    access: MemAccess::Fetch,
+                },
+            }),
+            hart.execute(6, 0x0050_0513)
+        );
+    }
+
+    #[test]
+    fn test_triggers() {
+        let mut hart = hart_with_program(&[
+            0x0050_0513, // li a0, 5
+            0x04a0_2023, // sw a0, 64(zero)
+            0x0000_0013, // nop
+        ]);
+        assert!(hart.csrs_mut().write(MTVEC, 0x200));
+        // Enter debug mode before executing 0x4, and raise a breakpoint on
+        // stores to 0x40
+        assert!(hart.csrs_mut().write(TDATA1, 1 << 12 | 1 << 6 | 1 << 2));
+        assert!(hart.csrs_mut().write(TDATA2, 4));
+        assert!(hart.csrs_mut().write(TSELECT, 1));
+        assert!(hart.csrs_mut().write(TDATA1, 1 << 6 | 1 << 1));
+        assert!(hart.csrs_mut().write(TDATA2, 0x40));
+
+        hart.execute(0, 0x0050_0513).unwrap();
+        let stopped = hart.execute(4, 0x04a0_2023).unwrap_err();
+        assert_eq!(StopReason::Trigger { pc: 4, index: 0 }, stopped);
+        assert_eq!("trigger 0 fired at pc 0x00000004", stopped.to_string());
+
+        // Resuming executes the instruction up to the store
+        let executed = hart.execute(4, 0x04a0_2023).unwrap();
+        assert_eq!(0x200, executed.next_pc);
+        assert_eq!(Some(CAUSE_BREAKPOINT), hart.csrs().read(MCAUSE));
+        assert_eq!(Some(0x40), hart.csrs().read(MTVAL));
+        assert_eq!(0, hart.memory().read_pc(0x40));
+    }
+
+    #[test]
+    fn test_console_script() {
+        // lui a0, 0x10000; lbu a1, 0(a0); addi a1, a1, 1; sb a1, 0(a0)
+        let mut hart = hart_with_program(&[0x1000_0537, 0x0005_4583, 0x0015_8593, 0x00b5_0023]);
+        let script = ScriptConsole::parse("send x\nexpect y within 10").unwrap();
+        hart.uart_mut().set_console(Some(Box::new(script)));
+        run_until(&mut hart, 16);
+        assert_eq!(b"y", hart.output());
+        assert!(hart.uart().console().unwrap().finished());
+
+        let mut hart = hart_with_program(&[0x13; 8]);
+        let script = ScriptConsole::parse("expect z within 3").unwrap();
+        hart.uart_mut().set_console(Some(Box::new(script)));
+        let stopped = (0..8)
+            .map(|index| hart.execute(index << 2, 0x13))
+            .find_map(Result::err);
+        assert_eq!(Some(StopReason::ConsoleMismatch { pc: 16 }), stopped);
+        assert!(!hart.uart().console().unwrap().finished());
+    }
+
+    #[test]
+    fn test_memory_error_traps() {
+        let mut hart = hart_with_program(&[]);
+        hart.set_memory_error_policy(MemoryErrorPolicy::Trap);
+        assert!(hart.csrs_mut().write(MTVEC, 0x200));
+
+        // lw a3, 66(zero)
+        let executed = hart.execute(0, 0x0420_2683).unwrap();
+        assert_eq!(
+            (0x200, InstrClass::System),
+            (executed.next_pc, executed.class)
+        );
+        assert_eq!(Some(CAUSE_MISALIGNED_LOAD), hart.csrs().read(MCAUSE));
+        assert_eq!(Some(66), hart.csrs().read(MTVAL));
+        assert_eq!(Some(0), hart.csrs().read(MEPC));
+        assert_eq!((0, 0), hart.registers().read(13, 0));
+        // sh a0, 67(zero)
+        hart.execute(4, 0x04a0_11a3).unwrap();
+        assert_eq!(Some(CAUSE_MISALIGNED_STORE), hart.csrs().read(MCAUSE));
+        assert_eq!(Some(67), hart.csrs().read(MTVAL));
+        // A jump to the middle of a word
+        assert_eq!(0x200, hart.execute(6, 0x0050_0513).unwrap().next_pc);
+        assert_eq!(Some(CAUSE_MISALIGNED_FETCH), hart.csrs().read(MCAUSE));
+        assert_eq!(Some(6), hart.csrs().read(MTVAL));
+    }
+
+    ////////////////////////////////////////
+    // Control
+    ////////////////////////////////////////
+    #[test]
+    fn test_branches() {
+        // beq a3, a2, 8
+        let beq = 0x00c6_8463;
+        // bne a0, a0, 8
+        let bne = 0x00a5_1463;
+
+        let mut hart = Hart::new();
+        let executed = hart.execute(0x100, beq).unwrap();
+        assert_eq!(0x108, executed.next_pc);
+        assert_eq!(InstrClass::Branch, executed.class);
+
+        let executed = hart.execute(0x108, bne).unwrap();
+        assert_eq!(0x10c, executed.next_pc);
+    }
+
+    #[test]
+    fn test_signed_branches() {
+        let mut hart = Hart::new();
+        hart.registers_mut().write(10, -1);
+        hart.registers_mut().write(11, 1);
+
+        // blt a0, a1, 8
+        assert_eq!(8, hart.execute(0, 0x00b5_4463).unwrap().next_pc);
+        // bge a0, a1, 8
+        assert_eq!(4, hart.execute(0, 0x00b5_5463).unwrap().next_pc);
+        // bltu a0, a1, 8
+        assert_eq!(4, hart.execute(0, 0x00b5_6463).unwrap().next_pc);
+        // bgeu a0, a1, 8
+        assert_eq!(8, hart.execute(0, 0x00b5_7463).unwrap().next_pc);
+    }
+
+    #[test]
+    fn test_coverage() {
+        let mut hart = hart_with_program(&[
+            0x0020_0513, // li a0, 2
+            0xfff5_0513, // loop: addi a0, a0, -1
+            0xfe05_1ee3, // bnez a0, loop
+        ]);
+        hart.set_coverage(Some(Coverage::new()));
+        run_until(&mut hart, 12);
+
+        let coverage = hart.coverage().unwrap();
+        assert_eq!(vec![0, 4, 8], coverage.addresses());
+        assert_eq!(2, coverage.count(4));
+        assert_eq!((1, 1), coverage.branch(8));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut hart = hart_with_program(&[
+            0x00b5_0033, // add zero, a0, a1
+            0x0000_0013, // nop
+            0x0000_2223, // sw zero, 4(zero)
+            0x0000_2223, // sw zero, 4(zero)
+            0x0000_0067, // jr zero
+        ]);
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.add_code_region(0, 20);
+        hart.set_diagnostics(Some(diagnostics));
+        run_until(&mut hart, 16);
+        // The jump to 0 lands on the program
+        hart.execute(0x10, 0x0000_0067).unwrap();
+        // jalr zero, -4(zero), past the end of the memory
+        hart.execute(0x10, 0xffc0_0067).unwrap();
+        assert_eq!(
+            &[
+                Diagnostic::WriteToZero {
+                    pc: 0,
+                    raw: 0x00b5_0033
+                },
+                Diagnostic::StoreToCode { pc: 8, addr: 4 },
+                Diagnostic::StoreToCode { pc: 12, addr: 4 },
+                Diagnostic::UnmappedJump {
+                    pc: 0x10,
+                    target: 0xffff_fffc
+                },
+            ],
+            hart.diagnostics().unwrap().reported()
+        );
+    }
+
+    #[test]
+    fn test_jumps() {
+        let mut hart = Hart::new();
+
+        // jal ra, 8
+        let executed = hart.execute(0x20, 0x0080_00ef).unwrap();
+        assert_eq!(0x28, executed.next_pc);
+        assert_eq!(InstrClass::Jump, executed.class);
+        assert_eq!((0x24, 0), hart.registers().read(1, 0));
+        assert_eq!(1, hart.call_stack().depth());
+
+        // ret
+        let executed = hart.execute(0x28, 0x0000_8067).unwrap();
+        assert_eq!(0x24, executed.next_pc);
+        assert_eq!(0x24, hart.pc());
+        assert_eq!(0, hart.call_stack().depth());
+    }
+
+    #[test]
+    fn test_flamegraph() {
+        let mut hart = Hart::new();
+        hart.set_flamegraph(Some(Flamegraph::new(1)));
+        // jal ra, 8, then li a0, 1 and ret in the callee
+        hart.execute(0x20, 0x0080_00ef).unwrap();
+        hart.execute(0x28, 0x0010_0513).unwrap();
+        hart.execute(0x2c, 0x0000_8067).unwrap();
+        let functions = vec![
+            Symbol {
+                name: "main".to_string(),
+                value: 0x20,
+                size: 8,
+            },
+            Symbol {
+                name: "f".to_string(),
+                value: 0x28,
+                size: 8,
+            },
+        ];
+        assert_eq!(
+            vec![("main;f".to_string(), 2), ("main".to_string(), 1)],
+            hart.flamegraph().unwrap().folded(&functions)
+        );
+    }
+
+    #[test]
+    fn test_return_checks() {
+        let mut hart = Hart::new();
+        hart.set_return_checks(true);
+
+        // jal ra, 8 and ret
+        hart.execute(0x20, 0x0080_00ef).unwrap();
+        hart.execute(0x28, 0x0000_8067).unwrap();
+        // jal ra, 8, then overwrite ra with li ra, 0x40
+        hart.execute(0x20, 0x0080_00ef).unwrap();
+        hart.execute(0x28, 0x0400_0093).unwrap();
+        assert_eq!(
+            Err(StopReason::ReturnMismatch {
+                pc: 0x2c,
+                expected: 0x24,
+                target: 0x40
+            }),
+            hart.execute(0x2c, 0x0000_8067)
+        );
+        // The frame of the returning function is kept for the backtrace
+        assert_eq!(1, hart.call_stack().depth());
+    }
+
+    #[test]
+    fn test_resolve_control() {
+        let mut hart = Hart::new();
+        hart.registers_mut().write(1, 0x40);
+
+        // beq zero, zero, 8
+        assert_eq!(Some(0x108), hart.resolve_control(0x100, 0x0000_0463));
+        // bne zero, zero, 8
+        assert_eq!(Some(0x104), hart.resolve_control(0x100, 0x0000_1463));
+        // ret
+        assert_eq!(Some(0x40), hart.resolve_control(0x100, 0x0000_8067));
+        // li a0, 5
+        assert_eq!(None, hart.resolve_control(0x100, 0x0050_0513));
+        assert_eq!(None, hart.resolve_control(0x100, 0));
+        // Nothing was executed
+        assert_eq!(0, hart.pc());
+    }
+
+    ////////////////////////////////////////
+    // Control and status registers
+    ////////////////////////////////////////
+    #[test]
+    fn test_csr_instructions() {
+        let mut hart = hart_with_program(&[
+            0x1000_0293, // li t0, 0x100
+            0x3052_9073, // csrw mtvec, t0
+            0x3050_2573, // csrr a0, mtvec
+            0x3004_6673, // csrrsi a2, mstatus, 8
+            0x3000_36f3, // csrrc a3, mstatus, zero
+            0x3042_d773, // csrrwi a4, mie, 5
+        ]);
+        run_until(&mut hart, 24);
+
+        assert_eq!((0x100, 0), hart.registers().read(10, 12));
+        assert_eq!((8, 0), hart.registers().read(13, 14));
+        assert_eq!(Some(8), hart.csrs().read(MSTATUS));
+        assert_eq!(
+            InstrClass::System,
+            hart.execute(0, 0x3052_9073).unwrap().class
+        );
+    }
+
+    #[test]
+    fn test_invalid_csr_accesses() {
+        let mut hart = Hart::new();
+
+        // csrw mhartid, t0
+        assert!(hart.execute(0, 0xf142_9073).is_err());
+        // csrr a1, 0x7c0
+        assert!(hart.execute(0, 0x7c00_25f3).is_err());
+        assert_eq!(0, hart.pc());
+        // csrr a1, mhartid
+        assert!(hart.execute(0, 0xf140_25f3).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_instruction() {
+        let mut hart = Hart::new();
+
+        assert_eq!(
+            Err(StopReason::InvalidInstruction { pc: 8, raw: 0 }),
+            hart.execute(8, 0)
+        );
+        // Valid opcode with an invalid function
+        assert_eq!(
+            Err(StopReason::InvalidInstruction {
+                pc: 8,
+                raw: 0x0000_3003
+            }),
+            hart.execute(8, 0x0000_3003)
+        );
+    }
+
+    #[test]
+    fn test_illegal_instruction_policies() {
+        // Without a trap vector the default trap stops the simulation
+        let mut hart = Hart::new();
+        assert_eq!(
+            Err(StopReason::InvalidInstruction {
+                pc: 8,
+                raw: 0xffff_ffff
+            }),
+            hart.execute(8, 0xffff_ffff)
+        );
+
+        hart.csrs_mut().write(MTVEC, 0x200);
+        let executed = hart.execute(8, 0xffff_ffff).unwrap();
+        assert_eq!(0x200, executed.next_pc);
+        assert_eq!(0x200, hart.pc());
+        assert_eq!(Some(8), hart.csrs().read(MEPC));
+        assert_eq!(Some(CAUSE_ILLEGAL_INSTRUCTION), hart.csrs().read(MCAUSE));
+        assert_eq!(Some(0xffff_ffff), hart.csrs().read(MTVAL));
+
+        hart.set_illegal_instruction_policy(IllegalInstructionPolicy::Skip);
+        // csrw mhartid, t0
+        assert_eq!(0x10, hart.execute(0xc, 0xf142_9073).unwrap().next_pc);
+        assert_eq!(0x14, hart.execute(0x10, 0).unwrap().next_pc);
+        assert_eq!(0x14, hart.execute(0x10, 0).unwrap().next_pc);
+        assert_eq!(
+            &[
+                IllegalInstruction {
+                    pc: 0xc,
+                    raw: 0xf142_9073
+                },
+                IllegalInstruction { pc: 0x10, raw: 0 }
+            ],
+            hart.skipped_instructions()
+        );
+    }
+
+    #[test]
+    fn test_unsupported_extensions() {
+        let mut hart = Hart::new();
+        // mul a0, a0, a1
+        assert_eq!(
+            Err(StopReason::InvalidInstruction {
+                pc: 0x10,
+                raw: 0x02b5_0533
+            }),
+            hart.execute(0x10, 0x02b5_0533)
+        );
+        assert_eq!(
+            "invalid instruction 0x02b50533 at pc 0x00000010 of the unsupported M extension",
+            hart.execute(0x10, 0x02b5_0533).unwrap_err().to_string()
+        );
+
+        hart.set_illegal_instruction_policy(IllegalInstructionPolicy::Skip);
+        // flw fa0, 0(a0)
+        hart.execute(0x20, 0x0005_2507).unwrap();
+        hart.execute(0x24, 0x0005_2507).unwrap();
+        assert_eq!(
+            &[
+                ExtensionUse {
+                    extension: Extension::M,
+                    first_pc: 0x10,
+                    count: 2
+                },
+                ExtensionUse {
+                    extension: Extension::F,
+                    first_pc: 0x20,
+                    count: 2
+                }
+            ],
+            hart.extension_uses()
+        );
+    }
+
+    // Counts its instructions, which add the count to rs1, in the custom-0
+    // space and in the space of the multiplications
+    #[derive(Clone)]
+    struct Counter(i32);
+
+    impl CustomExtension for Counter {
+        fn decode(&self, raw: u32) -> Option<CustomInstruction> {
+            if raw & 0x7f != 0x0b && raw & 0xfe00_007f != 0x0200_0033 {
+                return None;
+            }
+            let mut instr = CustomInstruction::new(0, "count", InstrClass::Mul);
+            instr.rd = Some((raw >> 7 & 0x1f) as u8);
+            instr.rs1 = Some((raw >> 15 & 0x1f) as u8);
+            Some(instr)
+        }
+
+        fn execute(
+            &mut self,
+            _: &CustomInstruction,
+            _: u32,
+            rs1: i32,
+            _: i32,
+            _: &mut Memory,
+        ) -> Option<i32> {
+            self.0 += 1;
+            Some(rs1 + self.0)
+        }
+
+        fn box_clone(&self) -> Box<dyn CustomExtension> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn test_custom_extension() {
+        let mut hart = hart_with_program(&[
+            0x0645_050b, // count a0, a0
+            0x02b5_0533, // mul a0, a0, a1
+            0x0000_000b, // count zero, zero
+            0x0000_0000, // invalid
+        ]);
+        hart.registers_mut().write(10, 10);
+        assert_eq!(None, hart.decode_custom(0x0050_0513));
+        hart.set_custom_extension(Some(Box::new(Counter(0))));
+        // Valid instructions never reach the extension
+        assert_eq!(None, hart.decode_custom(0x0050_0513));
+        assert_eq!(
+            "count x10, x10",
+            hart.decode_custom(0x0645_050b).unwrap().to_string()
+        );
+
+        let executed = hart.execute(0, 0x0645_050b).unwrap();
+        assert_eq!((4, InstrClass::Mul), (executed.next_pc, executed.class));
+        assert_eq!((11, 0), hart.registers().read(10, 0));
+        let mut snapshot = hart.clone();
+        run_until(&mut hart, 12);
+        assert_eq!((13, 0), hart.registers().read(10, 0));
+        assert!(hart.extension_uses().is_empty());
+        assert_eq!(
+            StopReason::InvalidInstruction { pc: 12, raw: 0 },
+            hart.execute(12, 0).unwrap_err()
+        );
+
+        // The state of the extension is copied with the hart
+        run_until(&mut snapshot, 8);
+        assert_eq!((13, 0), snapshot.registers().read(10, 0));
+    }
+
+    #[test]
+    fn test_traps() {
+        let mut hart = Hart::new();
+        hart.csrs_mut().write(MTVEC, 0x301);
+        hart.set_environment_call_policy(EnvironmentCallPolicy::Trap);
+        // ecall
+        assert_eq!(0x300, hart.execute(0x10, 0x0000_0073).unwrap().next_pc);
+        assert_eq!(Some(CAUSE_MACHINE_ECALL), hart.csrs().read(MCAUSE));
+        // mret
+        assert_eq!(0x10, hart.execute(0x300, 0x3020_0073).unwrap().next_pc);
+
+        // The wait for interrupt skips to the timer interrupt
+        hart.clint_mut().write(CLINT_BASE + 0x4004, 4, 0);
+        hart.clint_mut().write(CLINT_BASE + 0x4000, 4, 100);
+        hart.csrs_mut().write(MIE, 1 << INTERRUPT_MACHINE_TIMER);
+        hart.csrs_mut().write(MSTATUS, 0x8);
+        assert_eq!(0x18, hart.execute(0x14, 0x1050_0073).unwrap().next_pc);
+        assert_eq!(100, hart.clint().mtime());
+        // The nop at 0x18 is interrupted, jumping to the vectored handler
+        let executed = hart.execute(0x18, 0x0000_0013).unwrap();
+        assert_eq!(0x31c, executed.next_pc);
+        assert_eq!(
+            Some(CAUSE_INTERRUPT | INTERRUPT_MACHINE_TIMER),
+            hart.csrs().read(MCAUSE)
+        );
+        assert_eq!(Some(0x18), hart.csrs().read(MEPC));
+    }
+
+    ////////////////////////////////////////
+    // Devices
+    ////////////////////////////////////////
+    #[test]
+    fn test_devices() {
+        let mut hart = hart_with_program(&[
+            0x1000_0e37, // lui t3, 0x10000
+            0x0680_0e93, // li t4, 'h'
+            0x01de_0023, // sb t4, 0(t3)
+            0x005e_4503, // lbu a0, 5(t3)
+            0x0200_42b7, // lui t0, 0x2004
+            0xfff0_0313, // li t1, -1
+            0x0062_a023, // sw t1, 0(t0)
+            0x0002_9583, // lh a1, 0(t0)
+            0x0200_c3b7, // lui t2, 0x200c
+            0xff83_a603, // lw a2, -8(t2)
+            0xc010_26f3, // rdtime a3
+            0xc020_2773, // rdinstret a4
+        ]);
+        run_until(&mut hart, 0x30);
+
+        assert_eq!(b"h", hart.output());
+        // The line status of the serial port and mtimecmp
+        assert_eq!((0x60, -1), hart.registers().read(10, 11));
+        // mtime counts every instruction, including the one reading it
+        assert_eq!((10, 11), hart.registers().read(12, 13));
+        assert_eq!((11, 0), hart.registers().read(14, 0));
+        // The memory under the serial port wasn't written
+        assert_eq!(0x1000_0e37, hart.memory().read_pc(0));
+    }
+
+    ////////////////////////////////////////
+    // Environment
+    ////////////////////////////////////////
+    #[test]
+    fn test_dma() {
+        let mut hart = hart_with_program(&[
+            0x1000_12b7, // lui t0, 0x10001
+            0x1000_0313, // li t1, 0x100
+            0x0062_a023, // sw t1, 0(t0)
+            0x2000_0313, // li t1, 0x200
+            0x0062_a223, // sw t1, 4(t0)
+            0x0080_0313, // li t1, 8
+            0x0062_a423, // sw t1, 8(t0)
+            0x0030_0313, // li t1, 3
+            0x0062_a623, // sw t1, 12(t0)
+            0x0000_2583, // lw a1, 0(zero)
+            0x0102_a503, // lw a0, 16(t0)
+            0x0102_a603, // lw a2, 16(t0)
+        ]);
+        for addr in &[0x100, 0x104] {
+            hart.memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), *addr, *addr)
+                .unwrap();
+        }
+        run_until(&mut hart, 0x30);
+
+        // The load from the memory stalls the copy of the second word
+        assert_eq!((1, 0x1000_12b7), hart.registers().read(10, 11));
+        assert_eq!((2, 0), hart.registers().read(12, 0));
+        assert_eq!(
+            Ok(0x104),
+            hart.memory().load_data(&MemLoadOp::from(RV32I::LW), 0x204)
+        );
+        assert!(!hart.dma().busy());
+        assert_eq!(Some(1 << INTERRUPT_MACHINE_EXTERNAL), hart.csrs().read(MIP));
+    }
+
+    #[test]
+    fn test_bus_arbitration() {
+        // Copy three words with the DMA engine while loading from the memory
+        let run = |arbitration| {
+            let mut hart = hart_with_program(&[
+                0x1000_12b7, // lui t0, 0x10001
+                0x1000_0313, // li t1, 0x100
+                0x0062_a023, // sw t1, 0(t0)
+                0x2000_0313, // li t1, 0x200
+                0x0062_a223, // sw t1, 4(t0)
+                0x00c0_0313, // li t1, 12
+                0x0062_a423, // sw t1, 8(t0)
+                0x0010_0313, // li t1, 1
+                0x0062_a623, // sw t1, 12(t0)
+                0x0000_2583, // lw a1, 0(zero)
+                0x0000_2583, // lw a1, 0(zero)
+                0x0000_2583, // lw a1, 0(zero)
+            ]);
+            hart.set_arbitration(arbitration);
+            run_until(&mut hart, 0x24);
+            let waits: Vec<_> = (0..3)
+                .map(|_| {
+                    let pc = hart.pc();
+                    let raw = hart.memory().read_pc(pc);
+                    hart.execute(pc, raw).unwrap().bus_wait
+                })
+                .collect();
+            (waits, hart)
+        };
+
+        // The core always wins, and the engine only copied before the loads
+        let (waits, hart) = run(Arbitration::FixedPriority);
+        assert_eq!(vec![0, 0, 0], waits);
+        assert_eq!(4, hart.dma().bytes_copied());
+        assert_eq!(0, hart.arbiter().waits(Master::Core));
+        assert_eq!(3, hart.arbiter().waits(Master::Dma));
+
+        // The second load waits for the engine to copy a word
+        let (waits, hart) = run(Arbitration::RoundRobin);
+        assert_eq!(vec![0, 1, 0], waits);
+        assert_eq!(8, hart.dma().bytes_copied());
+        assert_eq!(1, hart.arbiter().waits(Master::Core));
+        assert_eq!(2, hart.arbiter().waits(Master::Dma));
+    }
+
+    #[test]
+    fn test_brk() {
+        let mut hart = hart_with_program(&[
+            0x0d60_0893, // li a7, 214
+            0x0000_0513, // li a0, 0
+            0x0000_0073, // ecall
+            0x0005_0413, // mv s0, a0
+            0x0405_0513, // addi a0, a0, 64
+            0x0000_0073, // ecall
+        ]);
+        hart.set_heap(Heap::new(0x1000, 0x2000));
+        run_until(&mut hart, 24);
+
+        assert_eq!((0x1000, 0x1040), hart.registers().read(8, 10));
+        assert_eq!(0x1040, hart.heap().brk());
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut hart = hart_with_program(&[
+            0x05d0_0893, // li a7, 93
+            0x0030_0513, // li a0, 3
+        ]);
+        run_until(&mut hart, 8);
+        assert_eq!(Err(StopReason::Exit(3)), hart.execute(8, 0x0000_0073));
+    }
+
+    #[test]
+    fn test_write() {
+        let mut hart = hart_with_program(&[
+            0x0400_0893, // li a7, 64
+            0x0010_0513, // li a0, 1
+            0x0200_0593, // li a1, 32
+            0x0030_0613, // li a2, 3
+            0x0000_0073, // ecall
+            0x0005_0413, // mv s0, a0
+            0x0050_0513, // li a0, 5
+            0x0000_0073, // ecall
+            0x0063_6261, // "abc"
+        ]);
+        run_until(&mut hart, 32);
+
+        assert_eq!(b"abc", hart.output());
+        // The second write goes to a file descriptor which isn't open
+        assert_eq!((3, -9), hart.registers().read(8, 10));
+
+        let mut hart = hart_with_program(&[
+            0x0400_0893, // li a7, 64
+            0x0010_0513, // li a0, 1
+            0x8000_0637, // lui a2, 0x80000
+            0x0000_0073, // ecall
+        ]);
+        run_until(&mut hart, 16);
+        assert!(hart.output().is_empty());
+        assert_eq!(-22, hart.registers().read(10, 0).0);
+    }
+
+    #[test]
+    fn test_tohost() {
+        let mut hart = hart_with_program(&[
+            0x0000_12b7, // lui t0, 1
+            0x0070_0313, // li t1, 7
+            0x0062_a023, // sw t1, 0(t0)
+        ]);
+        hart.set_tohost(Some(0x1000));
+        run_until(&mut hart, 8);
+        assert_eq!(Err(StopReason::Exit(3)), hart.execute(8, 0x0062_a023));
+    }
+
+    ////////////////////////////////////////
+    // Uninitialized memory
+    ////////////////////////////////////////
+    #[test]
+    fn test_uninitialized_reads() {
+        let mut hart = Hart::new();
+        hart.memory_mut().track_writes();
+        let program = [
+            0x0000_12b7, // lui t0, 1
+            0x0002_a303, // lw t1, 0(t0)
+            0xfe00_0ce3, // beqz zero, -8
+        ];
+        for (index, instruction) in program.iter().enumerate() {
+            hart.memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    (index as u32) << 2,
+                    *instruction,
+                )
+                .unwrap();
+        }
+
+        // The load is only reported once
+        for _ in 0..6 {
+            let pc = hart.pc();
+            let raw = hart.memory().read_pc(pc);
+            hart.execute(pc, raw).unwrap();
+        }
+        assert_eq!(
+            &[UninitializedRead {
+                pc: 4,
+                addr: 0x1000
+            }],
+            hart.uninitialized_reads()
+        );
+
+        hart.set_uninitialized_policy(UninitializedPolicy::Stop);
+        assert_eq!(
+            Err(StopReason::UninitializedRead {
+                pc: 4,
+                addr: 0x1000
+            }),
+            hart.execute(4, 0x0002_a303)
+        );
+
+        // Memory written by a store is initialized
+        hart.memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SW), 0x1000, 1)
+            .unwrap();
+        assert!(hart.execute(4, 0x0002_a303).is_ok());
+    }
+
+    #[test]
+    fn test_guard_fault() {
+        let mut hart = hart_with_program(&[
+            0x0000_22b7, // lui t0, 2
+            0xfe02_ae23, // sw zero, -4(t0)
+        ]);
+        hart.add_guard(Guard::below_stack(0x3000, 0x1000));
+        hart.add_guard(Guard::after_heap(&Heap::new(0x100, 0x400)));
+        run_until(&mut hart, 4);
+
+        assert_eq!(
+            Err(StopReason::GuardFault {
+                pc: 4,
+                addr: 0x1ffc,
+                kind: GuardKind::Stack
+            }),
+            hart.execute(4, 0xfe02_ae23)
+        );
+        // lw t1, 0x400(zero)
+        assert_eq!(
+            Err(StopReason::GuardFault {
+                pc: 8,
+                addr: 0x400,
+                kind: GuardKind::Heap
+            }),
+            hart.execute(8, 0x4000_2303)
+        );
+    }
+
+    #[test]
+    fn test_unsupported_syscall() {
+        let mut hart = hart_with_program(&[
+            0x0390_0893, // li a7, 57
+        ]);
+        run_until(&mut hart, 4);
+        assert_eq!(
+            Err(StopReason::UnsupportedSyscall { pc: 4, number: 57 }),
+            hart.execute(4, 0x0000_0073)
+        );
+        assert_eq!(
+            Err(StopReason::Breakpoint { pc: 4 }),
+            hart.execute(4, 0x0010_0073)
+        );
+    }
+
+    #[test]
+    fn test_gettimeofday() {
+        let mut hart = hart_with_program(&[
+            0x0a90_0893, // li a7, 169
+            0x1000_0513, // li a0, 0x100
+        ]);
+        run_until(&mut hart, 8);
+        // mtime ticks once every thousand cycles of a 1 GHz clock
+        hart.clint_mut().set_clock(Some(1_000_000_000));
+        hart.clint_mut().write(CLINT_BASE + 0xbff8, 4, 3_500_042);
+        assert!(hart.execute(8, 0x0000_0073).is_ok());
+        assert_eq!((0, 0), hart.registers().read(10, 0));
+        let timeval: Vec<_> = (0..3)
+            .map(|index| hart.memory().read_pc(0x100 + 4 * index))
+            .collect();
+        assert_eq!(vec![3, 0, 500_042], timeval);
+    }
+}
@@ -0,0 +1,231 @@
+//! Heatmap of the memory accesses of the guest program. The loads and stores
+//! are counted per region of a configurable size, a 4 KiB page by default,
+//! to find the hot spots of the data and how much of it would fit in a
+//! scratchpad. The heatmap is exported as CSV or as a text report with a bar
+//! per region.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::heatmap::Heatmap;
+//! let mut heatmap = Heatmap::new(0x100);
+//! heatmap.load(0x1004, 4);
+//! heatmap.store(0x10fc, 4);
+//! heatmap.store(0x1100, 1);
+//! assert_eq!((1, 1), heatmap.counts(0x1000));
+//! assert_eq!(vec![(0x1000, 1, 1), (0x1100, 0, 1)], heatmap.regions());
+//! ```
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Size of the regions of a heatmap by default, a page
+pub const DEFAULT_GRANULARITY: u32 = 4096;
+
+// Width of the bar of the hottest region in the text report
+const BAR_WIDTH: u64 = 40;
+
+/// Loads and stores counted by region of the memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heatmap {
+    granularity: u32,
+    // Reads and writes of every accessed region, by its first address
+    regions: HashMap<u32, (u64, u64)>,
+}
+
+impl Default for Heatmap {
+    fn default() -> Self {
+        Self::new(DEFAULT_GRANULARITY)
+    }
+}
+
+impl Heatmap {
+    /// Create an empty heatmap
+    ///
+    /// # Arguments
+    /// * `granularity` => size of the regions in bytes, a power of two
+    pub fn new(granularity: u32) -> Self {
+        assert!(
+            granularity.is_power_of_two(),
+            "the granularity of a heatmap must be a power of two: {}",
+            granularity
+        );
+        Heatmap {
+            granularity,
+            regions: HashMap::new(),
+        }
+    }
+
+    pub fn granularity(&self) -> u32 {
+        self.granularity
+    }
+
+    /// Record a load. An access which spans two regions counts in both.
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first byte
+    /// * `size` => number of bytes read
+    pub fn load(&mut self, addr: u32, size: u32) {
+        for region in self.touched(addr, size) {
+            self.regions.entry(region).or_default().0 += 1;
+        }
+    }
+
+    /// Record a store. An access which spans two regions counts in both.
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first byte
+    /// * `size` => number of bytes written
+    pub fn store(&mut self, addr: u32, size: u32) {
+        for region in self.touched(addr, size) {
+            self.regions.entry(region).or_default().1 += 1;
+        }
+    }
+
+    /// Number of reads and writes of the region holding an address
+    pub fn counts(&self, addr: u32) -> (u64, u64) {
+        self.regions
+            .get(&self.region(addr))
+            .cloned()
+            .unwrap_or((0, 0))
+    }
+
+    /// First address, reads and writes of every accessed region, in
+    /// increasing order
+    pub fn regions(&self) -> Vec<(u32, u64, u64)> {
+        let mut regions: Vec<_> = self
+            .regions
+            .iter()
+            .map(|(&region, &(reads, writes))| (region, reads, writes))
+            .collect();
+        regions.sort_unstable();
+        regions
+    }
+
+    /// Write one line per accessed region with its first address, its reads
+    /// and its writes, after a header
+    ///
+    /// # Arguments
+    /// * `out` => destination of the table
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "address,reads,writes")?;
+        for (region, reads, writes) in self.regions() {
+            writeln!(out, "0x{:08x},{},{}", region, reads, writes)?;
+        }
+        Ok(())
+    }
+
+    /// Write one line per accessed region with its range, its reads, its
+    /// writes and a bar as long as its accesses relative to the hottest
+    /// region
+    ///
+    /// # Arguments
+    /// * `out` => destination of the report
+    pub fn write_text<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let regions = self.regions();
+        let hottest = regions
+            .iter()
+            .map(|&(_, reads, writes)| reads + writes)
+            .max()
+            .unwrap_or(0);
+        writeln!(
+            out,
+            "{} regions of {} bytes accessed",
+            regions.len(),
+            self.granularity
+        )?;
+        for (region, reads, writes) in regions {
+            let last = region.wrapping_add(self.granularity - 1);
+            // Every accessed region gets at least one mark
+            let width = ((reads + writes) * BAR_WIDTH).div_ceil(hottest);
+            writeln!(
+                out,
+                "0x{:08x}-0x{:08x} {:>10} {:>10} {}",
+                region,
+                last,
+                reads,
+                writes,
+                "#".repeat(width as usize)
+            )?;
+        }
+        Ok(())
+    }
+
+    // First address of the region holding an address
+    fn region(&self, addr: u32) -> u32 {
+        addr & !(self.granularity - 1)
+    }
+
+    // Regions holding the first and the last byte of an access
+    fn touched(&self, addr: u32, size: u32) -> Vec<u32> {
+        let first = self.region(addr);
+        let last = self.region(addr.wrapping_add(size.max(1) - 1));
+        if first == last {
+            vec![first]
+        } else {
+            vec![first, last]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heatmap() -> Heatmap {
+        let mut heatmap = Heatmap::default();
+        for _ in 0..3 {
+            heatmap.load(0x0001_0000, 4);
+        }
+        heatmap.store(0x0001_0ffe, 2);
+        // Across two pages
+        heatmap.store(0x0002_0ffe, 4);
+        heatmap
+    }
+
+    #[test]
+    fn test_counts() {
+        let heatmap = heatmap();
+        assert_eq!(4096, heatmap.granularity());
+        assert_eq!((3, 1), heatmap.counts(0x0001_0abc));
+        assert_eq!((0, 1), heatmap.counts(0x0002_0000));
+        assert_eq!((0, 1), heatmap.counts(0x0002_1000));
+        assert_eq!((0, 0), heatmap.counts(0x0003_0000));
+        assert_eq!(3, heatmap.regions().len());
+    }
+
+    #[test]
+    fn test_reports() {
+        let heatmap = heatmap();
+        let mut csv = Vec::new();
+        heatmap.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            "address,reads,writes\n\
+             0x00010000,3,1\n\
+             0x00020000,0,1\n\
+             0x00021000,0,1\n",
+            String::from_utf8(csv).unwrap()
+        );
+
+        let mut text = Vec::new();
+        heatmap.write_text(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("3 regions of 4096 bytes accessed", lines[0]);
+        assert_eq!(
+            format!(
+                "0x00010000-0x00010fff {:>10} {:>10} {}",
+                3,
+                1,
+                "#".repeat(40)
+            ),
+            lines[1]
+        );
+        assert!(lines[2].ends_with(&format!(" {}", "#".repeat(10))));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_granularity() {
+        let _ = Heatmap::new(1000);
+    }
+}
@@ -0,0 +1,527 @@
+//! Memory images composed from several files. Firmware is often delivered in
+//! parts, such as a boot ROM, the main program and a data blob, in different
+//! formats. Every part is written over the image in order, so later parts
+//! overlay the earlier ones byte by byte.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::image::MemoryImage;
+//! let mut image = MemoryImage::new();
+//! image.write_word(0x100, 0x1122_3344);
+//! // Overlay two bytes in the middle of the word
+//! image.write_bytes(0x101, &[0xaa, 0xbb]);
+//! assert_eq!(vec![(0x100, 0x11bb_aa44)], image.words());
+//! ```
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+// Intel HEX record types
+const HEX_DATA: u8 = 0x00;
+const HEX_END_OF_FILE: u8 = 0x01;
+const HEX_EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const HEX_START_SEGMENT_ADDRESS: u8 = 0x03;
+const HEX_EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const HEX_START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// Formats of the files which can be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Elf,
+    /// Raw bytes
+    Binary,
+    /// Intel HEX records
+    IntelHex,
+    /// Words for the $readmemh task of Verilog, with word addresses
+    ReadMemH,
+}
+
+impl ImageFormat {
+    /// Guess the format of a file from its contents and its name
+    ///
+    /// # Arguments
+    /// * `path` => name of the file
+    /// * `data` => contents of the file
+    pub fn detect(path: &str, data: &[u8]) -> Self {
+        let extension = path.rsplit('.').next().unwrap_or("");
+        if data.starts_with(&[0x7f, b'E', b'L', b'F']) {
+            ImageFormat::Elf
+        } else if extension.eq_ignore_ascii_case("hex")
+            || extension.eq_ignore_ascii_case("ihex")
+            || data.first() == Some(&b':')
+        {
+            ImageFormat::IntelHex
+        } else if extension.eq_ignore_ascii_case("mem")
+            || extension.eq_ignore_ascii_case("vmem")
+            || data.first() == Some(&b'@')
+        {
+            ImageFormat::ReadMemH
+        } else {
+            ImageFormat::Binary
+        }
+    }
+}
+
+/// Formats the image can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Raw bytes from the first written word to the last one, with the gaps
+    /// cleared
+    Binary,
+    /// Intel HEX records
+    IntelHex,
+    /// Words for the $readmemh task of Verilog, with word addresses
+    ReadMemH,
+}
+
+impl ExportFormat {
+    /// Find a format from its name: bin, ihex or readmemh
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "bin" => Some(ExportFormat::Binary),
+            "ihex" => Some(ExportFormat::IntelHex),
+            "readmemh" => Some(ExportFormat::ReadMemH),
+            _ => None,
+        }
+    }
+
+    /// Guess the format of a file from its name: Intel HEX for .hex and
+    /// .ihex, $readmemh for .mem and .vmem and binary otherwise
+    pub fn detect(path: &str) -> Self {
+        let extension = path.rsplit('.').next().unwrap_or("");
+        if extension.eq_ignore_ascii_case("hex") || extension.eq_ignore_ascii_case("ihex") {
+            ExportFormat::IntelHex
+        } else if extension.eq_ignore_ascii_case("mem") || extension.eq_ignore_ascii_case("vmem") {
+            ExportFormat::ReadMemH
+        } else {
+            ExportFormat::Binary
+        }
+    }
+}
+
+/// Error in a file holding part of an image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ImageError {}
+
+/// Contents of the memory, by word
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryImage {
+    words: BTreeMap<u32, u32>,
+}
+
+impl MemoryImage {
+    pub fn new() -> Self {
+        MemoryImage::default()
+    }
+
+    /// Write an aligned word
+    ///
+    /// # Arguments
+    /// * `addr` => address of the word, the lowest two bits are ignored
+    /// * `value` => the word
+    pub fn write_word(&mut self, addr: u32, value: u32) {
+        self.words.insert(addr & !3, value);
+    }
+
+    /// Write bytes at any address, keeping the other bytes of the words they
+    /// fall in. Bytes which were never written are zero.
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first byte
+    /// * `data` => the bytes
+    pub fn write_bytes(&mut self, addr: u32, data: &[u8]) {
+        for (offset, byte) in data.iter().enumerate() {
+            let addr = addr.wrapping_add(offset as u32);
+            let shift = (addr & 3) << 3;
+            let word = self.words.entry(addr & !3).or_insert(0);
+            *word = *word & !(0xff << shift) | u32::from(*byte) << shift;
+        }
+    }
+
+    /// Write the data records of an Intel HEX file. The start address
+    /// records are ignored.
+    ///
+    /// # Arguments
+    /// * `text` => contents of the file
+    /// * `offset` => added to every address of the file
+    pub fn write_intel_hex(&mut self, text: &str, offset: u32) -> Result<(), ImageError> {
+        let mut base = 0u32;
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: &str| ImageError {
+                line: line_number,
+                message: message.to_string(),
+            };
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let record = match line.strip_prefix(':') {
+                Some(record) => parse_hex_bytes(record).ok_or_else(|| error("invalid digits"))?,
+                None => return Err(error("records start with ':'")),
+            };
+            if record.len() < 5 || record.len() != usize::from(record[0]) + 5 {
+                return Err(error("the record length doesn't match its byte count"));
+            }
+            if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+                return Err(error("wrong checksum"));
+            }
+
+            let address = u32::from(record[1]) << 8 | u32::from(record[2]);
+            let data = &record[4..record.len() - 1];
+            let value = data
+                .iter()
+                .fold(0u32, |value, byte| value << 8 | u32::from(*byte));
+            match record[3] {
+                HEX_DATA => self.write_bytes(offset.wrapping_add(base).wrapping_add(address), data),
+                HEX_END_OF_FILE => return Ok(()),
+                HEX_EXTENDED_SEGMENT_ADDRESS if data.len() == 2 => base = value << 4,
+                HEX_EXTENDED_LINEAR_ADDRESS if data.len() == 2 => base = value << 16,
+                HEX_START_SEGMENT_ADDRESS | HEX_START_LINEAR_ADDRESS if data.len() == 4 => (),
+                _ => return Err(error("invalid record")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the words of a file for the $readmemh task of Verilog, for a
+    /// memory of 32 bit words. The addresses of the file count words from
+    /// the first one.
+    ///
+    /// # Arguments
+    /// * `text` => contents of the file
+    /// * `offset` => address of the first word of the memory
+    pub fn write_readmemh(&mut self, text: &str, offset: u32) -> Result<(), ImageError> {
+        let mut index = 0u32;
+        let mut in_comment = false;
+        for (line_index, line) in text.lines().enumerate() {
+            let error = |message: String| ImageError {
+                line: line_index + 1,
+                message,
+            };
+
+            let mut rest = line;
+            while !rest.is_empty() {
+                if in_comment {
+                    match rest.find("*/") {
+                        Some(end) => {
+                            rest = &rest[end + 2..];
+                            in_comment = false;
+                        }
+                        None => break,
+                    }
+                    continue;
+                }
+                rest = rest.trim_start();
+                if rest.starts_with("//") {
+                    break;
+                }
+                if let Some(comment) = rest.strip_prefix("/*") {
+                    rest = comment;
+                    in_comment = true;
+                    continue;
+                }
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '/')
+                    .unwrap_or(rest.len());
+                let (token, next) = rest.split_at(end);
+                rest = next;
+                if token.is_empty() {
+                    return Err(error(format!("invalid token {}", next)));
+                }
+                let (address, digits) = match token.strip_prefix('@') {
+                    Some(digits) => (true, digits),
+                    None => (false, token),
+                };
+                let digits = digits.replace('_', "");
+                let value = match u32::from_str_radix(&digits, 16) {
+                    Ok(value) if !digits.starts_with('+') => value,
+                    _ => return Err(error(format!("invalid word {}", token))),
+                };
+                if address {
+                    index = value;
+                } else {
+                    self.write_word(offset.wrapping_add(index << 2), value);
+                    index = index.wrapping_add(1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Address and value of every written word, in increasing order
+    pub fn words(&self) -> Vec<(u32, u32)> {
+        self.words
+            .iter()
+            .map(|(addr, word)| (*addr, *word))
+            .collect()
+    }
+
+    /// Address after the last written word
+    pub fn end(&self) -> u32 {
+        self.words
+            .keys()
+            .next_back()
+            .map_or(0, |addr| addr.wrapping_add(4))
+    }
+
+    /// Write the image in a format
+    pub fn export(&self, format: ExportFormat) -> Vec<u8> {
+        match format {
+            ExportFormat::Binary => self.to_binary(),
+            ExportFormat::IntelHex => self.to_intel_hex().into_bytes(),
+            ExportFormat::ReadMemH => self.to_readmemh().into_bytes(),
+        }
+    }
+
+    /// Bytes from the first written word to the last one, which is where
+    /// the binary starts. Bytes which were never written are zero.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let (start, last) = match (self.words.keys().next(), self.words.keys().next_back()) {
+            (Some(start), Some(last)) => (*start, *last),
+            _ => return Vec::new(),
+        };
+        let mut bytes = vec![0; (u64::from(last - start) + 4) as usize];
+        for (addr, word) in &self.words {
+            let offset = (addr - start) as usize;
+            bytes[offset..offset + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Intel HEX records of the words, up to 16 bytes each, with an extended
+    /// linear address record before every 64 KiB segment
+    pub fn to_intel_hex(&self) -> String {
+        let mut text = String::new();
+        let mut segment = None;
+        let mut record: Vec<u8> = Vec::new();
+        let mut record_addr = 0;
+        for (addr, word) in &self.words {
+            let contiguous = record_addr + record.len() as u32 == *addr;
+            if !record.is_empty()
+                && (!contiguous || record.len() == 16 || addr >> 16 != record_addr >> 16)
+            {
+                push_hex_record(&mut text, HEX_DATA, record_addr as u16, &record);
+                record.clear();
+            }
+            if segment != Some(addr >> 16) {
+                segment = Some(addr >> 16);
+                push_hex_record(
+                    &mut text,
+                    HEX_EXTENDED_LINEAR_ADDRESS,
+                    0,
+                    &((addr >> 16) as u16).to_be_bytes(),
+                );
+            }
+            if record.is_empty() {
+                record_addr = *addr;
+            }
+            record.extend_from_slice(&word.to_le_bytes());
+        }
+        if !record.is_empty() {
+            push_hex_record(&mut text, HEX_DATA, record_addr as u16, &record);
+        }
+        push_hex_record(&mut text, HEX_END_OF_FILE, 0, &[]);
+        text
+    }
+
+    /// A word per line for the $readmemh task of a memory of 32 bit words,
+    /// with an address, in words, before every run of contiguous words
+    pub fn to_readmemh(&self) -> String {
+        let mut text = String::new();
+        let mut next = None;
+        for (addr, word) in &self.words {
+            if next != Some(*addr) {
+                text.push_str(&format!("@{:08x}\n", addr >> 2));
+            }
+            text.push_str(&format!("{:08x}\n", word));
+            next = addr.checked_add(4);
+        }
+        text
+    }
+}
+
+// Append an Intel HEX record, with its checksum
+fn push_hex_record(text: &mut String, kind: u8, addr: u16, data: &[u8]) {
+    let mut record = vec![data.len() as u8];
+    record.extend_from_slice(&addr.to_be_bytes());
+    record.push(kind);
+    record.extend_from_slice(data);
+    let sum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    record.push(sum.wrapping_neg());
+    text.push(':');
+    for byte in record {
+        text.push_str(&format!("{:02X}", byte));
+    }
+    text.push('\n');
+}
+
+// Bytes given as pairs of hexadecimal digits
+fn parse_hex_bytes(digits: &str) -> Option<Vec<u8>> {
+    if digits.len() & 1 != 0 || !digits.is_ascii() {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&digits[index..index + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let elf = [0x7f, b'E', b'L', b'F', 1, 1, 1];
+        assert_eq!(ImageFormat::Elf, ImageFormat::detect("boot.bin", &elf));
+        assert_eq!(
+            ImageFormat::IntelHex,
+            ImageFormat::detect("data.HEX", b"\n")
+        );
+        assert_eq!(
+            ImageFormat::IntelHex,
+            ImageFormat::detect("data", b":00000001FF")
+        );
+        assert_eq!(
+            ImageFormat::Binary,
+            ImageFormat::detect("blob.bin", &[0x13, 0])
+        );
+    }
+
+    #[test]
+    fn test_overlays() {
+        let mut image = MemoryImage::new();
+        image.write_bytes(0x1002, &[1, 2, 3]);
+        image.write_word(0x1004, 0xffff_ffff);
+        image.write_bytes(0x1007, &[0]);
+        assert_eq!(
+            vec![(0x1000, 0x0201_0000), (0x1004, 0x00ff_ffff)],
+            image.words()
+        );
+        assert_eq!(0x1008, image.end());
+        assert_eq!(0, MemoryImage::new().end());
+    }
+
+    #[test]
+    fn test_intel_hex() {
+        let mut image = MemoryImage::new();
+        image
+            .write_intel_hex(
+                ":020000040001F9\n\
+                 :0400100013050500CF\n\
+                 :0400000500010000F6\n\
+                 \n\
+                 :00000001FF\n\
+                 :0400000001020304F2\n",
+                0x100,
+            )
+            .unwrap();
+        // The record after the end of the file is ignored
+        assert_eq!(vec![(0x0001_0110, 0x0005_0513)], image.words());
+    }
+
+    #[test]
+    fn test_invalid_intel_hex() {
+        let mut image = MemoryImage::new();
+        let error = |text| image.clone().write_intel_hex(text, 0).unwrap_err();
+        assert_eq!(1, error("0400100013050500CF").line);
+        assert_eq!(
+            "line 2: wrong checksum",
+            error(":020000040001F9\n:0400100013050500C8").to_string()
+        );
+        assert_eq!(1, error(":0400100013050500").line);
+        assert_eq!(1, error(":0G00000001FF").line);
+        assert_eq!(1, error(":00000006FA").line);
+        assert!(image.write_intel_hex("", 0).is_ok());
+    }
+
+    #[test]
+    fn test_export() {
+        let mut image = MemoryImage::new();
+        image.write_word(0x0001_fff8, 0x0005_0513);
+        image.write_word(0x0001_fffc, 0x0000_0013);
+        image.write_word(0x0002_0000, 0x1122_3344);
+        image.write_word(0x0002_0008, 0xaabb_ccdd);
+
+        let binary = image.to_binary();
+        assert_eq!(0x14, binary.len());
+        assert_eq!(&[0x13, 0x05, 0x05, 0x00], &binary[..4]);
+        assert_eq!(&[0; 4], &binary[12..16]);
+
+        assert_eq!(
+            "@00007ffe\n00050513\n00000013\n11223344\n@00008002\naabbccdd\n",
+            image.to_readmemh()
+        );
+
+        // The records are read back to the same image
+        let hex = image.to_intel_hex();
+        assert!(hex.starts_with(":020000040001F9\n:08FFF8001305050013000000D1\n"));
+        let mut loaded = MemoryImage::new();
+        loaded.write_intel_hex(&hex, 0).unwrap();
+        assert_eq!(image, loaded);
+
+        assert_eq!(":00000001FF\n", MemoryImage::new().to_intel_hex());
+        assert!(MemoryImage::new().to_binary().is_empty());
+    }
+
+    #[test]
+    fn test_export_formats() {
+        assert_eq!(
+            Some(ExportFormat::ReadMemH),
+            ExportFormat::parse("readmemh")
+        );
+        assert_eq!(None, ExportFormat::parse("elf"));
+        assert_eq!(ExportFormat::IntelHex, ExportFormat::detect("ram.hex"));
+        assert_eq!(ExportFormat::ReadMemH, ExportFormat::detect("ram.vmem"));
+        assert_eq!(ExportFormat::Binary, ExportFormat::detect("ram"));
+    }
+
+    #[test]
+    fn test_readmemh() {
+        let mut image = MemoryImage::new();
+        image
+            .write_readmemh(
+                "// Program\n\
+                 00050513 0000_0013\n\
+                 @10 /* data\n\
+                 over two lines */ aabbccdd\n\
+                 11223344// last\n",
+                0x100,
+            )
+            .unwrap();
+        assert_eq!(
+            vec![
+                (0x100, 0x0005_0513),
+                (0x104, 0x13),
+                (0x140, 0xaabb_ccdd),
+                (0x144, 0x1122_3344)
+            ],
+            image.words()
+        );
+
+        // The exported words are read back to the same image
+        let mut loaded = MemoryImage::new();
+        loaded.write_readmemh(&image.to_readmemh(), 0).unwrap();
+        assert_eq!(image, loaded);
+
+        let error = |text| MemoryImage::new().write_readmemh(text, 0).unwrap_err();
+        assert_eq!("line 2: invalid word 1x", error("0\n1x").to_string());
+        assert_eq!(1, error("123456789").line);
+        assert_eq!(1, error("@+1").line);
+        assert_eq!(ImageFormat::ReadMemH, ImageFormat::detect("ram.vmem", b"0"));
+    }
+}
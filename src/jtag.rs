@@ -0,0 +1,447 @@
+//! JTAG TAP of the Adept processor with the Debug Transport Module (DTM) of
+//! the debug spec, driven through the remote_bitbang protocol of OpenOCD so
+//! that an unmodified OpenOCD and GDB debug the simulation as they debug the
+//! hardware. The TAP has a 5-bit instruction register selecting IDCODE,
+//! dtmcs, dmi or BYPASS, and the dmi accesses go to a `DebugModule`.
+//!
+//! OpenOCD connects with a configuration like:
+//!
+//! ```text
+//! adapter driver remote_bitbang
+//! remote_bitbang host localhost
+//! remote_bitbang port 9824
+//! jtag newtap adept cpu -irlen 5 -expected-id 0x1ade0003
+//! target create adept.cpu riscv -chain-position adept.cpu
+//! ```
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::debug_module::DebugModule;
+//! # use adept_lib::jtag::{Tap, IDCODE};
+//! # use adept_lib::pipeline::PipelineConfig;
+//! let mut cpu = Cpu::new(PipelineConfig::default());
+//! let mut dm = DebugModule::new();
+//! let mut tap = Tap::new();
+//! // From Test-Logic-Reset through Run-Test/Idle and Select-DR-Scan to
+//! // Shift-DR, where IDCODE is selected after a reset
+//! let mut idcode = 0;
+//! for (cycle, tms) in [0, 1, 0, 0].iter().chain([0; 32].iter()).enumerate() {
+//!     if cycle >= 4 {
+//!         idcode |= (tap.tdo() as u32) << (cycle - 4);
+//!     }
+//!     tap.set_pins(&mut dm, &mut cpu, false, *tms != 0, false);
+//!     tap.set_pins(&mut dm, &mut cpu, true, *tms != 0, false);
+//! }
+//! assert_eq!(IDCODE, idcode);
+//! ```
+use cpu::Cpu;
+use debug_module::DebugModule;
+use hart::StopReason;
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+/// Value of the IDCODE register: version 1, part 0xade0
+pub const IDCODE: u32 = 0x1ade_0003;
+
+/// Length of the instruction register
+pub const IR_LENGTH: u32 = 5;
+
+// Instructions of the TAP
+const IR_IDCODE: u32 = 0x01;
+const IR_DTMCS: u32 = 0x10;
+const IR_DMI: u32 = 0x11;
+
+// Fields of dtmcs
+const DTMCS_VERSION: u32 = 1;
+const DTMCS_ABITS_SHIFT: u32 = 4;
+
+// Width of the DMI addresses and width of the dmi register, an address, 32
+// bits of data and a 2-bit operation
+const ABITS: u32 = 7;
+const DMI_LENGTH: u32 = ABITS + 34;
+
+// Operations of the dmi register
+const DMI_OP_READ: u64 = 1;
+const DMI_OP_WRITE: u64 = 2;
+
+/// States of the TAP controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapState {
+    TestLogicReset,
+    RunTestIdle,
+    SelectDrScan,
+    CaptureDr,
+    ShiftDr,
+    Exit1Dr,
+    PauseDr,
+    Exit2Dr,
+    UpdateDr,
+    SelectIrScan,
+    CaptureIr,
+    ShiftIr,
+    Exit1Ir,
+    PauseIr,
+    Exit2Ir,
+    UpdateIr,
+}
+
+impl TapState {
+    /// State after a rising edge of TCK
+    ///
+    /// # Arguments
+    /// * `tms` => value of TMS at the edge
+    pub fn next(self, tms: bool) -> Self {
+        use self::TapState::*;
+        match (self, tms) {
+            (TestLogicReset, false)
+            | (RunTestIdle, false)
+            | (UpdateDr, false)
+            | (UpdateIr, false) => RunTestIdle,
+            (TestLogicReset, true) | (SelectIrScan, true) => TestLogicReset,
+            (RunTestIdle, true) | (UpdateDr, true) | (UpdateIr, true) => SelectDrScan,
+            (SelectDrScan, false) => CaptureDr,
+            (SelectDrScan, true) => SelectIrScan,
+            (CaptureDr, false) | (ShiftDr, false) | (Exit2Dr, false) => ShiftDr,
+            (CaptureDr, true) | (ShiftDr, true) => Exit1Dr,
+            (Exit1Dr, false) | (PauseDr, false) => PauseDr,
+            (Exit1Dr, true) | (Exit2Dr, true) => UpdateDr,
+            (PauseDr, true) => Exit2Dr,
+            (SelectIrScan, false) => CaptureIr,
+            (CaptureIr, false) | (ShiftIr, false) | (Exit2Ir, false) => ShiftIr,
+            (CaptureIr, true) | (ShiftIr, true) => Exit1Ir,
+            (Exit1Ir, false) | (PauseIr, false) => PauseIr,
+            (Exit1Ir, true) | (Exit2Ir, true) => UpdateIr,
+            (PauseIr, true) => Exit2Ir,
+        }
+    }
+}
+
+/// The TAP controller and its registers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tap {
+    state: TapState,
+    tck: bool,
+    tdo: bool,
+    ir: u32,
+    // Shift register of the current scan and its length
+    shift: u64,
+    length: u32,
+    // Content of the dmi register captured by the next scan
+    dmi: u64,
+}
+
+impl Default for Tap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tap {
+    /// Create a TAP in Test-Logic-Reset
+    pub fn new() -> Self {
+        Tap {
+            state: TapState::TestLogicReset,
+            tck: false,
+            tdo: false,
+            ir: IR_IDCODE,
+            shift: 0,
+            length: 0,
+            dmi: 0,
+        }
+    }
+
+    /// Reset the TAP, like a pulse of TRST
+    pub fn reset(&mut self) {
+        *self = Tap::new();
+    }
+
+    /// State of the TAP controller
+    pub fn state(&self) -> TapState {
+        self.state
+    }
+
+    /// Value driven on TDO
+    pub fn tdo(&self) -> bool {
+        self.tdo
+    }
+
+    /// Drive the inputs of the TAP. The TAP shifts and changes its state on
+    /// the rising edges of TCK.
+    ///
+    /// # Arguments
+    /// * `dm` => Debug Module accessed by the dmi register
+    /// * `cpu` => the simulated processor
+    /// * `tck` => value of TCK
+    /// * `tms` => value of TMS
+    /// * `tdi` => value of TDI
+    pub fn set_pins(
+        &mut self,
+        dm: &mut DebugModule,
+        cpu: &mut Cpu,
+        tck: bool,
+        tms: bool,
+        tdi: bool,
+    ) {
+        let rising = tck && !self.tck;
+        self.tck = tck;
+        if !rising {
+            return;
+        }
+        if self.state == TapState::ShiftDr || self.state == TapState::ShiftIr {
+            self.shift = self.shift >> 1 | u64::from(tdi) << (self.length - 1);
+        }
+        self.state = self.state.next(tms);
+        match self.state {
+            TapState::TestLogicReset => self.ir = IR_IDCODE,
+            TapState::CaptureDr => self.capture_dr(),
+            TapState::UpdateDr => self.update_dr(dm, cpu),
+            // The two least significant bits of the captured IR are 01
+            TapState::CaptureIr => {
+                self.shift = 1;
+                self.length = IR_LENGTH;
+            }
+            TapState::UpdateIr => self.ir = self.shift as u32,
+            _ => (),
+        }
+        self.tdo = self.shift & 1 != 0;
+    }
+
+    fn capture_dr(&mut self) {
+        let (value, length) = match self.ir {
+            IR_IDCODE => (u64::from(IDCODE), 32),
+            IR_DTMCS => (u64::from(DTMCS_VERSION | ABITS << DTMCS_ABITS_SHIFT), 32),
+            IR_DMI => (self.dmi, DMI_LENGTH),
+            // BYPASS and the unused instructions
+            _ => (0, 1),
+        };
+        self.shift = value;
+        self.length = length;
+    }
+
+    fn update_dr(&mut self, dm: &mut DebugModule, cpu: &mut Cpu) {
+        // dtmcs only has dmireset and dmihardreset writable, and there are
+        // no errors or pending accesses to reset
+        if self.ir != IR_DMI {
+            return;
+        }
+        let addr = (self.shift >> 34) as u32 & ((1 << ABITS) - 1);
+        let data = (self.shift >> 2) as u32;
+        // The accesses complete at once, so the result of the operation is
+        // always a success
+        self.dmi = match self.shift & 3 {
+            DMI_OP_READ => u64::from(addr) << 34 | u64::from(dm.read(addr)) << 2,
+            DMI_OP_WRITE => {
+                dm.write(cpu, addr, data);
+                u64::from(addr) << 34 | u64::from(data) << 2
+            }
+            _ => self.dmi & !3,
+        };
+    }
+}
+
+/// Serve the remote_bitbang protocol of OpenOCD on a connection until it's
+/// closed or OpenOCD quits, simulating the program while the hart isn't
+/// halted
+///
+/// # Arguments
+/// * `dm` => Debug Module behind the TAP
+/// * `cpu` => the simulated processor
+/// * `stream` => connection of OpenOCD
+///
+/// # Return Value
+/// Why the program stopped the last time, or None if it didn't
+pub fn serve_remote_bitbang(
+    dm: &mut DebugModule,
+    cpu: &mut Cpu,
+    mut stream: TcpStream,
+) -> io::Result<Option<StopReason>> {
+    let mut tap = Tap::new();
+    let mut buffer = [0; 4096];
+    let mut replies = Vec::new();
+    loop {
+        // Wait for OpenOCD while the hart is halted, otherwise poll it
+        // between slices of the simulation
+        stream.set_nonblocking(!dm.halted())?;
+        let read = match stream.read(&mut buffer) {
+            Ok(0) => return Ok(dm.stop_reason()),
+            Ok(read) => read,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                dm.run_slice(cpu);
+                continue;
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for byte in &buffer[..read] {
+            match *byte {
+                b'R' => replies.push(if tap.tdo() { b'1' } else { b'0' }),
+                b'0'..=b'7' => {
+                    let pins = byte - b'0';
+                    tap.set_pins(dm, cpu, pins & 4 != 0, pins & 2 != 0, pins & 1 != 0);
+                }
+                // TRST is asserted by r and s, SRST isn't connected
+                b'r' | b's' => tap.reset(),
+                b'Q' => return Ok(dm.stop_reason()),
+                // Blinking and the rest of the resets
+                b'B' | b'b' | b't' | b'u' => (),
+                byte => warn!("remote bitbang: unknown command {:?}", byte as char),
+            }
+        }
+        if !replies.is_empty() {
+            stream.set_nonblocking(false)?;
+            stream.write_all(&replies)?;
+            replies.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use debug_module::{DMCONTROL, DMSTATUS};
+    use pipeline::PipelineConfig;
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Drive TMS for a cycle, returning TDO before the edge
+    fn clock(tap: &mut Tap, dm: &mut DebugModule, cpu: &mut Cpu, tms: bool, tdi: bool) -> bool {
+        let tdo = tap.tdo();
+        tap.set_pins(dm, cpu, false, tms, tdi);
+        tap.set_pins(dm, cpu, true, tms, tdi);
+        tdo
+    }
+
+    // Scan a register from Run-Test/Idle back to Run-Test/Idle, returning
+    // the bits shifted out
+    fn scan(
+        tap: &mut Tap,
+        dm: &mut DebugModule,
+        cpu: &mut Cpu,
+        ir: bool,
+        value: u64,
+        length: u32,
+    ) -> u64 {
+        clock(tap, dm, cpu, true, false);
+        if ir {
+            clock(tap, dm, cpu, true, false);
+        }
+        clock(tap, dm, cpu, false, false);
+        clock(tap, dm, cpu, false, false);
+        let mut out = 0;
+        for bit in 0..length {
+            let last = bit == length - 1;
+            let tdo = clock(tap, dm, cpu, last, value >> bit & 1 != 0);
+            out |= u64::from(tdo) << bit;
+        }
+        clock(tap, dm, cpu, true, false);
+        clock(tap, dm, cpu, false, false);
+        out
+    }
+
+    #[test]
+    fn test_states() {
+        use self::TapState::*;
+        let mut state = RunTestIdle;
+        // Five cycles with TMS high reset from any state
+        for _ in 0..5 {
+            state = state.next(true);
+        }
+        assert_eq!(TestLogicReset, state);
+        let path = [
+            (false, RunTestIdle),
+            (true, SelectDrScan),
+            (true, SelectIrScan),
+            (false, CaptureIr),
+            (true, Exit1Ir),
+            (false, PauseIr),
+            (true, Exit2Ir),
+            (false, ShiftIr),
+            (true, Exit1Ir),
+            (true, UpdateIr),
+            (true, SelectDrScan),
+        ];
+        for (tms, expected) in path.iter() {
+            state = state.next(*tms);
+            assert_eq!(*expected, state);
+        }
+    }
+
+    #[test]
+    fn test_dmi() {
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        let mut dm = DebugModule::new();
+        let mut tap = Tap::new();
+        clock(&mut tap, &mut dm, &mut cpu, false, false);
+        assert_eq!(
+            u64::from(IDCODE),
+            scan(&mut tap, &mut dm, &mut cpu, false, 0, 32)
+        );
+        // Shifting the IR returns 01
+        assert_eq!(
+            1,
+            scan(
+                &mut tap,
+                &mut dm,
+                &mut cpu,
+                true,
+                u64::from(IR_DTMCS),
+                IR_LENGTH
+            )
+        );
+        assert_eq!(0x71, scan(&mut tap, &mut dm, &mut cpu, false, 0, 32));
+
+        scan(
+            &mut tap,
+            &mut dm,
+            &mut cpu,
+            true,
+            u64::from(IR_DMI),
+            IR_LENGTH,
+        );
+        let write = u64::from(DMCONTROL) << 34 | (1 << 31 | 1) << 2 | DMI_OP_WRITE;
+        scan(&mut tap, &mut dm, &mut cpu, false, write, DMI_LENGTH);
+        assert!(dm.halted());
+        let read = u64::from(DMSTATUS) << 34 | DMI_OP_READ;
+        scan(&mut tap, &mut dm, &mut cpu, false, read, DMI_LENGTH);
+        // The result is captured by the next scan
+        let result = scan(&mut tap, &mut dm, &mut cpu, false, 0, DMI_LENGTH);
+        assert_eq!(0, result & 3);
+        assert_eq!(u64::from(DMSTATUS), result >> 34);
+        assert_eq!(u64::from(dm.read(DMSTATUS)), result >> 2 & 0xffff_ffff);
+
+        // BYPASS is a single bit
+        scan(&mut tap, &mut dm, &mut cpu, true, 0x1f, IR_LENGTH);
+        assert_eq!(0b10, scan(&mut tap, &mut dm, &mut cpu, false, 0b01, 2));
+    }
+
+    #[test]
+    fn test_serve_remote_bitbang() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            // Reset, go to Shift-DR and read 4 bits of IDCODE, then quit
+            let mut commands = b"r0".to_vec();
+            for tms in [0, 1, 0, 0].iter() {
+                commands.extend_from_slice(&[b'0' + tms * 2, b'4' + tms * 2]);
+            }
+            for _ in 0..4 {
+                commands.extend_from_slice(b"R04");
+            }
+            stream.write_all(&commands).unwrap();
+            let mut tdo = [0; 4];
+            stream.read_exact(&mut tdo).unwrap();
+            stream.write_all(b"Q").unwrap();
+            tdo
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        let mut dm = DebugModule::new();
+        serve_remote_bitbang(&mut dm, &mut cpu, stream).unwrap();
+        assert_eq!(b"1100", &client.join().unwrap());
+    }
+}
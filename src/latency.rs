@@ -0,0 +1,243 @@
+//! Execute latencies of the pipeline model. Every instruction belongs to an
+//! instruction class and each class takes a configurable number of cycles in
+//! the execute stage. The table can be read from the `[latency]` table of a
+//! configuration file, which allows modelling different Adept hardware
+//! revisions without changing the simulator.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::config::Config;
+//! # use adept_lib::latency::{InstrClass, LatencyTable};
+//! # use adept_lib::riscv::isa::RV32I;
+//! let config = Config::parse("[latency]\nload = 2\n").unwrap();
+//! let table = LatencyTable::from_config(&config).unwrap();
+//! assert_eq!(2, table.get(InstrClass::from(RV32I::LW)));
+//! // Classes that are not in the configuration keep the default latency
+//! assert_eq!(1, table.get(InstrClass::from(RV32I::ADD)));
+//! ```
+use config::{value_error, Config, ConfigError};
+use riscv::isa::RV32I;
+
+/// Instruction classes with independent latencies
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum InstrClass {
+    /// Arithmetic, logic, shifts, comparisons, LUI and AUIPC
+    Alu,
+    /// Multiplications, which only custom instructions belong to as the M
+    /// extension isn't supported
+    Mul,
+    /// Divisions and remainders, which only custom instructions belong to as
+    /// the M extension isn't supported
+    Div,
+    /// Memory loads
+    Load,
+    /// Memory stores
+    Store,
+    /// Conditional branches
+    Branch,
+    /// JAL and JALR
+    Jump,
+    /// Control and status register accesses and environment calls
+    System,
+}
+
+impl InstrClass {
+    /// All instruction classes, in table order
+    pub const ALL: [InstrClass; 8] = [
+        InstrClass::Alu,
+        InstrClass::Mul,
+        InstrClass::Div,
+        InstrClass::Load,
+        InstrClass::Store,
+        InstrClass::Branch,
+        InstrClass::Jump,
+        InstrClass::System,
+    ];
+
+    /// Name of the class as used in configuration files
+    pub fn name(self) -> &'static str {
+        match self {
+            InstrClass::Alu => "alu",
+            InstrClass::Mul => "mul",
+            InstrClass::Div => "div",
+            InstrClass::Load => "load",
+            InstrClass::Store => "store",
+            InstrClass::Branch => "branch",
+            InstrClass::Jump => "jump",
+            InstrClass::System => "system",
+        }
+    }
+
+    /// Get a class from its configuration file name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|class| class.name() == name).cloned()
+    }
+
+    /// Position of the class in `ALL` and in the latency table
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl From<RV32I> for InstrClass {
+    fn from(instr: RV32I) -> Self {
+        match instr {
+            RV32I::LB | RV32I::LH | RV32I::LW | RV32I::LBU | RV32I::LHU => InstrClass::Load,
+            RV32I::SB | RV32I::SH | RV32I::SW => InstrClass::Store,
+            RV32I::BEQ | RV32I::BNE | RV32I::BLT | RV32I::BGE | RV32I::BLTU | RV32I::BGEU => {
+                InstrClass::Branch
+            }
+            RV32I::JAL | RV32I::JALR => InstrClass::Jump,
+            RV32I::CSRRW
+            | RV32I::CSRRS
+            | RV32I::CSRRC
+            | RV32I::CSRRWI
+            | RV32I::CSRRSI
+            | RV32I::CSRRCI
+            | RV32I::ECALL
+            | RV32I::EBREAK
+            | RV32I::MRET
+            | RV32I::WFI => InstrClass::System,
+            _ => InstrClass::Alu,
+        }
+    }
+}
+
+/// Number of execute cycles of each instruction class
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct LatencyTable {
+    cycles: [u32; 8],
+}
+
+impl Default for LatencyTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyTable {
+    /// Create a table where every class takes a single cycle
+    pub fn new() -> Self {
+        LatencyTable { cycles: [1; 8] }
+    }
+
+    /// Create a table from the `[latency]` table of a configuration. Classes
+    /// which are not present keep a latency of one cycle.
+    ///
+    /// # Arguments
+    /// * `config` => parsed configuration
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let mut table = Self::new();
+
+        if let Some(entries) = config.table("latency") {
+            for key in entries.keys() {
+                let class = match InstrClass::from_name(key) {
+                    Some(class) => class,
+                    None => return Err(value_error("latency", key, "unknown instruction class")),
+                };
+                if matches!(class, InstrClass::Mul | InstrClass::Div) {
+                    warn!(
+                        "latency.{}: the M extension isn't supported, only custom instructions take this latency",
+                        key
+                    );
+                }
+                match config.get_integer("latency", key)? {
+                    Some(cycles) if cycles >= 1 && cycles <= i64::from(u32::MAX) => {
+                        table.set(class, cycles as u32)
+                    }
+                    _ => return Err(value_error("latency", key, "expected at least 1 cycle")),
+                }
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Number of execute cycles of an instruction class
+    pub fn get(&self, class: InstrClass) -> u32 {
+        self.cycles[class.index()]
+    }
+
+    /// Change the number of execute cycles of an instruction class
+    ///
+    /// # Arguments
+    /// * `class` => instruction class to change
+    /// * `cycles` => new latency, must be at least 1
+    pub fn set(&mut self, class: InstrClass, cycles: u32) {
+        assert!(cycles >= 1, "Latencies must be at least one cycle");
+        self.cycles[class.index()] = cycles;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classes() {
+        assert_eq!(InstrClass::Alu, InstrClass::from(RV32I::ADDI));
+        assert_eq!(InstrClass::Alu, InstrClass::from(RV32I::LUI));
+        assert_eq!(InstrClass::Load, InstrClass::from(RV32I::LHU));
+        assert_eq!(InstrClass::Store, InstrClass::from(RV32I::SB));
+        assert_eq!(InstrClass::Branch, InstrClass::from(RV32I::BGEU));
+        assert_eq!(InstrClass::Jump, InstrClass::from(RV32I::JALR));
+        assert_eq!(InstrClass::System, InstrClass::from(RV32I::CSRRSI));
+        assert_eq!(InstrClass::System, InstrClass::from(RV32I::ECALL));
+    }
+
+    #[test]
+    fn test_class_names() {
+        for class in InstrClass::ALL.iter() {
+            assert_eq!(Some(*class), InstrClass::from_name(class.name()));
+        }
+        assert_eq!(None, InstrClass::from_name("fpu"));
+    }
+
+    #[test]
+    fn test_default_table() {
+        let table = LatencyTable::new();
+        for class in InstrClass::ALL.iter() {
+            assert_eq!(1, table.get(*class));
+        }
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = Config::parse("[latency]\nmul = 3\ndiv = 34\nload = 2\n").unwrap();
+        let table = LatencyTable::from_config(&config).unwrap();
+
+        assert_eq!(3, table.get(InstrClass::Mul));
+        assert_eq!(34, table.get(InstrClass::Div));
+        assert_eq!(2, table.get(InstrClass::Load));
+        assert_eq!(1, table.get(InstrClass::Store));
+    }
+
+    #[test]
+    fn test_from_config_without_table() {
+        let config = Config::parse("[pipeline]\nstages = 3\n").unwrap();
+        assert_eq!(
+            LatencyTable::new(),
+            LatencyTable::from_config(&config).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_from_config_errors() {
+        let unknown = Config::parse("[latency]\nfpu = 4\n").unwrap();
+        assert!(LatencyTable::from_config(&unknown).is_err());
+
+        let zero = Config::parse("[latency]\nalu = 0\n").unwrap();
+        assert!(LatencyTable::from_config(&zero).is_err());
+
+        let string = Config::parse("[latency]\nalu = \"fast\"\n").unwrap();
+        assert!(LatencyTable::from_config(&string).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_zero() {
+        let mut table = LatencyTable::new();
+        table.set(InstrClass::Alu, 0);
+    }
+}
@@ -1,7 +1,151 @@
 //! A simulation program of the Adept processor. This simulation supports two
 //! configurations, a 1-stage configuration and a 3-stage configuration.
+//!
+//! The modules are split by Cargo features, so that a crate which only
+//! decodes instructions doesn't build the simulator:
+//!
+//! * `decoder` => the decoder, the instruction set tables and the ALU
+//! * `disasm` => the ELF files and the functions of their code
+//! * `sim` => the processor, its pipeline and memory models, and the
+//!   analyses of its runs
+//! * `devices` => the connections of the peripherals to the host, such as
+//!   the consoles of the serial port and the device tree
+//! * `gdb` => the Debug Module and the JTAG TAP, for OpenOCD and GDB
+//!
+//! `cli` builds the `adept` binary with all of them, and is the default.
+//!
+//! The simulator reports its progress through the `tracing` crate: a run of
+//! a `Cpu` is in a `run` span, and every cycle it simulates in a `step`
+//! span. Nothing is written until the program embedding the simulator
+//! installs a subscriber, such as the one of `tracing-subscriber`.
 
-pub mod alu;
+extern crate adept_core;
+#[cfg(feature = "serde")]
+extern crate serde;
+
+// The logging macros are used by the modules which follow
+#[cfg(feature = "sim")]
+#[macro_use]
+extern crate tracing;
+// Parser of the configuration files
+#[cfg(feature = "sim")]
+extern crate toml;
+
+#[cfg(feature = "decoder")]
+pub use adept_core::alu;
+pub use error::Error;
+#[cfg(feature = "sim")]
+pub mod analysis;
+#[cfg(feature = "sim")]
+pub mod benchmark;
+#[cfg(feature = "sim")]
+pub mod boot;
+#[cfg(feature = "disasm")]
+pub mod boundary;
+#[cfg(feature = "sim")]
+pub mod bus;
+#[cfg(feature = "sim")]
+pub mod cache;
+#[cfg(feature = "sim")]
+pub mod callstack;
+#[cfg(feature = "sim")]
+pub mod campaign;
+#[cfg(feature = "sim")]
+pub mod cfg;
+#[cfg(feature = "sim")]
+pub mod clint;
+#[cfg(feature = "sim")]
+pub mod coherence;
+#[cfg(feature = "sim")]
+pub mod config;
+#[cfg(feature = "sim")]
+pub mod coverage;
+#[cfg(feature = "sim")]
+pub mod cpu;
+#[cfg(feature = "sim")]
+pub mod crash;
+#[cfg(feature = "sim")]
+pub mod csr;
+#[cfg(feature = "sim")]
+pub mod custom;
+#[cfg(feature = "gdb")]
+pub mod debug_module;
+#[cfg(feature = "sim")]
+pub mod debugger;
+#[cfg(feature = "sim")]
+pub mod device;
+#[cfg(feature = "sim")]
+pub mod diagnostics;
+#[cfg(feature = "sim")]
+pub mod divergence;
+#[cfg(feature = "sim")]
+pub mod dma;
+#[cfg(feature = "disasm")]
+pub mod elf;
+#[cfg(feature = "sim")]
+pub mod env;
+pub mod error;
+#[cfg(feature = "sim")]
+pub mod expr;
+#[cfg(feature = "sim")]
+pub mod fault;
+#[cfg(feature = "devices")]
+pub mod fdt;
+#[cfg(feature = "sim")]
+pub mod fixture;
+#[cfg(feature = "sim")]
+pub mod flamegraph;
+#[cfg(feature = "sim")]
+pub mod frontend;
+#[cfg(feature = "sim")]
+pub mod generator;
+#[cfg(feature = "sim")]
+pub mod hart;
+#[cfg(feature = "sim")]
+pub mod heatmap;
+#[cfg(feature = "sim")]
+pub mod image;
+#[cfg(feature = "gdb")]
+pub mod jtag;
+#[cfg(feature = "sim")]
+pub mod latency;
+#[cfg(feature = "sim")]
+pub mod lockstep;
+#[cfg(feature = "sim")]
+pub mod machine;
+#[cfg(feature = "sim")]
+pub mod manifest;
+#[cfg(feature = "sim")]
 pub mod mem;
+#[cfg(feature = "sim")]
+pub mod pipeline;
+#[cfg(feature = "sim")]
+pub mod prefetch;
+#[cfg(feature = "sim")]
+pub mod profile;
+#[cfg(feature = "sim")]
 pub mod register_file;
+#[cfg(feature = "sim")]
+pub mod replay;
+#[cfg(feature = "sim")]
+pub mod report;
+#[cfg(feature = "decoder")]
 pub mod riscv;
+#[cfg(feature = "sim")]
+pub mod stats;
+#[cfg(feature = "sim")]
+pub mod stimulus;
+#[cfg(feature = "sim")]
+pub mod summary;
+#[cfg(feature = "sim")]
+pub mod taint;
+#[cfg(feature = "sim")]
+pub mod timeline;
+#[cfg(feature = "sim")]
+pub mod trace;
+#[cfg(feature = "sim")]
+pub mod tracepoint;
+#[cfg(feature = "sim")]
+pub mod trigger;
+#[cfg(feature = "sim")]
+pub mod uart;
@@ -0,0 +1,339 @@
+//! Lockstep co-simulation with an RTL testbench. The testbench sends the
+//! commit record of every instruction its core retires, a line each, and the
+//! simulator executes the same instruction on its hart and answers whether
+//! the records agree. A record is:
+//!
+//! * `PC INSN` => the instruction INSN retired at PC without writing a
+//!   register
+//! * `PC INSN xRD VALUE` => the same, writing VALUE to xRD
+//!
+//! with PC, INSN and VALUE in hexadecimal. The answers are `pass`,
+//! `mismatch: ...` with the expected values of the simulator and the ones of
+//! the testbench, or `stop: REASON` when the simulated program stops, which
+//! ends the session. Malformed records are answered with `error: ...`.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::hart::Hart;
+//! # use adept_lib::lockstep::Lockstep;
+//! # use adept_lib::mem::MemStoreOp;
+//! # use adept_lib::riscv::isa::RV32I;
+//! let mut hart = Hart::new();
+//! // addi a0, zero, 5; addi a0, a0, 1
+//! hart.memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 0, 0x0050_0513).unwrap();
+//! hart.memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 4, 0x0015_0513).unwrap();
+//! let records = b"00000000 00500513 x10 00000005\n00000004 00150513 x10 00000007\n";
+//! let mut answers = Vec::new();
+//! let summary = Lockstep::new().serve(&mut hart, &records[..], &mut answers).unwrap();
+//! assert_eq!((2, 1), (summary.commits, summary.mismatches));
+//! assert_eq!(
+//!     "pass\nmismatch: write to x10 of 0x00000007 instead of 0x00000006\n",
+//!     String::from_utf8(answers).unwrap()
+//! );
+//! ```
+use hart::{Hart, StopReason};
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, BufRead, Write};
+
+/// Commit record of a retired instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Commit {
+    pub pc: u32,
+    pub raw: u32,
+    /// Written register and value
+    pub write: Option<(u8, u32)>,
+}
+
+impl Commit {
+    /// Parse a commit record
+    ///
+    /// # Return Value
+    /// The record, or why it's malformed
+    pub fn parse(line: &str) -> Result<Commit, String> {
+        let hex = |field: &str| {
+            u32::from_str_radix(field, 16).map_err(|e| format!("invalid number {}: {}", field, e))
+        };
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let write = match fields.len() {
+            2 => None,
+            4 => {
+                let register = fields[2]
+                    .strip_prefix('x')
+                    .and_then(|index| index.parse::<u8>().ok())
+                    .filter(|index| *index < 32)
+                    .ok_or_else(|| format!("invalid register {}", fields[2]))?;
+                Some((register, hex(fields[3])?))
+            }
+            _ => return Err(format!("expected PC INSN [xRD VALUE], got {:?}", line)),
+        };
+        Ok(Commit {
+            pc: hex(fields[0])?,
+            raw: hex(fields[1])?,
+            write,
+        })
+    }
+}
+
+/// Difference between a commit record of the testbench and the simulator,
+/// with the expected value of the simulator and the one of the testbench
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    Pc {
+        expected: u32,
+        got: u32,
+    },
+    Instruction {
+        expected: u32,
+        got: u32,
+    },
+    Write {
+        expected: Option<(u8, u32)>,
+        got: Option<(u8, u32)>,
+    },
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Pc { expected, got } => {
+                write!(f, "pc 0x{:08x} instead of 0x{:08x}", got, expected)
+            }
+            Mismatch::Instruction { expected, got } => {
+                write!(f, "instruction 0x{:08x} instead of 0x{:08x}", got, expected)
+            }
+            Mismatch::Write {
+                expected: Some((register, expected)),
+                got: Some((other, got)),
+            } if register == other => write!(
+                f,
+                "write to x{} of 0x{:08x} instead of 0x{:08x}",
+                register, got, expected
+            ),
+            Mismatch::Write { expected, got } => write!(
+                f,
+                "{} instead of {}",
+                describe_write(*got),
+                describe_write(*expected)
+            ),
+        }
+    }
+}
+
+fn describe_write(write: Option<(u8, u32)>) -> String {
+    match write {
+        Some((register, value)) => format!("write to x{} of 0x{:08x}", register, value),
+        None => "no write".to_string(),
+    }
+}
+
+/// Counts of a lockstep session
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    /// Commit records checked
+    pub commits: u64,
+    /// Records which didn't match
+    pub mismatches: u64,
+    /// Why the simulated program stopped, if it did
+    pub stopped: Option<StopReason>,
+}
+
+/// Checker of the commit records of a testbench
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Lockstep {
+    summary: Summary,
+}
+
+impl Lockstep {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn summary(&self) -> Summary {
+        self.summary
+    }
+
+    /// Execute the next instruction of the hart and compare it with a commit
+    /// record. A register written with the value it had is no write. After a
+    /// mismatch the hart goes on with its own results.
+    ///
+    /// # Return Value
+    /// The first difference, if any, or why the program stopped
+    pub fn check(
+        &mut self,
+        hart: &mut Hart,
+        commit: &Commit,
+    ) -> Result<Option<Mismatch>, StopReason> {
+        let pc = hart.pc();
+        let raw = hart.memory().read_pc(pc);
+        let before = hart.registers().clone();
+        if let Err(reason) = hart.execute(pc, raw) {
+            self.summary.stopped = Some(reason);
+            return Err(reason);
+        }
+        self.summary.commits += 1;
+        let write = (1..32).find_map(|index| {
+            let value = hart.registers().read(index, 0).0;
+            if value != before.read(index, 0).0 {
+                Some((index, value as u32))
+            } else {
+                None
+            }
+        });
+        // The testbench may report writes of the value the register had
+        let reported = commit
+            .write
+            .filter(|(index, value)| *index != 0 && before.read(*index, 0).0 as u32 != *value);
+        let mismatch = if commit.pc != pc {
+            Some(Mismatch::Pc {
+                expected: pc,
+                got: commit.pc,
+            })
+        } else if commit.raw != raw {
+            Some(Mismatch::Instruction {
+                expected: raw,
+                got: commit.raw,
+            })
+        } else if reported != write {
+            Some(Mismatch::Write {
+                expected: write,
+                got: commit.write,
+            })
+        } else {
+            None
+        };
+        if mismatch.is_some() {
+            self.summary.mismatches += 1;
+        }
+        Ok(mismatch)
+    }
+
+    /// Check the commit records of a testbench until it closes the
+    /// connection or the program stops
+    ///
+    /// # Arguments
+    /// * `hart` => the simulated hart, ready to execute the first
+    ///   instruction
+    /// * `input` => the commit records
+    /// * `output` => where the answers go
+    pub fn serve<R: BufRead, W: Write>(
+        &mut self,
+        hart: &mut Hart,
+        input: R,
+        mut output: W,
+    ) -> io::Result<Summary> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let pc = hart.pc();
+            match Commit::parse(&line).map(|commit| self.check(hart, &commit)) {
+                Ok(Ok(None)) => writeln!(output, "pass")?,
+                Ok(Ok(Some(mismatch))) => {
+                    warn!("lockstep: pc 0x{:08x}: {}", pc, mismatch);
+                    writeln!(output, "mismatch: {}", mismatch)?
+                }
+                Ok(Err(reason)) => {
+                    writeln!(output, "stop: {}", reason)?;
+                    break;
+                }
+                Err(e) => writeln!(output, "error: {}", e)?,
+            }
+            output.flush()?;
+        }
+        output.flush()?;
+        Ok(self.summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem::MemStoreOp;
+    use riscv::isa::RV32I;
+
+    fn hart_with_program(program: &[u32]) -> Hart {
+        let mut hart = Hart::new();
+        for (index, word) in program.iter().enumerate() {
+            hart.memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), (index as u32) << 2, *word)
+                .unwrap();
+        }
+        hart
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Ok(Commit {
+                pc: 0x100,
+                raw: 0x0050_0513,
+                write: Some((10, 5))
+            }),
+            Commit::parse("00000100 00500513 x10 00000005")
+        );
+        assert_eq!(Ok(None), Commit::parse(" 4 13 ").map(|commit| commit.write));
+        assert!(Commit::parse("4 13 x32 0").is_err());
+        assert!(Commit::parse("4 13 x1").is_err());
+        assert!(Commit::parse("4 nop").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        // addi a0, zero, 5; addi a0, a0, 0; addi a1, zero, 1; nop
+        let mut hart = hart_with_program(&[0x0050_0513, 0x0005_0513, 0x0010_0593, 0x13]);
+        let mut lockstep = Lockstep::new();
+        let mut check = |line: &str| lockstep.check(&mut hart, &Commit::parse(line).unwrap());
+        assert_eq!(Ok(None), check("0 00500513 x10 5"));
+        // Writing the same value is no write
+        assert_eq!(Ok(None), check("4 00050513 x10 5"));
+        assert_eq!(
+            Ok(Some(Mismatch::Write {
+                expected: Some((11, 1)),
+                got: None
+            })),
+            check("8 00100593")
+        );
+        assert_eq!(
+            Ok(Some(Mismatch::Pc {
+                expected: 0xc,
+                got: 0x10
+            })),
+            check("10 00000013")
+        );
+        // The next instruction is invalid
+        assert_eq!(
+            Err(StopReason::InvalidInstruction { pc: 0x10, raw: 0 }),
+            check("10 00000013")
+        );
+    }
+
+    #[test]
+    fn test_serve() {
+        let mut hart = hart_with_program(&[0x0050_0513]);
+        let mut answers = Vec::new();
+        let summary = Lockstep::new()
+            .serve(
+                &mut hart,
+                &b"0 00500513 x11 5\n\nx\n4 13\n8 13\n"[..],
+                &mut answers,
+            )
+            .unwrap();
+        assert_eq!(
+            "mismatch: write to x11 of 0x00000005 instead of write to x10 of 0x00000005\n\
+             error: expected PC INSN [xRD VALUE], got \"x\"\n\
+             stop: invalid instruction 0x00000000 at pc 0x00000004\n",
+            String::from_utf8(answers).unwrap()
+        );
+        assert_eq!(
+            Summary {
+                commits: 1,
+                mismatches: 1,
+                stopped: Some(StopReason::InvalidInstruction { pc: 4, raw: 0 })
+            },
+            summary
+        );
+    }
+}
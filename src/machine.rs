@@ -0,0 +1,315 @@
+//! Processor running on a thread of its own, for applications which keep
+//! their main loop, such as a GUI or an async server. The machine owns the
+//! processor, and its handles can be cloned and sent to any thread to pause,
+//! resume and inspect it. The simulation runs in batches of cycles, and the
+//! handles get the processor in between.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::machine::Machine;
+//! # use adept_lib::mem::MemStoreOp;
+//! # use adept_lib::pipeline::PipelineConfig;
+//! # use adept_lib::riscv::isa::RV32I;
+//! # use std::thread;
+//! let mut cpu = Cpu::new(PipelineConfig::default());
+//! // addi a0, a0, 1; j -4
+//! cpu.hart_mut().memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 0, 0x0015_0513).unwrap();
+//! cpu.hart_mut().memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 4, 0xffdf_f06f).unwrap();
+//! let machine = Machine::spawn(cpu);
+//! let handle = machine.handle();
+//! handle.resume();
+//! let count = thread::spawn(move || {
+//!     handle.pause();
+//!     handle.inspect(|cpu| cpu.hart().registers().read(10, 0).0)
+//! })
+//! .join()
+//! .unwrap();
+//! // Nothing ran since the pause
+//! let cpu = machine.join();
+//! assert_eq!(count, Some(cpu.hart().registers().read(10, 0).0));
+//! ```
+use cpu::Cpu;
+use hart::StopReason;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+// Cycles simulated between two checks of the handles
+const BATCH_CYCLES: u64 = 1024;
+
+// State shared by the machine thread and the handles
+struct State {
+    // Taken back when the machine is joined
+    cpu: Option<Cpu>,
+    running: bool,
+    stopped: Option<StopReason>,
+    quit: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // Notified when running, stopped or quit change
+    changed: Condvar,
+    // Handles waiting for the state, which the machine thread lets through
+    // before its next batch
+    requests: AtomicUsize,
+}
+
+impl Shared {
+    // A panic of the simulation leaves the state usable
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Control of a machine from any thread
+#[derive(Clone)]
+pub struct MachineHandle {
+    shared: Arc<Shared>,
+}
+
+impl MachineHandle {
+    /// Let the program run, unless it stopped
+    pub fn resume(&self) {
+        let mut state = self.lock();
+        state.running = state.stopped.is_none() && state.cpu.is_some();
+        self.shared.changed.notify_all();
+    }
+
+    /// Pause the program, which is between two batches of cycles when this
+    /// returns
+    pub fn pause(&self) {
+        self.lock().running = false;
+        self.shared.changed.notify_all();
+    }
+
+    /// Check if the program runs
+    pub fn is_running(&self) -> bool {
+        self.lock().running
+    }
+
+    /// Why the program stopped, if it did
+    pub fn stopped(&self) -> Option<StopReason> {
+        self.lock().stopped
+    }
+
+    /// Wait until the program is paused or stops
+    ///
+    /// # Return Value
+    /// Why it stopped, or None if it was paused
+    pub fn wait(&self) -> Option<StopReason> {
+        let mut state = self.lock();
+        while state.running {
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        state.stopped
+    }
+
+    /// Look at the processor between two batches of cycles
+    ///
+    /// # Return Value
+    /// The result of the closure, or None once the machine was joined
+    pub fn inspect<T, F: FnOnce(&Cpu) -> T>(&self, inspect: F) -> Option<T> {
+        self.lock().cpu.as_ref().map(inspect)
+    }
+
+    /// Change the processor between two batches of cycles, such as its
+    /// registers or its memory
+    ///
+    /// # Return Value
+    /// The result of the closure, or None once the machine was joined
+    pub fn inspect_mut<T, F: FnOnce(&mut Cpu) -> T>(&self, inspect: F) -> Option<T> {
+        self.lock().cpu.as_mut().map(inspect)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.shared.requests.fetch_add(1, Ordering::SeqCst);
+        let state = self.shared.lock();
+        self.shared.requests.fetch_sub(1, Ordering::SeqCst);
+        state
+    }
+}
+
+/// Processor running on a thread of its own
+pub struct Machine {
+    handle: MachineHandle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Machine {
+    /// Move a processor to a new thread, where it waits to be resumed
+    pub fn spawn(cpu: Cpu) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                cpu: Some(cpu),
+                running: false,
+                stopped: None,
+                quit: false,
+            }),
+            changed: Condvar::new(),
+            requests: AtomicUsize::new(0),
+        });
+        let runner = shared.clone();
+        Machine {
+            handle: MachineHandle { shared },
+            thread: Some(thread::spawn(move || run(&runner))),
+        }
+    }
+
+    /// Handle controlling the machine, which can be sent to other threads
+    pub fn handle(&self) -> MachineHandle {
+        self.handle.clone()
+    }
+
+    /// Stop the thread of the machine and take the processor back. The
+    /// handles can't inspect it anymore.
+    pub fn join(mut self) -> Cpu {
+        self.shut_down();
+        let mut state = self.handle.lock();
+        state.running = false;
+        state.cpu.take().expect("the processor was already taken")
+    }
+
+    fn shut_down(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.handle.lock().quit = true;
+            self.handle.shared.changed.notify_all();
+            // A panic of the simulation was already reported by its hook
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Machine {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+// Pauses the machine when the simulation panics, so that nobody waits for
+// it forever
+struct PauseOnPanic<'a>(&'a Shared);
+
+impl<'a> Drop for PauseOnPanic<'a> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.lock().running = false;
+            self.0.changed.notify_all();
+        }
+    }
+}
+
+// Simulate batches of cycles while the machine runs, until it quits
+fn run(shared: &Shared) {
+    let _pause = PauseOnPanic(shared);
+    loop {
+        {
+            let mut state = shared.lock();
+            while !state.running && !state.quit {
+                state = shared
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+            if state.quit {
+                return;
+            }
+            let ticked = match state.cpu {
+                Some(ref mut cpu) => cpu.tick(BATCH_CYCLES),
+                None => return,
+            };
+            if let Err(reason) = ticked {
+                state.stopped = Some(reason);
+                state.running = false;
+                shared.changed.notify_all();
+            }
+        }
+        // Let the handles waiting for the state have it before the next
+        // batch
+        while shared.requests.load(Ordering::SeqCst) > 0 {
+            thread::yield_now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem::MemStoreOp;
+    use pipeline::PipelineConfig;
+    use riscv::isa::RV32I;
+
+    // Count a0 up to a limit in a1, then stop at an invalid instruction
+    fn counter(limit: u32) -> Cpu {
+        let program = [
+            0x0015_0513, // loop: addi a0, a0, 1
+            0xfeb5_4ee3, // blt a0, a1, loop
+        ];
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        for (index, word) in program.iter().enumerate() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), 4 * index as u32, *word)
+                .unwrap();
+        }
+        cpu.hart_mut().registers_mut().write(11, limit as i32);
+        cpu
+    }
+
+    #[test]
+    fn test_machine_is_send() {
+        fn send<T: Send + Sync>(_: &T) {}
+        let machine = Machine::spawn(counter(1));
+        send(&machine.handle());
+    }
+
+    #[test]
+    fn test_run_to_the_end() {
+        let machine = Machine::spawn(counter(100_000));
+        let handle = machine.handle();
+        assert!(!handle.is_running());
+        assert_eq!(None, handle.wait());
+
+        let remote = handle.clone();
+        thread::spawn(move || remote.resume()).join().unwrap();
+        assert_eq!(
+            Some(StopReason::InvalidInstruction { pc: 8, raw: 0 }),
+            handle.wait()
+        );
+        assert!(!handle.is_running());
+        // A stopped program can't be resumed
+        handle.resume();
+        assert!(!handle.is_running());
+
+        let cpu = machine.join();
+        assert_eq!((100_000, 0), cpu.hart().registers().read(10, 0));
+        assert_eq!(None, handle.inspect(|cpu| cpu.stats().cycles));
+    }
+
+    #[test]
+    fn test_pause_and_inspect() {
+        let machine = Machine::spawn(counter(1 << 30));
+        let handle = machine.handle();
+        handle.resume();
+        handle.pause();
+        let count =
+            |handle: &MachineHandle| handle.inspect(|cpu| cpu.hart().registers().read(10, 0).0);
+        let paused = count(&handle);
+        assert_eq!(paused, count(&handle));
+
+        // Skip most of the loop while paused
+        handle.inspect_mut(|cpu| cpu.hart_mut().registers_mut().write(10, (1 << 30) - 10));
+        handle.resume();
+        assert!(handle.wait().is_some());
+        assert_eq!(Some(1 << 30), count(&handle));
+    }
+}
@@ -0,0 +1,274 @@
+//! Run manifests, which record what a run depends on so that it can be
+//! repeated exactly: the version of the simulator, the seed of the random
+//! numbers, the command line and a hash of every input file. Sources which
+//! the seed doesn't control, such as a wall clock timeout or a console fed by
+//! a person, are listed as well, since a run using them may not repeat.
+//!
+//! Manifests are written as configuration files:
+//!
+//! ```text
+//! [run]
+//! version = "0.1.0"
+//! seed = 42
+//! args = ["--seed", "42", "program.elf"]
+//! nondeterministic = []
+//!
+//! [inputs]
+//! roles = ["program"]
+//! paths = ["program.elf"]
+//! hashes = ["af63bd4c8601b7df"]
+//! ```
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::manifest::Manifest;
+//! let mut manifest = Manifest::new(42, vec!["program.elf".to_string()]);
+//! manifest.add_input("program", "program.elf", b"\x7fELF");
+//! let mut text = Vec::new();
+//! manifest.write(&mut text).unwrap();
+//! let parsed = Manifest::parse(&String::from_utf8(text).unwrap()).unwrap();
+//! assert_eq!(manifest, parsed);
+//! ```
+use config::{value_error, Config, ConfigError, Value};
+
+use std::fs;
+use std::io::{self, Write};
+
+/// Hash of the contents of a file, a 64 bit FNV-1a
+pub fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// File read by a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Input {
+    /// What the file is for, such as `program` or `config`
+    pub role: String,
+    pub path: String,
+    pub hash: u64,
+}
+
+/// What a run depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// Version of the simulator
+    pub version: String,
+    pub seed: u64,
+    /// Command line arguments, without the name of the simulator
+    pub args: Vec<String>,
+    pub inputs: Vec<Input>,
+    /// Options making the run depend on something else than its inputs
+    pub nondeterministic: Vec<String>,
+}
+
+impl Manifest {
+    /// Create the manifest of a run of this version of the simulator
+    pub fn new(seed: u64, args: Vec<String>) -> Self {
+        Manifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            seed,
+            args,
+            inputs: Vec::new(),
+            nondeterministic: Vec::new(),
+        }
+    }
+
+    /// Record an input file from its contents
+    pub fn add_input(&mut self, role: &str, path: &str, data: &[u8]) {
+        self.inputs.push(Input {
+            role: role.to_string(),
+            path: path.to_string(),
+            hash: hash(data),
+        });
+    }
+
+    /// Read and record an input file
+    pub fn add_file(&mut self, role: &str, path: &str) -> io::Result<()> {
+        let data = fs::read(path)?;
+        self.add_input(role, path, &data);
+        Ok(())
+    }
+
+    /// Record an option which makes the run depend on something else than
+    /// its inputs
+    pub fn add_nondeterministic(&mut self, option: &str) {
+        self.nondeterministic.push(option.to_string());
+    }
+
+    /// Inputs whose files changed since the run, or can't be read anymore
+    pub fn changed_inputs(&self) -> Vec<&Input> {
+        self.inputs
+            .iter()
+            .filter(|input| fs::read(&input.path).map(|data| hash(&data)).ok() != Some(input.hash))
+            .collect()
+    }
+
+    /// Write the manifest as a configuration file
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let roles: Vec<&str> = self
+            .inputs
+            .iter()
+            .map(|input| input.role.as_str())
+            .collect();
+        let paths: Vec<&str> = self
+            .inputs
+            .iter()
+            .map(|input| input.path.as_str())
+            .collect();
+        let hashes: Vec<String> = self
+            .inputs
+            .iter()
+            .map(|input| format!("{:016x}", input.hash))
+            .collect();
+        writeln!(out, "[run]")?;
+        writeln!(out, "version = {}", quote(&self.version))?;
+        // The integers of the configuration files are signed, the largest
+        // seeds are written as negative numbers
+        writeln!(out, "seed = {}", self.seed as i64)?;
+        writeln!(out, "args = {}", array(&self.args))?;
+        writeln!(out, "nondeterministic = {}", array(&self.nondeterministic))?;
+        writeln!(out)?;
+        writeln!(out, "[inputs]")?;
+        writeln!(out, "roles = {}", array(&roles))?;
+        writeln!(out, "paths = {}", array(&paths))?;
+        writeln!(out, "hashes = {}", array(&hashes))
+    }
+
+    /// Read and parse a manifest file
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        Manifest::from_config(&Config::from_file(path)?)
+    }
+
+    /// Parse a manifest
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        Manifest::from_config(&Config::parse(text)?)
+    }
+
+    fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let seed = match config.get("run", "seed") {
+            Some(Value::Integer(seed)) => *seed as u64,
+            _ => return Err(value_error("run", "seed", "expected an integer")),
+        };
+        let roles = strings(config, "inputs", "roles")?;
+        let paths = strings(config, "inputs", "paths")?;
+        let hashes = strings(config, "inputs", "hashes")?;
+        if roles.len() != paths.len() || roles.len() != hashes.len() {
+            return Err(value_error(
+                "inputs",
+                "hashes",
+                "expected as many roles, paths and hashes",
+            ));
+        }
+        let mut inputs = Vec::new();
+        for ((role, path), text) in roles.into_iter().zip(paths).zip(hashes) {
+            let hash = u64::from_str_radix(&text, 16)
+                .map_err(|_| value_error("inputs", "hashes", &format!("invalid hash {}", text)))?;
+            inputs.push(Input { role, path, hash });
+        }
+        Ok(Manifest {
+            version: config
+                .get_string("run", "version")?
+                .unwrap_or_default()
+                .to_string(),
+            seed,
+            args: strings(config, "run", "args")?,
+            inputs,
+            nondeterministic: strings(config, "run", "nondeterministic")?,
+        })
+    }
+}
+
+// Read an array of strings, empty if it's missing
+fn strings(config: &Config, table: &str, key: &str) -> Result<Vec<String>, ConfigError> {
+    match config.get(table, key) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(text) => Ok(text.clone()),
+                _ => Err(value_error(table, key, "expected an array of strings")),
+            })
+            .collect(),
+        Some(_) => Err(value_error(table, key, "expected an array of strings")),
+    }
+}
+
+// Write a basic string of the configuration files
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn array<S: AsRef<str>>(values: &[S]) -> String {
+    let quoted: Vec<String> = values.iter().map(|value| quote(value.as_ref())).collect();
+    format!("[{}]", quoted.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(0xcbf2_9ce4_8422_2325, hash(b""));
+        assert_eq!(0xaf63_dc4c_8601_ec8c, hash(b"a"));
+        assert_ne!(hash(b"ab"), hash(b"ba"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut manifest = Manifest::new(
+            u64::MAX,
+            vec![
+                "--uart".to_string(),
+                "tcp:4000".to_string(),
+                "a \"b\"\\c.elf".to_string(),
+            ],
+        );
+        manifest.add_input("program", "a \"b\"\\c.elf", b"program");
+        manifest.add_input("config", "pipeline.toml", b"[pipeline]\n");
+        manifest.add_nondeterministic("uart");
+        let mut text = Vec::new();
+        manifest.write(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("seed = -1\n"));
+        assert_eq!(manifest, Manifest::parse(&text).unwrap());
+
+        assert!(Manifest::parse("[run]\nseed = \"1\"\n").is_err());
+        assert!(Manifest::parse("[run]\nseed = 1\n[inputs]\nroles = [\"program\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_changed_inputs() {
+        let path = std::env::temp_dir().join(format!("adept-manifest-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, b"one").unwrap();
+        let mut manifest = Manifest::new(0, Vec::new());
+        manifest.add_file("program", path).unwrap();
+        manifest.add_input("load", "/nonexistent/adept", b"");
+        assert_eq!(
+            vec!["/nonexistent/adept"],
+            paths(&manifest.changed_inputs())
+        );
+        fs::write(path, b"two").unwrap();
+        assert_eq!(2, manifest.changed_inputs().len());
+        fs::remove_file(path).unwrap();
+    }
+
+    fn paths<'a>(inputs: &[&'a Input]) -> Vec<&'a str> {
+        inputs.iter().map(|input| input.path.as_str()).collect()
+    }
+}
@@ -1,7 +1,8 @@
 //! This module contains the necessary methods and struct to operate over a
-//! memory. The memory by default is created with an address space of 21 bits,
-//! which means the memory should use 8MB. You should consider storing this in
-//! the heap instead of the stack.
+//! memory. The memory by default is created with an address space of 23 bits,
+//! which means the memory holds up to 8MB, and up to 32 bits on request. Only
+//! the pages which are written take space on the host. You should consider
+//! storing this in the heap instead of the stack.
 //!
 //! # Example:
 //!
@@ -9,37 +10,413 @@
 //! # use adept_lib::mem::{Memory, MemStoreOp, MemLoadOp};
 //! # use adept_lib::riscv::isa::RV32I;
 //! let mut my_mem = Box::new(Memory::new());
-//! # my_mem.write_data(&MemStoreOp::from(RV32I::SW), 0x0040_babc, 0xdead_beef);
+//! # my_mem.write_data(&MemStoreOp::from(RV32I::SW), 0x0040_babc, 0xdead_beef).unwrap();
 //! // To read the PC use the read_pc method
 //! assert_eq!(0xdead_beef, my_mem.read_pc(0x0040_babc));
 //! // To store data use the write_data method. You can use the object returned
 //! // by the decoder directly in the method.
-//! my_mem.write_data(&MemStoreOp::from(RV32I::SW), 0x0040_babc, 0xdead_babe);
+//! my_mem.write_data(&MemStoreOp::from(RV32I::SW), 0x0040_babc, 0xdead_babe).unwrap();
 //! // To load data use the read_data method
-//! assert_eq!(0xdead_babe, my_mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc));
+//! assert_eq!(Ok(0xdead_babe_u32 as i32), my_mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc));
+//! // Accesses which don't fit in a word fail
+//! let error = my_mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babe).unwrap_err();
+//! assert_eq!("misaligned load at 0x0040babe (4 bytes)", error.to_string());
 //! ```
+use error::Error;
 use riscv::isa::RV32I;
 
-/// Memory is represented has 4 banks of 1 byte each.
-#[derive(Default, Debug)]
+use std::error::Error as StdError;
+use std::fmt;
+
+/// Kinds of memory accesses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemAccess {
+    /// Instruction fetch
+    Fetch,
+    Load,
+    Store,
+}
+
+impl MemAccess {
+    pub fn name(self) -> &'static str {
+        match self {
+            MemAccess::Fetch => "fetch",
+            MemAccess::Load => "load",
+            MemAccess::Store => "store",
+        }
+    }
+}
+
+/// Reasons for a memory access to fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemErrorKind {
+    /// The access crosses the boundary of a word, and misaligned accesses
+    /// aren't allowed
+    Misaligned,
+    /// The access is not allowed, such as an invalid operation or a store
+    /// to the read-only region
+    AccessFault,
+}
+
+/// Failed memory access, with the faulting address and the number of bytes
+/// accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemError {
+    pub kind: MemErrorKind,
+    pub addr: u32,
+    pub size: u32,
+    pub access: MemAccess,
+}
+
+impl fmt::Display for MemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            MemErrorKind::Misaligned => write!(f, "misaligned {}", self.access.name())?,
+            MemErrorKind::AccessFault => write!(f, "{} access fault", self.access.name())?,
+        }
+        write!(f, " at 0x{:08x} ({} bytes)", self.addr, self.size)
+    }
+}
+
+impl StdError for MemError {}
+
+// Read-only region of the memory, whose bytes are kept apart from the pages
+#[derive(Debug, Clone)]
+struct Rom {
+    // Offset of the first byte in the memory
+    base: usize,
+    bytes: Vec<u8>,
+}
+
+impl Rom {
+    // Byte at an offset of the memory, if it belongs to the ROM. The ROM
+    // wraps around the memory like the addresses do.
+    fn get(&self, offset: usize, memory_size: usize) -> Option<u8> {
+        let index = (offset + memory_size - self.base) % memory_size;
+        self.bytes.get(index).cloned()
+    }
+
+    fn get_mut(&mut self, offset: usize, memory_size: usize) -> Option<&mut u8> {
+        let index = (offset + memory_size - self.base) % memory_size;
+        self.bytes.get_mut(index)
+    }
+}
+
+// Scratchpad memory at its own addresses, outside of the pages and their
+// mirrors
+#[derive(Debug, Clone)]
+struct Scratchpad {
+    base: u32,
+    bytes: Vec<u8>,
+}
+
+impl Scratchpad {
+    // Index of the byte at an address, if it belongs to the scratchpad
+    fn index(&self, addr: u32) -> Option<usize> {
+        let index = addr.wrapping_sub(self.base) as usize;
+        if index < self.bytes.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+// Bytes in a page of the memory
+const PAGE_SIZE: usize = 1 << 12;
+
+// Page of the memory, allocated on its first write
+type Page = Box<[u8; PAGE_SIZE]>;
+
+// Bits of a page telling which of its bytes were written, one per byte
+type WrittenPage = Box<[u8; PAGE_SIZE / 8]>;
+
+/// Number of address bits of the memory created by `Memory::new`, 8 MiB
+pub const DEFAULT_ADDRESS_BITS: u32 = 23;
+/// Fewest address bits of a memory, a single page
+pub const MIN_ADDRESS_BITS: u32 = 12;
+/// Most address bits of a memory, the whole 4 GiB address space
+pub const MAX_ADDRESS_BITS: u32 = 32;
+
+/// Memory is split in pages of 4 KiB which are only allocated when they are
+/// first written, so the untouched part of a large memory takes no space on
+/// the host. Inside a page, the bytes of a word are its 4 banks.
+#[derive(Debug, Clone)]
 pub struct Memory {
-    bank_0: Vec<u8>,
-    bank_1: Vec<u8>,
-    bank_2: Vec<u8>,
-    bank_3: Vec<u8>,
+    pages: Vec<Option<Page>>,
+    // Bytes read from pages which were never written, by bank
+    pattern: [u8; 4],
+    // Addresses wrap around after this many bits
+    address_bits: u32,
+    // Bytes written in each page, when writes are tracked
+    written: Option<Vec<Option<WrittenPage>>>,
+    // Set if accesses which cross a word are split instead of failing
+    misaligned_access: bool,
+    rom: Option<Rom>,
+    scratchpad: Option<Scratchpad>,
 }
 
-impl Memory {
-    const MEMORY_ADDR_SIZE: u32 = 21;
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    /// Create memory component. Memory is byte addressable, little endian, and
-    /// has a bank per byte.
+impl Memory {
+    /// Create memory component with DEFAULT_ADDRESS_BITS. Memory is byte
+    /// addressable, little endian, and has a bank per byte.
     pub fn new() -> Self {
+        Self::with_size(DEFAULT_ADDRESS_BITS)
+    }
+
+    /// Create memory component of `1 << address_bits` bytes. Only the pages
+    /// which get written are allocated, so even a memory covering the whole
+    /// address space starts empty.
+    ///
+    /// # Arguments
+    /// * `address_bits` => between MIN_ADDRESS_BITS and MAX_ADDRESS_BITS
+    ///
+    /// # Return Value
+    /// The memory, or an error if the number of address bits is out of range
+    pub fn with_address_bits(address_bits: u32) -> Result<Self, Error> {
+        if !(MIN_ADDRESS_BITS..=MAX_ADDRESS_BITS).contains(&address_bits) {
+            return Err(Error::invalid(
+                "number of address bits",
+                format!(
+                    "{}, expected {} to {}",
+                    address_bits, MIN_ADDRESS_BITS, MAX_ADDRESS_BITS
+                ),
+            ));
+        }
+        Ok(Self::with_size(address_bits))
+    }
+
+    // Memory of an address width in range
+    fn with_size(address_bits: u32) -> Self {
+        let pages = 1 << (address_bits - MIN_ADDRESS_BITS);
         Memory {
-            bank_0: vec![0; 1 << Self::MEMORY_ADDR_SIZE],
-            bank_1: vec![0; 1 << Self::MEMORY_ADDR_SIZE],
-            bank_2: vec![0; 1 << Self::MEMORY_ADDR_SIZE],
-            bank_3: vec![0; 1 << Self::MEMORY_ADDR_SIZE],
+            pages: (0..pages).map(|_| None).collect(),
+            pattern: [0; 4],
+            address_bits,
+            written: None,
+            misaligned_access: false,
+            rom: None,
+            scratchpad: None,
+        }
+    }
+
+    /// Fill the whole memory with a pattern, so reads of memory which was
+    /// never written stand out. The memory is not marked as written.
+    ///
+    /// # Arguments
+    /// * `pattern` => word repeated over the memory
+    pub fn fill(&mut self, pattern: u32) {
+        self.pattern = pattern.to_le_bytes();
+        for page in &mut self.pages {
+            *page = None;
+        }
+        if let Some(ref mut scratchpad) = self.scratchpad {
+            for (index, byte) in scratchpad.bytes.iter_mut().enumerate() {
+                *byte = self.pattern[index & 3];
+            }
+        }
+    }
+
+    /// Start tracking which bytes are written. Bytes written before are
+    /// considered uninitialized.
+    pub fn track_writes(&mut self) {
+        self.written = Some((0..self.pages.len()).map(|_| None).collect());
+    }
+
+    /// Check if every byte read by a load was written before. Always true
+    /// when writes are not tracked, and for the scratchpad.
+    ///
+    /// # Arguments
+    /// * `op` => read operation (load byte, half, or word)
+    /// * `addr` => memory address to read from
+    pub fn is_initialized(&self, op: &MemLoadOp, addr: u32) -> bool {
+        let written = match self.written {
+            Some(ref written) => written,
+            None => return true,
+        };
+        (0..op.size()).all(|offset| {
+            let byte = addr.wrapping_add(offset);
+            let index = self.byte_offset(byte) % PAGE_SIZE;
+            let page_written = match written[self.byte_offset(byte) / PAGE_SIZE] {
+                Some(ref page) => page[index >> 3] & 1 << (index & 7) != 0,
+                None => false,
+            };
+            page_written || self.is_read_only(byte, 1) || self.in_scratchpad(byte, 1)
+        })
+    }
+
+    /// Allow loads and stores which cross the boundary of a word, as cores
+    /// with hardware support for misaligned accesses do. They are assembled
+    /// from the bytes of both words instead of failing.
+    pub fn set_misaligned_access(&mut self, enabled: bool) {
+        self.misaligned_access = enabled;
+    }
+
+    pub fn misaligned_access(&self) -> bool {
+        self.misaligned_access
+    }
+
+    /// Check if an access crosses the boundary of the word holding its first
+    /// byte, which takes two accesses when misaligned accesses are allowed
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first byte
+    /// * `size` => number of bytes accessed
+    pub fn crosses_word(addr: u32, size: u32) -> bool {
+        (addr & 3) + size > 4
+    }
+
+    /// Make a region read-only, backed by the bytes of the loaded image
+    /// instead of the pages. Loads and fetches read the bytes, while stores
+    /// to the region fail with an access fault. The region counts as
+    /// written.
+    ///
+    /// # Arguments
+    /// * `base` => address of the first byte
+    /// * `bytes` => contents of the region, no bigger than the memory
+    pub fn map_rom(&mut self, base: u32, bytes: Vec<u8>) {
+        self.rom = Some(Rom {
+            base: self.byte_offset(base),
+            bytes,
+        });
+    }
+
+    /// Check if any byte of an access belongs to the read-only region. The
+    /// scratchpad hides the mirrors of the region under it.
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first byte
+    /// * `size` => number of bytes accessed
+    pub fn is_read_only(&self, addr: u32, size: u32) -> bool {
+        let memory_size = self.size() as usize;
+        match self.rom {
+            Some(ref rom) => (0..size).any(|offset| {
+                let byte = addr.wrapping_add(offset);
+                rom.get(self.byte_offset(byte), memory_size).is_some()
+                    && !self.in_scratchpad(byte, 1)
+            }),
+            None => false,
+        }
+    }
+
+    /// Map a scratchpad, a memory apart from the main one which answers at
+    /// its own addresses. Accesses to the region reach the scratchpad
+    /// instead of the main memory, and it starts filled with the pattern.
+    ///
+    /// # Arguments
+    /// * `base` => address of the first byte
+    /// * `size` => size in bytes, the region must fit in the address space
+    ///
+    /// # Return Value
+    /// An error if the region doesn't fit, in which case nothing is mapped
+    pub fn map_scratchpad(&mut self, base: u32, size: u32) -> Result<(), Error> {
+        if u64::from(base) + u64::from(size) > 1 << 32 {
+            return Err(Error::invalid(
+                "scratchpad",
+                format!("0x{:08x}:{}, past the address space", base, size),
+            ));
+        }
+        let pattern = self.pattern;
+        self.scratchpad = Some(Scratchpad {
+            base,
+            bytes: (0..size as usize).map(|index| pattern[index & 3]).collect(),
+        });
+        Ok(())
+    }
+
+    /// Check if any byte of an access belongs to the scratchpad
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first byte
+    /// * `size` => number of bytes accessed
+    pub fn in_scratchpad(&self, addr: u32, size: u32) -> bool {
+        match self.scratchpad {
+            Some(ref scratchpad) => {
+                (0..size).any(|offset| scratchpad.index(addr.wrapping_add(offset)).is_some())
+            }
+            None => false,
+        }
+    }
+
+    /// Check if anything was loaded or stored at an address: a written page,
+    /// the ROM or the scratchpad. Addresses past the end of the memory,
+    /// which wrap around, are never mapped.
+    pub fn is_mapped(&self, addr: u32) -> bool {
+        if self.in_scratchpad(addr, 1) || self.is_read_only(addr, 1) {
+            return true;
+        }
+        u64::from(addr) < self.size() && self.pages[self.byte_offset(addr) / PAGE_SIZE].is_some()
+    }
+
+    /// Invert one bit of the memory, as a soft error would. The bit is
+    /// flipped wherever the byte lives, the read-only region included, and
+    /// the byte isn't marked as written.
+    ///
+    /// # Arguments
+    /// * `addr` => address of the byte
+    /// * `bit` => bit of the byte, below 8
+    pub fn flip_bit(&mut self, addr: u32, bit: u32) {
+        let mask = 1 << bit;
+        if let Some(ref mut scratchpad) = self.scratchpad {
+            if let Some(index) = scratchpad.index(addr) {
+                scratchpad.bytes[index] ^= mask;
+                return;
+            }
+        }
+        let offset = self.byte_offset(addr);
+        let memory_size = self.size() as usize;
+        if let Some(ref mut rom) = self.rom {
+            if let Some(byte) = rom.get_mut(offset, memory_size) {
+                *byte ^= mask;
+                return;
+            }
+        }
+        self.page_mut(offset)[offset % PAGE_SIZE] ^= mask;
+    }
+
+    /// Size of the memory in bytes. Addresses wrap around at this size.
+    pub fn size(&self) -> u64 {
+        1 << self.address_bits
+    }
+
+    /// Bytes of the host taken by the pages which were written
+    pub fn allocated_size(&self) -> u64 {
+        let pages = self.pages.iter().filter(|page| page.is_some()).count();
+        (pages * PAGE_SIZE) as u64
+    }
+
+    /// Address and contents of the pages which were written, in increasing
+    /// order. The ROM and the scratchpad aren't included.
+    pub fn pages(&self) -> Vec<(u32, &[u8])> {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(index, page)| {
+                page.as_ref()
+                    .map(|page| ((index * PAGE_SIZE) as u32, &page[..]))
+            })
+            .collect()
+    }
+
+    /// Replace the contents of the pages by the ones of `pages`, leaving
+    /// the others unwritten
+    ///
+    /// # Arguments
+    /// * `pages` => address and contents of pages, as returned by `pages`
+    pub fn set_pages(&mut self, pages: &[(u32, Vec<u8>)]) {
+        for page in &mut self.pages {
+            *page = None;
+        }
+        for (base, bytes) in pages {
+            let offset = self.byte_offset(*base);
+            let index = offset % PAGE_SIZE;
+            let length = bytes.len().min(PAGE_SIZE - index);
+            self.page_mut(offset)[index..index + length].copy_from_slice(&bytes[..length]);
         }
     }
 
@@ -52,77 +429,146 @@ impl Memory {
     /// The instruction in the selected address
     pub fn read_pc(&self, pc: u32) -> u32 {
         // Memory has a 32-bit address space but here we only use
-        // MEMORY_ADDR_SIZE bits to address the memory. Thus, we are going to
+        // address_bits bits to address the memory. Thus, we are going to
         // mask the pc address.
-        let masked_pc = Self::mask_addr(pc >> 2);
+        let masked_pc = self.mask_addr(pc >> 2);
+        if self.rom.is_some() || self.scratchpad.is_some() {
+            return (0..4).fold(0, |word, bank| {
+                let byte = self.read_byte((pc & !3) | bank);
+                word | u32::from(byte) << (bank << 3)
+            });
+        }
 
         // Concatenate addresses
-        let final_data: u32 = u32::from(self.bank_3[masked_pc]) << 24
-            | u32::from(self.bank_2[masked_pc]) << 16
-            | u32::from(self.bank_1[masked_pc]) << 8
-            | u32::from(self.bank_0[masked_pc]);
-
-        final_data
+        let offset = masked_pc << 2;
+        match self.pages[offset / PAGE_SIZE] {
+            Some(ref page) => {
+                let index = offset % PAGE_SIZE;
+                u32::from_le_bytes([
+                    page[index],
+                    page[index + 1],
+                    page[index + 2],
+                    page[index + 3],
+                ])
+            }
+            None => u32::from_le_bytes(self.pattern),
+        }
     }
 
-    // Mask address to be read or written depending on MEMORY_ADDR_SIZE.
+    // Mask a word address to be read or written depending on address_bits.
     //
     // # Arguments
     // * `addr` => address to mask
     //
     // # Return Value
     // Masked address converted to usize as to ease Vec addressing
-    fn mask_addr(addr: u32) -> usize {
-        (addr % (1 << Self::MEMORY_ADDR_SIZE)) as usize
+    fn mask_addr(&self, addr: u32) -> usize {
+        (u64::from(addr) % (self.size() >> 2)) as usize
+    }
+
+    // Offset of the byte at an address from the start of the memory
+    fn byte_offset(&self, addr: u32) -> usize {
+        (u64::from(addr) % self.size()) as usize
+    }
+
+    // Page holding a byte, which is allocated filled with the pattern if it
+    // was never written
+    fn page_mut(&mut self, offset: usize) -> &mut Page {
+        let pattern = self.pattern;
+        self.pages[offset / PAGE_SIZE].get_or_insert_with(|| {
+            let mut page = Box::new([0; PAGE_SIZE]);
+            for (index, byte) in page.iter_mut().enumerate() {
+                *byte = pattern[index & 3];
+            }
+            page
+        })
     }
 
     // Write some garbage data to memory. This is only used in tests, please
     // ignore.
     fn __write_garbage(&mut self, data: u32, addr: u32) {
-        let data_0 = (data & 0x0000_00ff) as u8;
-        let data_1 = ((data & 0x0000_ff00) >> 8) as u8;
-        let data_2 = ((data & 0x00ff_0000) >> 16) as u8;
-        let data_3 = ((data & 0xff00_0000) >> 24) as u8;
+        let offset = self.mask_addr(addr >> 2) << 2;
+        let index = offset % PAGE_SIZE;
+        self.page_mut(offset)[index..index + 4].copy_from_slice(&data.to_le_bytes());
+    }
 
-        let masked_addr = Self::mask_addr(addr >> 2);
+    // Check that an access fits in the word holding its first byte, unless
+    // misaligned accesses are allowed
+    //
+    // # Arguments
+    // * `addr` => address of the first byte
+    // * `size` => number of bytes accessed
+    // * `access` => kind of access, for the error
+    fn check_alignment(&self, addr: u32, size: u32, access: MemAccess) -> Result<(), MemError> {
+        if Self::crosses_word(addr, size) && !self.misaligned_access {
+            Err(MemError {
+                kind: MemErrorKind::Misaligned,
+                addr,
+                size,
+                access,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    // Read the byte at an address, from the scratchpad or the banks
+    fn read_byte(&self, addr: u32) -> u8 {
+        if let Some(ref scratchpad) = self.scratchpad {
+            if let Some(index) = scratchpad.index(addr) {
+                return scratchpad.bytes[index];
+            }
+        }
+        self.get_data(self.mask_addr(addr >> 2), (addr & 3) as u8)
+    }
 
-        self.bank_0[masked_addr] = data_0;
-        self.bank_1[masked_addr] = data_1;
-        self.bank_2[masked_addr] = data_2;
-        self.bank_3[masked_addr] = data_3;
+    // Write the byte at an address, to the scratchpad or the banks
+    fn write_byte(&mut self, addr: u32, data: u8) {
+        if let Some(ref mut scratchpad) = self.scratchpad {
+            if let Some(index) = scratchpad.index(addr) {
+                scratchpad.bytes[index] = data;
+                return;
+            }
+        }
+        let masked_addr = self.mask_addr(addr >> 2);
+        self.put_data(masked_addr, (addr & 3) as u8, data);
     }
 
     // Get data from one memory bank at a specific address
     //
     // # Arguments
-    // * `addr` => address to read data from
-    // * `select_bank` => ...
+    // * `addr` => word address to read data from
+    // * `select_bank` => byte of the word
     //
     // # Return Value
     // Value selected from the memory bank at the specified address
     fn get_data(&self, addr: usize, select_bank: u8) -> u8 {
-        match select_bank {
-            0 => self.bank_0[addr],
-            1 => self.bank_1[addr],
-            2 => self.bank_2[addr],
-            3 => self.bank_3[addr],
-            _ => panic!("LSBs in read address is greater than 3"),
+        let offset = addr << 2 | select_bank as usize;
+        if let Some(ref rom) = self.rom {
+            if let Some(byte) = rom.get(offset, self.size() as usize) {
+                return byte;
+            }
+        }
+        match self.pages[offset / PAGE_SIZE] {
+            Some(ref page) => page[offset % PAGE_SIZE],
+            None => self.pattern[select_bank as usize],
         }
     }
 
     // Write data to one memory bank at a specific address
     //
     // # Arguments
-    // * `addr` => address to read data from
-    // * `select_bank` => ...
+    // * `addr` => word address to write data to
+    // * `select_bank` => byte of the word
     // * `data` => data to write
     fn put_data(&mut self, addr: usize, select_bank: u8, data: u8) {
-        match select_bank {
-            0 => self.bank_0[addr] = data,
-            1 => self.bank_1[addr] = data,
-            2 => self.bank_2[addr] = data,
-            3 => self.bank_3[addr] = data,
-            _ => panic!("LSBs in read address is greater than 3"),
+        let offset = addr << 2 | select_bank as usize;
+        let index = offset % PAGE_SIZE;
+        self.page_mut(offset)[index] = data;
+        if let Some(ref mut written) = self.written {
+            let page =
+                written[offset / PAGE_SIZE].get_or_insert_with(|| Box::new([0; PAGE_SIZE / 8]));
+            page[index >> 3] |= 1 << (index & 7);
         }
     }
 
@@ -133,12 +579,30 @@ impl Memory {
     /// * `addr` => memory address to read from
     ///
     /// # Return Value
-    /// Value read from memory
-    pub fn load_data(&self, op: &MemLoadOp, addr: u32) -> i32 {
-        let masked_addr = Self::mask_addr(addr >> 2);
+    /// Value read from memory, or an error if the operation is invalid or
+    /// crosses the boundary of a word while misaligned accesses aren't
+    /// allowed
+    pub fn load_data(&self, op: &MemLoadOp, addr: u32) -> Result<i32, MemError> {
+        if let MemLoadOp::InvalidLoad = *op {
+            return Err(MemError {
+                kind: MemErrorKind::AccessFault,
+                addr,
+                size: op.size(),
+                access: MemAccess::Load,
+            });
+        }
+        self.check_alignment(addr, op.size(), MemAccess::Load)?;
+        if Self::crosses_word(addr, op.size()) || self.in_scratchpad(addr, op.size()) {
+            let value = (0..op.size()).fold(0, |value, offset| {
+                let data = self.read_byte(addr.wrapping_add(offset));
+                value | u32::from(data) << (offset << 3)
+            });
+            return Ok(op.extend(value));
+        }
+        let masked_addr = self.mask_addr(addr >> 2);
         let addr_lsbs = (addr & 0x0000_0003) as u8;
 
-        match *op {
+        let value = match *op {
             MemLoadOp::LoadByte => {
                 let data = self.get_data(masked_addr, addr_lsbs);
 
@@ -182,8 +646,10 @@ impl Memory {
 
                 (u32::from(data_1) << 8 | u32::from(data_0)) as i32
             }
-            MemLoadOp::InvalidLoad => panic!("Invalid Load operation on Memory"),
-        }
+            // Checked before the access
+            MemLoadOp::InvalidLoad => unreachable!(),
+        };
+        Ok(value)
     }
 
     /// Perform a write operation on the memory
@@ -192,14 +658,42 @@ impl Memory {
     /// * `op` => write operation to perform (store byte, half, or word)
     /// * `addr` => memory address to write to
     /// * `data` => ...
-    pub fn write_data(&mut self, op: &MemStoreOp, addr: u32, data: u32) {
+    ///
+    /// # Return Value
+    /// An error if the operation is invalid, writes the read-only region or
+    /// crosses the boundary of a word while misaligned accesses aren't
+    /// allowed, in which case the memory is unchanged
+    pub fn write_data(&mut self, op: &MemStoreOp, addr: u32, data: u32) -> Result<(), MemError> {
+        if let MemStoreOp::InvalidStore = *op {
+            return Err(MemError {
+                kind: MemErrorKind::AccessFault,
+                addr,
+                size: op.size(),
+                access: MemAccess::Store,
+            });
+        }
+        self.check_alignment(addr, op.size(), MemAccess::Store)?;
+        if self.is_read_only(addr, op.size()) {
+            return Err(MemError {
+                kind: MemErrorKind::AccessFault,
+                addr,
+                size: op.size(),
+                access: MemAccess::Store,
+            });
+        }
+        if Self::crosses_word(addr, op.size()) || self.in_scratchpad(addr, op.size()) {
+            for offset in 0..op.size() {
+                self.write_byte(addr.wrapping_add(offset), (data >> (offset << 3)) as u8);
+            }
+            return Ok(());
+        }
         let split_data = (
             data & 0x0000_00ff,
             (data & 0x0000_ff00) >> 8,
             (data & 0x00ff_0000) >> 16,
             (data & 0xff00_0000) >> 24,
         );
-        let masked_addr = Self::mask_addr(addr >> 2);
+        let masked_addr = self.mask_addr(addr >> 2);
         let addr_lsbs = (addr & 0x0000_0003) as u8;
 
         match *op {
@@ -214,8 +708,10 @@ impl Memory {
                 self.put_data(masked_addr, addr_lsbs + 2, split_data.2 as u8);
                 self.put_data(masked_addr, addr_lsbs + 3, split_data.3 as u8);
             }
-            MemStoreOp::InvalidStore => panic!("Invalid write operation on Memory"),
+            // Checked before the access
+            MemStoreOp::InvalidStore => unreachable!(),
         }
+        Ok(())
     }
 }
 
@@ -242,6 +738,31 @@ impl From<RV32I> for MemLoadOp {
     }
 }
 
+impl MemLoadOp {
+    /// Number of bytes read
+    pub fn size(&self) -> u32 {
+        match *self {
+            MemLoadOp::LoadByte | MemLoadOp::LoadByteUnsigned => 1,
+            MemLoadOp::LoadHalf | MemLoadOp::LoadHalfUnsigned => 2,
+            _ => 4,
+        }
+    }
+
+    /// Sign or zero extend the bytes read to a register
+    ///
+    /// # Arguments
+    /// * `value` => bytes read, in the lowest bits
+    pub fn extend(&self, value: u32) -> i32 {
+        match *self {
+            MemLoadOp::LoadByte => i32::from(value as u8 as i8),
+            MemLoadOp::LoadHalf => i32::from(value as u16 as i16),
+            MemLoadOp::LoadByteUnsigned => i32::from(value as u8),
+            MemLoadOp::LoadHalfUnsigned => i32::from(value as u16),
+            _ => value as i32,
+        }
+    }
+}
+
 /// Memory Store Operations
 pub enum MemStoreOp {
     StoreByte,
@@ -261,6 +782,17 @@ impl From<RV32I> for MemStoreOp {
     }
 }
 
+impl MemStoreOp {
+    /// Number of bytes written
+    pub fn size(&self) -> u32 {
+        match *self {
+            MemStoreOp::StoreByte => 1,
+            MemStoreOp::StoreHalf => 2,
+            _ => 4,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -283,27 +815,201 @@ mod tests {
 
     #[test]
     fn test_mask_addr() {
-        assert_eq!(0x0000_babe, Memory::mask_addr(0x0000_babe));
-        assert_eq!(0x000d_babe, Memory::mask_addr(0xdead_babe));
-        assert_eq!(0x000f_0000, Memory::mask_addr(0xbeef_0000));
-        assert_eq!(0x0011_3131, Memory::mask_addr(0x3131_3131));
+        let mem = Memory::new();
+        assert_eq!(0x0000_babe, mem.mask_addr(0x0000_babe));
+        assert_eq!(0x000d_babe, mem.mask_addr(0xdead_babe));
+        assert_eq!(0x000f_0000, mem.mask_addr(0xbeef_0000));
+        assert_eq!(0x0011_3131, mem.mask_addr(0x3131_3131));
+    }
+
+    #[test]
+    fn test_size() {
+        let mut mem = Box::new(Memory::new());
+        assert_eq!(8 << 20, mem.size());
+
+        // Addresses wrap around at the memory size
+        mem.write_data(&MemStoreOp::from(RV32I::SW), 0x10, 0xdead_beef)
+            .unwrap();
+        assert_eq!(0xdead_beef, mem.read_pc((mem.size() + 0x10) as u32));
+    }
+
+    #[test]
+    fn test_address_bits() {
+        let mut mem = Box::new(Memory::with_address_bits(MAX_ADDRESS_BITS).unwrap());
+        assert_eq!(1 << 32, mem.size());
+        assert_eq!(0, mem.allocated_size());
+
+        // Only the written pages are allocated
+        mem.write_data(&MemStoreOp::from(RV32I::SW), 0xffff_fffc, 0xdead_beef)
+            .unwrap();
+        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x8000_0000, 0x42)
+            .unwrap();
+        assert_eq!(2 * 4096, mem.allocated_size());
+        assert_eq!(0xdead_beef, mem.read_pc(0xffff_fffc));
+        assert_eq!(0x42, mem.read_pc(0x8000_0000));
+        assert_eq!(0, mem.read_pc(0x0000_fffc));
+
+        let mut mem = Box::new(Memory::with_address_bits(MIN_ADDRESS_BITS).unwrap());
+        mem.write_data(&MemStoreOp::from(RV32I::SW), 0x1000, 1)
+            .unwrap();
+        assert_eq!(1, mem.read_pc(0));
+    }
+
+    #[test]
+    fn test_address_bits_invalid() {
+        let error = Memory::with_address_bits(MIN_ADDRESS_BITS - 1).unwrap_err();
+        assert_eq!(
+            "invalid number of address bits: 11, expected 12 to 32",
+            error.to_string()
+        );
+        assert!(Memory::with_address_bits(MAX_ADDRESS_BITS + 1).is_err());
+    }
+
+    #[test]
+    fn test_extend() {
+        assert_eq!(-1, MemLoadOp::from(RV32I::LB).extend(0xff));
+        assert_eq!(0xff, MemLoadOp::from(RV32I::LBU).extend(0xff));
+        assert_eq!(-2, MemLoadOp::from(RV32I::LH).extend(0xfffe));
+        assert_eq!(0xfffe, MemLoadOp::from(RV32I::LHU).extend(0xfffe));
+        assert_eq!(-1, MemLoadOp::from(RV32I::LW).extend(0xffff_ffff));
+    }
+
+    #[test]
+    fn test_pages() {
+        let mut memory = Memory::new();
+        memory.fill(0xdead_beef);
+        memory
+            .write_data(&MemStoreOp::StoreWord, 0x2004, 0x1234_5678)
+            .unwrap();
+        let pages = memory.pages();
+        assert_eq!(1, pages.len());
+        assert_eq!(0x2000, pages[0].0);
+        assert_eq!(&[0xef, 0xbe, 0xad, 0xde, 0x78, 0x56], &pages[0].1[..6]);
+        assert!(memory.is_mapped(0x2000 + PAGE_SIZE as u32 - 1));
+        assert!(!memory.is_mapped(0x2000 + PAGE_SIZE as u32));
+        assert!(!memory.is_mapped(0x2000 + memory.size() as u32));
+
+        let pages: Vec<_> = pages
+            .into_iter()
+            .map(|(base, bytes)| (base, bytes.to_vec()))
+            .collect();
+        let mut copy = Memory::new();
+        copy.fill(0xdead_beef);
+        copy.write_data(&MemStoreOp::StoreWord, 0x100, 1).unwrap();
+        copy.set_pages(&pages);
+        assert_eq!(
+            Ok(0x1234_5678),
+            copy.load_data(&MemLoadOp::LoadWord, 0x2004)
+        );
+        assert_eq!(
+            Ok(0xdead_beef_u32 as i32),
+            copy.load_data(&MemLoadOp::LoadWord, 0x100)
+        );
+        assert_eq!(PAGE_SIZE as u64, copy.allocated_size());
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut mem = Box::new(Memory::new());
+        mem.fill(0xdead_beef);
+        assert_eq!(0xdead_beef, mem.read_pc(0));
+        // Pages written later start with the pattern
+        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x100, 0x42)
+            .unwrap();
+        assert_eq!(0xdead_be42, mem.read_pc(0x100));
+        assert_eq!(0xdead_beef, mem.read_pc(0x104));
+        assert_eq!(0xdead_beef, mem.read_pc((mem.size() - 4) as u32));
+        // Without tracking everything is initialized
+        assert!(mem.is_initialized(&MemLoadOp::LoadWord, 0x100));
+    }
+
+    #[test]
+    fn test_track_writes() {
+        let mut mem = Box::new(Memory::new());
+        mem.track_writes();
+        assert!(!mem.is_initialized(&MemLoadOp::LoadByte, 0x100));
+
+        mem.write_data(&MemStoreOp::StoreHalf, 0x102, 0xbeef)
+            .unwrap();
+        assert!(mem.is_initialized(&MemLoadOp::LoadHalfUnsigned, 0x102));
+        assert!(mem.is_initialized(&MemLoadOp::LoadByte, 0x103));
+        assert!(!mem.is_initialized(&MemLoadOp::LoadWord, 0x100));
+        assert!(!mem.is_initialized(&MemLoadOp::LoadHalf, 0x100));
+
+        mem.write_data(&MemStoreOp::StoreByte, 0x100, 0xef).unwrap();
+        mem.write_data(&MemStoreOp::StoreByte, 0x101, 0xbe).unwrap();
+        assert!(mem.is_initialized(&MemLoadOp::LoadWord, 0x100));
+        // Loads which cross a word check the bytes of both words
+        assert!(!mem.is_initialized(&MemLoadOp::LoadWord, 0x102));
+        mem.write_data(&MemStoreOp::StoreByte, 0x104, 0).unwrap();
+        assert!(mem.is_initialized(&MemLoadOp::LoadHalf, 0x103));
+    }
+
+    #[test]
+    fn test_misaligned_access() {
+        let mut mem = Box::new(Memory::new());
+        mem.set_misaligned_access(true);
+        assert!(mem.misaligned_access());
+        mem.write_data(&MemStoreOp::from(RV32I::SW), 0x0040_babe, 0x8765_4321)
+            .unwrap();
+        assert_eq!(0x4321_0000, mem.read_pc(0x0040_babc));
+        assert_eq!(0x0000_8765, mem.read_pc(0x0040_bac0));
+        assert_eq!(
+            Ok(0x8765_4321_u32 as i32),
+            mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babe)
+        );
+        assert_eq!(
+            Ok(0xffff_ff87_u32 as i32),
+            mem.load_data(&MemLoadOp::from(RV32I::LB), 0x0040_bac1)
+        );
+        assert_eq!(
+            Ok(0x6543),
+            mem.load_data(&MemLoadOp::from(RV32I::LHU), 0x0040_babf)
+        );
+        mem.write_data(&MemStoreOp::from(RV32I::SH), 0x0040_babf, 0xabcd)
+            .unwrap();
+        assert_eq!(0xcd21_0000, mem.read_pc(0x0040_babc));
+        // Halves across the words are extended like aligned ones
+        assert_eq!(
+            Ok(0xffff_abcd_u32 as i32),
+            mem.load_data(&MemLoadOp::from(RV32I::LH), 0x0040_babf)
+        );
+        // Other accesses keep wrapping around the memory
+        mem.write_data(&MemStoreOp::from(RV32I::SW), 0xffff_fffd, 0x0102_0304)
+            .unwrap();
+        assert_eq!(0x0203_0400, mem.read_pc(0xffff_fffc));
+        assert_eq!(0x0000_0001, mem.read_pc(0));
     }
 
     ////////////////////////////////////////
     // Load Operations
     ////////////////////////////////////////
     #[test]
-    #[should_panic]
     fn test_load_data_invalid_with_mem_load_op() {
         let mem = Box::new(Memory::new());
-        let _ = mem.load_data(&MemLoadOp::InvalidLoad, 0x3141142);
+        assert_eq!(
+            Err(MemError {
+                kind: MemErrorKind::AccessFault,
+                addr: 0x3141142,
+                size: 4,
+                access: MemAccess::Load,
+            }),
+            mem.load_data(&MemLoadOp::InvalidLoad, 0x3141142)
+        );
     }
 
     #[test]
-    #[should_panic]
     fn test_load_data_invalid_with_rv32i() {
         let mem = Box::new(Memory::new());
-        let _ = mem.load_data(&MemLoadOp::from(RV32I::ADD), 0x3141142);
+        assert_eq!(
+            Err(MemError {
+                kind: MemErrorKind::AccessFault,
+                addr: 0x3141142,
+                size: 4,
+                access: MemAccess::Load,
+            }),
+            mem.load_data(&MemLoadOp::from(RV32I::ADD), 0x3141142)
+        );
     }
 
     #[test]
@@ -314,36 +1020,44 @@ mod tests {
         assert_eq!(
             (0xffff_ffef as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LB), 0x0040_babc)
+                .unwrap()
         );
         assert_eq!(
             (0xffff_ffbe as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LB), 0x0040_babd)
+                .unwrap()
         );
         assert_eq!(
             (0xffff_ffad as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LB), 0x0040_babe)
+                .unwrap()
         );
         assert_eq!(
             (0xffff_ffde as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LB), 0x0040_babf)
+                .unwrap()
         );
         // Non-Sign extension
         mem.__write_garbage(0x4624_3667, 0x0040_babc);
         assert_eq!(
             0x0000_0067,
             mem.load_data(&MemLoadOp::from(RV32I::LB), 0x0040_babc)
+                .unwrap()
         );
         assert_eq!(
             0x0000_0036,
             mem.load_data(&MemLoadOp::from(RV32I::LB), 0x0040_babd)
+                .unwrap()
         );
         assert_eq!(
             0x0000_0024,
             mem.load_data(&MemLoadOp::from(RV32I::LB), 0x0040_babe)
+                .unwrap()
         );
         assert_eq!(
             0x0000_0046,
             mem.load_data(&MemLoadOp::from(RV32I::LB), 0x0040_babf)
+                .unwrap()
         );
     }
 
@@ -355,37 +1069,50 @@ mod tests {
         assert_eq!(
             (0xffff_beef as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LH), 0x0040_babc)
+                .unwrap()
         );
         assert_eq!(
             (0xffff_adbe as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LH), 0x0040_babd)
+                .unwrap()
         );
         assert_eq!(
             (0xffff_dead as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LH), 0x0040_babe)
+                .unwrap()
         );
         // Non-Sign Extension
         mem.__write_garbage(0x4624_3667, 0x0040_babc);
         assert_eq!(
             0x0000_3667,
             mem.load_data(&MemLoadOp::from(RV32I::LH), 0x0040_babc)
+                .unwrap()
         );
         assert_eq!(
             0x0000_2436,
             mem.load_data(&MemLoadOp::from(RV32I::LH), 0x0040_babd)
+                .unwrap()
         );
         assert_eq!(
             0x0000_4624,
             mem.load_data(&MemLoadOp::from(RV32I::LH), 0x0040_babe)
+                .unwrap()
         );
     }
 
     #[test]
-    #[should_panic]
     fn test_load_data_half_invalid_lsb() {
         let mut mem = Box::new(Memory::new());
         mem.__write_garbage(0xdead_beef, 0x0040_babc);
-        let _ = mem.load_data(&MemLoadOp::from(RV32I::LH), 0x0040_babf);
+        assert_eq!(
+            Err(MemError {
+                kind: MemErrorKind::Misaligned,
+                addr: 0x0040_babf,
+                size: 2,
+                access: MemAccess::Load,
+            }),
+            mem.load_data(&MemLoadOp::from(RV32I::LH), 0x0040_babf)
+        );
     }
 
     #[test]
@@ -395,14 +1122,22 @@ mod tests {
         assert_eq!(
             (0xdead_beef as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc)
+                .unwrap()
         );
     }
 
     #[test]
-    #[should_panic]
     fn test_load_data_word_lsb_different_than_zero() {
         let mem = Box::new(Memory::new());
-        let _ = mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babd);
+        assert_eq!(
+            Err(MemError {
+                kind: MemErrorKind::Misaligned,
+                addr: 0x0040_babd,
+                size: 4,
+                access: MemAccess::Load,
+            }),
+            mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babd)
+        );
     }
 
     #[test]
@@ -412,18 +1147,22 @@ mod tests {
         assert_eq!(
             0x0000_00ef,
             mem.load_data(&MemLoadOp::from(RV32I::LBU), 0x0040_babc)
+                .unwrap()
         );
         assert_eq!(
             0x0000_00be,
             mem.load_data(&MemLoadOp::from(RV32I::LBU), 0x0040_babd)
+                .unwrap()
         );
         assert_eq!(
             0x0000_00ad,
             mem.load_data(&MemLoadOp::from(RV32I::LBU), 0x0040_babe)
+                .unwrap()
         );
         assert_eq!(
             0x0000_00de,
             mem.load_data(&MemLoadOp::from(RV32I::LBU), 0x0040_babf)
+                .unwrap()
         );
     }
 
@@ -434,39 +1173,63 @@ mod tests {
         assert_eq!(
             0x0000_beef,
             mem.load_data(&MemLoadOp::from(RV32I::LHU), 0x0040_babc)
+                .unwrap()
         );
         assert_eq!(
             0x0000_adbe,
             mem.load_data(&MemLoadOp::from(RV32I::LHU), 0x0040_babd)
+                .unwrap()
         );
         assert_eq!(
             0x0000_dead,
             mem.load_data(&MemLoadOp::from(RV32I::LHU), 0x0040_babe)
+                .unwrap()
         );
     }
 
     #[test]
-    #[should_panic]
     fn test_load_data_half_unsigned_invalid_lsb() {
         let mem = Box::new(Memory::new());
-        let _ = mem.load_data(&MemLoadOp::from(RV32I::LHU), 0x0040_babf);
+        assert_eq!(
+            Err(MemError {
+                kind: MemErrorKind::Misaligned,
+                addr: 0x0040_babf,
+                size: 2,
+                access: MemAccess::Load,
+            }),
+            mem.load_data(&MemLoadOp::from(RV32I::LHU), 0x0040_babf)
+        );
     }
 
     ////////////////////////////////////////
     // Write Operations
     ////////////////////////////////////////
     #[test]
-    #[should_panic]
     fn test_store_data_invalid_with_mem_store_op() {
         let mut mem = Box::new(Memory::new());
-        let _ = mem.write_data(&MemStoreOp::InvalidStore, 0x3141142, 0xdead_beef);
+        assert_eq!(
+            Err(MemError {
+                kind: MemErrorKind::AccessFault,
+                addr: 0x3141142,
+                size: 4,
+                access: MemAccess::Store,
+            }),
+            mem.write_data(&MemStoreOp::InvalidStore, 0x3141142, 0xdead_beef)
+        );
     }
 
     #[test]
-    #[should_panic]
     fn test_store_data_invalid_with_rv32i() {
         let mut mem = Box::new(Memory::new());
-        let _ = mem.write_data(&MemStoreOp::from(RV32I::ADD), 0x3141142, 0xdead_beef);
+        assert_eq!(
+            Err(MemError {
+                kind: MemErrorKind::AccessFault,
+                addr: 0x3141142,
+                size: 4,
+                access: MemAccess::Store,
+            }),
+            mem.write_data(&MemStoreOp::from(RV32I::ADD), 0x3141142, 0xdead_beef)
+        );
     }
 
     #[test]
@@ -475,25 +1238,33 @@ mod tests {
         // Sanity write
         mem.__write_garbage(0xdead_beef, 0x0040_babc);
         // Actual real write
-        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x0040_babc, 0x0000_0042);
+        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x0040_babc, 0x0000_0042)
+            .unwrap();
         assert_eq!(
             (0xdead_be42 as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc)
+                .unwrap()
         );
-        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x0040_babd, 0x0000_0042);
+        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x0040_babd, 0x0000_0042)
+            .unwrap();
         assert_eq!(
             (0xdead_4242 as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc)
+                .unwrap()
         );
-        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x0040_babe, 0x0000_0042);
+        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x0040_babe, 0x0000_0042)
+            .unwrap();
         assert_eq!(
             (0xde42_4242 as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc)
+                .unwrap()
         );
-        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x0040_babf, 0x0000_0042);
+        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x0040_babf, 0x0000_0042)
+            .unwrap();
         assert_eq!(
             0x4242_4242,
             mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc)
+                .unwrap()
         );
     }
 
@@ -503,28 +1274,43 @@ mod tests {
         // Sanity write
         mem.__write_garbage(0xdead_beef, 0x0040_babc);
         // Actual real write
-        mem.write_data(&MemStoreOp::from(RV32I::SH), 0x0040_babc, 0x0000_6942);
+        mem.write_data(&MemStoreOp::from(RV32I::SH), 0x0040_babc, 0x0000_6942)
+            .unwrap();
         assert_eq!(
             (0xdead_6942 as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc)
+                .unwrap()
         );
-        mem.write_data(&MemStoreOp::from(RV32I::SH), 0x0040_babd, 0x0000_3142);
+        mem.write_data(&MemStoreOp::from(RV32I::SH), 0x0040_babd, 0x0000_3142)
+            .unwrap();
         assert_eq!(
             (0xde31_4242 as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc)
+                .unwrap()
         );
-        mem.write_data(&MemStoreOp::from(RV32I::SH), 0x0040_babe, 0x0000_abcd);
+        mem.write_data(&MemStoreOp::from(RV32I::SH), 0x0040_babe, 0x0000_abcd)
+            .unwrap();
         assert_eq!(
             (0xabcd_4242 as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc)
+                .unwrap()
         );
     }
 
     #[test]
-    #[should_panic]
     fn test_write_data_half_invalid_lsb() {
         let mut mem = Box::new(Memory::new());
-        mem.write_data(&MemStoreOp::from(RV32I::SH), 0x0040_babf, 0xdeadbeef);
+        assert_eq!(
+            Err(MemError {
+                kind: MemErrorKind::Misaligned,
+                addr: 0x0040_babf,
+                size: 2,
+                access: MemAccess::Store,
+            }),
+            mem.write_data(&MemStoreOp::from(RV32I::SH), 0x0040_babf, 0xdeadbeef)
+        );
+        // Nothing is written
+        assert_eq!(0, mem.read_pc(0x0040_babc));
     }
 
     #[test]
@@ -533,17 +1319,122 @@ mod tests {
         // Sanity write
         mem.__write_garbage(0xdead_beef, 0x0040_babc);
         // Actual real write
-        mem.write_data(&MemStoreOp::from(RV32I::SW), 0x0040_babc, 0xbabe_31ab);
+        mem.write_data(&MemStoreOp::from(RV32I::SW), 0x0040_babc, 0xbabe_31ab)
+            .unwrap();
         assert_eq!(
             (0xbabe_31ab as u32) as i32,
             mem.load_data(&MemLoadOp::from(RV32I::LW), 0x0040_babc)
+                .unwrap()
         );
     }
 
     #[test]
-    #[should_panic]
     fn test_write_data_word_lsb_different_than_zero() {
         let mut mem = Box::new(Memory::new());
-        mem.write_data(&MemStoreOp::from(RV32I::LW), 0x0040_babd, 0xabcd_ef12);
+        assert_eq!(
+            Err(MemError {
+                kind: MemErrorKind::Misaligned,
+                addr: 0x0040_babd,
+                size: 4,
+                access: MemAccess::Store,
+            }),
+            mem.write_data(&MemStoreOp::from(RV32I::SW), 0x0040_babd, 0xabcd_ef12)
+        );
+    }
+
+    #[test]
+    fn test_rom() {
+        let mut mem = Box::new(Memory::new());
+        mem.write_data(&MemStoreOp::from(RV32I::SW), 0x100, 0x1111_1111)
+            .unwrap();
+        mem.track_writes();
+        mem.map_rom(0x102, vec![0xaa, 0xbb, 0xcc]);
+        assert!(mem.is_read_only(0x100, 4));
+        assert!(!mem.is_read_only(0x105, 4));
+
+        // The ROM hides the banks under it
+        assert_eq!(0xbbaa_1111, mem.read_pc(0x100));
+        assert_eq!(Ok(0xcc), mem.load_data(&MemLoadOp::from(RV32I::LBU), 0x104));
+        assert!(mem.is_initialized(&MemLoadOp::LoadHalf, 0x102));
+        assert_eq!(
+            Err(MemError {
+                kind: MemErrorKind::AccessFault,
+                addr: 0x100,
+                size: 4,
+                access: MemAccess::Store,
+            }),
+            mem.write_data(&MemStoreOp::from(RV32I::SW), 0x100, 0)
+        );
+        assert_eq!(0xbbaa_1111, mem.read_pc(0x100));
+        mem.write_data(&MemStoreOp::from(RV32I::SB), 0x105, 0x42)
+            .unwrap();
+        assert_eq!(0x0000_42cc, mem.read_pc(0x104));
+        // Mirrors of the ROM are read-only too
+        assert!(mem.is_read_only(0x102 + mem.size() as u32, 1));
+    }
+
+    #[test]
+    fn test_scratchpad() {
+        let mut mem = Box::new(Memory::new());
+        mem.track_writes();
+        mem.map_scratchpad(0xf000_0000, 0x100).unwrap();
+        assert!(mem.in_scratchpad(0xefff_fffe, 4));
+        assert!(!mem.in_scratchpad(0xf000_0100, 4));
+
+        mem.write_data(&MemStoreOp::from(RV32I::SW), 0xf000_0010, 0x1234_5678)
+            .unwrap();
+        assert_eq!(
+            Ok(0x1234),
+            mem.load_data(&MemLoadOp::from(RV32I::LHU), 0xf000_0012)
+        );
+        assert_eq!(0x1234_5678, mem.read_pc(0xf000_0010));
+        assert!(mem.is_initialized(&MemLoadOp::LoadWord, 0xf000_0020));
+        // The main memory at the mirror of the address is untouched
+        assert_eq!(0, mem.read_pc(0x10));
+        assert_eq!(0, mem.allocated_size());
+
+        mem.fill(0xdead_beef);
+        assert_eq!(0xdead_beef, mem.read_pc(0xf000_0010));
+    }
+
+    #[test]
+    fn test_flip_bit() {
+        let mut mem = Box::new(Memory::new());
+        mem.track_writes();
+        mem.fill(0xffff_ffff);
+        mem.flip_bit(0x101, 0);
+        assert_eq!(0xffff_feff, mem.read_pc(0x100));
+        assert!(!mem.is_initialized(&MemLoadOp::LoadByte, 0x101));
+
+        mem.map_rom(0x200, vec![0; 4]);
+        mem.flip_bit(0x203, 7);
+        assert_eq!(0x8000_0000, mem.read_pc(0x200));
+        mem.map_scratchpad(0xf000_0000, 4).unwrap();
+        mem.flip_bit(0xf000_0000, 4);
+        assert_eq!(0xffff_ffef, mem.read_pc(0xf000_0000));
+    }
+
+    #[test]
+    fn test_error_display() {
+        let error = MemError {
+            kind: MemErrorKind::AccessFault,
+            addr: 0xdead_beef,
+            size: 4,
+            access: MemAccess::Store,
+        };
+        assert_eq!(
+            "store access fault at 0xdeadbeef (4 bytes)",
+            error.to_string()
+        );
+        let error = MemError {
+            kind: MemErrorKind::Misaligned,
+            addr: 0x102,
+            size: 2,
+            access: MemAccess::Fetch,
+        };
+        assert_eq!(
+            "misaligned fetch at 0x00000102 (2 bytes)",
+            error.to_string()
+        );
     }
 }
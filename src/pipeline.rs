@@ -0,0 +1,1389 @@
+//! Cycle accurate model of the Adept pipeline. The pipeline can be configured
+//! with a single stage, where every instruction is fetched, executed and
+//! retired in the same stage, or with three stages:
+//!
+//! * Fetch => reads the instruction from memory
+//! * Decode => decodes the instruction and reads the register file
+//! * Execute => performs the operation, accesses the memory and writes back
+//!
+//! Instructions are executed on the hart when they enter the execute stage and
+//! stay there for the number of cycles given by the latency table. Taken
+//! branches and jumps redirect the fetch and flush every younger instruction.
+//! The fetch stage keeps going down the path it predicted until then, so the
+//! flushed instructions were fetched, and possibly waited for the bus, but
+//! never executed. They are counted as wrong path instructions. A cycle in
+//! which nothing retires is attributed to the reason which kept the execute
+//! stage from retiring, such as the refill after a flush (see `stats`).
+//!
+//! An instruction which depends on the result of the previous one gets it
+//! through the forwarding paths. Each path can be disabled, in which case the
+//! instruction waits in decode for the register file write back.
+//!
+//! Branches and jumps resolve in the execute stage by default, flushing the
+//! two younger instructions when taken, unless the branch target buffer or
+//! the return address stack of the fetch stage predicted their target (see
+//! `frontend`). They can also resolve in decode, where
+//! only the fetched instruction is flushed, but then they wait in decode until
+//! their operands are available.
+//!
+//! Memory accesses go through the bus model: wait states on a fetch hold the
+//! instruction in the fetch stage, while wait states on a load or a store
+//! extend its stay in the execute stage, as do the cycles it waits for the
+//! DMA engine to free the bus. When misaligned accesses are allowed,
+//! a load or a store which crosses a word accesses both words, taking an extra
+//! cycle.
+//!
+//! The three stage pipeline can also issue two instructions per cycle with
+//! `issue_width = 2`, as an experiment to measure the headroom of a wider
+//! Adept. The instructions then travel in pairs through a second lane: the
+//! fetch stage reads the instruction following the first one unless it
+//! predicted a taken branch, and both enter the execute stage together
+//! unless
+//!
+//! * the younger one reads the register written by the older one,
+//! * both access the memory, which has a single port,
+//! * either is a system instruction, which issues alone,
+//!
+//! in which case the younger one waits in decode for the next cycle. The
+//! instructions of a pair retire in order, the younger one waiting for the
+//! older one if it's faster. The cycle records only hold the register write
+//! of the older instruction.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::config::Config;
+//! # use adept_lib::pipeline::{PipelineConfig, Stages};
+//! let config = Config::parse("[pipeline]\nstages = 1\n[latency]\nload = 2\n").unwrap();
+//! let pipeline = PipelineConfig::from_config(&config).unwrap();
+//! assert_eq!(Stages::One, pipeline.stages);
+//! ```
+use bus::Bus;
+use config::{value_error, Config, ConfigError};
+use frontend::{Frontend, FrontendConfig, Prediction};
+use hart::{Executed, Hart, StopReason};
+use latency::{InstrClass, LatencyTable};
+use riscv::decoder::{Hint, Instruction};
+use stats::{StallReason, Stats};
+use trace::{BusKind, BusTransaction, CycleRecord, Occupant, TraceEntry};
+
+/// Pipeline depth configurations
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Stages {
+    /// Fetch, decode and execute in a single stage
+    One,
+    /// Fetch, decode and execute stages
+    Three,
+}
+
+impl Stages {
+    /// Every pipeline configuration, from the shortest one
+    pub const ALL: [Stages; 2] = [Stages::One, Stages::Three];
+
+    /// Number of pipeline stages
+    pub fn depth(self) -> usize {
+        match self {
+            Stages::One => 1,
+            Stages::Three => 3,
+        }
+    }
+
+    /// Short description of the configuration
+    pub fn name(self) -> &'static str {
+        match self {
+            Stages::One => "1-stage",
+            Stages::Three => "3-stage",
+        }
+    }
+
+    /// Names of the stages, from fetch to execute
+    pub fn names(self) -> &'static [&'static str] {
+        match self {
+            Stages::One => &["IF/ID/EX"],
+            Stages::Three => &["IF", "ID", "EX"],
+        }
+    }
+}
+
+/// Forwarding paths into the execute stage. The execute stage also accesses
+/// the memory, so ALU results are forwarded by the EX to EX path while load
+/// data takes the MEM to EX path.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Forwarding {
+    /// Forward the results of ALU instructions and jumps
+    pub ex_to_ex: bool,
+    /// Forward the data of loads
+    pub mem_to_ex: bool,
+}
+
+impl Default for Forwarding {
+    fn default() -> Self {
+        Forwarding {
+            ex_to_ex: true,
+            mem_to_ex: true,
+        }
+    }
+}
+
+impl Forwarding {
+    /// Extra cycles a dependent instruction waits for a result produced by
+    /// an instruction of `class`
+    pub fn penalty(self, class: InstrClass) -> u32 {
+        let forwarded = match class {
+            InstrClass::Load => self.mem_to_ex,
+            _ => self.ex_to_ex,
+        };
+        if forwarded {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+/// Stage of the three stage pipeline where branches and jumps are resolved
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum BranchResolution {
+    Decode,
+    Execute,
+}
+
+impl BranchResolution {
+    /// Name used in configuration files
+    pub fn name(self) -> &'static str {
+        match self {
+            BranchResolution::Decode => "decode",
+            BranchResolution::Execute => "execute",
+        }
+    }
+}
+
+/// Parameters of the pipeline model
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct PipelineConfig {
+    /// Number of stages
+    pub stages: Stages,
+    /// Execute latency of each instruction class
+    pub latency: LatencyTable,
+    /// Access latency of the memory regions
+    pub bus: Bus,
+    /// Forwarding paths of the three stage pipeline
+    pub forwarding: Forwarding,
+    /// Where the three stage pipeline resolves branches and jumps
+    pub branch_resolution: BranchResolution,
+    /// Prediction structures of the fetch stage
+    pub frontend: FrontendConfig,
+    /// Issue pairs of instructions in the three stage pipeline
+    pub dual_issue: bool,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig {
+            stages: Stages::Three,
+            latency: LatencyTable::new(),
+            bus: Bus::new(),
+            forwarding: Forwarding::default(),
+            branch_resolution: BranchResolution::Execute,
+            frontend: FrontendConfig::default(),
+            dual_issue: false,
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Create a pipeline configuration from the `[pipeline]`, `[latency]`,
+    /// `[bus]` and `[frontend]` tables of a configuration. Missing values
+    /// keep their defaults.
+    ///
+    /// # Arguments
+    /// * `config` => parsed configuration
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let mut pipeline = Self::default();
+
+        pipeline.stages = match config.get_integer("pipeline", "stages")? {
+            None => pipeline.stages,
+            Some(1) => Stages::One,
+            Some(3) => Stages::Three,
+            Some(_) => return Err(value_error("pipeline", "stages", "expected 1 or 3")),
+        };
+        if let Some(enabled) = config.get_boolean("pipeline", "forward_ex_to_ex")? {
+            pipeline.forwarding.ex_to_ex = enabled;
+        }
+        if let Some(enabled) = config.get_boolean("pipeline", "forward_mem_to_ex")? {
+            pipeline.forwarding.mem_to_ex = enabled;
+        }
+        pipeline.branch_resolution = match config.get_string("pipeline", "branch_resolution")? {
+            None => pipeline.branch_resolution,
+            Some("decode") => BranchResolution::Decode,
+            Some("execute") => BranchResolution::Execute,
+            Some(_) => {
+                return Err(value_error(
+                    "pipeline",
+                    "branch_resolution",
+                    "expected \"decode\" or \"execute\"",
+                ))
+            }
+        };
+        pipeline.dual_issue = match config.get_integer("pipeline", "issue_width")? {
+            None | Some(1) => false,
+            Some(2) if pipeline.stages == Stages::Three => true,
+            Some(2) => {
+                return Err(value_error(
+                    "pipeline",
+                    "issue_width",
+                    "dual issue needs 3 stages",
+                ))
+            }
+            Some(_) => return Err(value_error("pipeline", "issue_width", "expected 1 or 2")),
+        };
+        pipeline.latency = LatencyTable::from_config(config)?;
+        pipeline.bus = Bus::from_config(config)?;
+        pipeline.frontend = FrontendConfig::from_config(config)?;
+
+        Ok(pipeline)
+    }
+}
+
+/// Architectural side of the pipeline, which provides the instructions and
+/// executes them when they enter the execute stage. It's usually the hart,
+/// but a recorded trace can stand in for it to replay a run.
+pub trait Core {
+    /// Bits of the instruction at `pc`
+    fn fetch(&self, pc: u32) -> u32;
+
+    /// Execute an instruction, as `Hart::execute` does
+    fn execute(&mut self, pc: u32, raw: u32) -> Result<Executed, StopReason>;
+
+    /// Register written by the last executed instruction, and its value
+    fn last_write(&self) -> Option<(u8, u32)>;
+
+    /// Register an instruction writes, if any
+    fn destination(&self, raw: u32) -> Option<u8>;
+
+    /// Address of the instruction following a branch or a jump which is
+    /// still in decode, or None if it can't be resolved there
+    fn resolve_control(&self, pc: u32, raw: u32) -> Option<u32>;
+
+    /// Called at the end of every cycle
+    fn count_cycle(&mut self);
+}
+
+impl Core for Hart {
+    fn fetch(&self, pc: u32) -> u32 {
+        self.memory().read_pc(pc)
+    }
+
+    fn execute(&mut self, pc: u32, raw: u32) -> Result<Executed, StopReason> {
+        Hart::execute(self, pc, raw)
+    }
+
+    fn last_write(&self) -> Option<(u8, u32)> {
+        Hart::last_write(self)
+    }
+
+    fn destination(&self, raw: u32) -> Option<u8> {
+        match self.decode_custom(raw) {
+            Some(custom) => custom.rd,
+            None => Instruction::new(raw).get_rd(),
+        }
+    }
+
+    fn resolve_control(&self, pc: u32, raw: u32) -> Option<u32> {
+        Hart::resolve_control(self, pc, raw)
+    }
+
+    fn count_cycle(&mut self) {
+        Hart::count_cycle(self)
+    }
+}
+
+// An instruction in flight
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    id: u64,
+    pc: u32,
+    raw: u32,
+    // Set once the instruction entered the execute stage
+    executed: bool,
+    // Cycles left in the execute stage
+    remaining: u32,
+    // Cycles left waiting for the bus in the fetch stage
+    fetch_wait: u32,
+    // Cycles of the stay in the execute stage left waiting for the DMA
+    // engine to free the bus, then for the wait states of the memory
+    contention: u32,
+    memory: u32,
+    // Class of the instruction, known once it executed
+    class: InstrClass,
+    // Set once a branch or a jump redirected the fetch from decode
+    resolved: bool,
+    // Address the fetch stage continued from
+    prediction: Prediction,
+    // Why the younger instruction of a pair stopped the simulation when it
+    // executed, reported once it takes the first lane
+    stop: Option<StopReason>,
+}
+
+impl Slot {
+    fn entry(&self) -> TraceEntry {
+        TraceEntry {
+            id: self.id,
+            pc: self.pc,
+            raw: self.raw,
+        }
+    }
+}
+
+/// State of the pipeline stages
+#[derive(Clone)]
+pub struct Pipeline {
+    config: PipelineConfig,
+    frontend: Frontend,
+    stages: Vec<Option<Slot>>,
+    // Younger instruction of the pair held by each stage, when the first
+    // lane holds the older one
+    second_lane: Vec<Option<Slot>>,
+    fetch_pc: u32,
+    next_id: u64,
+    record: CycleRecord,
+    // First cycle in which each register can be used in the execute stage
+    ready: [u64; 32],
+    // Registers last written by a load
+    loaded: [bool; 32],
+    // Why the execute stage is empty in the next cycle, if it is
+    bubble: StallReason,
+    // Set from a flush until an instruction of the right path reaches the
+    // execute stage
+    refill: bool,
+}
+
+impl Pipeline {
+    /// Create an empty pipeline which starts fetching at address 0
+    pub fn new(config: PipelineConfig) -> Self {
+        Pipeline {
+            stages: vec![None; config.stages.depth()],
+            second_lane: vec![None; config.stages.depth()],
+            frontend: Frontend::new(config.frontend),
+            config,
+            fetch_pc: 0,
+            next_id: 0,
+            record: CycleRecord::default(),
+            ready: [0; 32],
+            loaded: [false; 32],
+            bubble: StallReason::Fetch,
+            refill: false,
+        }
+    }
+
+    pub fn config(&self) -> &PipelineConfig {
+        &self.config
+    }
+
+    /// Activity of the pipeline during the last simulated cycle
+    pub fn last_cycle(&self) -> &CycleRecord {
+        &self.record
+    }
+
+    /// Drop every instruction in flight and restart fetching at `pc`
+    pub fn reset(&mut self, pc: u32) {
+        for stage in self.stages.iter_mut().chain(&mut self.second_lane) {
+            *stage = None;
+        }
+        self.fetch_pc = pc;
+        self.ready = [0; 32];
+        self.bubble = StallReason::Fetch;
+        self.refill = false;
+    }
+
+    /// Simulate one cycle
+    ///
+    /// # Arguments
+    /// * `core` => architectural state the instructions execute on, usually
+    ///   the hart
+    /// * `stats` => statistics to update
+    ///
+    /// # Return Value
+    /// Nothing, or the reason why the instruction in the execute stage stopped
+    /// the simulation. The stopping cycle is not counted.
+    pub fn tick<C: Core>(&mut self, core: &mut C, stats: &mut Stats) -> Result<(), StopReason> {
+        let execute = self.stages.len() - 1;
+        self.record.flushed.clear();
+        self.record.retired = None;
+        self.record.paired = None;
+        self.record.stall = None;
+        self.record.register_write = None;
+        self.record.bus.clear();
+
+        // Fetch into the first stage when it's free, along with the next
+        // instruction in the dual issue pipeline
+        if self.stages[0].is_none() {
+            let first = self.fetch(core);
+            let mut fetch_wait = first.fetch_wait;
+            if self.config.dual_issue && first.prediction.next_pc == first.pc.wrapping_add(4) {
+                let mut second = self.fetch(core);
+                // Both words arrive together, and the pair waits for the first
+                // lane
+                fetch_wait = fetch_wait.max(second.fetch_wait);
+                second.fetch_wait = 0;
+                self.second_lane[0] = Some(second);
+            }
+            self.stages[0] = Some(Slot {
+                fetch_wait,
+                ..first
+            });
+        }
+
+        // Execute the instructions that just entered the execute stage. With a
+        // single stage it may still be waiting for the fetch.
+        if let Some(mut slot) = self.stages[execute] {
+            if !slot.executed && slot.fetch_wait == 0 {
+                if let Some(reason) = slot.stop {
+                    return Err(reason);
+                }
+                let executed = self.execute(core, &mut slot, stats)?;
+                self.stages[execute] = Some(slot);
+                self.record.register_write = core.last_write();
+                if !slot.resolved && executed.next_pc != slot.prediction.next_pc {
+                    // Everything younger is on the wrong path
+                    self.flush(execute, true, stats);
+                    self.fetch_pc = executed.next_pc;
+                }
+
+                // The younger instruction of a pair executes after the older
+                // one. If it stops the simulation, it does so once the older
+                // one retired, without executing again.
+                if let Some(mut second) = self.second_lane[execute] {
+                    match self.execute(core, &mut second, stats) {
+                        Ok(executed) => {
+                            self.second_lane[execute] = Some(second);
+                            stats.dual_issues += 1;
+                            if executed.next_pc != second.prediction.next_pc {
+                                self.flush(execute, false, stats);
+                                self.fetch_pc = executed.next_pc;
+                            }
+                        }
+                        Err(reason) => {
+                            second.stop = Some(reason);
+                            self.second_lane[execute] = Some(second);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Resolve the branch or jump in decode. Every older instruction already
+        // executed, so the registers hold its operands once they are ready.
+        let mut unresolved = false;
+        if self.config.branch_resolution == BranchResolution::Decode && execute == 2 {
+            if let Some(mut slot) = self.stages[1] {
+                let is_control = matches!(
+                    InstrClass::from(Instruction::new(slot.raw).op()),
+                    InstrClass::Branch | InstrClass::Jump
+                );
+                if is_control && !slot.resolved {
+                    if !self.operands_ready(slot.raw, stats.cycles + 1) {
+                        unresolved = true;
+                    } else if let Some(target) = core.resolve_control(slot.pc, slot.raw) {
+                        slot.resolved = true;
+                        self.stages[1] = Some(slot);
+                        if target != slot.prediction.next_pc {
+                            self.flush(1, true, stats);
+                            self.fetch_pc = target;
+                        }
+                    }
+                }
+            }
+        }
+
+        stats.cycles += 1;
+        core.count_cycle();
+        let entries = |lane: &[Option<Slot>]| -> Vec<Option<TraceEntry>> {
+            lane.iter()
+                .map(|stage| stage.map(|slot| slot.entry()))
+                .collect()
+        };
+        let occupants = entries(&self.stages);
+        let second_occupants = entries(&self.second_lane);
+
+        // Wait for the bus in the fetch stage
+        let mut fetching = false;
+        if let Some(slot) = self.stages[0].as_mut() {
+            if slot.fetch_wait > 0 {
+                slot.fetch_wait -= 1;
+                stats.fetch_stalls += 1;
+                fetching = true;
+            }
+        }
+
+        // Retire, in order. The younger instruction of a pair takes the first
+        // lane when it's slower.
+        if let Some(second) = self.second_lane[execute].as_mut() {
+            if second.executed && second.remaining > 0 {
+                second.remaining -= 1;
+            }
+        }
+        if let Some(slot) = self.stages[execute].as_mut() {
+            if slot.executed {
+                slot.remaining -= 1;
+                if slot.remaining == 0 {
+                    self.record.retired = Some(slot.entry());
+                    let (raw, class) = (slot.raw, slot.class);
+                    self.stages[execute] = None;
+                    count_retired(raw, class, stats);
+                }
+            }
+        }
+        if self.stages[execute].is_none() {
+            if let Some(second) = self.second_lane[execute].take() {
+                if second.executed && second.remaining == 0 {
+                    self.record.paired = Some(second.entry());
+                    count_retired(second.raw, second.class, stats);
+                } else {
+                    self.stages[execute] = Some(second);
+                }
+            }
+        }
+
+        // Find why nothing retired. A busy execute stage waits for the bus
+        // before the memory, and for both before its latency.
+        if self.record.retired.is_none() {
+            let reason = match self.stages[execute].as_mut() {
+                Some(slot) if slot.executed && slot.contention > 0 => {
+                    slot.contention -= 1;
+                    StallReason::BusContention
+                }
+                Some(slot) if slot.executed && slot.memory > 0 => {
+                    slot.memory -= 1;
+                    StallReason::Memory
+                }
+                Some(slot) if slot.executed => StallReason::ExecuteBusy,
+                // The single stage pipeline waits for the fetch
+                Some(_) => StallReason::Fetch,
+                None => self.bubble,
+            };
+            stats.stall_cycles[reason.index()] += 1;
+            self.record.stall = Some(reason);
+        }
+
+        // Advance the instructions into the free stages. Instructions wait in
+        // decode until their operands can be used in the next cycle.
+        let mut hazard = None;
+        for stage in (1..=execute).rev() {
+            if self.stages[stage].is_some() || (stage == 1 && fetching) {
+                continue;
+            }
+            if let Some(slot) = self.stages[stage - 1] {
+                if stage == execute
+                    && (unresolved || !self.operands_ready(slot.raw, stats.cycles + 1))
+                {
+                    stats.hazard_stalls += 1;
+                    hazard = Some(self.hazard(slot.raw));
+                    continue;
+                }
+                if stage == execute {
+                    self.refill = false;
+                }
+            }
+            let first = self.stages[stage - 1].take();
+            self.stages[stage] = first;
+
+            // The younger instruction of a pair moves along, unless the issue
+            // rules keep it in decode where it takes the first lane
+            if let (Some(first), Some(second)) = (first, self.second_lane[stage - 1].take()) {
+                if stage < execute || self.can_pair(core, &first, &second, stats.cycles + 1) {
+                    self.second_lane[stage] = Some(second);
+                } else {
+                    self.stages[stage - 1] = Some(second);
+                    stats.split_pairs += 1;
+                }
+            }
+        }
+
+        self.bubble = match hazard {
+            Some(reason) => reason,
+            None if self.refill => StallReason::BranchFlush,
+            None => StallReason::Fetch,
+        };
+
+        // Instructions which are still in the same stage were stalled
+        self.record.cycle = stats.cycles;
+        self.record.stages = self.occupants(occupants);
+        self.record.second_lane = if self.config.dual_issue {
+            self.occupants(second_occupants)
+        } else {
+            Vec::new()
+        };
+
+        Ok(())
+    }
+
+    // Fetch the instruction at the fetch address, which then moves to the
+    // address predicted for it
+    fn fetch<C: Core>(&mut self, core: &C) -> Slot {
+        let pc = self.fetch_pc;
+        let fetch_wait = self.config.bus.wait_states(pc);
+        let raw = core.fetch(pc);
+        let prediction = self.frontend.predict(pc, raw);
+        self.record.bus.push(BusTransaction {
+            kind: BusKind::Fetch,
+            addr: pc,
+            wait_states: fetch_wait,
+        });
+        self.fetch_pc = prediction.next_pc;
+        self.next_id += 1;
+        Slot {
+            id: self.next_id - 1,
+            pc,
+            raw,
+            executed: false,
+            remaining: 0,
+            fetch_wait,
+            contention: 0,
+            memory: 0,
+            class: InstrClass::Alu,
+            resolved: false,
+            prediction,
+            stop: None,
+        }
+    }
+
+    // Execute an instruction entering the execute stage, and count the
+    // cycles it stays there
+    fn execute<C: Core>(
+        &mut self,
+        core: &mut C,
+        slot: &mut Slot,
+        stats: &mut Stats,
+    ) -> Result<Executed, StopReason> {
+        let executed = core.execute(slot.pc, slot.raw)?;
+        let kind = match executed.class {
+            InstrClass::Store => BusKind::Store,
+            _ => BusKind::Load,
+        };
+        let data_wait = executed.mem_addr.map_or(0, |addr| {
+            let wait = self.config.bus.wait_states(addr);
+            self.record.bus.push(BusTransaction {
+                kind,
+                addr,
+                wait_states: wait,
+            });
+            if executed.split_access {
+                // The second word takes one more cycle and its own wait
+                // states
+                let second = (addr | 3).wrapping_add(1);
+                let second_wait = self.config.bus.wait_states(second);
+                self.record.bus.push(BusTransaction {
+                    kind,
+                    addr: second,
+                    wait_states: second_wait,
+                });
+                wait + 1 + second_wait
+            } else {
+                wait
+            }
+        }) + executed.bus_wait;
+        stats.data_stalls += u64::from(data_wait);
+        slot.contention = executed.bus_wait;
+        slot.memory = data_wait - executed.bus_wait;
+        slot.executed = true;
+        slot.class = executed.class;
+        slot.remaining = self.config.latency.get(executed.class) + data_wait;
+
+        // The single stage pipeline writes back before the next instruction
+        // starts
+        if let Some(rd) = core.destination(slot.raw) {
+            let penalty = match self.config.stages {
+                Stages::One => 0,
+                Stages::Three => self.config.forwarding.penalty(executed.class),
+            };
+            self.ready[rd as usize] = stats.cycles + 1 + u64::from(slot.remaining + penalty);
+            self.loaded[rd as usize] = executed.class == InstrClass::Load;
+        }
+
+        if executed.class == InstrClass::Branch || executed.class == InstrClass::Jump {
+            self.frontend
+                .resolve(slot.pc, slot.raw, slot.prediction, executed.next_pc, stats);
+        }
+        Ok(executed)
+    }
+
+    // Flush the instructions younger than the one in the first lane of a
+    // stage, or than both instructions of the stage
+    fn flush(&mut self, stage: usize, second_lane: bool, stats: &mut Stats) {
+        let mut flushed = Vec::new();
+        for younger in 0..stage {
+            flushed.push(self.second_lane[younger].take());
+            flushed.push(self.stages[younger].take());
+        }
+        if second_lane {
+            flushed.push(self.second_lane[stage].take());
+        }
+        for slot in flushed.into_iter().flatten() {
+            self.record.flushed.push(slot.entry());
+            stats.wrong_path += 1;
+        }
+        self.refill = true;
+    }
+
+    // Check if the younger instruction of a pair can enter the execute stage
+    // along with the older one in a cycle
+    fn can_pair<C: Core>(&self, core: &C, first: &Slot, second: &Slot, cycle: u64) -> bool {
+        let class = |slot: &Slot| InstrClass::from(Instruction::new(slot.raw).op());
+        let (older, younger) = (class(first), class(second));
+        let memory = |class: InstrClass| class == InstrClass::Load || class == InstrClass::Store;
+        let decoded = Instruction::new(second.raw);
+        let dependent = match core.destination(first.raw) {
+            Some(rd) if rd != 0 => [decoded.get_rs1(), decoded.get_rs2()].contains(&Some(rd)),
+            _ => false,
+        };
+        let single_port = memory(older) && memory(younger);
+        let system = older == InstrClass::System || younger == InstrClass::System;
+        !(dependent || single_port || system) && self.operands_ready(second.raw, cycle)
+    }
+
+    // What a lane held during the cycle, given the instructions of its stages
+    // at the start of the cycle. Instructions which are still in the same
+    // stage were stalled.
+    fn occupants(&self, held: Vec<Option<TraceEntry>>) -> Vec<Occupant> {
+        held.into_iter()
+            .enumerate()
+            .map(|(stage, occupant)| match occupant {
+                None => Occupant::Bubble,
+                Some(entry)
+                    if [self.stages[stage], self.second_lane[stage]]
+                        .iter()
+                        .any(|slot| slot.map(|slot| slot.id) == Some(entry.id)) =>
+                {
+                    Occupant::Stalled(entry)
+                }
+                Some(entry) => Occupant::Active(entry),
+            })
+            .collect()
+    }
+
+    // Why an instruction waits in decode: a load-use hazard if one of the
+    // registers it waits for comes from a load
+    fn hazard(&self, raw: u32) -> StallReason {
+        let decoded = Instruction::new(raw);
+        let load_use = [decoded.get_rs1(), decoded.get_rs2()]
+            .iter()
+            .filter_map(|rs| *rs)
+            .any(|rs| rs != 0 && self.loaded[rs as usize]);
+        if load_use {
+            StallReason::LoadUse
+        } else {
+            StallReason::Dependency
+        }
+    }
+
+    // Check if the source registers of an instruction can be used in a cycle
+    fn operands_ready(&self, raw: u32, cycle: u64) -> bool {
+        let decoded = Instruction::new(raw);
+        [decoded.get_rs1(), decoded.get_rs2()]
+            .iter()
+            .filter_map(|rs| *rs)
+            .all(|rs| rs == 0 || self.ready[rs as usize] <= cycle)
+    }
+}
+
+// Count an instruction which retired
+fn count_retired(raw: u32, class: InstrClass, stats: &mut Stats) {
+    stats.instructions += 1;
+    stats.classes[class.index()] += 1;
+    match Instruction::new(raw).hint() {
+        Some(Hint::Nop) => stats.nops += 1,
+        Some(Hint::Hint) => stats.hints += 1,
+        None => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bus::Region;
+    use csr::CYCLE;
+    use mem::MemStoreOp;
+    use riscv::isa::RV32I;
+
+    // Run a program stored at address 0 until it reaches an invalid
+    // instruction
+    fn run(config: PipelineConfig, program: &[u32]) -> (Hart, Stats) {
+        let mut hart = Hart::new();
+        for (index, instruction) in program.iter().enumerate() {
+            hart.memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    (index as u32) << 2,
+                    *instruction,
+                )
+                .unwrap();
+        }
+
+        let mut pipeline = Pipeline::new(config);
+        let mut stats = Stats::new();
+        while pipeline.tick(&mut hart, &mut stats).is_ok() {}
+
+        (hart, stats)
+    }
+
+    fn config(stages: Stages) -> PipelineConfig {
+        PipelineConfig {
+            stages,
+            ..PipelineConfig::default()
+        }
+    }
+
+    const STRAIGHT_LINE: [u32; 4] = [
+        0x0050_0513, // li a0, 5
+        0x0070_0593, // li a1, 7
+        0x00b5_0633, // add a2, a0, a1
+        0x04c0_2023, // sw a2, 64(zero)
+    ];
+
+    ////////////////////////////////////////
+    // Configuration
+    ////////////////////////////////////////
+    #[test]
+    fn test_from_config() {
+        let parsed = Config::parse(
+            "[pipeline]\nstages = 3\n[latency]\nmul = 3\n\
+             [bus.dram]\nbase = 0x8000_0000\nsize = 0x100\nlatency = 20\n",
+        )
+        .unwrap();
+        let pipeline = PipelineConfig::from_config(&parsed).unwrap();
+
+        assert_eq!(Stages::Three, pipeline.stages);
+        assert_eq!(3, pipeline.latency.get(InstrClass::Mul));
+        assert_eq!(20, pipeline.bus.latency(0x8000_0000));
+        assert_eq!(Forwarding::default(), pipeline.forwarding);
+
+        let parsed = Config::parse("[pipeline]\nforward_mem_to_ex = false\n").unwrap();
+        let pipeline = PipelineConfig::from_config(&parsed).unwrap();
+        assert!(pipeline.forwarding.ex_to_ex);
+        assert!(!pipeline.forwarding.mem_to_ex);
+        assert_eq!(BranchResolution::Execute, pipeline.branch_resolution);
+
+        let parsed = Config::parse("[pipeline]\nbranch_resolution = \"decode\"\n").unwrap();
+        let pipeline = PipelineConfig::from_config(&parsed).unwrap();
+        assert_eq!(BranchResolution::Decode, pipeline.branch_resolution);
+    }
+
+    #[test]
+    fn test_from_config_invalid_stages() {
+        let parsed = Config::parse("[pipeline]\nstages = 5\n").unwrap();
+        assert!(PipelineConfig::from_config(&parsed).is_err());
+        let parsed = Config::parse("[pipeline]\nbranch_resolution = \"fetch\"\n").unwrap();
+        assert!(PipelineConfig::from_config(&parsed).is_err());
+        let parsed = Config::parse("[pipeline]\nstages = 1\nissue_width = 2\n").unwrap();
+        assert!(PipelineConfig::from_config(&parsed).is_err());
+    }
+
+    ////////////////////////////////////////
+    // Timing
+    ////////////////////////////////////////
+    #[test]
+    fn test_one_stage() {
+        let (hart, stats) = run(config(Stages::One), &STRAIGHT_LINE);
+
+        assert_eq!(4, stats.instructions);
+        assert_eq!(4, stats.cycles);
+        assert_eq!(12, hart.memory().read_pc(64));
+    }
+
+    #[test]
+    fn test_three_stages() {
+        let (hart, stats) = run(config(Stages::Three), &STRAIGHT_LINE);
+
+        // Two cycles to fill the pipeline, then one instruction per cycle
+        assert_eq!(4, stats.instructions);
+        assert_eq!(6, stats.cycles);
+        assert_eq!(12, hart.memory().read_pc(64));
+        assert_eq!(
+            (3, 1),
+            (
+                stats.retired(InstrClass::Alu),
+                stats.retired(InstrClass::Store)
+            )
+        );
+    }
+
+    #[test]
+    fn test_dual_issue() {
+        let parsed = Config::parse("[pipeline]\nissue_width = 2\n").unwrap();
+        let dual = PipelineConfig::from_config(&parsed).unwrap();
+        assert!(dual.dual_issue);
+
+        // The store depends on the addition, which splits the second pair
+        let (hart, stats) = run(dual.clone(), &STRAIGHT_LINE);
+        assert_eq!(4, stats.instructions);
+        assert_eq!(5, stats.cycles);
+        assert_eq!((1, 1), (stats.dual_issues, stats.split_pairs));
+        assert_eq!(12, hart.memory().read_pc(64));
+
+        // Loads share the memory port, and the last instruction waits for
+        // the invalid one it was paired with
+        let program = [
+            0x0400_2503, // lw a0, 64(zero)
+            0x0440_2583, // lw a1, 68(zero)
+            0x0010_0613, // li a2, 1
+            0x0020_0693, // li a3, 2
+            0x0030_0713, // li a4, 3
+        ];
+        let (hart, stats) = run(dual, &program);
+        assert_eq!(5, stats.instructions);
+        assert_eq!(2, stats.retired(InstrClass::Load));
+        assert_eq!((1, 1), (stats.dual_issues, stats.split_pairs));
+        assert_eq!((3, 2), hart.registers().read(14, 13));
+    }
+
+    #[test]
+    fn test_dual_issue_stop() {
+        // Hart counting the instructions it executed, including the ones
+        // which stopped
+        struct Counting {
+            hart: Hart,
+            executions: u32,
+        }
+
+        impl Core for Counting {
+            fn fetch(&self, pc: u32) -> u32 {
+                self.hart.fetch(pc)
+            }
+
+            fn execute(&mut self, pc: u32, raw: u32) -> Result<Executed, StopReason> {
+                self.executions += 1;
+                self.hart.execute(pc, raw)
+            }
+
+            fn last_write(&self) -> Option<(u8, u32)> {
+                self.hart.last_write()
+            }
+
+            fn destination(&self, raw: u32) -> Option<u8> {
+                Core::destination(&self.hart, raw)
+            }
+
+            fn resolve_control(&self, pc: u32, raw: u32) -> Option<u32> {
+                self.hart.resolve_control(pc, raw)
+            }
+
+            fn count_cycle(&mut self) {
+                self.hart.count_cycle()
+            }
+        }
+
+        let mut core = Counting {
+            hart: Hart::new(),
+            executions: 0,
+        };
+        let program = [
+            0x0050_0513, // li a0, 5
+            0x0420_2683, // lw a3, 66(zero)
+        ];
+        for (index, instruction) in program.iter().enumerate() {
+            core.hart
+                .memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), 4 * index as u32, *instruction)
+                .unwrap();
+        }
+        let mut dual = config(Stages::Three);
+        dual.dual_issue = true;
+        let mut pipeline = Pipeline::new(dual);
+        let mut stats = Stats::new();
+        let reason = loop {
+            if let Err(reason) = pipeline.tick(&mut core, &mut stats) {
+                break reason;
+            }
+        };
+
+        // The misaligned load of the second lane stops the simulation once
+        // the addition retired, and executes only once
+        assert!(matches!(reason, StopReason::MemoryFault { pc: 4, .. }));
+        assert_eq!(2, core.executions);
+        assert_eq!(1, stats.instructions);
+        assert_eq!((5, 0), core.hart.registers().read(10, 0));
+    }
+
+    #[test]
+    fn test_hints() {
+        let program = [
+            0x0000_0013, // nop
+            0x0000_0013, // nop
+            0x0010_0013, // addi x0, x0, 1
+            0x0050_0513, // addi a0, zero, 5
+        ];
+        let (_, stats) = run(config(Stages::Three), &program);
+
+        assert_eq!(4, stats.instructions);
+        assert_eq!((2, 1), (stats.nops, stats.hints));
+    }
+
+    #[test]
+    fn test_cycle_counter() {
+        let mut slow_loads = config(Stages::Three);
+        slow_loads.latency.set(InstrClass::Load, 5);
+        let program = [
+            0xc000_2573, // rdcycle a0
+            0x0400_2603, // lw a2, 64(zero)
+            0xc000_25f3, // rdcycle a1
+            0xc020_26f3, // rdinstret a3
+        ];
+        let (hart, stats) = run(slow_loads, &program);
+
+        // The load takes 5 cycles
+        let (start, end) = hart.registers().read(10, 11);
+        assert_eq!(6, end - start);
+        assert_eq!((3, 0), hart.registers().read(13, 0));
+        assert_eq!(Some(stats.cycles as u32), hart.csrs().read(CYCLE));
+    }
+
+    #[test]
+    fn test_latencies() {
+        let mut slow_stores = config(Stages::Three);
+        slow_stores.latency.set(InstrClass::Store, 5);
+        let (_, stats) = run(slow_stores, &STRAIGHT_LINE);
+        assert_eq!(10, stats.cycles);
+
+        let mut slow_alu = config(Stages::One);
+        slow_alu.latency.set(InstrClass::Alu, 2);
+        let (_, stats) = run(slow_alu, &STRAIGHT_LINE);
+        assert_eq!(7, stats.cycles);
+    }
+
+    #[test]
+    fn test_fetch_wait_states() {
+        let mut slow_fetch = config(Stages::One);
+        slow_fetch.bus.add_region(Region::new("rom", 0, 0x40, 3));
+        let (_, stats) = run(slow_fetch, &STRAIGHT_LINE);
+
+        // Every fetch, including the invalid instruction that stops the
+        // simulation, waits two cycles
+        assert_eq!(4, stats.instructions);
+        assert_eq!(4 * 3 + 2, stats.cycles);
+        assert_eq!(5 * 2, stats.fetch_stalls);
+        assert_eq!(0, stats.data_stalls);
+    }
+
+    #[test]
+    fn test_data_wait_states() {
+        let mut slow_data = config(Stages::Three);
+        slow_data.bus.add_region(Region::new("dram", 0x40, 0x40, 5));
+        let (hart, stats) = run(slow_data, &STRAIGHT_LINE);
+
+        // Only the store accesses the slow region
+        assert_eq!(6 + 4, stats.cycles);
+        assert_eq!(0, stats.fetch_stalls);
+        assert_eq!(4, stats.data_stalls);
+        assert_eq!(12, hart.memory().read_pc(64));
+    }
+
+    #[test]
+    fn test_stall_reasons() {
+        // Every cycle retires an instruction or stalls for a single reason
+        let bottlenecks = |config: PipelineConfig, program: &[u32]| {
+            let (_, stats) = run(config, program);
+            let stalled: u64 = stats.stall_cycles.iter().sum();
+            assert_eq!(stats.cycles - stats.instructions, stalled);
+            stats.bottlenecks()
+        };
+        let jump = [
+            0x0080_006f, // j 8
+            0x0010_0513, // li a0, 1 (skipped)
+            0x0020_0593, // li a1, 2
+        ];
+        let load_use = [
+            0x0400_2503, // lw a0, 64(zero)
+            0x0015_0593, // addi a1, a0, 1
+        ];
+
+        // Filling the pipeline waits for the fetch
+        assert_eq!(
+            vec![(StallReason::Fetch, 2)],
+            bottlenecks(config(Stages::Three), &STRAIGHT_LINE)
+        );
+        assert_eq!(
+            vec![(StallReason::Fetch, 2), (StallReason::BranchFlush, 2)],
+            bottlenecks(config(Stages::Three), &jump)
+        );
+        let mut slow_stores = config(Stages::Three);
+        slow_stores.latency.set(InstrClass::Store, 5);
+        assert_eq!(
+            vec![(StallReason::ExecuteBusy, 4), (StallReason::Fetch, 2)],
+            bottlenecks(slow_stores, &STRAIGHT_LINE)
+        );
+        let mut slow_data = config(Stages::Three);
+        slow_data.bus.add_region(Region::new("dram", 0x40, 0x40, 5));
+        assert_eq!(
+            vec![(StallReason::Memory, 4), (StallReason::Fetch, 2)],
+            bottlenecks(slow_data, &STRAIGHT_LINE)
+        );
+        let no_forwarding = PipelineConfig {
+            forwarding: Forwarding {
+                ex_to_ex: false,
+                mem_to_ex: false,
+            },
+            ..config(Stages::Three)
+        };
+        assert_eq!(
+            vec![(StallReason::Fetch, 2), (StallReason::LoadUse, 1)],
+            bottlenecks(no_forwarding.clone(), &load_use)
+        );
+        assert_eq!(
+            vec![(StallReason::Fetch, 2), (StallReason::Dependency, 2)],
+            bottlenecks(no_forwarding, &STRAIGHT_LINE[1..])
+        );
+    }
+
+    #[test]
+    fn test_misaligned_accesses() {
+        let program = [
+            0xfff0_0513, // li a0, -1
+            0x04a0_2123, // sw a0, 66(zero)
+            0x0420_2583, // lw a1, 66(zero)
+        ];
+        let mut hart = Hart::new();
+        hart.memory_mut().set_misaligned_access(true);
+        for (index, instruction) in program.iter().enumerate() {
+            hart.memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    (index as u32) << 2,
+                    *instruction,
+                )
+                .unwrap();
+        }
+        let mut slow_data = config(Stages::One);
+        slow_data.bus.add_region(Region::new("dram", 0x44, 0x40, 3));
+        let mut pipeline = Pipeline::new(slow_data);
+        let mut stats = Stats::new();
+        while pipeline.tick(&mut hart, &mut stats).is_ok() {}
+
+        // Both accesses take an extra cycle for the second word, which is
+        // in the slow region
+        assert_eq!((-1, -1), hart.registers().read(10, 11));
+        assert_eq!(3 + 2 * 3, stats.cycles);
+        assert_eq!(2 * 3, stats.data_stalls);
+    }
+
+    #[test]
+    fn test_forwarding() {
+        let alu_use = [
+            0x0050_0513, // li a0, 5
+            0x0015_0593, // addi a1, a0, 1
+        ];
+        let load_use = [
+            0x0400_2503, // lw a0, 64(zero)
+            0x0015_0593, // addi a1, a0, 1
+        ];
+        let with = |ex_to_ex, mem_to_ex| PipelineConfig {
+            forwarding: Forwarding {
+                ex_to_ex,
+                mem_to_ex,
+            },
+            ..config(Stages::Three)
+        };
+
+        let (hart, stats) = run(with(true, true), &alu_use);
+        assert_eq!(4, stats.cycles);
+        assert_eq!(0, stats.hazard_stalls);
+        assert_eq!((5, 6), hart.registers().read(10, 11));
+
+        let (_, stats) = run(with(false, true), &alu_use);
+        assert_eq!(5, stats.cycles);
+        assert_eq!(1, stats.hazard_stalls);
+
+        // Loads only use the MEM to EX path
+        let (_, stats) = run(with(false, true), &load_use);
+        assert_eq!(4, stats.cycles);
+        let (_, stats) = run(with(true, false), &load_use);
+        assert_eq!(5, stats.cycles);
+        assert_eq!(1, stats.hazard_stalls);
+
+        // Independent instructions don't wait
+        let (_, stats) = run(with(false, false), &STRAIGHT_LINE[..2]);
+        assert_eq!(4, stats.cycles);
+
+        // Nor does the single stage pipeline
+        let single = PipelineConfig {
+            stages: Stages::One,
+            ..with(false, false)
+        };
+        let (_, stats) = run(single, &alu_use);
+        assert_eq!(2, stats.cycles);
+    }
+
+    #[test]
+    fn test_branch_resolution() {
+        let program = [
+            0x0080_006f, // j 8
+            0x0010_0513, // li a0, 1 (skipped)
+            0x0020_0593, // li a1, 2
+        ];
+        let in_decode = PipelineConfig {
+            branch_resolution: BranchResolution::Decode,
+            ..config(Stages::Three)
+        };
+
+        // Resolving in decode only flushes the fetched instruction
+        let (hart, stats) = run(in_decode.clone(), &program);
+        assert_eq!(2, stats.instructions);
+        assert_eq!(5, stats.cycles);
+        assert_eq!((0, 2), hart.registers().read(10, 11));
+        assert_eq!((1, 1), (stats.mispredictions, stats.wrong_path));
+
+        // A branch depending on the previous instruction waits for it in
+        // decode
+        let dependent = [
+            0x0010_0513, // li a0, 1
+            0x0005_1463, // bnez a0, 8
+            0x0010_0593, // li a1, 1 (skipped)
+            0x0020_0613, // li a2, 2
+        ];
+        let (hart, stats) = run(in_decode.clone(), &dependent);
+        assert_eq!(3, stats.instructions);
+        assert_eq!(2 + 3 + 1 + 1, stats.cycles);
+        assert_eq!(1, stats.hazard_stalls);
+        assert_eq!((0, 2), hart.registers().read(11, 12));
+
+        // Which costs the same as resolving in execute
+        let (_, stats) = run(config(Stages::Three), &dependent);
+        assert_eq!(2 + 3 + 2, stats.cycles);
+
+        // Not taken branches don't flush anything
+        let not_taken = [
+            0x0000_1463, // bnez zero, 8
+            0x0010_0593, // li a1, 1
+        ];
+        let (hart, stats) = run(in_decode, &not_taken);
+        assert_eq!(4, stats.cycles);
+        assert_eq!((1, 0), hart.registers().read(11, 0));
+    }
+
+    #[test]
+    fn test_taken_branch_flushes() {
+        let program = [
+            0x0080_006f, // j 8
+            0x0010_0513, // li a0, 1 (skipped)
+            0x0020_0593, // li a1, 2
+        ];
+
+        // The jump costs two wrong path cycles in the three stage pipeline
+        let (hart, stats) = run(config(Stages::Three), &program);
+        assert_eq!(2, stats.instructions);
+        assert_eq!(6, stats.cycles);
+        assert_eq!((0, 2), hart.registers().read(10, 11));
+        assert_eq!(2, stats.wrong_path);
+
+        // And nothing in the single stage configuration
+        let (hart, stats) = run(config(Stages::One), &program);
+        assert_eq!(2, stats.instructions);
+        assert_eq!(2, stats.cycles);
+        assert_eq!((0, 2), hart.registers().read(10, 11));
+        assert_eq!((1, 0), (stats.mispredictions, stats.wrong_path));
+    }
+
+    #[test]
+    fn test_branch_target_buffer() {
+        let program = [
+            0x00a0_0513, // li a0, 10
+            0xfff5_0513, // loop: addi a0, a0, -1
+            0xfe05_1ee3, // bnez a0, loop
+        ];
+        let with_btb = PipelineConfig {
+            frontend: FrontendConfig {
+                btb_entries: 4,
+                ras_depth: 0,
+            },
+            ..config(Stages::Three)
+        };
+        let (_, baseline) = run(config(Stages::Three), &program);
+        let (hart, stats) = run(with_btb, &program);
+        assert_eq!((0, 0), hart.registers().read(10, 0));
+        assert_eq!(baseline.instructions, stats.instructions);
+        // Only the first iteration and the exit flush the wrong path
+        assert_eq!((9, 2), (baseline.mispredictions, stats.mispredictions));
+        assert_eq!((9, 8), (stats.btb_lookups, stats.btb_hits));
+        assert_eq!(baseline.cycles - 7 * 2, stats.cycles);
+        // Two instructions behind each misprediction were fetched for nothing
+        assert_eq!((18, 4), (baseline.wrong_path, stats.wrong_path));
+    }
+
+    #[test]
+    fn test_cycle_records() {
+        let mut hart = Hart::new();
+        for (index, instruction) in [0x0080_006f, 0x0010_0513].iter().enumerate() {
+            hart.memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    (index as u32) << 2,
+                    *instruction,
+                )
+                .unwrap();
+        }
+        let mut pipeline = Pipeline::new(config(Stages::Three));
+        let mut stats = Stats::new();
+        let jump = TraceEntry {
+            id: 0,
+            pc: 0,
+            raw: 0x0080_006f,
+        };
+
+        pipeline.tick(&mut hart, &mut stats).unwrap();
+        pipeline.tick(&mut hart, &mut stats).unwrap();
+        assert_eq!(
+            vec![
+                Occupant::Active(TraceEntry {
+                    id: 1,
+                    pc: 4,
+                    raw: 0x0010_0513
+                }),
+                Occupant::Active(jump),
+                Occupant::Bubble,
+            ],
+            pipeline.last_cycle().stages
+        );
+
+        // The jump executes and flushes the instructions behind it
+        pipeline.tick(&mut hart, &mut stats).unwrap();
+        let record = pipeline.last_cycle();
+        assert_eq!(3, record.cycle);
+        assert_eq!(
+            vec![Occupant::Bubble, Occupant::Bubble, Occupant::Active(jump)],
+            record.stages
+        );
+        assert_eq!(
+            vec![8, 4],
+            record.flushed.iter().map(|e| e.pc).collect::<Vec<_>>()
+        );
+        assert_eq!(Some(jump), record.retired);
+
+        // Multi cycle instructions stall in the execute stage
+        let mut slow_alu = config(Stages::One);
+        slow_alu.latency.set(InstrClass::Alu, 2);
+        let mut pipeline = Pipeline::new(slow_alu);
+        pipeline.reset(4);
+        pipeline.tick(&mut hart, &mut stats).unwrap();
+        match pipeline.last_cycle().stages[0] {
+            Occupant::Stalled(entry) => assert_eq!(4, entry.pc),
+            ref occupant => panic!("unexpected {:?}", occupant),
+        }
+        assert_eq!(None, pipeline.last_cycle().retired);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut hart = Hart::new();
+        hart.memory_mut()
+            .write_data(&MemStoreOp::from(RV32I::SW), 0x100, 0x0050_0513)
+            .unwrap();
+
+        let mut pipeline = Pipeline::new(config(Stages::One));
+        let mut stats = Stats::new();
+        pipeline.reset(0x100);
+        pipeline.tick(&mut hart, &mut stats).unwrap();
+
+        assert_eq!((5, 0), hart.registers().read(10, 0));
+        assert_eq!(0x104, hart.pc());
+    }
+}
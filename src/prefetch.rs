@@ -0,0 +1,199 @@
+//! Prefetcher models of the caches, which guess the lines a stream of
+//! accesses needs next and fill them ahead of their use:
+//!
+//! * next line => a miss, or the first hit on a prefetched line, prefetches
+//!   the following line
+//! * stride => a table indexed by the pc of the access keeps the last address
+//!   and stride of each load or store, and prefetches the next address once
+//!   the same stride repeats. The streams follow the instructions, so it only
+//!   suits the data cache.
+//!
+//! The accuracy of a prefetcher is the share of its prefetches used before
+//! their eviction, and its coverage the share of the misses it removed.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cache::{Cache, CacheConfig};
+//! # use adept_lib::prefetch::{PrefetchKind, Prefetcher};
+//! let mut cache = Cache::new(CacheConfig::new(1024, 16, 2).unwrap());
+//! let mut prefetcher = Prefetcher::new(PrefetchKind::NextLine);
+//! for addr in (0..0x100).step_by(4) {
+//!     prefetcher.access(&mut cache, 0, addr);
+//! }
+//! // Only the first line misses
+//! assert_eq!(1, cache.misses());
+//! assert_eq!(15, cache.useful_prefetches());
+//! ```
+use cache::Cache;
+
+/// Entries of the table of a stride prefetcher
+pub const STRIDE_ENTRIES: usize = 16;
+
+/// Prefetching policies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefetchKind {
+    /// Prefetch the line after the one accessed
+    NextLine,
+    /// Prefetch the next address of the instructions repeating a stride
+    Stride,
+}
+
+impl PrefetchKind {
+    /// Every policy, from the simplest one
+    pub const ALL: [PrefetchKind; 2] = [PrefetchKind::NextLine, PrefetchKind::Stride];
+
+    /// Name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            PrefetchKind::NextLine => "next-line",
+            PrefetchKind::Stride => "stride",
+        }
+    }
+
+    /// Find a policy by name
+    pub fn from_name(name: &str) -> Option<PrefetchKind> {
+        Self::ALL.iter().cloned().find(|kind| kind.name() == name)
+    }
+}
+
+/// Prefetchers of the instruction and the data caches, if any
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PrefetchConfig {
+    pub icache: Option<PrefetchKind>,
+    pub dcache: Option<PrefetchKind>,
+}
+
+// Stream of a load or a store in the table of a stride prefetcher
+#[derive(Debug, Clone, Copy)]
+struct Stream {
+    pc: u32,
+    last: u32,
+    stride: u32,
+}
+
+/// Prefetcher in front of a cache
+#[derive(Debug, Clone)]
+pub struct Prefetcher {
+    kind: PrefetchKind,
+    // Streams by pc, for the stride prefetcher
+    streams: Vec<Option<Stream>>,
+}
+
+impl Prefetcher {
+    pub fn new(kind: PrefetchKind) -> Self {
+        Prefetcher {
+            kind,
+            streams: vec![None; STRIDE_ENTRIES],
+        }
+    }
+
+    pub fn kind(&self) -> PrefetchKind {
+        self.kind
+    }
+
+    /// Access a cache and prefetch the line the access predicts
+    ///
+    /// # Arguments
+    /// * `cache` => cache the prefetcher fills
+    /// * `pc` => address of the instruction accessing the cache
+    /// * `addr` => address accessed
+    ///
+    /// # Return Value
+    /// True on a hit
+    pub fn access(&mut self, cache: &mut Cache, pc: u32, addr: u32) -> bool {
+        let useful = cache.useful_prefetches();
+        let hit = cache.access(addr);
+        let target = match self.kind {
+            PrefetchKind::NextLine if !hit || cache.useful_prefetches() > useful => {
+                let line = cache.config().line;
+                Some((addr & !(line - 1)).wrapping_add(line))
+            }
+            PrefetchKind::NextLine => None,
+            PrefetchKind::Stride => self.stride(pc, addr),
+        };
+        if let Some(target) = target {
+            cache.prefetch(target);
+        }
+        hit
+    }
+
+    // Follow the stream of an instruction
+    //
+    // Return Value
+    // The next address once the stride repeats
+    fn stride(&mut self, pc: u32, addr: u32) -> Option<u32> {
+        let entry = &mut self.streams[(pc >> 2) as usize % STRIDE_ENTRIES];
+        match *entry {
+            Some(ref mut stream) if stream.pc == pc => {
+                let stride = addr.wrapping_sub(stream.last);
+                let repeated = stride != 0 && stride == stream.stride;
+                stream.last = addr;
+                stream.stride = stride;
+                if repeated {
+                    Some(addr.wrapping_add(stride))
+                } else {
+                    None
+                }
+            }
+            _ => {
+                *entry = Some(Stream {
+                    pc,
+                    last: addr,
+                    stride: 0,
+                });
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cache::CacheConfig;
+
+    fn cache() -> Cache {
+        Cache::new(CacheConfig::new(1024, 16, 2).unwrap())
+    }
+
+    #[test]
+    fn test_names() {
+        for kind in PrefetchKind::ALL.iter() {
+            assert_eq!(Some(*kind), PrefetchKind::from_name(kind.name()));
+        }
+        assert_eq!(None, PrefetchKind::from_name("markov"));
+    }
+
+    #[test]
+    fn test_next_line() {
+        let mut cache = cache();
+        let mut prefetcher = Prefetcher::new(PrefetchKind::NextLine);
+        // Every other line: the prefetches are never used
+        for addr in (0..0x200).step_by(0x20) {
+            assert!(!prefetcher.access(&mut cache, 0, addr));
+        }
+        assert_eq!((16, 0), (cache.prefetches(), cache.useful_prefetches()));
+    }
+
+    #[test]
+    fn test_stride() {
+        let mut cache = cache();
+        let mut prefetcher = Prefetcher::new(PrefetchKind::Stride);
+        // Two loads walk arrays with strides of 64 and 4 bytes
+        let hits: Vec<bool> = (0..8)
+            .map(|index| {
+                let far = prefetcher.access(&mut cache, 0x100, 0x1000 + 64 * index);
+                prefetcher.access(&mut cache, 0x104, 0x2000 + 4 * index);
+                far
+            })
+            .collect();
+        // The stride shows on the third access, which prefetches the fourth
+        assert_eq!(
+            vec![false, false, false, true, true, true, true, true],
+            hits
+        );
+        // The 4 byte stride only prefetches when it crosses a line
+        assert_eq!((8, 6), (cache.prefetches(), cache.useful_prefetches()));
+    }
+}
@@ -0,0 +1,677 @@
+//! Basic block profiles built from the code coverage. Blocks start at the
+//! targets of branches and jumps and end at control flow instructions, and
+//! every edge between two blocks is counted, so the profile can feed
+//! profile guided optimizations or be drawn as a control flow graph with
+//! Graphviz.
+//!
+//! The cycle profile charges every cycle of the pipeline, and the reason of
+//! every stall cycle, to the oldest instruction in flight, which is the one
+//! in the execute stage unless the stage is empty. The cycles are then added
+//! up by function into a table, sorted by the total cycles or by the stall
+//! cycles of a reason:
+//!
+//! ```text
+//! function    cycles  stalled  fetch  branch flush  load-use ...
+//! memcpy         840      312      2            40       270 ...
+//! main           120       38     10            28         0 ...
+//! ```
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::coverage::Coverage;
+//! # use adept_lib::latency::InstrClass;
+//! # use adept_lib::profile::{EdgeKind, Profile};
+//! let mut coverage = Coverage::new();
+//! // A loop of two instructions running twice
+//! for next_pc in &[0x100, 0x108] {
+//!     coverage.execute(0x100, InstrClass::Alu, 0x104);
+//!     coverage.execute(0x104, InstrClass::Branch, *next_pc);
+//! }
+//! let profile = Profile::new(&coverage);
+//! assert_eq!(1, profile.blocks().len());
+//! assert_eq!(2, profile.blocks()[0].count);
+//! assert_eq!(EdgeKind::Taken, profile.edges()[0].kind);
+//! ```
+use coverage::Coverage;
+use elf::Symbol;
+use stats::StallReason;
+use trace::{CycleRecord, Occupant};
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+/// Sequence of instructions which always execute together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Block {
+    /// Address of the first instruction
+    pub start: u32,
+    /// Address of the last instruction
+    pub end: u32,
+    /// Number of times the block was entered
+    pub count: u64,
+}
+
+/// How the control flow moved between two blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// To the next instruction in memory
+    Fallthrough,
+    /// A taken branch
+    Taken,
+    /// A jump, a call or a return
+    Jump,
+}
+
+impl EdgeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeKind::Fallthrough => "fallthrough",
+            EdgeKind::Taken => "taken",
+            EdgeKind::Jump => "jump",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "fallthrough" => Some(EdgeKind::Fallthrough),
+            "taken" => Some(EdgeKind::Taken),
+            "jump" => Some(EdgeKind::Jump),
+            _ => None,
+        }
+    }
+}
+
+/// Control flow between the end of a block and the start of another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    /// Start of the source block
+    pub from: u32,
+    /// Start of the destination block
+    pub to: u32,
+    pub kind: EdgeKind,
+    pub count: u64,
+}
+
+/// Executed blocks and the edges between them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    blocks: Vec<Block>,
+    edges: Vec<Edge>,
+}
+
+impl Profile {
+    /// Split the executed instructions into blocks
+    ///
+    /// # Arguments
+    /// * `coverage` => coverage of the whole run
+    pub fn new(coverage: &Coverage) -> Self {
+        let control = coverage.edges();
+        let sources: HashSet<u32> = control.iter().map(|(from, _, _)| *from).collect();
+        let targets: HashSet<u32> = control.iter().map(|(_, to, _)| *to).collect();
+
+        // Blocks end at control flow instructions, before targets and at
+        // gaps in the executed addresses
+        let mut blocks: Vec<Block> = Vec::new();
+        for pc in coverage.addresses() {
+            match blocks.last_mut() {
+                Some(block)
+                    if block.end.wrapping_add(4) == pc
+                        && !sources.contains(&block.end)
+                        && !targets.contains(&pc) =>
+                {
+                    block.end = pc
+                }
+                _ => blocks.push(Block {
+                    start: pc,
+                    end: pc,
+                    count: coverage.count(pc),
+                }),
+            }
+        }
+
+        let start_of = |pc: u32| -> Option<u32> {
+            let index = match blocks.binary_search_by_key(&pc, |block| block.start) {
+                Ok(index) => index,
+                Err(index) => index.checked_sub(1)?,
+            };
+            Some(blocks[index].start).filter(|_| pc <= blocks[index].end)
+        };
+
+        let mut edges = Vec::new();
+        for (from, to, count) in control {
+            let kind = if to == from.wrapping_add(4) {
+                EdgeKind::Fallthrough
+            } else if coverage.is_branch(from) {
+                EdgeKind::Taken
+            } else {
+                EdgeKind::Jump
+            };
+            if let (Some(from), Some(to)) = (start_of(from), start_of(to)) {
+                edges.push(Edge {
+                    from,
+                    to,
+                    kind,
+                    count,
+                });
+            }
+        }
+        // Blocks which end before a target fall through into it
+        for (block, next) in blocks.iter().zip(blocks.iter().skip(1)) {
+            if block.end.wrapping_add(4) == next.start && !sources.contains(&block.end) {
+                edges.push(Edge {
+                    from: block.start,
+                    to: next.start,
+                    kind: EdgeKind::Fallthrough,
+                    count: coverage.count(block.end),
+                });
+            }
+        }
+        edges.sort_unstable_by_key(|edge| (edge.from, edge.to));
+
+        Profile { blocks, edges }
+    }
+
+    /// Read a profile written by write_text
+    ///
+    /// # Return Value
+    /// The profile, or the number of the first invalid line
+    pub fn parse_text(text: &str) -> Result<Self, usize> {
+        let mut profile = Profile::default();
+        for (index, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let address = |field: usize| {
+                fields
+                    .get(field)
+                    .and_then(|field| u32::from_str_radix(field.trim_start_matches("0x"), 16).ok())
+            };
+            let parsed = match fields.first() {
+                Some(&"block") => match (address(1), address(2), fields.get(3)) {
+                    (Some(start), Some(end), Some(count)) => count.parse().ok().map(|count| {
+                        profile.blocks.push(Block { start, end, count });
+                    }),
+                    _ => None,
+                },
+                Some(&"edge") => match (address(1), address(2), fields.get(3), fields.get(4)) {
+                    (Some(from), Some(to), Some(kind), Some(count)) => {
+                        match (EdgeKind::parse(kind), count.parse()) {
+                            (Some(kind), Ok(count)) => {
+                                profile.edges.push(Edge {
+                                    from,
+                                    to,
+                                    kind,
+                                    count,
+                                });
+                                Some(())
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                },
+                None => Some(()),
+                _ => None,
+            };
+            if parsed.is_none() {
+                return Err(index + 1);
+            }
+        }
+        profile.blocks.sort_unstable_by_key(|block| block.start);
+        profile
+            .edges
+            .sort_unstable_by_key(|edge| (edge.from, edge.to));
+        Ok(profile)
+    }
+
+    /// Executed blocks, in increasing order
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Edges between the blocks, sorted by source and destination
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Write one line per block with its addresses, its count and its
+    /// function, followed by one line per edge with its kind and its count
+    ///
+    /// # Arguments
+    /// * `out` => destination of the profile
+    /// * `functions` => functions sorted by address
+    pub fn write_text<W: Write>(&self, out: &mut W, functions: &[Symbol]) -> io::Result<()> {
+        for block in &self.blocks {
+            write!(
+                out,
+                "block 0x{:08x} 0x{:08x} {}",
+                block.start, block.end, block.count
+            )?;
+            if let Some(name) = block_name(functions, block.start) {
+                write!(out, " {}", name)?;
+            }
+            writeln!(out)?;
+        }
+        for edge in &self.edges {
+            writeln!(
+                out,
+                "edge 0x{:08x} 0x{:08x} {} {}",
+                edge.from,
+                edge.to,
+                edge.kind.name(),
+                edge.count
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the control flow graph in the dot language of Graphviz. Hot
+    /// edges are drawn thicker, and jumps are dashed.
+    ///
+    /// # Arguments
+    /// * `out` => destination of the graph
+    /// * `functions` => functions sorted by address
+    pub fn write_dot<W: Write>(&self, out: &mut W, functions: &[Symbol]) -> io::Result<()> {
+        writeln!(out, "digraph cfg {{")?;
+        writeln!(out, "    node [shape=box, fontname=monospace];")?;
+        for block in &self.blocks {
+            let name = block_name(functions, block.start)
+                .unwrap_or_else(|| format!("0x{:08x}", block.start));
+            writeln!(
+                out,
+                "    b{:08x} [label=\"{}\\n{} executions\"];",
+                block.start, name, block.count
+            )?;
+        }
+
+        let hottest = self.edges.iter().map(|edge| edge.count).max().unwrap_or(0);
+        for edge in &self.edges {
+            let width = 1.0 + 4.0 * edge.count as f64 / hottest.max(1) as f64;
+            write!(
+                out,
+                "    b{:08x} -> b{:08x} [label=\"{}\", penwidth={:.1}",
+                edge.from, edge.to, edge.count, width
+            )?;
+            if edge.kind == EdgeKind::Jump {
+                write!(out, ", style=dashed")?;
+            }
+            writeln!(out, "];")?;
+        }
+        writeln!(out, "}}")
+    }
+}
+
+/// Cycles charged to an instruction address or to a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CycleCounts {
+    /// Every cycle, stalled or not
+    pub cycles: u64,
+    /// Stall cycles by reason, in the order of `StallReason::ALL`
+    pub stalls: [u64; 7],
+}
+
+impl CycleCounts {
+    /// Cycles in which nothing retired
+    pub fn stalled(&self) -> u64 {
+        self.stalls.iter().sum()
+    }
+
+    fn add(&mut self, other: &CycleCounts) {
+        self.cycles += other.cycles;
+        for (stalls, other) in self.stalls.iter_mut().zip(&other.stalls) {
+            *stalls += *other;
+        }
+    }
+}
+
+/// Order of the rows of the function table, the largest count first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileSort {
+    Cycles,
+    Stalls(StallReason),
+}
+
+/// Cycles of a run charged to the instructions in flight
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CycleProfile {
+    counts: HashMap<u32, CycleCounts>,
+    // Instruction charged while the pipeline is empty
+    last_pc: u32,
+}
+
+impl CycleProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Charge a cycle to the oldest instruction in the pipeline
+    ///
+    /// # Arguments
+    /// * `record` => activity of the pipeline during the cycle
+    pub fn record(&mut self, record: &CycleRecord) {
+        if let Some(entry) = record.stages.iter().rev().find_map(Occupant::entry) {
+            self.last_pc = entry.pc;
+        }
+        let counts = self.counts.entry(self.last_pc).or_default();
+        counts.cycles += 1;
+        if let Some(reason) = record.stall {
+            counts.stalls[reason.index()] += 1;
+        }
+    }
+
+    /// Cycles charged to an instruction address
+    pub fn counts(&self, pc: u32) -> CycleCounts {
+        self.counts.get(&pc).cloned().unwrap_or_default()
+    }
+
+    /// Add the cycles up by function, named by the symbol which holds their
+    /// address or `[unknown]`
+    ///
+    /// # Arguments
+    /// * `functions` => functions sorted by address
+    /// * `sort` => order of the functions
+    pub fn functions(&self, functions: &[Symbol], sort: ProfileSort) -> Vec<(String, CycleCounts)> {
+        let mut totals: HashMap<&str, CycleCounts> = HashMap::new();
+        for (pc, counts) in &self.counts {
+            let name = Symbol::lookup(functions, *pc).map_or("[unknown]", |symbol| &symbol.name);
+            totals.entry(name).or_default().add(counts);
+        }
+        let mut totals: Vec<(String, CycleCounts)> = totals
+            .into_iter()
+            .map(|(name, counts)| (name.to_string(), counts))
+            .collect();
+        totals.sort_by_key(|(name, counts)| {
+            let key = match sort {
+                ProfileSort::Cycles => counts.cycles,
+                ProfileSort::Stalls(reason) => counts.stalls[reason.index()],
+            };
+            (Reverse(key), name.clone())
+        });
+        totals
+    }
+
+    /// Write the cycles of every function as a table, with a column per
+    /// stall reason
+    ///
+    /// # Arguments
+    /// * `out` => destination of the table
+    /// * `functions` => functions sorted by address
+    /// * `sort` => order of the rows
+    pub fn write_table<W: Write>(
+        &self,
+        out: &mut W,
+        functions: &[Symbol],
+        sort: ProfileSort,
+    ) -> io::Result<()> {
+        let rows = self.functions(functions, sort);
+        let width = rows
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain(Some("function".len()))
+            .max()
+            .unwrap_or(0);
+        let headers: Vec<&str> = ["cycles", "stalled"]
+            .iter()
+            .cloned()
+            .chain(StallReason::ALL.iter().map(|reason| reason.name()))
+            .collect();
+
+        write!(out, "{:w$}", "function", w = width)?;
+        for header in &headers {
+            write!(out, "  {}", header)?;
+        }
+        writeln!(out)?;
+        for (name, counts) in &rows {
+            write!(out, "{:w$}", name, w = width)?;
+            let values = [counts.cycles, counts.stalled()]
+                .iter()
+                .chain(&counts.stalls)
+                .cloned()
+                .collect::<Vec<u64>>();
+            for (header, value) in headers.iter().zip(values) {
+                write!(out, "  {:>w$}", value, w = header.len())?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+// Function and offset of the start of a block
+fn block_name(functions: &[Symbol], start: u32) -> Option<String> {
+    Symbol::lookup(functions, start)
+        .map(|symbol| format!("{}+0x{:x}", symbol.name, start - symbol.value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use latency::InstrClass;
+    use trace::TraceEntry;
+
+    // li a0, 2 at 0x100, a loop of addi and bnez at 0x104 and a call to
+    // 0x200 after it, which returns right away
+    fn coverage() -> Coverage {
+        let mut coverage = Coverage::new();
+        coverage.execute(0x100, InstrClass::Alu, 0x104);
+        for next_pc in &[0x104, 0x10c] {
+            coverage.execute(0x104, InstrClass::Alu, 0x108);
+            coverage.execute(0x108, InstrClass::Branch, *next_pc);
+        }
+        coverage.execute(0x10c, InstrClass::Jump, 0x200);
+        coverage.execute(0x200, InstrClass::Jump, 0x110);
+        coverage.execute(0x110, InstrClass::Alu, 0x114);
+        coverage
+    }
+
+    #[test]
+    fn test_blocks() {
+        let profile = Profile::new(&coverage());
+        assert_eq!(
+            &[
+                Block {
+                    start: 0x100,
+                    end: 0x100,
+                    count: 1
+                },
+                Block {
+                    start: 0x104,
+                    end: 0x108,
+                    count: 2
+                },
+                Block {
+                    start: 0x10c,
+                    end: 0x10c,
+                    count: 1
+                },
+                Block {
+                    start: 0x110,
+                    end: 0x110,
+                    count: 1
+                },
+                Block {
+                    start: 0x200,
+                    end: 0x200,
+                    count: 1
+                },
+            ],
+            profile.blocks()
+        );
+    }
+
+    #[test]
+    fn test_edges() {
+        let profile = Profile::new(&coverage());
+        let edges: Vec<_> = profile
+            .edges()
+            .iter()
+            .map(|edge| (edge.from, edge.to, edge.kind, edge.count))
+            .collect();
+        assert_eq!(
+            vec![
+                (0x100, 0x104, EdgeKind::Fallthrough, 1),
+                (0x104, 0x104, EdgeKind::Taken, 1),
+                (0x104, 0x10c, EdgeKind::Fallthrough, 1),
+                (0x10c, 0x200, EdgeKind::Jump, 1),
+                (0x200, 0x110, EdgeKind::Jump, 1),
+            ],
+            edges
+        );
+    }
+
+    #[test]
+    fn test_write_text() {
+        let functions = vec![Symbol {
+            name: "f".to_string(),
+            value: 0x200,
+            size: 4,
+        }];
+        let mut out = Vec::new();
+        Profile::new(&coverage())
+            .write_text(&mut out, &functions)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("block 0x00000100 0x00000100 1\n"));
+        assert!(out.contains("block 0x00000200 0x00000200 1 f+0x0\n"));
+        assert!(out.contains("edge 0x00000104 0x00000104 taken 1\n"));
+        assert!(out.ends_with("edge 0x00000200 0x00000110 jump 1\n"));
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let profile = Profile::new(&coverage());
+        let functions = vec![Symbol {
+            name: "f".to_string(),
+            value: 0x200,
+            size: 4,
+        }];
+        let mut out = Vec::new();
+        profile.write_text(&mut out, &functions).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(Ok(profile), Profile::parse_text(&text));
+        assert_eq!(
+            Err(2),
+            Profile::parse_text("block 0x100 0x104 1\nedge 0x100 0x104 call 1\n")
+        );
+    }
+
+    // A cycle of the three stage pipeline
+    fn cycle(stages: [Option<u32>; 3], stall: Option<StallReason>) -> CycleRecord {
+        CycleRecord {
+            stages: stages
+                .iter()
+                .map(|pc| match pc {
+                    Some(pc) => Occupant::Active(TraceEntry {
+                        id: 0,
+                        pc: *pc,
+                        raw: 0,
+                    }),
+                    None => Occupant::Bubble,
+                })
+                .collect(),
+            stall,
+            ..CycleRecord::default()
+        }
+    }
+
+    #[test]
+    fn test_cycle_profile() {
+        let mut profile = CycleProfile::new();
+        profile.record(&cycle([Some(0x100), None, None], Some(StallReason::Fetch)));
+        profile.record(&cycle(
+            [Some(0x104), Some(0x100), None],
+            Some(StallReason::Fetch),
+        ));
+        profile.record(&cycle([Some(0x108), Some(0x104), Some(0x100)], None));
+        profile.record(&cycle([Some(0x204), None, Some(0x104)], None));
+        profile.record(&cycle(
+            [Some(0x208), Some(0x204), None],
+            Some(StallReason::BranchFlush),
+        ));
+        // An empty pipeline charges the last instruction
+        profile.record(&cycle([None, None, None], Some(StallReason::Fetch)));
+
+        assert_eq!(
+            CycleCounts {
+                cycles: 3,
+                stalls: [2, 0, 0, 0, 0, 0, 0],
+            },
+            profile.counts(0x100)
+        );
+        assert_eq!(1, profile.counts(0x104).cycles);
+        assert_eq!(
+            (2, 2),
+            (
+                profile.counts(0x204).cycles,
+                profile.counts(0x204).stalled()
+            )
+        );
+        assert_eq!(CycleCounts::default(), profile.counts(0x108));
+    }
+
+    #[test]
+    fn test_function_table() {
+        let functions = vec![
+            Symbol {
+                name: "main".to_string(),
+                value: 0x100,
+                size: 0x100,
+            },
+            Symbol {
+                name: "f".to_string(),
+                value: 0x200,
+                size: 0x10,
+            },
+        ];
+        let mut profile = CycleProfile::new();
+        for _ in 0..3 {
+            profile.record(&cycle([None, None, Some(0x100)], None));
+        }
+        for reason in &[
+            StallReason::LoadUse,
+            StallReason::LoadUse,
+            StallReason::Memory,
+        ] {
+            profile.record(&cycle([Some(0x208), Some(0x204), None], Some(*reason)));
+        }
+        profile.record(&cycle([None, None, Some(0x400)], Some(StallReason::Memory)));
+
+        let names = |sort| -> Vec<String> {
+            profile
+                .functions(&functions, sort)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+        assert_eq!(vec!["f", "main", "[unknown]"], names(ProfileSort::Cycles));
+        assert_eq!(
+            vec!["[unknown]", "f", "main"],
+            names(ProfileSort::Stalls(StallReason::Memory))
+        );
+
+        let mut out = Vec::new();
+        profile
+            .write_table(&mut out, &functions, ProfileSort::Cycles)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(4, lines.len());
+        assert!(lines[0].starts_with("function   cycles  stalled  fetch  branch flush  load-use"));
+        assert!(lines[1].starts_with("f               3        3      0             0         2"));
+        assert!(lines[2].starts_with("main            3        0"));
+    }
+
+    #[test]
+    fn test_write_dot() {
+        let mut out = Vec::new();
+        Profile::new(&coverage()).write_dot(&mut out, &[]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("digraph cfg {\n"));
+        assert!(out.contains("    b00000104 [label=\"0x00000104\\n2 executions\"];\n"));
+        assert!(out.contains("    b00000104 -> b00000104 [label=\"1\", penwidth=5.0];\n"));
+        assert!(
+            out.contains("    b0000010c -> b00000200 [label=\"1\", penwidth=5.0, style=dashed];\n")
+        );
+        assert!(out.ends_with("}\n"));
+    }
+}
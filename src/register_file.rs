@@ -14,7 +14,11 @@
 //! assert_eq!((0, 31), my_reg_file.read(0, 21));
 //! ```
 
-#[derive(Default)]
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RegisterFile {
     registers: Vec<i32>,
 }
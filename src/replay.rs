@@ -0,0 +1,649 @@
+//! Replay of a recorded run through the timing models of the pipeline. The
+//! commit log of a run holds every retired instruction with the address of
+//! its memory access, which is all the pipeline needs to time it again under
+//! other latencies, bus wait states, forwarding paths or branch resolution,
+//! without executing anything. The trace stands in for the hart: it hands the
+//! recorded instructions to the pipeline in order, and the next recorded pc
+//! tells where each one went.
+//!
+//! A replay with the configuration of the recorded run gives its cycle
+//! counts back, as long as the program didn't depend on time, such as by
+//! reading the cycle counter or taking timer interrupts.
+//!
+//! A sweep replays the run once and passes its fetches and its data accesses
+//! through instruction and data caches of every geometry of a grid. The
+//! cycles of each geometry are estimated from the ones of the replay, where
+//! every access takes the wait states of the bus as a hit would, plus a
+//! penalty per miss. Prefetchers can fill either cache, with their accuracy
+//! and coverage reported along with the miss rates.
+//!
+//! The commit logs of several harts can also be interleaved, an instruction
+//! of each in turn, to pass their data accesses through private caches kept
+//! coherent by the MSI or the MESI protocol and count the traffic it takes.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::pipeline::{PipelineConfig, Stages};
+//! # use adept_lib::replay::{read_trace, replay};
+//! // addi a0, zero, 5; sw a0, 0(sp); j 8; nop
+//! let log = b"00000000 00500513\n00000004 00a12023 00000ffc\n00000008 0080006f\n00000010 00000013\n";
+//! let trace = read_trace(&log[..]).unwrap();
+//! let stats = replay(PipelineConfig::default(), &trace);
+//! assert_eq!(4, stats.instructions);
+//! let mut config = PipelineConfig::default();
+//! config.stages = Stages::One;
+//! assert!(replay(config, &trace).cycles < stats.cycles);
+//! ```
+use cache::{Cache, CacheConfig};
+use coherence::{CoherenceStats, CoherentCaches, Protocol};
+use hart::{Executed, StopReason};
+use latency::InstrClass;
+use pipeline::{Core, Pipeline, PipelineConfig};
+use prefetch::{PrefetchConfig, Prefetcher};
+use riscv::decoder::Instruction;
+use riscv::isa::RV32I;
+use stats::Stats;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+/// Retired instruction of a commit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retired {
+    pub pc: u32,
+    pub raw: u32,
+    /// Address accessed by a load or a store
+    pub addr: Option<u32>,
+}
+
+impl Retired {
+    /// Parse a line of a commit log, `PC INSN [ADDR]` in hexadecimal
+    ///
+    /// # Return Value
+    /// The instruction, or why the line is malformed
+    pub fn parse(line: &str) -> Result<Retired, String> {
+        let hex = |field: &str| {
+            u32::from_str_radix(field, 16).map_err(|e| format!("invalid number {}: {}", field, e))
+        };
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let addr = match fields.len() {
+            2 => None,
+            3 => Some(hex(fields[2])?),
+            _ => return Err(format!("expected PC INSN [ADDR], got {:?}", line)),
+        };
+        Ok(Retired {
+            pc: hex(fields[0])?,
+            raw: hex(fields[1])?,
+            addr,
+        })
+    }
+}
+
+/// Read a commit log, skipping the empty lines
+///
+/// # Return Value
+/// The retired instructions in order, or the first malformed line and why
+pub fn read_trace<R: BufRead>(input: R) -> Result<Vec<Retired>, String> {
+    records(input).collect()
+}
+
+/// Parse a commit log one line at a time, skipping the empty lines, so that
+/// logs larger than the memory can be scanned
+///
+/// # Return Value
+/// The retired instructions in order, or a malformed line and why
+pub fn records<R: BufRead>(input: R) -> impl Iterator<Item = Result<Retired, String>> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| match line {
+            Ok(line) => !line.trim().is_empty(),
+            Err(_) => true,
+        })
+        .map(|(index, line)| {
+            let line = line.map_err(|e| e.to_string())?;
+            Retired::parse(&line).map_err(|e| format!("line {}: {}", index + 1, e))
+        })
+}
+
+/// Time a recorded run through a pipeline model
+///
+/// # Arguments
+/// * `config` => model of the pipeline to replay the run on
+/// * `trace` => retired instructions of the run
+///
+/// # Return Value
+/// The statistics of the replay
+pub fn replay(config: PipelineConfig, trace: &[Retired]) -> Stats {
+    let mut stats = Stats::new();
+    let first = match trace.first() {
+        Some(first) => first.pc,
+        None => return stats,
+    };
+    let mut pipeline = Pipeline::new(config);
+    pipeline.reset(first);
+    let mut core = TraceCore::new(trace);
+    while stats.instructions < trace.len() as u64 {
+        if pipeline.tick(&mut core, &mut stats).is_err() {
+            break;
+        }
+    }
+    stats
+}
+
+/// Cache activity of a run on one geometry of a sweep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SweepResult {
+    /// Geometry of both the instruction and the data cache
+    pub cache: CacheConfig,
+    pub icache_accesses: u64,
+    pub icache_misses: u64,
+    pub dcache_accesses: u64,
+    pub dcache_misses: u64,
+    /// Lines the prefetcher of the instruction cache filled, and how many
+    /// of them were used
+    pub icache_prefetches: (u64, u64),
+    /// Lines the prefetcher of the data cache filled, and how many of them
+    /// were used
+    pub dcache_prefetches: (u64, u64),
+    /// Estimated cycles of the run
+    pub cycles: u64,
+}
+
+/// Replay a recorded run through caches of several geometries
+///
+/// # Arguments
+/// * `config` => model of the pipeline, whose bus wait states are the ones
+///   of a hit
+/// * `trace` => retired instructions of the run
+/// * `caches` => geometries of the instruction and the data caches
+/// * `miss_penalty` => cycles added by every miss
+/// * `prefetch` => prefetchers of the caches
+///
+/// # Return Value
+/// The activity of the caches of every geometry, in order
+pub fn sweep(
+    config: PipelineConfig,
+    trace: &[Retired],
+    caches: &[CacheConfig],
+    miss_penalty: u32,
+    prefetch: PrefetchConfig,
+) -> Vec<SweepResult> {
+    let cycles = replay(config, trace).cycles;
+    caches
+        .iter()
+        .map(|geometry| {
+            let (mut icache, mut dcache) = (Cache::new(*geometry), Cache::new(*geometry));
+            let mut iprefetcher = prefetch.icache.map(Prefetcher::new);
+            let mut dprefetcher = prefetch.dcache.map(Prefetcher::new);
+            for retired in trace {
+                access(&mut icache, &mut iprefetcher, retired.pc, retired.pc);
+                if let Some(addr) = retired.addr {
+                    access(&mut dcache, &mut dprefetcher, retired.pc, addr);
+                    if let Some(second) = second_word(retired) {
+                        access(&mut dcache, &mut dprefetcher, retired.pc, second);
+                    }
+                }
+            }
+            let misses = icache.misses() + dcache.misses();
+            SweepResult {
+                cache: *geometry,
+                icache_accesses: icache.accesses(),
+                icache_misses: icache.misses(),
+                dcache_accesses: dcache.accesses(),
+                dcache_misses: dcache.misses(),
+                icache_prefetches: (icache.prefetches(), icache.useful_prefetches()),
+                dcache_prefetches: (dcache.prefetches(), dcache.useful_prefetches()),
+                cycles: cycles + misses * u64::from(miss_penalty),
+            }
+        })
+        .collect()
+}
+
+/// Write the results of a sweep as CSV, with a header line
+pub fn write_sweep_csv<W: Write>(results: &[SweepResult], out: &mut W) -> io::Result<()> {
+    writeln!(
+        out,
+        "size,line,ways,icache_accesses,icache_misses,icache_miss_rate,dcache_accesses,dcache_misses,dcache_miss_rate,cycles,icache_prefetches,icache_prefetch_accuracy,icache_prefetch_coverage,dcache_prefetches,dcache_prefetch_accuracy,dcache_prefetch_coverage"
+    )?;
+    let rate = |count: u64, total: u64| {
+        if total == 0 {
+            0.0
+        } else {
+            count as f64 / total as f64
+        }
+    };
+    for result in results {
+        let (icache_prefetches, icache_useful) = result.icache_prefetches;
+        let (dcache_prefetches, dcache_useful) = result.dcache_prefetches;
+        writeln!(
+            out,
+            "{},{},{},{},{},{:.4},{},{},{:.4},{},{},{:.4},{:.4},{},{:.4},{:.4}",
+            result.cache.size,
+            result.cache.line,
+            result.cache.ways,
+            result.icache_accesses,
+            result.icache_misses,
+            rate(result.icache_misses, result.icache_accesses),
+            result.dcache_accesses,
+            result.dcache_misses,
+            rate(result.dcache_misses, result.dcache_accesses),
+            result.cycles,
+            icache_prefetches,
+            rate(icache_useful, icache_prefetches),
+            // The misses the useful prefetches removed
+            rate(icache_useful, icache_useful + result.icache_misses),
+            dcache_prefetches,
+            rate(dcache_useful, dcache_prefetches),
+            rate(dcache_useful, dcache_useful + result.dcache_misses)
+        )?;
+    }
+    Ok(())
+}
+
+/// Coherence traffic of the harts of a run on one geometry of a sweep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoherenceResult {
+    /// Geometry of the data cache of every hart
+    pub cache: CacheConfig,
+    pub stats: CoherenceStats,
+}
+
+/// Replay the data accesses of the harts of a run through coherent caches
+/// of several geometries
+///
+/// # Arguments
+/// * `traces` => retired instructions of every hart, interleaved an
+///   instruction of each in turn
+/// * `caches` => geometries of the data caches
+/// * `protocol` => coherence protocol of the caches
+///
+/// # Return Value
+/// The accesses and the traffic of every geometry, in order
+pub fn coherence_sweep(
+    traces: &[Vec<Retired>],
+    caches: &[CacheConfig],
+    protocol: Protocol,
+) -> Vec<CoherenceResult> {
+    let length = traces.iter().map(Vec::len).max().unwrap_or(0);
+    caches
+        .iter()
+        .map(|geometry| {
+            let mut coherent = CoherentCaches::new(protocol, *geometry, traces.len());
+            for index in 0..length {
+                for (hart, trace) in traces.iter().enumerate() {
+                    let retired = match trace.get(index) {
+                        Some(retired) => retired,
+                        None => continue,
+                    };
+                    let addr = match retired.addr {
+                        Some(addr) => addr,
+                        None => continue,
+                    };
+                    let op = Instruction::new(retired.raw).op();
+                    let is_store = op == RV32I::SB || op == RV32I::SH || op == RV32I::SW;
+                    for addr in Some(addr).into_iter().chain(second_word(retired)) {
+                        if is_store {
+                            coherent.write(hart, addr);
+                        } else {
+                            coherent.read(hart, addr);
+                        }
+                    }
+                }
+            }
+            CoherenceResult {
+                cache: *geometry,
+                stats: *coherent.stats(),
+            }
+        })
+        .collect()
+}
+
+/// Write the results of a coherence sweep as CSV, with a header line
+pub fn write_coherence_csv<W: Write>(
+    results: &[CoherenceResult],
+    protocol: Protocol,
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "size,line,ways,protocol,accesses,misses,miss_rate,bus_reads,bus_read_exclusives,upgrades,invalidations,writebacks,interventions"
+    )?;
+    for result in results {
+        let stats = &result.stats;
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{:.4},{},{},{},{},{},{}",
+            result.cache.size,
+            result.cache.line,
+            result.cache.ways,
+            protocol.name(),
+            stats.accesses,
+            stats.misses,
+            stats.miss_rate(),
+            stats.bus_reads,
+            stats.bus_read_exclusives,
+            stats.upgrades,
+            stats.invalidations,
+            stats.writebacks,
+            stats.interventions
+        )?;
+    }
+    Ok(())
+}
+
+// Access a cache of a sweep through its prefetcher, if any
+fn access(cache: &mut Cache, prefetcher: &mut Option<Prefetcher>, pc: u32, addr: u32) {
+    match prefetcher {
+        Some(prefetcher) => prefetcher.access(cache, pc, addr),
+        None => cache.access(addr),
+    };
+}
+
+// Address of the second word of an access crossing a word
+fn second_word(retired: &Retired) -> Option<u32> {
+    let addr = retired.addr?;
+    if (addr & 3) + access_size(Instruction::new(retired.raw).op()) > 4 {
+        Some((addr | 3).wrapping_add(1))
+    } else {
+        None
+    }
+}
+
+// Bytes accessed by a load or a store
+pub(crate) fn access_size(op: RV32I) -> u32 {
+    match op {
+        RV32I::LW | RV32I::SW => 4,
+        RV32I::LH | RV32I::LHU | RV32I::SH => 2,
+        _ => 1,
+    }
+}
+
+// The trace standing in for the hart
+struct TraceCore<'a> {
+    trace: &'a [Retired],
+    // Next instruction of the trace to execute
+    next: usize,
+    // Bits of every recorded instruction, for the fetches of the instructions
+    // which are flushed before they execute
+    code: HashMap<u32, u32>,
+}
+
+impl<'a> TraceCore<'a> {
+    fn new(trace: &'a [Retired]) -> Self {
+        TraceCore {
+            trace,
+            next: 0,
+            code: trace
+                .iter()
+                .map(|retired| (retired.pc, retired.raw))
+                .collect(),
+        }
+    }
+
+    // Address of the instruction recorded after the one at `index`
+    fn next_pc(&self, index: usize) -> u32 {
+        match self.trace.get(index + 1) {
+            Some(next) => next.pc,
+            None => self.trace[index].pc.wrapping_add(4),
+        }
+    }
+}
+
+impl<'a> Core for TraceCore<'a> {
+    fn fetch(&self, pc: u32) -> u32 {
+        self.code.get(&pc).cloned().unwrap_or(0)
+    }
+
+    // The instructions execute in the order of the trace, which ends with the
+    // one after the last recorded instruction
+    fn execute(&mut self, pc: u32, raw: u32) -> Result<Executed, StopReason> {
+        let retired = match self.trace.get(self.next) {
+            Some(retired) => *retired,
+            None => return Err(StopReason::InvalidInstruction { pc, raw }),
+        };
+        let op = Instruction::new(retired.raw).op();
+        // Accesses crossing a word were split by the recorded run
+        let split_access = match retired.addr {
+            Some(addr) => (addr & 3) + access_size(op) > 4,
+            None => false,
+        };
+        let executed = Executed {
+            next_pc: self.next_pc(self.next),
+            class: InstrClass::from(op),
+            mem_addr: retired.addr,
+            split_access,
+            bus_wait: 0,
+        };
+        self.next += 1;
+        Ok(executed)
+    }
+
+    fn last_write(&self) -> Option<(u8, u32)> {
+        None
+    }
+
+    fn destination(&self, raw: u32) -> Option<u8> {
+        Instruction::new(raw).get_rd()
+    }
+
+    // Only the next instruction to execute is on the recorded path
+    fn resolve_control(&self, pc: u32, _: u32) -> Option<u32> {
+        match self.trace.get(self.next) {
+            Some(retired) if retired.pc == pc => Some(self.next_pc(self.next)),
+            _ => None,
+        }
+    }
+
+    fn count_cycle(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bus::Region;
+    use cpu::Cpu;
+    use mem::MemStoreOp;
+    use pipeline::{BranchResolution, Forwarding, Stages};
+    use prefetch::PrefetchKind;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+    use trace::CommitLog;
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // A loop summing a buffer, with loads, a dependent add and a branch
+    const PROGRAM: [u32; 8] = [
+        0x1000_0293, // li t0, 0x100
+        0x0040_0313, // li t1, 4
+        0x0002_a383, // lw t2, 0(t0)
+        0x0075_0533, // add a0, a0, t2
+        0x0042_8293, // addi t0, t0, 4
+        0xfff3_0313, // addi t1, t1, -1
+        0xfe03_18e3, // bnez t1, -16
+        0x0000_0000, // invalid
+    ];
+
+    // Run the program and return its statistics and its commit log
+    fn record(config: PipelineConfig) -> (Stats, Vec<Retired>) {
+        let mut cpu = Cpu::new(config);
+        for (index, word) in PROGRAM.iter().enumerate() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), 4 * index as u32, *word)
+                .unwrap();
+        }
+        let capture = Capture::default();
+        cpu.add_tracer(Box::new(CommitLog::new(capture.clone())));
+        cpu.set_pc(0);
+        cpu.run();
+        let log = capture.0.lock().unwrap().clone();
+        (cpu.stats().clone(), read_trace(&log[..]).unwrap())
+    }
+
+    fn configs() -> Vec<PipelineConfig> {
+        let mut slow = PipelineConfig::default();
+        slow.bus.add_region(Region::new("slow", 0x100, 0x100, 3));
+        slow.latency.set(InstrClass::Load, 2);
+        vec![
+            PipelineConfig::default(),
+            PipelineConfig {
+                stages: Stages::One,
+                ..PipelineConfig::default()
+            },
+            PipelineConfig {
+                branch_resolution: BranchResolution::Decode,
+                forwarding: Forwarding {
+                    ex_to_ex: true,
+                    mem_to_ex: false,
+                },
+                ..PipelineConfig::default()
+            },
+            slow,
+        ]
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Ok(Retired {
+                pc: 0x108,
+                raw: 0x00a1_2223,
+                addr: Some(0xffc)
+            }),
+            Retired::parse("00000108 00a12223 00000ffc")
+        );
+        assert!(Retired::parse("00000108").is_err());
+        assert!(Retired::parse("00000108 xyz").is_err());
+        let error = read_trace(&b"00000000 00000013\n\n0 1 2 3\n"[..]).unwrap_err();
+        assert!(error.starts_with("line 3: "));
+    }
+
+    #[test]
+    fn test_replay_matches_the_run() {
+        for config in configs() {
+            let (stats, trace) = record(config.clone());
+            assert_eq!(stats, replay(config, &trace));
+        }
+    }
+
+    #[test]
+    fn test_what_if() {
+        // A trace recorded with the default model gives the timing of the
+        // other models without running the program again
+        let (_, trace) = record(PipelineConfig::default());
+        for config in configs() {
+            let (stats, _) = record(config.clone());
+            assert_eq!(stats, replay(config, &trace));
+        }
+    }
+
+    #[test]
+    fn test_sweep() {
+        let (stats, trace) = record(PipelineConfig::default());
+        let caches = [
+            CacheConfig::new(16, 16, 1).unwrap(),
+            CacheConfig::new(64, 16, 2).unwrap(),
+        ];
+        let results = sweep(
+            PipelineConfig::default(),
+            &trace,
+            &caches,
+            10,
+            PrefetchConfig::default(),
+        );
+        // 22 instructions fetched from 2 lines of 16 bytes, and 4 loads of a
+        // line
+        let small = results[0];
+        assert_eq!((22, 4), (small.icache_accesses, small.dcache_accesses));
+        assert_eq!(1, small.dcache_misses);
+        // The loop doesn't fit in a line, so the small cache misses on every
+        // jump back
+        assert!(small.icache_misses > results[1].icache_misses);
+        assert_eq!(2, results[1].icache_misses);
+        assert_eq!(stats.cycles + 30, results[1].cycles);
+
+        let mut csv = Vec::new();
+        write_sweep_csv(&results[1..], &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.starts_with("64,16,2,22,2,0.0909,4,1,0.2500,"));
+        assert!(row.ends_with(",0,0.0000,0.0000,0,0.0000,0.0000"));
+    }
+
+    #[test]
+    fn test_sweep_prefetch() {
+        let (stats, trace) = record(PipelineConfig::default());
+        let prefetch = PrefetchConfig {
+            icache: Some(PrefetchKind::NextLine),
+            dcache: Some(PrefetchKind::Stride),
+        };
+        let caches = [CacheConfig::new(64, 16, 2).unwrap()];
+        let result = sweep(PipelineConfig::default(), &trace, &caches, 10, prefetch)[0];
+        // The second line of the loop was prefetched, and so was the line
+        // after the loaded words, which nothing used
+        assert_eq!((1, 1), (result.icache_misses, result.dcache_misses));
+        assert_eq!((2, 1), result.icache_prefetches);
+        assert_eq!((1, 0), result.dcache_prefetches);
+        assert_eq!(stats.cycles + 20, result.cycles);
+
+        let mut csv = Vec::new();
+        write_sweep_csv(&[result], &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with(",2,0.5000,0.5000,1,0.0000,0.0000"));
+    }
+
+    #[test]
+    fn test_coherence_sweep() {
+        // One hart stores twice to a word which the other one loads
+        let writer = read_trace(
+            &b"00000000 00a12023 00000100\n00000004 00a12023 00000100\n00000008 00000013\n"[..],
+        )
+        .unwrap();
+        let reader = read_trace(
+            &b"00000000 00012503 00000104\n00000004 00012503 00000104\n00000008 00012503 00000104\n"[..],
+        )
+        .unwrap();
+        let caches = [CacheConfig::new(64, 16, 2).unwrap()];
+        let results = coherence_sweep(&[writer, reader.clone()], &caches, Protocol::Msi);
+        let stats = results[0].stats;
+        assert_eq!((5, 3), (stats.accesses, stats.misses));
+        assert_eq!(
+            (2, 1, 1),
+            (stats.bus_reads, stats.bus_read_exclusives, stats.upgrades)
+        );
+        assert_eq!(
+            (1, 2, 2),
+            (stats.invalidations, stats.writebacks, stats.interventions)
+        );
+
+        // Alone, the reader keeps its line
+        let alone = coherence_sweep(&[reader], &caches, Protocol::Mesi);
+        assert_eq!((3, 1), (alone[0].stats.accesses, alone[0].stats.misses));
+
+        let mut csv = Vec::new();
+        write_coherence_csv(&results, Protocol::Msi, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            "64,16,2,msi,5,3,0.6000,2,1,1,1,2,2",
+            csv.lines().nth(1).unwrap()
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! Report of a run as a single HTML page, for the readers who won't open a
+//! CSV file. The page has no external resources: the charts are bars drawn
+//! with CSS, so it can be mailed or attached to a ticket as it is. It holds:
+//!
+//! * the summary of the run => why it stopped, the cycles, the instructions
+//!   and the device counters
+//! * the instruction mix => the retired instructions by class
+//! * the hot functions => the functions with the most cycles charged to
+//!   them, when the cycles were profiled
+//! * the pipeline => the stall cycles by reason and the prediction rates
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::hart::StopReason;
+//! # use adept_lib::pipeline::PipelineConfig;
+//! # use adept_lib::report::Report;
+//! # use adept_lib::summary::Summary;
+//! let cpu = Cpu::new(PipelineConfig::default());
+//! let report = Report::new("hello.elf", Summary::new(&cpu, StopReason::Exit(0)), Vec::new());
+//! let mut html = Vec::new();
+//! report.write_html(&mut html).unwrap();
+//! let html = String::from_utf8(html).unwrap();
+//! assert!(html.starts_with("<!DOCTYPE html>"));
+//! assert!(html.contains("<title>hello.elf</title>"));
+//! ```
+use latency::InstrClass;
+use profile::CycleCounts;
+use stats::StallReason;
+use summary::Summary;
+
+use std::io::{self, Write};
+
+/// Rows of the table of the hot functions
+pub const HOT_FUNCTIONS: usize = 20;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em auto;max-width:60em;color:#222}\
+h1{font-size:1.5em}h2{font-size:1.2em;margin-top:2em;border-bottom:1px solid #ccc}\
+table{border-collapse:collapse}td,th{padding:.2em .8em;text-align:right}\
+td:first-child,th:first-child{text-align:left}th{border-bottom:1px solid #ccc}\
+.chart{width:100%}.chart td.bar{width:60%}\
+.bar div{background:#4a7ebb;height:1em;min-width:1px}\
+.stopped{font-weight:bold}";
+
+/// Results of a run, with the functions it spent its cycles in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// Heading of the page, usually the program
+    pub title: String,
+    pub summary: Summary,
+    /// Cycles charged to every function, the hottest first, or no function
+    /// if the cycles weren't profiled
+    pub functions: Vec<(String, CycleCounts)>,
+}
+
+impl Report {
+    /// Create the report of a run
+    ///
+    /// # Arguments
+    /// * `title` => heading of the page
+    /// * `summary` => summary of the run
+    /// * `functions` => cycles of the functions, the hottest first
+    pub fn new(title: &str, summary: Summary, functions: Vec<(String, CycleCounts)>) -> Self {
+        Report {
+            title: title.to_string(),
+            summary,
+            functions,
+        }
+    }
+
+    /// Write the report as an HTML page
+    pub fn write_html<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let title = html_escape(&self.title);
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html><head><meta charset=\"utf-8\">")?;
+        writeln!(out, "<title>{}</title>", title)?;
+        writeln!(out, "<style>{}</style></head><body>", STYLE)?;
+        writeln!(out, "<h1>{}</h1>", title)?;
+        self.write_summary(out)?;
+        self.write_mix(out)?;
+        self.write_functions(out)?;
+        self.write_pipeline(out)?;
+        writeln!(out, "</body></html>")
+    }
+
+    fn write_summary<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let summary = &self.summary;
+        let stats = &summary.stats;
+        writeln!(out, "<h2>Summary</h2>")?;
+        writeln!(
+            out,
+            "<p class=\"stopped\">{}{}</p>",
+            html_escape(&summary.reason),
+            if summary.timeout { " (timeout)" } else { "" }
+        )?;
+        let exit_code = summary
+            .exit_code
+            .map_or_else(|| "-".to_string(), |code| code.to_string());
+        let rows = [
+            ("exit code", exit_code),
+            ("cycles", stats.cycles.to_string()),
+            ("instructions", stats.instructions.to_string()),
+            ("CPI", format!("{:.3}", stats.cpi())),
+            (
+                "UART bytes",
+                format!(
+                    "{} sent, {} received",
+                    summary.uart_transmitted, summary.uart_received
+                ),
+            ),
+            (
+                "DMA transfers",
+                format!("{} ({} bytes)", summary.dma_transfers, summary.dma_bytes),
+            ),
+            ("mtime", summary.mtime.to_string()),
+        ];
+        write_table(out, &rows)
+    }
+
+    fn write_mix<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let stats = &self.summary.stats;
+        writeln!(out, "<h2>Instruction mix</h2>")?;
+        let bars: Vec<(String, u64)> = InstrClass::ALL
+            .iter()
+            .map(|class| (class.name().to_string(), stats.retired(*class)))
+            .collect();
+        write_chart(out, &bars, stats.instructions)?;
+        writeln!(
+            out,
+            "<p>{} nops and {} other hints retired.</p>",
+            stats.nops, stats.hints
+        )
+    }
+
+    fn write_functions<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "<h2>Hot functions</h2>")?;
+        if self.functions.is_empty() {
+            return writeln!(out, "<p>The cycles weren't profiled.</p>");
+        }
+        writeln!(
+            out,
+            "<table class=\"chart\"><tr><th>function</th><th>cycles</th><th>stalled</th><th></th></tr>"
+        )?;
+        let cycles = self.summary.stats.cycles;
+        for (name, counts) in self.functions.iter().take(HOT_FUNCTIONS) {
+            writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td>{}</tr>",
+                html_escape(name),
+                counts.cycles,
+                counts.stalled(),
+                bar(counts.cycles, cycles)
+            )?;
+        }
+        writeln!(out, "</table>")?;
+        if self.functions.len() > HOT_FUNCTIONS {
+            writeln!(
+                out,
+                "<p>{} more functions.</p>",
+                self.functions.len() - HOT_FUNCTIONS
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_pipeline<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let stats = &self.summary.stats;
+        writeln!(out, "<h2>Pipeline</h2>")?;
+        let stalled: u64 = stats.stall_cycles.iter().sum();
+        let mut bars = vec![("retiring".to_string(), stats.cycles - stalled)];
+        bars.extend(
+            StallReason::ALL
+                .iter()
+                .map(|reason| (reason.name().to_string(), stats.stalls(*reason))),
+        );
+        write_chart(out, &bars, stats.cycles)?;
+        let rate = |hits: u64, lookups: u64| {
+            if lookups == 0 {
+                "-".to_string()
+            } else {
+                format!("{:.2}%", 100.0 * hits as f64 / lookups as f64)
+            }
+        };
+        let rows = [
+            ("mispredicted", stats.mispredictions.to_string()),
+            ("wrong path", stats.wrong_path.to_string()),
+            ("BTB hit rate", rate(stats.btb_hits, stats.btb_lookups)),
+            ("RAS hit rate", rate(stats.ras_hits, stats.ras_lookups)),
+            ("dual issued", stats.dual_issues.to_string()),
+            ("split pairs", stats.split_pairs.to_string()),
+        ];
+        write_table(out, &rows)
+    }
+}
+
+// A table of labels and values
+fn write_table<W: Write>(out: &mut W, rows: &[(&str, String)]) -> io::Result<()> {
+    writeln!(out, "<table>")?;
+    for (label, value) in rows {
+        writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            label,
+            html_escape(value)
+        )?;
+    }
+    writeln!(out, "</table>")
+}
+
+// A bar chart with the share of the total of every value
+fn write_chart<W: Write>(out: &mut W, bars: &[(String, u64)], total: u64) -> io::Result<()> {
+    writeln!(out, "<table class=\"chart\">")?;
+    for (label, value) in bars {
+        writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td>{}</tr>",
+            html_escape(label),
+            value,
+            bar(*value, total)
+        )?;
+    }
+    writeln!(out, "</table>")
+}
+
+// The cells with the share of a value and its bar
+fn bar(value: u64, total: u64) -> String {
+    let share = if total == 0 {
+        0.0
+    } else {
+        100.0 * value as f64 / total as f64
+    };
+    format!(
+        "<td>{:.2}%</td><td class=\"bar\"><div style=\"width:{:.2}%\"></div></td>",
+        share, share
+    )
+}
+
+// Text with the characters HTML reserves replaced by their entities
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::Cpu;
+    use hart::StopReason;
+    use pipeline::PipelineConfig;
+
+    fn html(report: &Report) -> String {
+        let mut html = Vec::new();
+        report.write_html(&mut html).unwrap();
+        String::from_utf8(html).unwrap()
+    }
+
+    #[test]
+    fn test_charts() {
+        let cpu = Cpu::new(PipelineConfig::default());
+        let mut summary = Summary::new(&cpu, StopReason::Exit(1));
+        summary.stats.cycles = 10;
+        summary.stats.instructions = 8;
+        summary.stats.classes[InstrClass::Load.index()] = 2;
+        summary.stats.stall_cycles[StallReason::LoadUse.index()] = 2;
+        let report = Report::new(
+            "<test>",
+            summary,
+            vec![(
+                "main".to_string(),
+                CycleCounts {
+                    cycles: 10,
+                    stalls: [0, 0, 2, 0, 0, 0, 0],
+                },
+            )],
+        );
+        let html = html(&report);
+        assert!(html.contains("<h1>&lt;test&gt;</h1>"));
+        assert!(html.contains("<tr><td>exit code</td><td>1</td></tr>"));
+        assert!(html.contains(
+            "<tr><td>load</td><td>2</td><td>25.00%</td><td class=\"bar\"><div style=\"width:25.00%\"></div></td></tr>"
+        ));
+        assert!(html.contains("<tr><td>retiring</td><td>8</td><td>80.00%</td>"));
+        assert!(html.contains("<tr><td>main</td><td>10</td><td>2</td><td>100.00%</td>"));
+        assert!(html.ends_with("</body></html>\n"));
+    }
+
+    #[test]
+    fn test_without_profile() {
+        let cpu = Cpu::new(PipelineConfig::default());
+        let report = Report::new("run", Summary::new(&cpu, StopReason::Exit(0)), Vec::new());
+        let html = html(&report);
+        assert!(html.contains("<p>The cycles weren't profiled.</p>"));
+        assert!(html.contains("<tr><td>BTB hit rate</td><td>-</td></tr>"));
+    }
+
+    #[test]
+    fn test_html_escape() {
+        assert_eq!(
+            "a &lt;b&gt; &amp; &quot;c&quot;",
+            html_escape("a <b> & \"c\"")
+        );
+    }
+}
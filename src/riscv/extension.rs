@@ -0,0 +1,169 @@
+//! Standard extensions which aren't implemented. Their encodings are
+//! recognised so that programs built for a wider ISA than RV32I can be
+//! told apart from programs jumping to garbage.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::riscv::extension::Extension;
+//! // mul a0, a0, a1
+//! assert_eq!(Some(Extension::M), Extension::of(0x02b5_0533));
+//! // add a0, a0, a1
+//! assert_eq!(None, Extension::of(0x00b5_0533));
+//! ```
+use adept_core::riscv::{RV32_OP_CODES_ARITH_REG, RV32_OP_CODES_MISC_MEM};
+
+use std::fmt::{self, Display, Formatter};
+
+// Major opcodes of the extensions
+const OP_CODE_AMO: u32 = 0x2f;
+const OP_CODE_LOAD_FP: u32 = 0x07;
+const OP_CODE_STORE_FP: u32 = 0x27;
+const OP_CODE_FMADD: u32 = 0x43;
+const OP_CODE_FMSUB: u32 = 0x47;
+const OP_CODE_FNMSUB: u32 = 0x4b;
+const OP_CODE_FNMADD: u32 = 0x4f;
+const OP_CODE_OP_FP: u32 = 0x53;
+const OP_CODE_OP_V: u32 = 0x57;
+
+// funct7 of the multiplications and divisions
+const FUNCT7_MULDIV: u32 = 0x01;
+
+/// Standard extensions of the instruction set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Extension {
+    /// Integer multiplication and division
+    M,
+    /// Atomic instructions
+    A,
+    /// Single precision floating point
+    F,
+    /// Double precision floating point
+    D,
+    /// Quad precision floating point
+    Q,
+    /// Half precision floating point
+    Zfh,
+    /// Compressed instructions
+    C,
+    /// Vector operations
+    V,
+    /// Instruction fetch fence
+    Zifencei,
+}
+
+impl Extension {
+    /// Find the unimplemented extension an instruction belongs to
+    ///
+    /// # Arguments
+    /// * `raw` => instruction bits
+    ///
+    /// # Return Value
+    /// The extension, or None if the instruction belongs to RV32I or to no
+    /// known extension
+    pub fn of(raw: u32) -> Option<Self> {
+        // The lowest two bits of 32 bit instructions are set, and an all
+        // zero parcel is illegal in every ISA
+        if raw & 3 != 3 {
+            return if raw & 0xffff == 0 {
+                None
+            } else {
+                Some(Extension::C)
+            };
+        }
+
+        let funct3 = (raw >> 12) & 7;
+        match raw & 0x7f {
+            op_code if op_code == u32::from(RV32_OP_CODES_ARITH_REG) => {
+                if raw >> 25 == FUNCT7_MULDIV {
+                    Some(Extension::M)
+                } else {
+                    None
+                }
+            }
+            OP_CODE_AMO => Some(Extension::A),
+            op_code if op_code == u32::from(RV32_OP_CODES_MISC_MEM) && funct3 == 1 => {
+                Some(Extension::Zifencei)
+            }
+            // The width of floating point loads and stores, vector accesses
+            // use the remaining widths
+            OP_CODE_LOAD_FP | OP_CODE_STORE_FP => Some(match funct3 {
+                1 => Extension::Zfh,
+                2 => Extension::F,
+                3 => Extension::D,
+                4 => Extension::Q,
+                _ => Extension::V,
+            }),
+            OP_CODE_FMADD | OP_CODE_FMSUB | OP_CODE_FNMSUB | OP_CODE_FNMADD | OP_CODE_OP_FP => {
+                Some(match (raw >> 25) & 3 {
+                    0 => Extension::F,
+                    1 => Extension::D,
+                    2 => Extension::Zfh,
+                    _ => Extension::Q,
+                })
+            }
+            OP_CODE_OP_V => Some(Extension::V),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Extension::M => "M",
+            Extension::A => "A",
+            Extension::F => "F",
+            Extension::D => "D",
+            Extension::Q => "Q",
+            Extension::Zfh => "Zfh",
+            Extension::C => "C",
+            Extension::V => "V",
+            Extension::Zifencei => "Zifencei",
+        }
+    }
+}
+
+impl Display for Extension {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_instructions() {
+        // addi a0, a0, 1
+        assert_eq!(None, Extension::of(0x0015_0513));
+        // sub a0, a0, a1
+        assert_eq!(None, Extension::of(0x40b5_0533));
+        // fence
+        assert_eq!(None, Extension::of(0x0ff0_000f));
+        assert_eq!(None, Extension::of(0));
+        assert_eq!(None, Extension::of(0xffff_ffff));
+    }
+
+    #[test]
+    fn test_extensions() {
+        // divu a0, a0, a1
+        assert_eq!(Some(Extension::M), Extension::of(0x02b5_5533));
+        // amoadd.w a0, a1, (a2)
+        assert_eq!(Some(Extension::A), Extension::of(0x00b6_252f));
+        // flw fa0, 0(a0)
+        assert_eq!(Some(Extension::F), Extension::of(0x0005_2507));
+        // fsd fa0, 0(a0)
+        assert_eq!(Some(Extension::D), Extension::of(0x00a5_3027));
+        // fadd.s fa0, fa0, fa1
+        assert_eq!(Some(Extension::F), Extension::of(0x00b5_7553));
+        // fmadd.d fa0, fa0, fa1, fa2
+        assert_eq!(Some(Extension::D), Extension::of(0x62b5_7543));
+        // c.addi a0, 1
+        assert_eq!(Some(Extension::C), Extension::of(0x0505));
+        // vadd.vv v1, v2, v3
+        assert_eq!(Some(Extension::V), Extension::of(0x0221_80d7));
+        // fence.i
+        assert_eq!(Some(Extension::Zifencei), Extension::of(0x0000_100f));
+        assert_eq!("Zifencei", Extension::Zifencei.to_string());
+    }
+}
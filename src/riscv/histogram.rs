@@ -0,0 +1,190 @@
+//! Static histogram of the instructions of a program: how often every
+//! mnemonic, format and register appears in its code, and which
+//! unimplemented extensions its encodings belong to. Unlike the statistics of
+//! a simulation, it counts every instruction once, whether it runs or not.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::riscv::histogram::Histogram;
+//! let mut histogram = Histogram::new();
+//! // addi a0, a0, 1; addi a0, a0, 1; mul a0, a0, a1
+//! for raw in &[0x0015_0513, 0x0015_0513, 0x02b5_0533] {
+//!     histogram.add(*raw);
+//! }
+//! assert_eq!(2, histogram.mnemonic("addi"));
+//! assert_eq!(Some(1), histogram.extensions().get("M").cloned());
+//! ```
+use super::decoder::Instruction;
+use super::extension::Extension;
+use super::isa::{RV32I, RVT};
+use super::labels::get_register_label;
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+/// Counts of the instructions of a program
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Histogram {
+    /// Words added to the histogram
+    pub words: u64,
+    /// Words which are neither RV32I instructions nor encodings of a known
+    /// extension, usually data in the code
+    pub unknown: u64,
+    mnemonics: BTreeMap<&'static str, u64>,
+    formats: BTreeMap<&'static str, u64>,
+    extensions: BTreeMap<&'static str, u64>,
+    reads: [u64; 32],
+    writes: [u64; 32],
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count an instruction
+    ///
+    /// # Arguments
+    /// * `raw` => instruction bits
+    pub fn add(&mut self, raw: u32) {
+        self.words += 1;
+        // Encodings of the extensions are checked first, in case they decode
+        // as another instruction
+        if let Some(extension) = Extension::of(raw) {
+            *self.extensions.entry(extension.name()).or_insert(0) += 1;
+            return;
+        }
+        let decoded = Instruction::new(raw);
+        if !decoded.is_valid() || decoded.op() == RV32I::Invalid {
+            self.unknown += 1;
+            return;
+        }
+        *self.mnemonics.entry(decoded.mnemonic()).or_insert(0) += 1;
+        *self
+            .formats
+            .entry(format_name(decoded.instr_type()))
+            .or_insert(0) += 1;
+        for rs in decoded.get_rs1().iter().chain(decoded.get_rs2().iter()) {
+            self.reads[usize::from(*rs)] += 1;
+        }
+        if let Some(rd) = decoded.get_rd() {
+            self.writes[usize::from(rd)] += 1;
+        }
+    }
+
+    /// Occurrences of a mnemonic, such as `addi`
+    pub fn mnemonic(&self, mnemonic: &str) -> u64 {
+        self.mnemonics.get(mnemonic).cloned().unwrap_or(0)
+    }
+
+    /// Encodings of every unimplemented extension found, by name
+    pub fn extensions(&self) -> &BTreeMap<&'static str, u64> {
+        &self.extensions
+    }
+
+    /// Times a register is read and written
+    pub fn register(&self, register: u8) -> (u64, u64) {
+        let index = usize::from(register);
+        (self.reads[index], self.writes[index])
+    }
+}
+
+fn format_name(format: RVT) -> &'static str {
+    match format {
+        RVT::R => "R",
+        RVT::I => "I",
+        RVT::S => "S",
+        RVT::B => "B",
+        RVT::U => "U",
+        RVT::J => "J",
+        RVT::Invalid => "invalid",
+    }
+}
+
+// Entries from the most to the least frequent, in alphabetical order on ties
+fn by_count(counts: &BTreeMap<&'static str, u64>) -> Vec<(&'static str, u64)> {
+    let mut entries: Vec<_> = counts.iter().map(|(name, count)| (*name, *count)).collect();
+    entries.sort_by_key(|&(_, count)| Reverse(count));
+    entries
+}
+
+/// The summary of the histogram
+impl Display for Histogram {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let instructions: u64 = self.mnemonics.values().sum();
+        writeln!(f, "words:        {}", self.words)?;
+        writeln!(f, "instructions: {}", instructions)?;
+        writeln!(f, "unknown:      {}", self.unknown)?;
+
+        writeln!(f, "\nextensions:")?;
+        if self.extensions.is_empty() {
+            writeln!(f, "  none, RV32I is enough")?;
+        }
+        for (name, count) in by_count(&self.extensions) {
+            writeln!(f, "  {:<10} {:>8}", name, count)?;
+        }
+
+        writeln!(f, "\nformats:")?;
+        for (name, count) in by_count(&self.formats) {
+            writeln!(f, "  {:<10} {:>8}", name, count)?;
+        }
+
+        writeln!(f, "\nmnemonics:")?;
+        for (name, count) in by_count(&self.mnemonics) {
+            writeln!(f, "  {:<10} {:>8}", name, count)?;
+        }
+
+        writeln!(f, "\nregisters:       reads   writes")?;
+        for register in 0..32 {
+            let (reads, writes) = self.register(register);
+            if reads + writes > 0 {
+                writeln!(
+                    f,
+                    "  x{:<2} {:<6} {:>8} {:>8}",
+                    register,
+                    get_register_label(register),
+                    reads,
+                    writes
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts() {
+        let mut histogram = Histogram::new();
+        // add a0, a1, a2; sw a0, 4(sp); lui a0, 0x12345; fence.i; 0
+        for raw in &[0x00c5_8533, 0x00a1_2223, 0x1234_5537, 0x0000_100f, 0] {
+            histogram.add(*raw);
+        }
+        assert_eq!(5, histogram.words);
+        assert_eq!(1, histogram.unknown);
+        assert_eq!(1, histogram.mnemonic("sw"));
+        assert_eq!(0, histogram.mnemonic("addi"));
+        assert_eq!(Some(&1), histogram.extensions().get("Zifencei"));
+        // a0 is read by the store and written by the add and the lui
+        assert_eq!((1, 2), histogram.register(10));
+        assert_eq!((1, 0), histogram.register(2));
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut histogram = Histogram::new();
+        // addi a0, a0, 1; add a0, a1, a2; addi a0, a0, 1
+        for raw in &[0x0015_0513, 0x00c5_8533, 0x0015_0513] {
+            histogram.add(*raw);
+        }
+        let summary = histogram.to_string();
+        assert!(summary.contains("  none, RV32I is enough\n"));
+        assert!(summary.contains("mnemonics:\n  addi              2\n  add               1\n"));
+        assert!(summary.contains("  x10 a0            2        3\n"));
+    }
+}
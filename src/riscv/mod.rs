@@ -1,16 +1,9 @@
-//! Helper RISC-V functions for decoding
+//! Helper RISC-V functions for decoding. The decoder, the instruction set
+//! tables and the register labels come from the `adept_core` crate, which
+//! doesn't need the standard library.
 
-pub mod decoder;
-pub mod isa;
-pub mod labels;
+pub use adept_core::riscv::{decoder, fields, isa, labels, DecodeError};
 
-// Instruction OP codes
-const RV32_OP_CODES_ARITH_IMM: u8 = 0x13;
-const RV32_OP_CODES_ARITH_REG: u8 = 0x33;
-const RV32_OP_CODES_MEM_LD: u8 = 0x03;
-const RV32_OP_CODES_MEM_ST: u8 = 0x23;
-const RV32_OP_CODES_BR: u8 = 0x63;
-const RV32_OP_CODES_JALR: u8 = 0x67;
-const RV32_OP_CODES_JAL: u8 = 0x6f;
-const RV32_OP_CODES_AUIPC: u8 = 0x17;
-const RV32_OP_CODES_LUI: u8 = 0x37;
+pub mod extension;
+pub mod histogram;
+pub mod pseudo;
@@ -0,0 +1,501 @@
+//! Pseudoinstructions made of a single instruction, from the table of the
+//! RISC-V spec. Every pseudoinstruction is a base instruction with some of
+//! its fields fixed, and is written with the remaining fields as operands.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::riscv::decoder::Instruction;
+//! # use adept_lib::riscv::pseudo::PseudoInstrWith1Instr;
+//! // jalr x0, 0(x1)
+//! let pseudo = PseudoInstrWith1Instr::new(Instruction::new(0x0000_8067));
+//! assert_eq!(Some("ret"), pseudo.name());
+//! assert_eq!("ret", pseudo.to_string());
+//! ```
+use super::decoder::Instruction;
+use super::isa::RV32I;
+use riscv::labels::*;
+
+use std::fmt::{self, Display, Formatter};
+
+/// Field of the base instruction written as an operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Rd,
+    Rs1,
+    Rs2,
+    /// Immediate, or the unsigned immediate of CSR instructions
+    Imm,
+    Csr,
+}
+
+/// Pseudoinstruction of the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PseudoSpec {
+    pub name: &'static str,
+    /// Base instruction
+    pub op: RV32I,
+    /// Fixed fields of the base instruction
+    pub rd: Option<u8>,
+    pub rs1: Option<u8>,
+    pub rs2: Option<u8>,
+    pub imm: Option<i32>,
+    pub csr: Option<u16>,
+    /// Remaining fields, in the order they are written
+    pub operands: &'static [Operand],
+    /// Whether the base instruction is disassembled as this pseudoinstruction.
+    /// The aliases which only swap the operands of a branch are only meant
+    /// for assembly.
+    pub preferred: bool,
+}
+
+const ANY: PseudoSpec = PseudoSpec {
+    name: "",
+    op: RV32I::Invalid,
+    rd: None,
+    rs1: None,
+    rs2: None,
+    imm: None,
+    csr: None,
+    operands: &[],
+    preferred: true,
+};
+
+/// Pseudoinstructions made of a single instruction, in the order they are
+/// tried when disassembling, so that the most specific ones come first
+pub const PSEUDO_INSTRUCTIONS: &[PseudoSpec] = &[
+    PseudoSpec {
+        name: "nop",
+        op: RV32I::ADDI,
+        rd: Some(0),
+        rs1: Some(0),
+        imm: Some(0),
+        ..ANY
+    },
+    PseudoSpec {
+        name: "mv",
+        op: RV32I::ADDI,
+        imm: Some(0),
+        operands: &[Operand::Rd, Operand::Rs1],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "not",
+        op: RV32I::XORI,
+        imm: Some(-1),
+        operands: &[Operand::Rd, Operand::Rs1],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "neg",
+        op: RV32I::SUB,
+        rs1: Some(0),
+        operands: &[Operand::Rd, Operand::Rs2],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "seqz",
+        op: RV32I::SLTIU,
+        imm: Some(1),
+        operands: &[Operand::Rd, Operand::Rs1],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "snez",
+        op: RV32I::SLTU,
+        rs1: Some(0),
+        operands: &[Operand::Rd, Operand::Rs2],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "sltz",
+        op: RV32I::SLT,
+        rs2: Some(0),
+        operands: &[Operand::Rd, Operand::Rs1],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "sgtz",
+        op: RV32I::SLT,
+        rs1: Some(0),
+        operands: &[Operand::Rd, Operand::Rs2],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "beqz",
+        op: RV32I::BEQ,
+        rs2: Some(0),
+        operands: &[Operand::Rs1, Operand::Imm],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "bnez",
+        op: RV32I::BNE,
+        rs2: Some(0),
+        operands: &[Operand::Rs1, Operand::Imm],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "blez",
+        op: RV32I::BGE,
+        rs1: Some(0),
+        operands: &[Operand::Rs2, Operand::Imm],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "bgez",
+        op: RV32I::BGE,
+        rs2: Some(0),
+        operands: &[Operand::Rs1, Operand::Imm],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "bltz",
+        op: RV32I::BLT,
+        rs2: Some(0),
+        operands: &[Operand::Rs1, Operand::Imm],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "bgtz",
+        op: RV32I::BLT,
+        rs1: Some(0),
+        operands: &[Operand::Rs2, Operand::Imm],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "bgt",
+        op: RV32I::BLT,
+        operands: &[Operand::Rs2, Operand::Rs1, Operand::Imm],
+        preferred: false,
+        ..ANY
+    },
+    PseudoSpec {
+        name: "ble",
+        op: RV32I::BGE,
+        operands: &[Operand::Rs2, Operand::Rs1, Operand::Imm],
+        preferred: false,
+        ..ANY
+    },
+    PseudoSpec {
+        name: "bgtu",
+        op: RV32I::BLTU,
+        operands: &[Operand::Rs2, Operand::Rs1, Operand::Imm],
+        preferred: false,
+        ..ANY
+    },
+    PseudoSpec {
+        name: "bleu",
+        op: RV32I::BGEU,
+        operands: &[Operand::Rs2, Operand::Rs1, Operand::Imm],
+        preferred: false,
+        ..ANY
+    },
+    PseudoSpec {
+        name: "j",
+        op: RV32I::JAL,
+        rd: Some(0),
+        operands: &[Operand::Imm],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "jal",
+        op: RV32I::JAL,
+        rd: Some(1),
+        operands: &[Operand::Imm],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "ret",
+        op: RV32I::JALR,
+        rd: Some(0),
+        rs1: Some(1),
+        imm: Some(0),
+        ..ANY
+    },
+    PseudoSpec {
+        name: "jr",
+        op: RV32I::JALR,
+        rd: Some(0),
+        imm: Some(0),
+        operands: &[Operand::Rs1],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "jalr",
+        op: RV32I::JALR,
+        rd: Some(1),
+        imm: Some(0),
+        operands: &[Operand::Rs1],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "rdinstret",
+        op: RV32I::CSRRS,
+        rs1: Some(0),
+        csr: Some(0xc02),
+        operands: &[Operand::Rd],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "rdinstreth",
+        op: RV32I::CSRRS,
+        rs1: Some(0),
+        csr: Some(0xc82),
+        operands: &[Operand::Rd],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "rdcycle",
+        op: RV32I::CSRRS,
+        rs1: Some(0),
+        csr: Some(0xc00),
+        operands: &[Operand::Rd],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "rdcycleh",
+        op: RV32I::CSRRS,
+        rs1: Some(0),
+        csr: Some(0xc80),
+        operands: &[Operand::Rd],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "rdtime",
+        op: RV32I::CSRRS,
+        rs1: Some(0),
+        csr: Some(0xc01),
+        operands: &[Operand::Rd],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "rdtimeh",
+        op: RV32I::CSRRS,
+        rs1: Some(0),
+        csr: Some(0xc81),
+        operands: &[Operand::Rd],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "csrr",
+        op: RV32I::CSRRS,
+        rs1: Some(0),
+        operands: &[Operand::Rd, Operand::Csr],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "csrw",
+        op: RV32I::CSRRW,
+        rd: Some(0),
+        operands: &[Operand::Csr, Operand::Rs1],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "csrs",
+        op: RV32I::CSRRS,
+        rd: Some(0),
+        operands: &[Operand::Csr, Operand::Rs1],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "csrc",
+        op: RV32I::CSRRC,
+        rd: Some(0),
+        operands: &[Operand::Csr, Operand::Rs1],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "csrwi",
+        op: RV32I::CSRRWI,
+        rd: Some(0),
+        operands: &[Operand::Csr, Operand::Imm],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "csrsi",
+        op: RV32I::CSRRSI,
+        rd: Some(0),
+        operands: &[Operand::Csr, Operand::Imm],
+        ..ANY
+    },
+    PseudoSpec {
+        name: "csrci",
+        op: RV32I::CSRRCI,
+        rd: Some(0),
+        operands: &[Operand::Csr, Operand::Imm],
+        ..ANY
+    },
+];
+
+// Check a fixed field of the base instruction
+fn fixed<T: PartialEq>(field: Option<T>, value: Option<T>) -> bool {
+    field.is_none() || field == value
+}
+
+impl PseudoSpec {
+    /// Check if an instruction can be written as this pseudoinstruction
+    pub fn matches(&self, instr: &Instruction) -> bool {
+        instr.op() == self.op
+            && fixed(self.rd, instr.get_rd())
+            && fixed(self.rs1, instr.get_rs1())
+            && fixed(self.rs2, instr.get_rs2())
+            && fixed(self.imm, instr.get_imm())
+            && fixed(self.csr, instr.get_csr())
+    }
+
+    /// Find the pseudoinstruction an instruction is disassembled as
+    pub fn find(instr: &Instruction) -> Option<&'static PseudoSpec> {
+        PSEUDO_INSTRUCTIONS
+            .iter()
+            .find(|spec| spec.preferred && spec.matches(instr))
+    }
+}
+
+/// Instruction written as a pseudoinstruction when it is one
+#[derive(Debug)]
+pub struct PseudoInstrWith1Instr {
+    /// Corresponding instruction
+    instr: Instruction,
+    spec: Option<&'static PseudoSpec>,
+}
+
+impl PseudoInstrWith1Instr {
+    pub fn new(instr: Instruction) -> Self {
+        let spec = PseudoSpec::find(&instr);
+        PseudoInstrWith1Instr { instr, spec }
+    }
+
+    pub fn is_pseudo(&self) -> bool {
+        self.spec.is_some()
+    }
+
+    /// Name of the pseudoinstruction, if the instruction is one
+    pub fn name(&self) -> Option<&'static str> {
+        self.spec.map(|spec| spec.name)
+    }
+
+    pub fn instruction(&self) -> &Instruction {
+        &self.instr
+    }
+}
+
+impl Display for PseudoInstrWith1Instr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let spec = match self.spec {
+            Some(spec) => spec,
+            None => return self.instr.fmt(f),
+        };
+        if spec.operands.is_empty() {
+            return write!(f, "{}", spec.name);
+        }
+
+        let operands: Vec<String> = spec
+            .operands
+            .iter()
+            .map(|operand| match operand {
+                Operand::Rd => get_register_label(self.instr.get_rd().unwrap()).to_string(),
+                Operand::Rs1 => get_register_label(self.instr.get_rs1().unwrap()).to_string(),
+                Operand::Rs2 => get_register_label(self.instr.get_rs2().unwrap()).to_string(),
+                Operand::Imm => self.instr.get_imm().unwrap().to_string(),
+                Operand::Csr => {
+                    let csr = self.instr.get_csr().unwrap();
+                    match get_csr_label(csr) {
+                        Some(label) => label.to_string(),
+                        None => format!("{:#05x}", csr),
+                    }
+                }
+            })
+            .collect();
+        write!(f, "{:<7} {}", spec.name, operands.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Instruction and its disassembly
+    const CASES: &[(u32, &str)] = &[
+        (0x0000_0013, "nop"),
+        (0x0005_8513, "mv      a0,a1"),
+        (0xfff5_c513, "not     a0,a1"),
+        (0x40b0_0533, "neg     a0,a1"),
+        (0x0015_b513, "seqz    a0,a1"),
+        (0x00b0_3533, "snez    a0,a1"),
+        (0x0005_a533, "sltz    a0,a1"),
+        (0x00b0_2533, "sgtz    a0,a1"),
+        (0xfe05_0ce3, "beqz    a0,-8"),
+        (0x0005_1863, "bnez    a0,16"),
+        (0x00a0_5863, "blez    a0,16"),
+        (0x0005_5863, "bgez    a0,16"),
+        (0x0005_4863, "bltz    a0,16"),
+        (0x00a0_4863, "bgtz    a0,16"),
+        (0xffdf_f06f, "j       -4"),
+        (0x0010_00ef, "jal     2048"),
+        (0x0000_8067, "ret"),
+        (0x0003_0067, "jr      t1"),
+        (0x0003_00e7, "jalr    t1"),
+        (0xc020_2573, "rdinstret a0"),
+        (0xc820_2573, "rdinstreth a0"),
+        (0xc000_2573, "rdcycle a0"),
+        (0xc800_2573, "rdcycleh a0"),
+        (0xc010_2573, "rdtime  a0"),
+        (0xc810_2573, "rdtimeh a0"),
+        (0x3000_2573, "csrr    a0,mstatus"),
+        (0x3055_9073, "csrw    mtvec,a1"),
+        (0x3005_a073, "csrs    mstatus,a1"),
+        (0x3005_b073, "csrc    mstatus,a1"),
+        (0x3401_d073, "csrwi   mscratch,3"),
+        (0x3004_6073, "csrsi   mstatus,8"),
+        (0x3004_7073, "csrci   mstatus,8"),
+    ];
+
+    #[test]
+    fn test_pseudo_instructions() {
+        for (raw, text) in CASES {
+            let pseudo = PseudoInstrWith1Instr::new(Instruction::new(*raw));
+            assert!(pseudo.is_pseudo(), "{:08x}", raw);
+            assert_eq!(*text, pseudo.to_string(), "{:08x}", raw);
+        }
+
+        // Every preferred pseudoinstruction of the table is tested
+        for spec in PSEUDO_INSTRUCTIONS.iter().filter(|spec| spec.preferred) {
+            assert!(
+                CASES
+                    .iter()
+                    .any(|(raw, _)| PseudoSpec::find(&Instruction::new(*raw)) == Some(spec)),
+                "{}",
+                spec.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_base_instructions() {
+        for raw in &[
+            0x00b5_4863, // blt a0, a1, 16
+            0x00a5_d863, // bge a1, a0, 16
+            0x00b5_6863, // bltu a0, a1, 16
+            0x00a5_f863, // bgeu a1, a0, 16
+            0x0055_8513, // addi a0, a1, 5
+            0x0043_00e7, // jalr ra, 4(t1)
+        ] {
+            let pseudo = PseudoInstrWith1Instr::new(Instruction::new(*raw));
+            assert!(!pseudo.is_pseudo(), "{:08x}", raw);
+            assert_eq!(Instruction::new(*raw).to_string(), pseudo.to_string());
+        }
+    }
+
+    #[test]
+    fn test_aliases() {
+        let blt = Instruction::new(0x00b5_4863);
+        let bgt = PSEUDO_INSTRUCTIONS
+            .iter()
+            .find(|spec| spec.name == "bgt")
+            .unwrap();
+        assert!(bgt.matches(&blt));
+        assert_eq!(&[Operand::Rs2, Operand::Rs1, Operand::Imm], bgt.operands);
+        assert_eq!(None, PseudoSpec::find(&blt));
+    }
+}
@@ -0,0 +1,393 @@
+//! Statistics collected by the pipeline model during a simulation, and the
+//! report comparing the statistics of several pipeline configurations.
+//!
+//! Every cycle in which no instruction retires is a stall cycle, which the
+//! pipeline attributes to a single reason. The report breaks the stall
+//! cycles down by reason, the largest one first, to point at the bottleneck
+//! of the program:
+//!
+//! ```text
+//! stall cycles:  40 (28.57%)
+//!   load-use        25 (17.86%)
+//!   branch flush    15 (10.71%)
+//! ```
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::stats::Stats;
+//! let mut stats = Stats::new();
+//! stats.cycles = 30;
+//! stats.instructions = 20;
+//! assert_eq!(1.5, stats.cpi());
+//! ```
+use latency::InstrClass;
+
+use std::cmp::Reverse;
+use std::fmt::{self, Display, Formatter};
+
+/// Why no instruction retired in a cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StallReason {
+    /// The pipeline waited for instructions: its first fill, or the wait
+    /// states of a fetch
+    Fetch,
+    /// The pipeline refilled after flushing the wrong path of a branch or a
+    /// jump
+    BranchFlush,
+    /// An instruction waited in decode for the data of a load
+    LoadUse,
+    /// An instruction waited in decode for another result, which wasn't
+    /// forwarded or took more than a cycle
+    Dependency,
+    /// An instruction took more than a cycle to execute, such as a division
+    ExecuteBusy,
+    /// A load or a store waited for the wait states of the memory
+    Memory,
+    /// A load or a store waited for the DMA engine to free the bus
+    BusContention,
+}
+
+impl StallReason {
+    /// Every reason, from the fetch stage to the bus
+    pub const ALL: [StallReason; 7] = [
+        StallReason::Fetch,
+        StallReason::BranchFlush,
+        StallReason::LoadUse,
+        StallReason::Dependency,
+        StallReason::ExecuteBusy,
+        StallReason::Memory,
+        StallReason::BusContention,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StallReason::Fetch => "fetch",
+            StallReason::BranchFlush => "branch flush",
+            StallReason::LoadUse => "load-use",
+            StallReason::Dependency => "dependency",
+            StallReason::ExecuteBusy => "execute busy",
+            StallReason::Memory => "memory",
+            StallReason::BusContention => "bus contention",
+        }
+    }
+
+    /// Position of the reason in `ALL`
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    /// Number of simulated cycles
+    pub cycles: u64,
+    /// Number of retired instructions
+    pub instructions: u64,
+    /// Retired canonical NOPs, usually padding for alignment
+    pub nops: u64,
+    /// Retired encodings of the HINT space other than the canonical NOP
+    pub hints: u64,
+    /// Cycles the fetch stage waited for the bus
+    pub fetch_stalls: u64,
+    /// Cycles the execute stage waited for the bus on loads and stores
+    pub data_stalls: u64,
+    /// Cycles instructions waited in decode for the result of the previous
+    /// instruction
+    pub hazard_stalls: u64,
+    /// Branches and jumps whose next instruction the fetch stage got wrong
+    pub mispredictions: u64,
+    /// Instructions fetched on a wrong path, which were flushed before they
+    /// executed
+    pub wrong_path: u64,
+    /// Taken branches and jumps other than returns, which need the branch
+    /// target buffer
+    pub btb_lookups: u64,
+    /// Lookups which found the target in the branch target buffer
+    pub btb_hits: u64,
+    /// Returns, which need the return address stack
+    pub ras_lookups: u64,
+    /// Returns whose address was on top of the return address stack
+    pub ras_hits: u64,
+    /// Pairs of instructions which executed together in the dual issue
+    /// pipeline
+    pub dual_issues: u64,
+    /// Pairs which the issue rules split in decode
+    pub split_pairs: u64,
+    /// Cycles in which no instruction retired, by reason in the order of
+    /// `StallReason::ALL`
+    pub stall_cycles: [u64; 7],
+    /// Retired instructions by class, in the order of `InstrClass::ALL`
+    pub classes: [u64; 8],
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            cycles: 0,
+            instructions: 0,
+            nops: 0,
+            hints: 0,
+            fetch_stalls: 0,
+            data_stalls: 0,
+            hazard_stalls: 0,
+            mispredictions: 0,
+            wrong_path: 0,
+            btb_lookups: 0,
+            btb_hits: 0,
+            ras_lookups: 0,
+            ras_hits: 0,
+            dual_issues: 0,
+            split_pairs: 0,
+            stall_cycles: [0; 7],
+            classes: [0; 8],
+        }
+    }
+
+    /// Cycles per instruction. It's 0 if no instruction retired.
+    pub fn cpi(&self) -> f64 {
+        if self.instructions == 0 {
+            0.0
+        } else {
+            self.cycles as f64 / self.instructions as f64
+        }
+    }
+
+    /// Stall cycles of a reason
+    pub fn stalls(&self, reason: StallReason) -> u64 {
+        self.stall_cycles[reason.index()]
+    }
+
+    /// Retired instructions of a class
+    pub fn retired(&self, class: InstrClass) -> u64 {
+        self.classes[class.index()]
+    }
+
+    /// Reasons which stalled the pipeline, with their cycles, from the one
+    /// which stalled it the most
+    pub fn bottlenecks(&self) -> Vec<(StallReason, u64)> {
+        let mut bottlenecks: Vec<(StallReason, u64)> = StallReason::ALL
+            .iter()
+            .map(|reason| (*reason, self.stalls(*reason)))
+            .filter(|(_, cycles)| *cycles > 0)
+            .collect();
+        // The sort is stable, so ties keep the order of the pipeline
+        bottlenecks.sort_by_key(|(_, cycles)| Reverse(*cycles));
+        bottlenecks
+    }
+}
+
+/// Side by side statistics of the same program simulated with several
+/// configurations. The speedup is relative to the first configuration.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Comparison {
+    columns: Vec<(String, Stats)>,
+}
+
+impl Comparison {
+    pub fn new() -> Self {
+        Comparison {
+            columns: Vec::new(),
+        }
+    }
+
+    /// Add the statistics of a configuration
+    ///
+    /// # Arguments
+    /// * `name` => name of the configuration
+    /// * `stats` => statistics of the simulation
+    pub fn add(&mut self, name: &str, stats: Stats) {
+        self.columns.push((name.to_string(), stats));
+    }
+
+    /// Speedup of every configuration over the first one
+    pub fn speedups(&self) -> Vec<f64> {
+        let baseline = self.columns.first().map_or(0, |(_, stats)| stats.cycles);
+        self.columns
+            .iter()
+            .map(|(_, stats)| {
+                if stats.cycles == 0 {
+                    0.0
+                } else {
+                    baseline as f64 / stats.cycles as f64
+                }
+            })
+            .collect()
+    }
+}
+
+// A row of the comparison table: its label and how to get the value
+type Row = (&'static str, fn(&Stats) -> String);
+
+// Width of the columns of the comparison table
+const LABEL_WIDTH: usize = 14;
+const VALUE_WIDTH: usize = 12;
+
+/// The comparison table
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let rows: [Row; 10] = [
+            ("cycles", |stats| stats.cycles.to_string()),
+            ("instructions", |stats| stats.instructions.to_string()),
+            ("nops", |stats| stats.nops.to_string()),
+            ("hints", |stats| stats.hints.to_string()),
+            ("CPI", |stats| format!("{:.3}", stats.cpi())),
+            ("fetch stalls", |stats| stats.fetch_stalls.to_string()),
+            ("data stalls", |stats| stats.data_stalls.to_string()),
+            ("hazard stalls", |stats| stats.hazard_stalls.to_string()),
+            ("mispredicted", |stats| stats.mispredictions.to_string()),
+            ("wrong path", |stats| stats.wrong_path.to_string()),
+        ];
+
+        write!(f, "{:w$}", "", w = LABEL_WIDTH)?;
+        for (name, _) in &self.columns {
+            write!(f, " {:>w$}", name, w = VALUE_WIDTH)?;
+        }
+        writeln!(f)?;
+
+        for (label, value) in rows.iter() {
+            write!(f, "{:w$}", label, w = LABEL_WIDTH)?;
+            for (_, stats) in &self.columns {
+                write!(f, " {:>w$}", value(stats), w = VALUE_WIDTH)?;
+            }
+            writeln!(f)?;
+        }
+
+        write!(f, "{:w$}", "speedup", w = LABEL_WIDTH)?;
+        for speedup in self.speedups() {
+            write!(f, " {:>w$.3}", speedup, w = VALUE_WIDTH)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// The statistics report
+impl Display for Stats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "cycles:        {}", self.cycles)?;
+        writeln!(f, "instructions:  {}", self.instructions)?;
+        writeln!(f, "nops:          {}", self.nops)?;
+        writeln!(f, "hints:         {}", self.hints)?;
+        writeln!(f, "CPI:           {:.3}", self.cpi())?;
+        writeln!(f, "fetch stalls:  {}", self.fetch_stalls)?;
+        writeln!(f, "data stalls:   {}", self.data_stalls)?;
+        writeln!(f, "hazard stalls: {}", self.hazard_stalls)?;
+        writeln!(f, "mispredicted:  {}", self.mispredictions)?;
+        writeln!(f, "wrong path:    {}", self.wrong_path)?;
+        // The prediction structures only show when the pipeline has them
+        if self.btb_lookups > 0 || self.ras_lookups > 0 {
+            let rate = |hits: u64, lookups: u64| {
+                if lookups == 0 {
+                    0.0
+                } else {
+                    hits as f64 * 100.0 / lookups as f64
+                }
+            };
+            writeln!(
+                f,
+                "BTB hits:      {}/{} ({:.2}%)",
+                self.btb_hits,
+                self.btb_lookups,
+                rate(self.btb_hits, self.btb_lookups)
+            )?;
+            writeln!(
+                f,
+                "RAS hits:      {}/{} ({:.2}%)",
+                self.ras_hits,
+                self.ras_lookups,
+                rate(self.ras_hits, self.ras_lookups)
+            )?;
+        }
+        if self.dual_issues > 0 || self.split_pairs > 0 {
+            writeln!(f, "dual issued:   {}", self.dual_issues)?;
+            writeln!(f, "split pairs:   {}", self.split_pairs)?;
+        }
+        let bottlenecks = self.bottlenecks();
+        if !bottlenecks.is_empty() {
+            let share = |cycles: u64| cycles as f64 * 100.0 / self.cycles.max(1) as f64;
+            let stalled = self.stall_cycles.iter().sum();
+            writeln!(f, "stall cycles:  {} ({:.2}%)", stalled, share(stalled))?;
+            for (reason, cycles) in bottlenecks {
+                writeln!(
+                    f,
+                    "  {:14}  {} ({:.2}%)",
+                    reason.name(),
+                    cycles,
+                    share(cycles)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpi_without_instructions() {
+        let mut stats = Stats::new();
+        stats.cycles = 4;
+        assert_eq!(0.0, stats.cpi());
+    }
+
+    #[test]
+    fn test_report() {
+        let mut stats = Stats::new();
+        stats.cycles = 7;
+        stats.instructions = 4;
+        stats.nops = 1;
+        stats.data_stalls = 2;
+        assert_eq!(
+            "cycles:        7\ninstructions:  4\nnops:          1\nhints:         0\n\
+             CPI:           1.750\n\
+             fetch stalls:  0\ndata stalls:   2\nhazard stalls: 0\n\
+             mispredicted:  0\nwrong path:    0\n",
+            stats.to_string()
+        );
+
+        stats.btb_lookups = 8;
+        stats.btb_hits = 6;
+        assert!(stats
+            .to_string()
+            .ends_with("BTB hits:      6/8 (75.00%)\nRAS hits:      0/0 (0.00%)\n"));
+
+        stats.dual_issues = 4;
+        assert!(stats
+            .to_string()
+            .ends_with("dual issued:   4\nsplit pairs:   0\n"));
+
+        stats.stall_cycles[StallReason::Fetch.index()] = 1;
+        stats.stall_cycles[StallReason::LoadUse.index()] = 2;
+        assert!(stats.to_string().ends_with(
+            "stall cycles:  3 (42.86%)\n  load-use        2 (28.57%)\n  fetch           1 (14.29%)\n"
+        ));
+    }
+
+    #[test]
+    fn test_comparison() {
+        let mut slow = Stats::new();
+        slow.cycles = 40;
+        slow.instructions = 20;
+        let mut fast = Stats::new();
+        fast.cycles = 20;
+        fast.instructions = 20;
+        fast.hazard_stalls = 3;
+
+        let mut comparison = Comparison::new();
+        comparison.add("slow", slow);
+        comparison.add("fast", fast);
+        assert_eq!(vec![1.0, 2.0], comparison.speedups());
+
+        let table = comparison.to_string();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(12, lines.len());
+        assert_eq!("                       slow         fast", lines[0]);
+        assert_eq!("nops                      0            0", lines[3]);
+        assert_eq!("CPI                   2.000        1.000", lines[5]);
+        assert_eq!("hazard stalls             0            3", lines[8]);
+        assert_eq!("wrong path                0            0", lines[10]);
+        assert_eq!("speedup               1.000        2.000", lines[11]);
+    }
+}
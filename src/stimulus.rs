@@ -0,0 +1,306 @@
+//! Scripted console of the serial port, which types the input of the program
+//! and checks its output like expect(1), failing the run when the output
+//! doesn't show up in time. The script has a step per line, run in order:
+//!
+//! * `send TEXT` => type TEXT, a character each time the program polls the
+//!   receiver
+//! * `wait CYCLES` => let CYCLES cycles pass
+//! * `at CYCLE` => wait for a cycle of the simulation
+//! * `expect TEXT` => wait until the program transmits TEXT, which may come
+//!   after other output
+//! * `expect TEXT within CYCLES` => the same, failing the run unless TEXT
+//!   comes in CYCLES cycles
+//!
+//! TEXT runs to the end of the line, or to ` within`, and takes the escapes
+//! `\n`, `\r`, `\t`, `\\` and `\xNN`. Blank lines and lines starting with
+//! `#` are ignored. The run fails as well if it ends before the last step.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::stimulus::ScriptConsole;
+//! # use adept_lib::uart::Console;
+//! let mut console = ScriptConsole::parse("expect > within 100\nsend ls\\n").unwrap();
+//! assert_eq!(None, console.receive());
+//! console.transmit(b'>');
+//! assert!(console.tick(10));
+//! assert_eq!(Some(b'l'), console.receive());
+//! assert_eq!(Some(b's'), console.receive());
+//! assert_eq!(Some(b'\n'), console.receive());
+//! assert!(console.finished());
+//! ```
+use uart::Console;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A step of a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Send(Vec<u8>),
+    Wait(u64),
+    At(u64),
+    Expect { text: Vec<u8>, within: Option<u64> },
+}
+
+/// Console running a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptConsole {
+    steps: Vec<Step>,
+    // Current step, the cycle it started and the characters it sent
+    step: usize,
+    started: u64,
+    sent: usize,
+    cycle: u64,
+    // Output of the program not matched by an expect yet
+    output: Vec<u8>,
+    failed: bool,
+}
+
+impl ScriptConsole {
+    /// Create a console running steps
+    pub fn new(steps: Vec<Step>) -> Self {
+        let mut console = ScriptConsole {
+            steps,
+            step: 0,
+            started: 0,
+            sent: 0,
+            cycle: 0,
+            output: Vec::new(),
+            failed: false,
+        };
+        console.advance();
+        console
+    }
+
+    /// Read and parse a script file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        ScriptConsole::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Parse a script
+    ///
+    /// # Arguments
+    /// * `text` => the steps, one per line
+    ///
+    /// # Return Value
+    /// The console, or the line and the reason why it's malformed
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut steps = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim_start();
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let step = parse_step(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+            steps.push(step);
+        }
+        Ok(ScriptConsole::new(steps))
+    }
+
+    /// The steps of the script
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    // Move past the steps which are done, failing if an expect timed out
+    fn advance(&mut self) {
+        while !self.failed && self.step < self.steps.len() {
+            let done = match self.steps[self.step] {
+                Step::Send(ref text) => self.sent == text.len(),
+                Step::Wait(cycles) => self.cycle >= self.started.saturating_add(cycles),
+                Step::At(cycle) => self.cycle >= cycle,
+                Step::Expect { ref text, within } => {
+                    let found = self
+                        .output
+                        .windows(text.len().max(1))
+                        .position(|window| window == &text[..]);
+                    match found {
+                        Some(start) => {
+                            self.output.drain(..start + text.len());
+                            true
+                        }
+                        None if text.is_empty() => true,
+                        None => {
+                            if let Some(cycles) = within {
+                                if self.cycle > self.started.saturating_add(cycles) {
+                                    error!(
+                                        "console: expected {:?} within {} cycles of cycle {}, got {:?}",
+                                        String::from_utf8_lossy(text),
+                                        cycles,
+                                        self.started,
+                                        String::from_utf8_lossy(&self.output)
+                                    );
+                                    self.failed = true;
+                                }
+                            }
+                            false
+                        }
+                    }
+                }
+            };
+            if !done {
+                return;
+            }
+            self.step += 1;
+            self.started = self.cycle;
+            self.sent = 0;
+        }
+    }
+}
+
+impl Console for ScriptConsole {
+    fn receive(&mut self) -> Option<u8> {
+        let byte = match self.steps.get(self.step) {
+            Some(Step::Send(text)) if !self.failed => text[self.sent],
+            _ => return None,
+        };
+        self.sent += 1;
+        self.advance();
+        Some(byte)
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        self.output.push(byte);
+        self.advance();
+    }
+
+    fn tick(&mut self, cycle: u64) -> bool {
+        self.cycle = cycle;
+        self.advance();
+        !self.failed
+    }
+
+    fn finished(&self) -> bool {
+        !self.failed && self.step == self.steps.len()
+    }
+
+    fn box_clone(&self) -> Box<dyn Console> {
+        Box::new(self.clone())
+    }
+}
+
+fn parse_step(line: &str) -> Result<Step, String> {
+    let (command, argument) = match line.find(' ') {
+        Some(space) => (&line[..space], &line[space + 1..]),
+        None => (line, ""),
+    };
+    let number = |text: &str| {
+        text.trim()
+            .parse::<u64>()
+            .map_err(|e| format!("invalid number of cycles {:?}: {}", text.trim(), e))
+    };
+    match command {
+        "send" => Ok(Step::Send(unescape(argument)?)),
+        "wait" => Ok(Step::Wait(number(argument)?)),
+        "at" => Ok(Step::At(number(argument)?)),
+        "expect" => match argument.rfind(" within ") {
+            Some(within) => Ok(Step::Expect {
+                text: unescape(&argument[..within])?,
+                within: Some(number(&argument[within + 8..])?),
+            }),
+            None => Ok(Step::Expect {
+                text: unescape(argument)?,
+                within: None,
+            }),
+        },
+        _ => Err(format!("unknown step {:?}", command)),
+    }
+}
+
+// Replace the escapes of a text by the characters they stand for
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 => bytes.push(byte),
+                    _ => return Err(format!("invalid escape \\x{}", digits)),
+                }
+            }
+            Some(c) => return Err(format!("invalid escape \\{}", c)),
+            None => return Err("escape at the end of the text".to_string()),
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let console = ScriptConsole::parse(
+            "# Log in\nexpect login: within 5000\n\nsend root\\r\nwait 100\nat 2000\nexpect \\x1b[0m\n",
+        )
+        .unwrap();
+        assert_eq!(
+            &[
+                Step::Expect {
+                    text: b"login:".to_vec(),
+                    within: Some(5000)
+                },
+                Step::Send(b"root\r".to_vec()),
+                Step::Wait(100),
+                Step::At(2000),
+                Step::Expect {
+                    text: b"\x1b[0m".to_vec(),
+                    within: None
+                },
+            ],
+            console.steps()
+        );
+        assert_eq!(
+            Err("line 2: unknown step \"type\"".to_string()),
+            ScriptConsole::parse("\ntype x")
+        );
+        assert_eq!(
+            Err("line 1: invalid escape \\q".to_string()),
+            ScriptConsole::parse("send \\q")
+        );
+        assert!(ScriptConsole::parse("wait soon").is_err());
+    }
+
+    #[test]
+    fn test_timing() {
+        let mut console = ScriptConsole::parse("wait 10\nsend a\nat 30\nsend b").unwrap();
+        assert!(console.tick(9));
+        assert_eq!(None, console.receive());
+        assert!(console.tick(10));
+        assert_eq!(Some(b'a'), console.receive());
+        assert_eq!(None, console.receive());
+        assert!(console.tick(30));
+        assert_eq!(Some(b'b'), console.receive());
+        assert!(console.finished());
+    }
+
+    #[test]
+    fn test_expect() {
+        let mut console = ScriptConsole::parse("expect ok\nexpect done within 20").unwrap();
+        assert!(console.tick(100));
+        for byte in b"boot ok, " {
+            console.transmit(*byte);
+        }
+        // The output before the match is skipped, the rest is kept
+        console.transmit(b'd');
+        assert!(console.tick(120));
+        assert!(!console.finished());
+        assert!(!console.tick(121));
+        assert!(!console.finished());
+    }
+}
@@ -0,0 +1,186 @@
+//! Machine readable summary of a run, written as a JSON object when the
+//! simulation stops so that dashboards can read the results without parsing
+//! the log:
+//!
+//! ```text
+//! {"reason":"exited with code 0","exit_code":0,"timeout":false,"cycles":120,"instructions":100,"cpi":1.200,"nops":0,"hints":0,"stalls":{"fetch":5,"data":10,"hazard":5},"mispredictions":2,"wrong_path":4,"stall_cycles":{"fetch":2,"branch_flush":8,"load_use":10,"dependency":0,"execute_busy":0,"memory":0,"bus_contention":0},"devices":{"uart":{"transmitted":12,"received":0},"dma":{"transfers":0,"bytes":0},"clint":{"mtime":120}}}
+//! ```
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::hart::StopReason;
+//! # use adept_lib::pipeline::PipelineConfig;
+//! # use adept_lib::summary::Summary;
+//! let cpu = Cpu::new(PipelineConfig::default());
+//! let summary = Summary::new(&cpu, StopReason::Exit(3));
+//! assert_eq!(Some(3), summary.exit_code);
+//! let mut json = Vec::new();
+//! summary.write_json(&mut json).unwrap();
+//! assert!(String::from_utf8(json).unwrap().starts_with("{\"reason\":\"exited with code 3\""));
+//! ```
+use cpu::Cpu;
+use hart::StopReason;
+use stats::{StallReason, Stats};
+
+use std::io::{self, Write};
+
+/// Results of a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    /// Why the simulation stopped
+    pub reason: String,
+    /// The exit code of the program, if it exited
+    pub exit_code: Option<i32>,
+    /// Set if one of the limits of the simulation stopped it
+    pub timeout: bool,
+    pub stats: Stats,
+    /// Characters written and read by the program on the serial port
+    pub uart_transmitted: u64,
+    pub uart_received: u64,
+    /// Finished transfers of the DMA engine and the bytes they copied
+    pub dma_transfers: u64,
+    pub dma_bytes: u64,
+    /// Time of the core local interruptor
+    pub mtime: u64,
+}
+
+impl Summary {
+    /// Summarize a run
+    ///
+    /// # Arguments
+    /// * `cpu` => the processor, once the simulation stopped
+    /// * `reason` => why it stopped
+    pub fn new(cpu: &Cpu, reason: StopReason) -> Self {
+        let hart = cpu.hart();
+        Summary {
+            reason: reason.to_string(),
+            exit_code: match reason {
+                StopReason::Exit(code) => Some(code),
+                _ => None,
+            },
+            timeout: reason.is_timeout(),
+            stats: cpu.stats().clone(),
+            uart_transmitted: hart.uart().bytes_transmitted(),
+            uart_received: hart.uart().bytes_received(),
+            dma_transfers: hart.dma().transfers(),
+            dma_bytes: hart.dma().bytes_copied(),
+            mtime: hart.clint().mtime(),
+        }
+    }
+
+    /// Write the summary as a JSON object on a line
+    pub fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let exit_code = match self.exit_code {
+            Some(code) => code.to_string(),
+            None => "null".to_string(),
+        };
+        let stall_cycles: Vec<String> = StallReason::ALL
+            .iter()
+            .map(|reason| {
+                format!(
+                    "\"{}\":{}",
+                    reason.name().replace(&[' ', '-'][..], "_"),
+                    self.stats.stalls(*reason)
+                )
+            })
+            .collect();
+        writeln!(
+            out,
+            "{{\"reason\":{},\"exit_code\":{},\"timeout\":{},\"cycles\":{},\"instructions\":{},\"cpi\":{:.3},\"nops\":{},\"hints\":{},\
+             \"stalls\":{{\"fetch\":{},\"data\":{},\"hazard\":{}}},\"mispredictions\":{},\"wrong_path\":{},\"stall_cycles\":{{{}}},\
+             \"devices\":{{\"uart\":{{\"transmitted\":{},\"received\":{}}},\"dma\":{{\"transfers\":{},\"bytes\":{}}},\"clint\":{{\"mtime\":{}}}}}}}",
+            json_string(&self.reason),
+            exit_code,
+            self.timeout,
+            self.stats.cycles,
+            self.stats.instructions,
+            self.stats.cpi(),
+            self.stats.nops,
+            self.stats.hints,
+            self.stats.fetch_stalls,
+            self.stats.data_stalls,
+            self.stats.hazard_stalls,
+            self.stats.mispredictions,
+            self.stats.wrong_path,
+            stall_cycles.join(","),
+            self.uart_transmitted,
+            self.uart_received,
+            self.dma_transfers,
+            self.dma_bytes,
+            self.mtime
+        )
+    }
+}
+
+// A JSON string, with its quotes
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem::MemStoreOp;
+    use pipeline::PipelineConfig;
+    use riscv::isa::RV32I;
+
+    #[test]
+    fn test_run() {
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        let program = [
+            0x1000_0537, // lui a0, 0x10000
+            0x0410_0593, // li a1, 'A'
+            0x00b5_0023, // sb a1, 0(a0)
+        ];
+        for (index, word) in program.iter().enumerate() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(
+                    &MemStoreOp::from(RV32I::SW),
+                    0x100 + 4 * index as u32,
+                    *word,
+                )
+                .unwrap();
+        }
+        cpu.set_pc(0x100);
+        let reason = cpu.run();
+        let summary = Summary::new(&cpu, reason);
+        assert_eq!(None, summary.exit_code);
+        assert!(!summary.timeout);
+        assert_eq!(1, summary.uart_transmitted);
+        assert_eq!(3, summary.stats.instructions);
+
+        let mut json = Vec::new();
+        summary.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with(
+            "{\"reason\":\"invalid instruction 0x00000000 at pc 0x0000010c\",\"exit_code\":null,"
+        ));
+        assert!(json.contains(",\"stall_cycles\":{\"fetch\":2,\"branch_flush\":0,"));
+        assert!(json.contains(
+            ",\"devices\":{\"uart\":{\"transmitted\":1,\"received\":0},\"dma\":{\"transfers\":0,\"bytes\":0},"
+        ));
+        assert!(json.ends_with("}}}\n"));
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(
+            "\"a \\\"b\\\"\\\\\\n\\u0001\"",
+            json_string("a \"b\"\\\n\u{1}")
+        );
+    }
+}
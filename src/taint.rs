@@ -0,0 +1,275 @@
+//! Taint tracking. Bytes coming from untrusted sources are marked as tainted
+//! and the taint follows the data through loads, arithmetic and stores. Every
+//! time tainted data becomes the target of a jump, or is stored to one of the
+//! chosen sinks, the instruction is reported.
+//!
+//! Only explicit data flow is tracked: the address of a load or a store and
+//! the outcome of branches don't taint the result.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::hart::Hart;
+//! # use adept_lib::mem::MemStoreOp;
+//! # use adept_lib::riscv::isa::RV32I;
+//! # use adept_lib::taint::{TaintEvent, TaintSink, TaintTracker};
+//! let mut hart = Hart::new();
+//! let mut tracker = TaintTracker::new();
+//! // The input buffer of the program
+//! tracker.taint(0x100, 4);
+//! hart.set_taint_tracker(Some(tracker));
+//!
+//! // lw t0, 0x100(zero) and jr t0
+//! hart.memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 0, 0x1000_2283).unwrap();
+//! hart.memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 4, 0x0002_8067).unwrap();
+//! hart.execute(0, 0x1000_2283).unwrap();
+//! hart.execute(4, 0x0002_8067).unwrap();
+//!
+//! let events = hart.taint_tracker().unwrap().events();
+//! assert_eq!(&[TaintEvent { pc: 4, sink: TaintSink::Pc }], events);
+//! ```
+use latency::InstrClass;
+use mem::{MemLoadOp, MemStoreOp};
+use riscv::decoder::Instruction;
+use riscv::isa::RV32I;
+
+// The shadow memory holds one bit per byte of the memory
+const SHADOW_BYTES: usize = 1 << 20;
+
+/// Destination reached by tainted data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaintSink {
+    /// A jump to a tainted address
+    Pc,
+    /// A store of tainted data to a sink region, at the given address
+    Memory(u32),
+}
+
+/// Instruction which moved tainted data to a sink
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaintEvent {
+    pub pc: u32,
+    pub sink: TaintSink,
+}
+
+/// Taint of the registers and of every byte of the memory
+#[derive(Debug, Clone)]
+pub struct TaintTracker {
+    // One bit per register, x0 is never tainted
+    registers: u32,
+    memory: Vec<u8>,
+    // Start and size of the sink regions
+    sinks: Vec<(u32, u32)>,
+    events: Vec<TaintEvent>,
+}
+
+impl Default for TaintTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TaintTracker {
+    /// Create a tracker where nothing is tainted
+    pub fn new() -> Self {
+        TaintTracker {
+            registers: 0,
+            memory: vec![0; SHADOW_BYTES],
+            sinks: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Mark memory as tainted, usually because it was written by a source
+    /// of untrusted data
+    ///
+    /// # Arguments
+    /// * `addr` => first address of the tainted bytes
+    /// * `size` => number of tainted bytes
+    pub fn taint(&mut self, addr: u32, size: u32) {
+        for offset in 0..size {
+            self.set_byte(addr.wrapping_add(offset), true);
+        }
+    }
+
+    /// Report stores of tainted data to a memory region
+    ///
+    /// # Arguments
+    /// * `addr` => first address of the region
+    /// * `size` => size of the region in bytes
+    pub fn add_sink(&mut self, addr: u32, size: u32) {
+        self.sinks.push((addr, size));
+    }
+
+    /// Check if any byte of a memory range is tainted
+    pub fn is_memory_tainted(&self, addr: u32, size: u32) -> bool {
+        (0..size).any(|offset| self.byte(addr.wrapping_add(offset)))
+    }
+
+    pub fn is_register_tainted(&self, register: u8) -> bool {
+        self.registers & (1 << register) != 0
+    }
+
+    /// First event of every instruction which moved tainted data to a sink
+    pub fn events(&self) -> &[TaintEvent] {
+        &self.events
+    }
+
+    /// Propagate the taint through an executed instruction
+    ///
+    /// # Arguments
+    /// * `pc` => address of the instruction
+    /// * `decoded` => the instruction
+    /// * `mem_addr` => address accessed by a load or a store
+    pub fn execute(&mut self, pc: u32, decoded: &Instruction, mem_addr: Option<u32>) {
+        let op = decoded.op();
+        // Missing operands read x0, which is never tainted
+        let rs1 = self.is_register_tainted(decoded.get_rs1().unwrap_or(0));
+        let rs2 = self.is_register_tainted(decoded.get_rs2().unwrap_or(0));
+
+        let rd = match (InstrClass::from(op), mem_addr) {
+            (InstrClass::Load, Some(addr)) => {
+                self.is_memory_tainted(addr, MemLoadOp::from(op).size())
+            }
+            (InstrClass::Store, Some(addr)) => {
+                let size = MemStoreOp::from(op).size();
+                for offset in 0..size {
+                    self.set_byte(addr.wrapping_add(offset), rs2);
+                }
+                if rs2 {
+                    if let Some(addr) = self.sink_hit(addr, size) {
+                        self.report(pc, TaintSink::Memory(addr));
+                    }
+                }
+                false
+            }
+            (InstrClass::Jump, _) => {
+                if op == RV32I::JALR && rs1 {
+                    self.report(pc, TaintSink::Pc);
+                }
+                false
+            }
+            (InstrClass::System, _) => false,
+            _ => rs1 || rs2,
+        };
+
+        if let Some(index) = decoded.get_rd() {
+            if rd && index != 0 {
+                self.registers |= 1 << index;
+            } else {
+                self.registers &= !(1 << index);
+            }
+        }
+    }
+
+    // First address of a store which falls in a sink region
+    fn sink_hit(&self, addr: u32, size: u32) -> Option<u32> {
+        (0..size)
+            .map(|offset| addr.wrapping_add(offset))
+            .find(|addr| {
+                self.sinks
+                    .iter()
+                    .any(|(start, length)| addr.wrapping_sub(*start) < *length)
+            })
+    }
+
+    fn report(&mut self, pc: u32, sink: TaintSink) {
+        if !self.events.iter().any(|event| event.pc == pc) {
+            self.events.push(TaintEvent { pc, sink });
+        }
+    }
+
+    // Shadow memory wraps around like the memory
+    fn byte(&self, addr: u32) -> bool {
+        let index = (addr as usize >> 3) % SHADOW_BYTES;
+        self.memory[index] & (1 << (addr & 7)) != 0
+    }
+
+    fn set_byte(&mut self, addr: u32, tainted: bool) {
+        let index = (addr as usize >> 3) % SHADOW_BYTES;
+        if tainted {
+            self.memory[index] |= 1 << (addr & 7);
+        } else {
+            self.memory[index] &= !(1 << (addr & 7));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Propagate the taint through an instruction
+    fn track(tracker: &mut TaintTracker, pc: u32, raw: u32, mem_addr: Option<u32>) {
+        tracker.execute(pc, &Instruction::new(raw), mem_addr);
+    }
+
+    #[test]
+    fn test_taint_memory() {
+        let mut tracker = TaintTracker::new();
+        tracker.taint(0x101, 2);
+        assert!(!tracker.is_memory_tainted(0x100, 1));
+        assert!(tracker.is_memory_tainted(0x100, 2));
+        assert!(tracker.is_memory_tainted(0x102, 4));
+        assert!(!tracker.is_memory_tainted(0x103, 4));
+    }
+
+    #[test]
+    fn test_propagation() {
+        let mut tracker = TaintTracker::new();
+        tracker.taint(0x200, 1);
+
+        // lbu t0, 0x200(zero)
+        track(&mut tracker, 0, 0x2000_4283, Some(0x200));
+        assert!(tracker.is_register_tainted(5));
+        // add t1, t0, a0
+        track(&mut tracker, 4, 0x00a2_8333, None);
+        assert!(tracker.is_register_tainted(6));
+        // sw t1, 0x300(zero)
+        track(&mut tracker, 8, 0x3060_2023, Some(0x300));
+        assert!(tracker.is_memory_tainted(0x300, 4));
+        // lui t1, 1 clears the taint
+        track(&mut tracker, 12, 0x0000_1337, None);
+        assert!(!tracker.is_register_tainted(6));
+        // Stores of clean data clear the taint of memory
+        track(&mut tracker, 16, 0x3060_2023, Some(0x300));
+        assert!(!tracker.is_memory_tainted(0x300, 4));
+        // x0 is never tainted
+        track(&mut tracker, 20, 0x0002_8033, None);
+        assert!(!tracker.is_register_tainted(0));
+
+        assert!(tracker.events().is_empty());
+    }
+
+    #[test]
+    fn test_sinks() {
+        let mut tracker = TaintTracker::new();
+        tracker.taint(0x200, 4);
+        tracker.add_sink(0x1000, 0x10);
+
+        // lw t0, 0x200(zero)
+        track(&mut tracker, 0, 0x2000_2283, Some(0x200));
+        // sb t0, 0(t1), outside and inside of the sink
+        track(&mut tracker, 4, 0x0053_0023, Some(0x0fff));
+        track(&mut tracker, 8, 0x0053_0023, Some(0x100f));
+        track(&mut tracker, 8, 0x0053_0023, Some(0x1000));
+        // jalr t0
+        track(&mut tracker, 12, 0x0002_80e7, None);
+
+        assert_eq!(
+            &[
+                TaintEvent {
+                    pc: 8,
+                    sink: TaintSink::Memory(0x100f)
+                },
+                TaintEvent {
+                    pc: 12,
+                    sink: TaintSink::Pc
+                }
+            ],
+            tracker.events()
+        );
+        // The return address isn't tainted
+        assert!(!tracker.is_register_tainted(1));
+    }
+}
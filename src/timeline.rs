@@ -0,0 +1,281 @@
+//! Recording of a run which lets the debugger go back in time. The timeline
+//! keeps the pc of every retired instruction and a snapshot of the processor
+//! every few instructions. Going to an instruction restores the last snapshot
+//! before it and runs forward from there, so it takes at most the interval of
+//! the snapshots, whatever the length of the run. The recorded pcs check that
+//! the program takes the same path again.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::cpu::Cpu;
+//! # use adept_lib::mem::MemStoreOp;
+//! # use adept_lib::pipeline::PipelineConfig;
+//! # use adept_lib::riscv::isa::RV32I;
+//! # use adept_lib::timeline::Timeline;
+//! let mut cpu = Cpu::new(PipelineConfig::default());
+//! // addi a0, a0, 1; j -4
+//! cpu.hart_mut().memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 0, 0x0015_0513).unwrap();
+//! cpu.hart_mut().memory_mut().write_data(&MemStoreOp::from(RV32I::SW), 4, 0xffdf_f06f).unwrap();
+//! let mut timeline = Timeline::new(&cpu, 8);
+//! for _ in 0..100 {
+//!     cpu.step().unwrap();
+//!     timeline.record(&cpu);
+//! }
+//! timeline.travel(&mut cpu, 21).unwrap();
+//! assert_eq!(21, cpu.stats().instructions);
+//! assert_eq!((11, 0), cpu.hart().registers().read(10, 0));
+//! ```
+use cpu::Cpu;
+use hart::StopReason;
+
+use std::fmt::{self, Display, Formatter};
+
+/// Instructions between two snapshots unless another interval is given
+pub const DEFAULT_INTERVAL: u64 = 10_000;
+
+/// Why the processor couldn't be brought to an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TravelError {
+    /// The instruction is outside of the recording
+    NotRecorded { index: u64, first: u64, last: u64 },
+    /// The program retired another instruction than the recorded one
+    Diverged {
+        index: u64,
+        expected: u32,
+        found: u32,
+    },
+    /// The program stopped before the instruction
+    Stopped(StopReason),
+}
+
+impl Display for TravelError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TravelError::NotRecorded { index, first, last } => write!(
+                f,
+                "instruction {} wasn't recorded, only {} to {} were",
+                index, first, last
+            ),
+            TravelError::Diverged {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "instruction {} retired at 0x{:08x} instead of 0x{:08x}",
+                index, found, expected
+            ),
+            TravelError::Stopped(reason) => write!(f, "the program stopped: {}", reason),
+        }
+    }
+}
+
+/// Recorded instructions and snapshots of a run
+pub struct Timeline {
+    interval: u64,
+    // Instructions retired before the recording
+    start: u64,
+    // Pc of every recorded instruction
+    trace: Vec<u32>,
+    // Snapshots of the processor every interval instructions, from the start
+    snapshots: Vec<Cpu>,
+}
+
+impl Timeline {
+    /// Start recording a run from the current state of a processor
+    ///
+    /// # Arguments
+    /// * `cpu` => processor running the program
+    /// * `interval` => instructions between two snapshots, more than 0
+    pub fn new(cpu: &Cpu, interval: u64) -> Self {
+        assert!(interval > 0, "snapshots every 0 instructions");
+        Timeline {
+            interval,
+            start: cpu.stats().instructions,
+            trace: Vec::new(),
+            snapshots: vec![cpu.snapshot()],
+        }
+    }
+
+    /// First instruction index the processor can travel to
+    pub fn first(&self) -> u64 {
+        self.start
+    }
+
+    /// Last instruction index the processor can travel to
+    pub fn last(&self) -> u64 {
+        self.start + self.trace.len() as u64
+    }
+
+    /// Record the instruction the processor just retired. An instruction
+    /// other than the recorded one at its index replaces the rest of the
+    /// recording.
+    pub fn record(&mut self, cpu: &Cpu) {
+        let pc = match cpu.last_cycle().retired {
+            Some(entry) => entry.pc,
+            None => return,
+        };
+        let position = match cpu.stats().instructions.checked_sub(self.start) {
+            Some(position) if position > 0 => position,
+            _ => return,
+        };
+        if position <= self.trace.len() as u64 {
+            if self.trace[position as usize - 1] == pc {
+                return;
+            }
+            self.trace.truncate(position as usize - 1);
+            self.snapshots
+                .truncate(((position - 1) / self.interval) as usize + 1);
+        }
+        if position == self.trace.len() as u64 + 1 {
+            self.trace.push(pc);
+            if position == self.snapshots.len() as u64 * self.interval {
+                self.snapshots.push(cpu.snapshot());
+            }
+        }
+    }
+
+    /// Bring a processor to its state after a number of retired
+    /// instructions of the recording. It runs forward from its current
+    /// state when that's closer than the last snapshot before the index,
+    /// and otherwise restores the snapshot, keeping its tracers.
+    ///
+    /// # Arguments
+    /// * `cpu` => processor running the recorded program
+    /// * `index` => number of retired instructions, from `first` to `last`
+    pub fn travel(&self, cpu: &mut Cpu, index: u64) -> Result<(), TravelError> {
+        if index < self.first() || index > self.last() {
+            return Err(TravelError::NotRecorded {
+                index,
+                first: self.first(),
+                last: self.last(),
+            });
+        }
+        let snapshot = &self.snapshots[((index - self.start) / self.interval) as usize];
+        let current = cpu.stats().instructions;
+        if current <= index && current >= snapshot.stats().instructions {
+            return self.run_to(cpu, index);
+        }
+        let mut travelled = snapshot.snapshot();
+        self.run_to(&mut travelled, index)?;
+        cpu.restore(&travelled);
+        Ok(())
+    }
+
+    // Run forward to an index, checking every instruction against the
+    // recording
+    fn run_to(&self, cpu: &mut Cpu, index: u64) -> Result<(), TravelError> {
+        while cpu.stats().instructions < index {
+            cpu.step().map_err(TravelError::Stopped)?;
+            let retired = cpu.stats().instructions;
+            let expected = self.trace[(retired - self.start - 1) as usize];
+            if let Some(entry) = cpu.last_cycle().retired {
+                if entry.pc != expected {
+                    return Err(TravelError::Diverged {
+                        index: retired,
+                        expected,
+                        found: entry.pc,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem::MemStoreOp;
+    use pipeline::PipelineConfig;
+    use riscv::isa::RV32I;
+
+    // Count in a0 forever, storing the count after every increment
+    const PROGRAM: [u32; 3] = [
+        0x0015_0513, // loop: addi a0, a0, 1
+        0x20a0_2023, // sw a0, 0x200(zero)
+        0xff9f_f06f, // j loop
+    ];
+
+    fn counter() -> Cpu {
+        let mut cpu = Cpu::new(PipelineConfig::default());
+        for (index, word) in PROGRAM.iter().enumerate() {
+            cpu.hart_mut()
+                .memory_mut()
+                .write_data(&MemStoreOp::from(RV32I::SW), 4 * index as u32, *word)
+                .unwrap();
+        }
+        cpu
+    }
+
+    fn record(cpu: &mut Cpu, timeline: &mut Timeline, count: u64) {
+        for _ in 0..count {
+            cpu.step().unwrap();
+            timeline.record(cpu);
+        }
+    }
+
+    // The count and the stored count
+    fn state(cpu: &Cpu) -> (i32, u32) {
+        let (count, _) = cpu.hart().registers().read(10, 0);
+        (count, cpu.hart().memory().read_pc(0x200))
+    }
+
+    #[test]
+    fn test_travel() {
+        let mut cpu = counter();
+        let mut timeline = Timeline::new(&cpu, 10);
+        record(&mut cpu, &mut timeline, 100);
+        assert_eq!((0, 100), (timeline.first(), timeline.last()));
+        assert_eq!(11, timeline.snapshots.len());
+
+        // A fresh run gives the states to compare with
+        let mut expected = counter();
+        let mut states = vec![state(&expected)];
+        for _ in 0..100 {
+            expected.step().unwrap();
+            states.push(state(&expected));
+        }
+        for index in &[100, 37, 0, 55, 56, 99, 40] {
+            timeline.travel(&mut cpu, *index).unwrap();
+            assert_eq!(*index, cpu.stats().instructions);
+            assert_eq!(states[*index as usize], state(&cpu));
+        }
+        assert_eq!(
+            Err(TravelError::NotRecorded {
+                index: 101,
+                first: 0,
+                last: 100
+            }),
+            timeline.travel(&mut cpu, 101)
+        );
+    }
+
+    #[test]
+    fn test_record_after_travel() {
+        let mut cpu = counter();
+        let mut timeline = Timeline::new(&cpu, 4);
+        record(&mut cpu, &mut timeline, 20);
+        timeline.travel(&mut cpu, 5).unwrap();
+        // The same path again keeps the recording
+        record(&mut cpu, &mut timeline, 3);
+        assert_eq!(20, timeline.last());
+        assert_eq!(6, timeline.snapshots.len());
+
+        // Another path replaces the rest of it
+        timeline.travel(&mut cpu, 9).unwrap();
+        cpu.set_pc(4);
+        record(&mut cpu, &mut timeline, 2);
+        assert_eq!(11, timeline.last());
+        assert_eq!(3, timeline.snapshots.len());
+        assert_eq!(
+            Err(TravelError::Diverged {
+                index: 10,
+                expected: 4,
+                found: 0
+            }),
+            timeline.travel(&mut counter(), 10)
+        );
+    }
+}
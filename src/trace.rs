@@ -0,0 +1,920 @@
+//! Pipeline activity traces. After every simulated cycle the pipeline
+//! produces a record with the instruction held by each stage, which tracers
+//! turn into logs: a plain text occupancy log, or a log in the Kanata format
+//! which can be opened in the [Konata](https://github.com/shioyadan/Konata)
+//! pipeline viewer. The records also hold the write port of the register
+//! file and the bus transactions of the cycle, which the state log writes
+//! as JSON lines for the scoreboards of RTL testbenches, and which the
+//! riscv-dv log writes as the instruction trace of an ISS. The commit log
+//! records the retired instructions for the replayer.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::pipeline::Stages;
+//! # use adept_lib::trace::{CycleRecord, Occupant, OccupancyLog, TraceEntry, Tracer};
+//! let mut log = OccupancyLog::new(Vec::new(), Stages::One);
+//! let entry = TraceEntry { id: 0, pc: 0x100, raw: 0x0050_0513 };
+//! log.cycle(&CycleRecord {
+//!     cycle: 1,
+//!     stages: vec![Occupant::Active(entry)],
+//!     flushed: Vec::new(),
+//!     retired: Some(entry),
+//!     ..CycleRecord::default()
+//! })
+//! .unwrap();
+//! ```
+use latency::InstrClass;
+use pipeline::Stages;
+use riscv::decoder::Instruction;
+use riscv::labels::get_register_label;
+use stats::StallReason;
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+
+/// An instruction in flight
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct TraceEntry {
+    /// Unique number given to the instruction when it's fetched
+    pub id: u64,
+    /// Address of the instruction
+    pub pc: u32,
+    /// Instruction bits
+    pub raw: u32,
+}
+
+/// What a pipeline stage held during a cycle
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Occupant {
+    /// The stage was empty
+    Bubble,
+    /// The instruction leaves the stage at the end of the cycle
+    Active(TraceEntry),
+    /// The instruction stays in the stage for another cycle
+    Stalled(TraceEntry),
+}
+
+impl Occupant {
+    /// The instruction held by the stage, if any
+    pub fn entry(&self) -> Option<&TraceEntry> {
+        match self {
+            Occupant::Bubble => None,
+            Occupant::Active(entry) | Occupant::Stalled(entry) => Some(entry),
+        }
+    }
+}
+
+impl Display for Occupant {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Format into a string first so the column width is honored
+        let text = match self {
+            Occupant::Bubble => String::from("-"),
+            Occupant::Active(entry) => {
+                format!("{:08x} {}", entry.pc, Instruction::new(entry.raw))
+            }
+            Occupant::Stalled(entry) => {
+                format!("{:08x} {} *", entry.pc, Instruction::new(entry.raw))
+            }
+        };
+        f.pad(&text)
+    }
+}
+
+/// Kind of a bus transaction
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum BusKind {
+    Fetch,
+    Load,
+    Store,
+}
+
+impl BusKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BusKind::Fetch => "fetch",
+            BusKind::Load => "load",
+            BusKind::Store => "store",
+        }
+    }
+}
+
+/// Access to a word of the bus
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct BusTransaction {
+    pub kind: BusKind,
+    pub addr: u32,
+    /// Cycles the access waits for the memory
+    pub wait_states: u32,
+}
+
+/// Activity of the pipeline during one cycle
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CycleRecord {
+    /// Number of the cycle, starting at 1
+    pub cycle: u64,
+    /// Occupant of every stage, from fetch to execute
+    pub stages: Vec<Occupant>,
+    /// Occupant of every stage of the second lane of the dual issue
+    /// pipeline, which holds the younger instruction of a pair. It's empty
+    /// when the pipeline issues a single instruction.
+    pub second_lane: Vec<Occupant>,
+    /// Wrong path instructions discarded during the cycle
+    pub flushed: Vec<TraceEntry>,
+    /// Instruction which retired at the end of the cycle
+    pub retired: Option<TraceEntry>,
+    /// Younger instruction which retired along with `retired`, in the dual
+    /// issue pipeline
+    pub paired: Option<TraceEntry>,
+    /// Why no instruction retired during the cycle
+    pub stall: Option<StallReason>,
+    /// Register written during the cycle, other than x0, and its value. When
+    /// a pair executes, it's the write of the older instruction.
+    pub register_write: Option<(u8, u32)>,
+    /// Accesses started on the bus during the cycle
+    pub bus: Vec<BusTransaction>,
+}
+
+/// Consumer of the pipeline activity. Tracers are sent along with the
+/// processor to the thread running it.
+pub trait Tracer: Send {
+    /// Called at the end of every simulated cycle
+    ///
+    /// # Arguments
+    /// * `record` => activity of the pipeline during the cycle
+    fn cycle(&mut self, record: &CycleRecord) -> io::Result<()>;
+}
+
+/// Human readable cycle by cycle log of the pipeline occupancy. Every line
+/// shows one cycle with a column per stage. Bubbles are shown as `-`,
+/// instructions which stay in their stage are marked with `*` and flushed
+/// instructions are listed at the end of the line.
+pub struct OccupancyLog<W: Write> {
+    out: W,
+    stages: Stages,
+    header_written: bool,
+}
+
+// Width of each stage column
+const COLUMN_WIDTH: usize = 32;
+
+// Columns are padded except the last one, to avoid trailing spaces
+fn column_width(index: usize, columns: usize) -> usize {
+    if index + 1 == columns {
+        0
+    } else {
+        COLUMN_WIDTH
+    }
+}
+
+impl<W: Write> OccupancyLog<W> {
+    /// Create a log
+    ///
+    /// # Arguments
+    /// * `out` => where the log is written
+    /// * `stages` => pipeline configuration which is logged
+    pub fn new(out: W, stages: Stages) -> Self {
+        OccupancyLog {
+            out,
+            stages,
+            header_written: false,
+        }
+    }
+
+    /// Get the output back
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write + Send> Tracer for OccupancyLog<W> {
+    fn cycle(&mut self, record: &CycleRecord) -> io::Result<()> {
+        if !self.header_written {
+            write!(self.out, "{:>8}", "cycle")?;
+            let names = self.stages.names();
+            for (index, name) in names.iter().enumerate() {
+                write!(
+                    self.out,
+                    " | {:width$}",
+                    name,
+                    width = column_width(index, names.len())
+                )?;
+            }
+            writeln!(self.out)?;
+            self.header_written = true;
+        }
+
+        write!(self.out, "{:>8}", record.cycle)?;
+        for (index, occupant) in record.stages.iter().enumerate() {
+            let width = column_width(index, record.stages.len());
+            // The last column isn't padded unless more text follows
+            let width = if record.flushed.is_empty() {
+                width
+            } else {
+                COLUMN_WIDTH
+            };
+            write!(self.out, " | {:width$}", occupant, width = width)?;
+        }
+        if !record.flushed.is_empty() {
+            write!(self.out, " | flushed")?;
+            for entry in &record.flushed {
+                write!(self.out, " {:08x}", entry.pc)?;
+            }
+        }
+        writeln!(self.out)
+    }
+}
+
+/// Pipeline log in the Kanata 0004 format used by the Konata viewer. Every
+/// instruction is labeled with its address and disassembly, and shows when
+/// it entered each stage and whether it retired or was flushed.
+pub struct KonataLog<W: Write> {
+    out: W,
+    stages: Stages,
+    // Stage currently occupied by each instruction in flight
+    in_flight: HashMap<u64, usize>,
+    last_cycle: Option<u64>,
+    retired: u64,
+}
+
+impl<W: Write> KonataLog<W> {
+    /// Create a log
+    ///
+    /// # Arguments
+    /// * `out` => where the log is written
+    /// * `stages` => pipeline configuration which is logged
+    pub fn new(out: W, stages: Stages) -> Self {
+        KonataLog {
+            out,
+            stages,
+            in_flight: HashMap::new(),
+            last_cycle: None,
+            retired: 0,
+        }
+    }
+
+    /// Get the output back
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    // Move an instruction into a stage, introducing it if it's new
+    fn enter(&mut self, entry: &TraceEntry, stage: usize) -> io::Result<()> {
+        let names = self.stages.names();
+        match self.in_flight.insert(entry.id, stage) {
+            None => {
+                writeln!(self.out, "I\t{}\t{}\t0", entry.id, entry.id)?;
+                writeln!(
+                    self.out,
+                    "L\t{}\t0\t{:08x}: {}",
+                    entry.id,
+                    entry.pc,
+                    Instruction::new(entry.raw)
+                )?;
+            }
+            Some(previous) if previous == stage => return Ok(()),
+            Some(previous) => {
+                writeln!(self.out, "E\t{}\t0\t{}", entry.id, names[previous])?;
+            }
+        }
+        writeln!(self.out, "S\t{}\t0\t{}", entry.id, names[stage])
+    }
+
+    // Remove an instruction from the pipeline, either retiring it or
+    // flushing it
+    fn leave(&mut self, entry: &TraceEntry, flushed: bool) -> io::Result<()> {
+        // Instructions flushed in the cycle they were fetched were never seen
+        if !self.in_flight.contains_key(&entry.id) {
+            self.enter(entry, 0)?;
+        }
+        // This call to unwrap is safe because the instruction was just added
+        let stage = self.in_flight.remove(&entry.id).unwrap();
+        writeln!(
+            self.out,
+            "E\t{}\t0\t{}",
+            entry.id,
+            self.stages.names()[stage]
+        )?;
+
+        if flushed {
+            writeln!(self.out, "R\t{}\t{}\t1", entry.id, entry.id)
+        } else {
+            self.retired += 1;
+            writeln!(self.out, "R\t{}\t{}\t0", entry.id, self.retired - 1)
+        }
+    }
+}
+
+impl<W: Write + Send> Tracer for KonataLog<W> {
+    fn cycle(&mut self, record: &CycleRecord) -> io::Result<()> {
+        match self.last_cycle {
+            None => {
+                writeln!(self.out, "Kanata\t0004")?;
+                writeln!(self.out, "C=\t{}", record.cycle)?;
+            }
+            Some(last) => writeln!(self.out, "C\t{}", record.cycle - last)?,
+        }
+        self.last_cycle = Some(record.cycle);
+
+        for entry in &record.flushed {
+            self.leave(entry, true)?;
+        }
+        for lane in &[&record.stages, &record.second_lane] {
+            for (stage, occupant) in lane.iter().enumerate() {
+                if let Some(entry) = occupant.entry() {
+                    self.enter(entry, stage)?;
+                }
+            }
+        }
+        for entry in record.retired.iter().chain(&record.paired) {
+            self.leave(entry, false)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Tracer writing the fetch, the retirement and the flush of every
+/// instruction to the log of the simulator, at the trace level, or to an
+/// output of its own
+#[derive(Default)]
+pub struct EventLog {
+    // Identifier of the next instruction which wasn't fetched yet
+    next_fetch: u64,
+    // The log is used without it
+    out: Option<Box<dyn Write + Send>>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the events to an output instead of the log
+    pub fn with_output(out: Box<dyn Write + Send>) -> Self {
+        EventLog {
+            next_fetch: 0,
+            out: Some(out),
+        }
+    }
+
+    fn write(&mut self, event: fmt::Arguments) -> io::Result<()> {
+        match self.out {
+            Some(ref mut out) => out.write_fmt(format_args!("{}\n", event)),
+            None => {
+                trace!("{}", event);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Tracer for EventLog {
+    fn cycle(&mut self, record: &CycleRecord) -> io::Result<()> {
+        let fetched = record
+            .stages
+            .first()
+            .into_iter()
+            .chain(record.second_lane.first());
+        for entry in fetched.filter_map(Occupant::entry) {
+            if entry.id >= self.next_fetch {
+                self.write(format_args!(
+                    "cycle {}: fetch {:08x}",
+                    record.cycle, entry.pc
+                ))?;
+                self.next_fetch = entry.id + 1;
+            }
+        }
+        for entry in &record.flushed {
+            self.write(format_args!(
+                "cycle {}: flush {:08x}",
+                record.cycle, entry.pc
+            ))?;
+        }
+        for entry in record.retired.iter().chain(&record.paired) {
+            self.write(format_args!(
+                "cycle {}: retire {:08x} {}",
+                record.cycle,
+                entry.pc,
+                Instruction::new(entry.raw)
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// Machine readable state of the pipeline after every cycle, a JSON object
+/// per line with the cycle, the stages from fetch to execute (null for a
+/// bubble), the flushed and retired instructions, the register write and the
+/// bus transactions:
+///
+/// ```text
+/// {"cycle":2,"stages":[{"id":1,"pc":4,"raw":19,"stalled":false},null],"flushed":[],"retired":{"id":0,"pc":0,"raw":5244179},"write":{"rd":10,"value":5},"bus":[{"kind":"fetch","addr":4,"wait_states":0}]}
+/// ```
+pub struct StateLog<W: Write> {
+    out: W,
+}
+
+impl<W: Write> StateLog<W> {
+    pub fn new(out: W) -> Self {
+        StateLog { out }
+    }
+
+    /// Give back the output, once the simulation is over
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+fn entry_json(entry: &TraceEntry) -> String {
+    format!(
+        "{{\"id\":{},\"pc\":{},\"raw\":{}}}",
+        entry.id, entry.pc, entry.raw
+    )
+}
+
+fn stage_json(entry: &TraceEntry, stalled: bool) -> String {
+    format!(
+        "{{\"id\":{},\"pc\":{},\"raw\":{},\"stalled\":{}}}",
+        entry.id, entry.pc, entry.raw, stalled
+    )
+}
+
+fn optional_json(entry: Option<&TraceEntry>) -> String {
+    entry.map_or_else(|| "null".to_string(), entry_json)
+}
+
+impl<W: Write + Send> Tracer for StateLog<W> {
+    fn cycle(&mut self, record: &CycleRecord) -> io::Result<()> {
+        let stages: Vec<String> = record
+            .stages
+            .iter()
+            .map(|occupant| match occupant {
+                Occupant::Bubble => "null".to_string(),
+                Occupant::Active(entry) => stage_json(entry, false),
+                Occupant::Stalled(entry) => stage_json(entry, true),
+            })
+            .collect();
+        let flushed: Vec<String> = record.flushed.iter().map(entry_json).collect();
+        let write = record.register_write.map_or_else(
+            || "null".to_string(),
+            |(rd, value)| format!("{{\"rd\":{},\"value\":{}}}", rd, value),
+        );
+        let bus: Vec<String> = record
+            .bus
+            .iter()
+            .map(|transaction| {
+                format!(
+                    "{{\"kind\":\"{}\",\"addr\":{},\"wait_states\":{}}}",
+                    transaction.kind.name(),
+                    transaction.addr,
+                    transaction.wait_states
+                )
+            })
+            .collect();
+        writeln!(
+            self.out,
+            "{{\"cycle\":{},\"stages\":[{}],\"flushed\":[{}],\"retired\":{},\"write\":{},\"bus\":[{}]}}",
+            record.cycle,
+            stages.join(","),
+            flushed.join(","),
+            optional_json(record.retired.as_ref()),
+            write,
+            bus.join(",")
+        )
+    }
+}
+
+/// Trace of the retired instructions in the CSV format of
+/// [riscv-dv](https://github.com/chipsalliance/riscv-dv), which its
+/// comparison flow checks against the trace of the RTL when the simulator
+/// serves as the instruction set simulator:
+///
+/// ```text
+/// pc,instr,gpr,csr,binary,mode,instr_str,operand,pad
+/// 00000104,addi,a0:00000005,,00500513,3,"addi a0,zero,5","a0,zero,5",
+/// ```
+///
+/// The programs run in machine mode and the writes to the CSRs aren't traced.
+pub struct RiscvDvLog<W: Write> {
+    out: W,
+    header: bool,
+    // Register written by the instruction in the execute stage, which may
+    // take more cycles to retire
+    pending_write: Option<(u8, u32)>,
+}
+
+impl<W: Write> RiscvDvLog<W> {
+    pub fn new(out: W) -> Self {
+        RiscvDvLog {
+            out,
+            header: false,
+            pending_write: None,
+        }
+    }
+
+    /// Give back the output, once the simulation is over
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+// Privilege mode of the column of riscv-dv, always machine mode
+const MACHINE_MODE: u8 = 3;
+
+// A CSV field, quoted when it holds a separator like the csv module of Python
+// writes it
+fn csv_field(text: &str) -> String {
+    if text.contains(',') || text.contains('"') {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+impl<W: Write + Send> Tracer for RiscvDvLog<W> {
+    fn cycle(&mut self, record: &CycleRecord) -> io::Result<()> {
+        if !self.header {
+            writeln!(
+                self.out,
+                "pc,instr,gpr,csr,binary,mode,instr_str,operand,pad"
+            )?;
+            self.header = true;
+        }
+        if record.register_write.is_some() {
+            self.pending_write = record.register_write;
+        }
+        let entry = match record.retired {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        let disassembly = Instruction::new(entry.raw).to_string();
+        let mut parts = disassembly.splitn(2, ' ');
+        let mnemonic = parts.next().unwrap_or("");
+        let operand: String = parts
+            .next()
+            .unwrap_or("")
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let instr_str = if operand.is_empty() {
+            mnemonic.to_string()
+        } else {
+            format!("{} {}", mnemonic, operand)
+        };
+        let gpr = self
+            .pending_write
+            .take()
+            .map_or_else(String::new, |(rd, value)| {
+                format!("{}:{:08x}", get_register_label(rd), value)
+            });
+        writeln!(
+            self.out,
+            "{:08x},{},{},,{:08x},{},{},{},",
+            entry.pc,
+            mnemonic,
+            gpr,
+            entry.raw,
+            MACHINE_MODE,
+            csv_field(&instr_str),
+            csv_field(&operand)
+        )
+    }
+}
+
+/// Log of the retired instructions which the replayer reads, a line each
+/// with the pc, the bits and, for loads and stores, the accessed address, in
+/// hexadecimal:
+///
+/// ```text
+/// 00000104 00500513
+/// 00000108 00a12223 00000ffc
+/// ```
+pub struct CommitLog<W: Write> {
+    out: W,
+    // Address accessed by the instruction in the execute stage, which may
+    // take more cycles to retire
+    pending_access: Option<u32>,
+}
+
+impl<W: Write> CommitLog<W> {
+    pub fn new(out: W) -> Self {
+        CommitLog {
+            out,
+            pending_access: None,
+        }
+    }
+
+    /// Give back the output, once the simulation is over
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write + Send> Tracer for CommitLog<W> {
+    fn cycle(&mut self, record: &CycleRecord) -> io::Result<()> {
+        if let Some(access) = record
+            .bus
+            .iter()
+            .find(|transaction| transaction.kind != BusKind::Fetch)
+        {
+            self.pending_access = Some(access.addr);
+        }
+        for entry in record.retired.iter().chain(&record.paired) {
+            // At most one instruction of a pair accesses the memory
+            let access = match InstrClass::from(Instruction::new(entry.raw).op()) {
+                InstrClass::Load | InstrClass::Store => self.pending_access.take(),
+                _ if record.paired.is_none() => self.pending_access.take(),
+                _ => None,
+            };
+            match access {
+                Some(addr) => {
+                    writeln!(self.out, "{:08x} {:08x} {:08x}", entry.pc, entry.raw, addr)?
+                }
+                None => writeln!(self.out, "{:08x} {:08x}", entry.pc, entry.raw)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // Writer whose bytes can be read after it's moved into a tracer
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const ENTRY: TraceEntry = TraceEntry {
+        id: 3,
+        pc: 0x104,
+        raw: 0x0050_0513,
+    };
+
+    #[test]
+    fn test_occupant_display() {
+        assert_eq!("-", Occupant::Bubble.to_string());
+        assert_eq!(
+            "00000104 addi    a0,zero,5",
+            Occupant::Active(ENTRY).to_string()
+        );
+        assert_eq!(
+            "00000104 addi    a0,zero,5 *",
+            Occupant::Stalled(ENTRY).to_string()
+        );
+        assert_eq!(Some(&ENTRY), Occupant::Stalled(ENTRY).entry());
+        assert_eq!(None, Occupant::Bubble.entry());
+    }
+
+    #[test]
+    fn test_occupancy_log() {
+        let mut log = OccupancyLog::new(Vec::new(), Stages::Three);
+        log.cycle(&CycleRecord {
+            cycle: 1,
+            stages: vec![Occupant::Stalled(ENTRY), Occupant::Bubble, Occupant::Bubble],
+            flushed: Vec::new(),
+            retired: None,
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        log.cycle(&CycleRecord {
+            cycle: 2,
+            stages: vec![Occupant::Bubble, Occupant::Bubble, Occupant::Active(ENTRY)],
+            flushed: vec![TraceEntry {
+                id: 4,
+                pc: 0x108,
+                raw: 0,
+            }],
+            retired: Some(ENTRY),
+            ..CycleRecord::default()
+        })
+        .unwrap();
+
+        let text = String::from_utf8(log.into_inner()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(3, lines.len());
+        assert!(lines[0].starts_with("   cycle | IF "));
+        assert!(lines[1].starts_with("       1 | 00000104 addi    a0,zero,5 * "));
+        assert!(lines[2].ends_with("| 00000104 addi    a0,zero,5       | flushed 00000108"));
+    }
+
+    #[test]
+    fn test_konata_log() {
+        let next = TraceEntry {
+            id: 4,
+            pc: 0x108,
+            raw: 0,
+        };
+        let mut log = KonataLog::new(Vec::new(), Stages::Three);
+        log.cycle(&CycleRecord {
+            cycle: 5,
+            stages: vec![Occupant::Active(ENTRY), Occupant::Bubble, Occupant::Bubble],
+            flushed: Vec::new(),
+            retired: None,
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        log.cycle(&CycleRecord {
+            cycle: 6,
+            stages: vec![
+                Occupant::Active(next),
+                Occupant::Stalled(ENTRY),
+                Occupant::Bubble,
+            ],
+            flushed: Vec::new(),
+            retired: None,
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        log.cycle(&CycleRecord {
+            cycle: 7,
+            stages: vec![Occupant::Bubble, Occupant::Bubble, Occupant::Active(ENTRY)],
+            flushed: vec![
+                TraceEntry {
+                    id: 5,
+                    pc: 0x10c,
+                    raw: 0,
+                },
+                next,
+            ],
+            retired: Some(ENTRY),
+            ..CycleRecord::default()
+        })
+        .unwrap();
+
+        let text = String::from_utf8(log.into_inner()).unwrap();
+        assert_eq!(
+            "Kanata\t0004\nC=\t5\n\
+             I\t3\t3\t0\nL\t3\t0\t00000104: addi    a0,zero,5\nS\t3\t0\tIF\n\
+             C\t1\n\
+             I\t4\t4\t0\nL\t4\t0\t00000108: Invalid!\nS\t4\t0\tIF\n\
+             E\t3\t0\tIF\nS\t3\t0\tID\n\
+             C\t1\n\
+             I\t5\t5\t0\nL\t5\t0\t0000010c: Invalid!\nS\t5\t0\tIF\n\
+             E\t5\t0\tIF\nR\t5\t5\t1\n\
+             E\t4\t0\tIF\nR\t4\t4\t1\n\
+             E\t3\t0\tID\nS\t3\t0\tEX\n\
+             E\t3\t0\tEX\nR\t3\t0\t0\n",
+            text
+        );
+    }
+
+    #[test]
+    fn test_state_log() {
+        let mut log = StateLog::new(Vec::new());
+        log.cycle(&CycleRecord {
+            cycle: 2,
+            stages: vec![Occupant::Stalled(ENTRY), Occupant::Bubble],
+            flushed: Vec::new(),
+            retired: Some(ENTRY),
+            register_write: Some((10, 5)),
+            bus: vec![BusTransaction {
+                kind: BusKind::Store,
+                addr: 0x40,
+                wait_states: 2,
+            }],
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        assert_eq!(
+            "{\"cycle\":2,\"stages\":[{\"id\":3,\"pc\":260,\"raw\":5244179,\"stalled\":true},null],\
+             \"flushed\":[],\"retired\":{\"id\":3,\"pc\":260,\"raw\":5244179},\"write\":{\"rd\":10,\"value\":5},\
+             \"bus\":[{\"kind\":\"store\",\"addr\":64,\"wait_states\":2}]}\n",
+            String::from_utf8(log.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_event_log_output() {
+        let capture = Capture::default();
+        let mut log = EventLog::with_output(Box::new(capture.clone()));
+        let next = TraceEntry {
+            id: 4,
+            pc: 0x108,
+            raw: 0,
+        };
+        log.cycle(&CycleRecord {
+            cycle: 1,
+            stages: vec![Occupant::Active(next), Occupant::Active(ENTRY)],
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        // Instructions staying in fetch aren't fetched again
+        log.cycle(&CycleRecord {
+            cycle: 2,
+            stages: vec![Occupant::Stalled(next), Occupant::Bubble],
+            retired: Some(ENTRY),
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        assert_eq!(
+            "cycle 1: fetch 00000108\ncycle 2: retire 00000104 addi    a0,zero,5\n",
+            String::from_utf8(capture.0.lock().unwrap().clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_riscv_dv_log() {
+        let mut log = RiscvDvLog::new(Vec::new());
+        let store = TraceEntry {
+            id: 4,
+            pc: 0x108,
+            raw: 0x00a1_2223,
+        };
+        // The write is done a cycle before the instruction retires
+        log.cycle(&CycleRecord {
+            cycle: 1,
+            register_write: Some((10, 5)),
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        log.cycle(&CycleRecord {
+            cycle: 2,
+            retired: Some(ENTRY),
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        log.cycle(&CycleRecord {
+            cycle: 3,
+            retired: Some(store),
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        assert_eq!(
+            "pc,instr,gpr,csr,binary,mode,instr_str,operand,pad\n\
+             00000104,addi,a0:00000005,,00500513,3,\"addi a0,zero,5\",\"a0,zero,5\",\n\
+             00000108,sw,,,00a12223,3,\"sw a0,4(sp)\",\"a0,4(sp)\",\n",
+            String::from_utf8(log.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_commit_log() {
+        let mut log = CommitLog::new(Vec::new());
+        let store = TraceEntry {
+            id: 4,
+            pc: 0x108,
+            raw: 0x00a1_2223,
+        };
+        log.cycle(&CycleRecord {
+            cycle: 1,
+            retired: Some(ENTRY),
+            bus: vec![BusTransaction {
+                kind: BusKind::Fetch,
+                addr: 0x10c,
+                wait_states: 0,
+            }],
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        // The store waits for the bus before it retires
+        log.cycle(&CycleRecord {
+            cycle: 2,
+            bus: vec![BusTransaction {
+                kind: BusKind::Store,
+                addr: 0xffc,
+                wait_states: 1,
+            }],
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        log.cycle(&CycleRecord {
+            cycle: 3,
+            retired: Some(store),
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        // The access of a pair belongs to its store, even when it's younger
+        log.cycle(&CycleRecord {
+            cycle: 4,
+            retired: Some(ENTRY),
+            paired: Some(store),
+            bus: vec![BusTransaction {
+                kind: BusKind::Store,
+                addr: 0xff8,
+                wait_states: 0,
+            }],
+            ..CycleRecord::default()
+        })
+        .unwrap();
+        assert_eq!(
+            "00000104 00500513\n00000108 00a12223 00000ffc\n\
+             00000104 00500513\n00000108 00a12223 00000ff8\n",
+            String::from_utf8(log.into_inner()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Tracepoints: addresses where expressions are written to the log, at the
+//! info level, every time the program reaches them, without stopping it.
+//! They are given as `EXPR: EXPR[, EXPR...]`, such as
+//! `uart_putc: a0, *(u8*)a1`.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::hart::Hart;
+//! # use adept_lib::tracepoint::{TracepointSpec, Tracepoints};
+//! let spec = TracepointSpec::parse("0x100: a0, sp + 16").unwrap();
+//! let mut tracepoints = Tracepoints::new();
+//! tracepoints.add(spec.resolve(&Hart::new(), &[]).unwrap());
+//! assert_eq!(0x100, tracepoints.points()[0].addr);
+//! ```
+use elf::Symbol;
+use expr::{Expr, ExprError};
+use hart::Hart;
+
+/// Tracepoint as given by the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracepointSpec {
+    /// Address of the instruction
+    pub location: Expr,
+    /// Text and expression of every value written
+    pub exprs: Vec<(String, Expr)>,
+}
+
+impl TracepointSpec {
+    pub fn parse(text: &str) -> Result<TracepointSpec, String> {
+        let index = match text.find(':') {
+            Some(index) => index,
+            None => return Err(String::from("expected EXPR: EXPR[, EXPR...]")),
+        };
+        let location = Expr::parse(&text[..index]).map_err(|e| e.to_string())?;
+        let mut exprs = Vec::new();
+        for expr in text[index + 1..].split(',') {
+            let expr = expr.trim();
+            exprs.push((
+                expr.to_string(),
+                Expr::parse(expr).map_err(|e| e.to_string())?,
+            ));
+        }
+        Ok(TracepointSpec { location, exprs })
+    }
+
+    /// Compute the address of the tracepoint in the current state of a hart
+    /// and replace the symbols of its expressions by their addresses
+    pub fn resolve(&self, hart: &Hart, symbols: &[Symbol]) -> Result<Tracepoint, ExprError> {
+        let mut exprs = Vec::new();
+        for (text, expr) in &self.exprs {
+            exprs.push((text.clone(), expr.resolve(symbols)?));
+        }
+        Ok(Tracepoint {
+            addr: self.location.evaluate(hart, symbols)?,
+            exprs,
+            hits: 0,
+        })
+    }
+}
+
+/// Tracepoint at an address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tracepoint {
+    pub addr: u32,
+    /// Text and expression without symbols of every value written
+    pub exprs: Vec<(String, Expr)>,
+    /// Number of times the program reached the address
+    pub hits: u64,
+}
+
+impl Tracepoint {
+    /// Line written when the program reaches the tracepoint, such as
+    /// `tracepoint at 0x00000100: a0 = 0x00000005`
+    pub fn message(&self, hart: &Hart) -> String {
+        let values: Vec<String> = self
+            .exprs
+            .iter()
+            .map(|(text, expr)| match expr.evaluate(hart, &[]) {
+                Ok(value) => format!("{} = 0x{:08x}", text, value),
+                Err(e) => format!("{} = <{}>", text, e),
+            })
+            .collect();
+        format!("tracepoint at 0x{:08x}: {}", self.addr, values.join(", "))
+    }
+}
+
+/// Tracepoints of a processor
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tracepoints {
+    points: Vec<Tracepoint>,
+}
+
+impl Tracepoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, tracepoint: Tracepoint) {
+        self.points.push(tracepoint);
+    }
+
+    pub fn points(&self) -> &[Tracepoint] {
+        &self.points
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Log the tracepoints at the next instruction of a hart
+    pub fn reach(&mut self, hart: &Hart) {
+        let pc = hart.pc();
+        for tracepoint in self.points.iter_mut().filter(|point| point.addr == pc) {
+            tracepoint.hits += 1;
+            info!("{}", tracepoint.message(hart));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_specs() {
+        let spec = TracepointSpec::parse("main + 4: a0, *(u8*)sp").unwrap();
+        assert_eq!(Expr::parse("main + 4").unwrap(), spec.location);
+        assert_eq!(
+            vec!["a0", "*(u8*)sp"],
+            spec.exprs
+                .iter()
+                .map(|(text, _)| text.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert!(TracepointSpec::parse("main").is_err());
+        assert!(TracepointSpec::parse("main: a0,").is_err());
+
+        let symbols = [Symbol {
+            name: String::from("main"),
+            value: 0x200,
+            size: 0,
+        }];
+        let mut hart = Hart::new();
+        hart.registers_mut().write(10, 5);
+        let tracepoint = spec.resolve(&hart, &symbols).unwrap();
+        assert_eq!(0x204, tracepoint.addr);
+        assert_eq!(
+            "tracepoint at 0x00000204: a0 = 0x00000005, *(u8*)sp = 0x00000000",
+            tracepoint.message(&hart)
+        );
+    }
+}
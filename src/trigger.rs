@@ -0,0 +1,268 @@
+//! Trigger module of the debug spec, the hardware breakpoints and
+//! watchpoints programmed by the guest through the tselect and tdata CSRs.
+//! Every trigger is an address match trigger (mcontrol) comparing the pc of
+//! the executed instructions, or the address of the loads and stores, with
+//! tdata2 before the access happens.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::mem::MemAccess;
+//! # use adept_lib::trigger::{TriggerAction, Triggers, TDATA1, TDATA2};
+//! let mut triggers = Triggers::new();
+//! // Watch the stores to 0x100 in machine mode, raising a breakpoint
+//! assert!(triggers.write(TDATA1, 1 << 6 | 1 << 1));
+//! assert!(triggers.write(TDATA2, 0x100));
+//! assert_eq!(None, triggers.check(MemAccess::Load, 0x100));
+//! assert_eq!(Some((0, TriggerAction::Breakpoint)), triggers.check(MemAccess::Store, 0x100));
+//! ```
+use mem::MemAccess;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Trigger select
+pub const TSELECT: u16 = 0x7a0;
+/// Type and configuration of the selected trigger
+pub const TDATA1: u16 = 0x7a1;
+/// Address compared by the selected trigger
+pub const TDATA2: u16 = 0x7a2;
+/// Further configuration of the selected trigger, unused
+pub const TDATA3: u16 = 0x7a3;
+/// Types supported by the selected trigger
+pub const TINFO: u16 = 0x7a4;
+
+/// Number of triggers
+pub const TRIGGERS: usize = 4;
+
+// Fields of mcontrol
+const TYPE_MCONTROL: u32 = 2 << 28;
+const MCONTROL_HIT: u32 = 1 << 20;
+const MCONTROL_ACTION_SHIFT: u32 = 12;
+const MCONTROL_MATCH_SHIFT: u32 = 7;
+const MCONTROL_M: u32 = 1 << 6;
+const MCONTROL_EXECUTE: u32 = 1 << 2;
+const MCONTROL_STORE: u32 = 1 << 1;
+const MCONTROL_LOAD: u32 = 1;
+// Writable fields, the others are hardwired to 0
+const MCONTROL_MASK: u32 = MCONTROL_HIT
+    | 0xf << MCONTROL_ACTION_SHIFT
+    | 0xf << MCONTROL_MATCH_SHIFT
+    | MCONTROL_M
+    | MCONTROL_EXECUTE
+    | MCONTROL_STORE
+    | MCONTROL_LOAD;
+
+// Supported comparisons of mcontrol
+const MATCH_EQUAL: u32 = 0;
+const MATCH_NAPOT: u32 = 1;
+const MATCH_GE: u32 = 2;
+const MATCH_LT: u32 = 3;
+
+/// What happens when a trigger fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerAction {
+    /// Raise a breakpoint exception, jumping to mtvec
+    Breakpoint,
+    /// Enter debug mode. The simulation stops, like at a breakpoint of the
+    /// debugger.
+    Debug,
+}
+
+/// The triggers, all of them disabled on reset
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Triggers {
+    select: u32,
+    tdata1: [u32; TRIGGERS],
+    tdata2: [u32; TRIGGERS],
+}
+
+impl Default for Triggers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Triggers {
+    pub fn new() -> Self {
+        Triggers {
+            select: 0,
+            tdata1: [TYPE_MCONTROL; TRIGGERS],
+            tdata2: [0; TRIGGERS],
+        }
+    }
+
+    /// Read a register of the selected trigger
+    ///
+    /// # Return Value
+    /// The value of the register, or None if it isn't a trigger register
+    pub fn read(&self, csr: u16) -> Option<u32> {
+        let index = self.select as usize;
+        match csr {
+            TSELECT => Some(self.select),
+            TDATA1 => Some(self.tdata1[index]),
+            TDATA2 => Some(self.tdata2[index]),
+            TDATA3 => Some(0),
+            TINFO => Some(1 << (TYPE_MCONTROL >> 28)),
+            _ => None,
+        }
+    }
+
+    /// Write a register of the selected trigger. Unsupported values of the
+    /// fields are replaced by supported ones.
+    ///
+    /// # Return Value
+    /// False if it isn't a writable trigger register
+    pub fn write(&mut self, csr: u16, value: u32) -> bool {
+        let index = self.select as usize;
+        match csr {
+            // Selecting a missing trigger keeps the current one
+            TSELECT if (value as usize) < TRIGGERS => self.select = value,
+            TSELECT | TDATA3 => (),
+            TDATA1 => {
+                let mut tdata1 = TYPE_MCONTROL | value & MCONTROL_MASK;
+                if action(tdata1).is_none() {
+                    tdata1 &= !(0xf << MCONTROL_ACTION_SHIFT);
+                }
+                if (tdata1 >> MCONTROL_MATCH_SHIFT & 0xf) > MATCH_LT {
+                    tdata1 &= !(0xf << MCONTROL_MATCH_SHIFT);
+                }
+                self.tdata1[index] = tdata1;
+            }
+            TDATA2 => self.tdata2[index] = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Find the first trigger matching an access of the hart in machine
+    /// mode, setting its hit bit
+    ///
+    /// # Arguments
+    /// * `access` => fetch of an executed instruction, load or store
+    /// * `addr` => address of the instruction or accessed address
+    ///
+    /// # Return Value
+    /// The index and the action of the trigger, or None if none fires
+    pub fn check(&mut self, access: MemAccess, addr: u32) -> Option<(usize, TriggerAction)> {
+        let enable = match access {
+            MemAccess::Fetch => MCONTROL_EXECUTE,
+            MemAccess::Load => MCONTROL_LOAD,
+            MemAccess::Store => MCONTROL_STORE,
+        };
+        for index in 0..TRIGGERS {
+            let tdata1 = self.tdata1[index];
+            if tdata1 & enable == 0 || tdata1 & MCONTROL_M == 0 {
+                continue;
+            }
+            let tdata2 = self.tdata2[index];
+            let matches = match tdata1 >> MCONTROL_MATCH_SHIFT & 0xf {
+                MATCH_EQUAL => addr == tdata2,
+                // The trailing ones of tdata2 and the bit above them are
+                // ignored
+                MATCH_NAPOT => {
+                    let ignored = tdata2 ^ tdata2.wrapping_add(1);
+                    addr | ignored == tdata2 | ignored
+                }
+                MATCH_GE => addr >= tdata2,
+                MATCH_LT => addr < tdata2,
+                _ => false,
+            };
+            if matches {
+                self.tdata1[index] |= MCONTROL_HIT;
+                // This call to unwrap is safe because writes only keep
+                // supported actions
+                return Some((index, action(tdata1).unwrap()));
+            }
+        }
+        None
+    }
+}
+
+// Action of an mcontrol value
+fn action(tdata1: u32) -> Option<TriggerAction> {
+    match tdata1 >> MCONTROL_ACTION_SHIFT & 0xf {
+        0 => Some(TriggerAction::Breakpoint),
+        1 => Some(TriggerAction::Debug),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let mut triggers = Triggers::new();
+        assert!(triggers.write(TSELECT, 2));
+        assert!(triggers.write(TDATA2, 0x200));
+        assert!(triggers.write(TSELECT, TRIGGERS as u32));
+        assert_eq!(Some(2), triggers.read(TSELECT));
+        assert_eq!(Some(0x200), triggers.read(TDATA2));
+        assert!(triggers.write(TSELECT, 0));
+        assert_eq!(Some(0), triggers.read(TDATA2));
+        assert_eq!(Some(TYPE_MCONTROL), triggers.read(TDATA1));
+        assert_eq!(Some(4), triggers.read(TINFO));
+        assert_eq!(None, triggers.read(0x7a5));
+    }
+
+    #[test]
+    fn test_write_unsupported_fields() {
+        let mut triggers = Triggers::new();
+        // Type 6, dmode, chain, action 2 and match 4
+        assert!(triggers.write(TDATA1, 0x6800_2a44));
+        assert_eq!(
+            Some(TYPE_MCONTROL | MCONTROL_M | MCONTROL_EXECUTE),
+            triggers.read(TDATA1)
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let mut triggers = Triggers::new();
+        // Execute at 0x100 entering debug mode
+        triggers.write(
+            TDATA1,
+            1 << MCONTROL_ACTION_SHIFT | MCONTROL_M | MCONTROL_EXECUTE,
+        );
+        triggers.write(TDATA2, 0x100);
+        // Loads from 0x40 to 0x7f
+        triggers.write(TSELECT, 1);
+        triggers.write(
+            TDATA1,
+            MATCH_NAPOT << MCONTROL_MATCH_SHIFT | MCONTROL_M | MCONTROL_LOAD,
+        );
+        triggers.write(TDATA2, 0x5f);
+        // Stores from 0x1000 without the machine mode bit, never firing
+        triggers.write(TSELECT, 2);
+        triggers.write(TDATA1, MATCH_GE << MCONTROL_MATCH_SHIFT | MCONTROL_STORE);
+        triggers.write(TDATA2, 0x1000);
+
+        assert_eq!(
+            Some((0, TriggerAction::Debug)),
+            triggers.check(MemAccess::Fetch, 0x100)
+        );
+        assert_eq!(None, triggers.check(MemAccess::Fetch, 0x104));
+        assert_eq!(None, triggers.check(MemAccess::Load, 0x100));
+        assert_eq!(
+            Some((1, TriggerAction::Breakpoint)),
+            triggers.check(MemAccess::Load, 0x7c)
+        );
+        assert_eq!(None, triggers.check(MemAccess::Load, 0x80));
+        assert_eq!(None, triggers.check(MemAccess::Store, 0x2000));
+
+        // The hit bits of the fired triggers are set
+        triggers.write(TSELECT, 1);
+        assert_eq!(
+            Some(MCONTROL_HIT),
+            triggers.read(TDATA1).map(|t| t & MCONTROL_HIT)
+        );
+        triggers.write(TSELECT, 0);
+        assert_eq!(
+            Some(MCONTROL_HIT),
+            triggers.read(TDATA1).map(|t| t & MCONTROL_HIT)
+        );
+    }
+}
@@ -0,0 +1,492 @@
+//! Polled NS16550A serial port, the console of the platform. Transmitted
+//! characters are appended to the output of the program and sent to the
+//! console attached to the port, if any, which also feeds the receiver.
+//! Without a console the receiver never has data. The transmitter is always
+//! empty, so drivers never wait.
+//!
+//! `StreamConsole` attaches a TCP connection or a pseudo-terminal, so that
+//! interactive sessions and scripts talk to the program apart from the
+//! output of the simulator. It comes with the `devices` feature.
+//!
+//! # Example:
+//!
+//! ```
+//! # use adept_lib::uart::{Uart, UART_BASE};
+//! let mut uart = Uart::new();
+//! // Line status: the transmitter is empty
+//! assert_eq!(0x60, uart.read(UART_BASE + 5, 1));
+//! assert_eq!(Some(b'A'), uart.write(UART_BASE, 1, u32::from(b'A')));
+//! ```
+// The consoles on host streams come with the devices feature
+#[cfg(feature = "devices")]
+use std::fs::{File, OpenOptions};
+#[cfg(feature = "devices")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "devices")]
+use std::net::TcpListener;
+#[cfg(feature = "devices")]
+use std::sync::mpsc::{self, Receiver};
+#[cfg(feature = "devices")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "devices")]
+use std::thread;
+
+/// First address of the serial port
+pub const UART_BASE: u32 = 0x1000_0000;
+/// Size of the serial port registers in bytes
+pub const UART_SIZE: u32 = 0x100;
+/// Frequency of the clock of the serial port, given to the drivers
+pub const UART_CLOCK_FREQUENCY: u32 = 3_686_400;
+
+// Offsets of the registers, one byte apart
+const RBR_THR: u32 = 0;
+const IER: u32 = 1;
+const IIR_FCR: u32 = 2;
+const LCR: u32 = 3;
+const LSR: u32 = 5;
+// Divisor latch access bit of the line control
+const LCR_DLAB: u8 = 0x80;
+// Transmit holding register and transmitter empty
+const LSR_TRANSMITTER_EMPTY: u8 = 0x60;
+// Data in the receive buffer register
+const LSR_DATA_READY: u8 = 0x01;
+// No interrupt pending, with the FIFOs enabled
+const IIR_NO_INTERRUPT: u8 = 0xc1;
+
+/// Terminal at the other end of the serial port
+pub trait Console: Send {
+    /// Take the next character typed on the terminal without waiting
+    ///
+    /// # Return Value
+    /// The character, or None if there is none yet
+    fn receive(&mut self) -> Option<u8>;
+
+    /// Show a character transmitted by the program
+    fn transmit(&mut self, byte: u8);
+
+    /// Let the time pass up to a cycle of the simulation
+    ///
+    /// # Return Value
+    /// False if the run must stop because the console didn't get what it
+    /// expected
+    fn tick(&mut self, _cycle: u64) -> bool {
+        true
+    }
+
+    /// Check at the end of the run if the console got everything it
+    /// expected
+    fn finished(&self) -> bool {
+        true
+    }
+
+    /// Copy the console, for snapshots of the hart. The copies may share the
+    /// terminal.
+    fn box_clone(&self) -> Box<dyn Console>;
+}
+
+impl Clone for Box<dyn Console> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Console on a stream read by a thread of its own, such as a TCP
+/// connection or a pseudo-terminal. Its copies share the stream.
+#[cfg(feature = "devices")]
+#[derive(Clone)]
+pub struct StreamConsole {
+    received: Arc<Mutex<Receiver<u8>>>,
+    writer: Arc<Mutex<dyn Write + Send>>,
+}
+
+#[cfg(feature = "devices")]
+impl StreamConsole {
+    /// Create a console on a stream
+    ///
+    /// # Arguments
+    /// * `reader` => where the typed characters come from
+    /// * `writer` => where the transmitted characters go
+    pub fn new<R, W>(mut reader: R, writer: W) -> Self
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let (sender, received) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 256];
+            // The thread ends with the stream or the console
+            while let Ok(read) = reader.read(&mut buffer) {
+                if read == 0
+                    || buffer[..read]
+                        .iter()
+                        .any(|byte| sender.send(*byte).is_err())
+                {
+                    break;
+                }
+            }
+        });
+        StreamConsole {
+            received: Arc::new(Mutex::new(received)),
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Wait for a connection to a local TCP port and use it as the console
+    ///
+    /// # Arguments
+    /// * `port` => the port
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        info!("Waiting for the console on port {}", port);
+        let (stream, addr) = listener.accept()?;
+        info!("Console connected from {}", addr);
+        stream.set_nodelay(true)?;
+        Ok(StreamConsole::new(stream.try_clone()?, stream))
+    }
+
+    /// Allocate a pseudo-terminal and use it as the console. The terminal
+    /// is in raw mode, so the characters pass unchanged.
+    ///
+    /// # Return Value
+    /// The console and the path of the terminal to open, such as /dev/pts/3
+    #[cfg(unix)]
+    pub fn open_pty() -> io::Result<(Self, String)> {
+        let (master, path) = pty::open()?;
+        Ok((StreamConsole::new(master.try_clone()?, master), path))
+    }
+}
+
+#[cfg(feature = "devices")]
+impl Console for StreamConsole {
+    fn receive(&mut self) -> Option<u8> {
+        // A poisoned lock means a copy panicked, drop the input then
+        self.received.lock().ok()?.try_recv().ok()
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        if let Ok(mut writer) = self.writer.lock() {
+            // The program doesn't notice a closed terminal, as on the
+            // hardware
+            if let Err(e) = writer.write_all(&[byte]).and_then(|_| writer.flush()) {
+                debug!("console: {}", e);
+            }
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Console> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct Uart {
+    // Registers which keep what was written: the interrupt enable, the line
+    // and modem control and the scratch
+    registers: [u8; 8],
+    divisor: u16,
+    console: Option<Box<dyn Console>>,
+    // Character in the receive buffer register
+    received: Option<u8>,
+    bytes_transmitted: u64,
+    bytes_received: u64,
+}
+
+impl Uart {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach a console, or detach it with None
+    pub fn set_console(&mut self, console: Option<Box<dyn Console>>) {
+        self.console = console;
+        self.received = None;
+    }
+
+    pub fn console(&self) -> Option<&dyn Console> {
+        self.console.as_ref().map(|console| console.as_ref())
+    }
+
+    /// Characters the program wrote to the transmit holding register
+    pub fn bytes_transmitted(&self) -> u64 {
+        self.bytes_transmitted
+    }
+
+    /// Characters the program read from the receive buffer register
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// Let the time pass for the console up to a cycle of the simulation
+    ///
+    /// # Return Value
+    /// False if the console stops the run
+    pub fn tick(&mut self, cycle: u64) -> bool {
+        match self.console {
+            Some(ref mut console) => console.tick(cycle),
+            None => true,
+        }
+    }
+
+    /// Check if an address belongs to the serial port
+    pub fn contains(&self, addr: u32) -> bool {
+        addr.wrapping_sub(UART_BASE) < UART_SIZE
+    }
+
+    /// Read the registers
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first register
+    /// * `size` => number of registers read, up to 4
+    ///
+    /// # Return Value
+    /// The registers in little endian
+    pub fn read(&mut self, addr: u32, size: u32) -> u32 {
+        (0..size).fold(0, |value, index| {
+            let offset = addr.wrapping_add(index).wrapping_sub(UART_BASE);
+            value | u32::from(self.read_register(offset)) << (index << 3)
+        })
+    }
+
+    /// Write the registers
+    ///
+    /// # Arguments
+    /// * `addr` => address of the first register
+    /// * `size` => number of registers written, up to 4
+    /// * `value` => the registers in little endian
+    ///
+    /// # Return Value
+    /// The transmitted character, if the transmit holding register was
+    /// written
+    pub fn write(&mut self, addr: u32, size: u32, value: u32) -> Option<u8> {
+        let mut transmitted = None;
+        for index in 0..size {
+            let offset = addr.wrapping_add(index).wrapping_sub(UART_BASE);
+            let byte = (value >> (index << 3)) as u8;
+            if offset == RBR_THR && !self.divisor_latch() {
+                if let Some(ref mut console) = self.console {
+                    console.transmit(byte);
+                }
+                transmitted = Some(byte);
+                self.bytes_transmitted += 1;
+            } else {
+                self.write_register(offset, byte);
+            }
+        }
+        transmitted
+    }
+
+    fn divisor_latch(&self) -> bool {
+        self.registers[LCR as usize] & LCR_DLAB != 0
+    }
+
+    // Fill the receive buffer register from the console, if it's empty
+    fn poll(&mut self) {
+        if self.received.is_none() {
+            self.received = self.console.as_mut().and_then(|console| console.receive());
+        }
+    }
+
+    // Read the register at an offset from the base. Reading the receive
+    // buffer register empties it.
+    fn read_register(&mut self, offset: u32) -> u8 {
+        match offset {
+            RBR_THR if self.divisor_latch() => self.divisor as u8,
+            IER if self.divisor_latch() => (self.divisor >> 8) as u8,
+            RBR_THR => {
+                self.poll();
+                match self.received.take() {
+                    Some(byte) => {
+                        self.bytes_received += 1;
+                        byte
+                    }
+                    None => 0,
+                }
+            }
+            IIR_FCR => IIR_NO_INTERRUPT,
+            LSR => {
+                self.poll();
+                if self.received.is_some() {
+                    LSR_TRANSMITTER_EMPTY | LSR_DATA_READY
+                } else {
+                    LSR_TRANSMITTER_EMPTY
+                }
+            }
+            _ if offset < 8 => self.registers[offset as usize],
+            _ => 0,
+        }
+    }
+
+    // Write the register at an offset from the base. The FIFO control and
+    // the status registers have no effect.
+    fn write_register(&mut self, offset: u32, byte: u8) {
+        match offset {
+            RBR_THR => self.divisor = self.divisor & 0xff00 | u16::from(byte),
+            IER if self.divisor_latch() => {
+                self.divisor = self.divisor & 0x00ff | u16::from(byte) << 8
+            }
+            IIR_FCR | LSR => (),
+            _ if offset < 8 => self.registers[offset as usize] = byte,
+            _ => (),
+        }
+    }
+}
+
+// Allocation of pseudo-terminals with the C library
+#[cfg(all(unix, feature = "devices"))]
+mod pty {
+    use super::*;
+
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_int, c_void};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    const O_RDWR: c_int = 2;
+    const TCSANOW: c_int = 0;
+
+    extern "C" {
+        fn posix_openpt(flags: c_int) -> c_int;
+        fn grantpt(fd: c_int) -> c_int;
+        fn unlockpt(fd: c_int) -> c_int;
+        fn ptsname(fd: c_int) -> *const c_char;
+        fn tcgetattr(fd: c_int, termios: *mut c_void) -> c_int;
+        fn tcsetattr(fd: c_int, actions: c_int, termios: *const c_void) -> c_int;
+        fn cfmakeraw(termios: *mut c_void);
+    }
+
+    // Open the master side of a new pseudo-terminal, returning it with the
+    // path of the slave side
+    pub fn open() -> io::Result<(File, String)> {
+        unsafe {
+            let fd = posix_openpt(O_RDWR);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let master = File::from_raw_fd(fd);
+            if grantpt(fd) < 0 || unlockpt(fd) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let name = ptsname(fd);
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let path = CStr::from_ptr(name).to_string_lossy().into_owned();
+            // Without echo and line editing, the terminal passes the
+            // characters unchanged. The slave side stays open for the life
+            // of the simulator, so that the master reads wait for the clients
+            // instead of failing between them.
+            let slave = OpenOptions::new().read(true).write(true).open(&path)?;
+            // Large enough for the termios structure of any platform
+            let mut termios = [0u64; 32];
+            let termios = termios.as_mut_ptr() as *mut c_void;
+            if tcgetattr(slave.as_raw_fd(), termios) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            cfmakeraw(termios);
+            if tcsetattr(slave.as_raw_fd(), TCSANOW, termios) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            ::std::mem::forget(slave);
+            Ok((master, path))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "devices")]
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_transmit() {
+        let mut uart = Uart::new();
+        assert!(uart.contains(UART_BASE + 7));
+        assert!(!uart.contains(UART_BASE + UART_SIZE));
+        assert_eq!(Some(b'h'), uart.write(UART_BASE + RBR_THR, 1, 0x68));
+        // Words write four registers, starting with the transmitter
+        assert_eq!(Some(b'i'), uart.write(UART_BASE, 4, 0x0300_0069));
+        assert_eq!(3, uart.read(UART_BASE + LCR, 1));
+        assert_eq!(None, uart.write(UART_BASE + 7, 1, 0x55));
+        assert_eq!(0x55, uart.read(UART_BASE + 7, 1));
+        assert_eq!(0, uart.read(UART_BASE + RBR_THR, 1));
+        assert_eq!((2, 0), (uart.bytes_transmitted(), uart.bytes_received()));
+    }
+
+    #[cfg(feature = "devices")]
+    #[test]
+    fn test_console() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream.write_all(b"ok").unwrap();
+            let mut echo = [0; 2];
+            stream.read_exact(&mut echo).unwrap();
+            echo
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut uart = Uart::new();
+        uart.set_console(Some(Box::new(StreamConsole::new(
+            stream.try_clone().unwrap(),
+            stream,
+        ))));
+        // Echo what the client types
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            if uart.read(UART_BASE + LSR, 1) & u32::from(LSR_DATA_READY) != 0 {
+                received.push(uart.read(UART_BASE + RBR_THR, 1) as u8);
+            }
+        }
+        assert_eq!(b"ok", &received[..]);
+        assert_eq!(
+            u32::from(LSR_TRANSMITTER_EMPTY),
+            uart.read(UART_BASE + LSR, 1)
+        );
+        for byte in received {
+            assert_eq!(Some(byte), uart.write(UART_BASE, 1, u32::from(byte)));
+        }
+        assert_eq!(*b"ok", client.join().unwrap());
+        assert_eq!((2, 2), (uart.bytes_transmitted(), uart.bytes_received()));
+    }
+
+    #[cfg(all(unix, feature = "devices"))]
+    #[test]
+    fn test_pty() {
+        let (console, path) = StreamConsole::open_pty().unwrap();
+        let mut uart = Uart::new();
+        uart.set_console(Some(Box::new(console)));
+        let mut terminal = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        uart.write(UART_BASE, 1, u32::from(b'>'));
+        let mut byte = [0];
+        terminal.read_exact(&mut byte).unwrap();
+        assert_eq!(*b">", byte);
+        terminal.write_all(b"\r").unwrap();
+        while uart.read(UART_BASE + LSR, 1) & u32::from(LSR_DATA_READY) == 0 {}
+        // Carriage returns aren't translated in raw mode
+        assert_eq!(u32::from(b'\r'), uart.read(UART_BASE, 1));
+    }
+
+    #[test]
+    fn test_divisor_latch() {
+        let mut uart = Uart::new();
+        uart.write(UART_BASE + LCR, 1, u32::from(LCR_DLAB));
+        // The divisor takes the place of the transmitter and interrupt enable
+        assert_eq!(None, uart.write(UART_BASE, 2, 0x0102));
+        assert_eq!(0x0102, uart.read(UART_BASE, 2));
+        uart.write(UART_BASE + LCR, 1, 3);
+        assert_eq!(0, uart.read(UART_BASE + IER, 1));
+        assert_eq!(
+            u32::from(IIR_NO_INTERRUPT),
+            uart.read(UART_BASE + IIR_FCR, 1)
+        );
+        assert_eq!(
+            u32::from(LSR_TRANSMITTER_EMPTY),
+            uart.read(UART_BASE + LSR, 1)
+        );
+    }
+}
@@ -0,0 +1,33 @@
+/* Configuration of the FreeRTOS demo of tests/rtos.rs, for the platform of
+ * adept: the CLINT at 0x02000000, with mtime counting executed
+ * instructions. */
+#ifndef FREERTOS_CONFIG_H
+#define FREERTOS_CONFIG_H
+
+#define configMTIME_BASE_ADDRESS 0x0200bff8
+#define configMTIMECMP_BASE_ADDRESS 0x02004000
+/* A tick every 1000 instructions */
+#define configCPU_CLOCK_HZ 1000000
+#define configTICK_RATE_HZ 1000
+
+#define configUSE_PREEMPTION 1
+#define configUSE_IDLE_HOOK 0
+#define configUSE_TICK_HOOK 0
+#define configMAX_PRIORITIES 4
+#define configMINIMAL_STACK_SIZE 256
+#define configISR_STACK_SIZE_WORDS 256
+#define configTOTAL_HEAP_SIZE (32 * 1024)
+#define configTICK_TYPE_WIDTH_IN_BITS TICK_TYPE_WIDTH_32_BITS
+#define configSUPPORT_DYNAMIC_ALLOCATION 1
+#define configSUPPORT_STATIC_ALLOCATION 0
+#define configUSE_MUTEXES 0
+#define configUSE_TIMERS 0
+#define configCHECK_FOR_STACK_OVERFLOW 0
+
+#define INCLUDE_vTaskDelay 1
+
+void vAssertCalled(const char *file, int line);
+#define configASSERT(x) \
+    if (!(x)) vAssertCalled(__FILE__, __LINE__)
+
+#endif
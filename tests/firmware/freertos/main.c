@@ -0,0 +1,87 @@
+/* FreeRTOS demo of tests/rtos.rs. It writes a banner to the NS16550 serial
+ * port, then two tasks write a line every 10 ticks, which only the tick
+ * interrupts wake them for. */
+#include <stddef.h>
+#include <stdint.h>
+
+#include "FreeRTOS.h"
+#include "task.h"
+
+#define UART_THR ((volatile uint8_t *)0x10000000)
+#define UART_LSR ((volatile uint8_t *)0x10000005)
+#define LSR_THR_EMPTY 0x20
+
+static void put(const char *text)
+{
+    while (*text) {
+        while (!(*UART_LSR & LSR_THR_EMPTY)) {
+        }
+        *UART_THR = *text++;
+    }
+}
+
+static void put_number(unsigned number)
+{
+    char digits[11];
+    char *digit = &digits[sizeof(digits) - 1];
+    *digit = '\0';
+    do {
+        *--digit = '0' + number % 10;
+        number /= 10;
+    } while (number);
+    put(digit);
+}
+
+static void ticker(void *name)
+{
+    for (unsigned count = 1;; count++) {
+        vTaskDelay(10);
+        put(name);
+        put(" tick ");
+        put_number(count);
+        put("\n");
+    }
+}
+
+int main(void)
+{
+    put("FreeRTOS " tskKERNEL_VERSION_NUMBER " booted\n");
+    xTaskCreate(ticker, "a", configMINIMAL_STACK_SIZE, "task a", 1, NULL);
+    xTaskCreate(ticker, "b", configMINIMAL_STACK_SIZE, "task b", 2, NULL);
+    vTaskStartScheduler();
+    put("the scheduler didn't start\n");
+    for (;;) {
+    }
+}
+
+void vAssertCalled(const char *file, int line)
+{
+    taskDISABLE_INTERRUPTS();
+    put("assertion failed: ");
+    put(file);
+    put(":");
+    put_number(line);
+    put("\n");
+    for (;;) {
+    }
+}
+
+/* Without a C library */
+void *memset(void *dest, int value, size_t size)
+{
+    uint8_t *bytes = dest;
+    while (size--) {
+        *bytes++ = value;
+    }
+    return dest;
+}
+
+void *memcpy(void *dest, const void *src, size_t size)
+{
+    uint8_t *to = dest;
+    const uint8_t *from = src;
+    while (size--) {
+        *to++ = *from++;
+    }
+    return dest;
+}
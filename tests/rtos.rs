@@ -0,0 +1,33 @@
+//! Boot of a real-time operating system on the simulated platform. The
+//! FreeRTOS demo of tests/firmware/freertos isn't in the tree: build it
+//! with `make -C tests/firmware/freertos`, which fetches the kernel, then
+//! run `cargo test --test rtos -- --ignored`. ADEPT_RTOS_ELF gives another
+//! image with the same output.
+extern crate adept_lib;
+
+use adept_lib::fixture::AdeptTest;
+use adept_lib::hart::EnvironmentCallPolicy;
+
+use std::env;
+
+const DEFAULT_IMAGE: &str = "tests/firmware/freertos/freertos-demo.elf";
+
+#[test]
+#[ignore]
+fn freertos_boots_and_ticks() {
+    let path = env::var("ADEPT_RTOS_ELF").unwrap_or_else(|_| DEFAULT_IMAGE.to_string());
+    // The kernel yields with ecall
+    let run = AdeptTest::new()
+        .load(&path)
+        .environment_calls(EnvironmentCallPolicy::Trap)
+        .expect_uart("FreeRTOS V")
+        .expect_uart(" booted\n")
+        .expect_uart("task a tick 3\n")
+        .expect_uart("task b tick 3\n")
+        .max_cycles(1e8)
+        .run();
+    assert!(run.output.starts_with("FreeRTOS V"), "{}", run.output);
+    assert!(!run.output.contains("assertion failed"), "{}", run.output);
+    // Every task waited 10 ticks 3 times
+    assert!(run.timer_interrupts >= 30, "{} ticks", run.timer_interrupts);
+}